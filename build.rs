@@ -1,3 +1,5 @@
 fn main() {
     pyo3_build_config::use_pyo3_cfgs();
+    // set by pyo3 itself and checked within the code `create_exception!` expands to
+    println!("cargo:rustc-check-cfg=cfg(addr_of)");
 }
@@ -1 +1,2 @@
+from ._internal import WorkerContext
 from .server import Granian
@@ -3,10 +3,19 @@ import asyncio
 async def task_wrapper(task, watcher):
     try:
         await task
+    except asyncio.CancelledError:
+        watcher.done()
+        raise
     except Exception:
         watcher.err()
         raise
     watcher.done()
 
+# Handlers of requests being watched for cancellation get their task attached,
+# which gets cancelled once their response is no longer awaited
 def future_wrapper(coro, watcher):
-    return asyncio.create_task(task_wrapper(coro, watcher))
+    task = asyncio.create_task(task_wrapper(coro, watcher))
+    cancellation = getattr(watcher, "cancellation", None)
+    if cancellation is not None:
+        cancellation.attach(task)
+    return task
@@ -1,8 +1,41 @@
-from typing import Any, Dict, List, Tuple, Optional
+from typing import Any, Dict, List, Tuple, Optional, Union
 
 from ._types import WebsocketMessage
 
 
+class GranianError(RuntimeError):
+    code: str
+    exit_status: int
+
+
+class BindError(GranianError):
+    ...
+
+
+class TlsError(GranianError):
+    ...
+
+
+class ProtocolError(GranianError):
+    ...
+
+
+class ResponseStateError(ProtocolError):
+    ...
+
+
+class ProtocolViolationError(ProtocolError):
+    ...
+
+
+class AppError(GranianError):
+    ...
+
+
+class TimeoutError(GranianError):
+    ...
+
+
 class ASGIScope:
     client_ip: str
     client_port: int
@@ -11,12 +44,24 @@ class ASGIScope:
     http_version: str
     method: str
     path: str
+    raw_path: str
     proto: str
     query_string: str
     scheme: str
+    worker_id: int
+    worker_generation: int
+    state: Dict[str, Any]
 
     @property
     def headers(self) -> List[Tuple[bytes, bytes]]: ...
+    def scratch_dir(self) -> str: ...
+    def deadline_remaining(self) -> Optional[float]: ...
+    def tls_info(self) -> Optional[Tuple[int, int, List[str], Optional[str]]]: ...
+    def query_params(self) -> List[Tuple[str, str]]: ...
+
+
+class ASGIConnectionClosed(OSError):
+    ...
 
 
 class RSGIHeaders:
@@ -25,6 +70,7 @@ class RSGIHeaders:
     def values(self) -> List[str]: ...
     def items(self) -> List[Tuple[str]]: ...
     def get(self, key: str, default: Any = None) -> Any: ...
+    def get_all(self, key: str) -> List[str]: ...
 
 
 class RSGIScope:
@@ -36,32 +82,80 @@ class RSGIScope:
     scheme: str
     method: str
     path: str
+    raw_path: str
     query_string: str
+    content_length: Optional[int]
+    worker_id: int
+    worker_generation: int
+    client_cert: Optional[bytes]
+    client_cert_subject: Optional[str]
+    client_cert_verify: str
 
     @property
     def headers(self) -> RSGIHeaders: ...
+    def scratch_dir(self) -> str: ...
+    def deadline_remaining(self) -> Optional[float]: ...
+    def query_params(self) -> List[Tuple[str, str]]: ...
+
+
+class RSGIBodyChunk:
+    def __len__(self) -> int: ...
+    def release(self): ...
 
 
 class RSGIHTTPProtocol:
     async def __call__(self) -> bytes: ...
-    def response_empty(self, status: int, headers: List[Tuple[str, str]]): ...
-    def response_str(self, status: int, headers: List[Tuple[str, str]], body: str): ...
-    def response_bytes(self, status: int, headers: List[Tuple[str, str]], body: bytes): ...
-    def response_file(self, status: int, headers: List[Tuple[str, str]], file: str): ...
+    def __aiter__(self) -> RSGIHTTPProtocol: ...
+    async def __anext__(self) -> RSGIBodyChunk: ...
+    async def receive(self) -> RSGIBodyChunk: ...
+    def response_empty(self, status: int, headers: List[Tuple[str, str]], reason: Optional[str] = None): ...
+    def response_str(self, status: int, headers: List[Tuple[str, str]], body: str, reason: Optional[str] = None): ...
+    def response_bytes(
+        self,
+        status: int,
+        headers: List[Tuple[str, str]],
+        body: Union[bytes, bytearray, memoryview],
+        reason: Optional[str] = None
+    ): ...
+    def response_file(self, status: int, headers: List[Tuple[str, str]], file: str, reason: Optional[str] = None): ...
+    def response_stream(
+        self,
+        status: int,
+        headers: List[Tuple[str, str]],
+        reason: Optional[str] = None
+    ) -> RSGIHTTPStreamTransport: ...
+    async def upgrade(self, headers: List[Tuple[str, str]] = []) -> RSGIUpgradedTransport: ...
+
+
+class RSGIHTTPStreamTransport:
+    async def send_bytes(self, data: Union[bytes, bytearray, memoryview]): ...
+    async def send_str(self, data: str): ...
+
+
+class RSGIUpgradedTransport:
+    async def read(self, size: int = 65536) -> bytes: ...
+    async def write(self, data: Union[bytes, bytearray, memoryview]): ...
+    async def close(self): ...
 
 
 class RSGIWebsocketTransport:
     async def receive(self) -> WebsocketMessage: ...
     async def send_bytes(self, data: bytes): ...
     async def send_str(self, data: str): ...
+    async def send_bytes_fragment(self, data: bytes, last: bool = False): ...
+    async def send_str_fragment(self, data: str, last: bool = False): ...
 
 
 class RSGIWebsocketProtocol:
-    async def accept(self) -> RSGIWebsocketTransport: ...
+    async def accept(
+        self,
+        headers: List[Tuple[str, str]] = [],
+        fragments: bool = False
+    ) -> RSGIWebsocketTransport: ...
     def close(self, status: Optional[int]) -> Tuple[int, bool]: ...
 
 
-class RSGIProtocolError(RuntimeError):
+class RSGIProtocolError(ProtocolError):
     ...
 
 
@@ -75,6 +169,61 @@ class WSGIScope:
     scheme: str
     method: str
     path: str
+    raw_path: str
     query_string: str
     headers: Dict[str, str]
     body: bytes
+    worker_id: int
+    worker_generation: int
+
+    def scratch_dir(self) -> str: ...
+    def deadline_remaining(self) -> Optional[float]: ...
+    def query_params(self) -> List[Tuple[str, str]]: ...
+
+
+def metrics() -> str: ...
+def process_memory(pid: int) -> Optional[int]: ...
+def cpu_quota() -> Optional[float]: ...
+def memory_limit() -> Optional[int]: ...
+def reset_log_levels(): ...
+
+
+class WorkerOptions:
+    def __init__(self, **options: Any): ...
+
+
+class TestClient:
+    def __init__(self, interface: str, callback: Any, options: WorkerOptions): ...
+    async def request(
+        self,
+        method: str,
+        path: str,
+        headers: List[Tuple[str, str]] = [],
+        body: Optional[bytes] = None
+    ) -> Tuple[int, List[Tuple[str, str]], bytes]: ...
+
+
+class TestServer:
+    address: Tuple[str, int]
+
+    def __init__(
+        self,
+        interface: str,
+        callback: Any,
+        address: str,
+        port: int,
+        uds: Optional[str],
+        fd: Optional[int],
+        options: WorkerOptions
+    ): ...
+    async def serve(self): ...
+    def shutdown(self): ...
+
+
+def reload_certificates() -> int: ...
+
+
+def mock_clock(): ...
+def advance_clock(seconds: float): ...
+def reset_clock(): ...
+def shutdown_test_runtime(): ...
@@ -1,10 +1,14 @@
+import inspect
 import os
 import re
 import sys
 import traceback
 
 from types import ModuleType
-from typing import Callable, List, Optional
+from typing import Any, Callable, Dict, List, NamedTuple, Optional
+
+from .constants import Interfaces
+from .errors import ConfigurationError
 
 
 def get_import_components(path: str) -> List[Optional[str]]:
@@ -64,3 +68,78 @@ def load_target(target: str) -> Callable[..., None]:
     for element in name.split("."):
         rv = getattr(rv, element)
     return rv
+
+
+# What application factories get called with, once per worker process
+class WorkerContext(NamedTuple):
+    worker_id: int
+    worker_generation: int
+    config: Dict[str, Any]
+
+
+def load_factory(loader: Callable[[], Any], context: WorkerContext) -> Callable[..., None]:
+    factory = loader()
+    if not callable(factory):
+        raise RuntimeError(f"Application factory {factory!r} is not callable.")
+    if inspect.signature(factory).parameters:
+        return factory(context)
+    return factory()
+
+
+# The calls applications of each interface get: coroutine functions taking the scope
+# and the receive and send callables for ASGI 3, the scope and the protocol for
+# RSGI, and plain functions taking the environ and start_response for WSGI.
+INTERFACE_CALLS = (
+    (Interfaces.ASGI, 3, True),
+    (Interfaces.RSGI, 2, True),
+    (Interfaces.WSGI, 2, False)
+)
+
+
+def _accepts_positional(signature: inspect.Signature, count: int) -> bool:
+    required, accepted = 0, 0
+    for param in signature.parameters.values():
+        if param.kind == param.VAR_POSITIONAL:
+            accepted = count
+        elif param.kind in (param.POSITIONAL_ONLY, param.POSITIONAL_OR_KEYWORD):
+            accepted += 1
+            if param.default is param.empty:
+                required += 1
+        elif param.kind == param.KEYWORD_ONLY and param.default is param.empty:
+            return False
+    return required <= count <= accepted
+
+
+# Applications with an `__rsgi__` method are RSGI ones, the others get told apart
+# by the signature of the callable. Those matching none or several interfaces
+# need the interface to be set explicitly.
+def detect_interface(target: Any) -> Interfaces:
+    if hasattr(target, "__rsgi__"):
+        return Interfaces.RSGI
+    if not callable(target):
+        raise ConfigurationError(f"Unable to detect the interface of {target!r}: it is not callable")
+    try:
+        signature = inspect.signature(target)
+    except (TypeError, ValueError):
+        raise ConfigurationError(
+            f"Unable to detect the interface of {target!r}: its signature can't be inspected, "
+            "set the interface explicitly"
+        )
+    coroutine = inspect.iscoroutinefunction(target) or inspect.iscoroutinefunction(
+        getattr(target, "__call__", None)
+    )
+    matches = [
+        interface for interface, count, is_coroutine in INTERFACE_CALLS
+        if is_coroutine == coroutine and _accepts_positional(signature, count)
+    ]
+    if not matches:
+        raise ConfigurationError(
+            f"Unable to detect the interface of {target!r}: {'coroutine' if coroutine else 'function'} "
+            f"with signature {signature} matches none of ASGI 3, RSGI and WSGI, set the interface explicitly"
+        )
+    if len(matches) > 1:
+        raise ConfigurationError(
+            f"Unable to detect the interface of {target!r}: signature {signature} is ambiguous, "
+            f"matching {' and '.join(interface.name for interface in matches)}, set the interface explicitly"
+        )
+    return matches[0]
@@ -0,0 +1,118 @@
+import os
+import socket
+import socketserver
+import stat
+import threading
+
+from multiprocessing.connection import Connection
+from typing import Callable, Dict, List, Optional, Tuple
+
+from .errors import ConfigurationError
+from .log import logger, set_log_level
+from .stacks import dump_stacks
+
+
+AdminCommand = Callable[[List[str]], str]
+
+
+class _AdminHandler(socketserver.StreamRequestHandler):
+    def handle(self):
+        for line in self.rfile:
+            args = line.decode("utf8", errors="replace").split()
+            if not args:
+                continue
+            self.wfile.write(self.server.admin.dispatch(args).encode("utf8") + b"\n")
+
+
+# Line based control socket of the main process: every command gets
+# a single line reply, starting either with `ok` or `error`.
+class AdminServer:
+    def __init__(self, path: str, commands: Dict[str, AdminCommand]):
+        self.path = str(path)
+        self.commands = commands
+        self._server = None
+        self._thread = None
+
+    def dispatch(self, args: List[str]) -> str:
+        command = self.commands.get(args[0])
+        if command is None:
+            return f"error unknown command {args[0]}"
+        try:
+            return command(args[1:])
+        except ValueError as exc:
+            return f"error {exc}"
+
+    def _clear_stale(self):
+        try:
+            mode = os.stat(self.path).st_mode
+        except FileNotFoundError:
+            return
+        if not stat.S_ISSOCK(mode):
+            raise ConfigurationError(f"Admin socket path {self.path} exists and is not a socket")
+        probe = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        try:
+            probe.connect(self.path)
+        except ConnectionRefusedError:
+            os.unlink(self.path)
+            return
+        finally:
+            probe.close()
+        raise ConfigurationError(f"Admin socket {self.path} is in use by another process")
+
+    def start(self):
+        if not hasattr(socketserver, "ThreadingUnixStreamServer"):
+            raise ConfigurationError("Admin sockets are not supported on this platform")
+        self._clear_stale()
+        try:
+            self._server = socketserver.ThreadingUnixStreamServer(self.path, _AdminHandler)
+        except OSError as exc:
+            raise ConfigurationError(f"Unable to bind admin socket {self.path}: {exc}")
+        os.chmod(self.path, 0o600)
+        self._server.daemon_threads = True
+        self._server.admin = self
+        self._thread = threading.Thread(
+            target=self._server.serve_forever, name="granian-admin", daemon=True
+        )
+        self._thread.start()
+        logger.info(f"Admin socket listening at: {self.path}")
+
+    def stop(self):
+        if self._server is None:
+            return
+        self._server.shutdown()
+        self._server.server_close()
+        self._server = None
+        try:
+            os.unlink(self.path)
+        except FileNotFoundError:
+            pass
+
+
+# Workers receive the commands issued to the admin socket through
+# the read end of a pipe owned by the main process.
+def watch_control(
+    conn: Connection,
+    worker: Optional[Tuple[int, int]] = None,
+    stack_dump_dir: Optional[str] = None
+):
+    def run():
+        while True:
+            try:
+                command, args = conn.recv()
+            except (EOFError, OSError):
+                return
+            if command == "log-level":
+                set_log_level(*args)
+            elif command == "stack-dump":
+                try:
+                    path = dump_stacks(stack_dump_dir, args, worker)
+                except OSError as exc:
+                    logger.error(f"Unable to write stack snapshot: {exc}")
+                    continue
+                logger.warning(f"Stack snapshot written to {path} ({args})")
+            elif command == "tls-reload":
+                from ._granian import reload_certificates
+
+                reload_certificates()
+
+    threading.Thread(target=run, name="granian-control", daemon=True).start()
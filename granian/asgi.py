@@ -4,13 +4,16 @@ from functools import wraps
 
 from ._futures import future_wrapper
 from ._granian import ASGIScope as Scope
+from .log import logger
 
 
 class LifespanProtocol:
     error_transition = 'Invalid lifespan state transition'
 
-    def __init__(self, callable):
+    def __init__(self, callable, state=None):
         self.callable = callable
+        self.state = {} if state is None else state
+        self.handler_task = None
         self.event_queue = asyncio.Queue()
         self.event_startup = asyncio.Event()
         self.event_shutdown = asyncio.Event()
@@ -25,24 +28,28 @@ class LifespanProtocol:
             await self.callable(
                 {
                     "type": "lifespan",
-                    "asgi": {"version": "3.0", "spec_version": "2.3"}
+                    "asgi": {"version": "3.0", "spec_version": "2.3"},
+                    "state": self.state
                 },
                 self.receive,
                 self.send
             )
-        except Exception:
+        except Exception as exc:
             self.errored = True
             if self.failure_startup or self.failure_shutdown:
                 return
+            if self.event_startup.is_set():
+                logger.error("Exception in ASGI application lifespan shutdown", exc_info=exc)
+                return
             self.unsupported = True
-            # self.logger.error(msg, exc_info=exc)
+            logger.info("ASGI application doesn't support the lifespan protocol")
         finally:
             self.event_startup.set()
             self.event_shutdown.set()
 
     async def startup(self):
         loop = asyncio.get_event_loop()
-        handler_task = loop.create_task(self.handle())
+        self.handler_task = loop.create_task(self.handle())
 
         await self.event_queue.put({"type": "lifespan.startup"})
         await self.event_startup.wait()
@@ -73,8 +80,7 @@ class LifespanProtocol:
         assert not self.event_shutdown.is_set(), self.error_transition
         self.event_startup.set()
         self.failure_startup = True
-        # if message.get("message"):
-        #     self.logger.error(message["message"])
+        logger.error(_failure_log("ASGI application startup failed", message))
 
     def _handle_shutdown_complete(self, message):
         assert self.event_startup.is_set(), self.error_transition
@@ -86,8 +92,7 @@ class LifespanProtocol:
         assert not self.event_shutdown.is_set(), self.error_transition
         self.event_shutdown.set()
         self.failure_shutdown = True
-        # if message.get("message"):
-        #     self.logger.error(message["message"])
+        logger.error(_failure_log("ASGI application shutdown failed", message))
 
     _event_handlers = {
         "lifespan.startup.complete": _handle_startup_complete,
@@ -98,17 +103,26 @@ class LifespanProtocol:
 
     async def send(self, message):
         handler = self._event_handlers[message["type"]]
-        handler(message)
+        handler(self, message)
+
+
+def _failure_log(msg, message):
+    if message.get("message"):
+        return f"{msg}: {message['message']}"
+    return msg
 
 
 def _noop_wrapper(proto):
     return proto
 
 
+# Streamed bodies make the application wait when the client reads slower than it sends
 def _send_http_wrapper(proto):
     @wraps(proto)
     async def send(data):
-        proto(data)
+        waiter = proto(data)
+        if waiter is not None:
+            await waiter
     return send
 
 
@@ -118,9 +132,33 @@ _send_wrappers = {
 }
 
 
-def _callback_wrapper(callback):
+# The state filled by the lifespan startup is kept by the worker along with the
+# callback, requests get a shallow copy of it from their scope
+def _callback_wrapper(callback, state=None):
     @wraps(callback)
-    def wrapper(watcher, scope: Scope):
+    def wrapper(watcher):
+        scope: Scope = watcher.scope
+        extensions = {
+            "http.response.pathsend": {},
+            "granian.scratch_dir": scope.scratch_dir,
+            "granian.deadline_remaining": scope.deadline_remaining,
+            "granian.query_params": scope.query_params,
+            "granian.worker": {
+                "id": scope.worker_id,
+                "generation": scope.worker_generation
+            }
+        }
+        tls = scope.tls_info()
+        if tls is not None:
+            # clients certificates get verified during the handshake
+            extensions["tls"] = {
+                "server_cert": None,
+                "client_cert_chain": tls[2],
+                "client_cert_name": tls[3],
+                "client_cert_error": None,
+                "tls_version": tls[0],
+                "cipher_suite": tls[1]
+            }
         coro = callback(
             {
                 "type": scope.proto,
@@ -129,16 +167,18 @@ def _callback_wrapper(callback):
                     "spec_version": "2.3"
                 },
                 "http_version": scope.http_version,
-                "server": (scope.server_ip, scope.server_port),
-                "client": (scope.client_ip, scope.client_port),
+                # connections over Unix domain sockets have no addresses
+                "server": (scope.server_ip, scope.server_port) if scope.server_ip else None,
+                "client": (scope.client_ip, scope.client_port) if scope.client_ip else None,
                 "scheme": scope.scheme,
                 "method": scope.method,
                 "root_path": "",
                 "path": scope.path,
-                "raw_path": scope.path.encode("ascii"),
+                "raw_path": scope.raw_path.encode("ascii"),
                 "query_string": scope.query_string.encode('latin-1'),
                 "headers": scope.headers,
-                "extensions": {}
+                "state": scope.state,
+                "extensions": extensions
             },
             watcher.proto.receive,
             _send_wrappers[scope.proto](watcher.proto.send)
@@ -149,4 +189,6 @@ def _callback_wrapper(callback):
             watcher,
             context=watcher.context
         )
+
+    wrapper.lifespan_state = {} if state is None else state
     return wrapper
@@ -1,17 +1,110 @@
 from pathlib import Path
-from typing import Optional
+from typing import Dict, List, Optional, Tuple
 
 import typer
 
 from .__version__ import __version__
-from .constants import Interfaces, HTTPModes, Loops, ThreadModes
-from .log import LogLevels
+from .constants import (
+    Interfaces,
+    ClientVerifyModes,
+    DisconnectPolicies,
+    ErrorFormats,
+    HeaderValidations,
+    HTTPModes,
+    Loops,
+    MountFailures,
+    PathDecodings,
+    ThreadModes,
+    WebsocketUTF8Policies
+)
+from .log import LogFormats, LogLevels
 from .server import Granian
 
 
 cli = typer.Typer(name="granian", context_settings={"ignore_unknown_options": True})
 
 
+def parse_filters(values: Optional[List[str]]) -> Dict[str, List[str]]:
+    rv = {}
+    for value in values or []:
+        key, _, filters = value.partition("=")
+        rv.setdefault(key, []).extend(
+            item.strip() for item in filters.split(",") if item.strip()
+        )
+    return rv
+
+
+def parse_synthetic_responses(
+    values: Optional[List[str]]
+) -> Dict[str, Tuple[int, Dict[str, str], str]]:
+    rv = {}
+    for value in values or []:
+        path, _, spec = value.partition("=")
+        status, _, body = spec.partition(":")
+        rv[path] = (
+            int(status),
+            {"content-type": "text/plain; charset=utf-8"},
+            body.replace("\\n", "\n")
+        )
+    return rv
+
+
+def parse_headers(values: Optional[List[str]]) -> Dict[str, str]:
+    rv = {}
+    for value in values or []:
+        key, _, val = value.partition(":")
+        rv[key.strip()] = val.strip()
+    return rv
+
+
+def parse_status_hooks(values: Optional[List[str]]) -> List[Tuple[str, str, str]]:
+    rv = []
+    for value in values or []:
+        statuses, _, spec = value.partition(":")
+        action, _, arg = spec.partition(":")
+        rv.append((statuses.strip(), action.strip().lower(), arg))
+    return rv
+
+
+def parse_duplicate_headers(values: Optional[List[str]]) -> Dict[str, str]:
+    rv = {}
+    for value in values or []:
+        key, _, mode = value.partition("=")
+        rv[key.strip().lower()] = mode.strip()
+    return rv
+
+
+def parse_log_targets(values: Optional[List[str]]) -> Dict[str, str]:
+    rv = {}
+    for value in values or []:
+        key, _, level = value.partition("=")
+        rv[key.strip()] = level.strip().lower()
+    return rv
+
+
+def parse_sni_certificates(values: Optional[List[str]]) -> Dict[str, Tuple[Path, Path]]:
+    rv = {}
+    for value in values or []:
+        host, _, files = value.partition("=")
+        cert, _, key = files.partition(",")
+        rv[host.strip()] = (Path(cert.strip()), Path(key.strip()))
+    return rv
+
+
+def parse_mounts(values: Optional[List[str]]) -> Dict[str, str]:
+    rv = {}
+    for value in values or []:
+        prefix, _, target = value.partition("=")
+        rv[prefix.strip()] = target.strip()
+    return rv
+
+
+def parse_permissions(value: Optional[str]) -> Optional[int]:
+    if value is None:
+        return None
+    return int(value, 8)
+
+
 def version_callback(value: bool):
     if value:
         typer.echo(f"{cli.info.name} {__version__}")
@@ -21,11 +114,52 @@ def version_callback(value: bool):
 @cli.command()
 def main(
     app: str = typer.Argument(..., help="Application target to serve."),
+    factory: bool = typer.Option(
+        False,
+        "--factory",
+        help="Treat the target as a callable returning the application, called once per worker"
+    ),
+    mount: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "ASGI application to serve from a path prefix, as PREFIX=TARGET, like '/api=api.main:app'; "
+            "requests matching no prefix go to the main target"
+        )
+    ),
+    mount_startup_timeout: float = typer.Option(
+        30.0,
+        min=0.0,
+        help="Seconds given to each mounted application lifespan to start up (0 for no limit)"
+    ),
+    mount_failure: MountFailures = typer.Option(
+        MountFailures.fail.value,
+        help=(
+            "Handling of mounted applications failing to start up: fail the worker boot, "
+            "or disable them, answering their requests with a 503"
+        )
+    ),
     host: str = typer.Option("127.0.0.1", help="Host address to bind to."),
     port: int = typer.Option(8000, help="Port to bind to."),
+    uds: Optional[Path] = typer.Option(
+        None,
+        help="Path of a Unix domain socket to bind to, instead of the host address and port",
+        dir_okay=False
+    ),
+    uds_permissions: Optional[str] = typer.Option(
+        None,
+        help="Permissions of the Unix domain socket, in octal notation (like 660)"
+    ),
+    fd: Optional[int] = typer.Option(
+        None,
+        min=0,
+        help=(
+            "File descriptor of an already listening socket to serve, instead of binding one; "
+            "sockets passed by systemd socket activation get picked up automatically"
+        )
+    ),
     interface: Interfaces = typer.Option(
         Interfaces.RSGI.value,
-        help="Application interface type."
+        help="Application interface type, or auto to detect it from the application."
     ),
     http: HTTPModes = typer.Option(
         HTTPModes.auto.value,
@@ -37,8 +171,23 @@ def main(
         help="Enable websockets handling",
         show_default="enabled"
     ),
-    workers: int = typer.Option(1, min=1, help="Number of worker processes."),
-    threads: int = typer.Option(1, min=1, help="Number of threads."),
+    workers: Optional[int] = typer.Option(
+        None,
+        min=1,
+        help="Number of worker processes, sized on the CPU and memory limits of the container by default.",
+        show_default="auto"
+    ),
+    workers_max_rss: Optional[int] = typer.Option(
+        None,
+        min=1,
+        help="Resident memory ceiling of workers in MiB: workers exceeding it get gracefully recycled"
+    ),
+    threads: Optional[int] = typer.Option(
+        None,
+        min=1,
+        help="Number of threads, sharing the CPUs left by the workers by default.",
+        show_default="auto"
+    ),
     threading_mode: ThreadModes = typer.Option(
         ThreadModes.workers.value,
         help="Threading mode to use."
@@ -49,11 +198,469 @@ def main(
         min=128,
         help="Maximum number of connections to hold in backlog."
     ),
+    http2_max_concurrent_streams: int = typer.Option(
+        0,
+        min=0,
+        help="Maximum number of concurrent streams per HTTP/2 connection (0 for no limit)"
+    ),
+    http2_initial_stream_window_size: int = typer.Option(
+        1048576,
+        min=1,
+        max=2147483647,
+        help="Initial HTTP/2 flow-control window size for streams"
+    ),
+    http2_initial_connection_window_size: int = typer.Option(
+        1048576,
+        min=1,
+        max=2147483647,
+        help="Initial HTTP/2 flow-control window size for connections"
+    ),
+    http2_adaptive_window: bool = typer.Option(
+        False,
+        "--http2-adaptive-window/--no-http2-adaptive-window",
+        help="Grow HTTP/2 flow-control windows based on the measured connection bandwidth-delay product"
+    ),
+    request_filter: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Request body filters to apply on a path prefix, matching whole segments, "
+            "as PREFIX=FILTER[,FILTER]. "
+            "Available filters: strip_bom, normalize_newlines, latin1_to_utf8, "
+            "inject_banner:HTML, replace:FROM|TO"
+        )
+    ),
+    response_filter: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Response body filters to apply on a content type (trailing * allowed), "
+            "as TYPE=FILTER[,FILTER]. Accepts the same filters of --request-filter"
+        )
+    ),
+    synthetic_response: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Static text response to serve on a path without calling the application, "
+            "as PATH=STATUS:BODY. The body can reference {method}, {path}, "
+            "{query_string}, {host}, {scheme} and {client}, or be loaded from secrets, "
+            "as env:VARIABLE or file:PATH. Only GET and HEAD requests are answered"
+        )
+    ),
+    idempotency_ttl: int = typer.Option(
+        0,
+        min=0,
+        help=(
+            "Seconds to cache POST/PUT responses by their Idempotency-Key header "
+            "and replay them to retries of the same client (0 to disable). Keys reused "
+            "with a different body get a 422, and bodies over 1 MiB are not cached"
+        )
+    ),
+    status_hook: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Hook applied to responses by status, a code or a class like 5xx, as STATUS:ACTION[:VALUE]. "
+            "Actions are header (with NAME:VALUE), body (a template like the ones of --synthetic-response, "
+            "or a secret, as file:PATH) and log (with an optional message)"
+        )
+    ),
+    file_drop_cache_size: int = typer.Option(
+        0,
+        min=0,
+        help=(
+            "Size in bytes above which served files get dropped from the page cache "
+            "while streaming, to keep one-off downloads from evicting hot data (0 to disable)"
+        )
+    ),
+    file_cache_size: int = typer.Option(
+        0,
+        min=0,
+        help="Number of open file handles to keep for served files (0 to disable)"
+    ),
+    file_cache_ttl: float = typer.Option(
+        1.0,
+        min=0,
+        help="Seconds before a cached file handle gets checked again against its path"
+    ),
+    deadline_header: Optional[str] = typer.Option(
+        None,
+        help=(
+            "Request header carrying the caller time budget (like grpc-timeout), "
+            "enforced as the request timeout"
+        )
+    ),
+    deadline_trusted: Optional[List[str]] = typer.Option(
+        None,
+        help="Address or network (CIDR) allowed to set request deadlines (defaults to localhost)"
+    ),
+    disconnect_policy: DisconnectPolicies = typer.Option(
+        DisconnectPolicies.discard.value,
+        help=(
+            "Handling of responses sent by the application once the client disconnected: "
+            "discard them, raise an error into the application or cancel it"
+        )
+    ),
+    websocket_origin: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Origin allowed to open websockets, like 'https://example.com' or '*' for any "
+            "(defaults to the requested host only)"
+        )
+    ),
+    websocket_utf8: WebsocketUTF8Policies = typer.Option(
+        WebsocketUTF8Policies.strict.value,
+        help=(
+            "Handling of incoming websocket text messages: validate them as UTF-8, closing "
+            "the connection with the 1007 code on invalid ones, or deliver them as bytes "
+            "with no validation"
+        )
+    ),
+    metrics_route: Optional[List[str]] = typer.Option(
+        None,
+        help="Route template used to label metrics, like '/rooms/{room}'"
+    ),
+    duplicate_header: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Handling of a repeated request header, as NAME=MODE: join its values in a list, "
+            "keep the first one or reject the request (host and content-length are rejected by default)"
+        )
+    ),
+    path_decoding: PathDecodings = typer.Option(
+        PathDecodings.raw.value,
+        help=(
+            "Request path exposed to applications: as received, percent-decoded keeping "
+            "malformed escapes and encoded slashes, or percent-decoded rejecting them"
+        )
+    ),
+    options_route: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Methods to answer OPTIONS requests with, as ROUTE=METHOD,METHOD "
+            "(use * to set the methods reported for the whole server)"
+        )
+    ),
+    allowed_host: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Host name the server answers for, like 'example.com' or '*.example.com' for its subdomains; "
+            "requests for other hosts are rejected (defaults to any host)"
+        )
+    ),
+    presign_route: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Route template, like '/uploads/{key}', only accepting S3-compatible presigned URLs "
+            "(AWS Signature Version 4 query parameters)"
+        )
+    ),
+    presign_credential: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Credential accepted for presigned URLs, as ACCESS_KEY:SECRET_KEY. "
+            "Secret keys can be loaded from secrets, as env:VARIABLE or file:PATH"
+        )
+    ),
+    presign_region: str = typer.Option(
+        "us-east-1",
+        help="Region expected in the credential scope of presigned URLs"
+    ),
+    connection_trace_sample: int = typer.Option(
+        0,
+        min=0,
+        help=(
+            "Trace 1 in N connections, logging the timeline of their events as JSON "
+            "when they get closed (0 to disable)"
+        )
+    ),
+    slo_objective: Optional[float] = typer.Option(
+        None,
+        min=0.0,
+        max=1.0,
+        help=(
+            "Share of the requests expected to succeed, like 0.999: when set, workers log alerts "
+            "as the error budget burns too fast"
+        )
+    ),
+    slo_latency: Optional[float] = typer.Option(
+        None,
+        help="Seconds within which responses should start for the latency objective, rounded up to the metrics buckets"
+    ),
+    slo_interval: float = typer.Option(
+        10.0,
+        help="Seconds between evaluations of the objectives"
+    ),
+    metrics_path: Optional[str] = typer.Option(
+        None,
+        help="Reserved path, like '/metrics', answered with the worker metrics in the Prometheus text format"
+    ),
+    metrics_address: str = typer.Option(
+        "127.0.0.1",
+        help="Host address the dedicated metrics listeners bind to"
+    ),
+    metrics_port: Optional[int] = typer.Option(
+        None,
+        min=1,
+        max=65535,
+        help="Port serving the metrics on '/metrics', offset by the worker id for every worker after the first one"
+    ),
+    stack_dump_dir: Optional[Path] = typer.Option(
+        None,
+        help="Directory where stack snapshots of the workers get written (defaults to the temporary one)",
+        exists=True,
+        file_okay=False,
+        dir_okay=True,
+        writable=True,
+        resolve_path=True
+    ),
+    stack_dump_threshold: Optional[float] = typer.Option(
+        None,
+        help="Seconds after which a request handler still running triggers a stack snapshot of its worker"
+    ),
+    idle_timeout: float = typer.Option(
+        30.0,
+        min=0.0,
+        help=(
+            "Seconds after which connections not sending any request get closed, "
+            "regardless of keep-alive (0 to disable)"
+        )
+    ),
+    drain_timeout: float = typer.Option(
+        30.0,
+        min=0.0,
+        help=(
+            "Seconds given to workers on shutdown to finish the requests in flight and the websocket sessions "
+            "before closing them (0 to wait with no limit)"
+        )
+    ),
+    handler_cancellation: bool = typer.Option(
+        False,
+        "--handler-cancellation/--no-handler-cancellation",
+        help=(
+            "Cancel the tasks of the request handlers whose response is no longer awaited, "
+            "on client disconnects, expired deadlines and shutdown"
+        )
+    ),
+    cancellation_grace: float = typer.Option(
+        0.0,
+        min=0.0,
+        help="Seconds given to abandoned request handlers to end on their own before cancelling them"
+    ),
+    cancellation_exempt: Optional[List[str]] = typer.Option(
+        None,
+        help="Route template of the request handlers which should never be cancelled, like '/payments/{id}'"
+    ),
+    header_validation: HeaderValidations = typer.Option(
+        HeaderValidations.strict.value,
+        help=(
+            "Handling of invalid response headers set by the application, like values with CR or LF: "
+            "fail the response, or strip the invalid bytes and drop the invalid names"
+        )
+    ),
+    forwarded_trusted: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Address or network (CIDR) of the proxies trusted to set the client address and scheme "
+            "with the Forwarded or X-Forwarded-For/X-Forwarded-Proto headers"
+        )
+    ),
+    protocol_strict: bool = typer.Option(
+        True,
+        "--protocol-strict/--protocol-lenient",
+        help=(
+            "Fail the requests of RSGI and ASGI applications violating their protocol, like sending events "
+            "out of order or values of the wrong type, or coerce the violations on a best-effort basis"
+        )
+    ),
+    access_log: Optional[str] = typer.Option(
+        None,
+        help="Write access log lines to 'stderr', to the 'granian.access' Python logger with 'python', or to the given file"
+    ),
+    access_log_format: str = typer.Option(
+        "common",
+        help=(
+            "Access log format: either 'common', 'combined', 'json' for JSON records of requests "
+            "and websocket sessions, or a custom one made of Apache like tokens"
+        )
+    ),
+    grpc_web: bool = typer.Option(
+        False,
+        "--grpc-web/--no-grpc-web",
+        help=(
+            "Translate gRPC-Web and streaming Connect calls to plain request bodies, "
+            "framing the responses back"
+        ),
+        show_default="disabled"
+    ),
+    transcoding: bool = typer.Option(
+        False,
+        "--transcoding/--no-transcoding",
+        help=(
+            "Transcode MessagePack and CBOR request bodies to JSON, and JSON responses "
+            "to the format preferred by the client Accept header"
+        ),
+        show_default="disabled"
+    ),
+    uploads_path: Optional[str] = typer.Option(
+        None,
+        help="Path, like '/uploads', under which resumable uploads are served following the tus protocol"
+    ),
+    uploads_dir: Optional[Path] = typer.Option(
+        None,
+        help="Directory storing the resumable uploads",
+        file_okay=False,
+        dir_okay=True,
+        writable=True,
+        resolve_path=True
+    ),
+    uploads_max_size: int = typer.Option(
+        0,
+        min=0,
+        help="Maximum size in bytes of resumable uploads (0 for no limit)"
+    ),
+    otel_endpoint: Optional[str] = typer.Option(
+        None,
+        help=(
+            "Export OpenTelemetry spans of connections, requests and application calls "
+            "to the given OTLP/HTTP collector (http:// URL)"
+        )
+    ),
+    otel_service_name: str = typer.Option("granian", help="Service name of the exported OpenTelemetry spans"),
+    otel_sample_ratio: float = typer.Option(
+        1.0,
+        min=0.0,
+        max=1.0,
+        help="Ratio of the traces started by Granian to sample, requests with a parent trace follow its decision"
+    ),
+    compression: bool = typer.Option(
+        False,
+        "--compression/--no-compression",
+        help=(
+            "Compress responses with gzip or deflate, as negotiated with the client Accept-Encoding header "
+            "(brotli and zstd are not supported)"
+        ),
+        show_default="disabled"
+    ),
+    compression_min_size: int = typer.Option(
+        1024,
+        min=0,
+        help="Minimum size in bytes of the response bodies to compress, streamed bodies are always compressed"
+    ),
+    compression_types: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Content type of the responses to compress, with an optional '*' wildcard like 'text/*' "
+            "(defaults to text, JSON, JavaScript, XML and SVG types)"
+        )
+    ),
+    decompression: bool = typer.Option(
+        False,
+        "--decompression/--no-decompression",
+        help=(
+            "Decompress gzip and deflate request bodies before passing them to the application "
+            "(brotli and zstd bodies are passed as they are)"
+        ),
+        show_default="disabled"
+    ),
+    decompression_max_size: int = typer.Option(
+        16777216,
+        min=0,
+        help="Maximum size in bytes of the decompressed request bodies, larger requests get rejected"
+    ),
+    header_read_timeout: float = typer.Option(
+        30.0,
+        min=0.0,
+        help="Seconds allowed to receive a request head, from its first byte (0 to disable)"
+    ),
+    body_read_timeout: float = typer.Option(
+        60.0,
+        min=0.0,
+        help="Seconds allowed between the chunks of a request body being received (0 to disable)"
+    ),
+    request_timeout: float = typer.Option(
+        0.0,
+        min=0.0,
+        help="Seconds allowed to receive a whole request, its head and body (0 to disable)"
+    ),
+    max_body_size: int = typer.Option(
+        0,
+        min=0,
+        help="Maximum size in bytes of the request bodies, larger requests get rejected (0 to disable)"
+    ),
+    max_header_size: int = typer.Option(
+        65536,
+        min=0,
+        help="Maximum size in bytes of the request header fields, larger requests get rejected (0 to disable)"
+    ),
+    max_headers: int = typer.Option(
+        100,
+        min=0,
+        max=100,
+        help="Maximum number of request header fields, requests with more get rejected (0 to disable)"
+    ),
+    max_uri_length: int = typer.Option(
+        8192,
+        min=0,
+        help="Maximum length in bytes of the request URIs, longer requests get rejected (0 to disable)"
+    ),
+    keep_alive_timeout: float = typer.Option(
+        30.0,
+        min=0.0,
+        help="Seconds after which keep-alive connections idle since their last response get closed (0 to disable)"
+    ),
+    keep_alive_max_requests: int = typer.Option(
+        0,
+        min=0,
+        help="Number of requests after which keep-alive connections get closed (0 to disable)"
+    ),
+    keep_alive_header: bool = typer.Option(
+        False,
+        "--keep-alive-header/--no-keep-alive-header",
+        help="Advertise the keep-alive limits to clients with the Keep-Alive response header"
+    ),
+    long_poll_route: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Route template of long-poll endpoints, like '/events/{channel}', whose requests get parked "
+            "with their request state released while awaiting the application"
+        )
+    ),
+    proxy_protocol: bool = typer.Option(
+        False,
+        "--proxy-protocol/--no-proxy-protocol",
+        help=(
+            "Expect connections to start with a PROXY protocol (v1 or v2) header, "
+            "and use the client address it carries"
+        ),
+        show_default="disabled"
+    ),
+    error_format: ErrorFormats = typer.Option(
+        ErrorFormats.auto.value,
+        help=(
+            "Format of the error responses generated by the server: "
+            "negotiated with the client from the Accept header, JSON problem details, HTML or plain text"
+        )
+    ),
     log_level: LogLevels = typer.Option(
         LogLevels.info.value,
         help="Log level",
         case_sensitive=False
     ),
+    log_target: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Log level of a single logger, as NAME=LEVEL; Rust modules are "
+            "named by their path, like 'granian::ws'"
+        )
+    ),
+    log_format: LogFormats = typer.Option(
+        LogFormats.plain.value,
+        help="Log output format: plain text, or JSON lines with a record per event",
+        case_sensitive=False
+    ),
+    admin_socket: Optional[Path] = typer.Option(
+        None,
+        help="Path of a unix socket accepting admin commands, like switching log levels at runtime or dumping stacks",
+        dir_okay=False
+    ),
     ssl_keyfile: Optional[Path] = typer.Option(
         None,
         help="SSL key file",
@@ -70,6 +677,42 @@ def main(
         dir_okay=False,
         readable=True
     ),
+    ssl_record_size_initial: int = typer.Option(
+        0,
+        min=0,
+        max=16384,
+        help="TLS record size used for new and idle connections (0 to disable dynamic sizing)"
+    ),
+    ssl_record_size_max: int = typer.Option(
+        16384,
+        min=32,
+        max=16384,
+        help="Maximum TLS record size"
+    ),
+    ssl_reload_interval: float = typer.Option(
+        0.0,
+        min=0.0,
+        help="Interval in seconds between checks for changed certificate files (0 to disable)"
+    ),
+    ssl_ca: Optional[Path] = typer.Option(
+        None,
+        help="CA bundle to verify client certificates with, enabling mutual TLS",
+        exists=True,
+        file_okay=True,
+        dir_okay=False,
+        readable=True
+    ),
+    ssl_client_verify: ClientVerifyModes = typer.Option(
+        ClientVerifyModes.required.value,
+        help="Whether clients must present a certificate (required) or can go without one (optional)"
+    ),
+    ssl_sni: Optional[List[str]] = typer.Option(
+        None,
+        help=(
+            "Certificate to serve to clients asking for a hostname through SNI, as HOST=CERT,KEY "
+            "(like *.example.com=example.pem,example-key.pem); others get the default certificate"
+        )
+    ),
     _: Optional[bool] = typer.Option(
         None,
         "--version",
@@ -84,6 +727,7 @@ def main(
         port=port,
         interface=interface,
         workers=workers,
+        workers_max_rss=workers_max_rss,
         threads=threads,
         pthreads=threads,
         threading_mode=threading_mode,
@@ -91,7 +735,93 @@ def main(
         http=http,
         websockets=websockets,
         backlog=backlog,
+        http2_max_concurrent_streams=http2_max_concurrent_streams,
+        http2_initial_stream_window_size=http2_initial_stream_window_size,
+        http2_initial_connection_window_size=http2_initial_connection_window_size,
+        http2_adaptive_window=http2_adaptive_window,
+        request_filters=parse_filters(request_filter),
+        response_filters=parse_filters(response_filter),
+        synthetic_responses=parse_synthetic_responses(synthetic_response),
+        idempotency_ttl=idempotency_ttl,
+        status_hooks=parse_status_hooks(status_hook),
+        file_drop_cache_size=file_drop_cache_size,
+        file_cache_size=file_cache_size,
+        file_cache_ttl=file_cache_ttl,
+        deadline_header=deadline_header,
+        deadline_trusted=deadline_trusted or None,
+        disconnect_policy=disconnect_policy,
+        websocket_origins=websocket_origin,
+        websocket_utf8=websocket_utf8,
+        metrics_routes=metrics_route,
+        error_format=error_format,
+        duplicate_headers=parse_duplicate_headers(duplicate_header),
+        path_decoding=path_decoding,
+        options_routes=parse_filters(options_route),
+        allowed_hosts=allowed_host,
+        presign_routes=presign_route,
+        presign_credentials=parse_headers(presign_credential),
+        presign_region=presign_region,
+        connection_trace_sample=connection_trace_sample,
+        slo_objective=slo_objective,
+        slo_latency=slo_latency,
+        slo_interval=slo_interval,
+        metrics_path=metrics_path,
+        metrics_address=metrics_address,
+        metrics_port=metrics_port,
+        stack_dump_dir=stack_dump_dir,
+        stack_dump_threshold=stack_dump_threshold,
+        idle_timeout=idle_timeout,
+        proxy_protocol=proxy_protocol,
+        forwarded_trusted=forwarded_trusted,
+        access_log=access_log,
+        access_log_format=access_log_format,
+        grpc_web=grpc_web,
+        transcoding=transcoding,
+        uploads_path=uploads_path,
+        uploads_dir=uploads_dir,
+        uploads_max_size=uploads_max_size,
+        otel_endpoint=otel_endpoint,
+        otel_service_name=otel_service_name,
+        otel_sample_ratio=otel_sample_ratio,
+        compression=compression,
+        compression_min_size=compression_min_size,
+        compression_types=compression_types,
+        decompression=decompression,
+        decompression_max_size=decompression_max_size,
+        header_read_timeout=header_read_timeout,
+        body_read_timeout=body_read_timeout,
+        request_timeout=request_timeout,
+        max_body_size=max_body_size,
+        max_header_size=max_header_size,
+        max_headers=max_headers,
+        max_uri_length=max_uri_length,
+        keep_alive_timeout=keep_alive_timeout,
+        keep_alive_max_requests=keep_alive_max_requests,
+        keep_alive_header=keep_alive_header,
+        drain_timeout=drain_timeout,
+        handler_cancellation=handler_cancellation,
+        cancellation_grace=cancellation_grace,
+        cancellation_exempt=cancellation_exempt,
+        long_poll_routes=long_poll_route,
+        header_validation=header_validation,
+        protocol_strict=protocol_strict,
+        factory=factory,
+        mounts=parse_mounts(mount),
+        mount_startup_timeout=mount_startup_timeout,
+        mount_failure=mount_failure,
         log_level=log_level,
+        log_targets=parse_log_targets(log_target),
+        log_format=log_format,
+        admin_socket=admin_socket,
         ssl_cert=ssl_certificate,
-        ssl_key=ssl_keyfile
+        ssl_key=ssl_keyfile,
+        ssl_record_size_initial=ssl_record_size_initial,
+        ssl_record_size_max=ssl_record_size_max,
+        ssl_reload_interval=ssl_reload_interval,
+        ssl_ca=ssl_ca,
+        ssl_client_verify=ssl_client_verify,
+        ssl_sni=parse_sni_certificates(ssl_sni),
+        uds=uds,
+        uds_permissions=parse_permissions(uds_permissions),
+        fd=fd
     ).serve()
@@ -2,6 +2,7 @@ from enum import Enum
 
 
 class Interfaces(str, Enum):
+    AUTO = "auto"
     ASGI = "asgi"
     RSGI = "rsgi"
     WSGI = "wsgi"
@@ -16,6 +17,46 @@ class HTTPModes(str, Enum):
 class ThreadModes(str, Enum):
     runtime = "runtime"
     workers = "workers"
+    sharded = "sharded"
+
+
+class DisconnectPolicies(str, Enum):
+    discard = "discard"
+    error = "error"
+    cancel = "cancel"
+
+
+class HeaderValidations(str, Enum):
+    strict = "strict"
+    sanitize = "sanitize"
+
+
+class ClientVerifyModes(str, Enum):
+    optional = "optional"
+    required = "required"
+
+
+class ErrorFormats(str, Enum):
+    auto = "auto"
+    json = "json"
+    html = "html"
+    text = "text"
+
+
+class PathDecodings(str, Enum):
+    raw = "raw"
+    lenient = "lenient"
+    strict = "strict"
+
+
+class WebsocketUTF8Policies(str, Enum):
+    strict = "strict"
+    bytes = "bytes"
+
+
+class MountFailures(str, Enum):
+    fail = "fail"
+    disable = "disable"
 
 
 class Loops(str, Enum):
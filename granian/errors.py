@@ -0,0 +1,21 @@
+from ._granian import (
+    AppError,
+    BindError,
+    GranianError,
+    ProtocolError,
+    ProtocolViolationError,
+    ResponseStateError,
+    TimeoutError,
+    TlsError
+)
+
+
+class ConfigurationError(GranianError):
+    code = "config"
+    exit_status = 78
+
+
+# Raised by workers when the application fails to start up, aborting their boot
+class StartupError(GranianError):
+    code = "startup"
+    exit_status = 3
@@ -1,8 +1,13 @@
 import copy
+import json
 import logging
 import logging.config
+import time
 
 from enum import Enum
+from typing import Dict, Optional, Tuple
+
+from ._granian import reset_log_levels
 
 
 class LogLevels(str, Enum):
@@ -14,6 +19,11 @@ class LogLevels(str, Enum):
     debug = "debug"
 
 
+class LogFormats(str, Enum):
+    plain = "plain"
+    json = "json"
+
+
 log_levels_map = {
     LogLevels.critical: logging.CRITICAL,
     LogLevels.error: logging.ERROR,
@@ -27,11 +37,18 @@ LOGGING_CONFIG = {
     "version": 1,
     "disable_existing_loggers": False,
     "root": {"level": "INFO", "handlers": ["console"]},
+    "loggers": {
+        "granian.access": {"level": "INFO", "handlers": ["access"], "propagate": False}
+    },
     "formatters": {
         "generic": {
             "()": "logging.Formatter",
             "fmt": "[%(levelname)s] %(message)s",
             "datefmt": "[%Y-%m-%d %H:%M:%S %z]"
+        },
+        "access": {
+            "()": "logging.Formatter",
+            "fmt": "%(message)s"
         }
     },
     "handlers": {
@@ -39,14 +56,93 @@ LOGGING_CONFIG = {
             "formatter": "generic",
             "class": "logging.StreamHandler",
             "stream": "ext://sys.stdout",
+        },
+        "access": {
+            "formatter": "access",
+            "class": "logging.StreamHandler",
+            "stream": "ext://sys.stdout",
         }
     }
 }
 
+WORKER_LOG_FORMAT = "[%(levelname)s] [worker-%(worker_id)s.%(worker_generation)s] %(message)s"
+
+
+# Writes records as JSON lines, with the worker identity when emitted by one.
+# Access lines already written as JSON records pass through untouched.
+class JSONFormatter(logging.Formatter):
+    def format(self, record: logging.LogRecord) -> str:
+        message = record.getMessage()
+        if record.name == "granian.access" and message.startswith("{"):
+            return message
+        data = {
+            "time": self.formatTime(record),
+            "level": record.levelname.lower(),
+            "logger": record.name,
+            "message": message
+        }
+        if hasattr(record, "worker_id"):
+            data["worker"] = record.worker_id
+            data["generation"] = record.worker_generation
+        if record.exc_info:
+            data["exception"] = self.formatException(record.exc_info)
+        return json.dumps(data, separators=(",", ":"))
+
+    def formatTime(self, record: logging.LogRecord, datefmt: Optional[str] = None) -> str:
+        secs = time.strftime("%Y-%m-%dT%H:%M:%S", time.gmtime(record.created))
+        return f"{secs}.{int(record.msecs):03d}Z"
+
 logger = logging.getLogger()
+_record_factory = logging.getLogRecordFactory()
+
+
+# Tags the records emitted from a worker process with its identity, so application
+# formatters can also reference the `worker_id` and `worker_generation` attributes.
+def _worker_record_factory(worker_id: int, generation: int):
+    def factory(*args, **kwargs):
+        record = _record_factory(*args, **kwargs)
+        record.worker_id = worker_id
+        record.worker_generation = generation
+        return record
+    return factory
+
 
+# Rust module paths, like `granian::ws`, name the loggers of the extension module
+def log_target(name: str) -> str:
+    if "::" not in name:
+        return "" if name == "root" else name
+    parts = name.split("::")
+    if parts[0] == "granian":
+        parts[0] = "_granian"
+    return ".".join(parts)
 
-def configure_logging(level: LogLevels):
+
+def set_log_level(target: str, level: Optional[LogLevels]):
+    name = log_target(target)
+    if not name and level is None:
+        raise ValueError("The root log level can't be reset")
+    logging.getLogger(name).setLevel(
+        logging.NOTSET if level is None else log_levels_map[LogLevels(level)]
+    )
+    reset_log_levels()
+
+
+def configure_logging(
+    level: LogLevels,
+    worker: Optional[Tuple[int, int]] = None,
+    targets: Optional[Dict[str, LogLevels]] = None,
+    fmt: LogFormats = LogFormats.plain
+):
     config = copy.deepcopy(LOGGING_CONFIG)
     config["root"]["level"] = log_levels_map[level]
+    if worker is not None:
+        logging.setLogRecordFactory(_worker_record_factory(*worker))
+        config["formatters"]["generic"]["fmt"] = WORKER_LOG_FORMAT
+    if LogFormats(fmt) == LogFormats.json:
+        config["formatters"] = {
+            "generic": {"()": JSONFormatter},
+            "access": {"()": JSONFormatter}
+        }
     logging.config.dictConfig(config)
+    for target, target_level in (targets or {}).items():
+        set_log_level(target, target_level)
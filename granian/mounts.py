@@ -0,0 +1,142 @@
+import asyncio
+
+from typing import Any, Callable, Dict, List, Optional
+
+from ._internal import load_target
+from .asgi import LifespanProtocol
+from .constants import MountFailures
+from .log import logger
+
+
+class Mount:
+    def __init__(self, prefix: str, app: Any, state: Dict[str, Any]):
+        self.prefix = prefix
+        self.app = app
+        self.state = state
+        self.lifespan: Optional[LifespanProtocol] = None
+        self.disabled = False
+
+    @property
+    def name(self) -> str:
+        return f"application mounted at {self.prefix}" if self.prefix else "main application"
+
+    def matches(self, path: str) -> bool:
+        return not self.prefix or path == self.prefix or path.startswith(self.prefix + "/")
+
+
+# Serves several ASGI applications from path prefixes, the target one taking the
+# requests matching no prefix. The lifespan of every application gets run within
+# the one of the mounts: the startup completes once all of them started, each
+# given `startup_timeout` seconds (0 for no limit), while mounted applications
+# failing to start up either fail the whole startup or get disabled, depending
+# on `failure`. Requests to disabled applications get a 503 response.
+class MountedApps:
+    def __init__(
+        self,
+        app: Any,
+        mounts: Dict[str, Any],
+        startup_timeout: float = 30.0,
+        failure: MountFailures = MountFailures.fail
+    ):
+        # longer prefixes first, so nested mounts take precedence
+        self.mounts: List[Mount] = sorted(
+            [Mount(prefix.rstrip("/"), mounted, {}) for prefix, mounted in mounts.items()],
+            key=lambda mount: len(mount.prefix),
+            reverse=True
+        )
+        self.root = Mount("", app, {})
+        self.startup_timeout = max(0.0, startup_timeout)
+        self.failure = MountFailures(failure)
+
+    @property
+    def _all(self) -> List[Mount]:
+        return [self.root, *self.mounts]
+
+    async def _startup(self, mount: Mount) -> bool:
+        mount.lifespan = LifespanProtocol(mount.app, mount.state)
+        try:
+            await asyncio.wait_for(mount.lifespan.startup(), self.startup_timeout or None)
+        except asyncio.TimeoutError:
+            mount.lifespan.handler_task.cancel()
+            logger.error(f"ASGI {mount.name} didn't start up within {self.startup_timeout}s")
+            return False
+        return not mount.lifespan.interrupt
+
+    async def startup(self) -> Optional[str]:
+        started = await asyncio.gather(*[self._startup(mount) for mount in self._all])
+        failed = [mount for mount, ok in zip(self._all, started) if not ok]
+        fatal = next(
+            (mount for mount in failed if mount is self.root or self.failure == MountFailures.fail),
+            None
+        )
+        for mount in failed:
+            mount.disabled = True
+        if fatal is not None:
+            # the applications which did start up still get shut down
+            await self.shutdown()
+            return f"ASGI {fatal.name} failed to start up"
+        for mount in failed:
+            logger.warning(f"Disabling ASGI {mount.name}, as it failed to start up")
+        return None
+
+    async def shutdown(self) -> bool:
+        running = [mount.lifespan for mount in self._all if not mount.disabled and mount.lifespan]
+        await asyncio.gather(*[lifespan.shutdown() for lifespan in running])
+        return not any(lifespan.interrupt for lifespan in running)
+
+    async def _lifespan(self, scope, receive, send):
+        self.root.state = scope["state"]
+        await receive()
+        failure = await self.startup()
+        if failure is not None:
+            await send({"type": "lifespan.startup.failed", "message": failure})
+            return
+        await send({"type": "lifespan.startup.complete"})
+        await receive()
+        if await self.shutdown():
+            await send({"type": "lifespan.shutdown.complete"})
+        else:
+            await send({"type": "lifespan.shutdown.failed", "message": "Mounted ASGI applications failed to shut down"})
+
+    def _match(self, path: str) -> Mount:
+        for mount in self.mounts:
+            if mount.matches(path):
+                return mount
+        return self.root
+
+    async def __call__(self, scope, receive, send):
+        if scope["type"] == "lifespan":
+            return await self._lifespan(scope, receive, send)
+        mount = self._match(scope["path"])
+        if mount.disabled:
+            return await _unavailable(scope, send)
+        if mount is not self.root:
+            # as per the ASGI spec, `path` keeps the prefix, also added to `root_path`
+            scope = dict(scope, root_path=scope.get("root_path", "") + mount.prefix, state=mount.state.copy())
+        return await mount.app(scope, receive, send)
+
+
+async def _unavailable(scope, send):
+    if scope["type"] == "websocket":
+        await send({"type": "websocket.close", "code": 1013})
+        return
+    await send({
+        "type": "http.response.start",
+        "status": 503,
+        "headers": [(b"content-type", b"text/plain; charset=utf-8")]
+    })
+    await send({"type": "http.response.body", "body": b"Service Unavailable"})
+
+
+def load_mounts(
+    loader: Callable[[], Any],
+    mounts: Dict[str, str],
+    startup_timeout: float,
+    failure: MountFailures
+) -> MountedApps:
+    return MountedApps(
+        loader(),
+        {prefix: load_target(target) for prefix, target in mounts.items()},
+        startup_timeout,
+        failure
+    )
@@ -1,4 +1,6 @@
 import copyreg
+import os
+from typing import List
 
 from ._granian import ListenerHolder as SocketHolder
 
@@ -6,3 +8,19 @@ copyreg.pickle(
     SocketHolder,
     lambda v: (SocketHolder, v.__getstate__())
 )
+
+SD_LISTEN_FDS_START = 3
+
+
+# The sockets passed by systemd socket activation, as `sd_listen_fds` does: the
+# variables get unset, so the processes spawned later don't pick them up again.
+def systemd_listen_fds() -> List[int]:
+    if os.environ.get("LISTEN_PID") != str(os.getpid()):
+        return []
+    try:
+        count = int(os.environ.get("LISTEN_FDS", "0"))
+    except ValueError:
+        count = 0
+    for key in ("LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"):
+        os.environ.pop(key, None)
+    return list(range(SD_LISTEN_FDS_START, SD_LISTEN_FDS_START + max(0, count)))
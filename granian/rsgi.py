@@ -1,10 +1,14 @@
+import inspect
+
 from enum import Enum
 from functools import wraps
 from typing import Union
 
 from ._futures import future_wrapper
 from ._granian import (
+    RSGIBodyChunk as BodyChunk,
     RSGIHTTPProtocol as HTTPProtocol,
+    RSGIHTTPStreamTransport as HTTPStreamTransport,
     RSGIWebsocketProtocol as WebsocketProtocol,
     RSGIHeaders as Headers,
     RSGIScope as Scope,
@@ -19,18 +23,38 @@ class WebsocketMessageType(int, Enum):
     string = 2
 
 
+class WebsocketMessageFragment(int, Enum):
+    none = 0
+    start = 1
+    continuation = 2
+    finish = 3
+
+
 class WebsocketMessage:
     kind: WebsocketMessageType
     data: Union[bytes, str]
+    fragment: WebsocketMessageFragment
+
+
+# Worker hooks of RSGI applications get the worker event loop, and can be either
+# plain or coroutine functions: coroutines get run to completion on that loop.
+def _call_hook(loop, target, name):
+    hook = getattr(target, name, None)
+    if hook is None:
+        return
+    rv = hook(loop)
+    if inspect.isawaitable(rv):
+        loop.run_until_complete(rv)
 
 
 def _callback_wrapper(callback):
     @wraps(callback)
-    def wrapper(watcher, scope: Scope):
+    def wrapper(watcher):
         watcher.event_loop.call_soon_threadsafe(
             future_wrapper,
-            callback(scope, watcher.proto),
+            callback(watcher.scope, watcher.proto),
             watcher,
             context=watcher.context
         )
+    wrapper.fast_path = getattr(callback, "__rsgi_fast__", None)
     return wrapper
@@ -0,0 +1,37 @@
+import os
+
+from pathlib import Path
+from typing import Any
+
+from .errors import ConfigurationError
+
+ENV_PREFIX = "env:"
+FILE_PREFIX = "file:"
+
+
+# A configuration value not to be exposed: it behaves as the plain string,
+# but its representation, used by the logs and the config dump, is redacted.
+class Secret(str):
+    __slots__ = []
+
+    def __repr__(self) -> str:
+        return "'<redacted>'"
+
+
+def resolve(value: Any) -> Any:
+    if not isinstance(value, str) or isinstance(value, Secret):
+        return value
+    if value.startswith(ENV_PREFIX):
+        name = value[len(ENV_PREFIX):]
+        if name not in os.environ:
+            raise ConfigurationError(f"Environment variable {name} is not set")
+        return Secret(os.environ[name])
+    if value.startswith(FILE_PREFIX):
+        path = Path(value[len(FILE_PREFIX):])
+        try:
+            contents = path.read_text()
+        except OSError as exc:
+            raise ConfigurationError(f"Unable to read secret file {path}: {exc.strerror}")
+        # files written by editors and secret stores usually end with a newline
+        return Secret(contents.rstrip("\r\n"))
+    return value
@@ -1,28 +1,66 @@
 import contextvars
+import math
 import multiprocessing
+import os
 import signal
 import socket
 import ssl
+import sys
 import threading
+import time
 
 from functools import partial
+from multiprocessing.connection import Connection
 from pathlib import Path
-from typing import List, Optional
+from typing import Any, Dict, List, Optional, Tuple
 
-from ._granian import ASGIWorker, RSGIWorker, WSGIWorker
-from ._internal import load_target
+from ._granian import ASGIWorker, RSGIWorker, WSGIWorker, WorkerOptions, cpu_quota, process_memory
+from ._internal import WorkerContext, detect_interface, load_factory, load_target
+from .admin import AdminServer, watch_control
 from .asgi import LifespanProtocol, _callback_wrapper as _asgi_call_wrap
-from .constants import Interfaces, HTTPModes, Loops, ThreadModes
-from .log import LogLevels, configure_logging, logger
-from .net import SocketHolder
-from .rsgi import _callback_wrapper as _rsgi_call_wrap
+from .constants import (
+    Interfaces,
+    ClientVerifyModes,
+    DisconnectPolicies,
+    ErrorFormats,
+    HeaderValidations,
+    HTTPModes,
+    Loops,
+    MountFailures,
+    PathDecodings,
+    ThreadModes,
+    WebsocketUTF8Policies
+)
+from .errors import ConfigurationError, GranianError, StartupError
+from .log import LogFormats, LogLevels, configure_logging, logger, set_log_level
+from .mounts import load_mounts
+from .net import SocketHolder, systemd_listen_fds
+from .rsgi import _call_hook as _rsgi_call_hook, _callback_wrapper as _rsgi_call_wrap
+from .secrets import Secret, resolve as resolve_secret
+from .sizing import auto_sizing
 from .wsgi import _callback_wrapper as _wsgi_call_wrap
 
 multiprocessing.allow_connection_pickling()
 
 
+# Bodies of hooks can be secrets as a whole, headers in their value only
+def _resolve_hook_value(action: str, value: str) -> str:
+    if action == "body":
+        return resolve_secret(value)
+    if action != "header" or ":" not in value:
+        return value
+    name, _, header_value = value.partition(":")
+    resolved = resolve_secret(header_value.strip())
+    return (Secret if isinstance(resolved, Secret) else str)(f"{name}:{resolved}")
+
+
 class Granian:
     SIGNALS = {signal.SIGINT, signal.SIGTERM}
+    NON_CONFIG_ATTRS = {"procs", "generations", "memory_recycles", "exit_event"}
+    MEMORY_CHECK_INTERVAL = 5.0
+    WORKERS_CHECK_INTERVAL = 1.0
+    RECYCLE_TIMEOUT = 30.0
+    SHUTDOWN_GRACE = 5.0
 
     def __init__(
         self,
@@ -30,39 +68,234 @@ class Granian:
         address: str = "127.0.0.1",
         port: int = 8000,
         interface: Interfaces = Interfaces.RSGI,
-        workers: int = 1,
-        threads: int = 1,
-        pthreads: int = 1,
+        workers: Optional[int] = None,
+        workers_max_rss: Optional[int] = None,
+        threads: Optional[int] = None,
+        pthreads: Optional[int] = 1,
         threading_mode: ThreadModes = ThreadModes.workers,
         loop: Loops = Loops.auto,
         http: HTTPModes = HTTPModes.auto,
         websockets: bool = True,
         backlog: int = 1024,
         http1_buffer_size: int = 65535,
+        http2_max_concurrent_streams: int = 0,
+        http2_initial_stream_window_size: int = 1048576,
+        http2_initial_connection_window_size: int = 1048576,
+        http2_adaptive_window: bool = False,
+        request_filters: Optional[Dict[str, List[str]]] = None,
+        response_filters: Optional[Dict[str, List[str]]] = None,
+        synthetic_responses: Optional[Dict[str, Tuple[int, Dict[str, str], str]]] = None,
+        idempotency_ttl: int = 0,
+        status_hooks: Optional[List[Tuple[str, str, str]]] = None,
+        file_drop_cache_size: int = 0,
+        file_cache_size: int = 0,
+        file_cache_ttl: float = 1.0,
+        deadline_header: Optional[str] = None,
+        deadline_trusted: Optional[List[str]] = None,
+        disconnect_policy: DisconnectPolicies = DisconnectPolicies.discard,
+        websocket_origins: Optional[List[str]] = None,
+        websocket_utf8: WebsocketUTF8Policies = WebsocketUTF8Policies.strict,
+        metrics_routes: Optional[List[str]] = None,
+        error_format: ErrorFormats = ErrorFormats.auto,
+        duplicate_headers: Optional[Dict[str, str]] = None,
+        path_decoding: PathDecodings = PathDecodings.raw,
+        options_routes: Optional[Dict[str, List[str]]] = None,
+        allowed_hosts: Optional[List[str]] = None,
+        presign_routes: Optional[List[str]] = None,
+        presign_credentials: Optional[Dict[str, str]] = None,
+        presign_region: str = "us-east-1",
+        connection_trace_sample: int = 0,
+        slo_objective: Optional[float] = None,
+        slo_latency: Optional[float] = None,
+        slo_interval: float = 10.0,
+        metrics_path: Optional[str] = None,
+        metrics_address: str = "127.0.0.1",
+        metrics_port: Optional[int] = None,
+        stack_dump_dir: Optional[Path] = None,
+        stack_dump_threshold: Optional[float] = None,
+        idle_timeout: float = 30.0,
+        proxy_protocol: bool = False,
+        forwarded_trusted: Optional[List[str]] = None,
+        access_log: Optional[str] = None,
+        access_log_format: str = "common",
+        grpc_web: bool = False,
+        transcoding: bool = False,
+        uploads_path: Optional[str] = None,
+        uploads_dir: Optional[Path] = None,
+        uploads_max_size: int = 0,
+        otel_endpoint: Optional[str] = None,
+        otel_service_name: str = "granian",
+        otel_sample_ratio: float = 1.0,
+        compression: bool = False,
+        compression_min_size: int = 1024,
+        compression_types: Optional[List[str]] = None,
+        decompression: bool = False,
+        decompression_max_size: int = 16777216,
+        header_read_timeout: float = 30.0,
+        body_read_timeout: float = 60.0,
+        request_timeout: float = 0.0,
+        max_body_size: int = 0,
+        max_header_size: int = 65536,
+        max_headers: int = 100,
+        max_uri_length: int = 8192,
+        keep_alive_timeout: float = 30.0,
+        keep_alive_max_requests: int = 0,
+        keep_alive_header: bool = False,
+        drain_timeout: float = 30.0,
+        handler_cancellation: bool = False,
+        cancellation_grace: float = 0.0,
+        cancellation_exempt: Optional[List[str]] = None,
+        long_poll_routes: Optional[List[str]] = None,
+        header_validation: HeaderValidations = HeaderValidations.strict,
+        protocol_strict: bool = True,
+        factory: bool = False,
+        mounts: Optional[Dict[str, str]] = None,
+        mount_startup_timeout: float = 30.0,
+        mount_failure: MountFailures = MountFailures.fail,
         log_level: LogLevels = LogLevels.info,
+        log_targets: Optional[Dict[str, LogLevels]] = None,
+        log_format: LogFormats = LogFormats.plain,
+        admin_socket: Optional[Path] = None,
         ssl_cert: Optional[Path] = None,
-        ssl_key: Optional[Path] = None
+        ssl_key: Optional[Path] = None,
+        ssl_record_size_initial: int = 0,
+        ssl_record_size_max: int = 16384,
+        ssl_reload_interval: float = 0.0,
+        ssl_ca: Optional[Path] = None,
+        ssl_client_verify: ClientVerifyModes = ClientVerifyModes.required,
+        ssl_sni: Optional[Dict[str, Tuple[Path, Path]]] = None,
+        uds: Optional[Path] = None,
+        uds_permissions: Optional[int] = None,
+        fd: Optional[int] = None
     ):
         self.target = target
         self.bind_addr = address
         self.bind_port = port
         self.interface = interface
-        self.workers = max(1, workers)
-        self.threads = max(1, threads)
-        self.pthreads = max(1, pthreads)
+        sizing = auto_sizing(workers, threads, workers_max_rss)
+        self.workers = sizing.workers
+        self.workers_max_rss = workers_max_rss
+        self.threads = sizing.threads
+        self.pthreads = self.threads if pthreads is None else max(1, pthreads)
         self.threading_mode = threading_mode
         self.loop = loop
         self.http = http
         self.websockets = websockets
         self.backlog = max(128, backlog)
         self.http1_buffer_size = http1_buffer_size
+        self.http2_max_concurrent_streams = max(0, http2_max_concurrent_streams)
+        self.http2_initial_stream_window_size = http2_initial_stream_window_size
+        self.http2_initial_connection_window_size = http2_initial_connection_window_size
+        self.http2_adaptive_window = http2_adaptive_window
+        self.request_filters = request_filters or {}
+        self.response_filters = response_filters or {}
+        self.synthetic_responses = [
+            (path, status, list(headers.items()), body)
+            for path, (status, headers, body) in (synthetic_responses or {}).items()
+        ]
+        self.idempotency_ttl = max(0, idempotency_ttl)
+        self.status_hooks = list(status_hooks or [])
+        self.file_drop_cache_size = max(0, file_drop_cache_size)
+        self.file_cache_size = max(0, file_cache_size)
+        self.file_cache_ttl = max(0.0, file_cache_ttl)
+        self.deadline_header = deadline_header
+        self.deadline_trusted = (
+            ["127.0.0.1", "::1"] if deadline_trusted is None else deadline_trusted
+        )
+        self.disconnect_policy = disconnect_policy
+        self.websocket_origins = websocket_origins or []
+        self.websocket_utf8 = websocket_utf8
+        self.metrics_routes = metrics_routes or []
+        self.error_format = error_format
+        self.duplicate_headers = list((duplicate_headers or {}).items())
+        self.path_decoding = path_decoding
+        self.options_routes = list((options_routes or {}).items())
+        self.allowed_hosts = allowed_hosts or []
+        self.presign_routes = presign_routes or []
+        self.presign_credentials = list((presign_credentials or {}).items())
+        self.presign_region = presign_region
+        self.connection_trace_sample = max(0, connection_trace_sample)
+        self.slo_objective = slo_objective
+        self.slo_latency = slo_latency
+        self.slo_interval = slo_interval
+        self.metrics_path = metrics_path
+        self.metrics_address = metrics_address
+        self.metrics_port = metrics_port
+        self.stack_dump_dir = str(stack_dump_dir) if stack_dump_dir else None
+        self.stack_dump_threshold = stack_dump_threshold
+        self.idle_timeout = max(0.0, idle_timeout)
+        self.proxy_protocol = proxy_protocol
+        self.forwarded_trusted = forwarded_trusted or []
+        self.access_log = access_log
+        self.access_log_format = access_log_format
+        self.grpc_web = grpc_web
+        self.transcoding = transcoding
+        self.uploads_path = uploads_path
+        self.uploads_dir = str(uploads_dir) if uploads_dir else None
+        self.uploads_max_size = uploads_max_size
+        self.otel_endpoint = otel_endpoint
+        self.otel_service_name = otel_service_name
+        self.otel_sample_ratio = otel_sample_ratio
+        self.compression = compression
+        self.compression_min_size = max(0, compression_min_size)
+        self.compression_types = compression_types or []
+        self.decompression = decompression
+        self.decompression_max_size = max(0, decompression_max_size)
+        self.header_read_timeout = max(0.0, header_read_timeout)
+        self.body_read_timeout = max(0.0, body_read_timeout)
+        self.request_timeout = max(0.0, request_timeout)
+        self.max_body_size = max(0, max_body_size)
+        self.max_header_size = max(0, max_header_size)
+        self.max_headers = max(0, max_headers)
+        self.max_uri_length = max(0, max_uri_length)
+        self.keep_alive_timeout = max(0.0, keep_alive_timeout)
+        self.keep_alive_max_requests = max(0, keep_alive_max_requests)
+        self.keep_alive_header = keep_alive_header
+        self.drain_timeout = max(0.0, drain_timeout)
+        self.handler_cancellation = handler_cancellation
+        self.cancellation_grace = max(0.0, cancellation_grace)
+        self.cancellation_exempt = cancellation_exempt or []
+        self.long_poll_routes = long_poll_routes or []
+        self.header_validation = header_validation
+        self.protocol_strict = protocol_strict
+        self.factory = factory
+        self.mounts = {prefix.rstrip("/"): target for prefix, target in (mounts or {}).items()}
+        self.mount_startup_timeout = max(0.0, mount_startup_timeout)
+        self.mount_failure = MountFailures(mount_failure)
+        self.ssl_record_size_initial = max(0, ssl_record_size_initial)
+        self.ssl_record_size_max = ssl_record_size_max
+        self.ssl_reload_interval = max(0.0, ssl_reload_interval)
+        self.ssl_ca = str(Path(ssl_ca).resolve()) if ssl_ca else None
+        self.ssl_client_verify = ClientVerifyModes(ssl_client_verify)
+        self.ssl_sni = [
+            (host, str(Path(cert).resolve()), str(Path(key).resolve()))
+            for host, (cert, key) in (ssl_sni or {}).items()
+        ]
         self.log_level = log_level
-        configure_logging(self.log_level)
+        self.log_targets = {
+            target: LogLevels(level) for target, level in (log_targets or {}).items()
+        }
+        self.log_format = LogFormats(log_format)
+        self.admin_socket = admin_socket
+        self.uds = uds
+        self.uds_permissions = uds_permissions
+        self.fd = fd
+        self._sizing = sizing if workers is None or threads is None else None
+        configure_logging(self.log_level, targets=self.log_targets, fmt=self.log_format)
         self.build_ssl_context(ssl_cert, ssl_key)
         self._shd = None
         self._sfd = None
+        self._listen_fd = None
         self.procs: List[multiprocessing.Process] = []
+        self.generations: Dict[int, int] = {}
+        self.memory_recycles: Dict[int, int] = {}
         self.exit_event = threading.Event()
+        self._admin = None
+        self._exit_status = 0
+        self._controls: Dict[int, Connection] = {}
+        self._controls_lock = threading.Lock()
+        self._retiring: List[Tuple[multiprocessing.Process, float]] = []
+        self._spawn_args = None
 
     def build_ssl_context(
         self,
@@ -84,21 +317,22 @@ class Granian:
     @staticmethod
     def _spawn_asgi_worker(
         worker_id,
+        worker_generation,
+        memory_recycles,
         callback_loader,
         socket,
         loop_impl,
-        threads,
-        pthreads,
         threading_mode,
-        http_mode,
-        http1_buffer_size,
-        websockets,
+        options,
         log_level,
-        ssl_ctx
+        log_targets,
+        log_format,
+        control
     ):
         from granian._loops import loops, set_loop_signals
 
-        configure_logging(log_level)
+        configure_logging(log_level, worker=(worker_id, worker_generation), targets=log_targets, fmt=log_format)
+        watch_control(control, (worker_id, worker_generation), options["stack_dump_dir"])
         loop = loops.get(loop_impl)
         sfd = socket.fileno()
         callback = callback_loader()
@@ -106,26 +340,19 @@ class Granian:
 
         loop.run_until_complete(lifespan_handler.startup())
         if lifespan_handler.interrupt:
-            return
+            logger.error("Aborting worker boot, as the application failed to start up")
+            sys.exit(StartupError.exit_status)
 
         shutdown_event = set_loop_signals(loop, [signal.SIGTERM, signal.SIGINT])
 
-        worker = ASGIWorker(
-            worker_id,
-            sfd,
-            threads,
-            pthreads,
-            http_mode,
-            http1_buffer_size,
-            websockets,
-            *ssl_ctx
-        )
+        worker = ASGIWorker(worker_id, worker_generation, memory_recycles, sfd, WorkerOptions(**options))
         serve = getattr(worker, {
             ThreadModes.runtime: "serve_rth",
-            ThreadModes.workers: "serve_wth"
+            ThreadModes.workers: "serve_wth",
+            ThreadModes.sharded: "serve_wth"
         }[threading_mode])
         serve(
-            _asgi_call_wrap(callback),
+            _asgi_call_wrap(callback, lifespan_handler.state),
             loop,
             contextvars.copy_context(),
             shutdown_event.wait()
@@ -136,21 +363,22 @@ class Granian:
     @staticmethod
     def _spawn_rsgi_worker(
         worker_id,
+        worker_generation,
+        memory_recycles,
         callback_loader,
         socket,
         loop_impl,
-        threads,
-        pthreads,
         threading_mode,
-        http_mode,
-        http1_buffer_size,
-        websockets,
+        options,
         log_level,
-        ssl_ctx
+        log_targets,
+        log_format,
+        control
     ):
         from granian._loops import loops, set_loop_signals
 
-        configure_logging(log_level)
+        configure_logging(log_level, worker=(worker_id, worker_generation), targets=log_targets, fmt=log_format)
+        watch_control(control, (worker_id, worker_generation), options["stack_dump_dir"])
         loop = loops.get(loop_impl)
         sfd = socket.fileno()
         target = callback_loader()
@@ -158,27 +386,19 @@ class Granian:
             getattr(target, '__rsgi__') if hasattr(target, '__rsgi__') else
             target
         )
-        callback_init = (
-            getattr(target, '__rsgi_init__') if hasattr(target, '__rsgi_init__') else
-            lambda *args, **kwargs: None
-        )
 
         shutdown_event = set_loop_signals(loop, [signal.SIGTERM, signal.SIGINT])
-        callback_init(loop)
-
-        worker = RSGIWorker(
-            worker_id,
-            sfd,
-            threads,
-            pthreads,
-            http_mode,
-            http1_buffer_size,
-            websockets,
-            *ssl_ctx
-        )
+        try:
+            _rsgi_call_hook(loop, target, "__rsgi_init__")
+        except Exception:
+            logger.exception("Aborting worker boot, as the RSGI application init failed")
+            sys.exit(StartupError.exit_status)
+
+        worker = RSGIWorker(worker_id, worker_generation, memory_recycles, sfd, WorkerOptions(**options))
         serve = getattr(worker, {
             ThreadModes.runtime: "serve_rth",
-            ThreadModes.workers: "serve_wth"
+            ThreadModes.workers: "serve_wth",
+            ThreadModes.sharded: "serve_wth"
         }[threading_mode])
         serve(
             _rsgi_call_wrap(callback),
@@ -186,43 +406,41 @@ class Granian:
             contextvars.copy_context(),
             shutdown_event.wait()
         )
+        try:
+            _rsgi_call_hook(loop, target, "__rsgi_del__")
+        except Exception:
+            logger.exception("RSGI application teardown failed")
 
     @staticmethod
     def _spawn_wsgi_worker(
         worker_id,
+        worker_generation,
+        memory_recycles,
         callback_loader,
         socket,
         loop_impl,
-        threads,
-        pthreads,
         threading_mode,
-        http_mode,
-        http1_buffer_size,
-        websockets,
+        options,
         log_level,
-        ssl_ctx
+        log_targets,
+        log_format,
+        control
     ):
         from granian._loops import loops, set_loop_signals
 
-        configure_logging(log_level)
+        configure_logging(log_level, worker=(worker_id, worker_generation), targets=log_targets, fmt=log_format)
+        watch_control(control, (worker_id, worker_generation), options["stack_dump_dir"])
         loop = loops.get(loop_impl)
         sfd = socket.fileno()
         callback = callback_loader()
 
         shutdown_event = set_loop_signals(loop, [signal.SIGTERM, signal.SIGINT])
 
-        worker = WSGIWorker(
-            worker_id,
-            sfd,
-            threads,
-            pthreads,
-            http_mode,
-            http1_buffer_size,
-            *ssl_ctx
-        )
+        worker = WSGIWorker(worker_id, worker_generation, memory_recycles, sfd, WorkerOptions(**options))
         serve = getattr(worker, {
             ThreadModes.runtime: "serve_rth",
-            ThreadModes.workers: "serve_wth"
+            ThreadModes.workers: "serve_wth",
+            ThreadModes.sharded: "serve_wth"
         }[threading_mode])
         serve(
             _wsgi_call_wrap(callback),
@@ -231,14 +449,59 @@ class Granian:
             shutdown_event.wait()
         )
 
+    # Workers detect the interface of the application they load, as factories
+    # only build it there, and boot with the matching spawner
+    @staticmethod
+    def _spawn_auto_worker(worker_id, worker_generation, memory_recycles, callback_loader, *args):
+        target = callback_loader()
+        try:
+            interface = detect_interface(target)
+        except ConfigurationError as exc:
+            logger.error(f"Aborting worker boot: {exc}")
+            sys.exit(StartupError.exit_status)
+        if worker_id == 1 and worker_generation == 1:
+            logger.info(f"Detected {interface.name} application interface")
+        spawner = {
+            Interfaces.ASGI: Granian._spawn_asgi_worker,
+            Interfaces.RSGI: Granian._spawn_rsgi_worker,
+            Interfaces.WSGI: Granian._spawn_wsgi_worker
+        }[interface]
+        spawner(worker_id, worker_generation, memory_recycles, lambda: target, *args)
+
+    # Sockets passed by a supervisor or systemd take precedence over binding
+    def _inherited_fd(self) -> Optional[int]:
+        if self.fd is not None:
+            return self.fd
+        fds = systemd_listen_fds()
+        if len(fds) > 1:
+            logger.warning(f"Received {len(fds)} sockets from systemd, only the first one gets served")
+        return fds[0] if fds else None
+
     def _init_shared_socket(self):
-        self._shd = SocketHolder.from_address(
-            self.bind_addr,
-            self.bind_port,
-            self.backlog
-        )
+        self._listen_fd = self._inherited_fd()
+        if self._listen_fd is not None:
+            self._shd = SocketHolder.from_fd(self._listen_fd)
+        elif self.uds:
+            if self.threading_mode == ThreadModes.sharded:
+                logger.warning("Listener shards are not available on Unix domain sockets, threads will share the socket")
+            self._shd = SocketHolder.from_unix_path(str(self.uds), self.backlog, self.uds_permissions)
+        else:
+            self._shd = SocketHolder.from_address(
+                self.bind_addr,
+                self.bind_port,
+                self.backlog,
+                self.threading_mode == ThreadModes.sharded
+            )
         self._sfd = self._shd.get_fd()
 
+    @property
+    def _bind_description(self) -> str:
+        if self._listen_fd is not None:
+            return f"inherited socket {self._listen_fd}"
+        if self.uds:
+            return f"unix:{self.uds}"
+        return f"{self.bind_addr}:{self.bind_port}"
+
     def signal_handler(self, *args, **kwargs):
         self.exit_event.set()
 
@@ -249,66 +512,381 @@ class Granian:
         callback_loader,
         socket_loader
     ) -> multiprocessing.Process:
+        generation = self.generations.get(id, 0) + 1
+        self.generations[id] = generation
+        if self.factory:
+            callback_loader = partial(
+                load_factory, callback_loader, WorkerContext(id, generation, self._config())
+            )
+        if self.mounts:
+            callback_loader = partial(
+                load_mounts, callback_loader, self.mounts, self.mount_startup_timeout, self.mount_failure
+            )
+        control, self._controls[id] = multiprocessing.Pipe(duplex=False)
         return multiprocessing.get_context().Process(
             name="granian-worker",
             target=target,
             args=(
                 id,
+                generation,
+                self.memory_recycles.get(id, 0),
                 callback_loader,
                 socket_loader(),
                 self.loop,
-                self.threads,
-                self.pthreads,
                 self.threading_mode,
-                self.http,
-                self.http1_buffer_size,
-                self.websockets,
+                self._worker_options(),
                 self.log_level,
-                self.ssl_ctx
+                self.log_targets,
+                self.log_format,
+                control
             )
         )
 
+    # Values referencing secrets with the `env:` or `file:` prefixes get loaded
+    # once, on boot; workers receive the resolved values.
+    def _resolve_secrets(self):
+        self.synthetic_responses = [
+            (
+                path,
+                status,
+                [(key, resolve_secret(value)) for key, value in headers],
+                resolve_secret(body)
+            )
+            for path, status, headers, body in self.synthetic_responses
+        ]
+        self.status_hooks = [
+            (statuses, action, _resolve_hook_value(action, value))
+            for statuses, action, value in self.status_hooks
+        ]
+        # signing keys are redacted even when given inline
+        self.presign_credentials = [
+            (access_key, Secret(resolve_secret(secret))) for access_key, secret in self.presign_credentials
+        ]
+
+    # Settings of the Rust workers: processes receive them as a dict, building the
+    # `WorkerOptions` object on their side, as it can't be pickled.
+    def _worker_options(self) -> Dict[str, Any]:
+        _, ssl_cert, ssl_key = self.ssl_ctx
+        return {
+            "threads": self.threads,
+            "pthreads": self.pthreads,
+            "http": self.http,
+            "http1_buffer_size": self.http1_buffer_size,
+            "websockets": self.websockets,
+            "listener_shards": self.threading_mode == ThreadModes.sharded,
+            "backlog": self.backlog,
+            "request_filters": list(self.request_filters.items()),
+            "response_filters": list(self.response_filters.items()),
+            "synthetic_responses": self.synthetic_responses,
+            "idempotency_ttl": self.idempotency_ttl,
+            "status_hooks": self.status_hooks,
+            "file_drop_cache_size": self.file_drop_cache_size,
+            "file_cache_size": self.file_cache_size,
+            "file_cache_ttl": self.file_cache_ttl,
+            "http2_max_concurrent_streams": self.http2_max_concurrent_streams,
+            "http2_initial_stream_window_size": self.http2_initial_stream_window_size,
+            "http2_initial_connection_window_size": self.http2_initial_connection_window_size,
+            "http2_adaptive_window": self.http2_adaptive_window,
+            "deadline_header": self.deadline_header,
+            "deadline_trusted": self.deadline_trusted,
+            "disconnect_policy": self.disconnect_policy,
+            "websocket_origins": self.websocket_origins,
+            "websocket_utf8": self.websocket_utf8,
+            "metrics_routes": self.metrics_routes,
+            "error_format": self.error_format,
+            "duplicate_headers": self.duplicate_headers,
+            "path_decoding": self.path_decoding,
+            "options_routes": self.options_routes,
+            "allowed_hosts": self.allowed_hosts,
+            "presign_routes": self.presign_routes,
+            "presign_credentials": self.presign_credentials,
+            "presign_region": self.presign_region,
+            "connection_trace_sample": self.connection_trace_sample,
+            "slo_objective": self.slo_objective,
+            "slo_latency": self.slo_latency,
+            "slo_interval": self.slo_interval,
+            "metrics_path": self.metrics_path,
+            "metrics_address": self.metrics_address,
+            "metrics_port": self.metrics_port,
+            "stack_dump_dir": self.stack_dump_dir,
+            "stack_dump_threshold": self.stack_dump_threshold,
+            "idle_timeout": self.idle_timeout,
+            "proxy_protocol": self.proxy_protocol,
+            "forwarded_trusted": self.forwarded_trusted,
+            "access_log": self.access_log,
+            "access_log_format": self.access_log_format,
+            "grpc_web": self.grpc_web,
+            "transcoding": self.transcoding,
+            "uploads_path": self.uploads_path,
+            "uploads_dir": self.uploads_dir,
+            "uploads_max_size": self.uploads_max_size,
+            "otel_endpoint": self.otel_endpoint,
+            "otel_service_name": self.otel_service_name,
+            "otel_sample_ratio": self.otel_sample_ratio,
+            "compression": self.compression,
+            "compression_min_size": self.compression_min_size,
+            "compression_types": self.compression_types,
+            "decompression": self.decompression,
+            "decompression_max_size": self.decompression_max_size,
+            "header_read_timeout": self.header_read_timeout,
+            "body_read_timeout": self.body_read_timeout,
+            "request_timeout": self.request_timeout,
+            "max_body_size": self.max_body_size,
+            "max_header_size": self.max_header_size,
+            "max_headers": self.max_headers,
+            "max_uri_length": self.max_uri_length,
+            "keep_alive_timeout": self.keep_alive_timeout,
+            "keep_alive_max_requests": self.keep_alive_max_requests,
+            "keep_alive_header": self.keep_alive_header,
+            "drain_timeout": self.drain_timeout,
+            "handler_cancellation": self.handler_cancellation,
+            "cancellation_grace": self.cancellation_grace,
+            "cancellation_exempt": self.cancellation_exempt,
+            "long_poll_routes": self.long_poll_routes,
+            "header_validation": self.header_validation,
+            "protocol_strict": self.protocol_strict,
+            "ssl_cert": ssl_cert,
+            "ssl_key": ssl_key,
+            "ssl_record_size_initial": self.ssl_record_size_initial,
+            "ssl_record_size_max": self.ssl_record_size_max,
+            "ssl_reload_interval": self.ssl_reload_interval,
+            "ssl_ca": self.ssl_ca,
+            "ssl_client_verify": self.ssl_client_verify,
+            "ssl_sni": self.ssl_sni
+        }
+
+    def _config(self) -> Dict[str, Any]:
+        return {
+            key: value for key, value in sorted(vars(self).items())
+            if not key.startswith("_") and key not in self.NON_CONFIG_ATTRS
+        }
+
+    def _dump_config(self):
+        for key, value in self._config().items():
+            logger.debug(f"Config {key}: {value!r}")
+
+    # Changes apply to the main process and get forwarded to every worker;
+    # workers spawned later pick them up from `log_targets`.
+    def _admin_log_level(self, args: List[str]) -> str:
+        if len(args) != 2:
+            raise ValueError("usage: log-level TARGET LEVEL|reset")
+        target, level = args
+        level = None if level == "reset" else LogLevels(level.lower())
+        with self._controls_lock:
+            set_log_level(target, level)
+            if level is None:
+                self.log_targets.pop(target, None)
+            else:
+                self.log_targets[target] = level
+            for conn in self._controls.values():
+                try:
+                    conn.send(("log-level", (target, level)))
+                except OSError:
+                    pass
+        return "ok"
+
+    def _admin_log_levels(self, args: List[str]) -> str:
+        targets = [f"{target}={level.value}" for target, level in sorted(self.log_targets.items())]
+        return " ".join(["ok", f"root={LogLevels(self.log_level).value}", *targets])
+
+    # Every worker writes its own snapshot, logging the path of the file
+    def _admin_stack_dump(self, args: List[str]) -> str:
+        if args:
+            raise ValueError("usage: stack-dump")
+        sent = 0
+        with self._controls_lock:
+            for conn in self._controls.values():
+                try:
+                    conn.send(("stack-dump", "requested through the admin socket"))
+                    sent += 1
+                except OSError:
+                    pass
+        return f"ok workers={sent}"
+
+    # Workers reload the certificate files of their TLS listeners, new connections
+    # getting the new certificate while the established ones are left untouched
+    def _reload_certificates(self) -> int:
+        sent = 0
+        with self._controls_lock:
+            for conn in self._controls.values():
+                try:
+                    conn.send(("tls-reload", None))
+                    sent += 1
+                except OSError:
+                    pass
+        return sent
+
+    def _admin_tls_reload(self, args: List[str]) -> str:
+        if args:
+            raise ValueError("usage: tls-reload")
+        if not self.ssl_ctx[0]:
+            raise ValueError("TLS is not enabled")
+        return f"ok workers={self._reload_certificates()}"
+
+    def _reload_handler(self, *args, **kwargs):
+        logger.info("Reloading TLS certificates")
+        self._reload_certificates()
+
     def startup(self, spawn_target, target_loader):
         logger.info("Starting granian")
+        if self.mounts and self.interface not in (Interfaces.ASGI, Interfaces.AUTO):
+            raise ConfigurationError("Mounted applications are only supported by the ASGI interface")
+        if self.handler_cancellation and self.interface == Interfaces.WSGI:
+            logger.warning("Handler cancellation is not supported by the WSGI interface, ignoring it")
+        self._resolve_secrets()
+        self._dump_config()
 
         for sig in self.SIGNALS:
             signal.signal(sig, self.signal_handler)
+        if self.ssl_ctx[0] and hasattr(signal, "SIGHUP"):
+            signal.signal(signal.SIGHUP, self._reload_handler)
 
         self._init_shared_socket()
         sock = socket.socket(fileno=self._sfd)
         sock.set_inheritable(True)
-        logger.info(f"Listening at: {self.bind_addr}:{self.bind_port}")
+        logger.info(f"Listening at: {self._bind_description}")
+
+        if self.admin_socket:
+            self._admin = AdminServer(self.admin_socket, {
+                "log-level": self._admin_log_level,
+                "log-levels": self._admin_log_levels,
+                "stack-dump": self._admin_stack_dump,
+                "tls-reload": self._admin_tls_reload
+            })
+            self._admin.start()
 
         def socket_loader():
             return sock
 
+        if self._sizing is not None:
+            logger.info(f"Running {self._sizing.describe()}")
+        self._check_cpu_quota()
+        if self.workers_max_rss and process_memory(os.getpid()) is None:
+            logger.warning("Worker memory ceilings are not supported on this platform, ignoring them")
+            self.workers_max_rss = None
+
+        self._spawn_args = (spawn_target, target_loader, socket_loader)
         for idx in range(self.workers):
+            self.procs.append(self._boot_worker(idx + 1))
+
+    # Containers often get a CPU quota lower than the CPUs they can see: running
+    # more threads than the quota allows gets the whole cgroup throttled.
+    def _check_cpu_quota(self):
+        quota = cpu_quota()
+        if quota is None:
+            return
+        threads = self.workers * self.threads
+        if threads > math.ceil(quota):
+            logger.warning(
+                f"Running {self.workers} workers with {self.threads} threads each ({threads} threads) "
+                f"exceeds the cgroup CPU quota of {quota:g} CPUs, expect CPU throttling"
+            )
+
+    def _boot_worker(self, id: int) -> multiprocessing.Process:
+        spawn_target, target_loader, socket_loader = self._spawn_args
+        with self._controls_lock:
             proc = self._spawn_proc(
-                id=idx + 1,
+                id=id,
                 target=spawn_target,
                 callback_loader=target_loader,
                 socket_loader=socket_loader
             )
-            proc.start()
-            self.procs.append(proc)
-            logger.info(f"Booting worker-{idx + 1} with pid: {proc.pid}")
+        proc.start()
+        logger.info(f"Booting worker-{id} with pid: {proc.pid}")
+        return proc
+
+    # Workers above the memory ceiling get replaced by a new process before being
+    # stopped, so their slot keeps serving while the old process drains.
+    def _recycle_workers(self):
+        ceiling = self.workers_max_rss * 1024 * 1024
+        for idx, proc in enumerate(self.procs):
+            rss = process_memory(proc.pid) if proc.is_alive() else None
+            if rss is None or rss <= ceiling:
+                continue
+            id = idx + 1
+            logger.warning(
+                f"Recycling worker-{id} with pid {proc.pid}: resident memory of {rss / 1048576:.1f} MiB "
+                f"exceeds the {self.workers_max_rss} MiB ceiling"
+            )
+            self.memory_recycles[id] = self.memory_recycles.get(id, 0) + 1
+            self.procs[idx] = self._boot_worker(id)
+            proc.terminate()
+            self._retiring.append((proc, time.monotonic() + self.RECYCLE_TIMEOUT))
+        self._reap_retiring()
+
+    def _reap_retiring(self):
+        retiring = []
+        for proc, deadline in self._retiring:
+            if not proc.is_alive():
+                proc.join()
+                continue
+            if time.monotonic() > deadline:
+                logger.warning(f"Killing recycled worker with pid {proc.pid}, still running after {self.RECYCLE_TIMEOUT}s")
+                proc.kill()
+                proc.join()
+                continue
+            retiring.append((proc, deadline))
+        self._retiring = retiring
+
+    # Workers exiting with the startup error status couldn't boot the application:
+    # the server gets stopped, rather than listening with nobody serving.
+    def _check_workers_boot(self):
+        for idx, proc in enumerate(self.procs):
+            if proc.exitcode == StartupError.exit_status:
+                logger.error(f"worker-{idx + 1} failed to boot, shutting down")
+                self._exit_status = StartupError.exit_status
+                self.exit_event.set()
+                return
+
+    def _supervise(self):
+        memory_check = time.monotonic() + self.MEMORY_CHECK_INTERVAL
+        while not self.exit_event.wait(self.WORKERS_CHECK_INTERVAL):
+            self._check_workers_boot()
+            if self.workers_max_rss and time.monotonic() >= memory_check:
+                memory_check = time.monotonic() + self.MEMORY_CHECK_INTERVAL
+                self._recycle_workers()
 
     def shutdown(self):
         logger.info("Shutting down granian")
-        for proc in self.procs:
+        if self._admin is not None:
+            self._admin.stop()
+        procs = self.procs + [proc for proc, _ in self._retiring]
+        for proc in procs:
             proc.terminate()
-        for proc in self.procs:
-            proc.join()
+        # workers drain their connections on their own, the ones stuck past the
+        # drain timeout, like in application shutdown code, get killed
+        deadline = time.monotonic() + self.drain_timeout + self.SHUTDOWN_GRACE
+        for proc in procs:
+            if not self.drain_timeout:
+                proc.join()
+                continue
+            proc.join(max(0.0, deadline - time.monotonic()))
+            if proc.is_alive():
+                logger.warning(f"Killing worker with pid {proc.pid}, still running after the drain timeout")
+                proc.kill()
+                proc.join()
+        if self.uds and self._listen_fd is None:
+            try:
+                os.unlink(self.uds)
+            except OSError:
+                pass
 
     def serve(self, spawn_target = None, target_loader = None):
         default_spawners = {
             Interfaces.ASGI: self._spawn_asgi_worker,
             Interfaces.RSGI: self._spawn_rsgi_worker,
-            Interfaces.WSGI: self._spawn_wsgi_worker
+            Interfaces.WSGI: self._spawn_wsgi_worker,
+            Interfaces.AUTO: self._spawn_auto_worker
         }
         target_loader = target_loader or load_target
         spawn_target = spawn_target or default_spawners[self.interface]
 
-        self.startup(spawn_target, partial(target_loader, self.target))
-        self.exit_event.wait()
+        try:
+            self.startup(spawn_target, partial(target_loader, self.target))
+        except GranianError as exc:
+            logger.error(str(exc))
+            sys.exit(exc.exit_status)
+        self._supervise()
         self.shutdown()
+        if self._exit_status:
+            sys.exit(self._exit_status)
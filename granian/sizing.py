@@ -0,0 +1,58 @@
+import math
+import os
+
+from typing import NamedTuple, Optional
+
+from ._granian import cpu_quota, memory_limit
+
+# Memory budget of each worker when no RSS ceiling is set, in MiB
+WORKER_MEMORY = 256
+
+
+class Sizing(NamedTuple):
+    workers: int
+    threads: int
+    cpus: int
+    memory: Optional[int]
+
+    def describe(self) -> str:
+        limits = [f"{self.cpus} CPUs"]
+        if self.memory is not None:
+            limits.append(f"{self.memory // (1024 * 1024)} MiB of memory")
+        return (
+            f"{self.workers} workers with {self.threads} threads each, "
+            f"sized on {' and '.join(limits)}"
+        )
+
+
+# The CPUs the process can actually use: the ones it is allowed to run on,
+# further reduced by the cgroup CPU quota, if any.
+def available_cpus() -> int:
+    if hasattr(os, "sched_getaffinity"):
+        cpus = len(os.sched_getaffinity(0))
+    else:
+        cpus = os.cpu_count() or 1
+    quota = cpu_quota()
+    if quota is not None:
+        cpus = min(cpus, math.ceil(quota))
+    return max(1, cpus)
+
+
+# Sizes the values not given explicitly on the container limits instead of the
+# host ones: workers get one CPU each, as long as the memory limit fits their
+# budget (`worker_memory` MiB), while runtime threads share the CPUs left.
+def auto_sizing(
+    workers: Optional[int],
+    threads: Optional[int],
+    worker_memory: Optional[int] = None
+) -> Sizing:
+    cpus = available_cpus()
+    memory = memory_limit()
+    if workers is None:
+        workers = cpus
+        if memory is not None:
+            workers = min(workers, memory // ((worker_memory or WORKER_MEMORY) * 1024 * 1024))
+    workers = max(1, workers)
+    if threads is None:
+        threads = cpus // workers
+    return Sizing(workers, max(1, threads), cpus, memory)
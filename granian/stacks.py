@@ -0,0 +1,88 @@
+import datetime
+import itertools
+import os
+import sys
+import tempfile
+import threading
+import traceback
+
+from typing import List, Optional, Tuple
+
+_counter = itertools.count(1)
+
+
+# Native threads of the process, with their scheduler state and, when blocked,
+# the kernel function they are waiting in. Only available on Linux.
+def _native_threads() -> List[str]:
+    try:
+        tids = sorted(os.listdir("/proc/self/task"), key=int)
+    except OSError:
+        return []
+    lines = []
+    for tid in tids:
+        base = f"/proc/self/task/{tid}"
+        try:
+            with open(f"{base}/comm") as f:
+                name = f.read().strip()
+            with open(f"{base}/stat") as f:
+                state = f.read().rsplit(")", 1)[1].split()[0]
+        except OSError:
+            continue
+        try:
+            with open(f"{base}/wchan") as f:
+                wchan = f.read().strip()
+        except OSError:
+            wchan = ""
+        line = f"  {tid} \"{name}\" state {state}"
+        if wchan and wchan != "0":
+            line += f", waiting in {wchan}"
+        lines.append(line)
+    return lines
+
+
+# The thread writing the snapshot is left out
+def _python_threads() -> List[str]:
+    threads = {thread.ident: thread for thread in threading.enumerate()}
+    current = threading.get_ident()
+    lines = []
+    for ident, frame in sys._current_frames().items():
+        if ident == current:
+            continue
+        thread = threads.get(ident)
+        name = thread.name if thread is not None else "<unknown>"
+        native_id = getattr(thread, "native_id", None)
+        native = f"native {native_id}, " if native_id is not None else ""
+        lines.append(f"Thread {ident} \"{name}\" ({native}most recent call last):")
+        lines.extend(line.rstrip("\n") for line in traceback.format_stack(frame))
+        lines.append("")
+    return lines
+
+
+# Writes the stacks of every thread of the current process into a new file of
+# `directory` (the temporary one by default), returning its path.
+def dump_stacks(
+    directory: Optional[str],
+    reason: str,
+    worker: Optional[Tuple[int, int]] = None
+) -> str:
+    now = datetime.datetime.now(datetime.timezone.utc)
+    pid = os.getpid()
+    name = "worker-{}.{}".format(*worker) if worker else f"pid-{pid}"
+    path = os.path.join(
+        directory or tempfile.gettempdir(),
+        f"granian-stacks-{name}-{now.strftime('%Y%m%dT%H%M%S')}-{next(_counter)}.txt"
+    )
+    lines = [
+        f"Stack snapshot of {name}, pid {pid}, at {now.isoformat(timespec='milliseconds')}",
+        f"Reason: {reason}",
+        "",
+        "Python threads:",
+        "",
+        *_python_threads()
+    ]
+    native = _native_threads()
+    if native:
+        lines.extend(["Native threads:", *native, ""])
+    with open(path, "w") as f:
+        f.write("\n".join(lines))
+    return path
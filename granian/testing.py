@@ -0,0 +1,392 @@
+import asyncio
+import atexit
+import inspect
+import json
+
+from pathlib import Path
+from typing import Any, Dict, List, Optional, Tuple, Union
+
+from ._granian import (
+    TestClient as _TestClient,
+    TestServer as _TestServer,
+    WorkerOptions,
+    advance_clock,
+    mock_clock,
+    reset_clock,
+    shutdown_test_runtime
+)
+from ._internal import detect_interface
+from .asgi import LifespanProtocol, _callback_wrapper as _asgi_call_wrap
+from .constants import (
+    ClientVerifyModes,
+    DisconnectPolicies,
+    ErrorFormats,
+    HeaderValidations,
+    HTTPModes,
+    Interfaces,
+    PathDecodings,
+    WebsocketUTF8Policies
+)
+from .rsgi import _callback_wrapper as _rsgi_call_wrap
+from .wsgi import _callback_wrapper as _wsgi_call_wrap
+
+atexit.register(shutdown_test_runtime)
+
+
+class TestResponse:
+    __slots__ = ["status_code", "headers", "content"]
+
+    def __init__(self, status_code: int, headers: List[Tuple[str, str]], content: bytes):
+        self.status_code = status_code
+        self.headers = headers
+        self.content = content
+
+    @property
+    def text(self) -> str:
+        return self.content.decode("utf8")
+
+    def json(self) -> Any:
+        return json.loads(self.content)
+
+    def header(self, name: str) -> Optional[str]:
+        name = name.lower()
+        for key, value in self.headers:
+            if key == name:
+                return value
+        return None
+
+
+# Freezes the clock used by the server caches and TLS record sizing,
+# which then only moves forward with `advance`.
+class MockClock:
+    __slots__ = []
+
+    def __enter__(self) -> "MockClock":
+        mock_clock()
+        return self
+
+    def __exit__(self, exc_type, exc, tb):
+        reset_clock()
+
+    def advance(self, seconds: float):
+        advance_clock(seconds)
+
+
+def _app_callback(app: Any, interface: Interfaces, state: Dict[str, Any]) -> Any:
+    if interface == Interfaces.ASGI:
+        return _asgi_call_wrap(app, state)
+    if interface == Interfaces.RSGI:
+        return _rsgi_call_wrap(getattr(app, "__rsgi__", app))
+    return _wsgi_call_wrap(app)
+
+
+class _AppRunner:
+    def __init__(self, app: Any, interface: Interfaces):
+        self.app = app
+        self.interface = Interfaces(interface)
+        if self.interface == Interfaces.AUTO:
+            self.interface = detect_interface(app)
+        self._lifespan = None
+        self._state = {}
+
+    async def _startup(self):
+        if self.interface == Interfaces.ASGI:
+            self._state.clear()
+            self._lifespan = LifespanProtocol(self.app, self._state)
+            await self._lifespan.startup()
+            if self._lifespan.interrupt:
+                raise RuntimeError("ASGI application failed to start up")
+        elif self.interface == Interfaces.RSGI:
+            await self._rsgi_hook("__rsgi_init__")
+
+    async def _shutdown(self):
+        if self._lifespan is not None:
+            await self._lifespan.shutdown()
+            self._lifespan = None
+        elif self.interface == Interfaces.RSGI:
+            await self._rsgi_hook("__rsgi_del__")
+
+    async def _rsgi_hook(self, name: str):
+        hook = getattr(self.app, name, None)
+        if hook is None:
+            return
+        rv = hook(asyncio.get_running_loop())
+        if inspect.isawaitable(rv):
+            await rv
+
+
+class TestClient(_AppRunner):
+    __test__ = False
+
+    def __init__(
+        self,
+        app: Any,
+        interface: Interfaces = Interfaces.RSGI,
+        request_filters: Optional[Dict[str, List[str]]] = None,
+        response_filters: Optional[Dict[str, List[str]]] = None,
+        synthetic_responses: Optional[Dict[str, Tuple[int, Dict[str, str], str]]] = None,
+        idempotency_ttl: int = 0,
+        status_hooks: Optional[List[Tuple[str, str, str]]] = None,
+        deadline_header: Optional[str] = None,
+        disconnect_policy: DisconnectPolicies = DisconnectPolicies.discard,
+        error_format: ErrorFormats = ErrorFormats.auto,
+        path_decoding: PathDecodings = PathDecodings.raw,
+        options_routes: Optional[Dict[str, List[str]]] = None,
+        allowed_hosts: Optional[List[str]] = None,
+        file_cache_size: int = 0,
+        file_cache_ttl: float = 1.0,
+        stack_dump_dir: Optional[str] = None,
+        stack_dump_threshold: Optional[float] = None
+    ):
+        super().__init__(app, interface)
+        self._client = _TestClient(
+            self.interface.value,
+            _app_callback(app, self.interface, self._state),
+            WorkerOptions(
+                request_filters=list((request_filters or {}).items()),
+                response_filters=list((response_filters or {}).items()),
+                synthetic_responses=[
+                    (path, status, list(headers.items()), body)
+                    for path, (status, headers, body) in (synthetic_responses or {}).items()
+                ],
+                idempotency_ttl=idempotency_ttl,
+                status_hooks=list(status_hooks or []),
+                deadline_header=deadline_header,
+                disconnect_policy=DisconnectPolicies(disconnect_policy).value,
+                error_format=ErrorFormats(error_format).value,
+                path_decoding=PathDecodings(path_decoding).value,
+                options_routes=list((options_routes or {}).items()),
+                allowed_hosts=allowed_hosts or [],
+                file_cache_size=max(0, file_cache_size),
+                file_cache_ttl=max(0.0, file_cache_ttl),
+                stack_dump_dir=stack_dump_dir,
+                stack_dump_threshold=stack_dump_threshold
+            )
+        )
+
+    async def __aenter__(self):
+        await self._startup()
+        return self
+
+    async def __aexit__(self, exc_type, exc, tb):
+        await self._shutdown()
+
+    async def request(
+        self,
+        method: str,
+        path: str,
+        headers: Union[Dict[str, str], List[Tuple[str, str]], None] = None,
+        body: Union[bytes, str, None] = None
+    ) -> TestResponse:
+        if isinstance(body, str):
+            body = body.encode("utf8")
+        if isinstance(headers, dict):
+            headers = headers.items()
+        status, res_headers, content = await self._client.request(
+            method.upper(), path, list(headers or []), body
+        )
+        return TestResponse(status, res_headers, content)
+
+    async def get(
+        self,
+        path: str,
+        headers: Union[Dict[str, str], List[Tuple[str, str]], None] = None
+    ) -> TestResponse:
+        return await self.request("GET", path, headers)
+
+    async def post(
+        self,
+        path: str,
+        headers: Union[Dict[str, str], List[Tuple[str, str]], None] = None,
+        body: Union[bytes, str, None] = None
+    ) -> TestResponse:
+        return await self.request("POST", path, headers, body)
+
+
+class TestServer(_AppRunner):
+    __test__ = False
+
+    def __init__(
+        self,
+        app: Any,
+        interface: Interfaces = Interfaces.RSGI,
+        address: str = "127.0.0.1",
+        port: int = 0,
+        uds: Optional[str] = None,
+        fd: Optional[int] = None,
+        websockets: bool = True,
+        websocket_utf8: WebsocketUTF8Policies = WebsocketUTF8Policies.strict,
+        request_filters: Optional[Dict[str, List[str]]] = None,
+        response_filters: Optional[Dict[str, List[str]]] = None,
+        idempotency_ttl: int = 0,
+        status_hooks: Optional[List[Tuple[str, str, str]]] = None,
+        deadline_header: Optional[str] = None,
+        disconnect_policy: DisconnectPolicies = DisconnectPolicies.discard,
+        error_format: ErrorFormats = ErrorFormats.auto,
+        path_decoding: PathDecodings = PathDecodings.raw,
+        options_routes: Optional[Dict[str, List[str]]] = None,
+        allowed_hosts: Optional[List[str]] = None,
+        presign_routes: Optional[List[str]] = None,
+        presign_credentials: Optional[Dict[str, str]] = None,
+        presign_region: str = "us-east-1",
+        connection_trace_sample: int = 0,
+        slo_objective: Optional[float] = None,
+        slo_latency: Optional[float] = None,
+        slo_interval: float = 10.0,
+        metrics_path: Optional[str] = None,
+        stack_dump_dir: Optional[str] = None,
+        stack_dump_threshold: Optional[float] = None,
+        idle_timeout: float = 30.0,
+        proxy_protocol: bool = False,
+        forwarded_trusted: Optional[List[str]] = None,
+        access_log: Optional[str] = None,
+        access_log_format: str = "common",
+        grpc_web: bool = False,
+        transcoding: bool = False,
+        uploads_path: Optional[str] = None,
+        uploads_dir: Optional[Union[str, Path]] = None,
+        uploads_max_size: int = 0,
+        otel_endpoint: Optional[str] = None,
+        otel_service_name: str = "granian",
+        otel_sample_ratio: float = 1.0,
+        compression: bool = False,
+        compression_min_size: int = 1024,
+        compression_types: Optional[List[str]] = None,
+        decompression: bool = False,
+        decompression_max_size: int = 16777216,
+        header_read_timeout: float = 30.0,
+        body_read_timeout: float = 60.0,
+        request_timeout: float = 0.0,
+        max_body_size: int = 0,
+        max_header_size: int = 65536,
+        max_headers: int = 100,
+        max_uri_length: int = 8192,
+        keep_alive_timeout: float = 30.0,
+        keep_alive_max_requests: int = 0,
+        keep_alive_header: bool = False,
+        drain_timeout: float = 30.0,
+        handler_cancellation: bool = False,
+        cancellation_grace: float = 0.0,
+        cancellation_exempt: Optional[List[str]] = None,
+        long_poll_routes: Optional[List[str]] = None,
+        header_validation: HeaderValidations = HeaderValidations.strict,
+        protocol_strict: bool = True,
+        http: HTTPModes = HTTPModes.auto,
+        http2_max_concurrent_streams: int = 0,
+        ssl_cert: Optional[Union[str, Path]] = None,
+        ssl_key: Optional[Union[str, Path]] = None,
+        ssl_reload_interval: float = 0.0,
+        ssl_ca: Optional[Union[str, Path]] = None,
+        ssl_client_verify: ClientVerifyModes = ClientVerifyModes.required,
+        ssl_sni: Optional[Dict[str, Tuple[Union[str, Path], Union[str, Path]]]] = None
+    ):
+        super().__init__(app, interface)
+        self._server = _TestServer(
+            self.interface.value,
+            _app_callback(app, self.interface, self._state),
+            address,
+            port,
+            uds,
+            fd,
+            WorkerOptions(
+                websockets=websockets,
+                websocket_utf8=WebsocketUTF8Policies(websocket_utf8).value,
+                request_filters=list((request_filters or {}).items()),
+                response_filters=list((response_filters or {}).items()),
+                idempotency_ttl=idempotency_ttl,
+                status_hooks=list(status_hooks or []),
+                deadline_header=deadline_header,
+                disconnect_policy=DisconnectPolicies(disconnect_policy).value,
+                error_format=ErrorFormats(error_format).value,
+                path_decoding=PathDecodings(path_decoding).value,
+                options_routes=list((options_routes or {}).items()),
+                allowed_hosts=allowed_hosts or [],
+                presign_routes=presign_routes or [],
+                presign_credentials=list((presign_credentials or {}).items()),
+                presign_region=presign_region,
+                connection_trace_sample=connection_trace_sample,
+                slo_objective=slo_objective,
+                slo_latency=slo_latency,
+                slo_interval=slo_interval,
+                metrics_path=metrics_path,
+                stack_dump_dir=stack_dump_dir,
+                stack_dump_threshold=stack_dump_threshold,
+                idle_timeout=idle_timeout,
+                proxy_protocol=proxy_protocol,
+                forwarded_trusted=forwarded_trusted or [],
+                access_log=access_log,
+                access_log_format=access_log_format,
+                grpc_web=grpc_web,
+                transcoding=transcoding,
+                uploads_path=uploads_path,
+                uploads_dir=str(uploads_dir) if uploads_dir else None,
+                uploads_max_size=uploads_max_size,
+                otel_endpoint=otel_endpoint,
+                otel_service_name=otel_service_name,
+                otel_sample_ratio=otel_sample_ratio,
+                compression=compression,
+                compression_min_size=compression_min_size,
+                compression_types=compression_types or [],
+                decompression=decompression,
+                decompression_max_size=decompression_max_size,
+                header_read_timeout=header_read_timeout,
+                body_read_timeout=body_read_timeout,
+                request_timeout=request_timeout,
+                max_body_size=max_body_size,
+                max_header_size=max_header_size,
+                max_headers=max_headers,
+                max_uri_length=max_uri_length,
+                keep_alive_timeout=keep_alive_timeout,
+                keep_alive_max_requests=keep_alive_max_requests,
+                keep_alive_header=keep_alive_header,
+                drain_timeout=drain_timeout,
+                handler_cancellation=handler_cancellation,
+                cancellation_grace=cancellation_grace,
+                cancellation_exempt=cancellation_exempt or [],
+                long_poll_routes=long_poll_routes or [],
+                header_validation=HeaderValidations(header_validation).value,
+                protocol_strict=protocol_strict,
+                http=HTTPModes(http).value,
+                http2_max_concurrent_streams=http2_max_concurrent_streams,
+                ssl_cert=str(ssl_cert) if ssl_cert else None,
+                ssl_key=str(ssl_key) if ssl_key else None,
+                ssl_reload_interval=ssl_reload_interval,
+                ssl_ca=str(ssl_ca) if ssl_ca else None,
+                ssl_client_verify=ClientVerifyModes(ssl_client_verify).value,
+                ssl_sni=[(host, str(cert), str(key)) for host, (cert, key) in (ssl_sni or {}).items()]
+            )
+        )
+        self.host, self.port = self._server.address
+        self.uds = uds
+        self.ssl = bool(ssl_cert and ssl_key)
+        self._task = None
+
+    @property
+    def url(self) -> str:
+        return f"{'https' if self.ssl else 'http'}://{self.host}:{self.port}"
+
+    @property
+    def ws_url(self) -> str:
+        return f"{'wss' if self.ssl else 'ws'}://{self.host}:{self.port}"
+
+    async def start(self) -> "TestServer":
+        if self._task is not None:
+            raise RuntimeError("Test server was already started")
+        await self._startup()
+        self._task = asyncio.ensure_future(self._server.serve())
+        return self
+
+    async def shutdown(self):
+        if self._task is None:
+            return
+        self._server.shutdown()
+        try:
+            await self._task
+        finally:
+            await self._shutdown()
+
+    async def __aenter__(self):
+        return await self.start()
+
+    async def __aexit__(self, exc_type, exc, tb):
+        await self.shutdown()
@@ -10,7 +10,7 @@ class Response:
     __slots__ = ['status', 'headers']
 
     def __init__(self):
-        self.status = 200
+        self.status = '200 OK'
         self.headers = []
 
 
@@ -32,19 +32,25 @@ def _callback_wrapper(callback):
     })
 
     @wraps(callback)
-    def wrapper(scope: Scope) -> Tuple[int, List[Tuple[str, str]], bytes]:
-        addr_server = scope.server.split(":")
+    def wrapper(scope: Scope) -> Tuple[str, List[Tuple[str, str]], bytes]:
+        server_name, _, server_port = scope.server.rpartition(":")
         environ = {
             **basic_env,
             **scope.headers,
-            'SERVER_NAME': addr_server[0],
-            'SERVER_PORT': str(addr_server[1]),
+            'SERVER_NAME': server_name,
+            'SERVER_PORT': server_port,
             'REQUEST_METHOD': scope.method,
             'PATH_INFO': scope.path,
             'QUERY_STRING': scope.query_string,
             'REMOTE_ADDR': scope.client,
             'wsgi.url_scheme': scope.scheme,
-            'wsgi.input': scope.body
+            'wsgi.input': scope.body,
+            'granian.scratch_dir': scope.scratch_dir,
+            'granian.deadline_remaining': scope.deadline_remaining,
+            'granian.raw_path': scope.raw_path,
+            'granian.query_params': scope.query_params,
+            'granian.worker_id': scope.worker_id,
+            'granian.worker_generation': scope.worker_generation
         }
         if 'HTTP_CONTENT_TYPE' in environ:
             environ['CONTENT_TYPE'] = environ.pop('HTTP_CONTENT_TYPE')
@@ -54,7 +60,7 @@ def _callback_wrapper(callback):
         resp = Response()
 
         def start_response(status: str, headers: List[Tuple[str, str]]):
-            resp.status = int(status.split(' ', 1)[0])
+            resp.status = status
             resp.headers = headers
 
         rv = callback(environ, start_response)
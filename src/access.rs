@@ -0,0 +1,403 @@
+use hyper::{Body, Method, Request, Response, Uri, Version, body::HttpBody, header::{HeaderName, REFERER, USER_AGENT}};
+use pyo3::{exceptions::{PyOSError, PyValueError}, prelude::*};
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::Write,
+    net::SocketAddr,
+    sync::{Arc, mpsc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{negotiation::escape_json, workers::identity};
+
+
+const COMMON_FORMAT: &str = "%h %l %u %t \"%r\" %s %b";
+const COMBINED_FORMAT: &str = "%h %l %u %t \"%r\" %s %b \"%{Referer}i\" \"%{User-Agent}i\"";
+// Lines queued while the output is busy: once full, further lines get dropped,
+// so a slow disk never stalls the runtime threads.
+const QUEUE_SIZE: usize = 16384;
+const BATCH_SIZE: usize = 64 * 1024;
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+enum Token {
+    Literal(String),
+    ClientHost,
+    Dash,
+    Time,
+    RequestLine,
+    Method,
+    Path,
+    Query,
+    Protocol,
+    Status,
+    Bytes,
+    DurationMicros,
+    DurationSecs,
+    RequestHeader(usize),
+    ResponseHeader(HeaderName)
+}
+
+enum Output {
+    Stderr,
+    File(std::fs::File),
+    Python
+}
+
+impl Output {
+    fn new(target: &str) -> PyResult<Self> {
+        match target {
+            "stderr" => Ok(Self::Stderr),
+            "python" => Ok(Self::Python),
+            path => OpenOptions::new().create(true).append(true).open(path)
+                .map(Self::File)
+                .map_err(|err| PyOSError::new_err(format!("Unable to open access log {}: {}", path, err)))
+        }
+    }
+
+    fn write(&mut self, batch: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Stderr => std::io::stderr().lock().write_all(batch),
+            Self::File(file) => file.write_all(batch),
+            Self::Python => {
+                for line in String::from_utf8_lossy(batch).lines() {
+                    log::info!(target: "granian.access", "{}", line);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct AccessLogFormat {
+    tokens: Vec<Token>,
+    request_headers: Vec<HeaderName>,
+    json: bool
+}
+
+impl AccessLogFormat {
+    // Tokens follow the Apache ones: `%{Name}i` and `%{Name}o` print the
+    // request and response headers with the given name. The `json` format
+    // writes records instead, for websocket sessions as well.
+    fn parse(format: &str) -> PyResult<Self> {
+        let format = match format {
+            "common" => COMMON_FORMAT,
+            "combined" => COMBINED_FORMAT,
+            "json" => return Ok(Self { tokens: Vec::new(), request_headers: vec![REFERER, USER_AGENT], json: true }),
+            format => format
+        };
+        let invalid = |msg: String| PyValueError::new_err(format!("Invalid access log format: {}", msg));
+        let mut tokens = Vec::new();
+        let mut request_headers = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars();
+        while let Some(char) = chars.next() {
+            if char != '%' {
+                literal.push(char);
+                continue
+            }
+            let token = match chars.next() {
+                Some('%') => {
+                    literal.push('%');
+                    continue
+                },
+                Some('h') => Token::ClientHost,
+                Some('l') | Some('u') => Token::Dash,
+                Some('t') => Token::Time,
+                Some('r') => Token::RequestLine,
+                Some('m') => Token::Method,
+                Some('U') => Token::Path,
+                Some('q') => Token::Query,
+                Some('H') => Token::Protocol,
+                Some('s') => Token::Status,
+                Some('b') => Token::Bytes,
+                Some('D') => Token::DurationMicros,
+                Some('T') => Token::DurationSecs,
+                Some('{') => {
+                    let name: String = chars.by_ref().take_while(|char| *char != '}').collect();
+                    let name = HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|_| invalid(format!("'{}' is not a header name", name)))?;
+                    match chars.next() {
+                        Some('i') => {
+                            request_headers.push(name);
+                            Token::RequestHeader(request_headers.len() - 1)
+                        },
+                        Some('o') => Token::ResponseHeader(name),
+                        _ => return Err(invalid(format!("missing 'i' or 'o' after the '{}' header", name)))
+                    }
+                },
+                Some(char) => return Err(invalid(format!("unknown token '%{}'", char))),
+                None => return Err(invalid("trailing '%'".to_string()))
+            };
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+            tokens.push(token);
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        Ok(Self { tokens, request_headers, json: false })
+    }
+}
+
+struct AccessLogState {
+    format: AccessLogFormat,
+    queue: mpsc::SyncSender<String>
+}
+
+// Access lines get formatted on the runtime threads, without the GIL, and
+// written in batches by a dedicated thread; with the `python` output, the
+// lines get emitted by that thread on the `granian.access` logger instead.
+#[derive(Clone, Default)]
+pub(crate) struct AccessLog {
+    state: Option<Arc<AccessLogState>>
+}
+
+impl AccessLog {
+    pub fn new(target: Option<String>, format: &str) -> PyResult<Self> {
+        let format = AccessLogFormat::parse(format)?;
+        let target = match target {
+            Some(target) => target,
+            None => return Ok(Self::default())
+        };
+        let mut output = Output::new(&target)?;
+        let (queue, lines) = mpsc::sync_channel::<String>(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("granian-access".to_string())
+            .spawn(move || {
+                let mut batch = Vec::with_capacity(BATCH_SIZE);
+                while let Ok(line) = lines.recv() {
+                    batch.extend_from_slice(line.as_bytes());
+                    while batch.len() < BATCH_SIZE {
+                        match lines.try_recv() {
+                            Ok(line) => batch.extend_from_slice(line.as_bytes()),
+                            Err(_) => break
+                        }
+                    }
+                    if let Err(err) = output.write(&batch) {
+                        log::error!("Unable to write access log to {}: {}", target, err);
+                    }
+                    batch.clear();
+                }
+            })
+            .map_err(|err| PyOSError::new_err(format!("Unable to start access log writer: {}", err)))?;
+        Ok(Self { state: Some(Arc::new(AccessLogState { format, queue })) })
+    }
+
+    pub fn request(&self, req: &Request<Body>) -> Option<AccessRecord> {
+        let state = self.state.as_ref()?;
+        Some(AccessRecord {
+            state: state.clone(),
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: state.format.request_headers.iter()
+                .map(|name| req.headers().get(name).map(|value| escape(value.as_bytes())))
+                .collect()
+        })
+    }
+
+    pub fn websocket(&self, req: &Request<Body>, client: SocketAddr) -> Option<SessionRecord> {
+        let state = self.state.as_ref().filter(|state| state.format.json)?;
+        Some(SessionRecord {
+            state: state.clone(),
+            client,
+            path: req.uri().path().to_string(),
+            started: Instant::now()
+        })
+    }
+}
+
+// The parts of a request needed to write its line, taken before it gets handled
+pub(crate) struct AccessRecord {
+    state: Arc<AccessLogState>,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: Vec<Option<String>>
+}
+
+impl AccessRecord {
+    pub fn finish(self, client: SocketAddr, res: &Response<Body>, elapsed: Duration) {
+        if self.state.format.json {
+            return self.finish_json(client, res, elapsed)
+        }
+        let mut line = String::with_capacity(128);
+        for token in &self.state.format.tokens {
+            let _ = match token {
+                Token::Literal(value) => write!(line, "{}", value),
+                Token::ClientHost => write!(line, "{}", client.ip()),
+                Token::Dash => write!(line, "-"),
+                Token::Time => write_time(&mut line, SystemTime::now()),
+                Token::RequestLine => write!(
+                    line,
+                    "{} {} {:?}",
+                    self.method,
+                    escape(self.uri.path_and_query().map_or("/", |pq| pq.as_str()).as_bytes()),
+                    self.version
+                ),
+                Token::Method => write!(line, "{}", self.method),
+                Token::Path => write!(line, "{}", escape(self.uri.path().as_bytes())),
+                Token::Query => match self.uri.query() {
+                    Some(query) => write!(line, "?{}", escape(query.as_bytes())),
+                    None => Ok(())
+                },
+                Token::Protocol => write!(line, "{:?}", self.version),
+                Token::Status => write!(line, "{}", res.status().as_u16()),
+                // streamed bodies have no known size when the response starts
+                Token::Bytes => match res.body().size_hint().exact() {
+                    Some(size) => write!(line, "{}", size),
+                    None => write!(line, "-")
+                },
+                Token::DurationMicros => write!(line, "{}", elapsed.as_micros()),
+                Token::DurationSecs => write!(line, "{:.3}", elapsed.as_secs_f64()),
+                Token::RequestHeader(idx) => write!(line, "{}", self.headers[*idx].as_deref().unwrap_or("-")),
+                Token::ResponseHeader(name) => match res.headers().get(name) {
+                    Some(value) => write!(line, "{}", escape(value.as_bytes())),
+                    None => write!(line, "-")
+                }
+            };
+        }
+        line.push('\n');
+        let _ = self.state.queue.try_send(line);
+    }
+
+    fn finish_json(self, client: SocketAddr, res: &Response<Body>, elapsed: Duration) {
+        let mut line = String::with_capacity(256);
+        write_record_head(&mut line, "request", client);
+        let _ = writeln!(
+            line,
+            ",\"method\":\"{}\",\"path\":\"{}\",\"query\":{},\"protocol\":\"{:?}\",\"status\":{},\"bytes\":{},\"latency\":{:.6},\"referer\":{},\"user_agent\":{}}}",
+            escape_json(self.method.as_str()),
+            escape_json(self.uri.path()),
+            json_string(self.uri.query()),
+            self.version,
+            res.status().as_u16(),
+            res.body().size_hint().exact().map_or("null".to_string(), |size| size.to_string()),
+            elapsed.as_secs_f64(),
+            json_string(self.headers[0].as_deref()),
+            json_string(self.headers[1].as_deref())
+        );
+        let _ = self.state.queue.try_send(line);
+    }
+}
+
+// A websocket session, written once the connection ends with its close code
+// and the data messages exchanged, only by the `json` format.
+pub(crate) struct SessionRecord {
+    state: Arc<AccessLogState>,
+    client: SocketAddr,
+    path: String,
+    started: Instant
+}
+
+pub(crate) struct SessionCounts {
+    pub frames_in: u64,
+    pub frames_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64
+}
+
+impl SessionRecord {
+    pub fn finish(self, close_code: u16, counts: SessionCounts) {
+        let mut line = String::with_capacity(256);
+        write_record_head(&mut line, "websocket", self.client);
+        let _ = writeln!(
+            line,
+            ",\"path\":\"{}\",\"close_code\":{},\"frames_in\":{},\"frames_out\":{},\"bytes_in\":{},\"bytes_out\":{},\"duration\":{:.6}}}",
+            escape_json(&self.path),
+            close_code,
+            counts.frames_in,
+            counts.frames_out,
+            counts.bytes_in,
+            counts.bytes_out,
+            self.started.elapsed().as_secs_f64()
+        );
+        let _ = self.state.queue.try_send(line);
+    }
+}
+
+fn write_record_head(line: &mut String, event: &str, client: SocketAddr) {
+    let identity = identity();
+    line.push_str("{\"time\":\"");
+    let _ = write_timestamp(line, SystemTime::now());
+    let _ = write!(
+        line,
+        "\",\"event\":\"{}\",\"worker\":{},\"generation\":{},\"client\":\"{}\"",
+        event,
+        identity.id,
+        identity.generation,
+        client.ip()
+    );
+}
+
+fn json_string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", escape_json(value)),
+        None => "null".to_string()
+    }
+}
+
+// Quotes, backslashes and non printable bytes get hex escaped, as clients
+// could otherwise forge lines or fields.
+fn escape(value: &[u8]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value {
+        match byte {
+            b'"' | b'\\' | 0..=0x1f | 0x7f.. => {
+                let _ = write!(escaped, "\\x{:02x}", byte);
+            },
+            _ => escaped.push(*byte as char)
+        }
+    }
+    escaped
+}
+
+// Days since the epoch to the year, month and day
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + u64::from(month <= 2), month, day)
+}
+
+// Times are written in UTC, like `[10/Oct/2000:13:55:36 +0000]`
+fn write_time(line: &mut String, now: SystemTime) -> std::fmt::Result {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, time) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_date(days);
+    write!(
+        line,
+        "[{:02}/{}/{}:{:02}:{:02}:{:02} +0000]",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// RFC 3339 timestamps in UTC with milliseconds, like `2000-10-10T13:55:36.123Z`
+fn write_timestamp(line: &mut String, now: SystemTime) -> std::fmt::Result {
+    let since = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, time) = (since.as_secs() / 86400, since.as_secs() % 86400);
+    let (year, month, day) = civil_date(days);
+    write!(
+        line,
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        since.subsec_millis()
+    )
+}
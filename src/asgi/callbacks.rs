@@ -4,11 +4,13 @@ use tokio::sync::oneshot;
 
 use crate::{
     callbacks::CallbackWrapper,
+    cancellation::{CancelHandle, HandlerTask},
+    errors::Error,
+    http::{DisconnectPolicy, HeaderValidation, ProtocolConformance},
     runtime::RuntimeRef,
     ws::{HyperWebsocket, UpgradeData}
 };
 use super::{
-    errors::{ASGIFlowError, error_flow},
     io::{ASGIHTTPProtocol, ASGIWebsocketProtocol},
     types::ASGIScope as Scope
 };
@@ -19,21 +21,29 @@ pub(crate) struct CallbackWatcherHTTP {
     #[pyo3(get)]
     proto: Py<ASGIHTTPProtocol>,
     #[pyo3(get)]
+    scope: Py<Scope>,
+    #[pyo3(get)]
     event_loop: PyObject,
     #[pyo3(get)]
-    context: PyObject
+    context: PyObject,
+    #[pyo3(get)]
+    cancellation: Option<Py<HandlerTask>>
 }
 
 impl CallbackWatcherHTTP {
     pub fn new(
         py: Python,
         cb: CallbackWrapper,
-        proto: ASGIHTTPProtocol
+        proto: ASGIHTTPProtocol,
+        scope: Scope,
+        cancellation: Option<CancelHandle>
     ) -> Self {
         Self {
             proto: Py::new(py, proto).unwrap(),
+            scope: Py::new(py, scope).unwrap(),
             event_loop: cb.context.event_loop(py).into(),
             context: cb.context.context(py).into(),
+            cancellation: cancellation.map(|handle| handle.task(py))
         }
     }
 }
@@ -43,10 +53,9 @@ impl CallbackWatcherHTTP {
     fn done(&mut self, py: Python) {
         if let Ok(mut proto) = self.proto.as_ref(py).try_borrow_mut() {
             if let Some(tx) = proto.tx() {
-                let mut res = Response::new("Internal server error".into());
-                *res.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
-                let _ = tx.send(res);
+                let _ = tx.send(Error::app("Application callable ended without sending a response").response());
             }
+            proto.abort_body();
         }
     }
 
@@ -61,6 +70,8 @@ pub(crate) struct CallbackWatcherWebsocket {
     #[pyo3(get)]
     proto: Py<ASGIWebsocketProtocol>,
     #[pyo3(get)]
+    scope: Py<Scope>,
+    #[pyo3(get)]
     event_loop: PyObject,
     #[pyo3(get)]
     context: PyObject
@@ -70,10 +81,12 @@ impl CallbackWatcherWebsocket {
     pub fn new(
         py: Python,
         cb: CallbackWrapper,
-        proto: ASGIWebsocketProtocol
+        proto: ASGIWebsocketProtocol,
+        scope: Scope
     ) -> Self {
         Self {
             proto: Py::new(py, proto).unwrap(),
+            scope: Py::new(py, scope).unwrap(),
             event_loop: cb.context.event_loop(py).into(),
             context: cb.context.context(py).into(),
         }
@@ -122,42 +135,47 @@ impl CallbackWatcherWebsocket {
 pub(crate) async fn call_rtb_http(
     cb: CallbackWrapper,
     rt: RuntimeRef,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
     req: Request<Body>,
     scope: Scope
-) -> Result<Response<Body>, ASGIFlowError> {
+) -> Result<Response<Body>, Error> {
     let callback = cb.callback.clone();
+    let cancellation = CancelHandle::of(&req);
     let (tx, rx) = oneshot::channel();
-    let protocol = ASGIHTTPProtocol::new(rt, req, tx);
+    let protocol = ASGIHTTPProtocol::new(rt, disconnect_policy, header_validation, conformance, req, tx);
 
     Python::with_gil(|py| {
-        callback.call1(py, (CallbackWatcherHTTP::new(py, cb, protocol), scope))
+        callback.call1(py, (CallbackWatcherHTTP::new(py, cb, protocol, scope, cancellation),))
     })?;
 
     match rx.await {
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("ASGI protocol failure");
-            error_flow!()
-        }
+        _ => Err(Error::protocol("ASGI protocol failure"))
     }
 }
 
 pub(crate) async fn call_rtt_http(
     cb: CallbackWrapper,
     rt: RuntimeRef,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
     req: Request<Body>,
     scope: Scope
-) -> Result<Response<Body>, ASGIFlowError> {
+) -> Result<Response<Body>, Error> {
     let callback = cb.callback.clone();
+    let cancellation = CancelHandle::of(&req);
     let (tx, rx) = oneshot::channel();
-    let protocol = ASGIHTTPProtocol::new(rt, req, tx);
+    let protocol = ASGIHTTPProtocol::new(rt, disconnect_policy, header_validation, conformance, req, tx);
 
     tokio::task::spawn_blocking(move || {
         Python::with_gil(|py| {
             let _ = callback.call1(
-                py, (CallbackWatcherHTTP::new(py, cb, protocol), scope)
+                py, (CallbackWatcherHTTP::new(py, cb, protocol, scope, cancellation),)
             );
         });
     });
@@ -166,10 +184,7 @@ pub(crate) async fn call_rtt_http(
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("ASGI protocol failure");
-            error_flow!()
-        }
+        _ => Err(Error::protocol("ASGI protocol failure"))
     }
 }
 
@@ -179,23 +194,20 @@ pub(crate) async fn call_rtb_ws(
     ws: HyperWebsocket,
     upgrade: UpgradeData,
     scope: Scope
-) -> Result<bool, ASGIFlowError> {
+) -> Result<bool, Error> {
     let callback = cb.callback.clone();
     let (tx, rx) = oneshot::channel();
     let protocol = ASGIWebsocketProtocol::new(rt, tx, ws, upgrade);
 
     Python::with_gil(|py| {
-        callback.call1(py, (CallbackWatcherWebsocket::new(py, cb, protocol), scope))
+        callback.call1(py, (CallbackWatcherWebsocket::new(py, cb, protocol, scope),))
     })?;
 
     match rx.await {
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("ASGI protocol failure");
-            error_flow!()
-        }
+        _ => Err(Error::protocol("ASGI protocol failure"))
     }
 }
 
@@ -205,7 +217,7 @@ pub(crate) async fn call_rtt_ws(
     ws: HyperWebsocket,
     upgrade: UpgradeData,
     scope: Scope
-) -> Result<bool, ASGIFlowError> {
+) -> Result<bool, Error> {
     let callback = cb.callback.clone();
     let (tx, rx) = oneshot::channel();
     let protocol = ASGIWebsocketProtocol::new(rt, tx, ws, upgrade);
@@ -213,7 +225,7 @@ pub(crate) async fn call_rtt_ws(
     tokio::task::spawn_blocking(move || {
         Python::with_gil(|py| {
             let _ = callback.call1(
-                py, (CallbackWatcherWebsocket::new(py, cb, protocol), scope)
+                py, (CallbackWatcherWebsocket::new(py, cb, protocol, scope),)
             );
         });
     });
@@ -222,9 +234,6 @@ pub(crate) async fn call_rtt_ws(
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("ASGI protocol failure");
-            error_flow!()
-        }
+        _ => Err(Error::protocol("ASGI protocol failure"))
     }
 }
@@ -1,7 +1,9 @@
-use pyo3::exceptions::PyRuntimeError;
+use pyo3::{create_exception, exceptions::{PyOSError, PyRuntimeError}};
 use pyo3::prelude::*;
 use std::{error, fmt};
 
+create_exception!(_granian, ASGIConnectionClosed, PyOSError, "ASGIConnectionClosed");
+
 #[derive(Debug)]
 pub(crate) struct UnsupportedASGIMessage;
 
@@ -65,5 +67,12 @@ macro_rules! error_message {
     };
 }
 
+macro_rules! error_closed {
+    () => {
+        super::errors::ASGIConnectionClosed::new_err("Client disconnected")
+    };
+}
+
+pub(crate) use error_closed;
 pub(crate) use error_flow;
 pub(crate) use error_message;
@@ -1,24 +1,39 @@
+use bytes::Bytes;
 use futures::{sink::SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
 use hyper::{
     Body,
     Request,
     Response,
-    header::{HeaderName, HeaderValue, HeaderMap, SERVER as HK_SERVER}
+    StatusCode,
+    header::{HeaderName, HeaderValue, HeaderMap}
 };
-use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyDict};
-use std::sync::Arc;
-use tokio_tungstenite::WebSocketStream;
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use pyo3::types::{PyBool, PyBytes, PyDict, PyInt, PyString};
+use std::{borrow::Cow, sync::Arc};
 use tokio::sync::{Mutex, oneshot};
 use tungstenite::Message;
 
 use crate::{
-    http::HV_SERVER,
+    errors::ProtocolViolationError,
+    buffers::BufferBody,
+    diagnostics::RequestTrace,
+    files::FilePath,
+    http::{
+        DisconnectPolicy,
+        HeaderValidation,
+        ProtocolConformance,
+        ResponseEvent,
+        ResponseState,
+        StreamedBodySender,
+        read_body,
+        response_status,
+        streamed_body
+    },
     runtime::{RuntimeRef, future_into_py},
-    ws::{HyperWebsocket, UpgradeData}
+    ws::{HyperWebsocket, UpgradeData, WebsocketStats, WebsocketTransport, fail_invalid_payload, invalid_payload_close}
 };
 use super::{
-    errors::{UnsupportedASGIMessage, error_flow, error_message},
+    errors::{UnsupportedASGIMessage, error_closed, error_flow, error_message},
     types::ASGIMessageType
 };
 
@@ -31,44 +46,120 @@ pub(crate) struct ASGIHTTPProtocol {
     rt: RuntimeRef,
     tx: Option<oneshot::Sender<Response<Body>>>,
     request: Arc<Mutex<Request<Body>>>,
-    response_inited: bool,
-    response_built: bool,
-    response_status: i16,
+    state: ResponseState,
+    response_status: StatusCode,
     response_headers: HeaderMap,
-    response_body: Vec<u8>
+    body_tx: Option<StreamedBodySender>,
+    trace: RequestTrace,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
+    disconnected: bool
 }
 
 impl ASGIHTTPProtocol {
     pub fn new(
         rt: RuntimeRef,
+        disconnect_policy: DisconnectPolicy,
+        header_validation: HeaderValidation,
+        conformance: ProtocolConformance,
         request: Request<Body>,
         tx: oneshot::Sender<Response<Body>>
     ) -> Self {
         Self {
-            rt: rt,
+            rt,
             tx: Some(tx),
+            disconnect_policy,
+            header_validation,
+            conformance,
+            disconnected: false,
+            trace: RequestTrace::of(&request),
             request: Arc::new(Mutex::new(request)),
-            response_inited: false,
-            response_built: false,
-            response_status: 0,
+            state: ResponseState::Pending,
+            response_status: StatusCode::OK,
             response_headers: HeaderMap::new(),
-            response_body: Vec::new()
+            body_tx: None
+        }
+    }
+
+    // Bodies sent while the streamed ones got their queue full return an awaitable,
+    // resolved once the client reads enough of them
+    #[inline(always)]
+    fn send_body<'p>(&mut self, py: Python<'p>, body: Bytes, finish: bool) -> PyResult<Option<&'p PyAny>> {
+        if !self.disconnected {
+            match self.deliver_body(body, finish) {
+                Ok(true) => {},
+                Ok(false) => self.disconnected = true,
+                Err((body_tx, body)) => {
+                    let disconnect_policy = self.disconnect_policy;
+                    return future_into_py(self.rt.clone(), py, async move {
+                        match body_tx.send(body).await {
+                            true => Ok(()),
+                            false => disconnect_policy.apply(|| error_closed!())
+                        }
+                    }).map(Some)
+                }
+            }
+        }
+        if self.disconnected {
+            return self.disconnect_policy.apply(|| error_closed!()).map(|_| None)
         }
+        Ok(None)
     }
 
+    // Returns `false` when the client went away before getting the body, or
+    // the body with its sender to wait on when the stream queue is full
     #[inline(always)]
-    fn send_body(&mut self, body: &[u8], finish: bool) {
-        self.response_body.extend_from_slice(body);
-        if finish {
-            if let Some(tx) = self.tx.take() {
-                let mut res = Response::new(self.response_body.to_owned().into());
-                *res.status_mut() = hyper::StatusCode::from_u16(
-                    self.response_status as u16
-                ).unwrap();
-                *res.headers_mut() = self.response_headers.to_owned();
-                let _ = tx.send(res);
+    fn deliver_body(&mut self, body: Bytes, finish: bool) -> Result<bool, (StreamedBodySender, Bytes)> {
+        if let Some(body_tx) = &self.body_tx {
+            let sent = body_tx.try_send(body).map_err(|body| (body_tx.clone(), body));
+            if finish {
+                self.body_tx = None;
             }
-            self.response_built = true;
+            return sent
+        }
+        if let Some(tx) = self.tx.take() {
+            // a single body message can be sent as it is, otherwise
+            // the response starts right away and the body gets streamed
+            let body = match finish {
+                true => body.into(),
+                false => {
+                    let (body_tx, stream) = streamed_body(self.trace.clone());
+                    let _ = body_tx.try_send(body);
+                    self.body_tx = Some(body_tx);
+                    stream
+                }
+            };
+            let mut res = Response::new(body);
+            *res.status_mut() = self.response_status;
+            *res.headers_mut() = std::mem::take(&mut self.response_headers);
+            return Ok(tx.send(res).is_ok())
+        }
+        Ok(true)
+    }
+
+    // The file gets opened and streamed by the request handler, so ranges and
+    // missing files are dealt with the same way of other file responses.
+    fn send_file(&mut self, path: String) -> PyResult<()> {
+        let tx = match self.tx.take() {
+            Some(tx) => tx,
+            _ => return error_flow!()
+        };
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = self.response_status;
+        *res.headers_mut() = std::mem::take(&mut self.response_headers);
+        res.extensions_mut().insert(FilePath(path));
+        if tx.send(res).is_err() {
+            self.disconnected = true;
+            return self.disconnect_policy.apply(|| error_closed!())
+        }
+        Ok(())
+    }
+
+    // Aborts a streamed response the application didn't complete
+    pub fn abort_body(&mut self) {
+        if let Some(body_tx) = self.body_tx.take() {
+            body_tx.abort();
         }
     }
 
@@ -83,52 +174,69 @@ impl ASGIHTTPProtocol {
         let transport = self.request.clone();
         future_into_py(self.rt.clone(), py, async move {
             let mut req = transport.lock().await;
-            let body = hyper::body::to_bytes(&mut *req).await.unwrap();
+            let body = read_body(req.body_mut()).await;
             Python::with_gil(|py| {
                 let dict = PyDict::new(py);
+                let body = match body {
+                    Ok(body) => body,
+                    _ => {
+                        RequestTrace::of(&req).client_disconnected();
+                        dict.set_item(
+                            pyo3::intern!(py, "type"),
+                            pyo3::intern!(py, "http.disconnect")
+                        )?;
+                        return Ok(dict.to_object(py))
+                    }
+                };
                 dict.set_item(
                     pyo3::intern!(py, "type"),
                     pyo3::intern!(py, "http.request")
                 )?;
-                dict.set_item(pyo3::intern!(py, "body"), &body.to_vec())?;
+                dict.set_item(pyo3::intern!(py, "body"), PyBytes::new(py, &body))?;
                 dict.set_item(pyo3::intern!(py, "more_body"), false)?;
                 Ok(dict.to_object(py))
             })
         })
     }
 
-    fn send(&mut self, data: &PyDict) -> PyResult<()> {
+    fn send<'p>(&mut self, py: Python<'p>, data: &PyDict) -> PyResult<Option<&'p PyAny>> {
         match adapt_message_type(data) {
             Ok(ASGIMessageType::HTTPStart) => {
-                match self.response_inited {
-                    false => {
-                        self.response_status = adapt_status_code(data).unwrap();
-                        self.response_headers = adapt_headers(data);
-                        self.response_inited = true;
-                        Ok(())
-                    },
-                    _ => error_flow!()
-                }
+                let state = match self.conformance.next_state(self.state, ResponseEvent::Start)? {
+                    Some(state) => state,
+                    None => return Ok(None)
+                };
+                self.response_status = adapt_status_code(data, self.conformance)?;
+                self.response_headers = adapt_response_headers(data, self.header_validation, self.conformance)?;
+                self.state = state;
+                Ok(None)
             },
             Ok(ASGIMessageType::HTTPBody) => {
-                match (self.response_inited, self.response_built) {
-                    (true, false) => {
-                        let (body, more) = adapt_body(data);
-                        self.send_body(&body[..], !more);
-                        Ok(())
-                    },
-                    _ => error_flow!()
-                }
+                let (body, more) = adapt_body(data, self.conformance)?;
+                self.state = match self.conformance.next_state(self.state, ResponseEvent::Body { more })? {
+                    Some(state) => state,
+                    None => return Ok(None)
+                };
+                self.send_body(py, body, !more)
+            },
+            Ok(ASGIMessageType::HTTPPathSend) => {
+                let state = match self.conformance.next_state(self.state, ResponseEvent::File)? {
+                    Some(state) => state,
+                    None => return Ok(None)
+                };
+                let path = adapt_path(data)?;
+                self.state = state;
+                self.send_file(path).map(|_| None)
             },
             Err(err) => Err(err.into()),
             _ => error_message!()
         }
     }
 
-    fn response_start(&mut self, status: i16, pyheaders: Vec<Vec<&[u8]>>) -> PyResult<()> {
+    fn response_start(&mut self, status: i32, pyheaders: Vec<Vec<&[u8]>>) -> PyResult<()> {
+        let state = self.state.next(ResponseEvent::Start)?;
         let mut headers = HeaderMap::new();
-        headers.insert(HK_SERVER, HV_SERVER);
-        self.response_status = status;
+        self.response_status = response_status(status)?;
         for tup in pyheaders.iter() {
             match (
                 HeaderName::from_bytes(tup[0]),
@@ -143,18 +251,13 @@ impl ASGIHTTPProtocol {
             }
         };
         self.response_headers = headers;
-        self.response_inited = true;
+        self.state = state;
         Ok(())
     }
 
-    fn response_body(&mut self, body: &[u8], has_more: bool) -> PyResult<()> {
-        match (self.response_inited, self.response_built) {
-            (true, false) => {
-                self.send_body(body, !has_more);
-                Ok(())
-            },
-            _ => error_flow!()
-        }
+    fn response_body<'p>(&mut self, py: Python<'p>, body: BufferBody, has_more: bool) -> PyResult<Option<&'p PyAny>> {
+        self.state = self.state.next(ResponseEvent::Body { more: has_more })?;
+        self.send_body(py, body.0, !has_more)
     }
 }
 
@@ -164,10 +267,11 @@ pub(crate) struct ASGIWebsocketProtocol {
     tx: Option<oneshot::Sender<bool>>,
     websocket: Option<HyperWebsocket>,
     upgrade: Option<UpgradeData>,
-    ws_tx: Arc<Mutex<Option<SplitSink<WebSocketStream<hyper::upgrade::Upgraded>, Message>>>>,
-    ws_rx: Arc<Mutex<Option<SplitStream<WebSocketStream<hyper::upgrade::Upgraded>>>>>,
+    ws_tx: Arc<Mutex<Option<SplitSink<WebsocketTransport, Message>>>>,
+    ws_rx: Arc<Mutex<Option<SplitStream<WebsocketTransport>>>>,
     accepted: Arc<Mutex<bool>>,
-    closed: bool
+    closed: bool,
+    stats: WebsocketStats
 }
 
 impl ASGIWebsocketProtocol {
@@ -175,12 +279,13 @@ impl ASGIWebsocketProtocol {
         rt: RuntimeRef,
         tx: oneshot::Sender<bool>,
         websocket: HyperWebsocket,
-        upgrade: UpgradeData
+        mut upgrade: UpgradeData
     ) -> Self {
         Self {
             rt: rt,
             tx: Some(tx),
             websocket: Some(websocket),
+            stats: WebsocketStats::new(upgrade.metrics.clone(), upgrade.session.take()),
             upgrade: Some(upgrade),
             ws_tx: Arc::new(Mutex::new(None)),
             ws_rx: Arc::new(Mutex::new(None)),
@@ -190,15 +295,18 @@ impl ASGIWebsocketProtocol {
     }
 
     #[inline(always)]
-    fn accept<'p>(&mut self, py: Python<'p>) -> PyResult<&'p PyAny> {
+    fn accept<'p>(&mut self, py: Python<'p>, data: &'p PyDict) -> PyResult<&'p PyAny> {
         let mut upgrade = self.upgrade.take().unwrap();
+        upgrade.extend_headers(adapt_headers(data));
         let websocket = self.websocket.take().unwrap();
         let accepted = self.accepted.clone();
         let tx = self.ws_tx.clone();
         let rx = self.ws_rx.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             if let Ok(_) = upgrade.send().await {
                 if let Ok(stream) = websocket.await {
+                    stats.opened();
                     let mut wtx = tx.lock().await;
                     let mut wrx = rx.lock().await;
                     let mut accepted = accepted.lock().await;
@@ -222,11 +330,14 @@ impl ASGIWebsocketProtocol {
         let transport = self.ws_tx.clone();
         let closed = self.closed.clone();
         let message = ws_message_into_rs(data);
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             if !closed {
                 if let Ok(message) = message {
+                    let size = message.len();
                     if let Some(ws) = &mut *(transport.lock().await) {
                         if let Ok(_) = ws.send(message).await {
+                            stats.outbound(size);
                             return Ok(())
                         }
                     };
@@ -260,8 +371,10 @@ macro_rules! empty_future {
 impl ASGIWebsocketProtocol {
     fn receive<'p>(&mut self, py: Python<'p>) -> PyResult<&'p PyAny> {
         let transport = self.ws_rx.clone();
+        let sink = self.ws_tx.clone();
         let accepted = self.accepted.clone();
         let closed = self.closed.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             let accepted = accepted.lock().await;
             match (*accepted, closed) {
@@ -289,8 +402,15 @@ impl ASGIWebsocketProtocol {
                                     continue
                                 },
                                 Ok(message) => {
+                                    stats.inbound(&message);
                                     return ws_message_into_py(message)
                                 },
+                                Err(tungstenite::Error::Utf8) => {
+                                    if let Some(sink) = &mut *(sink.lock().await) {
+                                        fail_invalid_payload(sink, &stats).await;
+                                    }
+                                    return ws_message_into_py(invalid_payload_close())
+                                },
                                 _ => {
                                     break
                                 }
@@ -309,10 +429,11 @@ impl ASGIWebsocketProtocol {
     fn send<'p>(&mut self, py: Python<'p>, data: &'p PyDict) -> PyResult<&'p PyAny> {
         match adapt_message_type(data) {
             Ok(ASGIMessageType::WSAccept) => {
-                self.accept(py)
+                self.accept(py, data)
             },
             Ok(ASGIMessageType::WSClose) => {
                 self.closed = true;
+                self.stats.closed(adapt_close_code(data));
                 empty_future!(self.rt.clone(), py)
             },
             Ok(ASGIMessageType::WSMessage) => {
@@ -334,6 +455,7 @@ fn adapt_message_type(
             match message_type {
                 "http.response.start" => Ok(ASGIMessageType::HTTPStart),
                 "http.response.body" => Ok(ASGIMessageType::HTTPBody),
+                "http.response.pathsend" => Ok(ASGIMessageType::HTTPPathSend),
                 "websocket.accept" => Ok(ASGIMessageType::WSAccept),
                 "websocket.close" => Ok(ASGIMessageType::WSClose),
                 "websocket.send" => Ok(ASGIMessageType::WSMessage),
@@ -344,20 +466,67 @@ fn adapt_message_type(
     }
 }
 
+// Statuses need to be integers, while lenient mode also takes their strings
 #[inline(always)]
-fn adapt_status_code(message: &PyDict) -> Result<i16, UnsupportedASGIMessage> {
-    match message.get_item("status") {
-        Some(item) => {
-            Ok(item.extract()?)
+fn adapt_status_code(message: &PyDict, conformance: ProtocolConformance) -> PyResult<StatusCode> {
+    let item = match message.get_item("status") {
+        Some(item) => item,
+        _ => return error_message!()
+    };
+    if item.is_instance_of::<PyInt>()? && !item.is_instance_of::<PyBool>()? {
+        return response_status(item.extract()?)
+    }
+    conformance.violation(format!(
+        "ASGI response start status should be an integer, got {}", item.get_type().name()?
+    ))?;
+    match item.str()?.to_str()?.trim().parse() {
+        Ok(status) => response_status(status),
+        _ => Err(ProtocolViolationError::new_err(format!(
+            "Invalid ASGI response start status: {}", item.str()?
+        )))
+    }
+}
+
+#[inline(always)]
+fn adapt_close_code(message: &PyDict) -> u16 {
+    message.get_item("code").and_then(|item| item.extract().ok()).unwrap_or(1000)
+}
+
+// Response headers get validated, keeping unchecked application data out of the
+// response. They need to be pairs of bytes, while lenient mode also takes strings,
+// mappings and other objects, converted to strings, skipping the malformed pairs.
+#[inline(always)]
+fn adapt_response_headers(
+    message: &PyDict,
+    validation: HeaderValidation,
+    conformance: ProtocolConformance
+) -> PyResult<HeaderMap> {
+    let item = match message.get_item("headers") {
+        Some(item) if !item.is_none() => item,
+        _ => return Ok(HeaderMap::new())
+    };
+    let accum: HeaderPairs = match item.extract::<Vec<Vec<&[u8]>>>() {
+        Ok(accum) if accum.iter().all(|tup| tup.len() == 2) => {
+            accum.into_iter().map(|tup| (Cow::Borrowed(tup[0]), Cow::Borrowed(tup[1]))).collect()
         },
-        _ => error_message!()
+        _ => {
+            conformance.violation("ASGI response start headers should be an iterable of [name, value] byte pairs")?;
+            coerce_headers(item)?
+        }
+    };
+    let headers = validation
+        .headers(accum.iter().map(|(key, value)| (&key[..], &value[..])))
+        .map_err(PyRuntimeError::new_err)?;
+    let mut ret = HeaderMap::with_capacity(headers.len());
+    for (key, val) in headers {
+        ret.append(key, val);
     }
+    Ok(ret)
 }
 
 #[inline(always)]
 fn adapt_headers(message: &PyDict) -> HeaderMap {
     let mut ret = HeaderMap::new();
-    ret.insert(HK_SERVER, HV_SERVER);
     match message.get_item("headers") {
         Some(item) => {
             let accum: Vec<Vec<&[u8]>> = item.extract().unwrap_or(Vec::new());
@@ -366,7 +535,7 @@ fn adapt_headers(message: &PyDict) -> HeaderMap {
                     HeaderName::from_bytes(tup[0]),
                     HeaderValue::from_bytes(tup[1])
                 ) {
-                    (Ok(key), Ok(val)) => { ret.insert(key, val); },
+                    (Ok(key), Ok(val)) => { ret.append(key, val); },
                     _ => {}
                 }
             };
@@ -376,21 +545,78 @@ fn adapt_headers(message: &PyDict) -> HeaderMap {
     }
 }
 
+type HeaderPairs<'a> = Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>;
+
+fn coerce_headers(item: &PyAny) -> PyResult<HeaderPairs<'_>> {
+    let pairs = match item.downcast::<PyDict>() {
+        Ok(dict) => dict.items().as_ref(),
+        _ => item
+    };
+    let mut ret = Vec::new();
+    for pair in pairs.iter()? {
+        let pair = pair?;
+        if pair.len().ok() != Some(2) || pair.is_instance_of::<PyString>()? || pair.is_instance_of::<PyBytes>()? {
+            continue
+        }
+        ret.push((coerce_bytes(pair.get_item(0)?)?, coerce_bytes(pair.get_item(1)?)?));
+    }
+    Ok(ret)
+}
+
+fn coerce_bytes(item: &PyAny) -> PyResult<Cow<'_, [u8]>> {
+    if let Ok(bytes) = item.downcast::<PyBytes>() {
+        return Ok(Cow::Borrowed(bytes.as_bytes()))
+    }
+    if let Ok(body) = item.extract::<BufferBody>() {
+        return Ok(Cow::Owned(body.0.to_vec()))
+    }
+    Ok(Cow::Owned(item.str()?.to_str()?.as_bytes().to_vec()))
+}
+
+// Bodies need to be bytes-like objects and `more_body` a boolean, while lenient
+// mode also takes strings, encoded to UTF-8, `None` for empty bodies and any
+// object for `more_body`, by its truth value.
 #[inline(always)]
-fn adapt_body(message: &PyDict) -> (Vec<u8>, bool) {
+fn adapt_body(message: &PyDict, conformance: ProtocolConformance) -> PyResult<(Bytes, bool)> {
     let body = match message.get_item("body") {
-        Some(item) => {
-            item.extract().unwrap_or(EMPTY_BYTES)
+        Some(item) => match item.extract::<BufferBody>() {
+            Ok(body) => body.0,
+            _ => {
+                conformance.violation(format!(
+                    "ASGI response body should be a bytes-like object, got {}", item.get_type().name()?
+                ))?;
+                match item.downcast::<PyString>() {
+                    Ok(string) => Bytes::from(string.to_str()?.to_owned()),
+                    _ if item.is_none() => Bytes::new(),
+                    _ => return Err(ProtocolViolationError::new_err(format!(
+                        "Invalid ASGI response body of type {}", item.get_type().name()?
+                    )))
+                }
+            }
         },
-        _ => EMPTY_BYTES
+        _ => Bytes::new()
     };
     let more = match message.get_item("more_body") {
-        Some(item) => {
-            item.extract().unwrap_or(false)
+        Some(item) => match item.downcast::<PyBool>() {
+            Ok(more) => more.is_true(),
+            _ => {
+                conformance.violation(format!(
+                    "ASGI response body more_body should be a boolean, got {}", item.get_type().name()?
+                ))?;
+                item.is_true()?
+            }
         },
         _ => false
     };
-    (body, more)
+    Ok((body, more))
+}
+
+#[inline(always)]
+fn adapt_path(message: &PyDict) -> Result<String, UnsupportedASGIMessage> {
+    match message.get_item("path") {
+        Some(item) => Ok(item.extract()?),
+        _ => error_message!()
+    }
 }
 
 #[inline(always)]
@@ -447,13 +673,17 @@ fn ws_message_into_py(message: Message) -> PyResult<PyObject> {
                 Ok(dict.to_object(py))
             })
         },
-        Message::Close(_) => {
+        Message::Close(frame) => {
             Python::with_gil(|py| {
                 let dict = PyDict::new(py);
                 dict.set_item(
                     pyo3::intern!(py, "type"),
                     pyo3::intern!(py, "websocket.disconnect")
                 )?;
+                dict.set_item(
+                    pyo3::intern!(py, "code"),
+                    frame.map_or(1005, |frame| u16::from(frame.code))
+                )?;
                 Ok(dict.to_object(py))
             })
         },
@@ -2,12 +2,13 @@ use pyo3::prelude::*;
 
 mod callbacks;
 mod errors;
-mod http;
+pub(crate) mod http;
 mod io;
 pub(crate) mod serve;
 mod types;
 
-pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+pub(crate) fn init_pymodule(py: Python, module: &PyModule) -> PyResult<()> {
+    module.add("ASGIConnectionClosed", py.get_type::<errors::ASGIConnectionClosed>())?;
     module.add_class::<io::ASGIHTTPProtocol>()?;
     module.add_class::<io::ASGIWebsocketProtocol>()?;
     module.add_class::<types::ASGIScope>()?;
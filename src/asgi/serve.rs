@@ -3,6 +3,8 @@ use pyo3::prelude::*;
 use crate::{
     workers::{
         WorkerConfig,
+        WorkerIdentity,
+        WorkerOptions,
         serve_rth,
         serve_wth,
         serve_rth_ssl,
@@ -17,6 +19,10 @@ pub struct ASGIWorker {
 }
 
 impl ASGIWorker {
+    pub(crate) fn from_config(config: WorkerConfig) -> Self {
+        Self { config }
+    }
+
     serve_rth!(_serve_rth, handle_rtb);
     serve_rth!(_serve_rth_ws, handle_rtb_ws);
     serve_wth!(_serve_wth, handle_rtt);
@@ -30,36 +36,18 @@ impl ASGIWorker {
 #[pymethods]
 impl ASGIWorker {
     #[new]
-    #[args(socket_fd, threads="1", http1_buffer_max="65535")]
     fn new(
         worker_id: i32,
+        worker_generation: u32,
+        memory_recycles: u64,
         socket_fd: i32,
-        threads: usize,
-        pthreads: usize,
-        http_mode: String,
-        http1_buffer_max: usize,
-        websockets_enabled: bool,
-        ssl_enabled: bool,
-        ssl_cert: Option<String>,
-        ssl_key: Option<String>
+        options: WorkerOptions
     ) -> PyResult<Self> {
-        Ok(Self {
-            config: WorkerConfig::new(
-                worker_id,
-                socket_fd,
-                threads,
-                pthreads,
-                http_mode,
-                http1_buffer_max,
-                websockets_enabled,
-                ssl_enabled,
-                ssl_cert,
-                ssl_key
-            )
-        })
+        let identity = WorkerIdentity { id: worker_id, generation: worker_generation, memory_recycles };
+        Ok(Self { config: WorkerConfig::new(identity, socket_fd, options)? })
     }
 
-    fn serve_rth(
+    pub(crate) fn serve_rth(
         &self,
         callback: PyObject,
         event_loop: &PyAny,
@@ -74,7 +62,7 @@ impl ASGIWorker {
         }
     }
 
-    fn serve_wth(
+    pub(crate) fn serve_wth(
         &self,
         callback: PyObject,
         event_loop: &PyAny,
@@ -1,6 +1,16 @@
 use hyper::{Uri, Version, header::{HeaderMap}};
-use pyo3::prelude::*;
-use std::net::SocketAddr;
+use pyo3::{prelude::*, types::{PyBytes, PyDict, PyString}};
+use std::{borrow::Cow, net::SocketAddr, sync::Arc};
+
+use crate::{
+    deadlines::Deadline,
+    interning::{header_value_bytes, intern_bytes, intern_str},
+    scratch::ScratchDir,
+    tcp::addr_ip,
+    tls::TlsSession,
+    urls::{PathDecoding, query_params},
+    workers::identity
+};
 
 
 const SCHEME_HTTPS: &str = "https";
@@ -10,6 +20,7 @@ const SCHEME_WSS: &str = "wss";
 pub(crate) enum ASGIMessageType {
     HTTPStart,
     HTTPBody,
+    HTTPPathSend,
     WSAccept,
     WSClose,
     WSMessage
@@ -19,7 +30,6 @@ pub(crate) enum ASGIMessageType {
 pub(crate) struct ASGIScope {
     http_version: Version,
     scheme: String,
-    #[pyo3(get)]
     method: String,
     uri: Uri,
     #[pyo3(get)]
@@ -31,7 +41,12 @@ pub(crate) struct ASGIScope {
     #[pyo3(get)]
     client_port: u16,
     headers: HeaderMap,
-    is_websocket: bool
+    path_decoding: PathDecoding,
+    is_websocket: bool,
+    deadline: Option<Deadline>,
+    tls: Option<Arc<TlsSession>>,
+    scratch: ScratchDir,
+    state: Option<Py<PyDict>>
 }
 
 // TODO: server address
@@ -43,22 +58,35 @@ impl ASGIScope {
         method: &str,
         server: SocketAddr,
         client: SocketAddr,
-        headers: &HeaderMap
+        headers: &HeaderMap,
+        path_decoding: PathDecoding,
+        deadline: Option<Deadline>,
+        tls: Option<Arc<TlsSession>>,
+        state: Option<Py<PyDict>>
     ) -> Self {
         Self {
             http_version: http_version,
             scheme: scheme.to_string(),
             method: method.to_string(),
             uri: uri,
-            server_ip: server.ip().to_string(),
+            server_ip: addr_ip(server),
             server_port: server.port(),
-            client_ip: client.ip().to_string(),
+            client_ip: addr_ip(client),
             client_port: client.port(),
             headers: headers.to_owned(),
-            is_websocket: false
+            path_decoding,
+            is_websocket: false,
+            deadline,
+            tls,
+            scratch: ScratchDir::default(),
+            state
         }
     }
 
+    pub fn scratch(&self) -> ScratchDir {
+        self.scratch.clone()
+    }
+
     pub fn set_websocket(&mut self) {
         self.is_websocket = true
     }
@@ -74,11 +102,19 @@ impl ASGIScope {
         }
     }
 
+    #[getter(method)]
+    fn get_method<'p>(&self, py: Python<'p>) -> &'p PyString {
+        intern_str(py, &self.method)
+    }
+
     #[getter(headers)]
-    fn get_headers(&self) -> Vec<(&[u8], &[u8])> {
+    fn get_headers<'p>(&self, py: Python<'p>) -> Vec<(&'p PyBytes, &'p PyBytes)> {
         let mut ret = Vec::with_capacity(self.headers.len());
         for (key, value) in self.headers.iter() {
-            ret.push((key.as_str().as_bytes(), value.as_bytes()));
+            ret.push((
+                intern_bytes(py, key.as_str().as_bytes()),
+                header_value_bytes(py, key, value)
+            ));
         }
         ret
     }
@@ -108,7 +144,12 @@ impl ASGIScope {
     }
 
     #[getter(path)]
-    fn get_path(&self) -> &str {
+    fn get_path(&self) -> Cow<'_, str> {
+        self.path_decoding.path(self.uri.path())
+    }
+
+    #[getter(raw_path)]
+    fn get_raw_path(&self) -> &str {
         self.uri.path()
     }
 
@@ -116,4 +157,47 @@ impl ASGIScope {
     fn get_query_string(&self) -> &str {
         self.uri.query().unwrap_or("")
     }
+
+    #[getter(worker_id)]
+    fn get_worker_id(&self) -> i32 {
+        identity().id
+    }
+
+    #[getter(worker_generation)]
+    fn get_worker_generation(&self) -> u32 {
+        identity().generation
+    }
+
+    // The `tls` extension of the ASGI spec: a tuple of the TLS version, cipher
+    // suite, client certificate chain in PEM format and client subject
+    fn tls_info(&self) -> Option<(u16, u16, Vec<String>, Option<String>)> {
+        self.tls.as_ref().map(|tls| (
+            tls.version,
+            tls.cipher_suite,
+            tls.client_chain_pem(),
+            tls.client_subject.clone()
+        ))
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        query_params(self.uri.query().unwrap_or(""))
+    }
+
+    fn scratch_dir(&self) -> PyResult<String> {
+        Ok(self.scratch.path()?.to_string_lossy().into_owned())
+    }
+
+    fn deadline_remaining(&self) -> Option<f64> {
+        self.deadline.map(|deadline| deadline.remaining())
+    }
+
+    // A shallow copy of the lifespan state: requests can't add keys for the
+    // following ones, while mutable values are shared across all of them.
+    #[getter(state)]
+    fn get_state<'p>(&self, py: Python<'p>) -> PyResult<&'p PyDict> {
+        match &self.state {
+            Some(state) => state.clone_ref(py).into_ref(py).copy(),
+            None => Ok(PyDict::new(py))
+        }
+    }
 }
@@ -0,0 +1,141 @@
+use once_cell::sync::Lazy;
+use std::{
+    net::TcpListener,
+    sync::{Mutex, Once, Weak},
+    time::Duration
+};
+
+use crate::workers::identity;
+
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+static LISTENERS: Lazy<Mutex<Vec<Weak<TcpListener>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static MONITOR: Once = Once::new();
+
+// Connections the kernel gave up on before the accept, as counted in the
+// `TcpExt` section of `/proc/net/netstat`: the overflows of the accept queue,
+// and the SYNs dropped on a listening socket for any reason, overflows
+// included. The counters are shared by all the listeners of the network
+// namespace, which is usually just Granian's own within containers.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ListenDrops {
+    pub overflows: u64,
+    pub drops: u64
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn listen_drops() -> Option<ListenDrops> {
+    let netstat = std::fs::read_to_string("/proc/net/netstat").ok()?;
+    let mut lines = netstat.lines().filter(|line| line.starts_with("TcpExt:"));
+    let (keys, values) = (lines.next()?, lines.next()?);
+    let (mut overflows, mut drops) = (None, None);
+    for (key, value) in keys.split_whitespace().zip(values.split_whitespace()).skip(1) {
+        match key {
+            "ListenOverflows" => overflows = value.parse().ok(),
+            "ListenDrops" => drops = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some(ListenDrops { overflows: overflows?, drops: drops? })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn listen_drops() -> Option<ListenDrops> {
+    None
+}
+
+// The connections waiting in the accept queue of a listening socket, along
+// with the queue size, as reported by `TCP_INFO` for sockets in listen state.
+pub(crate) struct AcceptQueue {
+    pub length: u32,
+    pub limit: u32
+}
+
+// The leading fields of the kernel `struct tcp_info`, the ones up to the
+// queue values, getting filled anyway as the kernel copies what fits
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct TcpInfo {
+    state: u8,
+    _flags: [u8; 7],
+    _rto: u32,
+    _ato: u32,
+    _snd_mss: u32,
+    _rcv_mss: u32,
+    unacked: u32,
+    sacked: u32
+}
+
+#[cfg(target_os = "linux")]
+fn accept_queue_of(listener: &TcpListener) -> Option<AcceptQueue> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut info = TcpInfo::default();
+    let mut len = std::mem::size_of::<TcpInfo>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            listener.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut TcpInfo as *mut libc::c_void,
+            &mut len
+        )
+    };
+    // TCP_LISTEN
+    if ret != 0 || info.state != 10 {
+        return None
+    }
+    Some(AcceptQueue { length: info.unacked, limit: info.sacked })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn accept_queue_of(_listener: &TcpListener) -> Option<AcceptQueue> {
+    None
+}
+
+// Workers threads accept from clones of the same socket, any of them will do
+pub(crate) fn accept_queue() -> Option<AcceptQueue> {
+    let mut listeners = LISTENERS.lock().unwrap();
+    listeners.retain(|listener| listener.strong_count() > 0);
+    listeners.iter().find_map(|listener| accept_queue_of(listener.upgrade()?.as_ref()))
+}
+
+// Listeners get followed until dropped. The first one starts a thread checking
+// the kernel counters periodically, warning when connections got dropped. As
+// the workers share the listening socket, only the first one logs about it.
+pub(crate) fn register(listener: Weak<TcpListener>) {
+    LISTENERS.lock().unwrap().push(listener);
+    MONITOR.call_once(|| {
+        let mut last = match listen_drops() {
+            Some(drops) => drops,
+            None => return
+        };
+        let _ = std::thread::Builder::new()
+            .name("granian-backlog".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(CHECK_INTERVAL);
+                let current = match listen_drops() {
+                    Some(drops) => drops,
+                    None => continue
+                };
+                let (overflows, drops) = (
+                    current.overflows.saturating_sub(last.overflows),
+                    current.drops.saturating_sub(last.drops)
+                );
+                last = current;
+                if drops == 0 || identity().id > 1 {
+                    continue
+                }
+                let queue = accept_queue()
+                    .map(|queue| format!(", accept queue at {}/{}", queue.length, queue.limit))
+                    .unwrap_or_default();
+                log::warn!(
+                    "The kernel dropped {} incoming connections in the last {}s ({} accept queue overflows{}), \
+                    consider more workers or a larger backlog",
+                    drops, CHECK_INTERVAL.as_secs(), overflows, queue
+                );
+            });
+    });
+}
@@ -0,0 +1,56 @@
+use bytes::Bytes;
+use pyo3::{ffi, prelude::*, AsPyPointer};
+
+
+// Copying small payloads is cheaper than holding the Python buffer,
+// since releasing it later requires the GIL.
+const COPY_THRESHOLD: usize = 16 * 1024;
+
+struct PyBufferView {
+    view: Box<ffi::Py_buffer>
+}
+
+// The exported memory stays valid until the view gets released, and the
+// release always happens holding the GIL.
+unsafe impl Send for PyBufferView {}
+
+impl PyBufferView {
+    fn get(obj: &PyAny) -> PyResult<Self> {
+        let mut view = Box::new(ffi::Py_buffer::new());
+        let ret = unsafe {
+            ffi::PyObject_GetBuffer(obj.as_ptr(), &mut *view, ffi::PyBUF_C_CONTIGUOUS)
+        };
+        if ret == -1 {
+            return Err(PyErr::fetch(obj.py()))
+        }
+        Ok(Self { view })
+    }
+}
+
+impl AsRef<[u8]> for PyBufferView {
+    fn as_ref(&self) -> &[u8] {
+        match self.view.len {
+            0 => &[],
+            len => unsafe { std::slice::from_raw_parts(self.view.buf as *const u8, len as usize) }
+        }
+    }
+}
+
+impl Drop for PyBufferView {
+    fn drop(&mut self) {
+        Python::with_gil(|_| unsafe { ffi::PyBuffer_Release(&mut *self.view) });
+    }
+}
+
+// Body data extracted from any C-contiguous object implementing the buffer protocol
+pub(crate) struct BufferBody(pub Bytes);
+
+impl<'source> FromPyObject<'source> for BufferBody {
+    fn extract(obj: &'source PyAny) -> PyResult<Self> {
+        let view = PyBufferView::get(obj)?;
+        match view.as_ref().len() {
+            len if len <= COPY_THRESHOLD => Ok(Self(Bytes::copy_from_slice(view.as_ref()))),
+            _ => Ok(Self(Bytes::from_owner(view)))
+        }
+    }
+}
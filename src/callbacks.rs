@@ -1,15 +1,23 @@
-use pyo3::prelude::*;
+use pyo3::{prelude::*, types::PyDict};
 
 #[derive(Clone)]
 pub(crate) struct CallbackWrapper {
     pub callback: PyObject,
+    pub fast_path: Option<PyObject>,
+    // the ASGI lifespan state, shared by every request of the worker
+    pub state: Option<Py<PyDict>>,
     pub context: pyo3_asyncio::TaskLocals
 }
 
 impl CallbackWrapper {
     pub(crate) fn new(callback: PyObject, event_loop: &PyAny, context: &PyAny) -> Self {
+        let py = event_loop.py();
+        let fast_path = callback.getattr(py, "fast_path").ok().filter(|obj| !obj.is_none(py));
+        let state = callback.getattr(py, "lifespan_state").ok().and_then(|obj| obj.extract(py).ok());
         Self {
             callback: callback,
+            fast_path,
+            state,
             context: pyo3_asyncio::TaskLocals::new(event_loop).with_context(context)
         }
     }
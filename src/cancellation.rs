@@ -0,0 +1,165 @@
+use hyper::{Body, Request};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration
+};
+
+use crate::{
+    deadlines::{Deadline, request_deadline},
+    drain::Drain,
+    metrics::{RouteSegment, parse_route, route_matches}
+};
+
+
+struct CancellationConfig {
+    grace: Option<Duration>,
+    exempt: Vec<Vec<RouteSegment>>
+}
+
+// Delivery of cancellations into the tasks of the handlers whose response
+// nobody waits for anymore: the client disconnected, the request deadline
+// expired, or the drain timeout did on shutdown. Handlers get `grace` to end on
+// their own first, but on shutdown, where the drain timeout already gave them
+// time. Routes matching `exempt` are never cancelled, neither are handlers
+// already sending their response, as their sends follow the disconnect policy.
+#[derive(Clone, Default)]
+pub(crate) struct Cancellation {
+    inner: Option<Arc<CancellationConfig>>
+}
+
+impl Cancellation {
+    pub fn new(enabled: bool, grace: f64, exempt: Vec<String>) -> PyResult<Self> {
+        if grace.is_nan() || grace < 0.0 {
+            return Err(PyValueError::new_err("Cancellation grace should not be negative"))
+        }
+        if !enabled {
+            return Ok(Self { inner: None })
+        }
+        let exempt = exempt.iter().map(|template| parse_route(template)).collect::<PyResult<_>>()?;
+        Ok(Self { inner: Some(Arc::new(CancellationConfig {
+            grace: (grace > 0.0).then(|| Duration::from_secs_f64(grace)),
+            exempt
+        })) })
+    }
+
+    // Arms the cancellation of the handler of the request, lasting until the
+    // returned guard gets either disarmed or dropped.
+    pub fn watch(
+        &self,
+        mut req: Request<Body>,
+        locals: &pyo3_asyncio::TaskLocals,
+        drain: &Drain
+    ) -> (Request<Body>, CancelGuard) {
+        let config = match &self.inner {
+            Some(config) if !config.exempt.iter().any(|segments| route_matches(segments, req.uri().path())) => config,
+            _ => return (req, CancelGuard(None))
+        };
+        let handle = CancelHandle::default();
+        req.extensions_mut().insert(handle.clone());
+        let guard = CancelGuard(Some(Armed {
+            config: config.clone(),
+            handle,
+            locals: locals.clone(),
+            drain: drain.clone(),
+            deadline: request_deadline(&req)
+        }));
+        (req, guard)
+    }
+}
+
+#[derive(Default)]
+struct TaskSlot {
+    task: Option<PyObject>,
+    reason: Option<&'static str>
+}
+
+// Shared between the request and its handler task, which gets attached once
+// scheduled on the event loop, possibly after the cancellation was requested.
+#[derive(Clone, Default)]
+pub(crate) struct CancelHandle(Arc<Mutex<TaskSlot>>);
+
+impl CancelHandle {
+    pub fn of(req: &Request<Body>) -> Option<Self> {
+        req.extensions().get::<Self>().cloned()
+    }
+
+    pub fn task(self, py: Python) -> Py<HandlerTask> {
+        Py::new(py, HandlerTask(self)).unwrap()
+    }
+
+    fn cancel(&self, locals: &pyo3_asyncio::TaskLocals, reason: &'static str) {
+        let task = {
+            let mut slot = self.0.lock().unwrap();
+            slot.reason = Some(reason);
+            slot.task.take()
+        };
+        log::debug!("Cancelling request handler: {}", reason);
+        if let Some(task) = task {
+            Python::with_gil(|py| {
+                let cancel = task.getattr(py, "cancel")?;
+                locals.event_loop(py).call_method1("call_soon_threadsafe", (cancel,))?;
+                Ok::<_, PyErr>(())
+            }).unwrap_or_else(|err| log::debug!("Unable to cancel request handler: {}", err));
+        }
+    }
+}
+
+#[pyclass(module="granian._granian")]
+pub(crate) struct HandlerTask(CancelHandle);
+
+#[pymethods]
+impl HandlerTask {
+    // Called on the event loop, right after the handler task got created
+    fn attach(&self, py: Python, task: PyObject) -> PyResult<()> {
+        let mut slot = self.0.0.lock().unwrap();
+        if slot.reason.is_some() {
+            drop(slot);
+            task.call_method0(py, "cancel")?;
+            return Ok(())
+        }
+        slot.task = Some(task);
+        Ok(())
+    }
+}
+
+struct Armed {
+    config: Arc<CancellationConfig>,
+    handle: CancelHandle,
+    locals: pyo3_asyncio::TaskLocals,
+    drain: Drain,
+    deadline: Option<Deadline>
+}
+
+pub(crate) struct CancelGuard(Option<Armed>);
+
+impl CancelGuard {
+    // The handler produced its response, and gets to end on its own
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        let armed = match self.0.take() {
+            Some(armed) => armed,
+            None => return
+        };
+        if armed.drain.draining() {
+            return armed.handle.cancel(&armed.locals, "server shutting down")
+        }
+        let reason = match armed.deadline {
+            Some(deadline) if deadline.remaining() <= 0.0 => "request deadline exceeded",
+            _ => "client disconnected"
+        };
+        let (grace, rt) = match (armed.config.grace, tokio::runtime::Handle::try_current()) {
+            (Some(grace), Ok(rt)) => (grace, rt),
+            _ => return armed.handle.cancel(&armed.locals, reason)
+        };
+        rt.spawn(async move {
+            tokio::time::sleep(grace).await;
+            armed.handle.cancel(&armed.locals, reason);
+        });
+    }
+}
@@ -0,0 +1,178 @@
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration
+};
+
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const V1_CPU_MOUNTS: [&str; 3] = ["cpu,cpuacct", "cpuacct,cpu", "cpu"];
+const V1_MEMORY_MOUNTS: [&str; 1] = ["memory"];
+// v1 reports no memory limit as the largest page aligned signed value
+const V1_MEMORY_UNLIMITED: u64 = 1 << 62;
+
+// The cgroup the process belongs to for a given controller, from the legacy
+// (v1) hierarchy or the unified (v2) one. Within containers the cgroup path
+// listed in `/proc/self/cgroup` might not be visible, in which case the root of
+// the mount is the container own cgroup.
+struct Cgroup {
+    legacy: bool,
+    mount: PathBuf,
+    dir: PathBuf
+}
+
+static CPU: Lazy<Option<Cgroup>> = Lazy::new(|| Cgroup::detect("cpu", &V1_CPU_MOUNTS, "cpu.cfs_quota_us"));
+static MEMORY: Lazy<Option<Cgroup>> = Lazy::new(|| {
+    Cgroup::detect("memory", &V1_MEMORY_MOUNTS, "memory.limit_in_bytes")
+});
+
+fn resolve(mount: &Path, path: &str) -> PathBuf {
+    let dir = mount.join(path.trim_start_matches('/'));
+    if dir.is_dir() { dir } else { mount.to_path_buf() }
+}
+
+fn read_value<T: std::str::FromStr>(path: PathBuf) -> Option<T> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+impl Cgroup {
+    fn detect(controller: &str, v1_mounts: &[&str], v1_probe: &str) -> Option<Self> {
+        let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+        let mut unified = None;
+        for line in cgroups.lines() {
+            let mut parts = line.splitn(3, ':');
+            let (id, controllers, path) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(id), Some(controllers), Some(path)) => (id, controllers, path),
+                _ => continue
+            };
+            // in hybrid setups the controllers stay on the legacy hierarchy
+            if controllers.split(',').any(|name| name == controller) {
+                for name in v1_mounts {
+                    let mount = Path::new(CGROUP_ROOT).join(name);
+                    if mount.join(v1_probe).exists() {
+                        let dir = resolve(&mount, path);
+                        return Some(Self { legacy: true, mount, dir })
+                    }
+                }
+            }
+            if id == "0" && controllers.is_empty() {
+                unified = Some(path.to_string());
+            }
+        }
+        let path = unified?;
+        let enabled = fs::read_to_string(Path::new(CGROUP_ROOT).join("cgroup.controllers")).ok()?;
+        if !enabled.split_whitespace().any(|name| name == controller) {
+            return None
+        }
+        let mount = PathBuf::from(CGROUP_ROOT);
+        Some(Self { legacy: false, dir: resolve(&mount, &path), mount })
+    }
+
+    // Limits of the parent cgroups apply as well, the strictest one wins
+    fn limit<T, F>(&self, read: F) -> Option<T>
+    where
+        T: PartialOrd,
+        F: Fn(&Path) -> Option<T>
+    {
+        self.dir.ancestors()
+            .take_while(|ancestor| ancestor.starts_with(&self.mount))
+            .filter_map(read)
+            .reduce(|strictest, limit| if limit < strictest { limit } else { strictest })
+    }
+
+    fn cpu_quota(&self) -> Option<f64> {
+        self.limit(|dir| {
+            let (quota, period) = if self.legacy {
+                let quota: i64 = read_value(dir.join("cpu.cfs_quota_us"))?;
+                let period: i64 = read_value(dir.join("cpu.cfs_period_us"))?;
+                (quota as f64, period as f64)
+            } else {
+                let max = fs::read_to_string(dir.join("cpu.max")).ok()?;
+                let mut parts = max.split_whitespace();
+                (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?)
+            };
+            (quota > 0.0 && period > 0.0).then(|| quota / period)
+        })
+    }
+
+    fn memory_limit(&self) -> Option<u64> {
+        self.limit(|dir| {
+            let limit: u64 = if self.legacy {
+                read_value(dir.join("memory.limit_in_bytes"))?
+            } else {
+                // "max" when unlimited
+                read_value(dir.join("memory.max"))?
+            };
+            (limit < V1_MEMORY_UNLIMITED).then_some(limit)
+        })
+    }
+
+    fn cpu_throttling(&self) -> Option<CpuThrottling> {
+        let (throttled_key, throttled_time): (_, fn(u64) -> Duration) = if self.legacy {
+            ("throttled_time", Duration::from_nanos)
+        } else {
+            ("throttled_usec", Duration::from_micros)
+        };
+        let stat = fs::read_to_string(self.dir.join("cpu.stat")).ok()?;
+        let (mut periods, mut throttled_periods, mut throttled) = (None, None, None);
+        for line in stat.lines() {
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim().parse::<u64>().ok()),
+                None => continue
+            };
+            match key {
+                "nr_periods" => periods = value,
+                "nr_throttled" => throttled_periods = value,
+                key if key == throttled_key => throttled = value,
+                _ => {}
+            }
+        }
+        Some(CpuThrottling {
+            periods: periods?,
+            throttled_periods: throttled_periods?,
+            throttled: throttled_time(throttled?)
+        })
+    }
+}
+
+// Statistics of the CFS bandwidth control: the enforcement periods elapsed,
+// the ones in which the cgroup ran out of quota and the overall time its
+// tasks were kept off the CPUs.
+pub(crate) struct CpuThrottling {
+    pub periods: u64,
+    pub throttled_periods: u64,
+    pub throttled: Duration
+}
+
+// The CPU quota of the process cgroup, as a number of CPUs
+pub(crate) fn cgroup_cpu_quota() -> Option<f64> {
+    CPU.as_ref()?.cpu_quota()
+}
+
+pub(crate) fn cgroup_cpu_throttling() -> Option<CpuThrottling> {
+    CPU.as_ref()?.cpu_throttling()
+}
+
+// The memory limit of the process cgroup, in bytes
+pub(crate) fn cgroup_memory_limit() -> Option<u64> {
+    MEMORY.as_ref()?.memory_limit()
+}
+
+#[pyfunction]
+fn cpu_quota() -> Option<f64> {
+    cgroup_cpu_quota()
+}
+
+#[pyfunction]
+fn memory_limit() -> Option<u64> {
+    cgroup_memory_limit()
+}
+
+pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(cpu_quota, module)?)?;
+    module.add_function(wrap_pyfunction!(memory_limit, module)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use once_cell::sync::Lazy;
+use std::{
+    sync::{Mutex, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant}
+};
+use tokio::sync::watch;
+
+
+// The time source of the subsystems keeping state across requests, like cache
+// expirations, TLS record sizing and the connection timers. Tests can swap it
+// with a manual clock: once mocked, time stands still and only moves forward
+// when advanced.
+static MOCKED: AtomicBool = AtomicBool::new(false);
+static MOCK_NOW: Mutex<Option<Instant>> = Mutex::new(None);
+// timers sleeping on the runtime need to know when the mocked time moves
+static CHANGES: Lazy<watch::Sender<()>> = Lazy::new(|| watch::channel(()).0);
+
+pub(crate) fn now() -> Instant {
+    if MOCKED.load(Ordering::Acquire) {
+        if let Some(now) = *MOCK_NOW.lock().unwrap() {
+            return now
+        }
+    }
+    Instant::now()
+}
+
+pub(crate) fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+pub(crate) fn mock() {
+    let mut mock_now = MOCK_NOW.lock().unwrap();
+    mock_now.get_or_insert_with(Instant::now);
+    MOCKED.store(true, Ordering::Release);
+}
+
+pub(crate) fn changes() -> watch::Receiver<()> {
+    CHANGES.subscribe()
+}
+
+pub(crate) fn advance(by: Duration) {
+    if let Some(mock_now) = MOCK_NOW.lock().unwrap().as_mut() {
+        *mock_now += by;
+    }
+    CHANGES.send_replace(());
+}
+
+pub(crate) fn reset() {
+    {
+        let mut mock_now = MOCK_NOW.lock().unwrap();
+        MOCKED.store(false, Ordering::Release);
+        *mock_now = None;
+    }
+    CHANGES.send_replace(());
+}
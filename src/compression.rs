@@ -0,0 +1,192 @@
+use bytes::Bytes;
+use futures::stream::Stream;
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    header::{
+        ACCEPT_ENCODING,
+        CACHE_CONTROL,
+        CONTENT_ENCODING,
+        CONTENT_LENGTH,
+        CONTENT_RANGE,
+        CONTENT_TYPE,
+        ETAG,
+        HeaderValue,
+        VARY
+    }
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use crate::{deflate::{Encoder, Wrapper}, http::content_length, negotiation::parse_weighted};
+
+
+const HV_VARY: HeaderValue = HeaderValue::from_static("accept-encoding");
+const DEFAULT_TYPES: [&str; 7] = [
+    "text/*",
+    "application/json",
+    "application/javascript",
+    "application/xml",
+    "application/*+json",
+    "application/*+xml",
+    "image/svg+xml"
+];
+// Compressed output gets sent once it reaches this size, or when the
+// application body has nothing more ready, so streams are never delayed.
+const OUTPUT_SIZE: usize = 16 * 1024;
+// Server preference, when the client weights several codings the same
+const OFFERS: [(&str, Wrapper); 2] = [("gzip", Wrapper::Gzip), ("deflate", Wrapper::Zlib)];
+
+struct CompressionPolicy {
+    min_size: u64,
+    types: Vec<String>
+}
+
+impl CompressionPolicy {
+    // Patterns might hold a single `*` wildcard, like `text/*` or `application/*+json`,
+    // or be `*/*` to compress any content type
+    fn compressible(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        self.types.iter().any(|pattern| pattern == "*/*" || match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                essence.len() >= prefix.len() + suffix.len() && essence.starts_with(prefix) && essence.ends_with(suffix)
+            },
+            None => essence == *pattern
+        })
+    }
+}
+
+// Compression of the application responses, with the coding preferred by the
+// client `Accept-Encoding` header among `gzip` and `deflate`. Responses get
+// compressed when their content type is allowed and they're large enough, with
+// streamed bodies, of unknown size, always compressed.
+#[derive(Clone, Default)]
+pub(crate) struct Compression {
+    policy: Option<Arc<CompressionPolicy>>
+}
+
+impl Compression {
+    pub fn new(enabled: bool, min_size: u64, types: Vec<String>) -> PyResult<Self> {
+        if !enabled {
+            return Ok(Self::default())
+        }
+        let types = match types.is_empty() {
+            true => DEFAULT_TYPES.iter().map(|value| value.to_string()).collect(),
+            false => types.iter().map(|value| value.trim().to_ascii_lowercase()).collect::<Vec<_>>()
+        };
+        if let Some(pattern) = types.iter().find(|pattern| {
+            *pattern != "*/*" && (pattern.matches('*').count() > 1 || !pattern.contains('/'))
+        }) {
+            return Err(PyValueError::new_err(format!("Invalid compression content type: {}", pattern)))
+        }
+        Ok(Self { policy: Some(Arc::new(CompressionPolicy { min_size, types })) })
+    }
+
+    pub fn negotiate(&self, req: &Request<Body>) -> Compress {
+        let policy = match &self.policy {
+            Some(policy) => policy.clone(),
+            None => return Compress(None)
+        };
+        let accepted = req.headers().get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(parse_weighted)
+            .unwrap_or_default();
+        let mut selected = (None, 0.0);
+        for (coding, wrapper) in OFFERS {
+            let quality = accepted.iter()
+                .filter(|(value, _)| value.eq_ignore_ascii_case(coding) || *value == "*")
+                .max_by_key(|(value, _)| *value != "*")
+                .map_or(0.0, |(_, quality)| *quality);
+            if quality > selected.1 {
+                selected = (Some(wrapper), quality);
+            }
+        }
+        Compress(Some((policy, selected.0)))
+    }
+}
+
+pub(crate) struct Compress(Option<(Arc<CompressionPolicy>, Option<Wrapper>)>);
+
+impl Compress {
+    pub fn respond(self, res: Response<Body>) -> Response<Body> {
+        let (policy, wrapper) = match self.0 {
+            Some(negotiated) => negotiated,
+            None => return res
+        };
+        let headers = res.headers();
+        let compressible = policy.compressible(headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or(""))
+            && !headers.contains_key(CONTENT_ENCODING)
+            && !headers.contains_key(CONTENT_RANGE)
+            && !matches!(res.status(), StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED | StatusCode::PARTIAL_CONTENT)
+            && !headers.get_all(CACHE_CONTROL).iter()
+                .any(|value| value.to_str().is_ok_and(|value| value.to_ascii_lowercase().contains("no-transform")))
+            && content_length(res.body()).is_none_or(|size| size >= policy.min_size);
+        if !compressible {
+            return res
+        }
+        let (mut parts, body) = res.into_parts();
+        parts.headers.append(VARY, HV_VARY);
+        let wrapper = match wrapper {
+            Some(wrapper) => wrapper,
+            None => return Response::from_parts(parts, body)
+        };
+        parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(match wrapper {
+            Wrapper::Gzip => "gzip",
+            Wrapper::Zlib => "deflate"
+        }));
+        parts.headers.remove(CONTENT_LENGTH);
+        // compressed representations are no longer the same bytes
+        if let Some(etag) = parts.headers.get(ETAG).and_then(|value| value.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                if let Ok(value) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                    parts.headers.insert(ETAG, value);
+                }
+            }
+        }
+        let body = Body::wrap_stream(CompressedBody { inner: body, encoder: Encoder::new(wrapper), done: false });
+        Response::from_parts(parts, body)
+    }
+}
+
+struct CompressedBody {
+    inner: Body,
+    encoder: Encoder,
+    done: bool
+}
+
+impl Stream for CompressedBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None)
+        }
+        loop {
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    this.encoder.write(&chunk);
+                    if this.encoder.output_size() >= OUTPUT_SIZE {
+                        return Poll::Ready(Some(Ok(Bytes::from(this.encoder.take()))))
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => {
+                    this.done = true;
+                    this.encoder.finish();
+                    return Poll::Ready(Some(Ok(Bytes::from(this.encoder.take()))))
+                },
+                Poll::Pending => {
+                    this.encoder.flush();
+                    let out = this.encoder.take();
+                    return match out.is_empty() {
+                        true => Poll::Pending,
+                        false => Poll::Ready(Some(Ok(Bytes::from(out))))
+                    }
+                }
+            }
+        }
+    }
+}
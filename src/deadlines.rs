@@ -0,0 +1,145 @@
+use hyper::{Body, Request, Response, header::HeaderName};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use crate::errors::Error;
+
+
+// The point in time by which the response is due, carried in the request extensions
+#[derive(Clone, Copy)]
+pub(crate) struct Deadline(Instant);
+
+impl Deadline {
+    pub fn remaining(&self) -> f64 {
+        self.0.saturating_duration_since(Instant::now()).as_secs_f64()
+    }
+}
+
+pub(crate) fn request_deadline(req: &Request<Body>) -> Option<Deadline> {
+    req.extensions().get::<Deadline>().copied()
+}
+
+pub(crate) struct Network {
+    addr: IpAddr,
+    prefix: u8
+}
+
+impl Network {
+    pub fn parse(value: &str) -> PyResult<Self> {
+        let invalid = || PyValueError::new_err(format!("Invalid trusted address: {}", value));
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None)
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|prefix| *prefix <= max).ok_or_else(invalid)?,
+            None => max
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            },
+            _ => false
+        }
+    }
+}
+
+// Budgets are either in the `grpc-timeout` format (an integer followed by one of
+// the H, M, S, m, u, n units) or a plain amount of seconds.
+fn parse_budget(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let amount = &value[..value.len() - unit.len_utf8()];
+    let amount = if unit.is_ascii_alphabetic() && !amount.is_empty() && amount.len() <= 8 {
+        amount.parse::<u64>().ok()?
+    } else {
+        return value.parse::<f64>().ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    };
+    match unit {
+        'H' => Some(Duration::from_secs(amount * 3600)),
+        'M' => Some(Duration::from_secs(amount * 60)),
+        'S' => Some(Duration::from_secs(amount)),
+        'm' => Some(Duration::from_millis(amount)),
+        'u' => Some(Duration::from_micros(amount)),
+        'n' => Some(Duration::from_nanos(amount)),
+        _ => None
+    }
+}
+
+struct DeadlinesConfig {
+    header: HeaderName,
+    trusted: Vec<Network>
+}
+
+// Time budgets received from trusted callers become the handler timeout: once
+// elapsed, the request gets a 504 response, while the application can read the
+// remaining budget from the scope to propagate it to its own upstream calls.
+#[derive(Clone)]
+pub(crate) struct Deadlines {
+    inner: Option<Arc<DeadlinesConfig>>
+}
+
+impl Deadlines {
+    pub fn new(header: Option<String>, trusted: Vec<String>) -> PyResult<Self> {
+        let header = match header {
+            Some(header) => HeaderName::from_bytes(header.as_bytes()).map_err(
+                |_| PyValueError::new_err(format!("Invalid deadline header name: {}", header))
+            )?,
+            None => return Ok(Self { inner: None })
+        };
+        let trusted = trusted.iter().map(|value| Network::parse(value)).collect::<PyResult<_>>()?;
+        Ok(Self { inner: Some(Arc::new(DeadlinesConfig { header, trusted })) })
+    }
+
+    fn budget_for(&self, req: &Request<Body>, client: SocketAddr) -> Option<Duration> {
+        let inner = self.inner.as_ref()?;
+        let value = req.headers().get(&inner.header)?.to_str().ok()?;
+        if !inner.trusted.iter().any(|network| network.contains(client.ip())) {
+            return None
+        }
+        parse_budget(value)
+    }
+
+    pub async fn handle<F, Fut>(
+        &self,
+        mut req: Request<Body>,
+        client: SocketAddr,
+        handler: F
+    ) -> Response<Body>
+    where
+        F: FnOnce(Request<Body>) -> Fut,
+        Fut: Future<Output=Response<Body>>
+    {
+        // budgets too far in the future to be represented are no deadline at all
+        let (budget, deadline) = match self.budget_for(&req, client)
+            .and_then(|budget| Some((budget, Instant::now().checked_add(budget)?))) {
+            Some(budget) => budget,
+            None => return handler(req).await
+        };
+        req.extensions_mut().insert(Deadline(deadline));
+        match tokio::time::timeout(budget, handler(req)).await {
+            Ok(res) => res,
+            _ => Error::timeout(format!("Request deadline of {:?} exceeded", budget)).response()
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use bytes::BytesMut;
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    body::HttpBody,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderValue, TRANSFER_ENCODING}
+};
+
+use crate::{
+    deflate::{DecodeError, Wrapper, decode, zlib_wrapped},
+    http::{request_content_length, response_error}
+};
+
+
+// Decompression of `gzip` and `deflate` request bodies, before they reach the
+// application. Bodies get decoded as a whole, so the application sees their
+// actual size, and those expanding over the maximum size get rejected, as
+// decompression bombs would otherwise exhaust the worker memory. Other codings
+// are left to the application.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Decompression(Option<usize>);
+
+impl Decompression {
+    pub fn new(enabled: bool, max_size: u64) -> Self {
+        Self(enabled.then_some(max_size.min(usize::MAX as u64) as usize))
+    }
+
+    pub async fn decode(&self, req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+        let max_size = match self.0 {
+            Some(max_size) => max_size,
+            None => return Ok(req)
+        };
+        let coding = req.headers().get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_ascii_lowercase());
+        let wrapper = match coding.as_deref() {
+            Some("gzip" | "x-gzip") => Some(Wrapper::Gzip),
+            Some("deflate") => None,
+            _ => return Ok(req)
+        };
+        // the compressed body is never expected to be larger than its data
+        if request_content_length(&req).is_some_and(|size| size > max_size as u64) {
+            return Err(too_large())
+        }
+        let (mut parts, mut body) = req.into_parts();
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| bad_request(err.to_string()))?;
+            if data.len() + chunk.len() > max_size {
+                return Err(too_large())
+            }
+            data.extend_from_slice(&chunk);
+        }
+        let decoded = match data.is_empty() {
+            true => Vec::new(),
+            false => {
+                let wrapper = wrapper.or_else(|| zlib_wrapped(&data).then_some(Wrapper::Zlib));
+                decode(&data, wrapper, max_size).map_err(|err| match err {
+                    DecodeError::TooLarge => too_large(),
+                    DecodeError::Invalid(detail) => bad_request(detail.to_string())
+                })?
+            }
+        };
+        parts.headers.remove(CONTENT_ENCODING);
+        parts.headers.remove(TRANSFER_ENCODING);
+        parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(decoded.len()));
+        Ok(Request::from_parts(parts, Body::from(decoded)))
+    }
+}
+
+fn bad_request(detail: String) -> Response<Body> {
+    let detail = format!("Invalid request body: {}", detail);
+    response_error(StatusCode::BAD_REQUEST, detail.clone(), Some(detail))
+}
+
+fn too_large() -> Response<Body> {
+    response_error(StatusCode::PAYLOAD_TOO_LARGE, "", None)
+}
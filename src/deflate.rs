@@ -0,0 +1,849 @@
+use once_cell::sync::Lazy;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+
+// DEFLATE (RFC 1951) streams, with the gzip (RFC 1952) and zlib (RFC 1950)
+// wrappers of the `gzip` and `deflate` content codings, in both directions.
+const WINDOW_SIZE: usize = 32 * 1024;
+// The largest stored block, used as the size of every block
+const BLOCK_SIZE: usize = 65535;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// Short matches far away cost more than the literals they replace
+const FAR_MIN_MATCH_DISTANCE: usize = 4096;
+const HASH_BITS: u32 = 15;
+// Bounds the time spent looking for matches, trading some ratio for speed
+const MAX_CHAIN: usize = 64;
+const NICE_MATCH: usize = 128;
+const NONE: u32 = u32::MAX;
+const END_OF_BLOCK: usize = 256;
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff];
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x9c];
+const CRC_TABLE: [u32; 256] = crc_table();
+
+static FIXED_CODES: Lazy<(Huffman, Huffman)> = Lazy::new(|| {
+    let (literal, distance) = fixed_lengths();
+    (Huffman::from_lengths(literal), Huffman::from_lengths(distance))
+});
+static FIXED_LOOKUPS: Lazy<(Lookup, Lookup)> = Lazy::new(|| {
+    let (literal, distance) = fixed_lengths();
+    match (Lookup::from_lengths(literal), Lookup::from_lengths(distance)) {
+        (Ok(literal), Ok(distance)) => (literal, distance),
+        _ => unreachable!()
+    }
+});
+
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let mut lengths = vec![8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (lengths, vec![5; 30])
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => 0xedb88320 ^ (crc >> 1)
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    // the largest run of bytes not overflowing the sums
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|base| *base as usize <= length) - 1
+}
+
+fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|base| *base as usize <= distance) - 1
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Wrapper {
+    Gzip,
+    Zlib
+}
+
+impl Wrapper {
+    fn checksum(&self, checksum: u32, data: &[u8]) -> u32 {
+        match self {
+            Self::Gzip => crc32(checksum, data),
+            Self::Zlib => adler32(checksum, data)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Bits {
+    out: Vec<u8>,
+    acc: u64,
+    count: u32
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, count: u32) {
+        self.acc |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+            self.acc = 0;
+            self.count = 0;
+        }
+    }
+}
+
+// Canonical Huffman codes, bit reversed as DEFLATE packs them from the least significant bit
+struct Huffman {
+    lengths: Vec<u8>,
+    codes: Vec<u16>
+}
+
+impl Huffman {
+    fn from_lengths(lengths: Vec<u8>) -> Self {
+        let mut counts = [0u16; 16];
+        for length in lengths.iter().filter(|length| **length > 0) {
+            counts[*length as usize] += 1;
+        }
+        let mut next = [0u16; 16];
+        let mut code = 0;
+        for bits in 1..16 {
+            code = (code + counts[bits - 1]) << 1;
+            next[bits] = code;
+        }
+        let codes = lengths.iter().map(|length| match *length {
+            0 => 0,
+            length => {
+                let code = next[length as usize];
+                next[length as usize] += 1;
+                code.reverse_bits() >> (16 - length)
+            }
+        }).collect();
+        Self { lengths, codes }
+    }
+
+    fn from_frequencies(frequencies: &[u32], limit: u8) -> Self {
+        Self::from_lengths(code_lengths(frequencies, limit))
+    }
+
+    fn put(&self, bits: &mut Bits, symbol: usize) {
+        bits.put(self.codes[symbol] as u32, self.lengths[symbol] as u32)
+    }
+
+    fn cost(&self, frequencies: &[u32]) -> u64 {
+        frequencies.iter().zip(&self.lengths).map(|(freq, length)| *freq as u64 * *length as u64).sum()
+    }
+}
+
+// Codes longer than the limit get avoided by flattening the frequencies,
+// which converges to a balanced tree.
+fn code_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    // codes need at least two symbols to be complete
+    let mut used = frequencies.iter().filter(|freq| **freq > 0).count();
+    for freq in frequencies.iter_mut() {
+        if used >= 2 {
+            break
+        }
+        if *freq == 0 {
+            *freq = 1;
+            used += 1;
+        }
+    }
+    loop {
+        let lengths = huffman_lengths(&frequencies);
+        if lengths.iter().all(|length| *length <= limit) {
+            return lengths
+        }
+        for freq in frequencies.iter_mut().filter(|freq| **freq > 0) {
+            *freq = freq.div_ceil(2);
+        }
+    }
+}
+
+fn huffman_lengths(frequencies: &[u32]) -> Vec<u8> {
+    let mut heap = BinaryHeap::new();
+    let mut parents = Vec::with_capacity(frequencies.len() * 2);
+    let mut leaves = vec![usize::MAX; frequencies.len()];
+    for (symbol, freq) in frequencies.iter().enumerate().filter(|(_, freq)| **freq > 0) {
+        leaves[symbol] = parents.len();
+        heap.push(Reverse((*freq as u64, parents.len())));
+        parents.push(usize::MAX);
+    }
+    while let (Some(Reverse((freq_a, a))), Some(Reverse((freq_b, b)))) = (heap.pop(), heap.pop()) {
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[a] = node;
+        parents[b] = node;
+        heap.push(Reverse((freq_a + freq_b, node)));
+    }
+    leaves.iter().map(|leaf| {
+        if *leaf == usize::MAX {
+            return 0
+        }
+        let (mut node, mut depth) = (*leaf, 0usize);
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            depth += 1;
+        }
+        depth.min(u8::MAX as usize) as u8
+    }).collect()
+}
+
+// Code lengths get sent run length encoded, with the repeat symbols 16 to 18
+fn run_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut ret = Vec::new();
+    let mut idx = 0;
+    while idx < lengths.len() {
+        let length = lengths[idx];
+        let mut run = lengths[idx..].iter().take_while(|value| **value == length).count();
+        idx += run;
+        if length == 0 {
+            while run >= 11 {
+                let count = run.min(138);
+                ret.push((18, (count - 11) as u8));
+                run -= count;
+            }
+            if run >= 3 {
+                ret.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            ret.push((length, 0));
+            run -= 1;
+            while run >= 3 {
+                let count = run.min(6);
+                ret.push((16, (count - 3) as u8));
+                run -= count;
+            }
+        }
+        ret.extend(std::iter::repeat_n((length, 0), run));
+    }
+    ret
+}
+
+fn repeat_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0
+    }
+}
+
+struct DynamicHeader {
+    literals: usize,
+    distances: usize,
+    code_lengths: usize,
+    code: Huffman,
+    symbols: Vec<(u8, u8)>
+}
+
+impl DynamicHeader {
+    fn new(literal: &Huffman, distance: &Huffman) -> Self {
+        let used = |lengths: &[u8]| lengths.iter().rposition(|length| *length > 0).map_or(0, |idx| idx + 1);
+        let literals = used(&literal.lengths).max(257);
+        let distances = used(&distance.lengths).max(1);
+        let mut lengths = literal.lengths[..literals].to_vec();
+        lengths.extend_from_slice(&distance.lengths[..distances]);
+        let symbols = run_lengths(&lengths);
+        let mut frequencies = [0u32; 19];
+        for (symbol, _) in &symbols {
+            frequencies[*symbol as usize] += 1;
+        }
+        let code = Huffman::from_frequencies(&frequencies, 7);
+        let code_lengths = CODE_LENGTH_ORDER.iter()
+            .rposition(|symbol| code.lengths[*symbol] > 0)
+            .map_or(0, |idx| idx + 1)
+            .max(4);
+        Self { literals, distances, code_lengths, code, symbols }
+    }
+
+    fn cost(&self) -> u64 {
+        14 + 3 * self.code_lengths as u64 + self.symbols.iter()
+            .map(|(symbol, _)| self.code.lengths[*symbol as usize] as u64 + repeat_bits(*symbol) as u64)
+            .sum::<u64>()
+    }
+
+    fn write(&self, bits: &mut Bits) {
+        bits.put((self.literals - 257) as u32, 5);
+        bits.put((self.distances - 1) as u32, 5);
+        bits.put((self.code_lengths - 4) as u32, 4);
+        for symbol in &CODE_LENGTH_ORDER[..self.code_lengths] {
+            bits.put(self.code.lengths[*symbol] as u32, 3);
+        }
+        for (symbol, extra) in &self.symbols {
+            self.code.put(bits, *symbol as usize);
+            bits.put(*extra as u32, repeat_bits(*symbol));
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match(usize, usize)
+}
+
+// Streaming compressor: input gets buffered into blocks, matched against the
+// previous 32KiB of data, and each block gets the cheapest of the dynamic,
+// fixed and stored encodings.
+pub(crate) struct Encoder {
+    wrapper: Wrapper,
+    bits: Bits,
+    // the history for matches, followed by the input not encoded yet
+    buf: Vec<u8>,
+    history: usize,
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    checksum: u32,
+    size: u32,
+    dirty: bool
+}
+
+impl Encoder {
+    pub fn new(wrapper: Wrapper) -> Self {
+        let mut bits = Bits::default();
+        match wrapper {
+            Wrapper::Gzip => bits.out.extend_from_slice(&GZIP_HEADER),
+            Wrapper::Zlib => bits.out.extend_from_slice(&ZLIB_HEADER)
+        }
+        Self {
+            wrapper,
+            bits,
+            buf: Vec::new(),
+            history: 0,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: Vec::new(),
+            checksum: match wrapper {
+                Wrapper::Gzip => 0,
+                Wrapper::Zlib => 1
+            },
+            size: 0,
+            dirty: false
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) {
+        self.checksum = self.wrapper.checksum(self.checksum, data);
+        self.size = self.size.wrapping_add(data.len() as u32);
+        self.dirty |= !data.is_empty();
+        self.buf.extend_from_slice(data);
+        while self.buf.len() - self.history >= BLOCK_SIZE {
+            self.encode(self.history + BLOCK_SIZE, false);
+        }
+    }
+
+    // Makes all the input written so far decodable, with an empty stored block
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return
+        }
+        if self.buf.len() > self.history {
+            self.encode(self.buf.len(), false);
+        }
+        self.bits.put(0, 3);
+        self.bits.align();
+        self.bits.out.extend_from_slice(&[0, 0, 0xff, 0xff]);
+        self.dirty = false;
+    }
+
+    pub fn finish(&mut self) {
+        self.encode(self.buf.len(), true);
+        self.bits.align();
+        match self.wrapper {
+            Wrapper::Gzip => {
+                self.bits.out.extend_from_slice(&self.checksum.to_le_bytes());
+                self.bits.out.extend_from_slice(&self.size.to_le_bytes());
+            },
+            Wrapper::Zlib => self.bits.out.extend_from_slice(&self.checksum.to_be_bytes())
+        }
+        self.dirty = false;
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.bits.out.len()
+    }
+
+    // The output produced so far, in whole bytes
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bits.out)
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let value = (self.buf[pos] as u32) << 16 | (self.buf[pos + 1] as u32) << 8 | self.buf[pos + 2] as u32;
+        (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.buf.len() {
+            let hash = self.hash(pos);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos as u32;
+        }
+    }
+
+    fn longest_match(&self, pos: usize, end: usize) -> (usize, usize) {
+        let max = (end - pos).min(MAX_MATCH);
+        if max < MIN_MATCH {
+            return (0, 0)
+        }
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+        let mut chain = MAX_CHAIN;
+        while candidate != NONE && chain > 0 {
+            let start = candidate as usize;
+            let distance = pos - start;
+            if distance > WINDOW_SIZE {
+                break
+            }
+            if self.buf[start + best.0] == self.buf[pos + best.0] {
+                let length = self.buf[start..].iter().zip(&self.buf[pos..pos + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, distance);
+                    if length >= NICE_MATCH.min(max) {
+                        break
+                    }
+                }
+            }
+            candidate = self.prev[start];
+            chain -= 1;
+        }
+        best
+    }
+
+    fn tokens(&mut self, end: usize) -> Vec<Token> {
+        self.head.fill(NONE);
+        self.prev.clear();
+        self.prev.resize(self.buf.len(), NONE);
+        for pos in 0..self.history {
+            self.insert(pos);
+        }
+        let mut tokens = Vec::with_capacity(end - self.history);
+        let mut pos = self.history;
+        while pos < end {
+            match self.longest_match(pos, end) {
+                (length, distance) if length > MIN_MATCH || (length == MIN_MATCH && distance <= FAR_MIN_MATCH_DISTANCE) => {
+                    tokens.push(Token::Match(length, distance));
+                    for idx in pos..pos + length {
+                        self.insert(idx);
+                    }
+                    pos += length;
+                },
+                _ => {
+                    tokens.push(Token::Literal(self.buf[pos]));
+                    self.insert(pos);
+                    pos += 1;
+                }
+            }
+        }
+        tokens
+    }
+
+    fn encode(&mut self, end: usize, last: bool) {
+        let tokens = self.tokens(end);
+        let mut literal_freqs = [0u32; 286];
+        let mut distance_freqs = [0u32; 30];
+        let mut extra_bits = 0u64;
+        for token in &tokens {
+            match *token {
+                Token::Literal(byte) => literal_freqs[byte as usize] += 1,
+                Token::Match(length, distance) => {
+                    let (length_code, distance_code) = (length_code(length), distance_code(distance));
+                    literal_freqs[257 + length_code] += 1;
+                    distance_freqs[distance_code] += 1;
+                    extra_bits += (LENGTH_EXTRA[length_code] + DISTANCE_EXTRA[distance_code]) as u64;
+                }
+            }
+        }
+        literal_freqs[END_OF_BLOCK] += 1;
+        let literal = Huffman::from_frequencies(&literal_freqs, 15);
+        let distance = Huffman::from_frequencies(&distance_freqs, 15);
+        let header = DynamicHeader::new(&literal, &distance);
+        let (fixed_literal, fixed_distance) = &*FIXED_CODES;
+        let dynamic_cost = header.cost() + literal.cost(&literal_freqs) + distance.cost(&distance_freqs);
+        let fixed_cost = fixed_literal.cost(&literal_freqs) + fixed_distance.cost(&distance_freqs);
+        let stored_cost = 7 + 32 + 8 * (end - self.history) as u64;
+
+        self.bits.put(last as u32, 1);
+        if stored_cost < dynamic_cost.min(fixed_cost) + extra_bits {
+            let size = (end - self.history) as u16;
+            self.bits.put(0, 2);
+            self.bits.align();
+            self.bits.out.extend_from_slice(&size.to_le_bytes());
+            self.bits.out.extend_from_slice(&(!size).to_le_bytes());
+            self.bits.out.extend_from_slice(&self.buf[self.history..end]);
+        } else if fixed_cost <= dynamic_cost {
+            self.bits.put(1, 2);
+            self.write_tokens(&tokens, fixed_literal, fixed_distance);
+        } else {
+            self.bits.put(2, 2);
+            header.write(&mut self.bits);
+            self.write_tokens(&tokens, &literal, &distance);
+        }
+
+        self.history = end;
+        if self.history > WINDOW_SIZE {
+            self.buf.drain(..self.history - WINDOW_SIZE);
+            self.history = WINDOW_SIZE;
+        }
+    }
+
+    fn write_tokens(&mut self, tokens: &[Token], literal: &Huffman, distance: &Huffman) {
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => literal.put(&mut self.bits, byte as usize),
+                Token::Match(length, dist) => {
+                    let code = length_code(length);
+                    literal.put(&mut self.bits, 257 + code);
+                    self.bits.put((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+                    let code = distance_code(dist);
+                    distance.put(&mut self.bits, code);
+                    self.bits.put((dist - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+                }
+            }
+        }
+        literal.put(&mut self.bits, END_OF_BLOCK);
+    }
+}
+
+pub(crate) enum DecodeError {
+    TooLarge,
+    Invalid(&'static str)
+}
+
+const TRUNCATED: DecodeError = DecodeError::Invalid("truncated data");
+
+// Decodes a whole stream, with no wrapper standing for raw DEFLATE data,
+// failing as soon as the output would exceed `limit` bytes.
+pub(crate) fn decode(data: &[u8], wrapper: Option<Wrapper>, limit: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut inflater = Inflater { data, pos: 0, acc: 0, count: 0, out: Vec::new(), limit };
+    match wrapper {
+        None => inflater.inflate(0)?,
+        Some(Wrapper::Zlib) => {
+            if !zlib_wrapped(data) {
+                return Err(DecodeError::Invalid("invalid zlib header"))
+            }
+            inflater.take(2)?;
+            inflater.inflate(0)?;
+            inflater.align();
+            let checksum = inflater.take(4)?;
+            if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(1, &inflater.out) {
+                return Err(DecodeError::Invalid("checksum mismatch"))
+            }
+            if inflater.pos < data.len() {
+                return Err(DecodeError::Invalid("trailing data"))
+            }
+        },
+        // concatenated members decode to the concatenation of their data
+        Some(Wrapper::Gzip) => loop {
+            let start = inflater.out.len();
+            inflater.gzip_header()?;
+            inflater.inflate(start)?;
+            inflater.align();
+            let trailer = inflater.take(8)?;
+            let checksum = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+            let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+            if checksum != crc32(0, &inflater.out[start..]) || size != (inflater.out.len() - start) as u32 {
+                return Err(DecodeError::Invalid("checksum mismatch"))
+            }
+            if inflater.pos >= data.len() {
+                break
+            }
+        }
+    }
+    Ok(inflater.out)
+}
+
+// Clients sending `deflate` bodies might omit the zlib wrapper, which is told
+// apart by its header check bits.
+pub(crate) fn zlib_wrapped(data: &[u8]) -> bool {
+    match data {
+        [method, flags, ..] => {
+            method & 0x0f == 8 && method >> 4 <= 7 && flags & 0x20 == 0
+                && ((*method as u16) << 8 | *flags as u16).is_multiple_of(31)
+        },
+        _ => false
+    }
+}
+
+// Symbols by the next input bits, with entries packing the symbol and its code
+// length, where zero length entries stand for bits matching no code.
+struct Lookup {
+    table: Vec<u16>,
+    bits: u32
+}
+
+impl Lookup {
+    fn from_lengths(lengths: Vec<u8>) -> Result<Self, DecodeError> {
+        let mut counts = [0i32; 16];
+        for length in lengths.iter().filter(|length| **length > 0) {
+            counts[*length as usize] += 1;
+        }
+        let mut left = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - count;
+            if left < 0 {
+                return Err(DecodeError::Invalid("over-subscribed code"))
+            }
+        }
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let codes = Huffman::from_lengths(lengths);
+        let mut table = vec![0u16; 1 << bits];
+        for (symbol, (length, code)) in codes.lengths.iter().zip(&codes.codes).enumerate() {
+            if *length == 0 {
+                continue
+            }
+            let mut idx = *code as usize;
+            while idx < table.len() {
+                table[idx] = (symbol as u16) << 4 | *length as u16;
+                idx += 1 << length;
+            }
+        }
+        Ok(Self { table, bits })
+    }
+}
+
+struct Inflater<'d> {
+    data: &'d [u8],
+    pos: usize,
+    acc: u64,
+    count: u32,
+    out: Vec<u8>,
+    limit: usize
+}
+
+impl<'d> Inflater<'d> {
+    fn load(&mut self) -> bool {
+        match self.data.get(self.pos) {
+            Some(byte) => {
+                self.acc |= (*byte as u64) << self.count;
+                self.pos += 1;
+                self.count += 8;
+                true
+            },
+            None => false
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<usize, DecodeError> {
+        while self.count < count {
+            if !self.load() {
+                return Err(TRUNCATED)
+            }
+        }
+        let value = self.acc & ((1 << count) - 1);
+        self.acc >>= count;
+        self.count -= count;
+        Ok(value as usize)
+    }
+
+    fn symbol(&mut self, lookup: &Lookup) -> Result<usize, DecodeError> {
+        while self.count < lookup.bits && self.load() {}
+        let entry = lookup.table[(self.acc & ((1 << lookup.bits) - 1)) as usize];
+        let length = (entry & 0x0f) as u32;
+        if length > self.count {
+            return Err(TRUNCATED)
+        }
+        if length == 0 {
+            return Err(DecodeError::Invalid("invalid code"))
+        }
+        self.acc >>= length;
+        self.count -= length;
+        Ok((entry >> 4) as usize)
+    }
+
+    // Skips to the next byte boundary, giving back the whole bytes read ahead
+    fn align(&mut self) {
+        self.pos -= (self.count / 8) as usize;
+        self.acc = 0;
+        self.count = 0;
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'d [u8], DecodeError> {
+        let data = self.data.get(self.pos..self.pos + size).ok_or(TRUNCATED)?;
+        self.pos += size;
+        Ok(data)
+    }
+
+    fn reserve(&self, size: usize) -> Result<(), DecodeError> {
+        match self.out.len() + size > self.limit {
+            true => Err(DecodeError::TooLarge),
+            false => Ok(())
+        }
+    }
+
+    fn gzip_header(&mut self) -> Result<(), DecodeError> {
+        let header = self.take(10)?;
+        if header[..3] != GZIP_HEADER[..3] {
+            return Err(DecodeError::Invalid("invalid gzip header"))
+        }
+        let flags = header[3];
+        if flags & 0xe0 != 0 {
+            return Err(DecodeError::Invalid("invalid gzip header"))
+        }
+        if flags & 0x04 != 0 {
+            let size = self.take(2)?;
+            self.take(u16::from_le_bytes([size[0], size[1]]) as usize)?;
+        }
+        // file name and comment, zero terminated
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                let size = self.data[self.pos..].iter().position(|byte| *byte == 0).ok_or(TRUNCATED)?;
+                self.pos += size + 1;
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.take(2)?;
+        }
+        Ok(())
+    }
+
+    // Matches can't reach back before `start`, where the current stream begins
+    fn inflate(&mut self, start: usize) -> Result<(), DecodeError> {
+        loop {
+            let last = self.bits(1)? == 1;
+            match self.bits(2)? {
+                0 => self.stored()?,
+                1 => {
+                    let (literal, distance) = &*FIXED_LOOKUPS;
+                    self.codes(literal, distance, start)?
+                },
+                2 => {
+                    let (literal, distance) = self.dynamic()?;
+                    self.codes(&literal, &distance, start)?
+                },
+                _ => return Err(DecodeError::Invalid("invalid block type"))
+            }
+            if last {
+                return Ok(())
+            }
+        }
+    }
+
+    fn stored(&mut self) -> Result<(), DecodeError> {
+        self.align();
+        let header = self.take(4)?;
+        let size = u16::from_le_bytes([header[0], header[1]]);
+        if size != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err(DecodeError::Invalid("invalid stored block length"))
+        }
+        self.reserve(size as usize)?;
+        let data = self.take(size as usize)?;
+        self.out.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn dynamic(&mut self) -> Result<(Lookup, Lookup), DecodeError> {
+        let literals = self.bits(5)? + 257;
+        let distances = self.bits(5)? + 1;
+        let code_lengths = self.bits(4)? + 4;
+        if literals > 286 || distances > 30 {
+            return Err(DecodeError::Invalid("invalid code lengths"))
+        }
+        let mut lengths = vec![0u8; 19];
+        for idx in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[*idx] = self.bits(3)? as u8;
+        }
+        let lookup = Lookup::from_lengths(lengths)?;
+        let mut lengths = Vec::with_capacity(literals + distances);
+        while lengths.len() < literals + distances {
+            let (length, repeat) = match self.symbol(&lookup)? {
+                16 => match lengths.last() {
+                    Some(length) => (*length, 3 + self.bits(2)?),
+                    None => return Err(DecodeError::Invalid("invalid code lengths"))
+                },
+                17 => (0, 3 + self.bits(3)?),
+                18 => (0, 11 + self.bits(7)?),
+                length => (length as u8, 1)
+            };
+            if lengths.len() + repeat > literals + distances {
+                return Err(DecodeError::Invalid("invalid code lengths"))
+            }
+            lengths.resize(lengths.len() + repeat, length);
+        }
+        if lengths[END_OF_BLOCK] == 0 {
+            return Err(DecodeError::Invalid("missing end of block code"))
+        }
+        let distance = lengths.split_off(literals);
+        Ok((Lookup::from_lengths(lengths)?, Lookup::from_lengths(distance)?))
+    }
+
+    fn codes(&mut self, literal: &Lookup, distance: &Lookup, start: usize) -> Result<(), DecodeError> {
+        loop {
+            let code = match self.symbol(literal)? {
+                byte @ 0..=255 => {
+                    self.reserve(1)?;
+                    self.out.push(byte as u8);
+                    continue
+                },
+                END_OF_BLOCK => return Ok(()),
+                symbol if symbol - 257 < LENGTH_BASE.len() => symbol - 257,
+                _ => return Err(DecodeError::Invalid("invalid length code"))
+            };
+            let length = LENGTH_BASE[code] as usize + self.bits(LENGTH_EXTRA[code] as u32)?;
+            let code = self.symbol(distance)?;
+            if code >= DISTANCE_BASE.len() {
+                return Err(DecodeError::Invalid("invalid distance code"))
+            }
+            let dist = DISTANCE_BASE[code] as usize + self.bits(DISTANCE_EXTRA[code] as u32)?;
+            if dist > self.out.len() - start {
+                return Err(DecodeError::Invalid("invalid distance"))
+            }
+            self.reserve(length)?;
+            let from = self.out.len() - dist;
+            for idx in from..from + length {
+                let byte = self.out[idx];
+                self.out.push(byte);
+            }
+        }
+    }
+}
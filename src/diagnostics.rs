@@ -0,0 +1,273 @@
+use hyper::{Body, Request, StatusCode};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering}},
+    time::{Duration, Instant}
+};
+
+use crate::{tls::TlsHandshake, workers::identity};
+
+
+const UNSET: u64 = u64::MAX;
+// Bounds the memory held by long lived keep-alive and HTTP/2 connections
+const CONN_TRACE_EVENTS_MAX: usize = 512;
+
+struct ConnTraceState {
+    id: u64,
+    remote_addr: SocketAddr,
+    tls: bool,
+    alpn: Option<&'static str>,
+    started: Instant,
+    requests: AtomicU32,
+    events: Mutex<Vec<(&'static str, u32, u64)>>,
+    dropped: AtomicU32
+}
+
+impl ConnTraceState {
+    fn record_at(&self, event: &'static str, request: u32, at: Instant) {
+        let at_us = at.saturating_duration_since(self.started).as_micros() as u64;
+        let mut events = self.events.lock().unwrap();
+        if events.len() < CONN_TRACE_EVENTS_MAX {
+            events.push((event, request, at_us));
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let events: Vec<String> = self.events.lock().unwrap().iter()
+            .map(|(event, request, at_us)| match request {
+                0 => format!("{{\"event\":\"{}\",\"at_us\":{}}}", event, at_us),
+                request => format!(
+                    "{{\"event\":\"{}\",\"request\":{},\"at_us\":{}}}", event, request, at_us
+                )
+            })
+            .collect();
+        format!(
+            "{{\"connection\":{},\"worker\":{},\"remote\":\"{}\",\"tls\":{},\"alpn\":{},\"requests\":{},\"dropped_events\":{},\"events\":[{}]}}",
+            self.id,
+            identity().id,
+            self.remote_addr,
+            self.tls,
+            self.alpn.map(|alpn| format!("\"{}\"", alpn)).unwrap_or_else(|| "null".into()),
+            self.requests.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+            events.join(",")
+        )
+    }
+}
+
+// The connection is gone once the service and the last of its requests get dropped
+impl Drop for ConnTraceState {
+    fn drop(&mut self) {
+        self.record_at("close", 0, Instant::now());
+        log::info!("Connection trace {}", self.to_json());
+    }
+}
+
+// Timeline of a sampled connection, shared by the requests it carries.
+// Connections not sampled get an empty trace, which records nothing.
+#[derive(Clone, Default)]
+pub(crate) struct ConnTrace {
+    state: Option<Arc<ConnTraceState>>
+}
+
+impl ConnTrace {
+    fn record(&self, event: &'static str, request: u32) {
+        if let Some(state) = &self.state {
+            state.record_at(event, request, Instant::now());
+        }
+    }
+
+    // Marks the headers of a new request as parsed, returning its sequence number
+    fn request(&self) -> u32 {
+        match &self.state {
+            Some(state) => {
+                let request = state.requests.fetch_add(1, Ordering::Relaxed) + 1;
+                state.record_at("headers", request, Instant::now());
+                request
+            }
+            None => 0
+        }
+    }
+}
+
+// Per-connection tracing for 1 in N connections, logged as JSON on close, to
+// tell where the time of slow requests goes (handshakes, the application or
+// the network) without a tracing infrastructure.
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionTraces {
+    sample: u64,
+    counter: Arc<AtomicU64>
+}
+
+impl ConnectionTraces {
+    pub fn new(sample: u64) -> Self {
+        Self { sample, counter: Arc::new(AtomicU64::new(0)) }
+    }
+
+    // TLS connections provide their handshake, starting the timeline from the TCP accept
+    pub fn sample(&self, remote_addr: SocketAddr, handshake: Option<TlsHandshake>) -> ConnTrace {
+        if self.sample == 0 {
+            return ConnTrace::default()
+        }
+        let seq = self.counter.fetch_add(1, Ordering::Relaxed);
+        if !seq.is_multiple_of(self.sample) {
+            return ConnTrace::default()
+        }
+        let (started, established, alpn) = match handshake {
+            Some(handshake) => (handshake.accepted, Some(handshake.established), handshake.alpn),
+            None => (Instant::now(), None, None)
+        };
+        let state = ConnTraceState {
+            id: seq + 1,
+            remote_addr,
+            tls: established.is_some(),
+            alpn,
+            started,
+            requests: AtomicU32::new(0),
+            events: Mutex::new(Vec::with_capacity(16)),
+            dropped: AtomicU32::new(0)
+        };
+        state.record_at("accept", 0, started);
+        if let Some(established) = established {
+            state.record_at("tls", 0, established);
+        }
+        ConnTrace { state: Some(Arc::new(state)) }
+    }
+}
+
+struct TraceState {
+    started: Instant,
+    callback_started: AtomicU64,
+    callback_ended: AtomicU64,
+    body_queued: AtomicI64,
+    client_disconnected: AtomicBool
+}
+
+// Diagnostic context of a request, collected along its path through the server
+// and logged when it ends in a failure, so protocol-level issues can be debugged
+// from the logs alone. Values are plain atomics, cheap enough to track always.
+#[derive(Clone)]
+pub(crate) struct RequestTrace {
+    state: Arc<TraceState>,
+    connection: ConnTrace,
+    request: u32
+}
+
+impl RequestTrace {
+    fn new(connection: &ConnTrace) -> Self {
+        Self {
+            state: Arc::new(TraceState {
+                started: Instant::now(),
+                callback_started: AtomicU64::new(UNSET),
+                callback_ended: AtomicU64::new(UNSET),
+                body_queued: AtomicI64::new(0),
+                client_disconnected: AtomicBool::new(false)
+            }),
+            connection: connection.clone(),
+            request: connection.request()
+        }
+    }
+
+    pub fn attach(req: &mut Request<Body>, connection: &ConnTrace) -> Self {
+        let trace = Self::new(connection);
+        req.extensions_mut().insert(trace.clone());
+        trace
+    }
+
+    // Requests not going through the worker services get a detached trace
+    pub fn of(req: &Request<Body>) -> Self {
+        req.extensions().get::<Self>().cloned().unwrap_or_else(|| Self::new(&ConnTrace::default()))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.state.started.elapsed()
+    }
+
+    fn elapsed_us(&self) -> u64 {
+        self.elapsed().as_micros() as u64
+    }
+
+    pub fn callback_started(&self) {
+        self.state.callback_started.store(self.elapsed_us(), Ordering::Relaxed);
+        self.connection.record("callback_start", self.request);
+    }
+
+    pub fn callback_ended(&self) {
+        self.state.callback_ended.store(self.elapsed_us(), Ordering::Relaxed);
+        self.connection.record("callback_end", self.request);
+    }
+
+    // The response head is handed to the connection, which writes it out right away
+    pub fn response_started(&self) {
+        self.connection.record("first_byte", self.request);
+    }
+
+    pub fn body_queued(&self) {
+        self.state.body_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn body_sent(&self) {
+        self.state.body_queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn client_disconnected(&self) {
+        self.state.client_disconnected.store(true, Ordering::Relaxed)
+    }
+
+    // Time spent waiting for the application, up to now if it didn't answer yet
+    fn callback_wait(&self) -> Option<(f64, bool)> {
+        let started = match self.state.callback_started.load(Ordering::Relaxed) {
+            UNSET => return None,
+            started => started
+        };
+        let (ended, completed) = match self.state.callback_ended.load(Ordering::Relaxed) {
+            UNSET => (self.elapsed_us(), false),
+            ended => (ended, true)
+        };
+        Some((ended.saturating_sub(started) as f64 / 1000.0, completed))
+    }
+
+    fn details(&self) -> String {
+        let callback = match self.callback_wait() {
+            Some((wait, true)) => format!("{:.3}ms", wait),
+            Some((wait, false)) => format!("{:.3}ms (still pending)", wait),
+            None => "not invoked".to_string()
+        };
+        format!(
+            "callback wait {}, body queue depth {}, client disconnected {}",
+            callback,
+            self.state.body_queued.load(Ordering::Relaxed).max(0),
+            self.state.client_disconnected.load(Ordering::Relaxed)
+        )
+    }
+
+    pub fn log_failure(&self, reason: &str) {
+        log::warn!(
+            "Request failed ({}) after {:.3}ms: {}",
+            reason,
+            self.elapsed_us() as f64 / 1000.0,
+            self.details()
+        );
+    }
+
+    // Responses failing once their head is out can't turn into an error response
+    // anymore: they get cut short, so clients don't mistake them for complete ones.
+    pub fn log_abort(&self, reason: &str) {
+        log::warn!(
+            "Response aborted ({}) after {:.3}ms, with the head already sent: {}",
+            reason,
+            self.elapsed_us() as f64 / 1000.0,
+            self.details()
+        );
+    }
+
+    pub fn check_response(&self, status: StatusCode) {
+        match status {
+            StatusCode::INTERNAL_SERVER_ERROR => self.log_failure("500"),
+            StatusCode::GATEWAY_TIMEOUT => self.log_failure("timeout"),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicUsize, Ordering}},
+    task::{Context, Poll, ready},
+    time::Duration
+};
+use tokio::{sync::Notify, time::Instant};
+
+
+// On shutdown workers stop accepting connections, and wait for the requests in
+// flight and the websocket sessions to end, for up to `timeout` since the signal.
+// Responses sent meanwhile on keep-alive connections tell the client to close
+// them. Sessions run detached from their connection, as hyper lets go of it on
+// upgrade, so they get counted here.
+#[derive(Clone, Default)]
+pub(crate) struct Drain {
+    timeout: Option<Duration>,
+    state: Arc<DrainState>
+}
+
+#[derive(Default)]
+struct DrainState {
+    started: OnceCell<Instant>,
+    sessions: AtomicUsize,
+    changed: Notify
+}
+
+impl Drain {
+    pub fn new(timeout: f64) -> PyResult<Self> {
+        if timeout.is_nan() || timeout < 0.0 {
+            return Err(PyValueError::new_err("Drain timeout should not be negative"))
+        }
+        Ok(Self {
+            timeout: (timeout > 0.0).then(|| Duration::from_secs_f64(timeout)),
+            state: Arc::default()
+        })
+    }
+
+    pub fn draining(&self) -> bool {
+        self.state.started.get().is_some()
+    }
+
+    // Wraps the shutdown signal of a server, starting the drain once it fires
+    pub fn signal<F>(&self, signal: F) -> impl Future<Output=()>
+    where F: Future<Output=()>
+    {
+        let drain = self.clone();
+        async move {
+            signal.await;
+            drain.start();
+        }
+    }
+
+    fn start(&self) {
+        if self.state.started.set(Instant::now()).is_ok() {
+            log::info!(
+                "Draining connections, {} websocket sessions open",
+                self.state.sessions.load(Ordering::Acquire)
+            );
+            self.state.changed.notify_waiters();
+        }
+    }
+
+    pub fn session(&self) -> DrainSession {
+        self.state.sessions.fetch_add(1, Ordering::AcqRel);
+        DrainSession(self.state.clone())
+    }
+
+    // Runs a server with graceful shutdown, then waits for the sessions to end:
+    // `None` means the timeout expired first, and whatever was left got dropped.
+    pub fn run<F, T>(&self, server: F) -> Draining<F, T>
+    where F: Future<Output=T>
+    {
+        Draining {
+            server,
+            ret: None,
+            state: self.state.clone(),
+            sessions: sessions_closed(self.state.clone()),
+            expired: expired(self.state.clone(), self.timeout)
+        }
+    }
+}
+
+// Not an async block, nor bound on the server future type: either way the server
+// future gets checked for `Send` across every lifetime, failing on its borrows.
+#[pin_project]
+pub(crate) struct Draining<F, T> {
+    #[pin]
+    server: F,
+    ret: Option<T>,
+    state: Arc<DrainState>,
+    sessions: BoxFuture<'static, ()>,
+    expired: BoxFuture<'static, ()>
+}
+
+impl<F: Future<Output=T>, T> Future for Draining<F, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.expired.as_mut().poll(cx).is_ready() {
+            log::warn!(
+                "Drain timeout expired, closing {} websocket sessions and the connections still open",
+                this.state.sessions.load(Ordering::Acquire)
+            );
+            return Poll::Ready(None)
+        }
+        if this.ret.is_none() {
+            *this.ret = Some(ready!(this.server.poll(cx)));
+        }
+        ready!(this.sessions.as_mut().poll(cx));
+        Poll::Ready(this.ret.take())
+    }
+}
+
+fn sessions_closed(state: Arc<DrainState>) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        loop {
+            let changed = state.changed.notified();
+            if state.sessions.load(Ordering::Acquire) == 0 {
+                return
+            }
+            changed.await;
+        }
+    })
+}
+
+fn expired(state: Arc<DrainState>, timeout: Option<Duration>) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return std::future::pending().await
+        };
+        let started = loop {
+            let changed = state.changed.notified();
+            if let Some(started) = state.started.get() {
+                break *started
+            }
+            changed.await;
+        };
+        tokio::time::sleep_until(started + timeout).await;
+    })
+}
+
+pub(crate) struct DrainSession(Arc<DrainState>);
+
+impl Drop for DrainSession {
+    fn drop(&mut self) {
+        if self.0.sessions.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.changed.notify_waiters();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use hyper::{Body, Response};
+use pyo3::{create_exception, exceptions::PyRuntimeError, prelude::*};
+use std::{error, fmt};
+
+use crate::{http::{response_500, response_504}, metrics::METRICS};
+
+
+create_exception!(_granian, GranianError, PyRuntimeError, "GranianError");
+create_exception!(_granian, BindError, GranianError, "BindError");
+create_exception!(_granian, TlsError, GranianError, "TlsError");
+create_exception!(_granian, ProtocolError, GranianError, "ProtocolError");
+create_exception!(_granian, AppError, GranianError, "AppError");
+create_exception!(_granian, TimeoutError, GranianError, "TimeoutError");
+create_exception!(_granian, ResponseStateError, ProtocolError, "ResponseStateError");
+create_exception!(_granian, ProtocolViolationError, ProtocolError, "ProtocolViolationError");
+
+// The server error taxonomy: every kind has a stable code, used as the label in
+// logs and metrics, and an exit status for the failures stopping a process,
+// following the BSD `sysexits.h` conventions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Bind,
+    Tls,
+    Protocol,
+    App,
+    Timeout
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 5] = [Self::Bind, Self::Tls, Self::Protocol, Self::App, Self::Timeout];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Bind => "bind",
+            Self::Tls => "tls",
+            Self::Protocol => "protocol",
+            Self::App => "app",
+            Self::Timeout => "timeout"
+        }
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            Self::Bind => 71,
+            Self::Tls => 78,
+            Self::Protocol => 76,
+            Self::App => 70,
+            Self::Timeout => 75
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String
+}
+
+impl Error {
+    fn new(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Self { kind, message: message.to_string() }
+    }
+
+    pub fn bind(message: impl fmt::Display) -> Self {
+        Self::new(ErrorKind::Bind, message)
+    }
+
+    pub fn tls(message: impl fmt::Display) -> Self {
+        Self::new(ErrorKind::Tls, message)
+    }
+
+    pub fn protocol(message: impl fmt::Display) -> Self {
+        Self::new(ErrorKind::Protocol, message)
+    }
+
+    pub fn app(message: impl fmt::Display) -> Self {
+        Self::new(ErrorKind::App, message)
+    }
+
+    pub fn timeout(message: impl fmt::Display) -> Self {
+        Self::new(ErrorKind::Timeout, message)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn log(&self) {
+        match self.kind {
+            ErrorKind::App | ErrorKind::Timeout => log::warn!("{}", self),
+            _ => log::error!("{}", self)
+        }
+    }
+
+    // The response sent to the client when the error ends a request
+    pub fn response(self) -> Response<Body> {
+        self.log();
+        METRICS.record_error(self.kind);
+        match self.kind {
+            ErrorKind::Timeout => response_504(),
+            _ => response_500()
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        self.log();
+        std::process::exit(self.kind.exit_status())
+    }
+}
+
+impl error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.kind.code())
+    }
+}
+
+impl From<PyErr> for Error {
+    fn from(err: PyErr) -> Self {
+        Self::app(err)
+    }
+}
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        let message = err.to_string();
+        match err.kind {
+            ErrorKind::Bind => BindError::new_err(message),
+            ErrorKind::Tls => TlsError::new_err(message),
+            ErrorKind::Protocol => ProtocolError::new_err(message),
+            ErrorKind::App => AppError::new_err(message),
+            ErrorKind::Timeout => TimeoutError::new_err(message)
+        }
+    }
+}
+
+pub(crate) fn init_pymodule(py: Python, module: &PyModule) -> PyResult<()> {
+    let base = py.get_type::<GranianError>();
+    base.setattr("code", "error")?;
+    base.setattr("exit_status", 1)?;
+    module.add("GranianError", base)?;
+    for (kind, error) in ErrorKind::ALL.iter().zip([
+        py.get_type::<BindError>(),
+        py.get_type::<TlsError>(),
+        py.get_type::<ProtocolError>(),
+        py.get_type::<AppError>(),
+        py.get_type::<TimeoutError>()
+    ]) {
+        error.setattr("code", kind.code())?;
+        error.setattr("exit_status", kind.exit_status())?;
+        module.add(error.name()?, error)?;
+    }
+    module.add("ResponseStateError", py.get_type::<ResponseStateError>())?;
+    module.add("ProtocolViolationError", py.get_type::<ProtocolViolationError>())?;
+
+    Ok(())
+}
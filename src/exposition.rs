@@ -0,0 +1,173 @@
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    header::{CONTENT_TYPE, HeaderValue, SERVER as HK_SERVER}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    time::Duration
+};
+
+use crate::{
+    http::HV_SERVER,
+    metrics::METRICS,
+    tcp::bind_listener,
+    workers::identity
+};
+
+const HV_EXPOSITION: HeaderValue = HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8");
+const REQUEST_HEAD_MAX: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Serves the metrics of the worker in the Prometheus text format, on a reserved
+// path of the application listener, answered before any application code runs,
+// and/or on the `/metrics` path of a dedicated port. As workers are separate
+// processes, each one listens on its own port, the given one offset by the
+// worker id, so that every worker can be scraped on its own.
+#[derive(Clone, Default)]
+pub(crate) struct MetricsExposition {
+    path: Option<Arc<str>>,
+    listen: Option<(IpAddr, u16)>
+}
+
+impl MetricsExposition {
+    pub fn new(path: Option<String>, address: String, port: Option<u16>) -> PyResult<Self> {
+        if let Some(path) = &path {
+            if !path.starts_with('/') {
+                return Err(PyValueError::new_err(format!("Invalid metrics path: {}", path)))
+            }
+        }
+        let listen = match port {
+            Some(port) => Some((
+                address.parse::<IpAddr>().map_err(
+                    |_| PyValueError::new_err(format!("Invalid metrics address: {}", address))
+                )?,
+                port
+            )),
+            None => None
+        };
+        Ok(Self { path: path.map(Arc::from), listen })
+    }
+
+    pub fn respond<B>(&self, req: &Request<B>) -> Option<Response<Body>> {
+        match &self.path {
+            Some(path) if req.uri().path() == &path[..] => {},
+            _ => return None
+        }
+        let mut res = match *req.method() {
+            Method::GET => Response::new(Body::from(METRICS.render())),
+            Method::HEAD => Response::new(Body::empty()),
+            _ => {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+                res.headers_mut().insert(hyper::header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+                res
+            }
+        };
+        res.headers_mut().insert(HK_SERVER, HV_SERVER);
+        if res.status() == StatusCode::OK {
+            res.headers_mut().insert(CONTENT_TYPE, HV_EXPOSITION);
+        }
+        Some(res)
+    }
+
+    // Listens on the dedicated port in a background thread, until the handle gets dropped
+    pub fn start(&self) -> Option<ExpositionHandle> {
+        let (ip, port) = self.listen?;
+        let port = match u16::try_from(i64::from(port) + i64::from(identity().id.max(1)) - 1) {
+            Ok(port) => port,
+            Err(_) => {
+                log::error!("Unable to serve metrics: port out of range for worker-{}", identity().id);
+                return None
+            }
+        };
+        let addr = SocketAddr::new(ip, port);
+        // replacement workers bind the port while the old process is still draining
+        let listener = match bind_listener(addr, 128, true) {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Unable to serve metrics: {}", err);
+                return None
+            }
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        std::thread::Builder::new()
+            .name("granian-metrics".to_string())
+            .spawn(move || serve(listener, stopped))
+            .ok()?;
+        log::info!("Serving metrics at: http://{}/metrics", addr);
+        Some(ExpositionHandle { stop, addr })
+    }
+}
+
+pub(crate) struct ExpositionHandle {
+    stop: Arc<AtomicBool>,
+    addr: SocketAddr
+}
+
+impl Drop for ExpositionHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wakes up the listening thread, blocked on accept
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_millis(100));
+    }
+}
+
+fn serve(listener: TcpListener, stop: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            break
+        }
+        if let Ok(stream) = stream {
+            if let Err(err) = respond(stream) {
+                log::debug!("Metrics request failed: {}", err);
+            }
+        }
+    }
+}
+
+// Requests get a single response each, as scrapes happen seconds apart anyway
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf)?;
+        if read == 0 || head.len() + read > REQUEST_HEAD_MAX {
+            return Ok(())
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    let line = String::from_utf8_lossy(&head);
+    let mut parts = line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let target = target.split('?').next().unwrap_or("");
+    let (status, body) = match (method, target == "/metrics") {
+        ("GET" | "HEAD", true) => ("200 OK", METRICS.render()),
+        (_, true) => ("405 Method Not Allowed", String::new()),
+        _ => ("404 Not Found", String::new())
+    };
+    let mut res = format!(
+        "HTTP/1.1 {}\r\nserver: granian\r\ncontent-length: {}\r\nconnection: close\r\n",
+        status,
+        body.len()
+    );
+    match status {
+        "200 OK" => res.push_str("content-type: text/plain; version=0.0.4; charset=utf-8\r\n"),
+        "405 Method Not Allowed" => res.push_str("allow: GET, HEAD\r\n"),
+        _ => {}
+    }
+    res.push_str("\r\n");
+    if method != "HEAD" {
+        res.push_str(&body);
+    }
+    stream.write_all(res.as_bytes())
+}
@@ -0,0 +1,516 @@
+use bytes::{Bytes, BytesMut};
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    header::{
+        ACCEPT_RANGES,
+        CONTENT_LENGTH,
+        CONTENT_RANGE,
+        CONTENT_TYPE,
+        ETAG,
+        HeaderValue,
+        IF_RANGE,
+        LAST_MODIFIED,
+        RANGE
+    }
+};
+use std::{
+    collections::HashMap,
+    fs::{File, Metadata},
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime}
+};
+
+use crate::{clock, errors::Error, http::response_error};
+
+
+const CHUNK_MIN: usize = 16 * 1024;
+const CHUNK_MAX: usize = 1024 * 1024;
+// Chunks get sized to take about this long to be consumed at the measured rate
+const CHUNK_TARGET: Duration = Duration::from_millis(10);
+
+// Read sizes following the pace at which the connection consumes the body:
+// fast clients quickly get larger chunks (fewer reads and wake-ups), while clients
+// applying backpressure get smaller ones, so we don't buffer data they can't take.
+struct ChunkSizer {
+    size: usize,
+    sent_at: Option<Instant>
+}
+
+impl ChunkSizer {
+    fn new() -> Self {
+        Self { size: CHUNK_MIN, sent_at: None }
+    }
+
+    // The body gets polled again once the previous chunk was taken by the connection
+    fn on_poll(&mut self) {
+        let elapsed = match self.sent_at.take() {
+            Some(sent_at) => sent_at.elapsed(),
+            None => return
+        };
+        let rate = self.size as f64 / elapsed.as_secs_f64().max(1e-6);
+        let wanted = (rate * CHUNK_TARGET.as_secs_f64()) as usize;
+        self.size = wanted.clamp(self.size / 2, self.size * 2).clamp(CHUNK_MIN, CHUNK_MAX);
+    }
+
+    fn on_send(&mut self) {
+        self.sent_at = Some(Instant::now());
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+// Positional reads, as cached handles are shared by concurrent responses
+fn read_chunk(file: &File, offset: u64, size: usize) -> io::Result<Bytes> {
+    let mut buf = BytesMut::zeroed(size);
+    let mut filled = 0;
+    while filled < size {
+        match read_at(file, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err)
+        }
+    }
+    buf.truncate(filled);
+    Ok(buf.freeze())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise(file: &File, offset: u64, len: u64, advice: libc::c_int) {
+    use std::os::unix::io::AsRawFd;
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise_sequential(file: &File) {
+    advise(file, 0, 0, libc::POSIX_FADV_SEQUENTIAL)
+}
+
+// Once a chunk is read, the next one gets prefetched; when dropping the cache, the
+// whole range read so far is advised, as the kernel can skip recently added pages.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise_read(file: &File, offset: u64, len: usize, drop_cache: bool) {
+    let end = offset + len as u64;
+    if drop_cache {
+        advise(file, 0, end, libc::POSIX_FADV_DONTNEED);
+    }
+    advise(file, end, len as u64, libc::POSIX_FADV_WILLNEED);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn advise_sequential(_file: &File) {}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn advise_read(_file: &File, _offset: u64, _len: usize, _drop_cache: bool) {}
+
+// Directories and special files can be opened, but not served as a response body
+fn check_regular(meta: Metadata) -> io::Result<Metadata> {
+    match meta.is_file() {
+        true => Ok(meta),
+        false => Err(io::Error::new(io::ErrorKind::NotFound, "not a regular file"))
+    }
+}
+
+fn open_regular(path: &str) -> io::Result<(File, FileIdentity)> {
+    let file = File::open(path)?;
+    let identity = FileIdentity::new(&check_regular(file.metadata()?)?);
+    advise_sequential(&file);
+    Ok((file, identity))
+}
+
+// Media types for the extensions commonly served, anything else being opaque data
+fn content_type(path: &str) -> HeaderValue {
+    let extension = Path::new(path).extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    HeaderValue::from_static(match &extension[..] {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "map" => "application/json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream"
+    })
+}
+
+// What identifies the file behind a path, to detect replaced or modified files
+#[derive(Clone, PartialEq)]
+struct FileIdentity {
+    #[cfg(unix)]
+    inode: (u64, u64),
+    modified: Option<SystemTime>,
+    len: u64
+}
+
+impl FileIdentity {
+    fn new(meta: &Metadata) -> Self {
+        Self {
+            #[cfg(unix)]
+            inode: {
+                use std::os::unix::fs::MetadataExt;
+                (meta.dev(), meta.ino())
+            },
+            modified: meta.modified().ok(),
+            len: meta.len()
+        }
+    }
+
+    // Strong validator for the file contents
+    fn etag(&self) -> Option<HeaderValue> {
+        let modified = self.modified?.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+        HeaderValue::from_str(&format!("\"{:x}-{:x}\"", self.len, modified.as_nanos())).ok()
+    }
+
+    fn last_modified(&self) -> Option<HeaderValue> {
+        HeaderValue::from_str(&httpdate::fmt_http_date(self.modified?)).ok()
+    }
+}
+
+struct CachedFile {
+    file: Arc<File>,
+    identity: FileIdentity,
+    checked_at: Instant,
+    used_at: u64
+}
+
+struct FileCacheEntries {
+    files: HashMap<String, CachedFile>,
+    clock: u64
+}
+
+// Open handles for the most recently served paths. Within the TTL a cached handle is
+// used as is, later its path gets checked again, and the file re-opened if changed.
+struct FileCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<FileCacheEntries>
+}
+
+impl FileCache {
+    fn get(&self, path: &str) -> Option<(Arc<File>, FileIdentity)> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.files.get_mut(path)?;
+        if clock::elapsed(entry.checked_at) > self.ttl {
+            return None
+        }
+        entry.used_at = clock;
+        Some((entry.file.clone(), entry.identity.clone()))
+    }
+
+    fn open(&self, path: String) -> io::Result<(Arc<File>, FileIdentity)> {
+        if let Some(cached) = self.get(&path) {
+            return Ok(cached)
+        }
+        let identity = FileIdentity::new(&check_regular(std::fs::metadata(&path)?)?);
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(entry) = entries.files.get_mut(&path) {
+                if entry.identity == identity {
+                    entry.checked_at = clock::now();
+                    return Ok((entry.file.clone(), identity))
+                }
+            }
+        }
+
+        let (file, identity) = open_regular(&path)?;
+        let file = Arc::new(file);
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.files.len() >= self.capacity && !entries.files.contains_key(&path) {
+            let lru = entries.files.iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = lru {
+                entries.files.remove(&key);
+            }
+        }
+        let used_at = entries.clock;
+        entries.files.insert(
+            path,
+            CachedFile { file: file.clone(), identity: identity.clone(), checked_at: clock::now(), used_at }
+        );
+        Ok((file, identity))
+    }
+}
+
+// An inclusive byte range, already checked against the file length
+struct ByteRange {
+    start: u64,
+    end: u64
+}
+
+enum RangeSelection {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable
+}
+
+// Only single `bytes` ranges are honoured: for anything else (multiple ranges
+// included) the full file gets sent, which is always a valid answer.
+fn parse_range(value: &str, len: u64) -> RangeSelection {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return RangeSelection::Full
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return RangeSelection::Full
+    };
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeSelection::Unsatisfiable,
+            Ok(suffix) => ByteRange { start: len.saturating_sub(suffix), end: len.saturating_sub(1) },
+            _ => return RangeSelection::Full
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => ByteRange { start, end: len.saturating_sub(1) },
+            _ => return RangeSelection::Full
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => ByteRange { start, end: end.min(len.saturating_sub(1)) },
+            _ => return RangeSelection::Full
+        }
+    };
+    match range.start < len {
+        true => RangeSelection::Partial(range),
+        false => RangeSelection::Unsatisfiable
+    }
+}
+
+// `If-Range` only accepts strong validators: entity tags get compared as they are,
+// dates need to match the last modification time exactly.
+fn if_range_matches(value: &HeaderValue, etag: Option<&HeaderValue>, last_modified: Option<&HeaderValue>) -> bool {
+    let value = match value.to_str() {
+        Ok(value) => value.trim(),
+        _ => return false
+    };
+    if value.starts_with('"') {
+        return etag.is_some_and(|etag| etag.as_bytes() == value.as_bytes())
+    }
+    if value.starts_with("W/") {
+        return false
+    }
+    match (httpdate::parse_http_date(value), last_modified.and_then(|lm| lm.to_str().ok())) {
+        (Ok(date), Some(last_modified)) => httpdate::parse_http_date(last_modified).is_ok_and(|lm| lm == date),
+        _ => false
+    }
+}
+
+// The range headers of a request, used to build the matching file response
+pub(crate) struct RangeRequest {
+    range: Option<HeaderValue>,
+    if_range: Option<HeaderValue>
+}
+
+impl RangeRequest {
+    pub fn new(req: &Request<Body>) -> Self {
+        match *req.method() {
+            Method::GET | Method::HEAD => Self {
+                range: req.headers().get(RANGE).cloned(),
+                if_range: req.headers().get(IF_RANGE).cloned()
+            },
+            _ => Self { range: None, if_range: None }
+        }
+    }
+}
+
+// Set as a response extension by interfaces whose applications send files from
+// within the response flow, the response gets filled once the callback returns.
+#[derive(Clone)]
+pub(crate) struct FilePath(pub String);
+
+struct FileStream {
+    file: Arc<File>,
+    offset: u64,
+    end: u64,
+    drop_cache: bool,
+    sizer: ChunkSizer
+}
+
+#[derive(Clone)]
+pub(crate) struct FileResponses {
+    drop_cache_threshold: u64,
+    cache: Option<Arc<FileCache>>
+}
+
+impl FileResponses {
+    // Files bigger than a non-zero `drop_cache_threshold` are considered one-off
+    // downloads, which shouldn't evict the hot working set from the page cache.
+    pub fn new(drop_cache_threshold: u64, cache_size: usize, cache_ttl: f64) -> Self {
+        let cache = match cache_size {
+            0 => None,
+            capacity => Some(Arc::new(FileCache {
+                capacity,
+                ttl: Duration::from_secs_f64(cache_ttl.max(0.0)),
+                entries: Mutex::new(FileCacheEntries { files: HashMap::new(), clock: 0 })
+            }))
+        };
+        Self { drop_cache_threshold, cache }
+    }
+
+    async fn open(&self, path: String) -> io::Result<(Arc<File>, FileIdentity)> {
+        let cache = self.cache.clone();
+        tokio::task::spawn_blocking(move || match cache {
+            Some(cache) => cache.open(path),
+            None => open_regular(&path).map(|(file, identity)| (Arc::new(file), identity))
+        }).await?
+    }
+
+    fn body(&self, file: Arc<File>, len: u64, range: ByteRange) -> Body {
+        let drop_cache = self.drop_cache_threshold > 0 && len > self.drop_cache_threshold;
+        let state = FileStream {
+            file,
+            offset: range.start,
+            end: range.end + 1,
+            drop_cache,
+            sizer: ChunkSizer::new()
+        };
+        let stream = futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            if state.offset >= state.end {
+                return None
+            }
+            state.sizer.on_poll();
+            let read = tokio::task::spawn_blocking(move || {
+                let size = state.sizer.size.min((state.end - state.offset) as usize);
+                let chunk = read_chunk(&state.file, state.offset, size);
+                if let Ok(chunk) = &chunk {
+                    advise_read(&state.file, state.offset, chunk.len(), state.drop_cache);
+                }
+                (state, chunk)
+            }).await;
+            match read {
+                Ok((_, Ok(chunk))) if chunk.is_empty() => None,
+                Ok((mut state, Ok(chunk))) => {
+                    state.offset += chunk.len() as u64;
+                    state.sizer.on_send();
+                    Some((Ok(chunk), Some(state)))
+                },
+                Ok((_, Err(err))) => Some((Err(err), None)),
+                Err(err) => Some((Err(io::Error::other(err)), None))
+            }
+        });
+        Body::wrap_stream(stream)
+    }
+
+    // Fills the given response with the file contents. Successful responses also get
+    // validators and range support, unless the application already set its own ones.
+    // Paths not pointing to a regular file get a 404, other failures a 500.
+    pub async fn respond(
+        &self,
+        mut res: Response<Body>,
+        path: String,
+        range: RangeRequest
+    ) -> Response<Body> {
+        let (file, identity) = match self.open(path.clone()).await {
+            Ok(opened) => opened,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                log::warn!("Unable to serve file response, {} not found", path);
+                return response_error(StatusCode::NOT_FOUND, "Not found", None)
+            },
+            Err(err) => {
+                return Error::app(format!("Unable to serve file response {}: {}", path, err)).response()
+            }
+        };
+        let len = identity.len;
+        let full = ByteRange { start: 0, end: len.saturating_sub(1) };
+        if !res.headers().contains_key(CONTENT_TYPE) {
+            res.headers_mut().insert(CONTENT_TYPE, content_type(&path));
+        }
+        if res.status() != StatusCode::OK {
+            *res.body_mut() = match len {
+                0 => Body::empty(),
+                _ => self.body(file, len, full)
+            };
+            return res
+        }
+
+        let headers = res.headers_mut();
+        if !headers.contains_key(ETAG) {
+            if let Some(etag) = identity.etag() {
+                headers.insert(ETAG, etag);
+            }
+        }
+        if !headers.contains_key(LAST_MODIFIED) {
+            if let Some(last_modified) = identity.last_modified() {
+                headers.insert(LAST_MODIFIED, last_modified);
+            }
+        }
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+        let validated = range.if_range.as_ref().is_none_or(|if_range| {
+            if_range_matches(if_range, headers.get(ETAG), headers.get(LAST_MODIFIED))
+        });
+        let selection = match range.range.as_ref().map(|value| value.to_str()) {
+            Some(Ok(value)) if validated => parse_range(value, len),
+            _ => RangeSelection::Full
+        };
+        match selection {
+            RangeSelection::Full => {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+                if len > 0 {
+                    *res.body_mut() = self.body(file, len, full);
+                }
+            },
+            RangeSelection::Partial(range) => {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(range.end - range.start + 1));
+                headers.insert(CONTENT_RANGE, HeaderValue::from_str(
+                    &format!("bytes {}-{}/{}", range.start, range.end, len)
+                ).unwrap());
+                *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+                *res.body_mut() = self.body(file, len, range);
+            },
+            RangeSelection::Unsatisfiable => {
+                headers.insert(CONTENT_RANGE, HeaderValue::from_str(&format!("bytes */{}", len)).unwrap());
+                headers.remove(CONTENT_LENGTH);
+                *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,446 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::stream::Stream;
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderMap}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{cmp::Reverse, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const HTML_BODY_TAG: &[u8] = b"<body";
+
+pub(crate) trait BodyFilter: Send {
+    fn apply(&mut self, chunk: Bytes) -> Bytes;
+
+    fn finish(&mut self) -> Bytes {
+        Bytes::new()
+    }
+}
+
+struct StripBom {
+    pending: Option<BytesMut>
+}
+
+impl BodyFilter for StripBom {
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return chunk
+        };
+        pending.extend_from_slice(&chunk);
+        if pending.len() < UTF8_BOM.len() && UTF8_BOM.starts_with(&pending) {
+            self.pending = Some(pending);
+            return Bytes::new()
+        }
+        let mut data = pending.freeze();
+        if data.starts_with(UTF8_BOM) {
+            data = data.slice(UTF8_BOM.len()..);
+        }
+        data
+    }
+
+    fn finish(&mut self) -> Bytes {
+        self.pending.take().map(|pending| pending.freeze()).unwrap_or_default()
+    }
+}
+
+struct NormalizeNewlines {
+    skip_lf: bool
+}
+
+impl BodyFilter for NormalizeNewlines {
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        // a carriage return ending the previous chunk still pairs with the next one
+        if chunk.is_empty() {
+            return chunk
+        }
+        if !chunk.contains(&b'\r') && (!self.skip_lf || chunk.first() != Some(&b'\n')) {
+            self.skip_lf = false;
+            return chunk
+        }
+        let mut ret = BytesMut::with_capacity(chunk.len());
+        for byte in chunk.iter() {
+            match (byte, self.skip_lf) {
+                (b'\n', true) => {},
+                (b'\r', _) => ret.put_u8(b'\n'),
+                _ => ret.put_u8(*byte)
+            }
+            self.skip_lf = *byte == b'\r';
+        }
+        ret.freeze()
+    }
+}
+
+struct Latin1ToUtf8;
+
+impl BodyFilter for Latin1ToUtf8 {
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        if chunk.is_ascii() {
+            return chunk
+        }
+        let mut ret = BytesMut::with_capacity(chunk.len() * 2);
+        for byte in chunk.iter() {
+            match byte {
+                0..=0x7f => ret.put_u8(*byte),
+                _ => {
+                    ret.put_u8(0xc0 | (byte >> 6));
+                    ret.put_u8(0x80 | (byte & 0x3f));
+                }
+            }
+        }
+        ret.freeze()
+    }
+}
+
+// Body tags longer than this get sent on as they come, rather than buffered
+// until their end shows up
+const BODY_TAG_MAX_PENDING: usize = 256;
+
+struct InjectBanner {
+    banner: Bytes,
+    pending: BytesMut,
+    // whether the body tag was found, but not its end yet
+    in_tag: bool,
+    done: bool
+}
+
+impl BodyFilter for InjectBanner {
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        if self.done {
+            return chunk
+        }
+        if self.in_tag {
+            self.pending.extend_from_slice(&chunk);
+            return match self.pending.iter().position(|&c| c == b'>') {
+                Some(end) => {
+                    let mut ret = self.pending.split_to(end + 1);
+                    ret.extend_from_slice(&self.banner);
+                    ret.extend_from_slice(&self.pending.split());
+                    self.done = true;
+                    ret.freeze()
+                },
+                None => self.pending.split().freeze()
+            }
+        }
+        self.pending.extend_from_slice(&chunk);
+        let tag_start = find_ignore_case(&self.pending, HTML_BODY_TAG);
+        let tag_end = tag_start.and_then(
+            |start| self.pending[start..].iter().position(|&c| c == b'>').map(|pos| start + pos)
+        );
+        match (tag_start, tag_end) {
+            (_, Some(end)) => {
+                let mut ret = self.pending.split_to(end + 1);
+                ret.extend_from_slice(&self.banner);
+                ret.extend_from_slice(&self.pending.split());
+                self.done = true;
+                ret.freeze()
+            },
+            (Some(start), None) if self.pending.len() - start > BODY_TAG_MAX_PENDING => {
+                self.in_tag = true;
+                self.pending.split().freeze()
+            },
+            (Some(start), None) => self.pending.split_to(start).freeze(),
+            // keep enough bytes to match a tag split across chunks
+            (None, None) => {
+                let keep = self.pending.len().min(HTML_BODY_TAG.len() - 1);
+                self.pending.split_to(self.pending.len() - keep).freeze()
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Bytes {
+        self.pending.split().freeze()
+    }
+}
+
+struct Replace {
+    from: Bytes,
+    to: Bytes,
+    pending: BytesMut
+}
+
+impl BodyFilter for Replace {
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        self.pending.extend_from_slice(&chunk);
+        let mut ret = BytesMut::with_capacity(self.pending.len());
+        let mut pos = 0;
+        while let Some(idx) = find(&self.pending[pos..], &self.from) {
+            ret.extend_from_slice(&self.pending[pos..pos + idx]);
+            ret.extend_from_slice(&self.to);
+            pos += idx + self.from.len();
+        }
+        let keep = (self.pending.len() - pos).min(self.from.len() - 1);
+        ret.extend_from_slice(&self.pending[pos..self.pending.len() - keep]);
+        let _ = self.pending.split_to(self.pending.len() - keep);
+        ret.freeze()
+    }
+
+    fn finish(&mut self) -> Bytes {
+        self.pending.split().freeze()
+    }
+}
+
+// Filtering bodies with a coding other than `identity` would need decoding them
+fn encoded(headers: &HeaderMap) -> bool {
+    headers.get_all(CONTENT_ENCODING).iter().any(|value| match value.to_str() {
+        Ok(value) => value.split(',')
+            .map(|coding| coding.trim())
+            .any(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity")),
+        Err(_) => true
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+#[derive(Clone)]
+pub(crate) enum FilterKind {
+    StripBom,
+    NormalizeNewlines,
+    Latin1ToUtf8,
+    InjectBanner(Bytes),
+    Replace(Bytes, Bytes)
+}
+
+impl FilterKind {
+    fn from_spec(spec: &str) -> PyResult<Self> {
+        let (name, arg) = match spec.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (spec, None)
+        };
+        match (name, arg) {
+            ("strip_bom", None) => Ok(Self::StripBom),
+            ("normalize_newlines", None) => Ok(Self::NormalizeNewlines),
+            ("latin1_to_utf8", None) => Ok(Self::Latin1ToUtf8),
+            ("inject_banner", Some(banner)) => {
+                Ok(Self::InjectBanner(Bytes::copy_from_slice(banner.as_bytes())))
+            },
+            ("replace", Some(arg)) => {
+                match arg.split_once('|') {
+                    Some((from, to)) if !from.is_empty() => Ok(Self::Replace(
+                        Bytes::copy_from_slice(from.as_bytes()),
+                        Bytes::copy_from_slice(to.as_bytes())
+                    )),
+                    _ => Err(PyValueError::new_err(
+                        format!("Invalid body filter '{}', expected replace:FROM|TO", spec)
+                    ))
+                }
+            },
+            _ => Err(PyValueError::new_err(format!("Unknown body filter '{}'", spec)))
+        }
+    }
+
+    fn build(&self) -> Box<dyn BodyFilter> {
+        match self {
+            Self::StripBom => Box::new(StripBom { pending: Some(BytesMut::new()) }),
+            Self::NormalizeNewlines => Box::new(NormalizeNewlines { skip_lf: false }),
+            Self::Latin1ToUtf8 => Box::new(Latin1ToUtf8),
+            Self::InjectBanner(banner) => Box::new(InjectBanner {
+                banner: banner.clone(),
+                pending: BytesMut::new(),
+                in_tag: false,
+                done: false
+            }),
+            Self::Replace(from, to) => Box::new(Replace {
+                from: from.clone(),
+                to: to.clone(),
+                pending: BytesMut::new()
+            })
+        }
+    }
+}
+
+// Filters to apply, keyed by path prefix or content type
+type FilterRule = (String, Vec<FilterKind>);
+
+fn parse_rules(rules: Vec<(String, Vec<String>)>) -> PyResult<Vec<FilterRule>> {
+    let mut parsed = Vec::with_capacity(rules.len());
+    for (key, specs) in rules {
+        let kinds = specs.iter()
+            .map(|spec| FilterKind::from_spec(spec))
+            .collect::<PyResult<Vec<FilterKind>>>()?;
+        parsed.push((key, kinds));
+    }
+    Ok(parsed)
+}
+
+pub(crate) struct FilterChain {
+    filters: Vec<Box<dyn BodyFilter>>
+}
+
+impl FilterChain {
+    fn new(kinds: &[FilterKind]) -> Self {
+        Self { filters: kinds.iter().map(|kind| kind.build()).collect() }
+    }
+
+    fn apply(&mut self, chunk: Bytes) -> Bytes {
+        self.filters.iter_mut().fold(chunk, |data, filter| filter.apply(data))
+    }
+
+    fn finish(&mut self) -> Bytes {
+        let mut tail = Bytes::new();
+        for filter in self.filters.iter_mut() {
+            let mut data = BytesMut::from(&filter.apply(tail)[..]);
+            data.extend_from_slice(&filter.finish());
+            tail = data.freeze();
+        }
+        tail
+    }
+}
+
+pub(crate) struct FilteredBody {
+    inner: Body,
+    chain: FilterChain,
+    done: bool
+}
+
+impl FilteredBody {
+    pub fn new(inner: Body, chain: FilterChain) -> Self {
+        Self { inner, chain, done: false }
+    }
+}
+
+impl Stream for FilteredBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None)
+            }
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    let data = self.chain.apply(chunk);
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(data)))
+                    }
+                },
+                Poll::Ready(None) => {
+                    self.done = true;
+                    let data = self.chain.finish();
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(data)))
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct RequestFilters {
+    rules: Vec<FilterRule>
+}
+
+impl RequestFilters {
+    pub fn new(rules: Vec<(String, Vec<String>)>) -> PyResult<Self> {
+        let mut parsed = parse_rules(rules)?;
+        // longest prefixes first, so the most specific rule wins
+        parsed.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+        Ok(Self { rules: parsed })
+    }
+
+    // Prefixes match whole path segments, so `/api` covers `/api/users` but not `/apis`
+    fn chain_for(&self, path: &str) -> Option<FilterChain> {
+        self.rules.iter()
+            .find(|(prefix, _)| match path.strip_prefix(&prefix[..]) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'),
+                None => false
+            })
+            .map(|(_, kinds)| FilterChain::new(kinds))
+    }
+
+    pub fn apply(&self, req: Request<Body>) -> Request<Body> {
+        if self.rules.is_empty() || encoded(req.headers()) {
+            return req
+        }
+        match self.chain_for(req.uri().path()) {
+            Some(chain) => {
+                let (mut parts, body) = req.into_parts();
+                // filters might change the body size, so the original length is meaningless
+                parts.headers.remove(CONTENT_LENGTH);
+                Request::from_parts(parts, Body::wrap_stream(FilteredBody::new(body, chain)))
+            },
+            None => req
+        }
+    }
+}
+
+// Filters of the response bodies, keyed by content type. Bodies which are
+// encoded, partial or absent get sent as they are, as filtering them would make
+// their headers describe something else.
+#[derive(Clone, Default)]
+pub(crate) struct ResponseFilters {
+    rules: Arc<Vec<FilterRule>>
+}
+
+impl ResponseFilters {
+    pub fn new(rules: Vec<(String, Vec<String>)>) -> PyResult<Self> {
+        let mut parsed = parse_rules(rules)?;
+        for (content_type, _) in parsed.iter_mut() {
+            *content_type = content_type.to_ascii_lowercase();
+        }
+        Ok(Self { rules: Arc::new(parsed) })
+    }
+
+    pub fn request(&self, req: &Request<Body>) -> FilterResponse {
+        match self.rules.is_empty() || req.method() == Method::HEAD {
+            true => FilterResponse(None),
+            false => FilterResponse(Some(self.rules.clone()))
+        }
+    }
+}
+
+pub(crate) struct FilterResponse(Option<Arc<Vec<FilterRule>>>);
+
+impl FilterResponse {
+    fn chain_for(rules: &[FilterRule], content_type: &str) -> Option<FilterChain> {
+        let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        rules.iter()
+            .find(|(key, _)| match key.strip_suffix('*') {
+                Some(prefix) => essence.starts_with(prefix),
+                None => *key == essence
+            })
+            .map(|(_, kinds)| FilterChain::new(kinds))
+    }
+
+    pub fn respond(self, res: Response<Body>) -> Response<Body> {
+        let rules = match self.0 {
+            Some(rules) => rules,
+            None => return res
+        };
+        let headers = res.headers();
+        if encoded(headers)
+            || headers.contains_key(CONTENT_RANGE)
+            || matches!(res.status(), StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED | StatusCode::PARTIAL_CONTENT) {
+            return res
+        }
+        let chain = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Self::chain_for(&rules, value));
+        match chain {
+            Some(chain) => {
+                let (mut parts, body) = res.into_parts();
+                parts.headers.remove(CONTENT_LENGTH);
+                // filtered bodies are no longer the representation the tag identified
+                parts.headers.remove(ETAG);
+                Response::from_parts(parts, Body::wrap_stream(FilteredBody::new(body, chain)))
+            },
+            None => res
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use hyper::{HeaderMap, header::FORWARDED};
+use pyo3::prelude::*;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc
+};
+
+use crate::deadlines::Network;
+
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+// A hop of the forwarding chain: the node which connected to the next proxy,
+// and the protocol it used to, when told.
+struct Hop {
+    addr: Option<SocketAddr>,
+    proto: Option<&'static str>
+}
+
+// Client addresses and schemes got from the `Forwarded` header (or, when missing,
+// from the `X-Forwarded-For` and `X-Forwarded-Proto` ones) of requests coming
+// from trusted proxies. The chain is walked from the nearest hop, until the
+// first one not trusted, so clients can't spoof the addresses set by proxies.
+#[derive(Clone, Default)]
+pub(crate) struct ForwardedHeaders {
+    trusted: Option<Arc<Vec<Network>>>
+}
+
+impl ForwardedHeaders {
+    pub fn new(trusted: Vec<String>) -> PyResult<Self> {
+        if trusted.is_empty() {
+            return Ok(Self::default())
+        }
+        let trusted = trusted.iter().map(|value| Network::parse(value)).collect::<PyResult<_>>()?;
+        Ok(Self { trusted: Some(Arc::new(trusted)) })
+    }
+
+    pub fn resolve(
+        &self,
+        headers: &HeaderMap,
+        peer: SocketAddr,
+        scheme: &'static str
+    ) -> (SocketAddr, &'static str) {
+        let trusted = match self.trusted.as_ref() {
+            Some(trusted) if is_trusted(trusted, peer.ip()) => trusted,
+            _ => return (peer, scheme)
+        };
+        let hops = match headers.contains_key(FORWARDED) {
+            true => forwarded_hops(headers),
+            false => x_forwarded_hops(headers)
+        };
+        let mut client = (peer, scheme);
+        for hop in hops.iter().rev() {
+            if let Some(proto) = hop.proto {
+                client.1 = proto;
+            }
+            match hop.addr {
+                Some(addr) => client.0 = addr,
+                None => break
+            }
+            if !is_trusted(trusted, client.0.ip()) {
+                break
+            }
+        }
+        client
+    }
+}
+
+fn is_trusted(trusted: &[Network], addr: IpAddr) -> bool {
+    trusted.iter().any(|network| network.contains(addr))
+}
+
+fn header_values<'h>(headers: &'h HeaderMap, name: &str) -> Vec<&'h str> {
+    headers.get_all(name).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+// Nodes are IP addresses, optionally with a port and IPv6 ones in brackets;
+// obfuscated identifiers and `unknown` are not usable.
+fn parse_node(value: &str) -> Option<SocketAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Some(addr)
+    }
+    let ip = value.strip_prefix('[').and_then(|ip| ip.strip_suffix(']')).unwrap_or(value);
+    ip.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 0))
+}
+
+fn parse_proto(value: &str) -> Option<&'static str> {
+    let value = value.trim().trim_matches('"');
+    if value.eq_ignore_ascii_case("https") || value.eq_ignore_ascii_case("wss") {
+        Some("https")
+    } else if value.eq_ignore_ascii_case("http") || value.eq_ignore_ascii_case("ws") {
+        Some("http")
+    } else {
+        None
+    }
+}
+
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    header_values(headers, FORWARDED.as_str()).into_iter().map(|element| {
+        let mut hop = Hop { addr: None, proto: None };
+        for pair in element.split(';') {
+            match pair.split_once('=') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("for") => hop.addr = parse_node(value),
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("proto") => hop.proto = parse_proto(value),
+                _ => {}
+            }
+        }
+        hop
+    }).collect()
+}
+
+// Proxies append to both headers, so protocols are matched to addresses from
+// the nearest hop; farther hops with no protocol keep the one of the nearer.
+fn x_forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    let addrs = header_values(headers, X_FORWARDED_FOR);
+    let protos = header_values(headers, X_FORWARDED_PROTO);
+    if addrs.is_empty() {
+        return protos.last().map(|proto| Hop { addr: None, proto: parse_proto(proto) }).into_iter().collect()
+    }
+    let skip = addrs.len().saturating_sub(protos.len());
+    let offset = protos.len().saturating_sub(addrs.len());
+    addrs.iter().enumerate().map(|(idx, addr)| Hop {
+        addr: parse_node(addr),
+        proto: match idx < skip {
+            true => None,
+            false => protos.get(idx - skip + offset).and_then(|proto| parse_proto(proto))
+        }
+    }).collect()
+}
@@ -0,0 +1,346 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::stream::Stream;
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue}
+};
+use std::{io, pin::Pin, task::{Context, Poll}};
+
+use crate::negotiation::escape_json;
+
+
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_MESSAGE: &str = "grpc-message";
+const FRAME_HEADER_SIZE: usize = 5;
+// Messages get buffered whole before reaching the application,
+// so their size is capped like gRPC servers do by default.
+const MESSAGE_SIZE_MAX: usize = 4 * 1024 * 1024;
+const FLAG_COMPRESSED: u8 = 0x01;
+const FLAG_CONNECT_END: u8 = 0x02;
+const FLAG_GRPC_TRAILERS: u8 = 0x80;
+const CONNECT_CODES: [&str; 17] = [
+    "ok",
+    "canceled",
+    "unknown",
+    "invalid_argument",
+    "deadline_exceeded",
+    "not_found",
+    "already_exists",
+    "permission_denied",
+    "resource_exhausted",
+    "failed_precondition",
+    "aborted",
+    "out_of_range",
+    "unimplemented",
+    "internal",
+    "unavailable",
+    "data_loss",
+    "unauthenticated"
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Protocol {
+    GrpcWeb,
+    GrpcWebText,
+    Connect
+}
+
+impl Protocol {
+    // Codecs are the `+proto` or `+json` suffixes, defaulting to protobuf
+    fn detect(content_type: &str) -> Option<(Self, &str)> {
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        let (base, codec) = essence.split_once('+').unwrap_or((essence, "proto"));
+        let protocol = match base.to_ascii_lowercase().as_str() {
+            "application/grpc-web" => Self::GrpcWeb,
+            "application/grpc-web-text" => Self::GrpcWebText,
+            "application/connect" if essence.contains('+') => Self::Connect,
+            _ => return None
+        };
+        Some((protocol, codec))
+    }
+}
+
+// Translation of gRPC-Web and streaming Connect calls: the length-prefixed
+// messages of requests, base64 encoded for `grpc-web-text`, reach applications
+// as plain bodies with an `application/{codec}` content type, like unary
+// Connect calls, one body chunk per message. Every chunk of the response body
+// gets framed as a message, and the call status follows them, taken from the
+// `grpc-status` and `grpc-message` response headers or from the HTTP status.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct GrpcWeb(bool);
+
+impl GrpcWeb {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    pub fn translate(&self, req: Request<Body>) -> (Request<Body>, GrpcCall) {
+        if !self.0 {
+            return (req, GrpcCall(None))
+        }
+        let detected = req.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                Protocol::detect(value).map(|(protocol, codec)| (protocol, value.to_string(), codec.to_string()))
+            });
+        let (protocol, content_type, codec) = match detected {
+            Some(detected) => detected,
+            None => return (req, GrpcCall(None))
+        };
+        let (mut parts, body) = req.into_parts();
+        parts.headers.remove(CONTENT_LENGTH);
+        if let Ok(value) = HeaderValue::from_str(&format!("application/{}", codec)) {
+            parts.headers.insert(CONTENT_TYPE, value);
+        }
+        let body = Body::wrap_stream(MessagesBody {
+            inner: body,
+            text: (protocol == Protocol::GrpcWebText).then(Base64Decoder::default),
+            buf: BytesMut::new(),
+            done: false
+        });
+        (Request::from_parts(parts, body), GrpcCall(Some((protocol, content_type))))
+    }
+}
+
+pub(crate) struct GrpcCall(Option<(Protocol, String)>);
+
+impl GrpcCall {
+    pub fn respond(self, res: Response<Body>) -> Response<Body> {
+        let (protocol, content_type) = match self.0 {
+            Some(call) => call,
+            None => return res
+        };
+        let (mut parts, body) = res.into_parts();
+        let code = parts.headers.remove(GRPC_STATUS)
+            .and_then(|value| value.to_str().ok().and_then(|value| value.trim().parse::<u8>().ok()))
+            .unwrap_or_else(|| status_code(parts.status));
+        let message = parts.headers.remove(GRPC_MESSAGE)
+            .and_then(|value| value.to_str().ok().map(str::to_string))
+            .or_else(|| (code != 0).then(|| parts.status.canonical_reason().unwrap_or_default().to_string()))
+            .unwrap_or_default();
+        // bodies of failed responses are error pages rather than messages
+        let body = match parts.status.is_success() {
+            true => body,
+            false => Body::empty()
+        };
+        parts.status = StatusCode::OK;
+        parts.headers.remove(CONTENT_LENGTH);
+        if let Ok(value) = HeaderValue::from_str(&content_type) {
+            parts.headers.insert(CONTENT_TYPE, value);
+        }
+        let body = Body::wrap_stream(FramedBody {
+            inner: body,
+            protocol,
+            text: (protocol == Protocol::GrpcWebText).then(BytesMut::new),
+            status: Some((code, message))
+        });
+        Response::from_parts(parts, body)
+    }
+}
+
+// The mapping of HTTP statuses to gRPC codes used by gRPC clients
+fn status_code(status: StatusCode) -> u8 {
+    match status.as_u16() {
+        200..=299 => 0,
+        400 => 13,
+        401 => 16,
+        403 => 7,
+        404 => 12,
+        429 | 502 | 503 | 504 => 14,
+        _ => 2
+    }
+}
+
+fn frame(flags: u8, payload: &[u8]) -> Bytes {
+    let mut ret = BytesMut::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    ret.put_u8(flags);
+    ret.put_u32(payload.len() as u32);
+    ret.extend_from_slice(payload);
+    ret.freeze()
+}
+
+fn status_frame(protocol: Protocol, code: u8, message: &str) -> Bytes {
+    match protocol {
+        Protocol::Connect => {
+            let end = match code {
+                0 => "{}".to_string(),
+                code => format!(
+                    "{{\"error\":{{\"code\":\"{}\",\"message\":\"{}\"}}}}",
+                    CONNECT_CODES.get(code as usize).unwrap_or(&"unknown"),
+                    escape_json(message)
+                )
+            };
+            frame(FLAG_CONNECT_END, end.as_bytes())
+        },
+        _ => {
+            let mut trailers = format!("{}: {}\r\n", GRPC_STATUS, code);
+            if !message.is_empty() {
+                trailers.push_str(&format!("{}: {}\r\n", GRPC_MESSAGE, message));
+            }
+            frame(FLAG_GRPC_TRAILERS, trailers.as_bytes())
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Text bodies might be made of several base64 segments, each one with its own
+// padding: quads get decoded as soon as they're complete.
+#[derive(Default)]
+struct Base64Decoder {
+    pending: Vec<u8>
+}
+
+impl Base64Decoder {
+    fn decode(&mut self, chunk: &[u8], out: &mut BytesMut) -> io::Result<()> {
+        self.pending.extend(chunk.iter().filter(|byte| !byte.is_ascii_whitespace()));
+        let complete = self.pending.len() - self.pending.len() % 4;
+        let mut start = 0;
+        while start < complete {
+            let end = self.pending[start..complete].chunks(4)
+                .position(|quad| quad.contains(&b'='))
+                .map_or(complete, |idx| start + (idx + 1) * 4);
+            let decoded = base64::decode(&self.pending[start..end])
+                .map_err(|_| invalid("invalid base64 encoded body"))?;
+            out.extend_from_slice(&decoded);
+            start = end;
+        }
+        self.pending.drain(..complete);
+        Ok(())
+    }
+}
+
+struct MessagesBody {
+    inner: Body,
+    text: Option<Base64Decoder>,
+    buf: BytesMut,
+    done: bool
+}
+
+impl MessagesBody {
+    fn next_message(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            if self.buf.len() < FRAME_HEADER_SIZE {
+                return Ok(None)
+            }
+            let flags = self.buf[0];
+            let size = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+            if size > MESSAGE_SIZE_MAX {
+                return Err(invalid("message exceeds the maximum size"))
+            }
+            if self.buf.len() < FRAME_HEADER_SIZE + size {
+                return Ok(None)
+            }
+            self.buf.advance(FRAME_HEADER_SIZE);
+            let payload = self.buf.split_to(size).freeze();
+            if flags & (FLAG_GRPC_TRAILERS | FLAG_CONNECT_END) != 0 {
+                continue
+            }
+            if flags & FLAG_COMPRESSED != 0 {
+                return Err(invalid("compressed messages are not supported"))
+            }
+            return Ok(Some(payload))
+        }
+    }
+}
+
+impl Stream for MessagesBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match this.next_message() {
+                Ok(Some(message)) => return Poll::Ready(Some(Ok(message))),
+                Ok(None) => {},
+                Err(err) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)))
+                }
+            }
+            if this.done {
+                return match this.buf.is_empty() {
+                    true => Poll::Ready(None),
+                    false => {
+                        this.buf.clear();
+                        Poll::Ready(Some(Err(invalid("truncated message"))))
+                    }
+                }
+            }
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => match this.text.as_mut() {
+                    Some(decoder) => {
+                        if let Err(err) = decoder.decode(&chunk, &mut this.buf) {
+                            this.done = true;
+                            this.buf.clear();
+                            return Poll::Ready(Some(Err(err)))
+                        }
+                    },
+                    None => this.buf.extend_from_slice(&chunk)
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(io::Error::other(err)))),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+// Encoded text responses are kept a single base64 stream, padded at its end,
+// as not all the clients deal with padding in between.
+struct FramedBody {
+    inner: Body,
+    protocol: Protocol,
+    text: Option<BytesMut>,
+    status: Option<(u8, String)>
+}
+
+impl FramedBody {
+    fn encode(&mut self, data: Bytes, last: bool) -> Bytes {
+        let pending = match self.text.as_mut() {
+            Some(pending) => pending,
+            None => return data
+        };
+        pending.extend_from_slice(&data);
+        let size = match last {
+            true => pending.len(),
+            false => pending.len() - pending.len() % 3
+        };
+        Bytes::from(base64::encode(pending.split_to(size)))
+    }
+}
+
+impl Stream for FramedBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.status.is_none() {
+                return Poll::Ready(None)
+            }
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    if chunk.is_empty() {
+                        continue
+                    }
+                    let data = self.encode(frame(0, &chunk), false);
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(data)))
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => {
+                    let (code, message) = self.status.take().unwrap_or_default();
+                    let data = status_frame(self.protocol, code, &message);
+                    return Poll::Ready(Some(Ok(self.encode(data, true))))
+                },
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
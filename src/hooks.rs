@@ -0,0 +1,183 @@
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    Uri,
+    header::{
+        CONTENT_ENCODING,
+        CONTENT_LENGTH,
+        CONTENT_RANGE,
+        CONTENT_TYPE,
+        ETAG,
+        HOST,
+        HeaderName,
+        HeaderValue,
+        LAST_MODIFIED
+    }
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::synthetic::BodyTemplate;
+
+
+const HV_TEXT: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
+
+enum StatusMatch {
+    Code(u16),
+    Class(u16)
+}
+
+impl StatusMatch {
+    fn parse(value: &str) -> PyResult<Self> {
+        let invalid = || PyValueError::new_err(format!("Invalid status for response hook: {}", value));
+        match value.as_bytes() {
+            [class @ b'1'..=b'9', x1, x2] if x1.eq_ignore_ascii_case(&b'x') && x2.eq_ignore_ascii_case(&b'x') => {
+                Ok(Self::Class((class - b'0') as u16))
+            },
+            _ => match value.parse::<u16>() {
+                Ok(code) if (100..=999).contains(&code) => Ok(Self::Code(code)),
+                _ => Err(invalid())
+            }
+        }
+    }
+
+    #[inline]
+    fn matches(&self, status: u16) -> bool {
+        match self {
+            Self::Code(code) => *code == status,
+            Self::Class(class) => *class == status / 100
+        }
+    }
+}
+
+enum Action {
+    Header(HeaderName, HeaderValue),
+    Body(BodyTemplate),
+    Log(Option<String>)
+}
+
+struct Hook {
+    statuses: StatusMatch,
+    action: Action
+}
+
+impl Hook {
+    fn new(statuses: &str, action: &str, value: &str) -> PyResult<Self> {
+        let action = match action {
+            "header" => {
+                let (name, value) = value.split_once(':').ok_or_else(|| PyValueError::new_err(
+                    format!("Response hook headers should be NAME:VALUE, got '{}'", value)
+                ))?;
+                let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(
+                    |_| PyValueError::new_err(format!("Invalid response hook header name: {}", name))
+                )?;
+                let value = HeaderValue::from_str(value.trim()).map_err(
+                    |_| PyValueError::new_err(format!("Invalid value for response hook header {}", name))
+                )?;
+                Action::Header(name, value)
+            },
+            "body" => Action::Body(BodyTemplate::parse(value)?),
+            "log" => Action::Log(Some(value.to_string()).filter(|value| !value.is_empty())),
+            _ => return Err(PyValueError::new_err(format!("Invalid response hook action: {}", action)))
+        };
+        Ok(Self { statuses: StatusMatch::parse(statuses)?, action })
+    }
+}
+
+// Hooks applied to the responses by their status, either a code like `502` or a
+// class like `5xx`, whether the response comes from the application or from the
+// server itself. Matching hooks can add headers, log an event, or replace the body,
+// like serving a maintenance page in place of the `502` responses of the application.
+#[derive(Clone, Default)]
+pub(crate) struct StatusHooks {
+    hooks: Arc<Vec<Hook>>
+}
+
+impl StatusHooks {
+    // (statuses, action, value)
+    pub fn new(hooks: Vec<(String, String, String)>) -> PyResult<Self> {
+        let hooks = hooks.iter()
+            .map(|(statuses, action, value)| Hook::new(statuses, action, value))
+            .collect::<PyResult<_>>()?;
+        Ok(Self { hooks: Arc::new(hooks) })
+    }
+
+    // Keeps what the hooks need from the request, which is gone once the response is ready
+    pub fn request(&self, req: &Request<Body>, client_addr: SocketAddr, scheme: &'static str) -> Option<HookedRequest> {
+        if self.hooks.is_empty() {
+            return None
+        }
+        Some(HookedRequest {
+            hooks: self.hooks.clone(),
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            host: req.headers().get(HOST).cloned(),
+            client_addr,
+            scheme
+        })
+    }
+}
+
+pub(crate) struct HookedRequest {
+    hooks: Arc<Vec<Hook>>,
+    method: Method,
+    uri: Uri,
+    host: Option<HeaderValue>,
+    client_addr: SocketAddr,
+    scheme: &'static str
+}
+
+impl HookedRequest {
+    pub fn respond(self, res: Response<Body>) -> Response<Body> {
+        let status = res.status().as_u16();
+        if !self.hooks.iter().any(|hook| hook.statuses.matches(status)) {
+            return res
+        }
+        let (mut parts, mut body) = res.into_parts();
+        let matched = self.hooks.iter().filter(|hook| hook.statuses.matches(status));
+
+        // the first body hook wins, and the replaced body takes its representation headers along
+        let template = matched.clone().find_map(|hook| match &hook.action {
+            Action::Body(template) => Some(template),
+            _ => None
+        });
+        if let Some(template) = template {
+            for name in [CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED] {
+                parts.headers.remove(name);
+            }
+            let content_type = matched.clone().find_map(|hook| match &hook.action {
+                Action::Header(name, value) if name == CONTENT_TYPE => Some(value),
+                _ => None
+            });
+            body = Body::from(template.render(
+                &self.method,
+                &self.uri,
+                self.host.as_ref(),
+                self.client_addr,
+                self.scheme,
+                content_type
+            ));
+        }
+        for hook in matched {
+            match &hook.action {
+                Action::Header(name, value) if !parts.headers.contains_key(name) => {
+                    parts.headers.insert(name.clone(), value.clone());
+                },
+                Action::Log(message) => log::warn!(
+                    "{} ({} response to {} {})",
+                    message.as_deref().unwrap_or("Response hook"),
+                    status,
+                    self.method,
+                    self.uri.path()
+                ),
+                _ => {}
+            }
+        }
+        if template.is_some() && !parts.headers.contains_key(CONTENT_TYPE) {
+            parts.headers.insert(CONTENT_TYPE, HV_TEXT);
+        }
+        Response::from_parts(parts, body)
+    }
+}
@@ -1,10 +1,640 @@
-use hyper::{Body, Response, header::{HeaderValue, SERVER as HK_SERVER}};
+use bytes::{Bytes, BytesMut};
+use futures::Stream;
+use hyper::{
+    Body,
+    HeaderMap,
+    Request,
+    Response,
+    StatusCode,
+    body::HttpBody,
+    ext::ReasonPhrase,
+    header::{CONTENT_LENGTH, COOKIE, HOST, HeaderName, HeaderValue, SERVER as HK_SERVER}
+};
+use pyo3::{exceptions::{PyValueError, asyncio::CancelledError}, prelude::*};
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll}
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    diagnostics::RequestTrace,
+    errors::{ProtocolViolationError, ResponseStateError},
+    metrics::METRICS,
+    negotiation::ServerError,
+    utils::header_value_latin1
+};
 
 pub(crate) const HV_SERVER: HeaderValue = HeaderValue::from_static("granian");
 
 pub(crate) fn response_500() -> Response<Body> {
-    let mut builder = Response::builder().status(500);
+    response_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error", None)
+}
+
+pub(crate) fn response_504() -> Response<Body> {
+    response_error(StatusCode::GATEWAY_TIMEOUT, "Gateway timeout", None)
+}
+
+// Errors produced by the server on its own: `body` is the plain text version,
+// while the detail is also carried over in the other formats.
+pub(crate) fn response_error(
+    status: StatusCode,
+    body: impl Into<Body>,
+    detail: Option<String>
+) -> Response<Body> {
+    let mut builder = Response::builder().status(status);
     let headers = builder.headers_mut().unwrap();
     headers.insert(HK_SERVER, HV_SERVER);
-    builder.body(Body::from("Internal server error")).unwrap()
+    builder.extensions_mut().unwrap().insert(ServerError { detail });
+    builder.body(body.into()).unwrap()
+}
+
+// Statuses sent by applications: any three digits code is accepted, including
+// the ones not registered like 499 or 599, but the informational ones: those
+// can't end a request, and get rejected here rather than being left to the
+// protocol layers, as HTTP/1 and HTTP/2 deal with them in different ways.
+pub(crate) fn response_status(status: i32) -> PyResult<StatusCode> {
+    let status = u16::try_from(status).ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid response status {}", status)))?;
+    if status.is_informational() {
+        return Err(PyValueError::new_err(format!(
+            "Informational status {} can't be sent as a final response", status.as_u16()
+        )))
+    }
+    Ok(status)
+}
+
+// Custom reason phrases replace the canonical ones on HTTP/1, as HTTP/2 has none
+pub(crate) fn reason_phrase(reason: &str) -> PyResult<ReasonPhrase> {
+    ReasonPhrase::try_from(reason.as_bytes())
+        .map_err(|_| PyValueError::new_err(format!("Invalid response reason phrase {:?}", reason)))
+}
+
+// Unregistered statuses sent without a reason phrase get an empty one, which
+// HTTP/1 allows, instead of the `<none>` placeholder hyper would write.
+pub(crate) fn reason_fallback(mut res: Response<Body>) -> Response<Body> {
+    if res.status().canonical_reason().is_none() && res.extensions().get::<ReasonPhrase>().is_none() {
+        res.extensions_mut().insert(ReasonPhrase::from_static(b""));
+    }
+    res
+}
+
+// The request body length, when known upfront: `None` for chunked requests,
+// and HTTP/2 ones without a `content-length` header.
+pub(crate) fn content_length(body: &Body) -> Option<u64> {
+    HttpBody::size_hint(body).exact()
+}
+
+// Request bodies wrapped while being received lose their size hint, while the
+// `content-length` header, validated by hyper, still tells it.
+pub(crate) fn request_content_length(req: &Request<Body>) -> Option<u64> {
+    content_length(req.body()).or_else(|| {
+        req.headers().get(CONTENT_LENGTH).and_then(|value| value.to_str().ok()?.parse().ok())
+    })
+}
+
+// Reads the whole request body, also consuming trailers sent after chunked data.
+pub(crate) async fn read_body(body: &mut Body) -> hyper::Result<Bytes> {
+    let data = hyper::body::to_bytes(&mut *body).await?;
+    body.trailers().await?;
+    Ok(data)
+}
+
+// Static headers sent with every response, encoded once per worker, like the
+// Server one. Headers already set by the application take precedence.
+#[derive(Clone)]
+pub(crate) struct ResponseHeaders {
+    block: Arc<Vec<(HeaderName, HeaderValue)>>
+}
+
+impl ResponseHeaders {
+    pub fn new() -> Self {
+        Self { block: Arc::new(vec![(HK_SERVER, HV_SERVER)]) }
+    }
+
+    pub fn apply(&self, mut res: Response<Body>) -> Response<Body> {
+        let headers = res.headers_mut();
+        for (key, value) in self.block.iter() {
+            if !headers.contains_key(key) {
+                headers.insert(key.clone(), value.clone());
+            }
+        }
+        res
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DuplicateMode {
+    Join,
+    First,
+    Reject
+}
+
+// Handling of repeated request headers where interfaces expose a single value per
+// name, like the WSGI environ and the RSGI `get`: values get joined in a list, as
+// RFC 9110 allows, or reduced to the first one. Requests repeating a header in
+// `reject` mode fail instead, as by default for the fields that can't be combined.
+#[derive(Clone)]
+pub(crate) struct DuplicateHeaders {
+    modes: Arc<Vec<(HeaderName, DuplicateMode)>>
+}
+
+impl DuplicateHeaders {
+    pub fn new(rules: Vec<(String, String)>) -> PyResult<Self> {
+        let mut modes = vec![(HOST, DuplicateMode::Reject), (CONTENT_LENGTH, DuplicateMode::Reject)];
+        for (key, mode) in rules {
+            let name = HeaderName::from_bytes(key.as_bytes()).map_err(
+                |_| PyValueError::new_err(format!("Invalid header name: {}", key))
+            )?;
+            let mode = match &mode[..] {
+                "join" => DuplicateMode::Join,
+                "first" => DuplicateMode::First,
+                "reject" => DuplicateMode::Reject,
+                _ => return Err(PyValueError::new_err(format!("Invalid duplicate header mode: {}", mode)))
+            };
+            modes.retain(|(existing, _)| *existing != name);
+            modes.push((name, mode));
+        }
+        Ok(Self { modes: Arc::new(modes) })
+    }
+
+    fn mode(&self, name: &HeaderName) -> DuplicateMode {
+        self.modes.iter()
+            .find(|(existing, _)| existing == name)
+            .map_or(DuplicateMode::Join, |(_, mode)| *mode)
+    }
+
+    pub fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        for (name, mode) in self.modes.iter() {
+            if *mode == DuplicateMode::Reject && req.headers().get_all(name).iter().nth(1).is_some() {
+                let detail = format!("Duplicate {} header", name);
+                return Some(response_error(StatusCode::BAD_REQUEST, detail.clone(), Some(detail)))
+            }
+        }
+        None
+    }
+
+    // The joined value of a repeated header, `None` when the first one should be used
+    pub fn joined(&self, headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+        let mut values = headers.get_all(name).iter();
+        values.next()?;
+        values.next()?;
+        if self.mode(name) != DuplicateMode::Join {
+            return None
+        }
+        let separator = if name == COOKIE { "; " } else { ", " };
+        let values: Vec<_> = headers.get_all(name).iter().map(header_value_latin1).collect();
+        Some(values.join(separator))
+    }
+}
+
+impl Default for DuplicateHeaders {
+    fn default() -> Self {
+        Self::new(Vec::new()).unwrap()
+    }
+}
+
+fn valid_host_name(name: &str) -> bool {
+    match name.strip_prefix('[').and_then(|name| name.strip_suffix(']')) {
+        Some(addr) => addr.parse::<std::net::Ipv6Addr>().is_ok(),
+        None => !name.is_empty() && name.split('.').all(|label| {
+            !label.is_empty() && label.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        })
+    }
+}
+
+// The host name of a `Host` value, lowercased and without port or trailing dot
+fn host_name(value: &str) -> Option<String> {
+    let name = match value.rfind(':') {
+        Some(idx) if !value[idx..].contains(']') => {
+            let port = &value[idx + 1..];
+            if port.is_empty() || !port.bytes().all(|c| c.is_ascii_digit()) {
+                return None
+            }
+            &value[..idx]
+        },
+        _ => value
+    };
+    let name = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    valid_host_name(&name).then_some(name)
+}
+
+// Host names the server answers for, either exact or as wildcards, like
+// `*.example.com`, matching any subdomain but not the domain itself. Requests
+// with a missing, malformed or unknown host get a 400 before reaching the
+// application; both the `Host` header and the request target authority get
+// checked, as applications could be reading either. No entries disable the check.
+#[derive(Clone)]
+pub(crate) struct AllowedHosts {
+    exact: Arc<Vec<String>>,
+    wildcards: Arc<Vec<String>>,
+    any: bool
+}
+
+impl AllowedHosts {
+    pub fn new(hosts: Vec<String>) -> PyResult<Self> {
+        let mut exact = Vec::with_capacity(hosts.len());
+        let mut wildcards = Vec::new();
+        let mut any = hosts.is_empty();
+        for host in hosts {
+            let value = host.trim().to_ascii_lowercase();
+            let value = value.strip_suffix('.').unwrap_or(&value);
+            match value.strip_prefix("*.") {
+                _ if value == "*" => any = true,
+                Some(domain) if valid_host_name(domain) => wildcards.push(format!(".{}", domain)),
+                None if valid_host_name(value) => exact.push(value.to_string()),
+                _ => return Err(PyValueError::new_err(format!("Invalid allowed host: {}", host)))
+            }
+        }
+        Ok(Self { exact: Arc::new(exact), wildcards: Arc::new(wildcards), any })
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        self.exact.iter().any(|host| host == name) || self.wildcards.iter().any(
+            |suffix| name.len() > suffix.len() && name.ends_with(&suffix[..])
+        )
+    }
+
+    pub fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        if self.any {
+            return None
+        }
+        let host = req.headers().get(HOST).map(|value| value.to_str().ok().and_then(host_name));
+        let authority = req.uri().authority().map(|authority| host_name(authority.host()));
+        let valid = match (host, authority) {
+            (None, None) => false,
+            (host, authority) => [host, authority].into_iter().flatten().all(
+                |name| name.is_some_and(|name| self.allows(&name))
+            )
+        };
+        match valid {
+            true => None,
+            false => Some(response_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid host",
+                Some("The request host is not served by this server".to_string())
+            ))
+        }
+    }
+}
+
+impl Default for AllowedHosts {
+    fn default() -> Self {
+        Self::new(Vec::new()).unwrap()
+    }
+}
+
+const H2_WINDOW_MAX: u32 = (1 << 31) - 1;
+
+// HTTP/2 flow-control and concurrency settings. With the adaptive window enabled,
+// windows start from the configured sizes and then follow the measured BDP.
+#[derive(Clone, Copy)]
+pub(crate) struct Http2Settings {
+    pub max_concurrent_streams: Option<u32>,
+    pub stream_window: u32,
+    pub connection_window: u32,
+    pub adaptive_window: bool
+}
+
+impl Http2Settings {
+    pub fn new(
+        max_concurrent_streams: u32,
+        stream_window: u32,
+        connection_window: u32,
+        adaptive_window: bool
+    ) -> PyResult<Self> {
+        if stream_window > H2_WINDOW_MAX || connection_window > H2_WINDOW_MAX {
+            return Err(PyValueError::new_err(format!(
+                "HTTP/2 window sizes cannot exceed {}", H2_WINDOW_MAX
+            )))
+        }
+        Ok(Self {
+            max_concurrent_streams: match max_concurrent_streams {
+                0 => None,
+                value => Some(value)
+            },
+            stream_window,
+            connection_window,
+            adaptive_window
+        })
+    }
+}
+
+// The lifecycle of a response, as driven by the application: it gets started
+// once, then completed by its body, sent in one go or streamed, or by a file.
+// Responses sent with a single call, like RSGI ones and upgrades, go straight
+// from pending to completed. Anything out of order gets refused, leaving the
+// state untouched, with an error telling the state the response was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ResponseState {
+    Pending,
+    Started,
+    Streaming,
+    Completed
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ResponseEvent {
+    Start,
+    Body { more: bool },
+    File,
+    Whole
+}
+
+impl ResponseEvent {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Start => "response start",
+            Self::Body { .. } => "response body",
+            Self::File => "response file",
+            Self::Whole => "response"
+        }
+    }
+}
+
+impl ResponseState {
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Pending => "not started yet, expecting its start",
+            Self::Started => "started, expecting its body or a file",
+            Self::Streaming => "streaming its body, expecting more body",
+            Self::Completed => "already completed"
+        }
+    }
+
+    pub fn next(&self, event: ResponseEvent) -> PyResult<Self> {
+        match (self, event) {
+            (Self::Pending, ResponseEvent::Start) => Ok(Self::Started),
+            (Self::Pending, ResponseEvent::Whole) => Ok(Self::Completed),
+            (Self::Started | Self::Streaming, ResponseEvent::Body { more: true }) => Ok(Self::Streaming),
+            (Self::Started | Self::Streaming, ResponseEvent::Body { more: false }) => Ok(Self::Completed),
+            (Self::Started, ResponseEvent::File) => Ok(Self::Completed),
+            (state, event) => Err(ResponseStateError::new_err(state.unexpected(event)))
+        }
+    }
+
+    fn unexpected(&self, event: ResponseEvent) -> String {
+        format!("Unexpected {}: the response is {}", event.describe(), self.describe())
+    }
+}
+
+// How strictly applications are held to their interface protocol. In strict mode
+// violations fail: values of the wrong type raise a `ProtocolViolationError` telling
+// what was wrong, and events out of order a `ResponseStateError`. The lenient mode
+// coerces them instead, logging a warning, to ease the migration of applications
+// relying on the looser checks of other servers.
+#[derive(Clone, Copy, Default)]
+pub(crate) enum ProtocolConformance {
+    #[default]
+    Strict,
+    Lenient
+}
+
+impl ProtocolConformance {
+    pub fn new(strict: bool) -> Self {
+        match strict {
+            true => Self::Strict,
+            false => Self::Lenient
+        }
+    }
+
+    // Refuses the violation in strict mode, otherwise lets the caller coerce it
+    pub fn violation(&self, message: impl std::fmt::Display) -> PyResult<()> {
+        match self {
+            Self::Strict => Err(ProtocolViolationError::new_err(message.to_string())),
+            Self::Lenient => {
+                log::warn!("{}, coercing it", message);
+                Ok(())
+            }
+        }
+    }
+
+    // The state a response moves to on the event. Out of order events get refused
+    // in strict mode, while the lenient one repeats the start of responses not
+    // sent yet, implies the start of the ones getting their body first, and
+    // ignores the rest, telling so with `None`.
+    pub fn next_state(&self, state: ResponseState, event: ResponseEvent) -> PyResult<Option<ResponseState>> {
+        match (self, state.next(event)) {
+            (_, Ok(next)) => Ok(Some(next)),
+            (Self::Strict, Err(err)) => Err(err),
+            (Self::Lenient, Err(_)) => {
+                let next = match (state, event) {
+                    (ResponseState::Started, ResponseEvent::Start) => Some(ResponseState::Started),
+                    (ResponseState::Pending, ResponseEvent::Body { .. } | ResponseEvent::File) => {
+                        ResponseState::Started.next(event).ok()
+                    },
+                    _ => None
+                };
+                log::warn!(
+                    "{}, {}",
+                    state.unexpected(event),
+                    if next.is_some() { "coercing it" } else { "ignoring it" }
+                );
+                Ok(next)
+            }
+        }
+    }
+}
+
+// How a response the application keeps sending once the client disconnected gets
+// handled: dropped silently, refused with an error raised into the application, or
+// ended with the cancellation of the application coroutine.
+#[derive(Clone, Copy)]
+pub(crate) enum DisconnectPolicy {
+    Discard,
+    Error,
+    Cancel
+}
+
+impl DisconnectPolicy {
+    pub fn new(value: &str) -> PyResult<Self> {
+        match value {
+            "discard" => Ok(Self::Discard),
+            "error" => Ok(Self::Error),
+            "cancel" => Ok(Self::Cancel),
+            _ => Err(PyValueError::new_err(format!("Invalid disconnect policy: {}", value)))
+        }
+    }
+
+    // Outcome of a send to a disconnected client, with `error` producing the
+    // exception of the interface in use
+    pub fn apply<F>(&self, error: F) -> PyResult<()>
+    where F: FnOnce() -> PyErr
+    {
+        match self {
+            Self::Discard => Ok(()),
+            Self::Error => Err(error()),
+            Self::Cancel => Err(CancelledError::new_err("Client disconnected"))
+        }
+    }
+}
+
+// Validation of the response headers set by applications, before they reach the
+// response: names need to be tokens, and values can't carry control bytes but
+// tabs, so that unvalidated data can't split the response with CR or LF bytes.
+// Strict mode fails the responses with invalid headers, while the lenient one
+// strips the invalid bytes from values, and drops the headers with invalid names.
+#[derive(Clone, Copy, Default)]
+pub(crate) enum HeaderValidation {
+    #[default]
+    Strict,
+    Sanitize
+}
+
+impl HeaderValidation {
+    pub fn new(value: &str) -> PyResult<Self> {
+        match value {
+            "strict" => Ok(Self::Strict),
+            "sanitize" => Ok(Self::Sanitize),
+            _ => Err(PyValueError::new_err(format!("Invalid header validation mode: {}", value)))
+        }
+    }
+
+    // The headers to send, or the reason they got refused
+    pub fn headers<'a, I>(&self, headers: I) -> Result<Vec<(HeaderName, HeaderValue)>, String>
+    where I: IntoIterator<Item = (&'a [u8], &'a [u8])>
+    {
+        let headers = headers.into_iter();
+        let mut ret = Vec::with_capacity(headers.size_hint().0);
+        for (key, value) in headers {
+            let name = match (HeaderName::from_bytes(key), self) {
+                (Ok(name), _) => name,
+                (Err(_), Self::Strict) => return Err(format!(
+                    "Invalid response header name: {:?}", String::from_utf8_lossy(key)
+                )),
+                (Err(_), Self::Sanitize) => {
+                    log::warn!("Dropping response header with invalid name {:?}", String::from_utf8_lossy(key));
+                    continue
+                }
+            };
+            let value = match (HeaderValue::from_bytes(value), self) {
+                (Ok(value), _) => value,
+                (Err(_), Self::Strict) => return Err(format!("Invalid value for response header {}", name)),
+                (Err(_), Self::Sanitize) => {
+                    log::warn!("Removing invalid bytes from the value of response header {}", name);
+                    let value: Vec<u8> = value.iter().copied()
+                        .filter(|byte| *byte == b'\t' || (*byte >= 0x20 && *byte != 0x7f))
+                        .collect();
+                    HeaderValue::from_bytes(&value).unwrap()
+                }
+            };
+            ret.push((name, value));
+        }
+        Ok(ret)
+    }
+}
+
+// Upper bound for chunks merged into a single write
+const STREAM_BATCH_MAX: usize = 64 * 1024;
+// Chunks queued before the application has to wait for the client
+const STREAM_QUEUE_SIZE: usize = 8;
+
+#[derive(Clone)]
+pub(crate) struct StreamedBodySender {
+    tx: mpsc::Sender<Bytes>,
+    aborted: Arc<AtomicBool>,
+    trace: RequestTrace
+}
+
+impl StreamedBodySender {
+    // Queues the chunk when there's room for it, otherwise gives it back to be
+    // sent with `send`, waiting for the client. `Ok(false)` tells the client is gone
+    pub fn try_send(&self, chunk: Bytes) -> Result<bool, Bytes> {
+        self.trace.body_queued();
+        match self.tx.try_send(chunk) {
+            Ok(_) => Ok(true),
+            Err(mpsc::error::TrySendError::Full(chunk)) => {
+                self.trace.body_sent();
+                Err(chunk)
+            },
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                self.trace.body_sent();
+                self.trace.client_disconnected();
+                Ok(false)
+            }
+        }
+    }
+
+    // Returns `false` when the client is gone and the chunk got dropped
+    pub async fn send(&self, chunk: Bytes) -> bool {
+        self.trace.body_queued();
+        if self.tx.send(chunk).await.is_err() {
+            self.trace.body_sent();
+            self.trace.client_disconnected();
+            return false
+        }
+        true
+    }
+
+    // The body fails once the chunks already queued are sent
+    pub fn abort(self) {
+        self.aborted.store(true, Ordering::Relaxed);
+    }
+}
+
+// Bodies produced while the response is already being sent. The first chunk is
+// yielded on its own, so it gets flushed right away together with the headers;
+// later chunks queued by the time the connection asks for more data are batched.
+// The queue is bounded, so applications sending faster than the client reads
+// wait for it to catch up rather than buffering the whole body in memory.
+struct StreamedBody {
+    rx: mpsc::Receiver<Bytes>,
+    started: bool,
+    aborted: Arc<AtomicBool>,
+    trace: RequestTrace
+}
+
+impl Stream for StreamedBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunk = match self.rx.poll_recv(cx) {
+            Poll::Ready(Some(chunk)) => chunk,
+            Poll::Ready(None) if self.aborted.swap(false, Ordering::Relaxed) => {
+                return Poll::Ready(Some(Err(
+                    aborted_body(&self.trace, "application ended before the last body message")
+                )))
+            },
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending
+        };
+        self.trace.body_sent();
+        if !self.started {
+            self.started = true;
+            return Poll::Ready(Some(Ok(chunk)))
+        }
+
+        let mut batch: Option<BytesMut> = None;
+        while batch.as_ref().map_or(chunk.len(), |batch| batch.len()) < STREAM_BATCH_MAX {
+            match self.rx.try_recv() {
+                Ok(next) => {
+                    self.trace.body_sent();
+                    batch.get_or_insert_with(|| BytesMut::from(&chunk[..])).extend_from_slice(&next)
+                },
+                Err(_) => break
+            }
+        }
+        Poll::Ready(Some(Ok(batch.map_or(chunk, |batch| batch.freeze()))))
+    }
+}
+
+// The error ending the body of responses failed after their head got sent: hyper
+// closes HTTP/1 connections without writing the last chunk, and resets HTTP/2
+// streams with `INTERNAL_ERROR`, so clients can tell the response is incomplete.
+pub(crate) fn aborted_body(trace: &RequestTrace, reason: &str) -> io::Error {
+    METRICS.responses_aborted.inc();
+    trace.log_abort(reason);
+    io::Error::other(reason.to_string())
+}
+
+pub(crate) fn streamed_body(trace: RequestTrace) -> (StreamedBodySender, Body) {
+    let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+    let aborted = Arc::new(AtomicBool::new(false));
+    (
+        StreamedBodySender { tx, aborted: aborted.clone(), trace: trace.clone() },
+        Body::wrap_stream(StreamedBody { rx, started: false, aborted, trace })
+    )
 }
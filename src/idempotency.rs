@@ -0,0 +1,256 @@
+use bytes::Bytes;
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    body::HttpBody,
+    header::{AUTHORIZATION, CONTENT_LENGTH, COOKIE, HeaderMap, HeaderName, HeaderValue, SET_COOKIE}
+};
+use ring::digest;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+use tokio::sync::watch;
+
+use crate::{clock, errors::Error, http::response_error};
+
+
+const HK_IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+const HK_IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+const HV_TRUE: HeaderValue = HeaderValue::from_static("true");
+
+// Bodies are buffered to be fingerprinted and replayed, so only the
+// ones with a known length up to this size take part in the cache.
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+type Digest = [u8; 32];
+
+fn sha256(parts: &[&[u8]]) -> Digest {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        ctx.update(&(part.len() as u64).to_be_bytes());
+        ctx.update(part);
+    }
+    let mut rv = [0; 32];
+    rv.copy_from_slice(ctx.finish().as_ref());
+    rv
+}
+
+fn cacheable_length(headers: &HeaderMap, body: &Body) -> bool {
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .or_else(|| body.size_hint().exact());
+    matches!(length, Some(length) if length <= MAX_BODY_SIZE)
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    fingerprint: Digest,
+    expires_at: Instant
+}
+
+impl CachedResponse {
+    fn replay(&self) -> Response<Body> {
+        let mut res = Response::new(Body::from(self.body.clone()));
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        res.headers_mut().insert(HK_IDEMPOTENT_REPLAYED, HV_TRUE);
+        res
+    }
+}
+
+enum Entry {
+    Pending(Digest, watch::Receiver<Option<Arc<CachedResponse>>>),
+    Ready(Arc<CachedResponse>)
+}
+
+struct State {
+    entries: HashMap<Digest, Entry>,
+    purged_at: Instant
+}
+
+struct CacheInner {
+    ttl: Duration,
+    state: Mutex<State>
+}
+
+impl CacheInner {
+    fn purge(&self, state: &mut State, now: Instant) {
+        if now.duration_since(state.purged_at) < self.ttl {
+            return
+        }
+        state.entries.retain(|_, entry| match entry {
+            Entry::Ready(cached) => cached.expires_at > now,
+            Entry::Pending(..) => true
+        });
+        state.purged_at = now;
+    }
+}
+
+enum Action {
+    Mismatch,
+    Replay(Arc<CachedResponse>),
+    Wait(watch::Receiver<Option<Arc<CachedResponse>>>),
+    Lead(watch::Sender<Option<Arc<CachedResponse>>>)
+}
+
+// Drops the pending entry if the leading request never completes,
+// so that waiting retries can take over.
+struct PendingGuard<'a> {
+    inner: &'a CacheInner,
+    key: &'a Digest,
+    armed: bool
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            let mut state = self.inner.state.lock().unwrap();
+            if let Some(Entry::Pending(..)) = state.entries.get(self.key) {
+                state.entries.remove(self.key);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct IdempotencyCache {
+    inner: Option<Arc<CacheInner>>
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: u64) -> Self {
+        let inner = match ttl {
+            0 => None,
+            ttl => Some(Arc::new(CacheInner {
+                ttl: Duration::from_secs(ttl),
+                state: Mutex::new(State {
+                    entries: HashMap::new(),
+                    purged_at: clock::now()
+                })
+            }))
+        };
+        Self { inner }
+    }
+
+    // Keys are scoped to the caller, identified by its credentials when
+    // present or by its address otherwise, so clients can't replay each other.
+    fn key_for(req: &Request<Body>, client_addr: SocketAddr) -> Option<Digest> {
+        match *req.method() {
+            Method::POST | Method::PUT => {},
+            _ => return None
+        }
+        if !cacheable_length(req.headers(), req.body()) {
+            return None
+        }
+        let value = req.headers().get(HK_IDEMPOTENCY_KEY)?;
+        let client_ip = client_addr.ip().to_string();
+        let identity = req.headers()
+            .get(AUTHORIZATION)
+            .or_else(|| req.headers().get(COOKIE))
+            .map_or(client_ip.as_bytes(), |value| value.as_bytes());
+        Some(sha256(&[
+            req.method().as_str().as_bytes(),
+            req.uri().path().as_bytes(),
+            value.as_bytes(),
+            identity
+        ]))
+    }
+
+    fn action_for(inner: &CacheInner, key: &Digest, fingerprint: &Digest) -> Action {
+        let now = clock::now();
+        let mut state = inner.state.lock().unwrap();
+        inner.purge(&mut state, now);
+        match state.entries.get(key) {
+            Some(Entry::Ready(cached)) if cached.expires_at > now => {
+                if &cached.fingerprint != fingerprint {
+                    return Action::Mismatch
+                }
+                return Action::Replay(cached.clone())
+            },
+            Some(Entry::Pending(pending, _)) if pending != fingerprint => return Action::Mismatch,
+            Some(Entry::Pending(_, rx)) => return Action::Wait(rx.clone()),
+            _ => {}
+        }
+        let (tx, rx) = watch::channel(None);
+        state.entries.insert(*key, Entry::Pending(*fingerprint, rx));
+        Action::Lead(tx)
+    }
+
+    pub async fn handle<F, Fut>(&self, req: Request<Body>, client_addr: SocketAddr, handler: F) -> Response<Body>
+    where
+        F: FnOnce(Request<Body>) -> Fut,
+        Fut: Future<Output=Response<Body>>
+    {
+        let (inner, key) = match (&self.inner, Self::key_for(&req, client_addr)) {
+            (Some(inner), Some(key)) => (inner, key),
+            _ => return handler(req).await
+        };
+        let (parts, body) = req.into_parts();
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(err) => return Error::protocol(format!("Unable to buffer request: {}", err)).response()
+        };
+        let fingerprint = sha256(&[&body]);
+        let req = Request::from_parts(parts, Body::from(body));
+
+        loop {
+            match Self::action_for(inner, &key, &fingerprint) {
+                Action::Mismatch => return response_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "",
+                    Some("Idempotency-Key reused with a different request body".to_string())
+                ),
+                Action::Replay(cached) => return cached.replay(),
+                Action::Wait(mut rx) => {
+                    loop {
+                        if let Some(cached) = rx.borrow().clone() {
+                            return cached.replay()
+                        }
+                        if rx.changed().await.is_err() {
+                            break
+                        }
+                    }
+                },
+                Action::Lead(tx) => {
+                    let mut guard = PendingGuard { inner, key: &key, armed: true };
+                    let (parts, body) = handler(req).await.into_parts();
+                    // server errors are worth a retry, so they don't get cached
+                    if parts.status.is_server_error() || !cacheable_length(&parts.headers, &body) {
+                        return Response::from_parts(parts, body)
+                    }
+                    let body = match hyper::body::to_bytes(body).await {
+                        Ok(body) => body,
+                        Err(err) => return Error::protocol(format!("Unable to buffer response: {}", err)).response()
+                    };
+                    // cookies belong to the session of the original response only
+                    let mut headers = parts.headers.clone();
+                    headers.remove(SET_COOKIE);
+                    let cached = Arc::new(CachedResponse {
+                        status: parts.status,
+                        headers,
+                        body: body.clone(),
+                        fingerprint,
+                        expires_at: clock::now() + inner.ttl
+                    });
+                    inner.state.lock().unwrap().entries.insert(
+                        key, Entry::Ready(cached.clone())
+                    );
+                    guard.armed = false;
+                    let _ = tx.send(Some(cached));
+                    return Response::from_parts(parts, Body::from(body))
+                }
+            }
+        }
+    }
+}
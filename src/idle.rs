@@ -0,0 +1,183 @@
+use hyper::server::accept::Accept;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    io,
+    net::TcpListener,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll},
+    time::{Duration, Instant}
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    clock,
+    keepalive::{ConnectionKeepAlive, KeepAlive, KeepAliveRequests},
+    proxy::{ProxyProtocol, ProxyStream},
+    tcp::Incoming,
+    timeouts::{ConnectionTimeouts, Exchange, RequestTimeouts},
+    wheel::Timer
+};
+
+
+// Connections not sending a whole request head within `timeout` from the accept,
+// like the half-open ones left by port scanners, get closed to free their
+// descriptors. Once a request got received the connection is never timed out
+// by this: idling between keep-alive requests is left to `KeepAlive`.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct IdleTimeout(Option<Duration>);
+
+impl IdleTimeout {
+    pub fn new(timeout: f64) -> PyResult<Self> {
+        if timeout.is_nan() || timeout < 0.0 {
+            return Err(PyValueError::new_err("Connection idle timeout should not be negative"))
+        }
+        Ok(Self((timeout > 0.0).then(|| Duration::from_secs_f64(timeout))))
+    }
+
+    pub fn wrap<S>(&self, inner: S, request_timeouts: RequestTimeouts, keep_alive: KeepAlive) -> IdleStream<S> {
+        IdleStream {
+            inner,
+            deadline: self.0.map(|timeout| clock::now() + timeout),
+            timer: Timer::default(),
+            request_seen: RequestSeen::default(),
+            request_timeouts: request_timeouts.connection(),
+            keep_alive: keep_alive.connection()
+        }
+    }
+}
+
+// Set by the service as soon as the connection delivers a request
+#[derive(Clone, Default)]
+pub(crate) struct RequestSeen(Arc<AtomicBool>);
+
+impl RequestSeen {
+    pub fn mark(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// The deadline is only checked when reading, as connections waiting for their
+// first request are always waiting for data to read.
+pub(crate) struct IdleStream<S> {
+    inner: S,
+    deadline: Option<Instant>,
+    timer: Timer,
+    request_seen: RequestSeen,
+    request_timeouts: ConnectionTimeouts,
+    keep_alive: ConnectionKeepAlive
+}
+
+impl<S> IdleStream<S> {
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn request_seen(&self) -> RequestSeen {
+        self.request_seen.clone()
+    }
+
+    pub fn exchange(&self) -> Exchange {
+        self.request_timeouts.exchange()
+    }
+
+    pub fn keep_alive(&self) -> KeepAliveRequests {
+        self.keep_alive.requests()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for IdleStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(deadline) = this.deadline {
+            if this.request_seen.get() {
+                this.deadline = None;
+                this.timer.clear();
+            } else if this.timer.poll_at(deadline, cx).is_ready() {
+                log::debug!("Closing connection idle since being accepted");
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut, "no request received within the idle timeout"
+                )))
+            }
+        }
+        let filled = buf.filled().len();
+        let started = this.keep_alive.io_start();
+        match this.request_timeouts.poll_read(&mut this.inner, cx, buf) {
+            // reading nothing tells hyper the connection got closed
+            Poll::Pending => this.keep_alive.poll_idle(cx).map(Ok),
+            ret => {
+                if buf.filled().len() > filled {
+                    this.keep_alive.active(started);
+                }
+                ret
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<io::Result<usize>> {
+        let started = self.keep_alive.io_start();
+        let ret = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(size)) = ret {
+            if size > 0 {
+                self.keep_alive.active(started);
+            }
+        }
+        ret
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+pub(crate) struct IdleIncoming {
+    inner: Incoming,
+    timeout: IdleTimeout,
+    request_timeouts: RequestTimeouts,
+    keep_alive: KeepAlive,
+    proxy_protocol: ProxyProtocol
+}
+
+impl Accept for IdleIncoming {
+    type Conn = IdleStream<ProxyStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let (timeout, request_timeouts, keep_alive) = (self.timeout, self.request_timeouts, self.keep_alive);
+        let proxy_protocol = self.proxy_protocol;
+        Pin::new(&mut self.inner).poll_accept(cx).map(|conn| conn.map(|conn| conn.map(|stream| {
+            timeout.wrap(proxy_protocol.wrap(stream), request_timeouts, keep_alive)
+        })))
+    }
+}
+
+pub(crate) fn listen(
+    tcp: TcpListener,
+    timeout: IdleTimeout,
+    request_timeouts: RequestTimeouts,
+    keep_alive: KeepAlive,
+    proxy_protocol: ProxyProtocol
+) -> io::Result<IdleIncoming> {
+    Ok(IdleIncoming { inner: Incoming::new(tcp)?, timeout, request_timeouts, keep_alive, proxy_protocol })
+}
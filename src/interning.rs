@@ -0,0 +1,83 @@
+use hyper::header::{HeaderName, HeaderValue};
+use once_cell::sync::Lazy;
+use pyo3::{prelude::*, types::{PyBytes, PyString}};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::utils::header_value_latin1;
+
+
+// Caches are only grown up to a fixed size, so that arbitrary client values
+// can't make them unbounded; after that, new objects are just built per call.
+const MAX_ENTRIES: usize = 1024;
+const MAX_VALUE_LEN: usize = 128;
+
+// Headers with a small set of values repeated across most requests
+const INTERNED_VALUES: [&str; 8] = [
+    "accept",
+    "accept-encoding",
+    "accept-language",
+    "cache-control",
+    "connection",
+    "content-type",
+    "sec-fetch-mode",
+    "upgrade"
+];
+
+static STRINGS: Lazy<Mutex<HashMap<String, Py<PyString>>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+static BYTES: Lazy<Mutex<HashMap<Vec<u8>, Py<PyBytes>>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+pub(crate) fn intern_str<'p>(py: Python<'p>, value: &str) -> &'p PyString {
+    let mut cache = STRINGS.lock().unwrap();
+    if let Some(obj) = cache.get(value) {
+        return obj.clone_ref(py).into_ref(py)
+    }
+    let obj = PyString::new(py, value);
+    if cache.len() < MAX_ENTRIES && value.len() <= MAX_VALUE_LEN {
+        cache.insert(value.to_string(), obj.into());
+    }
+    obj
+}
+
+pub(crate) fn intern_bytes<'p>(py: Python<'p>, value: &[u8]) -> &'p PyBytes {
+    let mut cache = BYTES.lock().unwrap();
+    if let Some(obj) = cache.get(value) {
+        return obj.clone_ref(py).into_ref(py)
+    }
+    let obj = PyBytes::new(py, value);
+    if cache.len() < MAX_ENTRIES && value.len() <= MAX_VALUE_LEN {
+        cache.insert(value.to_vec(), obj.into());
+    }
+    obj
+}
+
+#[inline]
+fn interns_value(name: &HeaderName) -> bool {
+    INTERNED_VALUES.contains(&name.as_str())
+}
+
+pub(crate) fn header_value_str<'p>(
+    py: Python<'p>,
+    name: &HeaderName,
+    value: &HeaderValue
+) -> &'p PyString {
+    let value = header_value_latin1(value);
+    match interns_value(name) {
+        true => intern_str(py, &value),
+        false => PyString::new(py, &value)
+    }
+}
+
+pub(crate) fn header_value_bytes<'p>(
+    py: Python<'p>,
+    name: &HeaderName,
+    value: &HeaderValue
+) -> &'p PyBytes {
+    match interns_value(name) {
+        true => intern_bytes(py, value.as_bytes()),
+        false => PyBytes::new(py, value.as_bytes())
+    }
+}
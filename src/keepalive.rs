@@ -0,0 +1,233 @@
+use futures::{stream::Stream, task::AtomicWaker};
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    Version,
+    body::{Bytes, HttpBody},
+    header::{CONNECTION, HeaderMap, HeaderValue}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant}
+};
+
+use crate::{clock, wheel::Timer};
+
+
+const HV_CLOSE: HeaderValue = HeaderValue::from_static("close");
+const KEEP_ALIVE: &str = "keep-alive";
+
+// Keep-alive connections get closed when idling for more than `timeout` since
+// their last response, or once they served `max_requests`, their last response
+// telling the client so with `Connection: close`. The limits can also be
+// advertised with the `Keep-Alive` header. Only HTTP/1 connections are managed,
+// HTTP/2 and upgraded ones are left alone.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct KeepAlive {
+    timeout: Option<Duration>,
+    max_requests: Option<u64>,
+    header: bool
+}
+
+impl KeepAlive {
+    pub fn new(timeout: f64, max_requests: u64, header: bool) -> PyResult<Self> {
+        if timeout.is_nan() || timeout < 0.0 {
+            return Err(PyValueError::new_err("Keep-alive timeout should not be negative"))
+        }
+        Ok(Self {
+            timeout: (timeout > 0.0).then(|| Duration::from_secs_f64(timeout)),
+            max_requests: (max_requests > 0).then_some(max_requests),
+            header
+        })
+    }
+
+    pub fn connection(&self) -> ConnectionKeepAlive {
+        ConnectionKeepAlive { keep_alive: *self, state: Arc::default(), active: clock::now(), timer: Timer::default() }
+    }
+
+    fn header_value(&self, served: u64) -> Option<HeaderValue> {
+        let mut params = Vec::with_capacity(2);
+        if let Some(timeout) = self.timeout {
+            params.push(format!("timeout={}", timeout.as_secs().max(1)));
+        }
+        if let Some(max_requests) = self.max_requests {
+            params.push(format!("max={}", max_requests - served));
+        }
+        match params.is_empty() {
+            true => None,
+            false => HeaderValue::try_from(params.join(", ")).ok()
+        }
+    }
+}
+
+#[derive(Default)]
+struct KeepAliveState {
+    requests: AtomicU64,
+    in_flight: AtomicUsize,
+    // set once the connection turns out not to be managed
+    released: AtomicBool,
+    // the stream waiting for the requests in flight to end, as hyper doesn't
+    // read again a connection it found blocked until woken
+    idle: AtomicWaker
+}
+
+// The stream side, timing the connection from its last activity
+pub(crate) struct ConnectionKeepAlive {
+    keep_alive: KeepAlive,
+    state: Arc<KeepAliveState>,
+    active: Instant,
+    timer: Timer
+}
+
+impl ConnectionKeepAlive {
+    pub fn requests(&self) -> KeepAliveRequests {
+        KeepAliveRequests { keep_alive: self.keep_alive, state: self.state.clone() }
+    }
+
+    // The time reads and writes start at, when timed: the connection counts as
+    // active from then, as a peer might get the data and go on before they return
+    pub fn io_start(&self) -> Option<Instant> {
+        self.keep_alive.timeout.map(|_| clock::now())
+    }
+
+    pub fn active(&mut self, since: Option<Instant>) {
+        if let Some(since) = since {
+            self.active = since;
+        }
+    }
+
+    // Ready once the connection idled for too long, while waiting to read
+    pub fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let timeout = match self.keep_alive.timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending
+        };
+        self.state.idle.register(cx.waker());
+        let idle = !self.state.released.load(Ordering::Relaxed)
+            && self.state.in_flight.load(Ordering::Acquire) == 0
+            && self.state.requests.load(Ordering::Relaxed) > 0;
+        if !idle {
+            self.timer.clear();
+            return Poll::Pending
+        }
+        ready!(self.timer.poll_at(self.active + timeout, cx));
+        log::debug!("Closing keep-alive connection idle since its last response");
+        Poll::Ready(())
+    }
+}
+
+// The service side, following the requests served by the connection
+#[derive(Clone)]
+pub(crate) struct KeepAliveRequests {
+    keep_alive: KeepAlive,
+    state: Arc<KeepAliveState>
+}
+
+impl KeepAliveRequests {
+    pub fn start(&self, req: &Request<Body>) -> InFlight {
+        if req.version() >= Version::HTTP_2 {
+            self.state.released.store(true, Ordering::Relaxed);
+            return InFlight { guard: InFlightGuard(None), keep_alive: self.keep_alive, served: 0, persistent: false }
+        }
+        let served = self.state.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        self.state.in_flight.fetch_add(1, Ordering::AcqRel);
+        InFlight {
+            guard: InFlightGuard(Some(self.state.clone())),
+            keep_alive: self.keep_alive,
+            served,
+            persistent: persistent(req.version(), req.headers())
+        }
+    }
+}
+
+// A request being served, until its response body got sent
+pub(crate) struct InFlight {
+    guard: InFlightGuard,
+    keep_alive: KeepAlive,
+    served: u64,
+    persistent: bool
+}
+
+impl InFlight {
+    // Connections of draining workers get closed after their current response
+    pub fn respond(self, mut res: Response<Body>, draining: bool) -> Response<Body> {
+        let state = match &self.guard.0 {
+            Some(state) => state,
+            None => return res
+        };
+        if res.status() == StatusCode::SWITCHING_PROTOCOLS {
+            state.released.store(true, Ordering::Relaxed);
+            return res
+        }
+        if self.persistent && !has_token(res.headers(), "close") {
+            match self.keep_alive.max_requests {
+                _ if draining => {
+                    log::debug!("Closing keep-alive connection of a draining worker");
+                    res.headers_mut().insert(CONNECTION, HV_CLOSE);
+                },
+                Some(max_requests) if self.served >= max_requests => {
+                    log::debug!("Closing keep-alive connection after serving {} requests", self.served);
+                    res.headers_mut().insert(CONNECTION, HV_CLOSE);
+                },
+                _ if self.keep_alive.header => {
+                    if let Some(value) = self.keep_alive.header_value(self.served) {
+                        res.headers_mut().insert(KEEP_ALIVE, value);
+                    }
+                },
+                _ => {}
+            }
+        }
+        // sized bodies get written at once, streamed ones are followed to their end
+        if HttpBody::size_hint(res.body()).exact().is_some() {
+            return res
+        }
+        let (parts, body) = res.into_parts();
+        Response::from_parts(parts, Body::wrap_stream(InFlightBody { inner: body, _guard: self.guard }))
+    }
+}
+
+struct InFlightGuard(Option<Arc<KeepAliveState>>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(state) = self.0.take() {
+            if state.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+                state.idle.wake();
+            }
+        }
+    }
+}
+
+struct InFlightBody {
+    inner: Body,
+    _guard: InFlightGuard
+}
+
+impl Stream for InFlightBody {
+    type Item = hyper::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    headers.get_all(CONNECTION).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+// HTTP/1.0 clients need to ask for keep-alive, HTTP/1.1 ones to opt out of it
+fn persistent(version: Version, headers: &HeaderMap) -> bool {
+    match version {
+        Version::HTTP_11 => !has_token(headers, "close"),
+        Version::HTTP_10 => has_token(headers, KEEP_ALIVE),
+        _ => false
+    }
+}
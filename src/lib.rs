@@ -1,26 +1,75 @@
-#[cfg(not(all(target_os="linux", target_arch="aarch64")))]
+#[cfg(all(feature="extension-module", not(all(target_os="linux", target_arch="aarch64"))))]
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use pyo3::prelude::*;
 
+mod access;
 mod asgi;
+mod backlog;
+mod buffers;
 mod callbacks;
+mod cancellation;
+mod cgroups;
+mod clock;
+mod compression;
+mod deadlines;
+mod decompression;
+mod deflate;
+mod diagnostics;
+mod drain;
+pub mod errors;
+mod exposition;
+mod files;
+mod forwarded;
+mod grpc;
+mod hooks;
+mod filters;
 mod http;
+mod idempotency;
+mod idle;
+mod interning;
+mod keepalive;
+mod limits;
+mod longpoll;
+mod logging;
+mod metrics;
+mod negotiation;
+mod otel;
+mod presign;
+mod proxy;
 mod rsgi;
 mod runtime;
+mod scratch;
+pub mod server;
+mod slo;
+mod stacks;
+mod synthetic;
+mod testing;
 mod tls;
 mod tcp;
+mod timeouts;
+mod transcoding;
+mod uploads;
+mod urls;
 mod utils;
+mod wheel;
 mod workers;
 mod ws;
 mod wsgi;
+mod x509;
 
 #[pymodule]
 fn _granian(py: Python, module: &PyModule) -> PyResult<()> {
-    asgi::init_pymodule(module)?;
+    errors::init_pymodule(py, module)?;
+    logging::init_pymodule(module)?;
+    asgi::init_pymodule(py, module)?;
+    cgroups::init_pymodule(module)?;
+    metrics::init_pymodule(module)?;
     rsgi::init_pymodule(py, module)?;
     tcp::init_pymodule(module)?;
+    testing::init_pymodule(module)?;
+    tls::init_pymodule(module)?;
     workers::init_pymodule(module)?;
     wsgi::init_pymodule(py, module)?;
 
@@ -0,0 +1,164 @@
+use bytes::Bytes;
+use futures::stream::Stream;
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    header::{CONNECTION, HeaderValue}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll}
+};
+
+use crate::http::{request_content_length, response_error};
+
+
+// The most headers hyper parses in a request head, larger ones get a `431`
+const HEADERS_MAX: usize = 100;
+// Accounts for the separator and line ending of every header field
+const HEADER_OVERHEAD: usize = 4;
+
+// Maximum size of the request bodies, checked before any of their bytes reach
+// the application: requests declaring a larger `Content-Length` get rejected
+// straight away, while streamed bodies get aborted as soon as they cross it.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct BodyLimit(Option<u64>);
+
+impl BodyLimit {
+    pub fn new(max_size: u64) -> Self {
+        Self((max_size > 0).then_some(max_size))
+    }
+
+    pub fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let max_size = self.0?;
+        match request_content_length(req) {
+            Some(size) if size > max_size => {
+                log::debug!("Rejecting request with a body larger than {} bytes", max_size);
+                Some(too_large())
+            },
+            _ => None
+        }
+    }
+
+    // Sized bodies are known to stay within their length, as hyper checks it
+    pub fn apply(&self, req: Request<Body>) -> (Request<Body>, BodyLimited) {
+        let max_size = match self.0 {
+            Some(max_size) if request_content_length(&req).is_none() => max_size,
+            _ => return (req, BodyLimited(None))
+        };
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let (parts, body) = req.into_parts();
+        let body = LimitedBody { inner: body, remaining: max_size, exceeded: exceeded.clone() };
+        (Request::from_parts(parts, Body::wrap_stream(body)), BodyLimited(Some(exceeded)))
+    }
+}
+
+// Responses of requests whose streamed body got aborted get replaced, as the
+// application only saw the body failing.
+pub(crate) struct BodyLimited(Option<Arc<AtomicBool>>);
+
+impl BodyLimited {
+    pub fn check(&self) -> Option<Response<Body>> {
+        match &self.0 {
+            Some(exceeded) if exceeded.load(Ordering::Relaxed) => Some(too_large()),
+            _ => None
+        }
+    }
+
+    pub fn respond(&self, res: Response<Body>) -> Response<Body> {
+        self.check().unwrap_or(res)
+    }
+}
+
+struct LimitedBody {
+    inner: Body,
+    remaining: u64,
+    exceeded: Arc<AtomicBool>
+}
+
+impl Stream for LimitedBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let chunk = match futures::ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            Some(Ok(chunk)) => chunk,
+            ret => return Poll::Ready(ret.map(|chunk| chunk.map_err(io::Error::other)))
+        };
+        match self.remaining.checked_sub(chunk.len() as u64) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Poll::Ready(Some(Ok(chunk)))
+            },
+            None => {
+                log::debug!("Aborting request with a streamed body over the maximum size");
+                self.exceeded.store(true, Ordering::Relaxed);
+                // dropping the rest tells hyper not to wait for it
+                self.inner = Body::empty();
+                Poll::Ready(Some(Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"))))
+            }
+        }
+    }
+}
+
+// Limits of the request heads, checked before converting them into the
+// application scope: the total size of the header fields and their number get
+// a `431` response when exceeded, the target URI length a `414` one.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct HeaderLimits {
+    max_size: Option<usize>,
+    max_count: Option<usize>,
+    max_uri_length: Option<usize>
+}
+
+impl HeaderLimits {
+    pub fn new(max_size: usize, max_count: usize, max_uri_length: usize) -> PyResult<Self> {
+        if max_count > HEADERS_MAX {
+            return Err(PyValueError::new_err(format!("Maximum header count cannot exceed {}", HEADERS_MAX)))
+        }
+        Ok(Self {
+            max_size: (max_size > 0).then_some(max_size),
+            max_count: (max_count > 0).then_some(max_count),
+            max_uri_length: (max_uri_length > 0).then_some(max_uri_length)
+        })
+    }
+
+    pub fn check<B>(&self, req: &Request<B>) -> Option<Response<Body>> {
+        let headers = req.headers();
+        if self.max_count.is_some_and(|max_count| headers.len() > max_count) {
+            log::debug!("Rejecting request with {} headers", headers.len());
+            return Some(response_error(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "", None))
+        }
+        if let Some(max_size) = self.max_size {
+            let size = headers.iter()
+                .map(|(key, value)| key.as_str().len() + value.len() + HEADER_OVERHEAD)
+                .sum::<usize>();
+            if size > max_size {
+                log::debug!("Rejecting request with {} bytes of headers", size);
+                return Some(response_error(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "", None))
+            }
+        }
+        if let Some(max_uri_length) = self.max_uri_length {
+            let uri = req.uri();
+            let length = uri.scheme_str().map_or(0, |scheme| scheme.len() + 3)
+                + uri.authority().map_or(0, |authority| authority.as_str().len())
+                + uri.path_and_query().map_or(0, |path| path.as_str().len());
+            if length > max_uri_length {
+                log::debug!("Rejecting request with a {} bytes long URI", length);
+                return Some(response_error(StatusCode::URI_TOO_LONG, "", None))
+            }
+        }
+        None
+    }
+}
+
+// The rest of the body is left unread, so the connection can't be reused
+fn too_large() -> Response<Body> {
+    let mut res = response_error(StatusCode::PAYLOAD_TOO_LARGE, "", None);
+    res.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+    res
+}
@@ -0,0 +1,26 @@
+use once_cell::sync::OnceCell;
+use pyo3::prelude::*;
+use pyo3_log::ResetHandle;
+
+
+// Rust records are forwarded to the Python loggers named after their target,
+// which are cached along with their levels: the cache needs to be dropped when
+// levels get changed at runtime, or Rust code would keep the previous verbosity.
+static RESET: OnceCell<ResetHandle> = OnceCell::new();
+
+pub(crate) fn init() {
+    RESET.get_or_init(pyo3_log::init);
+}
+
+#[pyfunction]
+fn reset_log_levels() {
+    if let Some(handle) = RESET.get() {
+        handle.reset();
+    }
+}
+
+pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(reset_log_levels, module)?)?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use hyper::{Body, HeaderMap, Request, Uri, body::HttpBody};
+use pyo3::prelude::*;
+use std::sync::Arc;
+
+use crate::metrics::{METRICS, GaugeGuard, RouteSegment, parse_route, route_matches};
+
+
+// Routes declared as long-poll, where requests hang until the application has
+// something to send. Their requests get parked once the scope holds its own copy
+// of the head: the protocol keeps the request for its body and extensions only,
+// releasing the headers and the target, which would otherwise stay around for as
+// long as the handler waits. Requests carrying a body are never parked, neither
+// are their handlers watched by the stack dumps, as long waits are the expected
+// behaviour on these routes.
+#[derive(Clone, Default)]
+pub(crate) struct LongPolls {
+    routes: Arc<Vec<Vec<RouteSegment>>>
+}
+
+impl LongPolls {
+    pub fn new(routes: Vec<String>) -> PyResult<Self> {
+        let routes = routes.iter().map(|template| parse_route(template)).collect::<PyResult<_>>()?;
+        Ok(Self { routes: Arc::new(routes) })
+    }
+
+    pub fn park(&self, req: &Request<Body>) -> Parked {
+        if !req.body().is_end_stream() || !self.routes.iter().any(|segments| route_matches(segments, req.uri().path())) {
+            return Parked(None)
+        }
+        Parked(Some(METRICS.long_polls_parked.track()))
+    }
+}
+
+// Keeps a request parked, until dropped
+pub(crate) struct Parked(Option<GaugeGuard<'static>>);
+
+impl Parked {
+    #[inline]
+    pub fn is_parked(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn release(&self, req: Request<Body>) -> Request<Body> {
+        if !self.is_parked() {
+            return req
+        }
+        let (mut parts, body) = req.into_parts();
+        parts.headers = HeaderMap::new();
+        parts.uri = Uri::default();
+        Request::from_parts(parts, body)
+    }
+}
@@ -0,0 +1,533 @@
+use hyper::StatusCode;
+use once_cell::sync::Lazy;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, AtomicUsize, Ordering}},
+    time::{Duration, Instant}
+};
+
+use crate::{
+    backlog::{accept_queue, listen_drops},
+    cgroups::{cgroup_cpu_quota, cgroup_cpu_throttling},
+    errors::ErrorKind,
+    workers::identity
+};
+
+
+// Hot-path values are split in per-core shards, each one on its own cache line,
+// so that threads updating them never contend on the same atomic; the shards
+// are only summed up when the metrics get scraped.
+#[repr(align(64))]
+#[derive(Default)]
+struct Shard(AtomicU64);
+
+static SHARDS: Lazy<usize> = Lazy::new(|| {
+    std::thread::available_parallelism().map_or(1, |count| count.get()).next_power_of_two()
+});
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_ID: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) & (*SHARDS - 1);
+}
+
+struct ShardedValue {
+    shards: Box<[Shard]>
+}
+
+impl ShardedValue {
+    fn new() -> Self {
+        Self { shards: (0..*SHARDS).map(|_| Shard::default()).collect() }
+    }
+
+    #[inline]
+    fn add(&self, value: u64) {
+        SHARD_ID.with(|id| self.shards[*id].0.fetch_add(value, Ordering::Relaxed));
+    }
+
+    fn sum(&self) -> u64 {
+        self.shards.iter().fold(0, |acc, shard| acc.wrapping_add(shard.0.load(Ordering::Relaxed)))
+    }
+}
+
+pub(crate) struct Counter(ShardedValue);
+
+impl Counter {
+    fn new() -> Self {
+        Self(ShardedValue::new())
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.0.add(1)
+    }
+
+    #[inline]
+    pub fn add(&self, value: u64) {
+        self.0.add(value)
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.sum()
+    }
+}
+
+// Shards of a gauge can go below zero on their own, when a value gets decreased from
+// a different thread than the one which increased it: only their sum is meaningful.
+pub(crate) struct Gauge(ShardedValue);
+
+impl Gauge {
+    fn new() -> Self {
+        Self(ShardedValue::new())
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.0.add(1)
+    }
+
+    #[inline]
+    pub fn dec(&self) {
+        self.0.add(u64::MAX)
+    }
+
+    pub fn track(&self) -> GaugeGuard<'_> {
+        self.inc();
+        GaugeGuard { gauge: self }
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.sum() as i64
+    }
+}
+
+pub(crate) struct GaugeGuard<'g> {
+    gauge: &'g Gauge
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.gauge.dec()
+    }
+}
+
+// Upper bounds, in seconds, of the request duration buckets
+pub(crate) const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Values are counted in their own bucket only, and made cumulative when read
+struct Histogram {
+    buckets: Box<[Counter]>,
+    sum_us: Counter
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: (0..=DURATION_BUCKETS.len()).map(|_| Counter::new()).collect(),
+            sum_us: Counter::new()
+        }
+    }
+
+    #[inline]
+    fn observe(&self, value: Duration) {
+        let secs = value.as_secs_f64();
+        let idx = DURATION_BUCKETS.iter().position(|bound| secs <= *bound).unwrap_or(DURATION_BUCKETS.len());
+        self.buckets[idx].inc();
+        self.sum_us.add(value.as_micros() as u64);
+    }
+
+    fn cumulative(&self) -> Vec<u64> {
+        self.buckets.iter().scan(0, |acc, counter| {
+            *acc += counter.get();
+            Some(*acc)
+        }).collect()
+    }
+}
+
+// Totals of the responses, for the evaluation of service level objectives
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RequestCounts {
+    pub total: u64,
+    pub errors: u64,
+    pub slow: u64
+}
+
+pub(crate) enum RouteSegment {
+    Static(String),
+    Param
+}
+
+pub(crate) fn parse_route(template: &str) -> PyResult<Vec<RouteSegment>> {
+    if !template.starts_with('/') {
+        return Err(PyValueError::new_err(format!("Invalid route template: {}", template)))
+    }
+    template.split('/').skip(1).map(|segment| {
+        match segment.strip_prefix('{').and_then(|segment| segment.strip_suffix('}')) {
+            Some(name) if !name.is_empty() => Ok(RouteSegment::Param),
+            _ if segment.contains(['{', '}']) => Err(PyValueError::new_err(
+                format!("Invalid route template: {}", template)
+            )),
+            _ => Ok(RouteSegment::Static(segment.to_string()))
+        }
+    }).collect()
+}
+
+pub(crate) fn route_matches(segments: &[RouteSegment], path: &str) -> bool {
+    let mut parts = path.split('/').skip(1);
+    let matched = segments.iter().all(|segment| match (segment, parts.next()) {
+        (RouteSegment::Static(value), Some(part)) => value == part,
+        (RouteSegment::Param, Some(part)) => !part.is_empty(),
+        _ => false
+    });
+    matched && parts.next().is_none()
+}
+
+// Route templates, like `/rooms/{room}`, used to label metrics. Paths matching
+// no template are labelled with the path itself, so routes with dynamic segments
+// should be declared to keep the number of series bounded.
+#[derive(Clone, Default)]
+pub(crate) struct RouteTemplates {
+    routes: Arc<Vec<(String, Vec<RouteSegment>)>>
+}
+
+impl RouteTemplates {
+    pub fn new(templates: Vec<String>) -> PyResult<Self> {
+        let mut routes = Vec::with_capacity(templates.len());
+        for template in templates {
+            let segments = parse_route(&template)?;
+            routes.push((template, segments));
+        }
+        Ok(Self { routes: Arc::new(routes) })
+    }
+
+    pub fn label<'p>(&'p self, path: &'p str) -> &'p str {
+        for (template, segments) in self.routes.iter() {
+            if route_matches(segments, path) {
+                return template
+            }
+        }
+        path
+    }
+}
+
+// Websocket values, grouped by route. Close codes are counted under a lock,
+// as they're only recorded once per connection.
+pub(crate) struct WebsocketMetrics {
+    pub connections: Gauge,
+    pub connections_total: Counter,
+    pub messages_in: Counter,
+    pub messages_out: Counter,
+    pub bytes_in: Counter,
+    pub bytes_out: Counter,
+    closes: Mutex<BTreeMap<u16, u64>>
+}
+
+impl WebsocketMetrics {
+    fn new() -> Self {
+        Self {
+            connections: Gauge::new(),
+            connections_total: Counter::new(),
+            messages_in: Counter::new(),
+            messages_out: Counter::new(),
+            bytes_in: Counter::new(),
+            bytes_out: Counter::new(),
+            closes: Mutex::new(BTreeMap::new())
+        }
+    }
+
+    pub fn record_close(&self, code: u16) {
+        *self.closes.lock().unwrap().entry(code).or_insert(0) += 1;
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+// Process-wide registry, as every worker runs in its own process
+pub(crate) struct Metrics {
+    requests: [Counter; 5],
+    pub requests_in_flight: Gauge,
+    pub long_polls_parked: Gauge,
+    durations: Histogram,
+    errors: [Counter; 5],
+    pub accept_errors: Counter,
+    pub connections_shed: Counter,
+    pub responses_aborted: Counter,
+    websockets: RwLock<HashMap<String, Arc<WebsocketMetrics>>>
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            requests: [Counter::new(), Counter::new(), Counter::new(), Counter::new(), Counter::new()],
+            requests_in_flight: Gauge::new(),
+            long_polls_parked: Gauge::new(),
+            durations: Histogram::new(),
+            errors: [Counter::new(), Counter::new(), Counter::new(), Counter::new(), Counter::new()],
+            accept_errors: Counter::new(),
+            connections_shed: Counter::new(),
+            responses_aborted: Counter::new(),
+            websockets: RwLock::new(HashMap::new())
+        }
+    }
+
+    pub fn websocket_route(&self, route: &str) -> Arc<WebsocketMetrics> {
+        if let Some(metrics) = self.websockets.read().unwrap().get(route) {
+            return metrics.clone()
+        }
+        self.websockets.write().unwrap()
+            .entry(route.to_string())
+            .or_insert_with(|| Arc::new(WebsocketMetrics::new()))
+            .clone()
+    }
+
+    // The duration covers the time taken to produce the response head
+    #[inline]
+    pub fn record_response(&self, status: StatusCode, duration: Duration) {
+        let class = (status.as_u16() / 100).clamp(1, 5) as usize;
+        self.requests[class - 1].inc();
+        self.durations.observe(duration);
+    }
+
+    // Slow responses are the ones above the given duration bucket
+    pub fn request_counts(&self, latency_bucket: Option<usize>) -> RequestCounts {
+        let durations = self.durations.cumulative();
+        let total = durations[DURATION_BUCKETS.len()];
+        RequestCounts {
+            total,
+            errors: self.requests[4].get(),
+            slow: latency_bucket.map_or(0, |idx| total.saturating_sub(durations[idx]))
+        }
+    }
+
+    #[inline]
+    pub fn record_error(&self, kind: ErrorKind) {
+        self.errors[kind as usize].inc()
+    }
+
+    // Renders the current values in the Prometheus text exposition format. Every
+    // series is labelled with the worker id, as each process exposes its own values.
+    pub fn render(&self) -> String {
+        let identity = identity();
+        let worker = identity.id;
+        let mut ret = String::new();
+        ret.push_str("# HELP granian_worker_info Identity of the worker process exposing the metrics\n");
+        ret.push_str("# TYPE granian_worker_info gauge\n");
+        let _ = writeln!(
+            ret,
+            "granian_worker_info{{worker=\"{}\",generation=\"{}\",pid=\"{}\"}} 1",
+            worker, identity.generation, std::process::id()
+        );
+        if let Some(rss) = resident_memory(None) {
+            ret.push_str("# HELP granian_worker_resident_memory_bytes Resident memory size of the worker process\n");
+            ret.push_str("# TYPE granian_worker_resident_memory_bytes gauge\n");
+            let _ = writeln!(ret, "granian_worker_resident_memory_bytes{{worker=\"{}\"}} {}", worker, rss);
+        }
+        ret.push_str("# HELP granian_worker_uptime_seconds Time elapsed since the worker process started\n");
+        ret.push_str("# TYPE granian_worker_uptime_seconds gauge\n");
+        let _ = writeln!(ret, "granian_worker_uptime_seconds{{worker=\"{}\"}} {}", worker, STARTED.elapsed().as_secs_f64());
+        ret.push_str("# HELP granian_worker_memory_recycles_total Processes of the worker slot recycled for exceeding the memory ceiling\n");
+        ret.push_str("# TYPE granian_worker_memory_recycles_total counter\n");
+        let _ = writeln!(ret, "granian_worker_memory_recycles_total{{worker=\"{}\"}} {}", worker, identity.memory_recycles);
+        self.render_cgroup(&mut ret, worker);
+        ret.push_str("# HELP granian_requests_total Handled HTTP requests by status class\n");
+        ret.push_str("# TYPE granian_requests_total counter\n");
+        for (class, counter) in STATUS_CLASSES.iter().zip(self.requests.iter()) {
+            let _ = writeln!(
+                ret, "granian_requests_total{{worker=\"{}\",status=\"{}\"}} {}", worker, class, counter.get()
+            );
+        }
+        ret.push_str("# HELP granian_requests_in_flight HTTP requests currently being handled\n");
+        ret.push_str("# TYPE granian_requests_in_flight gauge\n");
+        let _ = writeln!(ret, "granian_requests_in_flight{{worker=\"{}\"}} {}", worker, self.requests_in_flight.get());
+        ret.push_str("# HELP granian_long_polls_parked Long-poll requests parked while awaiting the application\n");
+        ret.push_str("# TYPE granian_long_polls_parked gauge\n");
+        let _ = writeln!(ret, "granian_long_polls_parked{{worker=\"{}\"}} {}", worker, self.long_polls_parked.get());
+        ret.push_str("# HELP granian_request_duration_seconds Time taken to produce the HTTP response head\n");
+        ret.push_str("# TYPE granian_request_duration_seconds histogram\n");
+        let durations = self.durations.cumulative();
+        for (bound, count) in DURATION_BUCKETS.iter().zip(durations.iter()) {
+            let _ = writeln!(
+                ret, "granian_request_duration_seconds_bucket{{worker=\"{}\",le=\"{}\"}} {}", worker, bound, count
+            );
+        }
+        let total = durations[DURATION_BUCKETS.len()];
+        let _ = writeln!(ret, "granian_request_duration_seconds_bucket{{worker=\"{}\",le=\"+Inf\"}} {}", worker, total);
+        let _ = writeln!(
+            ret, "granian_request_duration_seconds_sum{{worker=\"{}\"}} {}", worker, self.durations.sum_us.get() as f64 / 1e6
+        );
+        let _ = writeln!(ret, "granian_request_duration_seconds_count{{worker=\"{}\"}} {}", worker, total);
+        ret.push_str("# HELP granian_errors_total Requests failed by server errors, by error code\n");
+        ret.push_str("# TYPE granian_errors_total counter\n");
+        for (kind, counter) in ErrorKind::ALL.iter().zip(self.errors.iter()) {
+            let _ = writeln!(
+                ret, "granian_errors_total{{worker=\"{}\",code=\"{}\"}} {}", worker, kind.code(), counter.get()
+            );
+        }
+        ret.push_str("# HELP granian_responses_aborted_total Responses cut short as the application failed after sending their head\n");
+        ret.push_str("# TYPE granian_responses_aborted_total counter\n");
+        let _ = writeln!(ret, "granian_responses_aborted_total{{worker=\"{}\"}} {}", worker, self.responses_aborted.get());
+        ret.push_str("# HELP granian_accept_errors_total Failed attempts to accept connections\n");
+        ret.push_str("# TYPE granian_accept_errors_total counter\n");
+        let _ = writeln!(ret, "granian_accept_errors_total{{worker=\"{}\"}} {}", worker, self.accept_errors.get());
+        ret.push_str("# HELP granian_connections_shed_total Connections closed right after the accept, as out of file descriptors\n");
+        ret.push_str("# TYPE granian_connections_shed_total counter\n");
+        let _ = writeln!(ret, "granian_connections_shed_total{{worker=\"{}\"}} {}", worker, self.connections_shed.get());
+        self.render_backlog(&mut ret, worker);
+        self.render_websockets(&mut ret, worker);
+        ret
+    }
+
+    // The cgroup values are shared by all the workers of a container, but get
+    // exposed by each of them anyway, as processes get scraped one by one.
+    fn render_cgroup(&self, ret: &mut String, worker: i32) {
+        if let Some(quota) = cgroup_cpu_quota() {
+            ret.push_str("# HELP granian_cgroup_cpu_quota CPU quota of the cgroup, in CPUs\n");
+            ret.push_str("# TYPE granian_cgroup_cpu_quota gauge\n");
+            let _ = writeln!(ret, "granian_cgroup_cpu_quota{{worker=\"{}\"}} {}", worker, quota);
+        }
+        let throttling = match cgroup_cpu_throttling() {
+            Some(throttling) => throttling,
+            None => return
+        };
+        ret.push_str("# HELP granian_cgroup_cpu_periods_total Elapsed CPU bandwidth enforcement periods of the cgroup\n");
+        ret.push_str("# TYPE granian_cgroup_cpu_periods_total counter\n");
+        let _ = writeln!(ret, "granian_cgroup_cpu_periods_total{{worker=\"{}\"}} {}", worker, throttling.periods);
+        ret.push_str("# HELP granian_cgroup_cpu_throttled_periods_total CPU bandwidth enforcement periods in which the cgroup got throttled\n");
+        ret.push_str("# TYPE granian_cgroup_cpu_throttled_periods_total counter\n");
+        let _ = writeln!(
+            ret, "granian_cgroup_cpu_throttled_periods_total{{worker=\"{}\"}} {}", worker, throttling.throttled_periods
+        );
+        ret.push_str("# HELP granian_cgroup_cpu_throttled_seconds_total Time the cgroup tasks spent throttled\n");
+        ret.push_str("# TYPE granian_cgroup_cpu_throttled_seconds_total counter\n");
+        let _ = writeln!(
+            ret, "granian_cgroup_cpu_throttled_seconds_total{{worker=\"{}\"}} {}", worker, throttling.throttled.as_secs_f64()
+        );
+    }
+
+    // Like the cgroup ones, the kernel counters are not specific to the worker
+    fn render_backlog(&self, ret: &mut String, worker: i32) {
+        if let Some(queue) = accept_queue() {
+            ret.push_str("# HELP granian_listen_queue_length Connections waiting in the accept queue of the listening socket\n");
+            ret.push_str("# TYPE granian_listen_queue_length gauge\n");
+            let _ = writeln!(ret, "granian_listen_queue_length{{worker=\"{}\"}} {}", worker, queue.length);
+            ret.push_str("# HELP granian_listen_queue_limit Size of the accept queue of the listening socket\n");
+            ret.push_str("# TYPE granian_listen_queue_limit gauge\n");
+            let _ = writeln!(ret, "granian_listen_queue_limit{{worker=\"{}\"}} {}", worker, queue.limit);
+        }
+        let drops = match listen_drops() {
+            Some(drops) => drops,
+            None => return
+        };
+        ret.push_str("# HELP granian_listen_overflows_total Connections dropped by the kernel as the accept queue was full\n");
+        ret.push_str("# TYPE granian_listen_overflows_total counter\n");
+        let _ = writeln!(ret, "granian_listen_overflows_total{{worker=\"{}\"}} {}", worker, drops.overflows);
+        ret.push_str("# HELP granian_listen_drops_total Connection attempts dropped by the kernel on listening sockets\n");
+        ret.push_str("# TYPE granian_listen_drops_total counter\n");
+        let _ = writeln!(ret, "granian_listen_drops_total{{worker=\"{}\"}} {}", worker, drops.drops);
+    }
+
+    fn render_websockets(&self, ret: &mut String, worker: i32) {
+        let mut routes: Vec<(String, Arc<WebsocketMetrics>)> = self.websockets.read().unwrap()
+            .iter()
+            .map(|(route, metrics)| (escape_label(route), metrics.clone()))
+            .collect();
+        if routes.is_empty() {
+            return
+        }
+        routes.sort_by(|a, b| a.0.cmp(&b.0));
+
+        ret.push_str("# HELP granian_websocket_connections Websocket connections currently open\n");
+        ret.push_str("# TYPE granian_websocket_connections gauge\n");
+        for (route, metrics) in routes.iter() {
+            let _ = writeln!(
+                ret, "granian_websocket_connections{{worker=\"{}\",route=\"{}\"}} {}", worker, route, metrics.connections.get()
+            );
+        }
+        ret.push_str("# HELP granian_websocket_connections_total Accepted websocket connections\n");
+        ret.push_str("# TYPE granian_websocket_connections_total counter\n");
+        for (route, metrics) in routes.iter() {
+            let _ = writeln!(
+                ret, "granian_websocket_connections_total{{worker=\"{}\",route=\"{}\"}} {}", worker, route, metrics.connections_total.get()
+            );
+        }
+        ret.push_str("# HELP granian_websocket_messages_total Websocket data messages by direction\n");
+        ret.push_str("# TYPE granian_websocket_messages_total counter\n");
+        for (route, metrics) in routes.iter() {
+            for (direction, counter) in [("in", &metrics.messages_in), ("out", &metrics.messages_out)] {
+                let _ = writeln!(
+                    ret,
+                    "granian_websocket_messages_total{{worker=\"{}\",route=\"{}\",direction=\"{}\"}} {}",
+                    worker, route, direction, counter.get()
+                );
+            }
+        }
+        ret.push_str("# HELP granian_websocket_bytes_total Websocket message payload bytes by direction\n");
+        ret.push_str("# TYPE granian_websocket_bytes_total counter\n");
+        for (route, metrics) in routes.iter() {
+            for (direction, counter) in [("in", &metrics.bytes_in), ("out", &metrics.bytes_out)] {
+                let _ = writeln!(
+                    ret,
+                    "granian_websocket_bytes_total{{worker=\"{}\",route=\"{}\",direction=\"{}\"}} {}",
+                    worker, route, direction, counter.get()
+                );
+            }
+        }
+        ret.push_str("# HELP granian_websocket_closes_total Closed websocket connections by close code\n");
+        ret.push_str("# TYPE granian_websocket_closes_total counter\n");
+        for (route, metrics) in routes.iter() {
+            for (code, count) in metrics.closes.lock().unwrap().iter() {
+                let _ = writeln!(
+                    ret,
+                    "granian_websocket_closes_total{{worker=\"{}\",route=\"{}\",code=\"{}\"}} {}",
+                    worker, route, code, count
+                );
+            }
+        }
+    }
+}
+
+pub(crate) static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+// Forced when the extension gets imported, early in the worker processes
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+// Resident set size of a process, the current one when no pid is given
+#[cfg(target_os = "linux")]
+pub(crate) fn resident_memory(pid: Option<u32>) -> Option<u64> {
+    let path = match pid {
+        Some(pid) => format!("/proc/{}/statm", pid),
+        None => "/proc/self/statm".to_string()
+    };
+    let statm = std::fs::read_to_string(path).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn resident_memory(_pid: Option<u32>) -> Option<u64> {
+    None
+}
+
+#[pyfunction]
+fn metrics() -> String {
+    METRICS.render()
+}
+
+#[pyfunction]
+fn process_memory(pid: u32) -> Option<u64> {
+    resident_memory(Some(pid))
+}
+
+pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+    Lazy::force(&STARTED);
+    module.add_function(wrap_pyfunction!(metrics, module)?)?;
+    module.add_function(wrap_pyfunction!(process_memory, module)?)?;
+
+    Ok(())
+}
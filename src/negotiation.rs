@@ -0,0 +1,162 @@
+use hyper::{
+    Body,
+    Response,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+
+const HV_JSON: HeaderValue = HeaderValue::from_static("application/problem+json");
+const HV_HTML: HeaderValue = HeaderValue::from_static("text/html; charset=utf-8");
+
+// Parses a list of weighted values, like `Accept` or `Accept-Encoding`, following
+// RFC 9110: values come with their `q` parameter (defaulting to 1), other parameters
+// are dropped, and entries with a malformed weight are ignored.
+pub(crate) fn parse_weighted(header: &str) -> Vec<(&str, f32)> {
+    header.split(',').filter_map(|item| {
+        let mut params = item.split(';').map(str::trim);
+        let value = params.next().filter(|value| !value.is_empty())?;
+        let mut quality = 1.0;
+        for param in params {
+            if let Some((key, weight)) = param.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("q") {
+                    quality = weight.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+                }
+            }
+        }
+        Some((value, quality))
+    }).collect()
+}
+
+// The weight given to a media type, from the most specific matching range
+pub(crate) fn media_quality(accepted: &[(&str, f32)], media: &str) -> f32 {
+    let (kind, _) = media.split_once('/').unwrap_or((media, ""));
+    accepted.iter().filter_map(|(range, quality)| {
+        let specificity = match range.split_once('/') {
+            Some(("*", "*")) => 0,
+            Some((range_kind, "*")) if range_kind.eq_ignore_ascii_case(kind) => 1,
+            _ if range.eq_ignore_ascii_case(media) => 2,
+            _ => return None
+        };
+        Some((specificity, *quality))
+    }).max_by_key(|(specificity, _)| *specificity).map_or(0.0, |(_, quality)| quality)
+}
+
+// Marks the responses generated by the server itself, rather than by the application,
+// so their body can be rendered in the format negotiated with the client.
+#[derive(Clone, Default)]
+pub(crate) struct ServerError {
+    pub detail: Option<String>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorFormat {
+    Auto,
+    Json,
+    Html,
+    Text
+}
+
+impl ErrorFormat {
+    // Server preference, when the client weights several formats the same
+    const OFFERS: [(ErrorFormat, &'static str); 4] = [
+        (Self::Text, "text/plain"),
+        (Self::Json, "application/problem+json"),
+        (Self::Json, "application/json"),
+        (Self::Html, "text/html")
+    ];
+
+    pub fn new(value: &str) -> PyResult<Self> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            "text" => Ok(Self::Text),
+            _ => Err(PyValueError::new_err(format!("Invalid error format: {}", value)))
+        }
+    }
+
+    fn negotiate(accept: Option<&HeaderValue>) -> Self {
+        let accepted = match accept.and_then(|value| value.to_str().ok()) {
+            Some(value) => parse_weighted(value),
+            None => return Self::Text
+        };
+        let mut selected = (Self::Text, 0.0);
+        for (format, media) in Self::OFFERS {
+            let quality = media_quality(&accepted, media);
+            if quality > selected.1 {
+                selected = (format, quality);
+            }
+        }
+        selected.0
+    }
+
+    pub fn render(&self, accept: Option<&HeaderValue>, res: Response<Body>) -> Response<Body> {
+        let error = match res.extensions().get::<ServerError>() {
+            Some(error) => error.clone(),
+            None => return res
+        };
+        let format = match self {
+            Self::Auto => Self::negotiate(accept),
+            format => *format
+        };
+        let (mut parts, body) = res.into_parts();
+        let status = parts.status;
+        let title = status.canonical_reason().unwrap_or("Error");
+        let (content_type, body) = match format {
+            Self::Json => {
+                let detail = error.detail
+                    .map(|detail| format!(",\"detail\":\"{}\"", escape_json(&detail)))
+                    .unwrap_or_default();
+                (HV_JSON, format!(
+                    "{{\"type\":\"about:blank\",\"title\":\"{}\",\"status\":{}{}}}",
+                    title, status.as_u16(), detail
+                ))
+            },
+            Self::Html => {
+                let detail = error.detail
+                    .map(|detail| format!("<p>{}</p>", escape_html(&detail)))
+                    .unwrap_or_default();
+                (HV_HTML, format!(
+                    "<!DOCTYPE html><html><head><title>{0} {1}</title></head><body><h1>{0} {1}</h1>{2}</body></html>",
+                    status.as_u16(), title, detail
+                ))
+            },
+            _ => return Response::from_parts(parts, body)
+        };
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(CONTENT_TYPE, content_type);
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+pub(crate) fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
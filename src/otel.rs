@@ -0,0 +1,509 @@
+use hyper::{Body, Request, Response, Uri, header::{HeaderValue, USER_AGENT}};
+use pyo3::{exceptions::{PyOSError, PyValueError}, prelude::*};
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Arc, atomic::{AtomicU32, Ordering}, mpsc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{negotiation::escape_json, tls::TlsHandshake, workers::identity};
+
+
+const TRACEPARENT: &str = "traceparent";
+// Spans queued while the collector is busy: once full, further spans get
+// dropped, so a slow or unreachable collector never stalls the runtime threads.
+const QUEUE_SIZE: usize = 4096;
+const BATCH_SIZE: usize = 512;
+const EXPORT_DELAY: Duration = Duration::from_secs(1);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+const KIND_INTERNAL: u8 = 1;
+const KIND_SERVER: u8 = 2;
+const STATUS_ERROR: u8 = 2;
+
+// The position of a span in a trace, as carried by W3C `traceparent` headers
+#[derive(Clone, Copy)]
+struct SpanContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool
+}
+
+impl SpanContext {
+    fn child_of(parent: &SpanContext) -> Self {
+        Self { trace_id: parent.trace_id, span_id: span_id(), sampled: parent.sampled }
+    }
+
+    // `{version}-{trace-id}-{parent-id}-{flags}`, where versions past 00 might
+    // append further fields; all-zero ids are invalid.
+    fn parse(value: &HeaderValue) -> Option<Self> {
+        let mut parts = value.to_str().ok()?.trim().split('-');
+        let (version, trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let lowerhex = |part: &str, size: usize| {
+            part.len() == size && part.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        if !lowerhex(version, 2) || version == "ff" || !lowerhex(trace_id, 32) || !lowerhex(span_id, 16) || !lowerhex(flags, 2) {
+            return None
+        }
+        if version == "00" && parts.next().is_some() {
+            return None
+        }
+        let ret = Self {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            span_id: u64::from_str_radix(span_id, 16).ok()?,
+            sampled: u8::from_str_radix(flags, 16).ok()? & 0x01 != 0
+        };
+        (ret.trace_id != 0 && ret.span_id != 0).then_some(ret)
+    }
+
+    fn header(&self) -> String {
+        format!("00-{:032x}-{:016x}-{:02x}", self.trace_id, self.span_id, self.sampled as u8)
+    }
+}
+
+fn trace_id() -> u128 {
+    rand::random::<u128>().max(1)
+}
+
+fn span_id() -> u64 {
+    rand::random::<u64>().max(1)
+}
+
+// Spans get timed with monotonic instants, converted to wall clock time once done
+fn wall_clock(at: Instant) -> SystemTime {
+    SystemTime::now() - at.elapsed()
+}
+
+enum Attribute {
+    Str(String),
+    Int(i64),
+    Bool(bool)
+}
+
+struct Span {
+    context: SpanContext,
+    parent: Option<u64>,
+    name: String,
+    kind: u8,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, Attribute)>,
+    links: Vec<SpanContext>,
+    error: bool
+}
+
+impl Span {
+    fn write_json(&self, out: &mut String) {
+        let nanos = |at: SystemTime| at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let _ = write!(
+            out,
+            "{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\",",
+            self.context.trace_id,
+            self.context.span_id
+        );
+        if let Some(parent) = self.parent {
+            let _ = write!(out, "\"parentSpanId\":\"{:016x}\",", parent);
+        }
+        let _ = write!(
+            out,
+            "\"name\":\"{}\",\"kind\":{},\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",",
+            escape_json(&self.name),
+            self.kind,
+            nanos(self.start),
+            nanos(self.end)
+        );
+        write_attributes(out, &self.attributes);
+        out.push_str(",\"links\":[");
+        for (idx, link) in self.links.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\"}}", link.trace_id, link.span_id);
+        }
+        out.push(']');
+        if self.error {
+            let _ = write!(out, ",\"status\":{{\"code\":{}}}", STATUS_ERROR);
+        }
+        out.push('}');
+    }
+}
+
+// OTLP JSON encodes 64 bits integers as strings
+fn write_attributes(out: &mut String, attributes: &[(&'static str, Attribute)]) {
+    out.push_str("\"attributes\":[");
+    for (idx, (key, value)) in attributes.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        let _ = match value {
+            Attribute::Str(value) => write!(
+                out, "{{\"key\":\"{}\",\"value\":{{\"stringValue\":\"{}\"}}}}", key, escape_json(value)
+            ),
+            Attribute::Int(value) => write!(out, "{{\"key\":\"{}\",\"value\":{{\"intValue\":\"{}\"}}}}", key, value),
+            Attribute::Bool(value) => write!(out, "{{\"key\":\"{}\",\"value\":{{\"boolValue\":{}}}}}", key, value)
+        };
+    }
+    out.push(']');
+}
+
+// OTLP/HTTP collector, with the JSON encoding, reached over plain HTTP as
+// collectors usually run as a sidecar or on the local network.
+struct Exporter {
+    host: String,
+    port: u16,
+    path: String,
+    service_name: String,
+    failing: bool
+}
+
+impl Exporter {
+    fn new(endpoint: &str, service_name: String) -> PyResult<Self> {
+        let uri: Uri = endpoint.parse()
+            .map_err(|_| PyValueError::new_err(format!("Invalid OTLP endpoint: {}", endpoint)))?;
+        if uri.scheme_str() != Some("http") {
+            return Err(PyValueError::new_err(format!("Unsupported OTLP endpoint, expected an http:// URL: {}", endpoint)))
+        }
+        let authority = uri.authority()
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid OTLP endpoint: {}", endpoint)))?;
+        // base endpoints get the traces path appended, like OTLP exporters do
+        let path = match uri.path() {
+            "" | "/" => "/v1/traces",
+            path => path
+        };
+        Ok(Self {
+            host: authority.host().to_string(),
+            port: authority.port_u16().unwrap_or(80),
+            path: path.to_string(),
+            service_name,
+            failing: false
+        })
+    }
+
+    fn encode(&self, spans: &[Span]) -> String {
+        let mut out = String::with_capacity(512 * spans.len());
+        out.push_str("{\"resourceSpans\":[{\"resource\":{");
+        write_attributes(&mut out, &[
+            ("service.name", Attribute::Str(self.service_name.clone())),
+            ("process.pid", Attribute::Int(std::process::id() as i64)),
+            ("granian.worker", Attribute::Int(identity().id as i64))
+        ]);
+        let _ = write!(
+            out,
+            "}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"granian\",\"version\":\"{}\"}},\"spans\":[",
+            env!("CARGO_PKG_VERSION")
+        );
+        for (idx, span) in spans.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+            span.write_json(&mut out);
+        }
+        out.push_str("]}]}]}");
+        out
+    }
+
+    fn post(&self, payload: &str) -> io::Result<()> {
+        let addr = (self.host.as_str(), self.port).to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::other("unable to resolve the collector address"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, EXPORT_TIMEOUT)?;
+        stream.set_read_timeout(Some(EXPORT_TIMEOUT))?;
+        stream.set_write_timeout(Some(EXPORT_TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nhost: {}:{}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            payload.len()
+        )?;
+        stream.write_all(payload.as_bytes())?;
+        let mut head = Vec::with_capacity(64);
+        let mut buf = [0; 256];
+        while !head.contains(&b'\n') {
+            match stream.read(&mut buf)? {
+                0 => break,
+                read => head.extend_from_slice(&buf[..read])
+            }
+        }
+        let status = String::from_utf8_lossy(&head);
+        match status.split(' ').nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            Some(code) => Err(io::Error::other(format!("collector responded with status {}", code))),
+            None => Err(io::Error::other("invalid collector response"))
+        }
+    }
+
+    // Failures get logged once, until an export succeeds again
+    fn export(&mut self, spans: &[Span]) {
+        match self.post(&self.encode(spans)) {
+            Ok(()) => self.failing = false,
+            Err(err) => {
+                if !self.failing {
+                    log::warn!("Unable to export spans to {}:{}{}: {}", self.host, self.port, self.path, err);
+                }
+                self.failing = true;
+            }
+        }
+    }
+}
+
+struct TracerState {
+    sample_ratio: f64,
+    spans: mpsc::SyncSender<Span>
+}
+
+impl TracerState {
+    fn export(&self, span: Span) {
+        let _ = self.spans.try_send(span);
+    }
+
+    // Traces started here get sampled by ratio, others follow their parent
+    fn root(&self) -> SpanContext {
+        SpanContext {
+            trace_id: trace_id(),
+            span_id: span_id(),
+            sampled: rand::random::<f64>() < self.sample_ratio
+        }
+    }
+}
+
+// OpenTelemetry spans for the connections, the requests and the application
+// callbacks, exported in batches by a dedicated thread. Requests continue the
+// traces of their `traceparent` header, which gets replaced with the request
+// span, so the spans of instrumented applications nest under the server ones.
+#[derive(Clone, Default)]
+pub(crate) struct Tracer {
+    state: Option<Arc<TracerState>>
+}
+
+impl Tracer {
+    pub fn new(endpoint: Option<String>, service_name: String, sample_ratio: f64) -> PyResult<Self> {
+        if !(0.0..=1.0).contains(&sample_ratio) {
+            return Err(PyValueError::new_err("OTLP sample ratio must be between 0 and 1"))
+        }
+        let mut exporter = match endpoint {
+            Some(endpoint) => Exporter::new(&endpoint, service_name)?,
+            None => return Ok(Self::default())
+        };
+        let (queue, spans) = mpsc::sync_channel::<Span>(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("granian-otlp".to_string())
+            .spawn(move || {
+                while let Ok(span) = spans.recv() {
+                    let mut batch = vec![span];
+                    let deadline = Instant::now() + EXPORT_DELAY;
+                    while batch.len() < BATCH_SIZE {
+                        match spans.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                            Ok(span) => batch.push(span),
+                            Err(_) => break
+                        }
+                    }
+                    exporter.export(&batch);
+                }
+            })
+            .map_err(|err| PyOSError::new_err(format!("Unable to start OTLP exporter: {}", err)))?;
+        Ok(Self { state: Some(Arc::new(TracerState { sample_ratio, spans: queue })) })
+    }
+
+    // TLS connections provide their handshake, which gets its own span
+    pub fn connection(&self, remote_addr: SocketAddr, handshake: Option<TlsHandshake>) -> ConnSpan {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return ConnSpan::default()
+        };
+        let context = state.root();
+        if !context.sampled {
+            return ConnSpan::default()
+        }
+        let started = handshake.map_or_else(Instant::now, |handshake| handshake.accepted);
+        if let Some(handshake) = handshake {
+            state.export(Span {
+                context: SpanContext::child_of(&context),
+                parent: Some(context.span_id),
+                name: "tls handshake".to_string(),
+                kind: KIND_INTERNAL,
+                start: wall_clock(handshake.accepted),
+                end: wall_clock(handshake.established),
+                attributes: handshake.alpn
+                    .map(|alpn| vec![("tls.next_protocol", Attribute::Str(alpn.to_string()))])
+                    .unwrap_or_default(),
+                links: Vec::new(),
+                error: false
+            });
+        }
+        ConnSpan {
+            state: Some(Arc::new(ConnSpanState {
+                tracer: state.clone(),
+                context,
+                remote_addr,
+                tls: handshake.is_some(),
+                started,
+                requests: AtomicU32::new(0)
+            }))
+        }
+    }
+
+    pub fn request(
+        &self,
+        req: &mut Request<Body>,
+        connection: &ConnSpan,
+        client_addr: SocketAddr,
+        scheme: &str
+    ) -> Option<RequestSpan> {
+        let state = self.state.as_ref()?;
+        let parent = req.headers().get(TRACEPARENT).and_then(SpanContext::parse);
+        let context = match &parent {
+            Some(parent) => SpanContext::child_of(parent),
+            None => state.root()
+        };
+        if let Ok(value) = HeaderValue::from_str(&context.header()) {
+            req.headers_mut().insert(TRACEPARENT, value);
+        }
+        req.extensions_mut().insert(ActiveSpan { tracer: state.clone(), context });
+        if !context.sampled {
+            return None
+        }
+        let mut attributes = vec![
+            ("http.request.method", Attribute::Str(req.method().to_string())),
+            ("url.path", Attribute::Str(req.uri().path().to_string())),
+            ("url.scheme", Attribute::Str(scheme.to_string())),
+            ("network.protocol.version", Attribute::Str(protocol_version(req.version()).to_string())),
+            ("client.address", Attribute::Str(client_addr.ip().to_string()))
+        ];
+        if let Some(query) = req.uri().query() {
+            attributes.push(("url.query", Attribute::Str(query.to_string())));
+        }
+        if let Some(agent) = req.headers().get(USER_AGENT).and_then(|value| value.to_str().ok()) {
+            attributes.push(("user_agent.original", Attribute::Str(agent.to_string())));
+        }
+        let links = match &connection.state {
+            Some(connection) => {
+                connection.requests.fetch_add(1, Ordering::Relaxed);
+                vec![connection.context]
+            },
+            None => Vec::new()
+        };
+        Some(RequestSpan {
+            tracer: state.clone(),
+            context,
+            parent: parent.map(|parent| parent.span_id),
+            name: req.method().to_string(),
+            started: Instant::now(),
+            attributes,
+            links
+        })
+    }
+
+    pub fn callback(&self, req: &Request<Body>) -> CallbackSpan {
+        CallbackSpan {
+            active: req.extensions().get::<ActiveSpan>().filter(|active| active.context.sampled).cloned(),
+            started: Instant::now()
+        }
+    }
+}
+
+fn protocol_version(version: hyper::Version) -> &'static str {
+    match version {
+        hyper::Version::HTTP_09 => "0.9",
+        hyper::Version::HTTP_10 => "1.0",
+        hyper::Version::HTTP_2 => "2",
+        hyper::Version::HTTP_3 => "3",
+        _ => "1.1"
+    }
+}
+
+struct ConnSpanState {
+    tracer: Arc<TracerState>,
+    context: SpanContext,
+    remote_addr: SocketAddr,
+    tls: bool,
+    started: Instant,
+    requests: AtomicU32
+}
+
+// The connection is gone once the service and the last of its requests get dropped
+impl Drop for ConnSpanState {
+    fn drop(&mut self) {
+        self.tracer.export(Span {
+            context: self.context,
+            parent: None,
+            name: "connection".to_string(),
+            kind: KIND_INTERNAL,
+            start: wall_clock(self.started),
+            end: SystemTime::now(),
+            attributes: vec![
+                ("network.peer.address", Attribute::Str(self.remote_addr.ip().to_string())),
+                ("network.peer.port", Attribute::Int(self.remote_addr.port() as i64)),
+                ("granian.tls", Attribute::Bool(self.tls)),
+                ("granian.requests", Attribute::Int(self.requests.load(Ordering::Relaxed) as i64))
+            ],
+            links: Vec::new(),
+            error: false
+        });
+    }
+}
+
+// Connections are traces of their own, linked from the spans of their requests,
+// as the requests they carry belong to unrelated traces.
+#[derive(Clone, Default)]
+pub(crate) struct ConnSpan {
+    state: Option<Arc<ConnSpanState>>
+}
+
+#[derive(Clone)]
+struct ActiveSpan {
+    tracer: Arc<TracerState>,
+    context: SpanContext
+}
+
+// Request spans last until the response head is ready
+pub(crate) struct RequestSpan {
+    tracer: Arc<TracerState>,
+    context: SpanContext,
+    parent: Option<u64>,
+    name: String,
+    started: Instant,
+    attributes: Vec<(&'static str, Attribute)>,
+    links: Vec<SpanContext>
+}
+
+impl RequestSpan {
+    pub fn finish(mut self, res: &Response<Body>) {
+        self.attributes.push(("http.response.status_code", Attribute::Int(res.status().as_u16() as i64)));
+        self.tracer.export(Span {
+            context: self.context,
+            parent: self.parent,
+            name: self.name,
+            kind: KIND_SERVER,
+            start: wall_clock(self.started),
+            end: SystemTime::now(),
+            attributes: self.attributes,
+            links: self.links,
+            error: res.status().is_server_error()
+        });
+    }
+}
+
+pub(crate) struct CallbackSpan {
+    active: Option<ActiveSpan>,
+    started: Instant
+}
+
+impl CallbackSpan {
+    pub fn finish(self, failed: bool) {
+        if let Some(active) = self.active {
+            active.tracer.export(Span {
+                context: SpanContext::child_of(&active.context),
+                parent: Some(active.context.span_id),
+                name: "callback".to_string(),
+                kind: KIND_INTERNAL,
+                start: wall_clock(self.started),
+                end: SystemTime::now(),
+                attributes: Vec::new(),
+                links: Vec::new(),
+                error: failed
+            });
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use hyper::{Body, Request, Response, StatusCode};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use ring::{digest, hmac};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use crate::{
+    http::response_error,
+    metrics::{RouteSegment, parse_route, route_matches},
+    urls::{PathDecoding, decode_component, query_params}
+};
+
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const SERVICE: &str = "s3";
+const SCOPE_TERMINATOR: &str = "aws4_request";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+// As for S3, URLs can't last longer than a week
+const EXPIRES_MAX: u64 = 604_800;
+// Tolerated advance of the signing time over the server clock
+const CLOCK_SKEW: u64 = 900;
+
+struct PresignConfig {
+    routes: Vec<Vec<RouteSegment>>,
+    credentials: HashMap<String, String>,
+    region: String
+}
+
+// Validation of S3-compatible presigned URLs, signed with AWS Signature Version 4
+// in their query parameters, for the routes matching the given templates: the
+// URLs of the upload endpoints of the application. Requests get rejected before
+// their body is read when the URL expired, its credential is unknown, or the
+// signature doesn't match the canonical request, computed as S3 does for
+// presigned URLs, with the payload unsigned.
+#[derive(Clone, Default)]
+pub(crate) struct PresignedUrls {
+    inner: Option<Arc<PresignConfig>>
+}
+
+impl PresignedUrls {
+    pub fn new(routes: Vec<String>, credentials: Vec<(String, String)>, region: String) -> PyResult<Self> {
+        if routes.is_empty() {
+            return Ok(Self { inner: None })
+        }
+        if credentials.is_empty() {
+            return Err(PyValueError::new_err("Presigned URLs validation needs at least a credential"))
+        }
+        let routes = routes.iter().map(|template| parse_route(template)).collect::<PyResult<_>>()?;
+        Ok(Self { inner: Some(Arc::new(PresignConfig {
+            routes,
+            credentials: credentials.into_iter().collect(),
+            region
+        })) })
+    }
+
+    pub fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let config = self.inner.as_ref()?;
+        // applications see decoded paths, so encoded ones can't skip the check
+        let path = req.uri().path();
+        let decoded = PathDecoding::Lenient.path(path);
+        if !config.routes.iter().any(|segments| route_matches(segments, path) || route_matches(segments, &decoded)) {
+            return None
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        match config.verify(req, now) {
+            Ok(()) => None,
+            Err(detail) => {
+                log::debug!("Rejected presigned URL for {}: {}", req.uri().path(), detail);
+                Some(response_error(StatusCode::FORBIDDEN, detail, Some(detail.to_string())))
+            }
+        }
+    }
+}
+
+impl PresignConfig {
+    fn verify(&self, req: &Request<Body>, now: u64) -> Result<(), &'static str> {
+        let params = query_params(req.uri().query().unwrap_or(""));
+        let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+
+        if param("X-Amz-Algorithm") != Some(ALGORITHM) {
+            return Err("Missing or unsupported signature algorithm")
+        }
+        let credential = param("X-Amz-Credential").ok_or("Missing credential")?;
+        let (access_key, scope) = credential.split_once('/').ok_or("Malformed credential")?;
+        let mut scope_parts = scope.split('/');
+        let (date, region, service, terminator) = match (
+            scope_parts.next(), scope_parts.next(), scope_parts.next(), scope_parts.next(), scope_parts.next()
+        ) {
+            (Some(date), Some(region), Some(service), Some(terminator), None) => (date, region, service, terminator),
+            _ => return Err("Malformed credential")
+        };
+        if region != self.region || service != SERVICE || terminator != SCOPE_TERMINATOR {
+            return Err("Invalid credential scope")
+        }
+        let secret = self.credentials.get(access_key).ok_or("Unknown access key")?;
+
+        let timestamp = param("X-Amz-Date").ok_or("Missing signing date")?;
+        let signed_at = parse_timestamp(timestamp).ok_or("Malformed signing date")?;
+        if !timestamp.starts_with(date) {
+            return Err("Signing date doesn't match the credential scope")
+        }
+        let expires: u64 = param("X-Amz-Expires").and_then(|value| value.parse().ok()).ok_or("Missing or invalid expiration")?;
+        if expires > EXPIRES_MAX {
+            return Err("Expiration exceeds one week")
+        }
+        if signed_at > now + CLOCK_SKEW {
+            return Err("Signing date is in the future")
+        }
+        if now > signed_at + expires {
+            return Err("Request has expired")
+        }
+
+        let signed_headers = param("X-Amz-SignedHeaders").ok_or("Missing signed headers")?;
+        if !signed_headers.split(';').any(|name| name == "host") {
+            return Err("Host header not signed")
+        }
+        let signature = param("X-Amz-Signature").and_then(decode_hex).ok_or("Missing or malformed signature")?;
+        let canonical = canonical_request(req, &params, signed_headers).ok_or("Signed header missing from the request")?;
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            timestamp,
+            scope,
+            encode_hex(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref())
+        );
+        let key = signing_key(secret, date, region, service);
+        hmac::verify(&key, string_to_sign.as_bytes(), &signature).map_err(|_| "Signature doesn't match")
+    }
+}
+
+fn canonical_request(req: &Request<Body>, params: &[(String, String)], signed_headers: &str) -> Option<String> {
+    let mut canonical = String::with_capacity(512);
+    canonical.push_str(req.method().as_str());
+    canonical.push('\n');
+    for (idx, segment) in req.uri().path().split('/').enumerate() {
+        if idx > 0 {
+            canonical.push('/');
+        }
+        encode_uri(&decode_component(segment), &mut canonical);
+    }
+    canonical.push('\n');
+
+    let mut query: Vec<(String, String)> = params.iter()
+        .filter(|(key, _)| key != "X-Amz-Signature")
+        .map(|(key, value)| {
+            let (mut ekey, mut evalue) = (String::new(), String::new());
+            encode_uri(key.as_bytes(), &mut ekey);
+            encode_uri(value.as_bytes(), &mut evalue);
+            (ekey, evalue)
+        })
+        .collect();
+    query.sort();
+    for (idx, (key, value)) in query.iter().enumerate() {
+        if idx > 0 {
+            canonical.push('&');
+        }
+        let _ = write!(canonical, "{}={}", key, value);
+    }
+    canonical.push('\n');
+
+    for name in signed_headers.split(';') {
+        let values: Vec<&str> = req.headers().get_all(name).iter().filter_map(|value| value.to_str().ok()).collect();
+        let value = match (values.is_empty(), name) {
+            (false, _) => values.iter().map(|value| collapse_spaces(value)).collect::<Vec<_>>().join(","),
+            // HTTP/2 requests carry the host in the `:authority` pseudo header
+            (true, "host") => req.uri().authority()?.as_str().to_string(),
+            _ => return None
+        };
+        let _ = writeln!(canonical, "{}:{}", name, value);
+    }
+    let _ = write!(canonical, "\n{}\n{}", signed_headers, UNSIGNED_PAYLOAD);
+    Some(canonical)
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> hmac::Key {
+    let sign = |key: &[u8], data: &str| hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes());
+    let key = sign(format!("AWS4{}", secret).as_bytes(), date);
+    let key = sign(key.as_ref(), region);
+    let key = sign(key.as_ref(), service);
+    let key = sign(key.as_ref(), SCOPE_TERMINATOR);
+    hmac::Key::new(hmac::HMAC_SHA256, key.as_ref())
+}
+
+// URI encoding as SigV4 defines it: every byte but the unreserved characters
+fn encode_uri(data: &[u8], out: &mut String) {
+    for byte in data {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(*byte as char),
+            _ => { let _ = write!(out, "%{:02X}", byte); }
+        }
+    }
+}
+
+fn collapse_spaces(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().fold(String::with_capacity(data.len() * 2), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None
+    }
+    (0..value.len()).step_by(2).map(|idx| u8::from_str_radix(value.get(idx..idx + 2)?, 16).ok()).collect()
+}
+
+// Signing times are in the ISO 8601 basic format, like `20130524T000000Z`
+fn parse_timestamp(value: &str) -> Option<u64> {
+    if value.len() != 16 || value.as_bytes()[8] != b'T' || !value.ends_with('Z') {
+        return None
+    }
+    let field = |range: std::ops::Range<usize>| value.get(range)?.parse::<u64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(9..11)?, field(11..13)?, field(13..15)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None
+    }
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146_097 + doe).saturating_sub(719_468)
+}
@@ -0,0 +1,266 @@
+use bytes::{Buf, Bytes, BytesMut};
+use once_cell::sync::OnceCell;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    str,
+    sync::Arc,
+    task::{Context, Poll}
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::tcp::Connection;
+
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+const V2_CMD_LOCAL: u8 = 0x20;
+const V2_CMD_PROXY: u8 = 0x21;
+const V2_AF_UNSPEC: u8 = 0x0;
+const V2_AF_INET: u8 = 0x1;
+const V2_AF_INET6: u8 = 0x2;
+const V2_AF_UNIX: u8 = 0x3;
+const V2_TLV_SSL: u8 = 0x20;
+const V2_CLIENT_SSL: u8 = 0x01;
+const READ_CHUNK: usize = 512;
+
+// Opt-in support for the PROXY protocol (v1 and v2) used by HAProxy and L4 load
+// balancers: once enabled, every connection is expected to start with a PROXY
+// header, carrying the address of the original client, and the ones which don't
+// get closed, as otherwise any client could spoof its address.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ProxyProtocol(bool);
+
+impl ProxyProtocol {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    pub fn wrap(&self, inner: Connection) -> ProxyStream {
+        let (pending, proxied) = match self.0 {
+            true => (Some(BytesMut::new()), ProxiedAddr(Some(Arc::default()))),
+            false => (None, ProxiedAddr(None))
+        };
+        ProxyStream { inner, pending, buffered: Bytes::new(), proxied }
+    }
+}
+
+// What the PROXY header told about the original client connection: `LOCAL` and
+// `UNKNOWN` headers, like the ones of load balancers health checks, carry none.
+#[derive(Clone, Copy, Default)]
+struct ProxiedClient {
+    addr: Option<SocketAddr>,
+    tls: bool
+}
+
+// Filled once the header gets read, which only happens when the connection is
+// first read from: the service reads it per request, as the header always
+// precedes the first request.
+#[derive(Clone)]
+pub(crate) struct ProxiedAddr(Option<Arc<OnceCell<ProxiedClient>>>);
+
+impl ProxiedAddr {
+    fn client(&self) -> Option<&ProxiedClient> {
+        self.0.as_ref().and_then(|cell| cell.get())
+    }
+
+    pub fn remote_addr(&self, peer: SocketAddr) -> SocketAddr {
+        self.client().and_then(|client| client.addr).unwrap_or(peer)
+    }
+
+    pub fn scheme(&self, scheme: &'static str) -> &'static str {
+        match self.client() {
+            Some(client) if client.tls => "https",
+            _ => scheme
+        }
+    }
+}
+
+pub(crate) struct ProxyStream {
+    inner: Connection,
+    pending: Option<BytesMut>,
+    buffered: Bytes,
+    proxied: ProxiedAddr
+}
+
+impl ProxyStream {
+    pub fn get_ref(&self) -> &Connection {
+        &self.inner
+    }
+
+    pub fn proxied(&self) -> ProxiedAddr {
+        self.proxied.clone()
+    }
+}
+
+impl AsyncRead for ProxyStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while let Some(pending) = this.pending.as_mut() {
+            match parse(pending) {
+                Ok(Some((client, len))) => {
+                    let mut rest = this.pending.take().unwrap();
+                    rest.advance(len);
+                    this.buffered = rest.freeze();
+                    if let Some(cell) = this.proxied.0.as_ref() {
+                        let _ = cell.set(client);
+                    }
+                    break
+                },
+                Ok(None) => {},
+                Err(err) => {
+                    log::warn!(
+                        "Invalid PROXY protocol header received from {}: {}",
+                        this.inner.remote_addr(), err
+                    );
+                    return Poll::Ready(Err(err))
+                }
+            }
+            let mut chunk = [0; READ_CHUNK];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            futures::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                return Poll::Ready(Ok(()))
+            }
+            pending.extend_from_slice(chunk_buf.filled());
+        }
+        if !this.buffered.is_empty() {
+            let len = this.buffered.len().min(buf.remaining());
+            buf.put_slice(&this.buffered.split_to(len));
+            return Poll::Ready(Ok(()))
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ProxyStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Returns the client and the header length once the whole header got buffered,
+// `None` while more data is needed.
+fn parse(buf: &[u8]) -> io::Result<Option<(ProxiedClient, usize)>> {
+    let v2_len = buf.len().min(V2_SIGNATURE.len());
+    if buf[..v2_len] == V2_SIGNATURE[..v2_len] {
+        return parse_v2(buf)
+    }
+    let v1_len = buf.len().min(V1_PREFIX.len());
+    if buf[..v1_len] == V1_PREFIX[..v1_len] {
+        return parse_v1(buf)
+    }
+    Err(invalid("missing PROXY protocol header"))
+}
+
+fn parse_v1(buf: &[u8]) -> io::Result<Option<(ProxiedClient, usize)>> {
+    let end = match buf.iter().take(V1_MAX_LEN).position(|&byte| byte == b'\n') {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LEN => return Err(invalid("PROXY protocol v1 header too long")),
+        None => return Ok(None)
+    };
+    if end < V1_PREFIX.len() || buf[end - 1] != b'\r' {
+        return Err(invalid("malformed PROXY protocol v1 header"))
+    }
+    let line = str::from_utf8(&buf[V1_PREFIX.len()..end - 1])
+        .map_err(|_| invalid("malformed PROXY protocol v1 header"))?;
+    let mut parts = line.split(' ');
+    let client = match parts.next() {
+        Some("UNKNOWN") => ProxiedClient::default(),
+        Some(proto @ ("TCP4" | "TCP6")) => {
+            let fields: Vec<&str> = parts.collect();
+            let (ip, port) = match fields[..] {
+                [src, dst, src_port, dst_port] => (
+                    src.parse::<IpAddr>().ok().filter(|ip| ip.is_ipv4() == (proto == "TCP4")),
+                    src_port.parse::<u16>().ok().filter(|_| {
+                        dst.parse::<IpAddr>().is_ok() && dst_port.parse::<u16>().is_ok()
+                    })
+                ),
+                _ => (None, None)
+            };
+            match (ip, port) {
+                (Some(ip), Some(port)) => ProxiedClient { addr: Some(SocketAddr::new(ip, port)), tls: false },
+                _ => return Err(invalid("malformed PROXY protocol v1 addresses"))
+            }
+        },
+        _ => return Err(invalid("unsupported PROXY protocol v1 transport"))
+    };
+    Ok(Some((client, end + 1)))
+}
+
+fn parse_v2(buf: &[u8]) -> io::Result<Option<(ProxiedClient, usize)>> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None)
+    }
+    let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if buf.len() < len {
+        return Ok(None)
+    }
+    let payload = &buf[V2_HEADER_LEN..len];
+    let client = match buf[12] {
+        V2_CMD_LOCAL => ProxiedClient::default(),
+        V2_CMD_PROXY => {
+            let (addr, tlvs) = match buf[13] >> 4 {
+                V2_AF_UNSPEC => (None, payload),
+                V2_AF_INET if payload.len() >= 12 => {
+                    let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+                    let port = u16::from_be_bytes([payload[8], payload[9]]);
+                    (Some(SocketAddr::new(ip.into(), port)), &payload[12..])
+                },
+                V2_AF_INET6 if payload.len() >= 36 => {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(&payload[..16]);
+                    let port = u16::from_be_bytes([payload[32], payload[33]]);
+                    (Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)), &payload[36..])
+                },
+                V2_AF_UNIX if payload.len() >= 216 => (None, &payload[216..]),
+                _ => return Err(invalid("malformed PROXY protocol v2 addresses"))
+            };
+            ProxiedClient { addr, tls: client_ssl(tlvs)? }
+        },
+        _ => return Err(invalid("unsupported PROXY protocol v2 version or command"))
+    };
+    Ok(Some((client, len)))
+}
+
+// Whether the `PP2_TYPE_SSL` TLV reports the client connected to the proxy over TLS
+fn client_ssl(mut tlvs: &[u8]) -> io::Result<bool> {
+    let mut tls = false;
+    while !tlvs.is_empty() {
+        if tlvs.len() < 3 {
+            return Err(invalid("malformed PROXY protocol v2 TLV"))
+        }
+        let len = 3 + u16::from_be_bytes([tlvs[1], tlvs[2]]) as usize;
+        if tlvs.len() < len {
+            return Err(invalid("malformed PROXY protocol v2 TLV"))
+        }
+        if tlvs[0] == V2_TLV_SSL && len > 3 {
+            tls = tlvs[3] & V2_CLIENT_SSL != 0;
+        }
+        tlvs = &tlvs[len..];
+    }
+    Ok(tls)
+}
@@ -1,15 +1,19 @@
-use pyo3::prelude::*;
+use hyper::Body;
+use pyo3::{prelude::*, types::PyString};
 use tokio::sync::oneshot;
 
 use crate::{
+    buffers::BufferBody,
     callbacks::CallbackWrapper,
+    cancellation::{CancelHandle, HandlerTask},
+    errors::Error,
+    http::{DisconnectPolicy, HeaderValidation, ProtocolConformance, response_status},
     runtime::RuntimeRef,
     ws::{HyperWebsocket, UpgradeData}
 };
 use super::{
-    errors::error_proto,
     io::{RSGIHTTPProtocol as HTTPProtocol, RSGIWebsocketProtocol as WebsocketProtocol},
-    types::{RSGIScope as Scope, Response}
+    types::{Coerced, HeadersArg, RSGIScope as Scope, Response}
 };
 
 
@@ -18,39 +22,52 @@ pub(crate) struct CallbackWatcherHTTP {
     #[pyo3(get)]
     proto: Py<HTTPProtocol>,
     #[pyo3(get)]
+    scope: Py<Scope>,
+    #[pyo3(get)]
     event_loop: PyObject,
     #[pyo3(get)]
-    context: PyObject
+    context: PyObject,
+    #[pyo3(get)]
+    cancellation: Option<Py<HandlerTask>>
 }
 
 impl CallbackWatcherHTTP {
     pub fn new(
         py: Python,
         cb: CallbackWrapper,
-        proto: HTTPProtocol
+        proto: HTTPProtocol,
+        scope: Py<Scope>,
+        cancellation: Option<CancelHandle>
     ) -> Self {
         Self {
             proto: Py::new(py, proto).unwrap(),
+            scope,
             event_loop: cb.context.event_loop(py).into(),
-            context: cb.context.context(py).into()
+            context: cb.context.context(py).into(),
+            cancellation: cancellation.map(|handle| handle.task(py))
         }
     }
-}
 
-#[pymethods]
-impl CallbackWatcherHTTP {
-    fn done(&mut self, py: Python) {
+    fn finish(&mut self, py: Python, failed: bool) {
         if let Ok(mut proto) = self.proto.as_ref(py).try_borrow_mut() {
             if let (Some(tx), Some(mut res)) = proto.tx() {
                 res.error();
                 let _ = tx.send(res);
             }
+            proto.close_stream(failed);
         }
     }
+}
+
+#[pymethods]
+impl CallbackWatcherHTTP {
+    fn done(&mut self, py: Python) {
+        self.finish(py, false)
+    }
 
     fn err(&mut self, py: Python) {
         log::warn!("Application callable raised an exception");
-        self.done(py)
+        self.finish(py, true)
     }
 }
 
@@ -59,6 +76,8 @@ pub(crate) struct CallbackWatcherWebsocket {
     #[pyo3(get)]
     proto: Py<WebsocketProtocol>,
     #[pyo3(get)]
+    scope: Py<Scope>,
+    #[pyo3(get)]
     event_loop: PyObject,
     #[pyo3(get)]
     context: PyObject
@@ -68,10 +87,12 @@ impl CallbackWatcherWebsocket {
     pub fn new(
         py: Python,
         cb: CallbackWrapper,
-        proto: WebsocketProtocol
+        proto: WebsocketProtocol,
+        scope: Scope
     ) -> Self {
         Self {
             proto: Py::new(py, proto).unwrap(),
+            scope: Py::new(py, scope).unwrap(),
             event_loop: cb.context.event_loop(py).into(),
             context: cb.context.context(py).into(),
         }
@@ -94,46 +115,104 @@ impl CallbackWatcherWebsocket {
     }
 }
 
+// Runs the application fast path, if any, returning the response it produced.
+fn call_fast_path(
+    py: Python,
+    cb: &CallbackWrapper,
+    scope: &Py<Scope>,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance
+) -> PyResult<Option<Response>> {
+    let fast_path = match &cb.fast_path {
+        Some(fast_path) => fast_path,
+        None => return Ok(None)
+    };
+    let ret = fast_path.call1(py, (scope.clone_ref(py),))?;
+    if ret.is_none(py) {
+        return Ok(None)
+    }
+    let (status, headers, body): (Coerced<i32>, HeadersArg, &PyAny) = ret.extract(py)?;
+    let mut response = Response::new();
+    response.head(
+        response_status(status.accept(conformance)?)?,
+        &headers.accept(conformance)?,
+        None,
+        header_validation
+    )?;
+    response.body = match body.downcast::<PyString>() {
+        Ok(string) => Body::from(string.to_str()?.to_owned()),
+        _ => Body::from(body.extract::<BufferBody>()?.0)
+    };
+    Ok(Some(response))
+}
+
 pub(crate) async fn call_rtb_http(
     cb: CallbackWrapper,
     rt: RuntimeRef,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
     req: hyper::Request<hyper::Body>,
     scope: Scope
-) -> PyResult<Response> {
+) -> Result<Response, Error> {
     let callback = cb.callback.clone();
+    let cancellation = CancelHandle::of(&req);
     let (tx, rx) = oneshot::channel();
-    let protocol = HTTPProtocol::new(rt, tx, req);
 
-    Python::with_gil(|py| {
-        callback.call1(py, (CallbackWatcherHTTP::new(py, cb, protocol), scope))
+    let fast_response = Python::with_gil(|py| -> PyResult<Option<Response>> {
+        let scope = Py::new(py, scope)?;
+        if let Some(response) = call_fast_path(py, &cb, &scope, header_validation, conformance)? {
+            return Ok(Some(response))
+        }
+        let protocol = HTTPProtocol::new(rt, disconnect_policy, header_validation, conformance, tx, req);
+        callback.call1(py, (CallbackWatcherHTTP::new(py, cb, protocol, scope, cancellation),))?;
+        Ok(None)
     })?;
+    if let Some(response) = fast_response {
+        return Ok(response)
+    }
 
     match rx.await {
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("RSGI protocol failure");
-            error_proto!()
-        }
+        _ => Err(Error::protocol("RSGI protocol failure"))
     }
 }
 
 pub(crate) async fn call_rtt_http(
     cb: CallbackWrapper,
     rt: RuntimeRef,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
     req: hyper::Request<hyper::Body>,
     scope: Scope
-) -> PyResult<Response> {
+) -> Result<Response, Error> {
     let callback = cb.callback.clone();
+    let cancellation = CancelHandle::of(&req);
     let (tx, rx) = oneshot::channel();
-    let protocol = HTTPProtocol::new(rt, tx, req);
 
     tokio::task::spawn_blocking(move || {
         Python::with_gil(|py| {
-            let _ = callback.call1(
-                py, (CallbackWatcherHTTP::new(py, cb, protocol), scope)
-            );
+            let scope = Py::new(py, scope).unwrap();
+            match call_fast_path(py, &cb, &scope, header_validation, conformance) {
+                Ok(Some(response)) => {
+                    let _ = tx.send(response);
+                },
+                Ok(None) => {
+                    let protocol = HTTPProtocol::new(rt, disconnect_policy, header_validation, conformance, tx, req);
+                    let _ = callback.call1(
+                        py, (CallbackWatcherHTTP::new(py, cb, protocol, scope, cancellation),)
+                    );
+                },
+                Err(err) => {
+                    log::warn!("Application fast path raised an exception: {}", err);
+                    let mut response = Response::new();
+                    response.error();
+                    let _ = tx.send(response);
+                }
+            }
         });
     });
 
@@ -141,10 +220,7 @@ pub(crate) async fn call_rtt_http(
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("RSGI protocol failure");
-            error_proto!()
-        }
+        _ => Err(Error::protocol("RSGI protocol failure"))
     }
 }
 
@@ -154,23 +230,20 @@ pub(crate) async fn call_rtb_ws(
     ws: HyperWebsocket,
     upgrade: UpgradeData,
     scope: Scope
-) -> PyResult<(i32, bool)> {
+) -> Result<(i32, bool), Error> {
     let callback = cb.callback.clone();
     let (tx, rx) = oneshot::channel();
     let protocol = WebsocketProtocol::new(rt, tx, ws, upgrade);
 
     Python::with_gil(|py| {
-        callback.call1(py, (CallbackWatcherWebsocket::new(py, cb, protocol), scope))
+        callback.call1(py, (CallbackWatcherWebsocket::new(py, cb, protocol, scope),))
     })?;
 
     match rx.await {
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("RSGI protocol failure");
-            error_proto!()
-        }
+        _ => Err(Error::protocol("RSGI protocol failure"))
     }
 }
 
@@ -180,7 +253,7 @@ pub(crate) async fn call_rtt_ws(
     ws: HyperWebsocket,
     upgrade: UpgradeData,
     scope: Scope
-) -> PyResult<(i32, bool)> {
+) -> Result<(i32, bool), Error> {
     let callback = cb.callback.clone();
     let (tx, rx) = oneshot::channel();
     let protocol = WebsocketProtocol::new(rt, tx, ws, upgrade);
@@ -188,7 +261,7 @@ pub(crate) async fn call_rtt_ws(
     tokio::task::spawn_blocking(move || {
         Python::with_gil(|py| {
             let _ = callback.call1(
-                py, (CallbackWatcherWebsocket::new(py, cb, protocol), scope)
+                py, (CallbackWatcherWebsocket::new(py, cb, protocol, scope),)
             );
         });
     });
@@ -197,9 +270,6 @@ pub(crate) async fn call_rtt_ws(
         Ok(res) => {
             Ok(res)
         },
-        _ => {
-            log::error!("RSGI protocol failure");
-            error_proto!()
-        }
+        _ => Err(Error::protocol("RSGI protocol failure"))
     }
 }
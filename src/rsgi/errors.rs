@@ -1,7 +1,9 @@
 use pyo3::{create_exception, exceptions::PyRuntimeError};
 
+use crate::errors::ProtocolError;
 
-create_exception!(_granian, RSGIProtocolError, PyRuntimeError, "RSGIProtocolError");
+
+create_exception!(_granian, RSGIProtocolError, ProtocolError, "RSGIProtocolError");
 create_exception!(_granian, RSGIProtocolClosed, PyRuntimeError, "RSGIProtocolClosed");
 
 macro_rules! error_proto {
@@ -2,18 +2,20 @@ use hyper::{
     Body,
     Request,
     Response,
-    StatusCode,
-    header::SERVER as HK_SERVER,
-    http::response::Builder as ResponseBuilder
+    StatusCode
 };
-use std::net::SocketAddr;
-use tokio::{fs::File, sync::mpsc};
-use tokio_util::codec::{BytesCodec, FramedRead};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::sync::mpsc;
 
 use crate::{
     callbacks::CallbackWrapper,
-    http::{HV_SERVER, response_500},
+    diagnostics::RequestTrace,
+    errors::Error,
+    http::{response_error, response_status},
+    files::RangeRequest,
+    metrics::METRICS,
     runtime::RuntimeRef,
+    workers::WorkerCtx,
     ws::{UpgradeData, is_upgrade_request as is_ws_upgrade, upgrade_intent as ws_upgrade}
 };
 use super::{
@@ -22,14 +24,8 @@ use super::{
 };
 
 
-async fn file_body(file_path: String) -> Body {
-    let file = File::open(file_path).await.unwrap();
-    let stream = FramedRead::new(file, BytesCodec::new());
-    Body::wrap_stream(stream)
-}
-
 macro_rules! default_scope {
-    ($server_addr:expr, $client_addr:expr, $req:expr, $scheme:expr) => {
+    ($server_addr:expr, $client_addr:expr, $req:expr, $scheme:expr, $ctx:expr) => {
         Scope::new(
             "http",
             $req.version(),
@@ -38,31 +34,58 @@ macro_rules! default_scope {
             $req.method().as_ref(),
             $server_addr,
             $client_addr,
-            $req.headers()
+            $req.headers(),
+            $ctx.duplicate_headers.clone(),
+            $ctx.path_decoding,
+            crate::http::request_content_length($req),
+            crate::deadlines::request_deadline($req),
+            crate::tls::request_session($req)
         )
     };
 }
 
 macro_rules! handle_http_response {
-    ($handler:expr, $rt:expr, $callback:expr, $req:expr, $scope:expr) => {
-        match $handler($callback, $rt, $req, $scope).await {
+    ($handler:expr, $rt:expr, $callback:expr, $ctx:expr, $req:expr, $scope:expr, $body_limit:expr) => {{
+        let file_range = RangeRequest::new(&$req);
+        let trace = RequestTrace::of(&$req);
+        trace.callback_started();
+        let span = $ctx.tracer.callback(&$req);
+        let parked = $ctx.long_polls.park(&$req);
+        let _watch = (!parked.is_parked()).then(|| $ctx.stack_dumps.watch(&$req));
+        let (req, cancel) = $ctx.cancellation.watch($req, &$callback.context, &$ctx.drain);
+        let req = parked.release(req);
+        let ret = $handler($callback, $rt, $ctx.disconnect_policy, $ctx.header_validation, $ctx.conformance, req, $scope).await;
+        cancel.disarm();
+        trace.callback_ended();
+        span.finish(match &ret {
+            Ok(pyres) => matches!(pyres.mode, ResponseType::Failed),
+            Err(_) => true
+        });
+        match ret {
             Ok(pyres) => {
                 let res = match pyres.mode {
-                    ResponseType::Body => {
+                    ResponseType::Body | ResponseType::Stream => {
                         pyres.inner.body(pyres.body)
                     },
-                    ResponseType::File => {
-                        pyres.inner.body(file_body(pyres.file.unwrap()).await)
+                    ResponseType::File => match pyres.inner.body(Body::empty()) {
+                        Ok(res) => Ok($ctx.files.respond(res, pyres.file.unwrap(), file_range).await),
+                        err => err
+                    },
+                    // applications reading aborted bodies fail as well
+                    ResponseType::Failed => {
+                        return $body_limit.respond(
+                            Error::app("Application callable ended without sending a response").response()
+                        )
                     }
                 };
                 match res {
                     Ok(res) => res,
-                    _ => response_500()
+                    Err(err) => Error::app(format!("Invalid response: {}", err)).response()
                 }
             },
-            _ => response_500()
+            Err(err) => err.response()
         }
-    };
+    }};
 }
 
 macro_rules! handle_request {
@@ -70,13 +93,61 @@ macro_rules! handle_request {
         pub(crate) async fn $func_name(
             rt: RuntimeRef,
             callback: CallbackWrapper,
+            ctx: Arc<WorkerCtx>,
             server_addr: SocketAddr,
             client_addr: SocketAddr,
             req: Request<Body>,
             scheme: &str
         ) -> Response<Body> {
-            let scope = default_scope!(server_addr, client_addr, &req, scheme);
-            handle_http_response!($handler, rt, callback, req, scope)
+            if let Some(res) = ctx.header_limits.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.duplicate_headers.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.allowed_hosts.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.path_decoding.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.presigned_urls.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.metrics_exposition.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.options_responses.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.synthetic_responses.respond(&req, client_addr, scheme) {
+                return res
+            }
+            let (req, upload) = match ctx.uploads.handle(req).await {
+                Ok(handled) => handled,
+                Err(res) => return res
+            };
+            let req = match ctx.decompression.decode(req).await {
+                Ok(req) => req,
+                Err(res) => return res
+            };
+            if let Some(res) = ctx.body_limit.check(&req) {
+                return res
+            }
+            let req = ctx.request_filters.apply(req);
+            let (req, body_limit) = ctx.body_limit.apply(req);
+            let compress = ctx.compression.negotiate(&req);
+            let filter = ctx.response_filters.request(&req);
+            let (req, grpc) = ctx.grpc_web.translate(req);
+            let (req, transcode) = match ctx.transcoding.translate(req).await {
+                Ok(translated) => translated,
+                Err(res) => return res
+            };
+            let scope = default_scope!(server_addr, client_addr, &req, scheme, ctx);
+            let scratch = scope.scratch().guard();
+            scratch.attach(ctx.response_headers.apply(compress.respond(filter.respond(grpc.respond(transcode.respond(upload.respond(body_limit.respond(
+                handle_http_response!($handler, rt, callback, ctx, req, scope, body_limit)
+            ))).await)))))
         }
     };
 }
@@ -86,47 +157,100 @@ macro_rules! handle_request_with_ws {
         pub(crate) async fn $func_name(
             rt: RuntimeRef,
             callback: CallbackWrapper,
+            ctx: Arc<WorkerCtx>,
             server_addr: SocketAddr,
             client_addr: SocketAddr,
             req: Request<Body>,
             scheme: &str
         ) -> Response<Body> {
-            let mut scope = default_scope!(server_addr, client_addr, &req, scheme);
+            if let Some(res) = ctx.header_limits.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.duplicate_headers.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.allowed_hosts.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.path_decoding.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.presigned_urls.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.metrics_exposition.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.options_responses.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.synthetic_responses.respond(&req, client_addr, scheme) {
+                return res
+            }
+            let (req, upload) = match ctx.uploads.handle(req).await {
+                Ok(handled) => handled,
+                Err(res) => return res
+            };
+            let req = match ctx.decompression.decode(req).await {
+                Ok(req) => req,
+                Err(res) => return res
+            };
+            if let Some(res) = ctx.body_limit.check(&req) {
+                return res
+            }
+            let req = ctx.request_filters.apply(req);
+            let (req, body_limit) = ctx.body_limit.apply(req);
+            let compress = ctx.compression.negotiate(&req);
+            let filter = ctx.response_filters.request(&req);
+            let (req, grpc) = ctx.grpc_web.translate(req);
+            let (req, transcode) = match ctx.transcoding.translate(req).await {
+                Ok(translated) => translated,
+                Err(res) => return res
+            };
+            let mut scope = default_scope!(server_addr, client_addr, &req, scheme, ctx);
 
             if is_ws_upgrade(&req) {
+                if !ctx.websocket_origins.allows(&req) {
+                    return ctx.response_headers.apply(
+                        response_error(StatusCode::FORBIDDEN, "", None)
+                    )
+                }
+                let ws_metrics = METRICS.websocket_route(ctx.metrics_routes.label(req.uri().path()));
+                let ws_session = ctx.access_log.websocket(&req, client_addr);
                 scope.set_proto("ws");
 
-                match ws_upgrade(req, None) {
+                match ws_upgrade(req, None, ctx.websocket_utf8) {
                     Ok((res, ws)) => {
                         let rth = rt.clone();
                         let (restx, mut resrx) = mpsc::channel(1);
+                        let session = ctx.drain.session();
 
                         rt.inner.spawn(async move {
+                            let _session = session;
                             let tx_ref = restx.clone();
 
                             match $handler_ws(
                                 callback,
                                 rth,
                                 ws,
-                                UpgradeData::new(res, restx),
+                                UpgradeData::new(res, restx, ws_metrics, ws_session),
                                 scope
                             ).await {
                                 Ok((status, consumed)) => {
+                                    // closing without accepting denies the handshake
                                     if !consumed {
-                                        let _ = tx_ref.send(
-                                            ResponseBuilder::new()
-                                                .status(
-                                                    StatusCode::from_u16(status as u16)
-                                                        .unwrap_or(StatusCode::FORBIDDEN)
-                                                )
-                                                .header(HK_SERVER, HV_SERVER)
-                                                .body(Body::from(""))
-                                                .unwrap()
-                                        ).await;
+                                        let res = match status {
+                                            0 => response_error(StatusCode::FORBIDDEN, "", None),
+                                            status => match response_status(status) {
+                                                Ok(status) => response_error(status, "", None),
+                                                Err(err) => Error::from(err).response()
+                                            }
+                                        };
+                                        let _ = tx_ref.send(res).await;
                                     }
                                 },
-                                _ => {
-                                    let _ = tx_ref.send(response_500()).await;
+                                Err(err) => {
+                                    let _ = tx_ref.send(err.response()).await;
                                 }
                             }
                         });
@@ -134,22 +258,26 @@ macro_rules! handle_request_with_ws {
                         return match resrx.recv().await {
                             Some(res) => {
                                 resrx.close();
-                                res
+                                ctx.response_headers.apply(res)
                             },
-                            _ => response_500()
+                            _ => Error::protocol("Websocket handler returned no response").response()
                         }
                     },
                     Err(err) => {
-                        return ResponseBuilder::new()
-                            .status(StatusCode::BAD_REQUEST)
-                            .header(HK_SERVER, HV_SERVER)
-                            .body(Body::from(format!("{}", err)))
-                            .unwrap()
+                        return ctx.response_headers.apply(
+                            {
+                                let detail = err.to_string();
+                                response_error(StatusCode::BAD_REQUEST, detail.clone(), Some(detail))
+                            }
+                        )
                     }
                 }
             }
 
-            handle_http_response!($handler_req, rt, callback, req, scope)
+            let scratch = scope.scratch().guard();
+            scratch.attach(ctx.response_headers.apply(compress.respond(filter.respond(grpc.respond(transcode.respond(upload.respond(body_limit.respond(
+                handle_http_response!($handler_req, rt, callback, ctx, req, scope, body_limit)
+            ))).await)))))
         }
 
     };
@@ -1,45 +1,231 @@
-use bytes::Buf;
-use futures::{sink::SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
-use hyper::{Body, Request};
-use pyo3::prelude::*;
+use bytes::{Buf, Bytes};
+use futures::{sink::SinkExt, stream::{SplitSink, Stream, StreamExt}};
+use hyper::{
+    Body,
+    Request,
+    StatusCode,
+    body::HttpBody,
+    ext::ReasonPhrase,
+    header::{CONNECTION, HeaderMap, HeaderName, HeaderValue, UPGRADE},
+    upgrade::Upgraded
+};
+use pyo3::{ffi, prelude::*, AsPyPointer};
+use pyo3::exceptions::{PyBufferError, PyStopAsyncIteration};
 use pyo3::types::{PyBytes, PyString};
-use std::sync::Arc;
-use tokio_tungstenite::WebSocketStream;
-use tokio::sync::{oneshot, Mutex};
-use tungstenite::Message;
+use std::{
+    io,
+    os::raw::{c_char, c_int, c_void},
+    pin::Pin,
+    ptr,
+    sync::{Arc, atomic::{AtomicBool, AtomicU8, Ordering}},
+    task::{Context, Poll}
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    sync::{mpsc, oneshot, Mutex}
+};
+use tungstenite::{Message, protocol::frame::{Frame, coding::{Data as OpData, OpCode}}};
 
 use crate::{
+    buffers::BufferBody,
+    diagnostics::RequestTrace,
+    http::{
+        DisconnectPolicy,
+        HeaderValidation,
+        ProtocolConformance,
+        ResponseEvent,
+        ResponseState,
+        aborted_body,
+        read_body,
+        reason_phrase,
+        response_status
+    },
     runtime::{RuntimeRef, future_into_py},
-    ws::{HyperWebsocket, UpgradeData}
+    ws::{
+        Fragment,
+        FragmentQueue,
+        HyperWebsocket,
+        InboundMessages,
+        UpgradeData,
+        WebsocketStats,
+        WebsocketTransport,
+        fail_invalid_payload, invalid_payload_close}
 };
-use super::{errors::{error_proto, error_stream}, types::{Response, ResponseType}};
+use super::{errors::{error_proto, error_stream}, types::{Coerced, HeadersArg, Response, ResponseType}};
+
+
+const BUFFER_FORMAT: &[u8] = b"B\0";
+// Chunks of streamed responses queued before the application has to wait
+const STREAM_QUEUE_SIZE: usize = 8;
+
+// Request body chunk exposing the underlying Rust buffer through the buffer protocol
+#[pyclass(module="granian._granian")]
+pub(crate) struct RSGIBodyChunk {
+    data: Option<Bytes>,
+    exports: usize
+}
+
+impl RSGIBodyChunk {
+    fn new(data: Bytes) -> Self {
+        Self { data: Some(data), exports: 0 }
+    }
+}
+
+#[pymethods]
+impl RSGIBodyChunk {
+    unsafe fn __getbuffer__(
+        mut slf: PyRefMut<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"))
+        }
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Body chunks are read-only"))
+        }
+        let (buf, len) = match &slf.data {
+            Some(data) => (data.as_ptr(), data.len()),
+            None => return Err(PyBufferError::new_err("Body chunk was released"))
+        };
+
+        (*view).obj = ffi::_Py_NewRef(slf.as_ptr());
+        (*view).buf = buf as *mut c_void;
+        (*view).len = len as isize;
+        (*view).readonly = 1;
+        (*view).itemsize = 1;
+        (*view).format = if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+            BUFFER_FORMAT.as_ptr() as *mut c_char
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if (flags & ffi::PyBUF_ND) == ffi::PyBUF_ND {
+            &mut (*view).len
+        } else {
+            ptr::null_mut()
+        };
+        (*view).strides = if (flags & ffi::PyBUF_STRIDES) == ffi::PyBUF_STRIDES {
+            &mut (*view).itemsize
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+
+        slf.exports += 1;
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(mut slf: PyRefMut<'_, Self>, _view: *mut ffi::Py_buffer) {
+        slf.exports -= 1;
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.as_ref().map_or(0, |data| data.len())
+    }
 
+    fn release(&mut self) -> PyResult<()> {
+        if self.exports > 0 {
+            return Err(PyBufferError::new_err("Body chunk has exported buffers"))
+        }
+        self.data = None;
+        Ok(())
+    }
+}
 
 #[pyclass(module="granian._granian")]
 pub(crate) struct RSGIHTTPProtocol {
     rt: RuntimeRef,
     tx: Option<oneshot::Sender<Response>>,
     request: Arc<Mutex<Request<Body>>>,
-    response: Option<Response>
+    response: Option<Response>,
+    state: ResponseState,
+    disconnect_policy: DisconnectPolicy,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
+    trace: RequestTrace,
+    stream: Option<Arc<ResponseStream>>
 }
 
 impl RSGIHTTPProtocol {
     pub fn new(
         rt: RuntimeRef,
+        disconnect_policy: DisconnectPolicy,
+        header_validation: HeaderValidation,
+        conformance: ProtocolConformance,
         tx: oneshot::Sender<Response>,
         request: Request<Body>
     ) -> Self {
         Self {
-            rt: rt,
+            rt,
             tx: Some(tx),
+            trace: RequestTrace::of(&request),
             request: Arc::new(Mutex::new(request)),
-            response: Some(Response::new())
+            response: Some(Response::new()),
+            state: ResponseState::Pending,
+            disconnect_policy,
+            header_validation,
+            conformance,
+            stream: None
+        }
+    }
+
+    fn send(&mut self, response: Response) -> PyResult<()> {
+        self.state = ResponseState::Completed;
+        if let Some(tx) = self.tx.take() {
+            if tx.send(response).is_err() {
+                return self.disconnect_policy.apply(
+                    || super::errors::RSGIProtocolClosed::new_err("RSGI transport is closed")
+                )
+            }
         }
+        Ok(())
+    }
+
+    // Whether a response can be sent still, the lenient conformance ignoring
+    // the ones following the first
+    fn accepts_response(&self) -> PyResult<bool> {
+        Ok(self.conformance.next_state(self.state, ResponseEvent::Whole)?.is_some())
+    }
+
+    // The response to send, with its head set, unless already sent. It stays
+    // around when the head gets refused, for the application to send another.
+    fn start_response(
+        &mut self,
+        status: StatusCode,
+        headers: &[(impl AsRef<str>, impl AsRef<str>)],
+        reason: Option<ReasonPhrase>
+    ) -> PyResult<Option<Response>> {
+        if let Some(response) = self.response.as_mut() {
+            response.head(status, headers, reason, self.header_validation)?;
+        }
+        Ok(self.response.take())
+    }
+
+    // Same as starting the response, with the arguments the application passed
+    fn start_app_response(
+        &mut self,
+        status: Coerced<i32>,
+        headers: HeadersArg,
+        reason: Option<&str>
+    ) -> PyResult<Option<Response>> {
+        let (status, reason) = response_head(status.accept(self.conformance)?, reason)?;
+        let headers = headers.accept(self.conformance)?;
+        self.start_response(status, &headers, reason)
     }
 
     pub fn tx(&mut self) -> (Option<oneshot::Sender<Response>>, Option<Response>) {
         return (self.tx.take(), self.response.take())
     }
+
+    // Ends the streamed response once the application returned, aborting it
+    // when the application failed
+    pub fn close_stream(&mut self, failed: bool) {
+        if let Some(stream) = self.stream.take() {
+            stream.close(failed);
+        }
+    }
 }
 
 #[pymethods]
@@ -48,7 +234,13 @@ impl RSGIHTTPProtocol {
         let req_ref = self.request.clone();
         future_into_py(self.rt.clone(), py, async move {
             let mut req = req_ref.lock().await;
-            let mut body = hyper::body::to_bytes(&mut *req).await.unwrap();
+            let mut body = match read_body(req.body_mut()).await {
+                Ok(body) => body,
+                _ => {
+                    RequestTrace::of(&req).client_disconnected();
+                    return error_stream!()
+                }
+            };
             Ok(Python::with_gil(|py| {
                 PyBytes::new_with(py, body.len(), |bytes: &mut [u8]| {
                     body.copy_to_slice(bytes);
@@ -58,65 +250,353 @@ impl RSGIHTTPProtocol {
         })
     }
 
-    #[args(status="200", headers="vec![]")]
-    fn response_empty(&mut self, status: u16, headers: Vec<(&str, &str)>) {
-        if let Some(mut response) = self.response.take() {
-            response.head(status, &headers);
-            if let Some(tx) = self.tx.take() {
-                let _ = tx.send(response);
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'p>(&self, py: Python<'p>) -> PyResult<Option<&'p PyAny>> {
+        let req_ref = self.request.clone();
+        Ok(Some(future_into_py(self.rt.clone(), py, async move {
+            match next_chunk(&req_ref).await? {
+                Some(chunk) => Ok(chunk),
+                None => Err(PyStopAsyncIteration::new_err(()))
             }
+        })?))
+    }
+
+    // Same as iterating, with an empty chunk telling the body is over
+    fn receive<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let req_ref = self.request.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            Ok(next_chunk(&req_ref).await?.unwrap_or_else(|| RSGIBodyChunk::new(Bytes::new())))
+        })
+    }
+
+    #[args(status="Coerced::from(200)", headers="Coerced::from(vec![])", reason="None")]
+    fn response_empty(&mut self, status: Coerced<i32>, headers: HeadersArg, reason: Option<&str>) -> PyResult<()> {
+        if !self.accepts_response()? {
+            return Ok(())
+        }
+        if let Some(response) = self.start_app_response(status, headers, reason)? {
+            return self.send(response)
         }
+        Ok(())
     }
 
-    #[args(status="200", headers="vec![]")]
-    fn response_bytes(&mut self, status: u16, headers: Vec<(&str, &str)>, body: Vec<u8>) {
-        if let Some(mut response) = self.response.take() {
-            response.head(status, &headers);
+    #[args(status="Coerced::from(200)", headers="Coerced::from(vec![])", reason="None")]
+    fn response_bytes(
+        &mut self,
+        status: Coerced<i32>,
+        headers: HeadersArg,
+        body: Coerced<Bytes>,
+        reason: Option<&str>
+    ) -> PyResult<()> {
+        if !self.accepts_response()? {
+            return Ok(())
+        }
+        let body = body.accept(self.conformance)?;
+        if let Some(mut response) = self.start_app_response(status, headers, reason)? {
             response.body = Body::from(body);
-            if let Some(tx) = self.tx.take() {
-                let _ = tx.send(response);
-            }
+            return self.send(response)
         }
+        Ok(())
     }
 
-    #[args(status="200", headers="vec![]")]
-    fn response_str(&mut self, status: u16, headers: Vec<(&str, &str)>, body: String) {
-        if let Some(mut response) = self.response.take() {
-            response.head(status, &headers);
+    #[args(status="Coerced::from(200)", headers="Coerced::from(vec![])", reason="None")]
+    fn response_str(
+        &mut self,
+        status: Coerced<i32>,
+        headers: HeadersArg,
+        body: Coerced<String>,
+        reason: Option<&str>
+    ) -> PyResult<()> {
+        if !self.accepts_response()? {
+            return Ok(())
+        }
+        let body = body.accept(self.conformance)?;
+        if let Some(mut response) = self.start_app_response(status, headers, reason)? {
             response.body = Body::from(body);
-            if let Some(tx) = self.tx.take() {
-                let _ = tx.send(response);
-            }
+            return self.send(response)
         }
+        Ok(())
     }
 
-    #[args(status="200", headers="vec![]")]
-    fn response_file(&mut self, status: u16, headers: Vec<(&str, &str)>, file: String) {
-        if let Some(mut response) = self.response.take() {
+    #[args(status="Coerced::from(200)", headers="Coerced::from(vec![])", reason="None")]
+    fn response_file(
+        &mut self,
+        status: Coerced<i32>,
+        headers: HeadersArg,
+        file: String,
+        reason: Option<&str>
+    ) -> PyResult<()> {
+        if !self.accepts_response()? {
+            return Ok(())
+        }
+        if let Some(mut response) = self.start_app_response(status, headers, reason)? {
             response.mode = ResponseType::File;
-            response.head(status, &headers);
             response.file = Some(file);
-            if let Some(tx) = self.tx.take() {
-                let _ = tx.send(response);
+            return self.send(response)
+        }
+        Ok(())
+    }
+
+    // Ignored streams get a transport detached from the client, unless the
+    // response sent was already streamed, which keeps going
+    #[args(status="Coerced::from(200)", headers="Coerced::from(vec![])", reason="None")]
+    fn response_stream(
+        &mut self,
+        status: Coerced<i32>,
+        headers: HeadersArg,
+        reason: Option<&str>
+    ) -> PyResult<RSGIHTTPStreamTransport> {
+        if !self.accepts_response()? {
+            let stream = match &self.stream {
+                Some(stream) => stream.clone(),
+                None => ResponseStream::new(self.trace.clone()).0
+            };
+            return Ok(RSGIHTTPStreamTransport { rt: self.rt.clone(), stream, disconnect_policy: self.disconnect_policy })
+        }
+        let (stream, body) = ResponseStream::new(self.trace.clone());
+        if let Some(mut response) = self.start_app_response(status, headers, reason)? {
+            response.mode = ResponseType::Stream;
+            response.body = body;
+            self.stream = Some(stream.clone());
+            self.send(response)?;
+        }
+        Ok(RSGIHTTPStreamTransport { rt: self.rt.clone(), stream, disconnect_policy: self.disconnect_policy })
+    }
+
+    // Switches protocols on requests asking for an upgrade other than websockets,
+    // handing the raw connection over to the application.
+    #[args(headers="vec![]")]
+    fn upgrade<'p>(&mut self, py: Python<'p>, headers: Vec<(&str, &str)>) -> PyResult<&'p PyAny> {
+        self.state.next(ResponseEvent::Whole)?;
+        let (protocol, on_upgrade) = match (self.response.is_some(), self.request.try_lock()) {
+            (true, Ok(mut req)) => match req.headers().get(UPGRADE).cloned() {
+                Some(protocol) => (protocol, hyper::upgrade::on(&mut *req)),
+                None => return error_proto!()
+            },
+            _ => return error_proto!()
+        };
+        let mut response = self.start_response(StatusCode::SWITCHING_PROTOCOLS, &headers, None)?.unwrap();
+        let rh = response.inner.headers_mut().unwrap();
+        rh.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        rh.insert(UPGRADE, protocol);
+        self.send(response)?;
+
+        let rt = self.rt.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            match on_upgrade.await {
+                Ok(upgraded) => Ok(Python::with_gil(|py| {
+                    RSGIUpgradedTransport::new(rt, upgraded).into_py(py)
+                })),
+                _ => error_stream!()
             }
+        })
+    }
+}
+
+// The body is read as it arrives, one chunk at a time
+async fn next_chunk(req: &Mutex<Request<Body>>) -> PyResult<Option<RSGIBodyChunk>> {
+    let mut req = req.lock().await;
+    match req.body_mut().data().await {
+        Some(Ok(chunk)) => Ok(Some(RSGIBodyChunk::new(chunk))),
+        None if req.body_mut().trailers().await.is_ok() => Ok(None),
+        _ => {
+            RequestTrace::of(&req).client_disconnected();
+            error_stream!()
         }
     }
 }
 
+// Shared by the protocol and the stream transport: the sender gets dropped once
+// the application returned, ending the body after the chunks still queued.
+struct ResponseStream {
+    tx: std::sync::Mutex<Option<mpsc::Sender<io::Result<Bytes>>>>,
+    aborted: AtomicBool,
+    trace: RequestTrace
+}
+
+impl ResponseStream {
+    fn new(trace: RequestTrace) -> (Arc<Self>, Body) {
+        let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+        let stream = Arc::new(Self { tx: std::sync::Mutex::new(Some(tx)), aborted: AtomicBool::new(false), trace });
+        (stream.clone(), Body::wrap_stream(ResponseStreamBody { rx, stream }))
+    }
+
+    fn sender(&self) -> Option<mpsc::Sender<io::Result<Bytes>>> {
+        self.tx.lock().unwrap().clone()
+    }
+
+    fn close(&self, failed: bool) {
+        self.aborted.store(failed, Ordering::Relaxed);
+        self.tx.lock().unwrap().take();
+    }
+}
+
+struct ResponseStreamBody {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    stream: Arc<ResponseStream>
+}
+
+impl Stream for ResponseStreamBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(None) if self.stream.aborted.swap(false, Ordering::Relaxed) => {
+                Poll::Ready(Some(Err(aborted_body(&self.stream.trace, "application failed while streaming"))))
+            },
+            ret => ret
+        }
+    }
+}
+
+#[pyclass(module="granian._granian")]
+pub(crate) struct RSGIHTTPStreamTransport {
+    rt: RuntimeRef,
+    stream: Arc<ResponseStream>,
+    disconnect_policy: DisconnectPolicy
+}
+
+impl RSGIHTTPStreamTransport {
+    // Waits for room in the queue, so the application can't outpace the client
+    fn send<'p>(&self, py: Python<'p>, data: Bytes) -> PyResult<&'p PyAny> {
+        let tx = self.stream.sender();
+        let disconnect_policy = self.disconnect_policy;
+        future_into_py(self.rt.clone(), py, async move {
+            match tx {
+                Some(tx) => match tx.send(Ok(data)).await {
+                    Ok(_) => Ok(()),
+                    _ => disconnect_policy.apply(
+                        || super::errors::RSGIProtocolClosed::new_err("RSGI transport is closed")
+                    )
+                },
+                None => error_stream!()
+            }
+        })
+    }
+}
+
+#[pymethods]
+impl RSGIHTTPStreamTransport {
+    fn send_bytes<'p>(&self, py: Python<'p>, data: BufferBody) -> PyResult<&'p PyAny> {
+        self.send(py, data.0)
+    }
+
+    fn send_str<'p>(&self, py: Python<'p>, data: String) -> PyResult<&'p PyAny> {
+        self.send(py, Bytes::from(data))
+    }
+}
+
+const UPGRADED_READ_SIZE: usize = 65536;
+
+#[pyclass(module="granian._granian")]
+pub(crate) struct RSGIUpgradedTransport {
+    rt: RuntimeRef,
+    rx: Arc<Mutex<ReadHalf<Upgraded>>>,
+    tx: Arc<Mutex<WriteHalf<Upgraded>>>
+}
+
+impl RSGIUpgradedTransport {
+    fn new(rt: RuntimeRef, upgraded: Upgraded) -> Self {
+        let (rx, tx) = tokio::io::split(upgraded);
+        Self { rt, rx: Arc::new(Mutex::new(rx)), tx: Arc::new(Mutex::new(tx)) }
+    }
+}
+
+#[pymethods]
+impl RSGIUpgradedTransport {
+    #[args(size="UPGRADED_READ_SIZE")]
+    fn read<'p>(&self, py: Python<'p>, size: usize) -> PyResult<&'p PyAny> {
+        let transport = self.rx.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            let mut buf = vec![0; size];
+            match transport.lock().await.read(&mut buf).await {
+                Ok(read) => Ok(Python::with_gil(|py| {
+                    PyBytes::new(py, &buf[..read]).to_object(py)
+                })),
+                _ => error_stream!()
+            }
+        })
+    }
+
+    fn write<'p>(&self, py: Python<'p>, data: BufferBody) -> PyResult<&'p PyAny> {
+        let transport = self.tx.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            let mut stream = transport.lock().await;
+            match stream.write_all(&data.0).await {
+                Ok(_) if stream.flush().await.is_ok() => Ok(()),
+                _ => error_stream!()
+            }
+        })
+    }
+
+    fn close<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let transport = self.tx.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            let _ = transport.lock().await.shutdown().await;
+            Ok(())
+        })
+    }
+}
+
 #[pyclass(module="granian._granian")]
 pub(crate) struct RSGIWebsocketTransport {
     rt: RuntimeRef,
-    tx: Arc<Mutex<SplitSink<WebSocketStream<hyper::upgrade::Upgraded>, Message>>>,
-    rx: Arc<Mutex<SplitStream<WebSocketStream<hyper::upgrade::Upgraded>>>>
+    tx: Arc<Mutex<SplitSink<WebsocketTransport, Message>>>,
+    rx: Arc<Mutex<InboundMessages>>,
+    stats: WebsocketStats,
+    fragmented: AtomicU8
 }
 
+// Kind of the outgoing message being sent in fragments, if any
+const FRAGMENTED_NONE: u8 = 0;
+const FRAGMENTED_BYTES: u8 = 1;
+const FRAGMENTED_STR: u8 = 2;
+
 impl RSGIWebsocketTransport {
     pub fn new(
         rt: RuntimeRef,
-        transport: WebSocketStream<hyper::upgrade::Upgraded>
+        transport: WebsocketTransport,
+        stats: WebsocketStats,
+        fragments: Option<FragmentQueue>
     ) -> Self {
         let (tx, rx) = transport.split();
-        Self { rt: rt, tx: Arc::new(Mutex::new(tx)), rx: Arc::new(Mutex::new(rx)) }
+        Self {
+            rt: rt,
+            tx: Arc::new(Mutex::new(tx)),
+            rx: Arc::new(Mutex::new(InboundMessages::new(rx, fragments))),
+            stats,
+            fragmented: AtomicU8::new(FRAGMENTED_NONE)
+        }
+    }
+
+    // The first fragment opens a message of the given kind, the following ones continue
+    // it until the last one: other data messages can't be sent in the meantime.
+    fn send_fragment<'p>(&self, py: Python<'p>, data: Vec<u8>, kind: u8, last: bool) -> PyResult<&'p PyAny> {
+        let opcode = match self.fragmented.load(Ordering::Relaxed) {
+            FRAGMENTED_NONE if kind == FRAGMENTED_STR => OpCode::Data(OpData::Text),
+            FRAGMENTED_NONE => OpCode::Data(OpData::Binary),
+            current if current == kind => OpCode::Data(OpData::Continue),
+            _ => return error_proto!()
+        };
+        self.fragmented.store(if last { FRAGMENTED_NONE } else { kind }, Ordering::Relaxed);
+        let transport = self.tx.clone();
+        let stats = self.stats.clone();
+        future_into_py(self.rt.clone(), py, async move {
+            if let Ok(mut stream) = transport.try_lock() {
+                let size = data.len();
+                return match stream.send(Message::Frame(Frame::message(data, opcode, last))).await {
+                    Ok(_) => {
+                        stats.outbound_fragment(size, last);
+                        Ok(())
+                    },
+                    _ => error_stream!()
+                }
+            }
+            error_proto!()
+        })
     }
 }
 
@@ -124,17 +604,24 @@ impl RSGIWebsocketTransport {
 impl RSGIWebsocketTransport {
     fn receive<'p>(&self, py: Python<'p>) -> PyResult<&'p PyAny> {
         let transport = self.rx.clone();
+        let sink = self.tx.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             if let Ok(mut stream) = transport.try_lock() {
                 loop {
                     match stream.next().await {
                         Some(recv) => {
                             match recv {
-                                Ok(Message::Ping(_)) => {
+                                Ok((Message::Ping(_), _)) => {
                                     continue
                                 },
-                                Ok(message) => {
-                                    return message_into_py(message)
+                                Ok((message, fragment)) => {
+                                    stats.inbound_fragment(&message, fragment);
+                                    return message_into_py(message, fragment)
+                                },
+                                Err(tungstenite::Error::Utf8) => {
+                                    fail_invalid_payload(&mut *(sink.lock().await), &stats).await;
+                                    return message_into_py(invalid_payload_close(), Fragment::None)
                                 },
                                 _ => {
                                     break
@@ -153,11 +640,19 @@ impl RSGIWebsocketTransport {
     }
 
     fn send_bytes<'p>(&self, py: Python<'p>, data: Vec<u8>) -> PyResult<&'p PyAny> {
+        if self.fragmented.load(Ordering::Relaxed) != FRAGMENTED_NONE {
+            return error_proto!()
+        }
         let transport = self.tx.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             if let Ok(mut stream) = transport.try_lock() {
+                let size = data.len();
                 return match stream.send(Message::Binary(data)).await {
-                    Ok(_) => Ok(()),
+                    Ok(_) => {
+                        stats.outbound(size);
+                        Ok(())
+                    },
                     _ => error_stream!()
                 }
             }
@@ -166,17 +661,35 @@ impl RSGIWebsocketTransport {
     }
 
     fn send_str<'p>(&self, py: Python<'p>, data: String) -> PyResult<&'p PyAny> {
+        if self.fragmented.load(Ordering::Relaxed) != FRAGMENTED_NONE {
+            return error_proto!()
+        }
         let transport = self.tx.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             if let Ok(mut stream) = transport.try_lock() {
+                let size = data.len();
                 return match stream.send(Message::Text(data)).await {
-                    Ok(_) => Ok(()),
+                    Ok(_) => {
+                        stats.outbound(size);
+                        Ok(())
+                    },
                     _ => error_stream!()
                 }
             }
             error_proto!()
         })
     }
+
+    #[args(last="false")]
+    fn send_bytes_fragment<'p>(&self, py: Python<'p>, data: Vec<u8>, last: bool) -> PyResult<&'p PyAny> {
+        self.send_fragment(py, data, FRAGMENTED_BYTES, last)
+    }
+
+    #[args(last="false")]
+    fn send_str_fragment<'p>(&self, py: Python<'p>, data: String, last: bool) -> PyResult<&'p PyAny> {
+        self.send_fragment(py, data.into_bytes(), FRAGMENTED_STR, last)
+    }
 }
 
 #[pyclass(module="granian._granian")]
@@ -185,7 +698,8 @@ pub(crate) struct RSGIWebsocketProtocol {
     tx: Option<oneshot::Sender<(i32, bool)>>,
     websocket: Arc<Mutex<HyperWebsocket>>,
     upgrade: Option<UpgradeData>,
-    status: i32
+    status: i32,
+    stats: WebsocketStats
 }
 
 impl RSGIWebsocketProtocol {
@@ -193,12 +707,13 @@ impl RSGIWebsocketProtocol {
         rt: RuntimeRef,
         tx: oneshot::Sender<(i32, bool)>,
         websocket: HyperWebsocket,
-        upgrade: UpgradeData
+        mut upgrade: UpgradeData
     ) -> Self {
         Self {
             rt: rt,
             tx: Some(tx),
             websocket: Arc::new(Mutex::new(websocket)),
+            stats: WebsocketStats::new(upgrade.metrics.clone(), upgrade.session.take()),
             upgrade: Some(upgrade),
             status: 0
         }
@@ -225,12 +740,14 @@ enum WebsocketMessageType {
 #[pyclass]
 struct WebsocketInboundCloseMessage {
     #[pyo3(get)]
-    kind: usize
+    kind: usize,
+    #[pyo3(get)]
+    fragment: usize
 }
 
 impl WebsocketInboundCloseMessage {
     pub fn new() -> Self {
-        Self { kind: WebsocketMessageType::Close as usize }
+        Self { kind: WebsocketMessageType::Close as usize, fragment: Fragment::None as usize }
     }
 }
 
@@ -239,12 +756,14 @@ struct WebsocketInboundBytesMessage {
     #[pyo3(get)]
     kind: usize,
     #[pyo3(get)]
-    data: Py<PyBytes>
+    data: Py<PyBytes>,
+    #[pyo3(get)]
+    fragment: usize
 }
 
 impl WebsocketInboundBytesMessage {
-    pub fn new(data:Py<PyBytes>) -> Self {
-        Self { kind: WebsocketMessageType::Bytes as usize, data: data }
+    pub fn new(data:Py<PyBytes>, fragment: Fragment) -> Self {
+        Self { kind: WebsocketMessageType::Bytes as usize, data: data, fragment: fragment as usize }
     }
 }
 
@@ -253,12 +772,14 @@ struct WebsocketInboundTextMessage {
     #[pyo3(get)]
     kind: usize,
     #[pyo3(get)]
-    data: Py<PyString>
+    data: Py<PyString>,
+    #[pyo3(get)]
+    fragment: usize
 }
 
 impl WebsocketInboundTextMessage {
-    pub fn new(data: Py<PyString>) -> Self {
-        Self { kind: WebsocketMessageType::Text as usize, data: data }
+    pub fn new(data: Py<PyString>, fragment: Fragment) -> Self {
+        Self { kind: WebsocketMessageType::Text as usize, data: data, fragment: fragment as usize }
     }
 }
 
@@ -267,24 +788,37 @@ impl RSGIWebsocketProtocol {
     #[args(status="None")]
     fn close(&mut self, status: Option<i32>) -> PyResult<()> {
         self.status = status.unwrap_or(0);
+        self.stats.closed(1000);
         if let Some(tx) = self.tx.take() {
             let _ = tx.send((self.status, self.consumed()));
         }
         Ok(())
     }
 
-    fn accept<'p>(&mut self, py: Python<'p>) -> PyResult<&'p PyAny> {
+    #[args(headers="vec![]", fragments="false")]
+    fn accept<'p>(&mut self, py: Python<'p>, headers: Vec<(&str, &str)>, fragments: bool) -> PyResult<&'p PyAny> {
+        let mut handshake_headers = HeaderMap::new();
+        for (key, value) in headers {
+            match (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(value)) {
+                (Ok(key), Ok(value)) => { handshake_headers.append(key, value); },
+                _ => return error_proto!()
+            }
+        }
         let rth = self.rt.clone();
         let mut upgrade = self.upgrade.take().unwrap();
+        upgrade.extend_headers(handshake_headers);
         let transport = self.websocket.clone();
+        let stats = self.stats.clone();
         future_into_py(self.rt.clone(), py, async move {
             let mut ws = transport.lock().await;
+            let fragments = fragments.then(|| ws.deliver_fragments());
             match upgrade.send().await {
                 Ok(_) => {
                     match (&mut *ws).await {
                         Ok(stream) => {
+                            stats.opened();
                             Ok(Python::with_gil(|py| {
-                                RSGIWebsocketTransport::new(rth, stream).into_py(py)
+                                RSGIWebsocketTransport::new(rth, stream, stats, fragments).into_py(py)
                             }))
                         },
                         _ => error_proto!()
@@ -297,19 +831,26 @@ impl RSGIWebsocketProtocol {
 }
 
 #[inline(always)]
-fn message_into_py(message: Message) -> PyResult<PyObject> {
+fn response_head(status: i32, reason: Option<&str>) -> PyResult<(StatusCode, Option<ReasonPhrase>)> {
+    Ok((response_status(status)?, reason.map(reason_phrase).transpose()?))
+}
+
+#[inline(always)]
+fn message_into_py(message: Message, fragment: Fragment) -> PyResult<PyObject> {
     match message {
         Message::Binary(message) => {
             Ok(Python::with_gil(|py| {
                 WebsocketInboundBytesMessage::new(
-                    PyBytes::new(py, &message).into()
+                    PyBytes::new(py, &message).into(),
+                    fragment
                 ).into_py(py)
             }))
         },
         Message::Text(message) => {
             Ok(Python::with_gil(|py| {
                 WebsocketInboundTextMessage::new(
-                    PyString::new(py, &message).into()
+                    PyString::new(py, &message).into(),
+                    fragment
                 ).into_py(py)
             }))
         },
@@ -1,48 +1,57 @@
+use bytes::Bytes;
 use hyper::{
-    header::{HeaderMap, HeaderName, HeaderValue, SERVER as HK_SERVER},
-    http::response::Builder as ResponseBuilder, Body, Uri, Version
+    header::{HeaderMap, HeaderName},
+    ext::ReasonPhrase,
+    http::response::Builder as ResponseBuilder, Body, StatusCode, Uri, Version
 };
 use pyo3::prelude::*;
-use pyo3::types::{PyString};
-use std::net::SocketAddr;
+use pyo3::types::{PyBytes, PyDict, PyInt, PyString};
+use std::{borrow::Cow, net::SocketAddr, sync::Arc};
 
-use crate::http::HV_SERVER;
+use crate::{
+    buffers::BufferBody,
+    deadlines::Deadline,
+    errors::ProtocolViolationError,
+    http::{DuplicateHeaders, HeaderValidation, ProtocolConformance},
+    interning::{header_value_str, intern_str},
+    scratch::ScratchDir,
+    tcp::addr_repr,
+    tls::TlsSession,
+    urls::{PathDecoding, query_params},
+    workers::identity
+};
+use super::errors::RSGIProtocolError;
 
 
 #[pyclass(module="granian._granian")]
 #[derive(Clone)]
 pub(crate) struct RSGIHeaders {
-    inner: HeaderMap
-}
-
-impl RSGIHeaders {
-    pub fn new(map: &HeaderMap) -> Self {
-        Self { inner: map.clone() }
-    }
+    inner: HeaderMap,
+    duplicates: DuplicateHeaders
 }
 
 #[pymethods]
 impl RSGIHeaders {
-    fn keys(&self) -> Vec<&str> {
+    fn keys<'p>(&self, py: Python<'p>) -> Vec<&'p PyString> {
         let mut ret = Vec::with_capacity(self.inner.keys_len());
         for key in self.inner.keys() {
-            ret.push(key.as_str());
+            ret.push(intern_str(py, key.as_str()));
         };
         ret
     }
 
-    fn values(&self) -> PyResult<Vec<&str>> {
+    fn values<'p>(&self, py: Python<'p>) -> PyResult<Vec<&'p PyString>> {
         let mut ret = Vec::with_capacity(self.inner.keys_len());
-        for val in self.inner.values() {
-            ret.push(val.to_str().unwrap());
+        for (key, val) in self.inner.iter() {
+            ret.push(header_value_str(py, key, val));
         };
         Ok(ret)
     }
 
-    fn items(&self) -> PyResult<Vec<(&str, &str)>> {
+    fn items<'p>(&self, py: Python<'p>) -> PyResult<Vec<(&'p PyString, &'p PyString)>> {
         let mut ret = Vec::with_capacity(self.inner.keys_len());
         for (key, val) in self.inner.iter() {
-            ret.push((key.as_str(), val.to_str().unwrap()));
+            ret.push((intern_str(py, key.as_str()), header_value_str(py, key, val)));
         };
         Ok(ret)
     }
@@ -53,69 +62,143 @@ impl RSGIHeaders {
 
     #[args(key, default="None")]
     fn get(&self, py: Python, key: &str, default: Option<PyObject>) -> Option<PyObject> {
-        match self.inner.get(key) {
-            Some(val) => {
-                match val.to_str() {
-                    Ok(string) => Some(PyString::new(py, string).into()),
-                    _ => default
-                }
-            },
+        let key = match HeaderName::from_bytes(key.as_bytes()) {
+            Ok(key) => key,
+            _ => return default
+        };
+        if let Some(joined) = self.duplicates.joined(&self.inner, &key) {
+            return Some(PyString::new(py, &joined).into())
+        }
+        match self.inner.get(&key) {
+            Some(val) => Some(header_value_str(py, &key, val).into()),
             _ => default
         }
     }
+
+    // All the values of a header, in the order they were received
+    fn get_all<'p>(&self, py: Python<'p>, key: &str) -> Vec<&'p PyString> {
+        let key = match HeaderName::from_bytes(key.as_bytes()) {
+            Ok(key) => key,
+            _ => return Vec::new()
+        };
+        self.inner.get_all(&key).iter().map(|val| header_value_str(py, &key, val)).collect()
+    }
 }
 
+// Scope attributes are converted to Python objects only when accessed
 #[pyclass(module="granian._granian")]
 pub(crate) struct RSGIScope {
-    #[pyo3(get)]
-    proto: String,
+    proto: &'static str,
     http_version: Version,
-    #[pyo3(get)]
-    rsgi_version: String,
-    #[pyo3(get)]
     scheme: String,
-    #[pyo3(get)]
     method: String,
     uri: Uri,
-    #[pyo3(get)]
-    server: String,
-    #[pyo3(get)]
-    client: String,
-    #[pyo3(get)]
-    headers: RSGIHeaders
+    server: SocketAddr,
+    client: SocketAddr,
+    headers: HeaderMap,
+    duplicates: DuplicateHeaders,
+    path_decoding: PathDecoding,
+    headers_obj: Option<Py<RSGIHeaders>>,
+    content_length: Option<u64>,
+    deadline: Option<Deadline>,
+    tls: Option<Arc<TlsSession>>,
+    scratch: ScratchDir
 }
 
 impl RSGIScope {
     pub fn new(
-        proto: &str,
+        proto: &'static str,
         http_version: Version,
         scheme: &str,
         uri: Uri,
         method: &str,
         server: SocketAddr,
         client: SocketAddr,
-        headers: &HeaderMap
+        headers: &HeaderMap,
+        duplicates: DuplicateHeaders,
+        path_decoding: PathDecoding,
+        content_length: Option<u64>,
+        deadline: Option<Deadline>,
+        tls: Option<Arc<TlsSession>>
     ) -> Self {
         Self {
-            proto: proto.to_string(),
+            proto,
             http_version: http_version,
-            rsgi_version: "1.0".to_string(),
             scheme: scheme.to_string(),
             method: method.to_string(),
             uri: uri,
-            server: server.to_string(),
-            client: client.to_string(),
-            headers: RSGIHeaders::new(headers)
+            server,
+            client,
+            headers: headers.clone(),
+            duplicates,
+            path_decoding,
+            headers_obj: None,
+            content_length,
+            deadline,
+            tls,
+            scratch: ScratchDir::default()
         }
     }
 
-    pub fn set_proto(&mut self, value: &str) {
-        self.proto = value.to_string()
+    pub fn scratch(&self) -> ScratchDir {
+        self.scratch.clone()
+    }
+
+    pub fn set_proto(&mut self, value: &'static str) {
+        self.proto = value
     }
 }
 
 #[pymethods]
 impl RSGIScope {
+    #[getter(proto)]
+    fn get_proto<'p>(&self, py: Python<'p>) -> &'p PyString {
+        intern_str(py, self.proto)
+    }
+
+    #[getter(rsgi_version)]
+    fn get_rsgi_version<'p>(&self, py: Python<'p>) -> &'p PyString {
+        pyo3::intern!(py, "1.0")
+    }
+
+    #[getter(scheme)]
+    fn get_scheme<'p>(&self, py: Python<'p>) -> &'p PyString {
+        intern_str(py, &self.scheme)
+    }
+
+    #[getter(method)]
+    fn get_method<'p>(&self, py: Python<'p>) -> &'p PyString {
+        intern_str(py, &self.method)
+    }
+
+    #[getter(server)]
+    fn get_server(&self) -> String {
+        addr_repr(self.server)
+    }
+
+    #[getter(client)]
+    fn get_client(&self) -> String {
+        addr_repr(self.client)
+    }
+
+    #[getter(headers)]
+    fn get_headers(&mut self, py: Python) -> PyResult<Py<RSGIHeaders>> {
+        if let Some(obj) = &self.headers_obj {
+            return Ok(obj.clone_ref(py))
+        }
+        let obj = Py::new(py, RSGIHeaders {
+            inner: std::mem::take(&mut self.headers),
+            duplicates: self.duplicates.clone()
+        })?;
+        self.headers_obj = Some(obj.clone_ref(py));
+        Ok(obj)
+    }
+
+    #[getter(content_length)]
+    fn get_content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
     #[getter(http_version)]
     fn get_http_version(&self) -> &str {
         match self.http_version {
@@ -128,7 +211,12 @@ impl RSGIScope {
     }
 
     #[getter(path)]
-    fn get_path(&self) -> &str {
+    fn get_path(&self) -> Cow<'_, str> {
+        self.path_decoding.path(self.uri.path())
+    }
+
+    #[getter(raw_path)]
+    fn get_raw_path(&self) -> &str {
         self.uri.path()
     }
 
@@ -136,12 +224,51 @@ impl RSGIScope {
     fn get_query_string(&self) -> &str {
         self.uri.query().unwrap_or("")
     }
+
+    #[getter(worker_id)]
+    fn get_worker_id(&self) -> i32 {
+        identity().id
+    }
+
+    #[getter(worker_generation)]
+    fn get_worker_generation(&self) -> u32 {
+        identity().generation
+    }
+
+    #[getter(client_cert)]
+    fn get_client_cert<'p>(&self, py: Python<'p>) -> Option<&'p PyBytes> {
+        self.tls.as_ref()?.client_cert().map(|der| PyBytes::new(py, der))
+    }
+
+    #[getter(client_cert_subject)]
+    fn get_client_cert_subject(&self) -> Option<&str> {
+        self.tls.as_ref()?.client_subject.as_deref()
+    }
+
+    #[getter(client_cert_verify)]
+    fn get_client_cert_verify(&self) -> &str {
+        self.tls.as_ref().map(|tls| tls.client_verify()).unwrap_or("NONE")
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        query_params(self.uri.query().unwrap_or(""))
+    }
+
+    fn scratch_dir(&self) -> PyResult<String> {
+        Ok(self.scratch.path()?.to_string_lossy().into_owned())
+    }
+
+    fn deadline_remaining(&self) -> Option<f64> {
+        self.deadline.map(|deadline| deadline.remaining())
+    }
 }
 
 #[derive(Debug)]
 pub(crate) enum ResponseType {
     Body = 1,
-    File = 10
+    File = 10,
+    Stream = 20,
+    Failed = 50
 }
 
 #[pyclass(frozen)]
@@ -163,26 +290,145 @@ impl Response {
         }
     }
 
-    pub fn head(&mut self, status: u16, headers: &Vec<(&str, &str)>) {
+    // Invalid headers fail the call before anything gets changed, so the
+    // application can still send another response
+    pub fn head(
+        &mut self,
+        status: StatusCode,
+        headers: &[(impl AsRef<str>, impl AsRef<str>)],
+        reason: Option<ReasonPhrase>,
+        validation: HeaderValidation
+    ) -> PyResult<()> {
+        let headers = validation
+            .headers(headers.iter().map(|(key, value)| (key.as_ref().as_bytes(), value.as_ref().as_bytes())))
+            .map_err(RSGIProtocolError::new_err)?;
         match status {
-            200 => {},
-            _ => {
+            StatusCode::OK => {},
+            status => {
                 self.inner = ResponseBuilder::new().status(status);
             }
         }
+        if let Some(reason) = reason {
+            self.inner.extensions_mut().unwrap().insert(reason);
+        }
 
         let rh = self.inner.headers_mut().unwrap();
-        rh.insert(HK_SERVER, HV_SERVER);
         for (key, value) in headers {
-            rh.append(
-                HeaderName::from_bytes(key.as_bytes()).unwrap(),
-                HeaderValue::from_str(value).unwrap()
-            );
+            rh.append(key, value);
         }
+        Ok(())
     }
 
+    // Marks the application as failed before sending a response
     pub fn error(&mut self) {
-        self.inner = ResponseBuilder::new().status(500);
-        self.body = Body::from("Internal server error");
+        self.mode = ResponseType::Failed;
+    }
+}
+
+// A response argument, along with the violation it took to convert it, when the
+// application passed a value of another type the lenient mode can coerce
+pub(crate) struct Coerced<T> {
+    pub value: T,
+    pub violation: Option<String>
+}
+
+impl<T> Coerced<T> {
+    fn with_violation(value: T, expected: &str, item: &PyAny) -> PyResult<Self> {
+        let violation = format!("RSGI response {}, got {}", expected, item.get_type().name()?);
+        Ok(Self { value, violation: Some(violation) })
+    }
+
+    // The value, once the protocol conformance accepted its conversion
+    pub fn accept(self, conformance: ProtocolConformance) -> PyResult<T> {
+        if let Some(violation) = self.violation {
+            conformance.violation(violation)?;
+        }
+        Ok(self.value)
+    }
+}
+
+pub(crate) type HeadersArg<'a> = Coerced<Vec<(Cow<'a, str>, Cow<'a, str>)>>;
+
+impl<T> From<T> for Coerced<T> {
+    fn from(value: T) -> Self {
+        Self { value, violation: None }
+    }
+}
+
+fn uncoercible<T>(expected: &str, item: &PyAny) -> PyResult<T> {
+    Err(ProtocolViolationError::new_err(format!(
+        "RSGI response {}, got {}", expected, item.get_type().name()?
+    )))
+}
+
+// Statuses need to be integers, their strings get coerced
+impl<'source> FromPyObject<'source> for Coerced<i32> {
+    fn extract(item: &'source PyAny) -> PyResult<Self> {
+        if item.is_instance_of::<PyInt>()? {
+            return Ok(item.extract::<i32>()?.into())
+        }
+        match item.downcast::<PyString>().map(|status| status.to_str().map(|status| status.trim().parse())) {
+            Ok(Ok(Ok(status))) => Self::with_violation(status, "status should be an integer", item),
+            _ => uncoercible("status should be an integer", item)
+        }
+    }
+}
+
+// Headers need to be a list of string pairs, mappings and values of other types
+// get coerced, converted to strings
+impl<'source> FromPyObject<'source> for Coerced<Vec<(Cow<'source, str>, Cow<'source, str>)>> {
+    fn extract(item: &'source PyAny) -> PyResult<Self> {
+        if let Ok(headers) = item.extract::<Vec<(&str, &str)>>() {
+            return Ok(headers.into_iter().map(|(key, value)| (key.into(), value.into())).collect::<Vec<_>>().into())
+        }
+        let pairs = match item.downcast::<PyDict>() {
+            Ok(dict) => dict.items().as_ref(),
+            _ => item
+        };
+        let mut headers = Vec::new();
+        for pair in pairs.iter().or_else(|_| uncoercible("headers should be a list of string pairs", item))? {
+            let (key, value): (&PyAny, &PyAny) = pair?.extract()
+                .or_else(|_| uncoercible("headers should be a list of string pairs", item))?;
+            headers.push((coerce_str(key)?, coerce_str(value)?));
+        }
+        Self::with_violation(headers, "headers should be a list of string pairs", item)
+    }
+}
+
+fn coerce_str(item: &PyAny) -> PyResult<Cow<'_, str>> {
+    if let Ok(string) = item.downcast::<PyString>() {
+        return Ok(string.to_str()?.into())
+    }
+    if let Ok(bytes) = item.downcast::<PyBytes>() {
+        return Ok(String::from_utf8_lossy(bytes.as_bytes()))
+    }
+    Ok(item.str()?.to_str()?.to_owned().into())
+}
+
+// Bytes bodies need to be bytes-like objects, strings get encoded to UTF-8
+impl<'source> FromPyObject<'source> for Coerced<Bytes> {
+    fn extract(item: &'source PyAny) -> PyResult<Self> {
+        if let Ok(body) = item.extract::<BufferBody>() {
+            return Ok(body.0.into())
+        }
+        match item.downcast::<PyString>() {
+            Ok(string) => Self::with_violation(Bytes::from(string.to_str()?.to_owned()), "body should be a bytes-like object", item),
+            _ => uncoercible("body should be a bytes-like object", item)
+        }
+    }
+}
+
+// String bodies need to be strings, bytes get decoded as UTF-8
+impl<'source> FromPyObject<'source> for Coerced<String> {
+    fn extract(item: &'source PyAny) -> PyResult<Self> {
+        if let Ok(string) = item.downcast::<PyString>() {
+            return Ok(string.to_str()?.to_owned().into())
+        }
+        match item.downcast::<PyBytes>() {
+            Ok(bytes) => Self::with_violation(
+                String::from_utf8_lossy(bytes.as_bytes()).into_owned(), "body should be a string", item
+            ),
+            _ => uncoercible("body should be a string", item)
+        }
     }
 }
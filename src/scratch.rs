@@ -0,0 +1,135 @@
+use bytes::Bytes;
+use futures::stream::Stream;
+use hyper::{Body, Response};
+use std::{
+    io,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}},
+    task::{Context, Poll}
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+
+
+static SCRATCH_SEQ: AtomicU64 = AtomicU64::new(0);
+
+enum State {
+    Idle,
+    Created(PathBuf),
+    Released
+}
+
+impl State {
+    fn release(&mut self) {
+        if let Self::Created(path) = std::mem::replace(self, Self::Released) {
+            // removal might be slow on big uploads, keep it off the reactor when possible
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn_blocking(move || { let _ = std::fs::remove_dir_all(path); });
+                },
+                _ => { let _ = std::fs::remove_dir_all(path); }
+            }
+        }
+    }
+}
+
+struct Inner {
+    state: Mutex<State>
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.state.get_mut().unwrap().release();
+    }
+}
+
+fn create_dir() -> io::Result<PathBuf> {
+    let base = std::env::temp_dir();
+    loop {
+        let path = base.join(format!(
+            "granian-{}-{}",
+            std::process::id(),
+            SCRATCH_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        match builder.create(&path) {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err)
+        }
+    }
+}
+
+// Lazily created per-request temporary directory
+#[derive(Clone)]
+pub(crate) struct ScratchDir {
+    inner: Arc<Inner>
+}
+
+impl Default for ScratchDir {
+    fn default() -> Self {
+        Self { inner: Arc::new(Inner { state: Mutex::new(State::Idle) }) }
+    }
+}
+
+impl ScratchDir {
+    pub fn path(&self) -> io::Result<PathBuf> {
+        let mut state = self.inner.state.lock().unwrap();
+        match &*state {
+            State::Created(path) => Ok(path.clone()),
+            State::Idle => {
+                let path = create_dir()?;
+                *state = State::Created(path.clone());
+                Ok(path)
+            },
+            State::Released => Err(io::Error::other("scratch directory already released"))
+        }
+    }
+
+    fn is_created(&self) -> bool {
+        matches!(*self.inner.state.lock().unwrap(), State::Created(_))
+    }
+
+    pub fn guard(&self) -> ScratchGuard {
+        ScratchGuard { dir: self.clone() }
+    }
+}
+
+// Releases the scratch directory once dropped, which happens either when the
+// response body is fully sent or when the request gets cancelled.
+pub(crate) struct ScratchGuard {
+    dir: ScratchDir
+}
+
+impl Drop for ScratchGuard {
+    fn drop(&mut self) {
+        self.dir.inner.state.lock().unwrap().release();
+    }
+}
+
+impl ScratchGuard {
+    pub fn attach(self, res: Response<Body>) -> Response<Body> {
+        if !self.dir.is_created() {
+            return res
+        }
+        let (parts, body) = res.into_parts();
+        Response::from_parts(parts, Body::wrap_stream(ScratchBody { inner: body, _guard: self }))
+    }
+}
+
+struct ScratchBody {
+    inner: Body,
+    _guard: ScratchGuard
+}
+
+impl Stream for ScratchBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
@@ -0,0 +1,281 @@
+use pyo3::{prelude::*, types::{PyDict, PyList}};
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::io::IntoRawFd;
+#[cfg(windows)]
+use std::os::windows::io::IntoRawSocket;
+
+use crate::{
+    asgi::serve::ASGIWorker,
+    errors::Error,
+    rsgi::serve::RSGIWorker,
+    tcp::bind_listener,
+    workers::{WorkerConfig, WorkerIdentity, WorkerOptions},
+    wsgi::serve::WSGIWorker
+};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interface {
+    Asgi,
+    Rsgi,
+    Wsgi
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadingMode {
+    Runtime,
+    Workers,
+    Sharded
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpMode {
+    Auto,
+    Http1,
+    Http2
+}
+
+impl HttpMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Http1 => "1",
+            Self::Http2 => "2"
+        }
+    }
+}
+
+macro_rules! serve_worker {
+    ($py:expr, $worker:expr, $mode:expr, $callback:expr, $event_loop:expr, $shutdown_event:expr) => {{
+        let context = $py.import("contextvars")?.call_method0("copy_context")?;
+        let signal_rx = $shutdown_event.call_method0("wait")?.into();
+        match $mode {
+            ThreadingMode::Runtime => $worker.serve_rth($callback.into(), $event_loop, context, signal_rx),
+            _ => $worker.serve_wth($callback.into(), $event_loop, context, signal_rx)
+        }
+    }};
+}
+
+// Library mode: runs a single worker in the current process, with the given
+// application, without going through the Python CLI and process manager.
+// The embedding program owns the interpreter, so serving needs the GIL token.
+pub struct ServerBuilder {
+    address: SocketAddr,
+    interface: Interface,
+    app: Option<PyObject>,
+    threads: usize,
+    pthreads: usize,
+    threading_mode: ThreadingMode,
+    http_mode: HttpMode,
+    websockets: bool,
+    backlog: i32,
+    drain_timeout: f64,
+    ssl: Option<(String, String)>
+}
+
+impl ServerBuilder {
+    fn new() -> Self {
+        Self {
+            address: ([127, 0, 0, 1], 8000).into(),
+            interface: Interface::Rsgi,
+            app: None,
+            threads: 1,
+            pthreads: 1,
+            threading_mode: ThreadingMode::Workers,
+            http_mode: HttpMode::Auto,
+            websockets: true,
+            backlog: 1024,
+            drain_timeout: 30.0,
+            ssl: None
+        }
+    }
+
+    pub fn bind(mut self, address: impl Into<SocketAddr>) -> Self {
+        self.address = address.into();
+        self
+    }
+
+    pub fn interface(mut self, interface: Interface) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    pub fn app(mut self, app: PyObject) -> Self {
+        self.app = Some(app);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn pthreads(mut self, pthreads: usize) -> Self {
+        self.pthreads = pthreads.max(1);
+        self
+    }
+
+    pub fn threading_mode(mut self, mode: ThreadingMode) -> Self {
+        self.threading_mode = mode;
+        self
+    }
+
+    pub fn http(mut self, mode: HttpMode) -> Self {
+        self.http_mode = mode;
+        self
+    }
+
+    pub fn websockets(mut self, enabled: bool) -> Self {
+        self.websockets = enabled;
+        self
+    }
+
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog.max(128);
+        self
+    }
+
+    // Time given to requests and websocket sessions to end on shutdown, 0 for no limit
+    pub fn drain_timeout(mut self, seconds: f64) -> Self {
+        self.drain_timeout = seconds.max(0.0);
+        self
+    }
+
+    pub fn ssl(mut self, cert: impl Into<String>, key: impl Into<String>) -> Self {
+        self.ssl = Some((cert.into(), key.into()));
+        self
+    }
+
+    // Binds the listening socket, so address errors surface before serving
+    pub fn build(self) -> Result<Server, Error> {
+        let app = self.app.ok_or_else(|| Error::app("No application provided to the server"))?;
+        let listener = bind_listener(
+            self.address, self.backlog, self.threading_mode == ThreadingMode::Sharded
+        )?;
+        Ok(Server {
+            listener,
+            interface: self.interface,
+            app,
+            threads: self.threads,
+            pthreads: self.pthreads,
+            threading_mode: self.threading_mode,
+            http_mode: self.http_mode,
+            websockets: self.websockets,
+            backlog: self.backlog,
+            drain_timeout: self.drain_timeout,
+            ssl: self.ssl
+        })
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    interface: Interface,
+    app: PyObject,
+    threads: usize,
+    pthreads: usize,
+    threading_mode: ThreadingMode,
+    http_mode: HttpMode,
+    websockets: bool,
+    backlog: i32,
+    drain_timeout: f64,
+    ssl: Option<(String, String)>
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn config(self) -> PyResult<(WorkerConfig, PyObject)> {
+        #[cfg(unix)]
+        let socket_fd = self.listener.into_raw_fd();
+        #[cfg(windows)]
+        let socket_fd = self.listener.into_raw_socket() as i32;
+        let (ssl_cert, ssl_key) = match self.ssl {
+            Some((cert, key)) => (Some(cert), Some(key)),
+            None => (None, None)
+        };
+        let options = WorkerOptions {
+            threads: self.threads,
+            pthreads: self.pthreads,
+            http: self.http_mode.as_str().to_string(),
+            websockets: self.websockets && self.interface != Interface::Wsgi,
+            listener_shards: self.threading_mode == ThreadingMode::Sharded,
+            backlog: self.backlog,
+            drain_timeout: self.drain_timeout,
+            ssl_cert,
+            ssl_key,
+            ..Default::default()
+        };
+        let identity = WorkerIdentity { id: 1, generation: 1, memory_recycles: 0 };
+        let config = WorkerConfig::new(identity, socket_fd, options)?;
+        Ok((config, self.app))
+    }
+
+    // Serves the application until the process receives SIGINT or SIGTERM
+    pub fn serve(self, py: Python) -> PyResult<()> {
+        let interface = self.interface;
+        let threading_mode = self.threading_mode;
+        let (config, app) = self.config()?;
+        register_pymodule(py)?;
+
+        let event_loop = py.import("granian._loops")?.getattr("loops")?.call_method1("get", ("auto",))?;
+        let signal = py.import("signal")?;
+        let signals = PyList::new(py, [signal.getattr("SIGTERM")?, signal.getattr("SIGINT")?]);
+        let shutdown_event = py.import("granian._loops")?
+            .getattr("set_loop_signals")?
+            .call1((event_loop, signals))?;
+
+        match interface {
+            Interface::Asgi => {
+                let lifespan = py.import("granian.asgi")?.getattr("LifespanProtocol")?.call1((&app,))?;
+                event_loop.call_method1("run_until_complete", (lifespan.call_method0("startup")?,))?;
+                if lifespan.getattr("interrupt")?.is_true()? {
+                    return Ok(())
+                }
+                let callback = py.import("granian.asgi")?.getattr("_callback_wrapper")?.call1((app,))?;
+                let worker = ASGIWorker::from_config(config);
+                serve_worker!(py, worker, threading_mode, callback, event_loop, shutdown_event);
+                event_loop.call_method1("run_until_complete", (lifespan.call_method0("shutdown")?,))?;
+            },
+            Interface::Rsgi => {
+                let target = app.as_ref(py);
+                let callback = match target.hasattr("__rsgi__")? {
+                    true => target.getattr("__rsgi__")?,
+                    false => target
+                };
+                if target.hasattr("__rsgi_init__")? {
+                    target.call_method1("__rsgi_init__", (event_loop,))?;
+                }
+                let callback = py.import("granian.rsgi")?.getattr("_callback_wrapper")?.call1((callback,))?;
+                let worker = RSGIWorker::from_config(config);
+                serve_worker!(py, worker, threading_mode, callback, event_loop, shutdown_event);
+            },
+            Interface::Wsgi => {
+                let callback = py.import("granian.wsgi")?.getattr("_callback_wrapper")?.call1((app,))?;
+                let worker = WSGIWorker::from_config(config);
+                serve_worker!(py, worker, threading_mode, callback, event_loop, shutdown_event);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// When the crate is linked into a binary, there's no `granian._granian` extension
+// to import: the Python side of the package gets bound to the embedded one instead.
+// Serving does this on its own, but applications importing granian modules need
+// it to happen before they get loaded.
+pub fn register_pymodule(py: Python) -> PyResult<()> {
+    let modules: &PyDict = py.import("sys")?.getattr("modules")?.downcast()?;
+    if !modules.contains("granian._granian")? {
+        modules.set_item("granian._granian", pyo3::wrap_pymodule!(crate::_granian)(py))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant}
+};
+
+use crate::{
+    metrics::{DURATION_BUCKETS, METRICS, RequestCounts},
+    workers::identity
+};
+
+
+// Burn rates below this amount of requests in a window are just noise
+const MIN_REQUESTS: u64 = 10;
+
+// Multi-window alerts: both windows need to burn the error budget faster than
+// the threshold, so alerts fire quickly on sharp drops and resolve as soon as
+// the recent traffic gets healthy again.
+struct AlertWindow {
+    severity: &'static str,
+    long: Duration,
+    short: Duration,
+    burn_rate: f64
+}
+
+const ALERT_WINDOWS: [AlertWindow; 2] = [
+    AlertWindow {
+        severity: "page",
+        long: Duration::from_secs(3600),
+        short: Duration::from_secs(300),
+        burn_rate: 14.4
+    },
+    AlertWindow {
+        severity: "ticket",
+        long: Duration::from_secs(6 * 3600),
+        short: Duration::from_secs(1800),
+        burn_rate: 6.0
+    }
+];
+
+#[derive(Clone, Copy)]
+enum Indicator {
+    Errors,
+    Latency
+}
+
+impl Indicator {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Errors => "errors",
+            Self::Latency => "latency"
+        }
+    }
+
+    fn bad(&self, counts: &RequestCounts) -> u64 {
+        match self {
+            Self::Errors => counts.errors,
+            Self::Latency => counts.slow
+        }
+    }
+}
+
+// Service level objectives over the responses of the worker: the share of them
+// to be successful and, optionally, faster than the latency threshold. Latency
+// thresholds get rounded up to the request duration histogram buckets.
+#[derive(Clone, Default)]
+pub(crate) struct SloPolicy {
+    objective: Option<f64>,
+    latency_bucket: Option<usize>,
+    interval: Duration
+}
+
+impl SloPolicy {
+    pub fn new(objective: Option<f64>, latency: Option<f64>, interval: f64) -> PyResult<Self> {
+        if let Some(objective) = objective {
+            if !(objective > 0.0 && objective < 1.0) {
+                return Err(PyValueError::new_err("SLO objective should be between 0 and 1"))
+            }
+        }
+        let latency_bucket = match latency {
+            Some(latency) if latency > 0.0 => Some(
+                DURATION_BUCKETS.iter().position(|bound| latency <= *bound).ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "SLO latency should be at most {}s", DURATION_BUCKETS[DURATION_BUCKETS.len() - 1]
+                    ))
+                })?
+            ),
+            Some(_) => return Err(PyValueError::new_err("SLO latency should be positive")),
+            None => None
+        };
+        if interval <= 0.0 {
+            return Err(PyValueError::new_err("SLO evaluation interval should be positive"))
+        }
+        Ok(Self { objective, latency_bucket, interval: Duration::from_secs_f64(interval) })
+    }
+
+    // Evaluates the objectives in a background thread, until the handle gets dropped
+    pub fn start(&self) -> Option<SloHandle> {
+        let objective = self.objective?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut evaluator = SloEvaluator::new(objective, self.latency_bucket);
+        let interval = self.interval;
+        let stopped = stop.clone();
+        if let Some(idx) = self.latency_bucket {
+            log::info!("Evaluating SLO latency against a {}s threshold", DURATION_BUCKETS[idx]);
+        }
+        std::thread::Builder::new()
+            .name("granian-slo".to_string())
+            .spawn(move || {
+                evaluator.sample(Instant::now());
+                loop {
+                    std::thread::sleep(interval);
+                    if stopped.load(Ordering::Relaxed) {
+                        break
+                    }
+                    evaluator.sample(Instant::now());
+                    evaluator.evaluate();
+                }
+            })
+            .ok()?;
+        Some(SloHandle { stop })
+    }
+}
+
+pub(crate) struct SloHandle {
+    stop: Arc<AtomicBool>
+}
+
+impl Drop for SloHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed)
+    }
+}
+
+struct SloEvaluator {
+    objective: f64,
+    indicators: Vec<Indicator>,
+    history: VecDeque<(Instant, RequestCounts)>,
+    latency_bucket: Option<usize>,
+    firing: Vec<bool>
+}
+
+impl SloEvaluator {
+    fn new(objective: f64, latency_bucket: Option<usize>) -> Self {
+        let mut indicators = vec![Indicator::Errors];
+        if latency_bucket.is_some() {
+            indicators.push(Indicator::Latency);
+        }
+        let firing = vec![false; indicators.len() * ALERT_WINDOWS.len()];
+        Self { objective, indicators, history: VecDeque::new(), latency_bucket, firing }
+    }
+
+    fn sample(&mut self, now: Instant) {
+        self.history.push_back((now, METRICS.request_counts(self.latency_bucket)));
+        let retention = ALERT_WINDOWS.iter().map(|window| window.long).max().unwrap_or_default();
+        while let Some((at, _)) = self.history.front() {
+            if now.saturating_duration_since(*at) <= retention || self.history.len() < 2 {
+                break
+            }
+            self.history.pop_front();
+        }
+    }
+
+    // Windows longer than the collected history use all of it
+    fn burn_rate(&self, indicator: Indicator, window: Duration) -> f64 {
+        let (now, current) = match self.history.back() {
+            Some(last) => last,
+            None => return 0.0
+        };
+        let (_, since) = self.history.iter()
+            .find(|(at, _)| now.saturating_duration_since(*at) <= window)
+            .unwrap_or(&self.history[0]);
+        let total = current.total.saturating_sub(since.total);
+        if total < MIN_REQUESTS {
+            return 0.0
+        }
+        let bad = indicator.bad(current).saturating_sub(indicator.bad(since));
+        (bad as f64 / total as f64) / (1.0 - self.objective)
+    }
+
+    fn evaluate(&mut self) {
+        for idx in 0..self.indicators.len() {
+            let indicator = self.indicators[idx];
+            for (widx, window) in ALERT_WINDOWS.iter().enumerate() {
+                let long = self.burn_rate(indicator, window.long);
+                let short = self.burn_rate(indicator, window.short);
+                let firing = long >= window.burn_rate && short >= window.burn_rate;
+                let state = &mut self.firing[idx * ALERT_WINDOWS.len() + widx];
+                if firing == *state {
+                    continue
+                }
+                *state = firing;
+                let event = format!(
+                    "{{\"alert\":\"{}\",\"indicator\":\"{}\",\"severity\":\"{}\",\"objective\":{},\"burn_rate_threshold\":{},\"burn_rate_long\":{:.3},\"burn_rate_short\":{:.3},\"window_long_s\":{},\"window_short_s\":{},\"worker\":{}}}",
+                    if firing { "firing" } else { "resolved" },
+                    indicator.name(),
+                    window.severity,
+                    self.objective,
+                    window.burn_rate,
+                    long,
+                    short,
+                    window.long.as_secs(),
+                    window.short.as_secs(),
+                    identity().id
+                );
+                match firing {
+                    true => log::warn!("SLO alert {}", event),
+                    false => log::info!("SLO alert {}", event)
+                }
+            }
+        }
+    }
+}
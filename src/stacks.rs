@@ -0,0 +1,154 @@
+use hyper::{Body, Request};
+use once_cell::sync::OnceCell;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak, atomic::{AtomicBool, AtomicU64, Ordering}},
+    time::{Duration, Instant}
+};
+
+use crate::workers::identity;
+
+
+// Handlers stuck for long usually stay stuck for a while, and every dump needs
+// the GIL: further slow handlers within this time don't trigger new ones.
+const THRESHOLD_COOLDOWN: Duration = Duration::from_secs(60);
+const WATCHDOG_INTERVAL_MAX: Duration = Duration::from_secs(1);
+
+struct RunningHandler {
+    started: Instant,
+    request: String,
+    reported: bool
+}
+
+#[derive(Default)]
+struct StackDumpState {
+    running: AtomicBool,
+    dumped_at: Mutex<Option<Instant>>,
+    handlers: Mutex<HashMap<u64, RunningHandler>>,
+    next_handler: AtomicU64,
+    watchdog: OnceCell<()>
+}
+
+// Snapshots of the worker stacks, written as text files by `granian.stacks`
+// for post-mortem analysis. Besides the ones requested through the admin socket,
+// a snapshot gets taken when a request handler runs longer than `threshold`:
+// handlers get checked from a watchdog thread, as a hanging application might
+// also be blocking the runtime threads.
+#[derive(Clone, Default)]
+pub(crate) struct StackDumps {
+    directory: Option<String>,
+    threshold: Option<Duration>,
+    state: Arc<StackDumpState>
+}
+
+impl StackDumps {
+    pub fn new(directory: Option<String>, threshold: Option<f64>) -> PyResult<Self> {
+        let threshold = match threshold {
+            Some(threshold) if threshold > 0.0 => Some(Duration::from_secs_f64(threshold)),
+            Some(_) => return Err(PyValueError::new_err("Stack dump threshold should be positive")),
+            None => None
+        };
+        Ok(Self { directory, threshold, ..Self::default() })
+    }
+
+    // Dumps get written from a dedicated thread, as getting the GIL might take
+    // a while when the application is the one hanging.
+    fn dump(&self, reason: String) {
+        if self.state.running.swap(true, Ordering::AcqRel) {
+            return
+        }
+        let directory = self.directory.clone();
+        let state = self.state.clone();
+        // in-process servers have no worker identity
+        let worker = Some(identity()).filter(|worker| worker.id > 0).map(|worker| (worker.id, worker.generation));
+        let spawned = std::thread::Builder::new()
+            .name("granian-stacks".to_string())
+            .spawn(move || {
+                let ret = Python::with_gil(|py| -> PyResult<String> {
+                    py.import("granian.stacks")?
+                        .getattr("dump_stacks")?
+                        .call1((directory, reason.as_str(), worker))?
+                        .extract()
+                });
+                match ret {
+                    Ok(path) => log::warn!("Stack snapshot written to {} ({})", path, reason),
+                    Err(err) => log::error!("Unable to write stack snapshot: {}", err)
+                }
+                state.running.store(false, Ordering::Release);
+            });
+        if spawned.is_err() {
+            self.state.running.store(false, Ordering::Release);
+        }
+    }
+
+    fn check_handlers(&self, threshold: Duration) {
+        let slow = {
+            let mut handlers = self.state.handlers.lock().unwrap();
+            let slow = handlers.values_mut()
+                .filter(|handler| !handler.reported && handler.started.elapsed() > threshold)
+                .min_by_key(|handler| handler.started);
+            match slow {
+                Some(handler) => {
+                    handler.reported = true;
+                    handler.request.clone()
+                },
+                None => return
+            }
+        };
+        {
+            let mut dumped_at = self.state.dumped_at.lock().unwrap();
+            if dumped_at.is_some_and(|at| at.elapsed() < THRESHOLD_COOLDOWN) {
+                return
+            }
+            *dumped_at = Some(Instant::now());
+        }
+        self.dump(format!("handler for {} running for more than {:.3}s", slow, threshold.as_secs_f64()));
+    }
+
+    // The watchdog stops once all the handles to the dumps state are gone
+    fn start_watchdog(&self, threshold: Duration) {
+        let state = Arc::downgrade(&self.state);
+        let directory = self.directory.clone();
+        let interval = (threshold / 4).min(WATCHDOG_INTERVAL_MAX);
+        let _ = std::thread::Builder::new()
+            .name("granian-watchdog".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                let state = match Weak::upgrade(&state) {
+                    Some(state) => state,
+                    None => break
+                };
+                let dumps = StackDumps { directory: directory.clone(), threshold: Some(threshold), state };
+                dumps.check_handlers(threshold);
+            });
+    }
+
+    pub fn watch(&self, req: &Request<Body>) -> HandlerWatch {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return HandlerWatch { watched: None }
+        };
+        self.state.watchdog.get_or_init(|| self.start_watchdog(threshold));
+        let id = self.state.next_handler.fetch_add(1, Ordering::Relaxed);
+        self.state.handlers.lock().unwrap().insert(id, RunningHandler {
+            started: Instant::now(),
+            request: format!("{} {}", req.method(), req.uri().path()),
+            reported: false
+        });
+        HandlerWatch { watched: Some((self.state.clone(), id)) }
+    }
+}
+
+// Keeps a request handler under watch, until dropped
+pub(crate) struct HandlerWatch {
+    watched: Option<(Arc<StackDumpState>, u64)>
+}
+
+impl Drop for HandlerWatch {
+    fn drop(&mut self) {
+        if let Some((state, id)) = self.watched.take() {
+            state.handlers.lock().unwrap().remove(&id);
+        }
+    }
+}
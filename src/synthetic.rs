@@ -0,0 +1,312 @@
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    Uri,
+    header::{
+        ACCESS_CONTROL_REQUEST_METHOD,
+        ALLOW,
+        CONTENT_LENGTH,
+        CONTENT_TYPE,
+        HeaderMap,
+        HeaderName,
+        HeaderValue,
+        HOST,
+        SERVER as HK_SERVER
+    }
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use crate::{
+    http::HV_SERVER,
+    metrics::{RouteSegment, parse_route, route_matches},
+    negotiation::{escape_html, escape_json}
+};
+
+const HV_DEFAULT_ALLOW: HeaderValue = HeaderValue::from_static("GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS");
+
+// (path, status, headers, body template)
+pub(crate) type SyntheticRoute = (String, u16, Vec<(String, String)>, String);
+
+enum Var {
+    Method,
+    Path,
+    QueryString,
+    Host,
+    Scheme,
+    Client
+}
+
+enum Segment {
+    Literal(String),
+    Var(Var)
+}
+
+fn parse_template(template: &str) -> PyResult<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            },
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(PyValueError::new_err(
+                            format!("Unterminated variable in response template '{}'", template)
+                        ))
+                    }
+                }
+                let var = match &name[..] {
+                    "method" => Var::Method,
+                    "path" => Var::Path,
+                    "query_string" => Var::QueryString,
+                    "host" => Var::Host,
+                    "scheme" => Var::Scheme,
+                    "client" => Var::Client,
+                    _ => return Err(PyValueError::new_err(
+                        format!("Unknown variable '{}' in response template", name)
+                    ))
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Var(var));
+            },
+            _ => literal.push(c)
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+// Request values are controlled by clients: they get escaped for the syntax of
+// the response content type, so they can't inject markup or JSON members
+#[derive(Clone, Copy)]
+enum Escaping {
+    Markup,
+    Json,
+    Verbatim
+}
+
+impl Escaping {
+    fn for_content_type(content_type: Option<&HeaderValue>) -> Self {
+        let essence = content_type
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        match &essence[..] {
+            "text/html" | "text/xml" | "application/xml" | "image/svg+xml" => Self::Markup,
+            "application/json" | "application/javascript" | "text/javascript" => Self::Json,
+            essence if essence.ends_with("+xml") => Self::Markup,
+            essence if essence.ends_with("+json") => Self::Json,
+            _ => Self::Verbatim
+        }
+    }
+
+    fn push(&self, body: &mut String, value: &str) {
+        match self {
+            Self::Markup => body.push_str(&escape_html(value)),
+            Self::Json => body.push_str(&escape_json(value)),
+            Self::Verbatim => body.push_str(value)
+        }
+    }
+}
+
+// Response bodies referencing values of the request
+pub(crate) struct BodyTemplate {
+    segments: Vec<Segment>
+}
+
+impl BodyTemplate {
+    pub fn parse(template: &str) -> PyResult<Self> {
+        Ok(Self { segments: parse_template(template)? })
+    }
+
+    pub fn render(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        host: Option<&HeaderValue>,
+        client_addr: SocketAddr,
+        scheme: &str,
+        content_type: Option<&HeaderValue>
+    ) -> String {
+        let escaping = Escaping::for_content_type(content_type);
+        let mut body = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(value) => body.push_str(value),
+                Segment::Var(Var::Method) => escaping.push(&mut body, method.as_str()),
+                Segment::Var(Var::Path) => escaping.push(&mut body, uri.path()),
+                Segment::Var(Var::QueryString) => escaping.push(&mut body, uri.query().unwrap_or("")),
+                Segment::Var(Var::Host) => escaping.push(&mut body, host.and_then(|v| v.to_str().ok()).unwrap_or("")),
+                Segment::Var(Var::Scheme) => body.push_str(scheme),
+                Segment::Var(Var::Client) => body.push_str(&client_addr.ip().to_string())
+            }
+        }
+        body
+    }
+}
+
+struct SyntheticResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: BodyTemplate
+}
+
+impl SyntheticResponse {
+    fn render<B>(&self, req: &Request<B>, client_addr: SocketAddr, scheme: &str) -> Response<Body> {
+        let body = self.body.render(
+            req.method(),
+            req.uri(),
+            req.headers().get(HOST),
+            client_addr,
+            scheme,
+            self.headers.get(CONTENT_TYPE)
+        );
+        // HEAD responses announce the length of the body GET would get
+        let length = HeaderValue::from(body.len());
+        let mut res = match *req.method() {
+            Method::HEAD => Response::new(Body::empty()),
+            _ => Response::new(Body::from(body))
+        };
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        res.headers_mut().insert(CONTENT_LENGTH, length);
+        res
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct SyntheticResponses {
+    routes: Arc<HashMap<String, SyntheticResponse>>
+}
+
+impl SyntheticResponses {
+    pub fn new(routes: Vec<SyntheticRoute>) -> PyResult<Self> {
+        let mut map = HashMap::with_capacity(routes.len());
+        for (path, status, headers, template) in routes {
+            let status = StatusCode::from_u16(status).map_err(
+                |_| PyValueError::new_err(format!("Invalid status code {} for '{}'", status, path))
+            )?;
+            let mut header_map = HeaderMap::with_capacity(headers.len() + 1);
+            header_map.insert(HK_SERVER, HV_SERVER);
+            for (key, value) in headers.iter() {
+                match (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(value)) {
+                    (Ok(key), Ok(value)) => { header_map.append(key, value); },
+                    _ => return Err(PyValueError::new_err(
+                        format!("Invalid header '{}' for '{}'", key, path)
+                    ))
+                }
+            }
+            map.insert(path, SyntheticResponse {
+                status,
+                headers: header_map,
+                body: BodyTemplate::parse(&template)?
+            });
+        }
+        Ok(Self { routes: Arc::new(map) })
+    }
+
+    pub fn respond<B>(
+        &self,
+        req: &Request<B>,
+        client_addr: SocketAddr,
+        scheme: &str
+    ) -> Option<Response<Body>> {
+        // other methods on the same paths are left to the application
+        if self.routes.is_empty() || !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return None
+        }
+        self.routes.get(req.uri().path()).map(|route| route.render(req, client_addr, scheme))
+    }
+}
+
+fn allow_header(methods: &[String]) -> PyResult<HeaderValue> {
+    let mut allowed: Vec<String> = Vec::with_capacity(methods.len() + 1);
+    for method in methods {
+        let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes()).map_err(
+            |_| PyValueError::new_err(format!("Invalid method: {}", method))
+        )?;
+        if !allowed.iter().any(|value| value == method.as_str()) {
+            allowed.push(method.to_string());
+        }
+    }
+    if !allowed.iter().any(|value| value == "OPTIONS") {
+        allowed.push("OPTIONS".to_string());
+    }
+    HeaderValue::from_str(&allowed.join(", ")).map_err(
+        |_| PyValueError::new_err(format!("Invalid methods: {:?}", methods))
+    )
+}
+
+// Answers `OPTIONS` requests at the server layer: the asterisk-form target, which
+// applications routing on paths can't handle, always gets the server-wide list of
+// allowed methods (overridable with the `*` route), while paths matching a route
+// template get the methods declared for it. CORS preflight requests are still
+// passed to the application, as they need its `Access-Control-*` headers.
+#[derive(Clone)]
+pub(crate) struct OptionsResponses {
+    server: HeaderValue,
+    routes: Arc<Vec<(Vec<RouteSegment>, HeaderValue)>>
+}
+
+impl OptionsResponses {
+    pub fn new(routes: Vec<(String, Vec<String>)>) -> PyResult<Self> {
+        let mut server = None;
+        let mut table = Vec::with_capacity(routes.len());
+        for (template, methods) in routes {
+            let allow = allow_header(&methods)?;
+            match &template[..] {
+                "*" => server = Some(allow),
+                _ => table.push((parse_route(&template)?, allow))
+            }
+        }
+        Ok(Self {
+            server: server.unwrap_or(HV_DEFAULT_ALLOW),
+            routes: Arc::new(table)
+        })
+    }
+
+    pub fn respond<B>(&self, req: &Request<B>) -> Option<Response<Body>> {
+        if req.method() != Method::OPTIONS {
+            return None
+        }
+        let allow = match req.uri().path() {
+            "*" => &self.server,
+            _ if self.routes.is_empty() || req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD) => {
+                return None
+            },
+            path => &self.routes.iter().find(|(segments, _)| route_matches(segments, path))?.1
+        };
+        let mut res = Response::new(Body::empty());
+        res.headers_mut().insert(HK_SERVER, HV_SERVER);
+        res.headers_mut().insert(ALLOW, allow.clone());
+        Some(res)
+    }
+}
+
+impl Default for OptionsResponses {
+    fn default() -> Self {
+        Self::new(Vec::new()).unwrap()
+    }
+}
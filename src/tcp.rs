@@ -1,14 +1,32 @@
+use hyper::server::{accept::Accept, conn::{AddrIncoming, AddrStream}};
 use pyo3::prelude::*;
 use pyo3::types::PyType;
 
-use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::fs::File;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 #[cfg(unix)]
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
 #[cfg(windows)]
 use std::os::windows::io::{AsRawSocket, FromRawSocket};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+use crate::{errors::Error, metrics::METRICS};
+
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+// Unix domain socket connections have no IP address, they get reported with the
+// unspecified one, which the scopes render as empty values
+pub(crate) const UNNAMED_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
 
 
 #[pyclass(module="granian._granian")]
@@ -118,19 +136,34 @@ impl ListenerHolder {
     }
 
     #[classmethod]
+    #[args(reuse_port="false")]
     pub fn from_address(
         _cls: &PyType,
         address: &str,
         port: u16,
-        backlog: i32
+        backlog: i32,
+        reuse_port: bool
     ) -> PyResult<Self> {
         let address: SocketAddr = (address.parse::<IpAddr>()?, port).into();
-        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
-        socket.set_reuse_address(true)?;
-        socket.bind(&address.into())?;
-        socket.listen(backlog)?;
-        let listener: TcpListener = socket.into();
-        Ok(Self { socket: listener })
+        Ok(Self { socket: bind_listener(address, backlog, reuse_port)? })
+    }
+
+    #[cfg(unix)]
+    #[classmethod]
+    pub fn from_fd(_cls: &PyType, fd: i32) -> PyResult<Self> {
+        Ok(Self { socket: inherited_listener(fd)? })
+    }
+
+    #[cfg(unix)]
+    #[classmethod]
+    #[args(permissions="None")]
+    pub fn from_unix_path(
+        _cls: &PyType,
+        path: &str,
+        backlog: i32,
+        permissions: Option<u32>
+    ) -> PyResult<Self> {
+        Ok(Self { socket: bind_unix_listener(path, backlog, permissions)? })
     }
 
     #[cfg(unix)]
@@ -167,6 +200,322 @@ impl ListenerHolder {
 }
 
 
+pub(crate) fn bind_listener(address: SocketAddr, backlog: i32, reuse_port: bool) -> Result<TcpListener, Error> {
+    let bind_error = |err: std::io::Error| Error::bind(format!("Unable to bind {}: {}", address, err));
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP)).map_err(bind_error)?;
+    socket.set_reuse_address(true).map_err(bind_error)?;
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuse_port(true).map_err(bind_error)?;
+    }
+    #[cfg(windows)]
+    let _ = reuse_port;
+    socket.bind(&address.into()).map_err(bind_error)?;
+    socket.listen(backlog).map_err(bind_error)?;
+    Ok(socket.into())
+}
+
+// Listening sockets get passed around as `TcpListener` handles, the Unix domain
+// ones included, and get told apart by their address family once accepting.
+// A socket file left over by a previous run gets replaced, unless something is
+// still listening on it.
+#[cfg(unix)]
+pub(crate) fn bind_unix_listener(path: &str, backlog: i32, permissions: Option<u32>) -> Result<TcpListener, Error> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let bind_error = |err: std::io::Error| Error::bind(format!("Unable to bind {}: {}", path, err));
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(Error::bind(format!("Unable to bind {}: the path exists and is not a socket", path)))
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => return Err(Error::bind(format!("Unable to bind {}: the socket is already in use", path))),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                log::info!("Removing stale socket {}", path);
+                std::fs::remove_file(path).map_err(bind_error)?;
+            },
+            Err(err) => return Err(bind_error(err))
+        }
+    }
+    let socket = Socket::new(Domain::UNIX, Type::STREAM, None).map_err(bind_error)?;
+    socket.bind(&socket2::SockAddr::unix(path).map_err(bind_error)?).map_err(bind_error)?;
+    if let Some(mode) = permissions {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(bind_error)?;
+    }
+    socket.listen(backlog).map_err(bind_error)?;
+    Ok(socket.into())
+}
+
+#[cfg_attr(windows, allow(unused_variables))]
+pub(crate) fn is_unix_listener(listener: &TcpListener) -> bool {
+    #[cfg(unix)]
+    return SockRef::from(listener).local_addr().is_ok_and(|addr| addr.family() == libc::AF_UNIX as libc::sa_family_t);
+    #[cfg(windows)]
+    return false;
+}
+
+// Sockets passed by systemd socket activation or a process supervisor, which
+// should already be bound and listening. The descriptor gets owned only once
+// checked, so to leave it alone on errors.
+#[cfg(unix)]
+pub(crate) fn inherited_listener(fd: i32) -> Result<TcpListener, Error> {
+    let inherited_error = |reason: String| Error::bind(format!("Unable to use inherited socket {}: {}", fd, reason));
+    let socket = std::mem::ManuallyDrop::new(unsafe { Socket::from_raw_fd(fd) });
+    let family = socket.local_addr().map_err(|err| inherited_error(err.to_string()))?.family() as libc::c_int;
+    if !matches!(family, libc::AF_INET | libc::AF_INET6 | libc::AF_UNIX) {
+        return Err(inherited_error("not an internet or Unix domain socket".to_string()))
+    }
+    if socket.r#type().map_err(|err| inherited_error(err.to_string()))? != Type::STREAM {
+        return Err(inherited_error("not a stream socket".to_string()))
+    }
+    let mut listening: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ACCEPTCONN,
+            &mut listening as *mut libc::c_int as *mut libc::c_void,
+            &mut len
+        )
+    };
+    if ret != 0 || listening == 0 {
+        return Err(inherited_error("not a listening socket".to_string()))
+    }
+    Ok(std::mem::ManuallyDrop::into_inner(socket).into())
+}
+
+// The scopes representation of an address
+pub(crate) fn addr_repr(addr: SocketAddr) -> String {
+    match addr == UNNAMED_ADDR {
+        true => String::new(),
+        false => addr.to_string()
+    }
+}
+
+pub(crate) fn addr_ip(addr: SocketAddr) -> String {
+    match addr == UNNAMED_ADDR {
+        true => String::new(),
+        false => addr.ip().to_string()
+    }
+}
+
+enum Listener {
+    Tcp(AddrIncoming),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener)
+}
+
+pub(crate) enum Connection {
+    Tcp(AddrStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream)
+}
+
+impl Connection {
+    pub fn remote_addr(&self) -> SocketAddr {
+        match self {
+            Self::Tcp(stream) => stream.remote_addr(),
+            #[cfg(unix)]
+            Self::Unix(_) => UNNAMED_ADDR
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        match self {
+            Self::Tcp(stream) => stream.local_addr(),
+            #[cfg(unix)]
+            Self::Unix(_) => UNNAMED_ADDR
+        }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf)
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf)
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>]
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs)
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.is_write_vectored()
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx)
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx)
+        }
+    }
+}
+
+// Accepts connections from a listening socket, surviving the failures of
+// `accept`: errors back off exponentially instead of tight looping. When out of
+// file descriptors, a reserved one gets released to accept and immediately close
+// pending connections, so clients get refused quickly instead of hanging in the
+// backlog until descriptors get freed. Aborted connections are skipped by hyper.
+pub(crate) struct Incoming {
+    inner: Listener,
+    listener: Arc<TcpListener>,
+    reserve: Option<File>,
+    backoff: Option<Pin<Box<Sleep>>>,
+    errors: u32
+}
+
+fn reserve_fd() -> Option<File> {
+    #[cfg(unix)]
+    return File::open("/dev/null").ok();
+    #[cfg(windows)]
+    return None;
+}
+
+#[cfg_attr(windows, allow(unused_variables))]
+fn fds_exhausted(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    return matches!(err.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE));
+    #[cfg(windows)]
+    return false;
+}
+
+impl Incoming {
+    pub fn new(tcp: TcpListener) -> io::Result<Self> {
+        tcp.set_nonblocking(true)?;
+        let listener = Arc::new(tcp.try_clone()?);
+        crate::backlog::register(Arc::downgrade(&listener));
+        #[cfg(unix)]
+        if is_unix_listener(&tcp) {
+            let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+            let inner = Listener::Unix(tokio::net::UnixListener::from_std(unix)?);
+            return Ok(Self { inner, listener, reserve: reserve_fd(), backoff: None, errors: 0 })
+        }
+        let mut inner = AddrIncoming::from_listener(tokio::net::TcpListener::from_std(tcp)?)
+            .map_err(io::Error::other)?;
+        inner.set_nodelay(true);
+        inner.set_sleep_on_errors(false);
+        Ok(Self { inner: Listener::Tcp(inner), listener, reserve: reserve_fd(), backoff: None, errors: 0 })
+    }
+
+    fn poll_listener(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Connection>>> {
+        match &mut self.inner {
+            Listener::Tcp(inner) => Pin::new(inner).poll_accept(cx).map(|conn| conn.map(|conn| conn.map(Connection::Tcp))),
+            #[cfg(unix)]
+            Listener::Unix(inner) => loop {
+                // like hyper does for TCP, connections aborted before the accept get skipped
+                match futures::ready!(inner.poll_accept(cx)) {
+                    Err(err) if is_connection_error(&err) => continue,
+                    ret => return Poll::Ready(Some(ret.map(|(stream, _)| Connection::Unix(stream))))
+                }
+            }
+        }
+    }
+
+    // Closes a pending connection using the reserved descriptor, if any
+    fn shed(&mut self) -> bool {
+        if self.reserve.take().is_none() {
+            return false
+        }
+        let shed = SockRef::from(&*self.listener).accept().is_ok();
+        if shed {
+            METRICS.connections_shed.inc();
+        }
+        self.reserve = reserve_fd();
+        shed
+    }
+
+    fn backoff(&mut self, err: io::Error) {
+        METRICS.accept_errors.inc();
+        let shed = fds_exhausted(&err) && self.shed();
+        // shedding keeps up with the incoming connections, retrying shortly
+        let delay = match shed {
+            true => ACCEPT_BACKOFF_MIN,
+            false => (ACCEPT_BACKOFF_MIN * 2u32.saturating_pow(self.errors)).min(ACCEPT_BACKOFF_MAX)
+        };
+        if self.errors == 0 {
+            match shed {
+                true => log::warn!("Out of file descriptors ({}), closing incoming connections", err),
+                false => log::warn!("Unable to accept connections ({}), retrying in {:?}", err, delay)
+            }
+        }
+        self.errors = self.errors.saturating_add(1);
+        self.backoff = Some(Box::pin(tokio::time::sleep(delay)));
+    }
+}
+
+#[cfg(unix)]
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    )
+}
+
+impl Accept for Incoming {
+    type Conn = Connection;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        loop {
+            if let Some(backoff) = self.backoff.as_mut() {
+                if backoff.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending
+                }
+                self.backoff = None;
+            }
+            match self.poll_listener(cx) {
+                Poll::Ready(Some(Err(err))) => self.backoff(err),
+                Poll::Ready(Some(Ok(stream))) => {
+                    if self.errors > 0 {
+                        log::info!("Accepting connections again");
+                        self.errors = 0;
+                    }
+                    return Poll::Ready(Some(Ok(stream)))
+                },
+                ret => return ret
+            }
+        }
+    }
+}
+
 pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
     module.add_class::<ListenerHolder>()?;
     module.add_class::<SocketHolder>()?;
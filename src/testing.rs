@@ -0,0 +1,349 @@
+use hyper::{Body, Request, Response, header::{HeaderName, HeaderValue}};
+use once_cell::sync::Lazy;
+use pyo3::{exceptions::{PyRuntimeError, PyValueError}, prelude::*, types::PyBytes};
+use std::{net::{IpAddr, SocketAddr, TcpListener}, sync::{Arc, Mutex}, time::Duration};
+use tokio::sync::Notify;
+use tokio_rustls::rustls::ServerConfig;
+
+use crate::{
+    asgi,
+    buffers::BufferBody,
+    callbacks::CallbackWrapper,
+    clock,
+    diagnostics::{ConnTrace, RequestTrace},
+    drain::Drain,
+    errors::Error,
+    http::Http2Settings,
+    idle::IdleTimeout,
+    keepalive::KeepAlive,
+    proxy::ProxyProtocol,
+    rsgi,
+    runtime::{RuntimeRef, RuntimeWrapper, future_into_py, init_runtime_mt},
+    server::Interface,
+    slo::SloPolicy,
+    tcp::bind_listener,
+    timeouts::RequestTimeouts,
+    tls::RecordSizing,
+    workers::{WorkerConfig, WorkerCtx, WorkerIdentity, WorkerOptions},
+    wsgi
+};
+#[cfg(unix)]
+use crate::tcp::{UNNAMED_ADDR, bind_unix_listener, inherited_listener};
+
+
+// Shared by all the clients, as test suites create many of them. It gets shut down
+// on interpreter exit, before its threads can race the finalization on the GIL.
+static RUNTIME: Lazy<Mutex<Option<RuntimeWrapper>>> = Lazy::new(|| Mutex::new(None));
+
+fn runtime() -> RuntimeRef {
+    RUNTIME.lock().unwrap().get_or_insert_with(|| init_runtime_mt(1, 1)).handler()
+}
+
+fn parse_interface(interface: &str) -> PyResult<Interface> {
+    match interface {
+        "asgi" => Ok(Interface::Asgi),
+        "rsgi" => Ok(Interface::Rsgi),
+        "wsgi" => Ok(Interface::Wsgi),
+        _ => Err(PyValueError::new_err(format!("Invalid interface: {}", interface)))
+    }
+}
+
+const SERVER_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 8000);
+const CLIENT_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 50000);
+
+// Dispatches requests straight into the interface handlers, with no sockets
+// involved: applications get the same scope and protocol objects they would
+// get from a running server, after the same filters and timeouts.
+#[pyclass(module="granian._granian")]
+pub(crate) struct TestClient {
+    interface: Interface,
+    callback: PyObject,
+    ctx: Arc<WorkerCtx>
+}
+
+#[pymethods]
+impl TestClient {
+    #[new]
+    fn new(interface: &str, callback: PyObject, options: WorkerOptions) -> PyResult<Self> {
+        crate::logging::init();
+        let interface = parse_interface(interface)?;
+        let config = WorkerConfig::new(WorkerIdentity::default(), -1, options)?;
+        Ok(Self { interface, callback, ctx: Arc::new(config.ctx()) })
+    }
+
+    #[args(headers="vec![]", body="None")]
+    fn request<'p>(
+        &self,
+        py: Python<'p>,
+        method: &str,
+        path: &str,
+        headers: Vec<(String, String)>,
+        body: Option<BufferBody>
+    ) -> PyResult<&'p PyAny> {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(req_headers) = builder.headers_mut() {
+            if let Some(body) = &body {
+                req_headers.insert(hyper::header::CONTENT_LENGTH, HeaderValue::from(body.0.len()));
+            }
+            for (key, value) in headers {
+                req_headers.append(
+                    HeaderName::from_bytes(key.as_bytes()).map_err(|err| PyValueError::new_err(err.to_string()))?,
+                    HeaderValue::from_str(&value).map_err(|err| PyValueError::new_err(err.to_string()))?
+                );
+            }
+            if !req_headers.contains_key(hyper::header::HOST) {
+                req_headers.insert(hyper::header::HOST, HeaderValue::from_static("testserver"));
+            }
+        }
+        let mut req = builder
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.0)))
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let context = py.import("contextvars")?.call_method0("copy_context")?;
+        let callback = CallbackWrapper::new(self.callback.clone_ref(py), event_loop, context);
+        let interface = self.interface;
+        let ctx = self.ctx.clone();
+        let rt = runtime();
+
+        future_into_py(rt.clone(), py, async move {
+            let trace = RequestTrace::attach(&mut req, &ConnTrace::default());
+            let deadlines = ctx.deadlines.clone();
+            let idempotency = ctx.idempotency.clone();
+            let error_format = ctx.error_format;
+            let accept = req.headers().get(hyper::header::ACCEPT).cloned();
+            let hooked = ctx.status_hooks.request(&req, CLIENT_ADDR.into(), "http");
+            let res = deadlines.handle(req, CLIENT_ADDR.into(), |req| idempotency.handle(
+                req,
+                CLIENT_ADDR.into(),
+                |req| dispatch(interface, rt, callback, ctx, req)
+            )).await;
+            let res = error_format.render(accept.as_ref(), res);
+            let res = match hooked {
+                Some(hooked) => hooked.respond(res),
+                None => res
+            };
+            trace.check_response(res.status());
+
+            let (parts, body) = res.into_parts();
+            let body = hyper::body::to_bytes(body).await
+                .map_err(|err| Error::protocol(format!("Unable to read response body: {}", err)))?;
+            let headers: Vec<(String, String)> = parts.headers.iter()
+                .map(|(key, value)| (key.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+                .collect();
+            Ok(Python::with_gil(|py| {
+                (parts.status.as_u16(), headers, PyBytes::new(py, &body[..])).to_object(py)
+            }))
+        })
+    }
+}
+
+async fn dispatch(
+    interface: Interface,
+    rt: RuntimeRef,
+    callback: CallbackWrapper,
+    ctx: Arc<WorkerCtx>,
+    req: Request<Body>
+) -> Response<Body> {
+    let server_addr: SocketAddr = SERVER_ADDR.into();
+    let client_addr: SocketAddr = CLIENT_ADDR.into();
+    match interface {
+        Interface::Asgi => asgi::http::handle_rtb(rt, callback, ctx, server_addr, client_addr, req, "http").await,
+        Interface::Rsgi => rsgi::http::handle_rtb(rt, callback, ctx, server_addr, client_addr, req, "http").await,
+        Interface::Wsgi => wsgi::http::handle_rtb(rt, callback, ctx, server_addr, client_addr, req, "http").await
+    }
+}
+
+macro_rules! serve_test {
+    ($callback:expr, $rt:expr, $ctx:expr, $listener:expr, $idle_timeout:expr, $request_timeouts:expr, $keep_alive:expr, $drain:expr, $proxy_protocol:expr, $http_mode:expr, $http2_settings:expr, $tls:expr, $tls_records:expr, $shutdown:expr, $target:expr) => {{
+        match $tls {
+            Some(tls) => {
+                let service = crate::workers::build_service_ssl!($callback, $rt, $ctx, $target);
+                let builder = hyper::Server::builder(crate::tls::tls_listen(tls, $tls_records, $idle_timeout, $request_timeouts, $keep_alive, $proxy_protocol, $listener))
+                    .http2_max_concurrent_streams($http2_settings.max_concurrent_streams);
+                let server = crate::workers::http_protocols(builder, &$http_mode)
+                    .serve(service)
+                    .with_graceful_shutdown($drain.signal(async move { $shutdown.notified().await }));
+                $drain.run(server).await.unwrap_or(Ok(())).map_err(Error::protocol)
+            },
+            None => {
+                let service = crate::workers::build_service!($callback, $rt, $ctx, $target);
+                let builder = hyper::Server::builder(crate::idle::listen($listener, $idle_timeout, $request_timeouts, $keep_alive, $proxy_protocol).map_err(Error::bind)?)
+                    .http2_max_concurrent_streams($http2_settings.max_concurrent_streams);
+                let server = crate::workers::http_protocols(builder, &$http_mode)
+                    .serve(service)
+                    .with_graceful_shutdown($drain.signal(async move { $shutdown.notified().await }));
+                $drain.run(server).await.unwrap_or(Ok(())).map_err(Error::protocol)
+            }
+        }
+    }};
+}
+
+// Serves the application from a real listener, on an ephemeral port unless told
+// otherwise, for tests needing actual HTTP and websocket traffic. It runs on the
+// shared runtime and the event loop awaiting `serve`, so the process stays the
+// one running the tests; `shutdown` stops it gracefully, letting `serve` return.
+#[pyclass(module="granian._granian")]
+pub(crate) struct TestServer {
+    interface: Interface,
+    callback: PyObject,
+    ctx: Arc<WorkerCtx>,
+    slo: SloPolicy,
+    idle_timeout: IdleTimeout,
+    request_timeouts: RequestTimeouts,
+    keep_alive: KeepAlive,
+    drain: Drain,
+    proxy_protocol: ProxyProtocol,
+    http_mode: String,
+    http2_settings: Http2Settings,
+    tls: Option<Arc<ServerConfig>>,
+    tls_records: RecordSizing,
+    websockets: bool,
+    listener: Option<TcpListener>,
+    local_addr: SocketAddr,
+    shutdown: Arc<Notify>
+}
+
+#[pymethods]
+impl TestServer {
+    #[new]
+    fn new(
+        interface: &str,
+        callback: PyObject,
+        address: String,
+        port: u16,
+        uds: Option<String>,
+        fd: Option<i32>,
+        options: WorkerOptions
+    ) -> PyResult<Self> {
+        crate::logging::init();
+        let interface = parse_interface(interface)?;
+        let (listener, local_addr) = match (fd, uds) {
+            #[cfg(unix)]
+            (Some(fd), _) => {
+                let listener = inherited_listener(fd)?;
+                let local_addr = listener.local_addr().unwrap_or(UNNAMED_ADDR);
+                (listener, local_addr)
+            },
+            #[cfg(unix)]
+            (None, Some(path)) => (bind_unix_listener(&path, 128, None)?, UNNAMED_ADDR),
+            #[cfg(windows)]
+            (Some(_), _) | (None, Some(_)) => return Err(PyValueError::new_err(
+                "Inherited sockets and Unix domain sockets are not supported on Windows"
+            )),
+            (None, None) => {
+                let ip: IpAddr = address.parse()
+                    .map_err(|_| PyValueError::new_err(format!("Invalid address: {}", address)))?;
+                let listener = bind_listener((ip, port).into(), 128, false)?;
+                let local_addr = listener.local_addr().map_err(Error::bind)?;
+                (listener, local_addr)
+            }
+        };
+        let websockets = options.websockets && interface != Interface::Wsgi;
+        let config = WorkerConfig::new(WorkerIdentity::default(), -1, WorkerOptions { websockets, ..options })?;
+        let tls = match config.ssl_enabled {
+            true => Some(Arc::new(config.tls_cfg()?)),
+            false => None
+        };
+        Ok(Self {
+            interface,
+            callback,
+            ctx: Arc::new(config.ctx()),
+            slo: config.slo.clone(),
+            idle_timeout: config.idle_timeout,
+            request_timeouts: config.request_timeouts,
+            keep_alive: config.keep_alive,
+            drain: config.drain.clone(),
+            proxy_protocol: config.proxy_protocol,
+            http_mode: config.http_mode.clone(),
+            http2_settings: config.http2_settings,
+            tls,
+            tls_records: config.tls_records,
+            websockets,
+            listener: Some(listener),
+            local_addr,
+            shutdown: Arc::new(Notify::new())
+        })
+    }
+
+    #[getter(address)]
+    fn get_address(&self) -> (String, u16) {
+        (self.local_addr.ip().to_string(), self.local_addr.port())
+    }
+
+    fn serve<'p>(&mut self, py: Python<'p>) -> PyResult<&'p PyAny> {
+        let listener = self.listener.take()
+            .ok_or_else(|| PyRuntimeError::new_err("Test server was already started"))?;
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let context = py.import("contextvars")?.call_method0("copy_context")?;
+        let callback = CallbackWrapper::new(self.callback.clone_ref(py), event_loop, context);
+        let interface = self.interface;
+        let websockets = self.websockets;
+        let ctx = self.ctx.clone();
+        let shutdown = self.shutdown.clone();
+        let slo = self.slo.clone();
+        let idle_timeout = self.idle_timeout;
+        let request_timeouts = self.request_timeouts;
+        let keep_alive = self.keep_alive;
+        let drain = self.drain.clone();
+        let proxy_protocol = self.proxy_protocol;
+        let http_mode = self.http_mode.clone();
+        let http2_settings = self.http2_settings;
+        let tls = self.tls.clone();
+        let tls_records = self.tls_records;
+        let rt = runtime();
+
+        future_into_py(rt.clone(), py, async move {
+            log::info!("Started test server");
+            let _slo = slo.start();
+            match (interface, websockets) {
+                (Interface::Asgi, false) => serve_test!(callback, rt, ctx, listener, idle_timeout, request_timeouts, keep_alive, drain, proxy_protocol, http_mode, http2_settings, tls, tls_records, shutdown, asgi::http::handle_rtb),
+                (Interface::Asgi, true) => serve_test!(callback, rt, ctx, listener, idle_timeout, request_timeouts, keep_alive, drain, proxy_protocol, http_mode, http2_settings, tls, tls_records, shutdown, asgi::http::handle_rtb_ws),
+                (Interface::Rsgi, false) => serve_test!(callback, rt, ctx, listener, idle_timeout, request_timeouts, keep_alive, drain, proxy_protocol, http_mode, http2_settings, tls, tls_records, shutdown, rsgi::http::handle_rtb),
+                (Interface::Rsgi, true) => serve_test!(callback, rt, ctx, listener, idle_timeout, request_timeouts, keep_alive, drain, proxy_protocol, http_mode, http2_settings, tls, tls_records, shutdown, rsgi::http::handle_rtb_ws),
+                (Interface::Wsgi, _) => serve_test!(callback, rt, ctx, listener, idle_timeout, request_timeouts, keep_alive, drain, proxy_protocol, http_mode, http2_settings, tls, tls_records, shutdown, wsgi::http::handle_rtb)
+            }?;
+            log::info!("Stopped test server");
+            Ok(Python::with_gil(|py| py.None()))
+        })
+    }
+
+    fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+}
+
+#[pyfunction]
+fn mock_clock() {
+    clock::mock();
+}
+
+#[pyfunction]
+fn advance_clock(seconds: f64) -> PyResult<()> {
+    let by = Duration::try_from_secs_f64(seconds)
+        .map_err(|_| PyValueError::new_err(format!("Invalid clock advance: {}", seconds)))?;
+    clock::advance(by);
+    Ok(())
+}
+
+#[pyfunction]
+fn reset_clock() {
+    clock::reset();
+}
+
+#[pyfunction]
+fn shutdown_test_runtime(py: Python) {
+    let rt = RUNTIME.lock().unwrap().take();
+    py.allow_threads(|| drop(rt));
+}
+
+pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+    module.add_class::<TestClient>()?;
+    module.add_class::<TestServer>()?;
+    module.add_function(wrap_pyfunction!(mock_clock, module)?)?;
+    module.add_function(wrap_pyfunction!(advance_clock, module)?)?;
+    module.add_function(wrap_pyfunction!(reset_clock, module)?)?;
+    module.add_function(wrap_pyfunction!(shutdown_test_runtime, module)?)?;
+
+    Ok(())
+}
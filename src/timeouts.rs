@@ -0,0 +1,303 @@
+use bytes::Bytes;
+use futures::stream::Stream;
+use hyper::{
+    Body,
+    Request,
+    Response,
+    StatusCode,
+    Version,
+    body::HttpBody,
+    header::{CONNECTION, HeaderValue}
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant}
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{clock, http::response_error, wheel::Timer};
+
+
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0";
+// Written straight to the connection, as no response can be in progress while
+// a request head is still being received.
+const TIMEOUT_RESPONSE: &[u8] = b"HTTP/1.1 408 Request Timeout\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+
+// Timeouts of the requests being received, against clients sending them as
+// slowly as possible to hold connections, like slowloris does: heads should be
+// received within `header` from their first byte, bodies should not stall for
+// more than `body` between chunks, and whole requests should be received within
+// `total`. Expired requests get a `408` response and their connection closed.
+// Timeouts only run while waiting on the client, not while the application is
+// busy, and only on HTTP/1 connections, as HTTP/2 multiplexes requests.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RequestTimeouts {
+    header: Option<Duration>,
+    body: Option<Duration>,
+    total: Option<Duration>
+}
+
+impl RequestTimeouts {
+    pub fn new(header: f64, body: f64, total: f64) -> PyResult<Self> {
+        let timeout = |value: f64, name: &str| match value.is_nan() || value < 0.0 {
+            true => Err(PyValueError::new_err(format!("Request {} timeout should not be negative", name))),
+            false => Ok((value > 0.0).then(|| Duration::from_secs_f64(value)))
+        };
+        Ok(Self { header: timeout(header, "header")?, body: timeout(body, "body")?, total: timeout(total, "total")? })
+    }
+
+    pub fn connection(&self) -> ConnectionTimeouts {
+        let enabled = self.header.is_some() || self.body.is_some() || self.total.is_some();
+        ConnectionTimeouts {
+            exchange: Exchange { state: enabled.then(Arc::default), timeouts: *self },
+            sniffed: Some(0),
+            timer: Timer::default(),
+            response: None
+        }
+    }
+
+    fn head_deadline(&self, started: Instant) -> Option<Instant> {
+        [self.header, self.total].into_iter().flatten().min().map(|timeout| started + timeout)
+    }
+
+    fn body_deadline(&self, started: Option<Instant>, waiting: Instant) -> Option<Instant> {
+        let total = self.total.zip(started).map(|(timeout, started)| started + timeout);
+        self.body.map(|timeout| waiting + timeout).into_iter().chain(total).min()
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Phase {
+    #[default]
+    Idle,
+    Head,
+    Body,
+    Upgraded
+}
+
+#[derive(Default)]
+struct ExchangeState {
+    phase: Phase,
+    // when the first byte of the request being received arrived
+    started: Option<Instant>,
+    request: u64,
+    timed_out: Option<u64>
+}
+
+impl ExchangeState {
+    fn finish(&mut self) {
+        self.phase = Phase::Idle;
+        self.started = None;
+    }
+}
+
+// The request being received on a connection, followed by both its stream,
+// receiving the bytes, and its service, knowing where requests begin and end.
+#[derive(Clone)]
+pub(crate) struct Exchange {
+    state: Option<Arc<Mutex<ExchangeState>>>,
+    timeouts: RequestTimeouts
+}
+
+impl Exchange {
+    pub fn request(&self, req: Request<Body>) -> Request<Body> {
+        let state = match &self.state {
+            Some(state) if req.version() < Version::HTTP_2 => state,
+            _ => return req
+        };
+        let mut guard = state.lock().unwrap();
+        guard.request += 1;
+        if req.body().is_end_stream() {
+            guard.finish();
+            return req
+        }
+        guard.phase = Phase::Body;
+        let (request, started) = (guard.request, guard.started);
+        drop(guard);
+        let (parts, body) = req.into_parts();
+        let body = TimedBody {
+            inner: body,
+            state: state.clone(),
+            timeouts: self.timeouts,
+            started,
+            request,
+            waiting: None,
+            timer: Timer::default(),
+            done: false
+        };
+        Request::from_parts(parts, Body::wrap_stream(body))
+    }
+
+    pub fn respond(&self, res: Response<Body>) -> Response<Body> {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return res
+        };
+        let mut state = state.lock().unwrap();
+        if res.status() == StatusCode::SWITCHING_PROTOCOLS {
+            state.phase = Phase::Upgraded;
+        }
+        if state.timed_out != Some(state.request) {
+            return res
+        }
+        let mut res = response_error(StatusCode::REQUEST_TIMEOUT, "", None);
+        res.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+        res
+    }
+}
+
+// The body timeouts run while the application waits for more data, as the
+// connection might get read ahead of it.
+struct TimedBody {
+    inner: Body,
+    state: Arc<Mutex<ExchangeState>>,
+    timeouts: RequestTimeouts,
+    started: Option<Instant>,
+    request: u64,
+    waiting: Option<Instant>,
+    timer: Timer,
+    done: bool
+}
+
+impl TimedBody {
+    // Bodies dropped before their end are not waited for anymore either
+    fn finish(&mut self) {
+        if self.done {
+            return
+        }
+        self.done = true;
+        let mut state = self.state.lock().unwrap();
+        if state.request == self.request && state.phase == Phase::Body {
+            state.finish();
+        }
+    }
+}
+
+impl Stream for TimedBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Poll::Ready(ret) = Pin::new(&mut this.inner).poll_next(cx) {
+            this.waiting = None;
+            if ret.is_none() {
+                this.finish();
+            }
+            return Poll::Ready(ret.map(|chunk| chunk.map_err(io::Error::other)))
+        }
+        if this.done {
+            return Poll::Pending
+        }
+        let waiting = *this.waiting.get_or_insert_with(clock::now);
+        let deadline = match this.timeouts.body_deadline(this.started, waiting) {
+            Some(deadline) => deadline,
+            None => return Poll::Pending
+        };
+        ready!(this.timer.poll_at(deadline, cx));
+        log::debug!("Request body not received within the timeout");
+        this.state.lock().unwrap().timed_out = Some(this.request);
+        // dropping the rest tells hyper not to wait for it
+        this.inner = Body::empty();
+        this.finish();
+        Poll::Ready(Some(Err(timed_out())))
+    }
+}
+
+impl Drop for TimedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+pub(crate) struct ConnectionTimeouts {
+    exchange: Exchange,
+    // the bytes matching the HTTP/2 preface so far, until told apart
+    sniffed: Option<usize>,
+    timer: Timer,
+    // the bytes of the timeout response written so far
+    response: Option<usize>
+}
+
+impl ConnectionTimeouts {
+    pub fn exchange(&self) -> Exchange {
+        self.exchange.clone()
+    }
+
+    pub fn poll_read<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        inner: &mut S,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        let state = match &self.exchange.state {
+            Some(state) => state.clone(),
+            None => return Pin::new(inner).poll_read(cx, buf)
+        };
+        if self.response.is_some() {
+            return self.poll_respond(inner, cx)
+        }
+        let filled = buf.filled().len();
+        if let Poll::Ready(ret) = Pin::new(&mut *inner).poll_read(cx, buf) {
+            if ret.is_ok() && buf.filled().len() > filled {
+                self.received(&state, &buf.filled()[filled..]);
+            }
+            return Poll::Ready(ret)
+        }
+        let deadline = {
+            let state = state.lock().unwrap();
+            match (state.phase, state.started) {
+                (Phase::Head, Some(started)) => self.exchange.timeouts.head_deadline(started),
+                _ => None
+            }
+        };
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.timer.clear();
+                return Poll::Pending
+            }
+        };
+        ready!(self.timer.poll_at(deadline, cx));
+        log::debug!("Closing connection not sending a request head within the timeout");
+        self.response = Some(0);
+        self.poll_respond(inner, cx)
+    }
+
+    fn received(&mut self, state: &Mutex<ExchangeState>, data: &[u8]) {
+        if let Some(sniffed) = self.sniffed {
+            let size = data.len().min(HTTP2_PREFACE.len() - sniffed);
+            self.sniffed = match data[..size] == HTTP2_PREFACE[sniffed..sniffed + size] {
+                true if sniffed + size == HTTP2_PREFACE.len() => {
+                    self.exchange.state = None;
+                    return
+                },
+                true => Some(sniffed + size),
+                false => None
+            };
+        }
+        let mut state = state.lock().unwrap();
+        if state.phase == Phase::Idle {
+            state.phase = Phase::Head;
+            state.started = Some(clock::now());
+        }
+    }
+
+    fn poll_respond<S: AsyncWrite + Unpin>(&mut self, inner: &mut S, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(written) = self.response.filter(|written| *written < TIMEOUT_RESPONSE.len()) {
+            match ready!(Pin::new(&mut *inner).poll_write(cx, &TIMEOUT_RESPONSE[written..])) {
+                Ok(size) if size > 0 => self.response = Some(written + size),
+                _ => break
+            }
+        }
+        let _ = ready!(Pin::new(&mut *inner).poll_flush(cx));
+        Poll::Ready(Err(timed_out()))
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "request not received within the timeout")
+}
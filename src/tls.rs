@@ -1,34 +1,558 @@
 use futures::stream::StreamExt;
-use hyper::server::{accept, conn::{AddrIncoming, AddrStream}};
-use std::{fs, future, io, iter::Iterator, net::TcpListener, sync::Arc};
-use tls_listener::{Error as TlsError, TlsListener};
+use hyper::{Body, Request, server::accept::{self, Accept}};
+use once_cell::sync::Lazy;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    collections::HashMap,
+    fs,
+    future,
+    io,
+    iter::Iterator,
+    net::TcpListener,
+    pin::Pin,
+    sync::{Arc, Mutex, Once, RwLock, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime}
+};
+use tls_listener::{AsyncAccept, Error as TlsError, TlsListener};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio_rustls::{
     TlsAcceptor,
-    rustls::{Certificate, PrivateKey, ServerConfig},
+    rustls::{
+        Certificate,
+        ConfigBuilder,
+        PrivateKey,
+        RootCertStore,
+        ServerConfig,
+        ServerConnection,
+        server::{
+            AllowAnyAnonymousOrAuthenticatedClient,
+            AllowAnyAuthenticatedClient,
+            ClientHello,
+            ResolvesServerCert,
+            WantsServerCert
+        },
+        sign::{CertifiedKey, any_supported_type}
+    },
     server::TlsStream
 };
 
+use crate::{
+    clock,
+    idle::{IdleStream, IdleTimeout},
+    keepalive::KeepAlive,
+    proxy::{ProxiedAddr, ProxyProtocol, ProxyStream},
+    tcp::{Connection, Incoming},
+    timeouts::RequestTimeouts,
+    x509::certificate_subject
+};
+
+
+const RECORD_SIZE_MIN: usize = 32;
+const RECORD_SIZE_MAX: usize = 16384;
+const RECORD_HEADER_LEN: usize = 5;
+// Small records are used until this much data gets sent, and again after idling
+const RECORD_BOOST_AFTER: u64 = 1024 * 1024;
+const RECORD_IDLE_RESET: Duration = Duration::from_secs(1);
+pub(crate) const ALPN_H2: &[u8] = b"h2";
+pub(crate) const ALPN_HTTP1: &[u8] = b"http/1.1";
+
+// Dynamic TLS record sizing: while a connection is starting or has been idle, records
+// are kept small enough to fit a single TCP segment, so clients can decrypt the first
+// bytes without waiting for a whole 16KB record; bulk transfers then use full records.
+#[derive(Clone, Copy)]
+pub(crate) struct RecordSizing {
+    initial: usize,
+    max: usize
+}
+
+impl RecordSizing {
+    pub fn new(initial: usize, max: usize) -> PyResult<Self> {
+        if !(RECORD_SIZE_MIN..=RECORD_SIZE_MAX).contains(&max) {
+            return Err(PyValueError::new_err(format!(
+                "TLS max record size should be between {} and {}", RECORD_SIZE_MIN, RECORD_SIZE_MAX
+            )))
+        }
+        if initial != 0 && !(RECORD_SIZE_MIN..=max).contains(&initial) {
+            return Err(PyValueError::new_err(format!(
+                "TLS initial record size should be between {} and the max record size", RECORD_SIZE_MIN
+            )))
+        }
+        Ok(Self { initial, max })
+    }
+
+    pub fn max_fragment_size(&self) -> Option<usize> {
+        match self.max {
+            RECORD_SIZE_MAX => None,
+            max => Some(max + RECORD_HEADER_LEN)
+        }
+    }
+}
+
+// The application protocols offered to clients during the handshake, in order
+// of preference, for the given HTTP mode. Clients not using ALPN get HTTP/1.1
+// or HTTP/2 depending on the connection preface.
+pub(crate) fn alpn_protocols(http_mode: &str) -> Vec<Vec<u8>> {
+    match http_mode {
+        "1" => vec![ALPN_HTTP1.to_vec()],
+        "2" => vec![ALPN_H2.to_vec()],
+        _ => vec![ALPN_H2.to_vec(), ALPN_HTTP1.to_vec()]
+    }
+}
+
+// Verification of the certificates presented by clients, against the CAs of a
+// PEM bundle. In `required` mode connections without a valid certificate fail
+// the handshake, while in `optional` mode clients can also go without one;
+// invalid certificates get refused by both.
+#[derive(Clone, Default)]
+pub(crate) struct ClientAuth {
+    roots: Option<RootCertStore>,
+    required: bool
+}
 
-pub(crate) type TlsAddrStream = TlsStream<AddrStream>;
+impl ClientAuth {
+    pub fn new(ca: Option<String>, mode: &str) -> PyResult<Self> {
+        let required = match mode {
+            "required" => true,
+            "optional" => false,
+            _ => return Err(PyValueError::new_err("Invalid TLS client verification mode"))
+        };
+        let roots = match ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                let certs = load_certs(&ca).map_err(|err| {
+                    PyValueError::new_err(format!("Unable to load TLS CA bundle: {}", err))
+                })?;
+                for cert in certs {
+                    roots.add(&cert).map_err(|err| {
+                        PyValueError::new_err(format!("Invalid certificate in TLS CA bundle {}: {:?}", ca, err))
+                    })?;
+                }
+                if roots.is_empty() {
+                    return Err(PyValueError::new_err(format!("No certificates found in TLS CA bundle {}", ca)))
+                }
+                Some(roots)
+            },
+            None => None
+        };
+        Ok(Self { roots, required })
+    }
+
+    pub fn config(&self) -> ConfigBuilder<ServerConfig, WantsServerCert> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        match (&self.roots, self.required) {
+            (Some(roots), true) => builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots.clone())),
+            (Some(roots), false) => builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots.clone())),
+            (None, _) => builder.with_no_client_auth()
+        }
+    }
+}
+
+// What requests get to know about the TLS session of their connection: the
+// negotiated version and cipher suite, and the certificate chain the client
+// authenticated with, if any, already verified during the handshake.
+pub(crate) struct TlsSession {
+    pub version: u16,
+    pub cipher_suite: u16,
+    pub client_chain: Vec<Certificate>,
+    pub client_subject: Option<String>
+}
+
+impl TlsSession {
+    fn new(conn: &ServerConnection) -> Self {
+        let client_chain = conn.peer_certificates().map(|certs| certs.to_vec()).unwrap_or_default();
+        let client_subject = client_chain.first().and_then(|cert| certificate_subject(&cert.0));
+        Self {
+            version: conn.protocol_version().map(|version| version.get_u16()).unwrap_or_default(),
+            cipher_suite: conn.negotiated_cipher_suite().map(|suite| suite.suite().get_u16()).unwrap_or_default(),
+            client_chain,
+            client_subject
+        }
+    }
+
+    pub fn client_cert(&self) -> Option<&[u8]> {
+        self.client_chain.first().map(|cert| &cert.0[..])
+    }
+
+    // As nginx reports it: certificates get verified during the handshake, so
+    // the ones presented are valid for sure
+    pub fn client_verify(&self) -> &'static str {
+        match self.client_chain.is_empty() {
+            true => "NONE",
+            false => "SUCCESS"
+        }
+    }
+
+    // The chain in PEM format, as the ASGI TLS extension wants it
+    pub fn client_chain_pem(&self) -> Vec<String> {
+        self.client_chain.iter().map(|cert| {
+            let encoded = base64::encode(&cert.0);
+            let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+            for line in encoded.as_bytes().chunks(64) {
+                pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+                pem.push('\n');
+            }
+            pem.push_str("-----END CERTIFICATE-----\n");
+            pem
+        }).collect()
+    }
+}
+
+pub(crate) fn request_session(req: &Request<Body>) -> Option<Arc<TlsSession>> {
+    req.extensions().get::<Arc<TlsSession>>().cloned()
+}
+
+// Outcome of the TLS handshake of a connection: the instants of the TCP accept
+// and of the completed handshake, along with the negotiated protocol, if any.
+#[derive(Clone, Copy)]
+pub(crate) struct TlsHandshake {
+    pub accepted: Instant,
+    pub established: Instant,
+    pub alpn: Option<&'static str>
+}
+
+// TCP streams remembering when they were accepted, so the time spent in
+// the TLS handshake can be told apart from the rest of the connection.
+pub(crate) struct AcceptedStream {
+    inner: ProxyStream,
+    accepted: Instant
+}
+
+impl AsyncRead for AcceptedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for AcceptedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+struct AcceptedIncoming(Incoming, ProxyProtocol);
+
+impl AsyncAccept for AcceptedIncoming {
+    type Connection = AcceptedStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>
+    ) -> Poll<Option<Result<Self::Connection, Self::Error>>> {
+        let proxy_protocol = self.1;
+        Pin::new(&mut self.0).poll_accept(cx).map(|conn| conn.map(|conn| conn.map(|stream| {
+            AcceptedStream { inner: proxy_protocol.wrap(stream), accepted: Instant::now() }
+        })))
+    }
+}
+
+pub(crate) struct TlsAddrStream {
+    inner: TlsStream<AcceptedStream>,
+    initial_record: usize,
+    sent: u64,
+    last_write: Instant,
+    established: Instant
+}
+
+impl TlsAddrStream {
+    fn new(inner: TlsStream<AcceptedStream>, records: RecordSizing) -> Self {
+        Self {
+            inner,
+            initial_record: records.initial,
+            sent: 0,
+            last_write: clock::now(),
+            established: Instant::now()
+        }
+    }
+
+    pub fn get_ref(&self) -> (&Connection, &ServerConnection) {
+        let (stream, conn) = self.inner.get_ref();
+        (stream.inner.get_ref(), conn)
+    }
+
+    pub fn proxied(&self) -> ProxiedAddr {
+        self.inner.get_ref().0.inner.proxied()
+    }
+
+    pub fn handshake(&self) -> TlsHandshake {
+        let (stream, conn) = self.inner.get_ref();
+        let alpn = match conn.alpn_protocol() {
+            Some(ALPN_H2) => Some("h2"),
+            Some(ALPN_HTTP1) => Some("http/1.1"),
+            _ => None
+        };
+        TlsHandshake { accepted: stream.accepted, established: self.established, alpn }
+    }
+
+    pub fn session(&self) -> Arc<TlsSession> {
+        Arc::new(TlsSession::new(self.inner.get_ref().1))
+    }
+}
+
+impl AsyncRead for TlsAddrStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsAddrStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8]
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = buf;
+        if self.initial_record > 0 {
+            let now = clock::now();
+            if now.saturating_duration_since(self.last_write) > RECORD_IDLE_RESET {
+                self.sent = 0;
+            }
+            self.last_write = now;
+            if self.sent < RECORD_BOOST_AFTER {
+                buf = &buf[..buf.len().min(self.initial_record)];
+            }
+        }
+        let ret = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(len)) = ret {
+            self.sent += len as u64;
+        }
+        ret
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
 
 pub(crate) fn tls_listen(
     config: Arc<ServerConfig>,
+    records: RecordSizing,
+    idle_timeout: IdleTimeout,
+    request_timeouts: RequestTimeouts,
+    keep_alive: KeepAlive,
+    proxy_protocol: ProxyProtocol,
     tcp: TcpListener
-) -> impl accept::Accept<Conn=TlsAddrStream, Error=TlsError<io::Error, io::Error>> {
-    tcp.set_nonblocking(true).unwrap();
-    let tcp_listener = tokio::net::TcpListener::from_std(tcp).unwrap();
-    let incoming = AddrIncoming::from_listener(tcp_listener).unwrap();
-    let listener = TlsListener::new_hyper(TlsAcceptor::from(config), incoming).filter(|conn| {
+) -> impl accept::Accept<Conn=IdleStream<TlsAddrStream>, Error=TlsError<io::Error, io::Error>> {
+    let incoming = Incoming::new(tcp).unwrap();
+    let listener = TlsListener::new(TlsAcceptor::from(config), AcceptedIncoming(incoming, proxy_protocol)).filter(|conn| {
         if let Err(err) = conn {
             log::warn!("Invalid TLS request received: {:?}", err);
             future::ready(false)
         } else {
             future::ready(true)
         }
-    });
+    }).map(move |conn| conn.map(|stream| idle_timeout.wrap(TlsAddrStream::new(stream, records), request_timeouts, keep_alive)));
     accept::from_stream(listener)
 }
 
+static CERTIFICATES: Lazy<Mutex<Vec<Weak<ReloadableCertificate>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static WATCHER: Once = Once::new();
+
+// The certificate served by the TLS listeners, loaded from its files. It gets
+// reloaded when asked to by the main process or, when watched, once the files
+// change: new handshakes get the new certificate, while established connections
+// keep their sessions. Files failing to load leave the current one in place.
+pub(crate) struct ReloadableCertificate {
+    cert: String,
+    key: String,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<Option<(SystemTime, SystemTime)>>
+}
+
+impl ReloadableCertificate {
+    // Listeners serving the same files share the certificate, like the ones of
+    // the threads of a worker, so that these get reloaded once
+    pub fn shared(cert: &str, key: &str) -> io::Result<Arc<Self>> {
+        if let Some(certificate) = certificates().into_iter().find(|item| item.cert == cert && item.key == key) {
+            return Ok(certificate)
+        }
+        let modified = modified_times(cert, key);
+        let certificate = Arc::new(Self {
+            cert: cert.to_string(),
+            key: key.to_string(),
+            current: RwLock::new(Arc::new(certified_key(cert, key)?)),
+            modified: Mutex::new(modified)
+        });
+        CERTIFICATES.lock().unwrap().push(Arc::downgrade(&certificate));
+        Ok(certificate)
+    }
+
+    fn reload(&self) -> bool {
+        let modified = modified_times(&self.cert, &self.key);
+        match certified_key(&self.cert, &self.key) {
+            Ok(key) => {
+                *self.current.write().unwrap() = Arc::new(key);
+                *self.modified.lock().unwrap() = modified;
+                log::info!("Reloaded TLS certificate {}", self.cert);
+                true
+            },
+            Err(err) => {
+                log::error!("{}, keeping the current one", err);
+                false
+            }
+        }
+    }
+
+    fn changed(&self) -> bool {
+        let modified = modified_times(&self.cert, &self.key);
+        modified.is_some() && modified != *self.modified.lock().unwrap()
+    }
+}
+
+// Certificates for the hostnames clients ask for through SNI, each with its
+// own files. Names are either exact, or `*.` wildcards matching a single label,
+// with exact ones taking precedence; the default certificate goes to clients
+// not sending SNI or asking for other names.
+#[derive(Clone, Default)]
+pub(crate) struct SniCertificates(Vec<(String, String, String)>);
+
+impl SniCertificates {
+    pub fn new(entries: Vec<(String, String, String)>) -> PyResult<Self> {
+        let mut hosts = Vec::with_capacity(entries.len());
+        for (host, cert, key) in entries {
+            let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+            let name = host.strip_prefix("*.").unwrap_or(&host);
+            if name.is_empty() || name.contains('*') || name.split('.').any(|label| label.is_empty()) {
+                return Err(PyValueError::new_err(format!("Invalid SNI hostname: {}", host)))
+            }
+            if hosts.iter().any(|(existing, _, _)| *existing == host) {
+                return Err(PyValueError::new_err(format!("Duplicate SNI hostname: {}", host)))
+            }
+            hosts.push((host, cert, key));
+        }
+        Ok(Self(hosts))
+    }
+
+    pub fn resolver(&self, default: Arc<ReloadableCertificate>) -> io::Result<Arc<SniResolver>> {
+        let mut hosts = HashMap::with_capacity(self.0.len());
+        for (host, cert, key) in &self.0 {
+            hosts.insert(host.clone(), ReloadableCertificate::shared(cert, key)?);
+        }
+        Ok(Arc::new(SniResolver { default, hosts }))
+    }
+}
+
+pub(crate) struct SniResolver {
+    default: Arc<ReloadableCertificate>,
+    hosts: HashMap<String, Arc<ReloadableCertificate>>
+}
+
+impl SniResolver {
+    fn lookup(&self, name: &str) -> Option<&Arc<ReloadableCertificate>> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        if let Some(certificate) = self.hosts.get(&name) {
+            return Some(certificate)
+        }
+        let (_, parent) = name.split_once('.')?;
+        self.hosts.get(&format!("*.{}", parent))
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let certificate = client_hello.server_name()
+            .and_then(|name| self.lookup(name))
+            .unwrap_or(&self.default);
+        Some(certificate.current.read().unwrap().clone())
+    }
+}
+
+fn certificates() -> Vec<Arc<ReloadableCertificate>> {
+    let mut certificates = CERTIFICATES.lock().unwrap();
+    certificates.retain(|certificate| certificate.strong_count() > 0);
+    certificates.iter().filter_map(Weak::upgrade).collect()
+}
+
+fn modified_times(cert: &str, key: &str) -> Option<(SystemTime, SystemTime)> {
+    Some((fs::metadata(cert).ok()?.modified().ok()?, fs::metadata(key).ok()?.modified().ok()?))
+}
+
+fn certified_key(cert: &str, key: &str) -> io::Result<CertifiedKey> {
+    let certs = load_certs(cert)
+        .map_err(|err| tls_error(format!("Unable to load TLS certificate: {}", err)))?;
+    if certs.is_empty() {
+        return Err(tls_error(format!("Unable to load TLS certificate: no certificates found in {}", cert)))
+    }
+    let pkey = load_private_key(key)
+        .map_err(|err| tls_error(format!("Unable to load TLS private key: {}", err)))?;
+    let pkey = any_supported_type(&pkey)
+        .map_err(|_| tls_error("Unable to load TLS private key: unsupported key type".into()))?;
+    Ok(CertifiedKey::new(certs, pkey))
+}
+
+// Files of the certificates checked for changes every `interval`, by a thread
+// shared by all the listeners of the process
+#[derive(Clone, Copy, Default)]
+pub(crate) struct CertificateWatch {
+    interval: Option<Duration>
+}
+
+impl CertificateWatch {
+    pub fn new(interval: f64) -> PyResult<Self> {
+        if interval.is_nan() || interval < 0.0 {
+            return Err(PyValueError::new_err("TLS certificate reload interval should not be negative"))
+        }
+        Ok(Self { interval: (interval > 0.0).then(|| Duration::from_secs_f64(interval)) })
+    }
+
+    pub fn start(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return
+        };
+        WATCHER.call_once(|| {
+            let _ = std::thread::Builder::new()
+                .name("granian-tls".to_string())
+                .spawn(move || loop {
+                    std::thread::sleep(interval);
+                    for certificate in certificates() {
+                        if certificate.changed() {
+                            certificate.reload();
+                        }
+                    }
+                });
+        });
+    }
+}
+
+// Reloads the certificates of every TLS listener of the process, returning how
+// many got reloaded, like when the main process receives SIGHUP
+#[pyfunction]
+fn reload_certificates() -> usize {
+    certificates().iter().filter(|certificate| certificate.reload()).count()
+}
+
+pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(reload_certificates, module)?)?;
+
+    Ok(())
+}
+
 fn tls_error(err: String) -> io::Error {
     io::Error::new(io::ErrorKind::Other, err)
 }
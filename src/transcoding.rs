@@ -0,0 +1,732 @@
+use bytes::{BufMut, BytesMut};
+use hyper::{
+    Body,
+    HeaderMap,
+    Request,
+    Response,
+    StatusCode,
+    body::HttpBody,
+    header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, HeaderValue, VARY}
+};
+
+use crate::{
+    http::{request_content_length, response_error},
+    negotiation::{escape_json, media_quality, parse_weighted}
+};
+
+
+// Bodies get transcoded as a whole, so they need to be buffered: larger
+// requests get rejected, larger responses are left as they are.
+const BODY_SIZE_MAX: usize = 4 * 1024 * 1024;
+// Nesting levels accepted when decoding, as values are decoded recursively
+const DEPTH_MAX: usize = 128;
+const HV_JSON: HeaderValue = HeaderValue::from_static("application/json");
+const HV_VARY: HeaderValue = HeaderValue::from_static("accept");
+const JSON: &str = "application/json";
+// Server preference, when the client weights several formats the same
+const OFFERS: [(&str, Format); 4] = [
+    ("application/msgpack", Format::MsgPack),
+    ("application/vnd.msgpack", Format::MsgPack),
+    ("application/x-msgpack", Format::MsgPack),
+    ("application/cbor", Format::Cbor)
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    MsgPack,
+    Cbor
+}
+
+impl Format {
+    fn of(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        OFFERS.iter().find(|(media, _)| media.eq_ignore_ascii_case(essence)).map(|(_, format)| *format)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Value, String> {
+        let mut decoder = Decoder { data, pos: 0 };
+        let value = match self {
+            Self::MsgPack => decoder.msgpack(0)?,
+            Self::Cbor => decoder.cbor(0)?
+        };
+        match decoder.pos == data.len() {
+            true => Ok(value),
+            false => Err("trailing data after the encoded value".to_string())
+        }
+    }
+
+    fn encode(&self, value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Self::MsgPack => encode_msgpack(value, &mut out),
+            Self::Cbor => encode_cbor(value, &mut out)
+        }
+        out
+    }
+}
+
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>)
+}
+
+// Transcoding of MessagePack and CBOR bodies: requests get decoded to JSON before
+// reaching the application, and JSON responses get encoded in the format the
+// client prefers in its `Accept` header over JSON itself. Binary strings, which
+// have no JSON counterpart, become base64 strings.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Transcoding(bool);
+
+impl Transcoding {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    pub async fn translate(&self, req: Request<Body>) -> Result<(Request<Body>, Transcode), Response<Body>> {
+        if !self.0 {
+            return Ok((req, Transcode(None)))
+        }
+        let transcode = Transcode(Some(negotiate(req.headers())));
+        let format = req.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::of);
+        let format = match format {
+            Some(format) => format,
+            None => return Ok((req, transcode))
+        };
+        if request_content_length(&req).is_some_and(|size| size > BODY_SIZE_MAX as u64) {
+            return Err(too_large())
+        }
+        let (mut parts, mut body) = req.into_parts();
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| bad_request(err.to_string()))?;
+            if data.len() + chunk.len() > BODY_SIZE_MAX {
+                return Err(too_large())
+            }
+            data.extend_from_slice(&chunk);
+        }
+        let value = format.decode(&data).map_err(bad_request)?;
+        let mut json = String::with_capacity(data.len() * 2);
+        write_json(&value, &mut json);
+        parts.headers.insert(CONTENT_TYPE, HV_JSON);
+        parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(json.len()));
+        Ok((Request::from_parts(parts, Body::from(json)), transcode))
+    }
+}
+
+// The JSON responses of transcoded requests vary on `Accept`, the offer is the
+// encoding to use, if the client prefers one over JSON.
+pub(crate) struct Transcode(Option<Option<(Format, &'static str)>>);
+
+impl Transcode {
+    // Streamed bodies, with no size known upfront, are left as they are
+    pub async fn respond(self, res: Response<Body>) -> Response<Body> {
+        let offer = match self.0 {
+            Some(offer) => offer,
+            None => return res
+        };
+        let is_json = res.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(JSON));
+        if !is_json {
+            return res
+        }
+        let (mut parts, body) = res.into_parts();
+        parts.headers.append(VARY, HV_VARY);
+        let sized = body.size_hint().exact().is_some_and(|size| size <= BODY_SIZE_MAX as u64);
+        let (format, media) = match offer {
+            Some(offer) if sized => offer,
+            _ => return Response::from_parts(parts, body)
+        };
+        let data = match hyper::body::to_bytes(body).await {
+            Ok(data) => data,
+            Err(_) => return Response::from_parts(parts, Body::empty())
+        };
+        let value = match parse_json(&data) {
+            Ok(value) => value,
+            Err(_) => return Response::from_parts(parts, Body::from(data))
+        };
+        let encoded = format.encode(&value);
+        parts.headers.insert(CONTENT_TYPE, HeaderValue::from_static(media));
+        parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+        Response::from_parts(parts, Body::from(encoded))
+    }
+}
+
+fn negotiate(headers: &HeaderMap) -> Option<(Format, &'static str)> {
+    let accepted = parse_weighted(headers.get(ACCEPT)?.to_str().ok()?);
+    let mut selected = (None, media_quality(&accepted, JSON));
+    for (media, format) in OFFERS {
+        let quality = media_quality(&accepted, media);
+        if quality > selected.1 {
+            selected = (Some((format, media)), quality);
+        }
+    }
+    selected.0
+}
+
+fn bad_request(detail: String) -> Response<Body> {
+    let detail = format!("Invalid request body: {}", detail);
+    response_error(StatusCode::BAD_REQUEST, detail.clone(), Some(detail))
+}
+
+fn too_large() -> Response<Body> {
+    response_error(StatusCode::PAYLOAD_TOO_LARGE, "", None)
+}
+
+struct Decoder<'d> {
+    data: &'d [u8],
+    pos: usize
+}
+
+impl<'d> Decoder<'d> {
+    fn take(&mut self, size: usize) -> Result<&'d [u8], String> {
+        if self.data.len() - self.pos < size {
+            return Err("truncated value".to_string())
+        }
+        let ret = &self.data[self.pos..self.pos + size];
+        self.pos += size;
+        Ok(ret)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uint(&mut self, size: usize) -> Result<u64, String> {
+        Ok(self.take(size)?.iter().fold(0, |acc, byte| (acc << 8) | u64::from(*byte)))
+    }
+
+    fn length(&mut self, size: usize) -> Result<usize, String> {
+        usize::try_from(self.uint(size)?).map_err(|_| "length out of range".to_string())
+    }
+
+    fn string(&mut self, size: usize) -> Result<String, String> {
+        String::from_utf8(self.take(size)?.to_vec()).map_err(|_| "invalid UTF-8 string".to_string())
+    }
+
+    fn items<F>(&mut self, count: usize, mut item: F) -> Result<Vec<Value>, String>
+    where F: FnMut(&mut Self) -> Result<Value, String>
+    {
+        // lengths come from the client, so they can't size allocations
+        let mut ret = Vec::with_capacity(count.min(self.data.len() - self.pos));
+        for _ in 0..count {
+            ret.push(item(self)?);
+        }
+        Ok(ret)
+    }
+
+    fn entries<F>(&mut self, count: usize, mut item: F) -> Result<Vec<(Value, Value)>, String>
+    where F: FnMut(&mut Self) -> Result<Value, String>
+    {
+        let mut ret = Vec::with_capacity(count.min(self.data.len() - self.pos));
+        for _ in 0..count {
+            ret.push((item(self)?, item(self)?));
+        }
+        Ok(ret)
+    }
+
+    fn msgpack(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > DEPTH_MAX {
+            return Err("too many nesting levels".to_string())
+        }
+        let next = |decoder: &mut Self| decoder.msgpack(depth + 1);
+        let marker = self.byte()?;
+        Ok(match marker {
+            0x00..=0x7f => Value::UInt(u64::from(marker)),
+            0x80..=0x8f => Value::Map(self.entries(usize::from(marker & 0x0f), next)?),
+            0x90..=0x9f => Value::Array(self.items(usize::from(marker & 0x0f), next)?),
+            0xa0..=0xbf => Value::Str(self.string(usize::from(marker & 0x1f))?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let size = self.length(1 << (marker - 0xc4))?;
+                Value::Bytes(self.take(size)?.to_vec())
+            },
+            0xca => Value::Float(f64::from(f32::from_bits(self.uint(4)? as u32))),
+            0xcb => Value::Float(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => Value::UInt(self.uint(1 << (marker - 0xcc))?),
+            0xd0..=0xd3 => {
+                let size = 1 << (marker - 0xd0);
+                let shift = 64 - size * 8;
+                Value::Int(((self.uint(size)? << shift) as i64) >> shift)
+            },
+            0xd9..=0xdb => {
+                let size = self.length(1 << (marker - 0xd9))?;
+                Value::Str(self.string(size)?)
+            },
+            0xdc | 0xdd => {
+                let count = self.length(2 << (marker - 0xdc))?;
+                Value::Array(self.items(count, next)?)
+            },
+            0xde | 0xdf => {
+                let count = self.length(2 << (marker - 0xde))?;
+                Value::Map(self.entries(count, next)?)
+            },
+            0xe0..=0xff => Value::Int(i64::from(marker as i8)),
+            _ => return Err("MessagePack extension types are not supported".to_string())
+        })
+    }
+
+    fn cbor_argument(&mut self, info: u8) -> Result<Option<u64>, String> {
+        match info {
+            0..=23 => Ok(Some(u64::from(info))),
+            24..=27 => Ok(Some(self.uint(1 << (info - 24))?)),
+            31 => Ok(None),
+            _ => Err("invalid CBOR argument".to_string())
+        }
+    }
+
+    fn cbor_chunks(&mut self, major: u8) -> Result<Vec<u8>, String> {
+        let mut ret = Vec::new();
+        loop {
+            let initial = self.byte()?;
+            if initial == 0xff {
+                return Ok(ret)
+            }
+            match (initial >> 5, self.cbor_argument(initial & 0x1f)?) {
+                (chunk_major, Some(size)) if chunk_major == major => {
+                    let size = usize::try_from(size).map_err(|_| "length out of range".to_string())?;
+                    ret.extend_from_slice(self.take(size)?);
+                },
+                _ => return Err("invalid CBOR indefinite length string".to_string())
+            }
+        }
+    }
+
+    fn cbor_break(&mut self) -> bool {
+        if self.data.get(self.pos) == Some(&0xff) {
+            self.pos += 1;
+            return true
+        }
+        false
+    }
+
+    fn cbor(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > DEPTH_MAX {
+            return Err("too many nesting levels".to_string())
+        }
+        let next = |decoder: &mut Self| decoder.cbor(depth + 1);
+        let initial = self.byte()?;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        if major == 7 {
+            return Ok(match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                25 => Value::Float(half_float(self.uint(2)? as u16)),
+                26 => Value::Float(f64::from(f32::from_bits(self.uint(4)? as u32))),
+                27 => Value::Float(f64::from_bits(self.uint(8)?)),
+                _ => return Err("unsupported CBOR simple value".to_string())
+            })
+        }
+        let argument = self.cbor_argument(info)?;
+        let length = |argument: u64| usize::try_from(argument).map_err(|_| "length out of range".to_string());
+        Ok(match (major, argument) {
+            (0, Some(value)) => Value::UInt(value),
+            (1, Some(value)) => match i64::try_from(value) {
+                Ok(value) => Value::Int(-1 - value),
+                Err(_) => Value::Float(-1.0 - value as f64)
+            },
+            (2, Some(size)) => Value::Bytes(self.take(length(size)?)?.to_vec()),
+            (2, None) => Value::Bytes(self.cbor_chunks(2)?),
+            (3, Some(size)) => Value::Str(self.string(length(size)?)?),
+            (3, None) => {
+                let data = self.cbor_chunks(3)?;
+                Value::Str(String::from_utf8(data).map_err(|_| "invalid UTF-8 string".to_string())?)
+            },
+            (4, Some(count)) => Value::Array(self.items(length(count)?, next)?),
+            (4, None) => {
+                let mut items = Vec::new();
+                while !self.cbor_break() {
+                    items.push(next(self)?);
+                }
+                Value::Array(items)
+            },
+            (5, Some(count)) => Value::Map(self.entries(length(count)?, next)?),
+            (5, None) => {
+                let mut entries = Vec::new();
+                while !self.cbor_break() {
+                    entries.push((next(self)?, next(self)?));
+                }
+                Value::Map(entries)
+            },
+            // tags only add semantics to the value they wrap
+            (6, Some(_)) => next(self)?,
+            _ => return Err("invalid CBOR value".to_string())
+        })
+    }
+}
+
+fn half_float(bits: u16) -> f64 {
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f64::from(bits & 0x3ff);
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1024.0 + mantissa) * 2f64.powi(exponent - 25)
+    };
+    if bits & 0x8000 != 0 { -value } else { value }
+}
+
+fn encode_msgpack(value: &Value, out: &mut Vec<u8>) {
+    // Markers of the 8, 16 and 32 bits lengths: collections have no 8 bits one
+    fn head(out: &mut Vec<u8>, size: usize, fixed: Option<(u8, usize)>, markers: (Option<u8>, u8, u8)) {
+        match (fixed, markers.0, size) {
+            (Some((marker, max)), _, _) if size <= max => out.put_u8(marker | size as u8),
+            (_, Some(marker), 0..=0xff) => {
+                out.put_u8(marker);
+                out.put_u8(size as u8);
+            },
+            (_, _, 0..=0xffff) => {
+                out.put_u8(markers.1);
+                out.put_u16(size as u16);
+            },
+            _ => {
+                out.put_u8(markers.2);
+                out.put_u32(size as u32);
+            }
+        }
+    }
+
+    match value {
+        Value::Null => out.put_u8(0xc0),
+        Value::Bool(value) => out.put_u8(if *value { 0xc3 } else { 0xc2 }),
+        Value::UInt(value) => match *value {
+            0..=0x7f => out.put_u8(*value as u8),
+            0x80..=0xff => out.extend_from_slice(&[0xcc, *value as u8]),
+            0x100..=0xffff => {
+                out.put_u8(0xcd);
+                out.put_u16(*value as u16);
+            },
+            0x1_0000..=0xffff_ffff => {
+                out.put_u8(0xce);
+                out.put_u32(*value as u32);
+            },
+            _ => {
+                out.put_u8(0xcf);
+                out.put_u64(*value);
+            }
+        },
+        Value::Int(value) => match *value {
+            0.. => encode_msgpack(&Value::UInt(*value as u64), out),
+            -32..=-1 => out.put_u8(*value as u8),
+            -0x80..=-33 => out.extend_from_slice(&[0xd0, *value as u8]),
+            -0x8000..=-0x81 => {
+                out.put_u8(0xd1);
+                out.put_i16(*value as i16);
+            },
+            -0x8000_0000..=-0x8001 => {
+                out.put_u8(0xd2);
+                out.put_i32(*value as i32);
+            },
+            _ => {
+                out.put_u8(0xd3);
+                out.put_i64(*value);
+            }
+        },
+        Value::Float(value) => {
+            out.put_u8(0xcb);
+            out.put_f64(*value);
+        },
+        Value::Str(value) => {
+            head(out, value.len(), Some((0xa0, 31)), (Some(0xd9), 0xda, 0xdb));
+            out.extend_from_slice(value.as_bytes());
+        },
+        Value::Bytes(value) => {
+            head(out, value.len(), None, (Some(0xc4), 0xc5, 0xc6));
+            out.extend_from_slice(value);
+        },
+        Value::Array(items) => {
+            head(out, items.len(), Some((0x90, 15)), (None, 0xdc, 0xdd));
+            items.iter().for_each(|item| encode_msgpack(item, out));
+        },
+        Value::Map(entries) => {
+            head(out, entries.len(), Some((0x80, 15)), (None, 0xde, 0xdf));
+            for (key, item) in entries {
+                encode_msgpack(key, out);
+                encode_msgpack(item, out);
+            }
+        }
+    }
+}
+
+fn encode_cbor(value: &Value, out: &mut Vec<u8>) {
+    fn head(out: &mut Vec<u8>, major: u8, argument: u64) {
+        let major = major << 5;
+        match argument {
+            0..=23 => out.put_u8(major | argument as u8),
+            24..=0xff => out.extend_from_slice(&[major | 24, argument as u8]),
+            0x100..=0xffff => {
+                out.put_u8(major | 25);
+                out.put_u16(argument as u16);
+            },
+            0x1_0000..=0xffff_ffff => {
+                out.put_u8(major | 26);
+                out.put_u32(argument as u32);
+            },
+            _ => {
+                out.put_u8(major | 27);
+                out.put_u64(argument);
+            }
+        }
+    }
+
+    match value {
+        Value::Null => out.put_u8(0xf6),
+        Value::Bool(value) => out.put_u8(if *value { 0xf5 } else { 0xf4 }),
+        Value::UInt(value) => head(out, 0, *value),
+        Value::Int(value) if *value >= 0 => head(out, 0, *value as u64),
+        Value::Int(value) => head(out, 1, (-1 - *value) as u64),
+        Value::Float(value) => {
+            out.put_u8(0xfb);
+            out.put_f64(*value);
+        },
+        Value::Str(value) => {
+            head(out, 3, value.len() as u64);
+            out.extend_from_slice(value.as_bytes());
+        },
+        Value::Bytes(value) => {
+            head(out, 2, value.len() as u64);
+            out.extend_from_slice(value);
+        },
+        Value::Array(items) => {
+            head(out, 4, items.len() as u64);
+            items.iter().for_each(|item| encode_cbor(item, out));
+        },
+        Value::Map(entries) => {
+            head(out, 5, entries.len() as u64);
+            for (key, item) in entries {
+                encode_cbor(key, out);
+                encode_cbor(item, out);
+            }
+        }
+    }
+}
+
+// JSON has no non finite numbers, nor keys other than strings: the former become
+// nulls, the latter their JSON text.
+fn write_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Int(value) => out.push_str(&value.to_string()),
+        Value::UInt(value) => out.push_str(&value.to_string()),
+        Value::Float(value) if value.is_finite() => out.push_str(&format!("{:?}", value)),
+        Value::Float(_) => out.push_str("null"),
+        Value::Str(value) => {
+            out.push('"');
+            out.push_str(&escape_json(value));
+            out.push('"');
+        },
+        Value::Bytes(value) => {
+            out.push('"');
+            out.push_str(&base64::encode(value));
+            out.push('"');
+        },
+        Value::Array(items) => {
+            out.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_json(item, out);
+            }
+            out.push(']');
+        },
+        Value::Map(entries) => {
+            out.push('{');
+            for (idx, (key, item)) in entries.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                match key {
+                    Value::Str(_) | Value::Bytes(_) => write_json(key, out),
+                    key => {
+                        let mut text = String::new();
+                        write_json(key, &mut text);
+                        write_json(&Value::Str(text), out);
+                    }
+                }
+                out.push(':');
+                write_json(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn parse_json(data: &[u8]) -> Result<Value, String> {
+    let mut parser = JsonParser { data, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    match parser.pos == data.len() {
+        true => Ok(value),
+        false => Err("trailing data after the JSON value".to_string())
+    }
+}
+
+struct JsonParser<'d> {
+    data: &'d [u8],
+    pos: usize
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.data.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8], value: Value) -> Result<Value, String> {
+        match self.data[self.pos..].starts_with(literal) {
+            true => {
+                self.pos += literal.len();
+                Ok(value)
+            },
+            false => Err("invalid JSON literal".to_string())
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > DEPTH_MAX {
+            return Err("too many nesting levels".to_string())
+        }
+        self.skip_whitespace();
+        match self.data.get(self.pos) {
+            Some(b'n') => self.expect(b"null", Value::Null),
+            Some(b't') => self.expect(b"true", Value::Bool(true)),
+            Some(b'f') => self.expect(b"false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::Str(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.data.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items))
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.data.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items))
+                        },
+                        _ => return Err("invalid JSON array".to_string())
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                self.skip_whitespace();
+                if self.data.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Map(entries))
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.data.get(self.pos) != Some(&b'"') {
+                        return Err("invalid JSON object key".to_string())
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.data.get(self.pos) != Some(&b':') {
+                        return Err("invalid JSON object".to_string())
+                    }
+                    self.pos += 1;
+                    entries.push((Value::Str(key), self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.data.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Map(entries))
+                        },
+                        _ => return Err("invalid JSON object".to_string())
+                    }
+                }
+            },
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err("invalid JSON value".to_string())
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.data.get(self.pos), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.data[start..self.pos]).unwrap_or_default();
+        let integer = !text.contains(['.', 'e', 'E']);
+        if integer {
+            if let Ok(value) = text.parse::<u64>() {
+                return Ok(Value::UInt(value))
+            }
+            if let Ok(value) = text.parse::<i64>() {
+                return Ok(Value::Int(value))
+            }
+        }
+        text.parse::<f64>().map(Value::Float).map_err(|_| "invalid JSON number".to_string())
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.data.get(self.pos..self.pos + 4).ok_or("truncated JSON escape")?;
+        self.pos += 4;
+        let digits = std::str::from_utf8(digits).map_err(|_| "invalid JSON escape".to_string())?;
+        u32::from_str_radix(digits, 16).map_err(|_| "invalid JSON escape".to_string())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut ret = Vec::new();
+        loop {
+            let byte = *self.data.get(self.pos).ok_or("unterminated JSON string")?;
+            self.pos += 1;
+            match byte {
+                b'"' => return String::from_utf8(ret).map_err(|_| "invalid UTF-8 string".to_string()),
+                b'\\' => {
+                    let escape = *self.data.get(self.pos).ok_or("truncated JSON escape")?;
+                    self.pos += 1;
+                    let char = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex()?;
+                            if (0xd800..0xdc00).contains(&code) && self.data[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        },
+                        _ => return Err("invalid JSON escape".to_string())
+                    };
+                    let mut buf = [0; 4];
+                    ret.extend_from_slice(char.encode_utf8(&mut buf).as_bytes());
+                },
+                0x00..=0x1f => return Err("invalid control character in JSON string".to_string()),
+                byte => ret.push(byte)
+            }
+        }
+    }
+}
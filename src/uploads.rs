@@ -0,0 +1,386 @@
+use hyper::{
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+    body::HttpBody,
+    header::{
+        ALLOW,
+        CACHE_CONTROL,
+        CONTENT_LENGTH,
+        CONTENT_TYPE,
+        HeaderName,
+        HeaderValue,
+        LOCATION,
+        SERVER as HK_SERVER
+    }
+};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    io,
+    path::PathBuf,
+    sync::Arc
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::http::{HV_SERVER, request_content_length, response_error};
+
+
+const TUS_VERSION: HeaderValue = HeaderValue::from_static("1.0.0");
+const TUS_EXTENSIONS: HeaderValue = HeaderValue::from_static("creation,termination");
+const HK_TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const HK_TUS_VERSION: HeaderName = HeaderName::from_static("tus-version");
+const HK_TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const HK_TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const HK_UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const HK_UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const HK_UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const HK_UPLOAD_ID: HeaderName = HeaderName::from_static("tus-upload-id");
+const HK_UPLOAD_FILE: HeaderName = HeaderName::from_static("tus-upload-file");
+const HV_NO_STORE: HeaderValue = HeaderValue::from_static("no-store");
+const HV_ALLOW_COLLECTION: HeaderValue = HeaderValue::from_static("POST, OPTIONS");
+const HV_ALLOW_UPLOAD: HeaderValue = HeaderValue::from_static("HEAD, PATCH, DELETE, OPTIONS");
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+struct UploadsConfig {
+    prefix: String,
+    dir: PathBuf,
+    max_size: u64
+}
+
+enum Target<'p> {
+    Collection,
+    Upload(&'p str)
+}
+
+// Resumable uploads, following the tus protocol (core, creation and termination
+// extensions) under the `prefix` path. Uploads are stored in `dir`, along with
+// their declared length and metadata, so that workers share them and they survive
+// restarts; the offset is the size of the data stored so far. Applications only
+// see the PATCH request completing an upload, with an empty body and the stored
+// file path in the `tus-upload-file` header, to move the file wherever they like.
+#[derive(Clone, Default)]
+pub(crate) struct ResumableUploads {
+    inner: Option<Arc<UploadsConfig>>
+}
+
+impl ResumableUploads {
+    pub fn new(path: Option<String>, dir: Option<String>, max_size: u64) -> PyResult<Self> {
+        let (path, dir) = match (path, dir) {
+            (None, None) => return Ok(Self { inner: None }),
+            (Some(path), Some(dir)) => (path, dir),
+            _ => return Err(PyValueError::new_err("Resumable uploads need both a path and a directory"))
+        };
+        if !path.starts_with('/') {
+            return Err(PyValueError::new_err(format!("Invalid uploads path: {}", path)))
+        }
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir).map_err(
+            |err| PyValueError::new_err(format!("Unable to use uploads directory {}: {}", dir.display(), err))
+        )?;
+        Ok(Self { inner: Some(Arc::new(UploadsConfig {
+            prefix: path.trim_end_matches('/').to_string(),
+            dir,
+            max_size
+        })) })
+    }
+
+    // Requests to the uploads paths are answered here, but the ones completing
+    // an upload, which get passed on to the application.
+    pub async fn handle(&self, mut req: Request<Body>) -> Result<(Request<Body>, UploadCompletion), Response<Body>> {
+        let config = match &self.inner {
+            Some(config) => config,
+            None => return Ok((req, UploadCompletion(None)))
+        };
+        // the headers describing completed uploads can only come from here
+        req.headers_mut().remove(HK_UPLOAD_ID);
+        req.headers_mut().remove(HK_UPLOAD_FILE);
+        let target = match req.uri().path().strip_prefix(&config.prefix[..]) {
+            Some("") | Some("/") => Target::Collection,
+            Some(id) => match id.strip_prefix('/').filter(|id| is_upload_id(id)) {
+                Some(id) => Target::Upload(id),
+                None => return Ok((passthrough(req), UploadCompletion(None)))
+            },
+            None => return Ok((passthrough(req), UploadCompletion(None)))
+        };
+        if req.method() == Method::OPTIONS {
+            return Err(config.options())
+        }
+        if req.headers().get(&HK_TUS_RESUMABLE) != Some(&TUS_VERSION) {
+            let mut res = tus_response(StatusCode::PRECONDITION_FAILED);
+            res.headers_mut().insert(HK_TUS_VERSION, TUS_VERSION);
+            return Err(res)
+        }
+        let ret = match (target, req.method()) {
+            (Target::Collection, &Method::POST) => config.create(&req).await,
+            (Target::Upload(id), &Method::HEAD) => config.status(id).await,
+            (Target::Upload(id), &Method::PATCH) => {
+                let id = id.to_string();
+                return config.append(&id, req).await.map_err(
+                    |res| res.unwrap_or_else(|| tus_response(StatusCode::INTERNAL_SERVER_ERROR))
+                )
+            },
+            (Target::Upload(id), &Method::DELETE) => config.terminate(id).await,
+            (target, _) => {
+                let mut res = tus_response(StatusCode::METHOD_NOT_ALLOWED);
+                res.headers_mut().insert(ALLOW, match target {
+                    Target::Collection => HV_ALLOW_COLLECTION,
+                    Target::Upload(_) => HV_ALLOW_UPLOAD
+                });
+                return Err(res)
+            }
+        };
+        Err(ret.unwrap_or_else(internal_error))
+    }
+}
+
+impl UploadsConfig {
+    fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.info", id))
+    }
+
+    fn options(&self) -> Response<Body> {
+        let mut res = tus_response(StatusCode::NO_CONTENT);
+        res.headers_mut().insert(HK_TUS_VERSION, TUS_VERSION);
+        res.headers_mut().insert(HK_TUS_EXTENSION, TUS_EXTENSIONS);
+        if self.max_size > 0 {
+            res.headers_mut().insert(HK_TUS_MAX_SIZE, HeaderValue::from(self.max_size));
+        }
+        res
+    }
+
+    async fn create(&self, req: &Request<Body>) -> io::Result<Response<Body>> {
+        let length = match header_u64(req, &HK_UPLOAD_LENGTH) {
+            Some(length) => length,
+            None => return Ok(tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Length"))
+        };
+        if self.max_size > 0 && length > self.max_size {
+            return Ok(tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Upload-Length exceeds the maximum size"))
+        }
+        let metadata = match req.headers().get(&HK_UPLOAD_METADATA).map(|value| value.to_str()) {
+            Some(Ok(metadata)) => metadata,
+            Some(Err(_)) => return Ok(tus_error(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata")),
+            None => ""
+        };
+        let id = format!("{:032x}", rand::random::<u128>());
+        fs::OpenOptions::new().write(true).create_new(true).open(self.data_path(&id)).await?;
+        fs::write(self.info_path(&id), format!("{}\n{}\n", length, metadata)).await?;
+
+        let mut res = tus_response(StatusCode::CREATED);
+        res.headers_mut().insert(
+            LOCATION,
+            HeaderValue::from_str(&format!("{}/{}", self.prefix, id)).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        );
+        Ok(res)
+    }
+
+    async fn status(&self, id: &str) -> io::Result<Response<Body>> {
+        let info = match UploadInfo::load(self, id).await? {
+            Some(info) => info,
+            None => return Ok(tus_response(StatusCode::NOT_FOUND))
+        };
+        let mut res = tus_response(StatusCode::OK);
+        res.headers_mut().insert(HK_UPLOAD_OFFSET, HeaderValue::from(info.offset));
+        res.headers_mut().insert(HK_UPLOAD_LENGTH, HeaderValue::from(info.length));
+        if let Ok(metadata) = HeaderValue::from_str(&info.metadata) {
+            if !metadata.is_empty() {
+                res.headers_mut().insert(HK_UPLOAD_METADATA, metadata);
+            }
+        }
+        res.headers_mut().insert(CACHE_CONTROL, HV_NO_STORE);
+        Ok(res)
+    }
+
+    async fn terminate(&self, id: &str) -> io::Result<Response<Body>> {
+        let _lock = match UploadLock::acquire(self, id).await? {
+            Some(lock) => lock,
+            None => return Ok(tus_response(StatusCode::LOCKED))
+        };
+        if UploadInfo::load(self, id).await?.is_none() {
+            return Ok(tus_response(StatusCode::NOT_FOUND))
+        }
+        fs::remove_file(self.info_path(id)).await?;
+        fs::remove_file(self.data_path(id)).await?;
+        Ok(tus_response(StatusCode::NO_CONTENT))
+    }
+
+    // Errors carry the response to send, or none for failed I/O. Data received
+    // before the client went away gets kept, so that the upload can resume from it.
+    async fn append(&self, id: &str, req: Request<Body>) -> Result<(Request<Body>, UploadCompletion), Option<Response<Body>>> {
+        let is_offset_stream = req.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case(OFFSET_CONTENT_TYPE));
+        if !is_offset_stream {
+            return Err(Some(tus_response(StatusCode::UNSUPPORTED_MEDIA_TYPE)))
+        }
+        let offset = header_u64(&req, &HK_UPLOAD_OFFSET)
+            .ok_or_else(|| Some(tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Offset")))?;
+        let _lock = UploadLock::acquire(self, id).await.map_err(io_error)?
+            .ok_or_else(|| Some(tus_response(StatusCode::LOCKED)))?;
+        let info = UploadInfo::load(self, id).await.map_err(io_error)?
+            .ok_or_else(|| Some(tus_response(StatusCode::NOT_FOUND)))?;
+        if offset != info.offset {
+            return Err(Some(tus_error(StatusCode::CONFLICT, "Upload-Offset does not match the current offset")))
+        }
+        let exceeds = |size: u64| offset.checked_add(size).is_none_or(|end| end > info.length);
+        if request_content_length(&req).is_some_and(exceeds) {
+            return Err(Some(tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Data exceeds the Upload-Length")))
+        }
+
+        let (mut parts, mut body) = req.into_parts();
+        let mut file = fs::OpenOptions::new().append(true).open(self.data_path(id)).await.map_err(io_error)?;
+        let mut written = offset;
+        let mut failure = None;
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    failure = Some(tus_error(StatusCode::BAD_REQUEST, &format!("Upload interrupted: {}", err)));
+                    break
+                }
+            };
+            if written.checked_add(chunk.len() as u64).is_none_or(|end| end > info.length) {
+                failure = Some(tus_error(StatusCode::PAYLOAD_TOO_LARGE, "Data exceeds the Upload-Length"));
+                break
+            }
+            file.write_all(&chunk).await.map_err(io_error)?;
+            written += chunk.len() as u64;
+        }
+        file.flush().await.map_err(io_error)?;
+        if let Some(res) = failure {
+            return Err(Some(res))
+        }
+        if written < info.length {
+            let mut res = tus_response(StatusCode::NO_CONTENT);
+            res.headers_mut().insert(HK_UPLOAD_OFFSET, HeaderValue::from(written));
+            return Err(Some(res))
+        }
+
+        let path = self.data_path(id);
+        let path = fs::canonicalize(&path).await.unwrap_or(path);
+        let file_path = HeaderValue::from_str(&path.to_string_lossy()).map_err(|_| None)?;
+        parts.headers.remove(CONTENT_TYPE);
+        parts.headers.remove(HK_UPLOAD_METADATA);
+        parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(0));
+        parts.headers.insert(HK_UPLOAD_ID, HeaderValue::from_str(id).map_err(|_| None)?);
+        parts.headers.insert(HK_UPLOAD_FILE, file_path);
+        parts.headers.insert(HK_UPLOAD_LENGTH, HeaderValue::from(info.length));
+        if let Ok(metadata) = HeaderValue::from_str(&info.metadata) {
+            if !metadata.is_empty() {
+                parts.headers.insert(HK_UPLOAD_METADATA, metadata);
+            }
+        }
+        Ok((Request::from_parts(parts, Body::empty()), UploadCompletion(Some(written))))
+    }
+}
+
+struct UploadInfo {
+    length: u64,
+    metadata: String,
+    offset: u64
+}
+
+impl UploadInfo {
+    async fn load(config: &UploadsConfig, id: &str) -> io::Result<Option<Self>> {
+        let info = match fs::read_to_string(config.info_path(id)).await {
+            Ok(info) => info,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err)
+        };
+        let mut lines = info.lines();
+        let length = lines.next().and_then(|line| line.parse().ok()).ok_or_else(
+            || io::Error::new(io::ErrorKind::InvalidData, format!("invalid upload info for {}", id))
+        )?;
+        let metadata = lines.next().unwrap_or("").to_string();
+        let offset = fs::metadata(config.data_path(id)).await?.len();
+        Ok(Some(Self { length, metadata, offset }))
+    }
+}
+
+// Lock files keep workers, which are separate processes, from writing the same
+// upload concurrently. Locks left behind by crashed workers need to be removed
+// by hand, or the upload gets terminated. Locks get released synchronously on
+// drop, so that the next request for the upload never finds them left over.
+struct UploadLock(PathBuf);
+
+impl UploadLock {
+    async fn acquire(config: &UploadsConfig, id: &str) -> io::Result<Option<Self>> {
+        let path = config.dir.join(format!("{}.lock", id));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+            Ok(_) => Ok(Some(Self(path))),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// The offset reached by the upload completed by the request, if any
+pub(crate) struct UploadCompletion(Option<u64>);
+
+impl UploadCompletion {
+    pub fn respond(self, mut res: Response<Body>) -> Response<Body> {
+        if let Some(offset) = self.0 {
+            res.headers_mut().insert(HK_TUS_RESUMABLE, TUS_VERSION);
+            res.headers_mut().insert(HK_UPLOAD_OFFSET, HeaderValue::from(offset));
+        }
+        res
+    }
+}
+
+// Requests the uploads don't handle reach the application without any of the
+// headers it would take for a completed upload.
+fn passthrough(mut req: Request<Body>) -> Request<Body> {
+    req.headers_mut().remove(HK_UPLOAD_LENGTH);
+    req.headers_mut().remove(HK_UPLOAD_METADATA);
+    req
+}
+
+fn is_upload_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+}
+
+fn header_u64<B>(req: &Request<B>, name: &HeaderName) -> Option<u64> {
+    req.headers().get(name)?.to_str().ok()?.parse().ok()
+}
+
+// Error responses are rendered in the negotiated format like the other server ones
+fn tus_response(status: StatusCode) -> Response<Body> {
+    let mut res = match status.as_u16() >= 400 {
+        true => response_error(status, "", None),
+        false => {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = status;
+            res.headers_mut().insert(HK_SERVER, HV_SERVER);
+            res
+        }
+    };
+    res.headers_mut().insert(HK_TUS_RESUMABLE, TUS_VERSION);
+    res
+}
+
+fn tus_error(status: StatusCode, detail: &str) -> Response<Body> {
+    let mut res = response_error(status, detail.to_string(), Some(detail.to_string()));
+    res.headers_mut().insert(HK_TUS_RESUMABLE, TUS_VERSION);
+    res
+}
+
+fn io_error(err: io::Error) -> Option<Response<Body>> {
+    log::error!("Upload storage failed: {}", err);
+    None
+}
+
+fn internal_error(err: io::Error) -> Response<Body> {
+    log::error!("Upload storage failed: {}", err);
+    tus_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
@@ -0,0 +1,150 @@
+use hyper::{Body, Request, Response, StatusCode};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::borrow::Cow;
+
+use crate::http::response_error;
+
+
+enum Invalid {
+    Escape,
+    Slash,
+    Utf8
+}
+
+impl Invalid {
+    fn detail(&self, part: &str) -> String {
+        match self {
+            Self::Escape => format!("Malformed percent-encoding in request {}", part),
+            Self::Slash => format!("Encoded slash in request {}", part),
+            Self::Utf8 => format!("Invalid UTF-8 in request {}", part)
+        }
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None
+    }
+}
+
+// Percent-decodes `input`: malformed escapes are kept as they are, unless `strict`,
+// as are encoded slashes when `keep_slash`, since decoding them would merge segments.
+fn percent_decode(input: &[u8], keep_slash: bool, plus_space: bool, strict: bool) -> Result<Cow<'_, [u8]>, Invalid> {
+    if !(input.contains(&b'%') || plus_space && input.contains(&b'+')) {
+        return Ok(Cow::Borrowed(input))
+    }
+    let mut ret = Vec::with_capacity(input.len());
+    let mut idx = 0;
+    while idx < input.len() {
+        let byte = match input[idx] {
+            b'+' if plus_space => b' ',
+            b'%' => match (input.get(idx + 1).copied().and_then(hex_value), input.get(idx + 2).copied().and_then(hex_value)) {
+                (Some(high), Some(low)) if keep_slash && (high << 4 | low) == b'/' => {
+                    if strict {
+                        return Err(Invalid::Slash)
+                    }
+                    ret.extend_from_slice(&input[idx..idx + 3]);
+                    idx += 3;
+                    continue
+                },
+                (Some(high), Some(low)) => {
+                    ret.push(high << 4 | low);
+                    idx += 3;
+                    continue
+                },
+                _ if strict => return Err(Invalid::Escape),
+                _ => b'%'
+            },
+            byte => byte
+        };
+        ret.push(byte);
+        idx += 1;
+    }
+    Ok(Cow::Owned(ret))
+}
+
+fn utf8_lossy(data: Cow<'_, [u8]>) -> Cow<'_, str> {
+    match data {
+        Cow::Borrowed(data) => String::from_utf8_lossy(data),
+        Cow::Owned(data) => match String::from_utf8(data) {
+            Ok(data) => Cow::Owned(data),
+            Err(err) => Cow::Owned(String::from_utf8_lossy(err.as_bytes()).into_owned())
+        }
+    }
+}
+
+// How the request path gets exposed to applications: as received (`raw`), or
+// percent-decoded. Lenient decoding keeps malformed escapes and `%2F` untouched,
+// while strict decoding rejects requests carrying them or invalid UTF-8 with a 400,
+// so the application and any proxy in front of it can't disagree on segments.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PathDecoding {
+    Raw,
+    Lenient,
+    Strict
+}
+
+impl PathDecoding {
+    pub fn new(value: &str) -> PyResult<Self> {
+        match value {
+            "raw" => Ok(Self::Raw),
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            _ => Err(PyValueError::new_err(format!("Invalid path decoding: {}", value)))
+        }
+    }
+
+    pub fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        if *self != Self::Strict {
+            return None
+        }
+        let invalid = validate(req.uri().path().as_bytes(), true, false)
+            .map_err(|err| err.detail("path"))
+            .and_then(|_| validate(req.uri().query().unwrap_or("").as_bytes(), false, true)
+                .map_err(|err| err.detail("query")));
+        match invalid {
+            Ok(_) => None,
+            Err(detail) => Some(response_error(StatusCode::BAD_REQUEST, detail.clone(), Some(detail)))
+        }
+    }
+
+    pub fn path_bytes<'p>(&self, path: &'p str) -> Cow<'p, [u8]> {
+        match self {
+            Self::Raw => Cow::Borrowed(path.as_bytes()),
+            // strict paths were validated with the request already
+            _ => percent_decode(path.as_bytes(), true, false, false).unwrap_or(Cow::Borrowed(path.as_bytes()))
+        }
+    }
+
+    pub fn path<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        match self {
+            Self::Raw => Cow::Borrowed(path),
+            _ => utf8_lossy(self.path_bytes(path))
+        }
+    }
+}
+
+fn validate(input: &[u8], keep_slash: bool, plus_space: bool) -> Result<(), Invalid> {
+    let decoded = percent_decode(input, keep_slash, plus_space, true)?;
+    std::str::from_utf8(&decoded).map_err(|_| Invalid::Utf8)?;
+    Ok(())
+}
+
+// The bytes of a single URL component, like a path segment
+pub(crate) fn decode_component(data: &str) -> Cow<'_, [u8]> {
+    percent_decode(data.as_bytes(), false, false, false).unwrap_or(Cow::Borrowed(data.as_bytes()))
+}
+
+// The decoded query string pairs, in order and keeping repeated keys
+pub(crate) fn query_params(query: &str) -> Vec<(String, String)> {
+    query.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |data: &str| utf8_lossy(
+            percent_decode(data.as_bytes(), false, true, false).unwrap_or(Cow::Borrowed(data.as_bytes()))
+        ).into_owned();
+        (decode(key), decode(value))
+    }).collect()
+}
@@ -1,3 +1,13 @@
+// Header values can carry bytes beyond visible ASCII, like the raw UTF-8 paths
+// some WebDAV clients send in `Destination`: these are decoded as ISO-8859-1, as
+// HTTP historically defines them and PEP 3333 requires, instead of failing.
+pub(crate) fn header_value_latin1(value: &hyper::header::HeaderValue) -> std::borrow::Cow<'_, str> {
+    match value.to_str() {
+        Ok(value) => value.into(),
+        Err(_) => value.as_bytes().iter().map(|&byte| byte as char).collect::<String>().into()
+    }
+}
+
 pub(crate) fn header_contains_value(
     headers: &hyper::HeaderMap,
     header: impl hyper::header::AsHeaderName,
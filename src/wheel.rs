@@ -0,0 +1,199 @@
+use futures::task::AtomicWaker;
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex, Weak, atomic::{AtomicU64, Ordering}},
+    task::{Context, Poll},
+    time::{Duration, Instant}
+};
+use tokio::sync::Notify;
+
+use crate::clock;
+
+
+// Resolution of the timers, deadlines get rounded up to the next tick
+const TICK: Duration = Duration::from_millis(10);
+const SLOTS: usize = 512;
+
+thread_local! {
+    static WHEEL: RefCell<Option<Arc<Wheel>>> = const { RefCell::new(None) };
+}
+
+// The timers of the connection timeouts, like the idle, keep-alive and request
+// ones. Rather than a tokio sleep each, connections share a hashed timer wheel
+// per runtime thread: deadlines get hashed to a slot by their tick, and a task
+// per wheel wakes the expired timers as the ticks go by. Deadlines pushed
+// further keep their entry, which gets replaced once fired early, so busy
+// connections postponing their timeouts on every read don't touch the wheel.
+#[derive(Default)]
+pub(crate) struct Timer(Option<(Arc<Wheel>, Arc<Entry>)>);
+
+impl Timer {
+    pub fn poll_at(&mut self, deadline: Instant, cx: &mut Context<'_>) -> Poll<()> {
+        if clock::now() >= deadline {
+            self.0 = None;
+            return Poll::Ready(())
+        }
+        if let Some((wheel, entry)) = &self.0 {
+            entry.waker.register(cx.waker());
+            // an entry the wheel went past might have woken someone else
+            if entry.tick <= wheel.tick_of(deadline) && entry.tick > wheel.current.load(Ordering::Acquire) {
+                return Poll::Pending
+            }
+        }
+        let wheel = WHEEL.with(|wheel| wheel.borrow_mut().get_or_insert_with(Wheel::new).clone());
+        let entry = wheel.insert(deadline, cx);
+        self.0 = Some((wheel, entry));
+        Poll::Pending
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = None;
+    }
+}
+
+pub(crate) struct Entry {
+    tick: u64,
+    waker: AtomicWaker
+}
+
+struct Slots {
+    slots: Vec<Vec<Weak<Entry>>>,
+    len: usize,
+    // the tick the driver sleeps until, if any
+    wake_at: Option<u64>,
+    driven: bool
+}
+
+pub(crate) struct Wheel {
+    epoch: Instant,
+    current: AtomicU64,
+    slots: Mutex<Slots>,
+    notify: Notify
+}
+
+impl Wheel {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            epoch: clock::now(),
+            current: AtomicU64::new(0),
+            slots: Mutex::new(Slots {
+                slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                len: 0,
+                wake_at: None,
+                driven: false
+            }),
+            notify: Notify::new()
+        })
+    }
+
+    fn tick_of(&self, deadline: Instant) -> u64 {
+        let nanos = deadline.saturating_duration_since(self.epoch).as_nanos();
+        nanos.div_ceil(TICK.as_nanos()) as u64
+    }
+
+    fn insert(self: &Arc<Self>, deadline: Instant, cx: &mut Context<'_>) -> Arc<Entry> {
+        let mut slots = self.slots.lock().unwrap();
+        let tick = self.tick_of(deadline).max(self.current.load(Ordering::Acquire) + 1);
+        let entry = Arc::new(Entry { tick, waker: AtomicWaker::new() });
+        entry.waker.register(cx.waker());
+        slots.slots[tick as usize % SLOTS].push(Arc::downgrade(&entry));
+        slots.len += 1;
+        if !slots.driven {
+            slots.driven = true;
+            tokio::spawn(drive(self.clone()));
+        } else if slots.wake_at.is_none_or(|wake_at| tick < wake_at) {
+            self.notify.notify_one();
+        }
+        entry
+    }
+
+    // The first tick with entries in its slot, maybe for a later round
+    fn next_tick(&self) -> Option<u64> {
+        let mut slots = self.slots.lock().unwrap();
+        let current = self.current.load(Ordering::Acquire);
+        slots.wake_at = match slots.len {
+            0 => None,
+            _ => (1..=SLOTS as u64)
+                .map(|offset| current + offset)
+                .find(|tick| !slots.slots[*tick as usize % SLOTS].is_empty())
+        };
+        slots.wake_at
+    }
+
+    fn advance(&self) {
+        let mut expired = Vec::new();
+        {
+            let mut slots = self.slots.lock().unwrap();
+            let Slots { slots: wheel, len, .. } = &mut *slots;
+            let current = self.current.load(Ordering::Acquire);
+            let now = self.tick_of(clock::now()).saturating_sub(1);
+            // a mocked clock getting reset goes back in time
+            if now < current {
+                self.current.store(now, Ordering::Release);
+                return
+            }
+            for tick in current + 1..=now.min(current + SLOTS as u64) {
+                wheel[tick as usize % SLOTS].retain(|entry| match entry.upgrade() {
+                    Some(entry) if entry.tick > now => true,
+                    entry => {
+                        expired.extend(entry);
+                        *len -= 1;
+                        false
+                    }
+                });
+            }
+            self.current.store(now, Ordering::Release);
+        }
+        for entry in expired {
+            entry.waker.wake();
+        }
+    }
+
+    // Timers get polled again on clock changes, checking their deadlines against
+    // the new time rather than the ticks of the entries they had
+    fn wake_all(&self) {
+        let pending: Vec<_> = self.slots.lock().unwrap().slots.iter()
+            .flatten()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for entry in pending {
+            entry.waker.wake();
+        }
+    }
+}
+
+// Runtimes getting dropped stop their drivers, the next insert starts another
+struct Driven(Arc<Wheel>);
+
+impl Drop for Driven {
+    fn drop(&mut self) {
+        self.0.slots.lock().unwrap().driven = false;
+    }
+}
+
+async fn drive(wheel: Arc<Wheel>) {
+    let driven = Driven(wheel);
+    let wheel = &driven.0;
+    // a mocked clock moves on its own, without the runtime timers noticing
+    let mut clock_changes = clock::changes();
+    loop {
+        let changed = match wheel.next_tick() {
+            Some(tick) => {
+                let wait = (wheel.epoch + TICK * tick as u32).saturating_duration_since(clock::now());
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => false,
+                    _ = wheel.notify.notified() => false,
+                    _ = clock_changes.changed() => true
+                }
+            },
+            None => tokio::select! {
+                _ = wheel.notify.notified() => false,
+                _ = clock_changes.changed() => true
+            }
+        };
+        wheel.advance();
+        if changed {
+            wheel.wake_all();
+        }
+    }
+}
@@ -1,15 +1,194 @@
-use pyo3::prelude::*;
+use once_cell::sync::OnceCell;
+use pyo3::{exceptions::PyTypeError, prelude::*, types::PyDict};
 use std::net::TcpListener;
 
+#[cfg(unix)]
+use socket2::{Domain, Protocol, Socket, Type};
 #[cfg(unix)]
 use std::os::unix::io::FromRawFd;
 #[cfg(windows)]
 use std::os::windows::io::FromRawSocket;
 
+use super::access::AccessLog;
 use super::asgi::serve::ASGIWorker;
+use super::cancellation::Cancellation;
+use super::compression::Compression;
+use super::decompression::Decompression;
+use super::deadlines::Deadlines;
+use super::diagnostics::ConnectionTraces;
+use super::drain::Drain;
+use super::exposition::MetricsExposition;
+use super::errors::Error;
+use super::files::FileResponses;
+use super::filters::{RequestFilters, ResponseFilters};
+use super::forwarded::ForwardedHeaders;
+use super::grpc::GrpcWeb;
+use super::http::{
+    AllowedHosts, DisconnectPolicy, DuplicateHeaders, HeaderValidation, Http2Settings, ProtocolConformance, ResponseHeaders
+};
+use super::hooks::StatusHooks;
+use super::idempotency::IdempotencyCache;
+use super::idle::IdleTimeout;
+use super::keepalive::KeepAlive;
+use super::limits::{BodyLimit, HeaderLimits};
+use super::longpoll::LongPolls;
+use super::metrics::RouteTemplates;
+use super::negotiation::ErrorFormat;
+use super::otel::Tracer;
+use super::proxy::ProxyProtocol;
 use super::rsgi::serve::RSGIWorker;
+use super::presign::PresignedUrls;
+use super::slo::SloPolicy;
+use super::stacks::StackDumps;
+use super::synthetic::{OptionsResponses, SyntheticResponses, SyntheticRoute};
+use super::timeouts::RequestTimeouts;
+use super::transcoding::Transcoding;
+use super::uploads::ResumableUploads;
 use super::wsgi::serve::WSGIWorker;
-use super::tls::{load_certs as tls_load_certs, load_private_key as tls_load_pkey};
+use super::ws::{Utf8Policy, WebsocketOrigins};
+use super::urls::PathDecoding;
+use super::tls::{CertificateWatch, ClientAuth, RecordSizing, ReloadableCertificate, SniCertificates};
+
+// Identity of the worker running in the current process: the id of its slot,
+// stable across respawns, and its generation, counting the processes spawned
+// for the slot so far, some of which might have been recycled for their memory
+// usage. In-process servers, like the testing ones, get zeroes.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct WorkerIdentity {
+    pub id: i32,
+    pub generation: u32,
+    pub memory_recycles: u64
+}
+
+static IDENTITY: OnceCell<WorkerIdentity> = OnceCell::new();
+
+pub(crate) fn identity() -> WorkerIdentity {
+    IDENTITY.get().copied().unwrap_or_default()
+}
+
+// Settings of a worker, as given by the Python side: every option defaults to the
+// value of the `Granian` server, so embedders and tests only set the ones they need.
+macro_rules! worker_options {
+    ($($name:ident: $ty:ty = $default:expr),* $(,)?) => {
+        #[pyclass(module="granian._granian")]
+        #[derive(Clone)]
+        pub(crate) struct WorkerOptions {
+            $(pub $name: $ty),*
+        }
+
+        impl Default for WorkerOptions {
+            fn default() -> Self {
+                Self { $($name: $default),* }
+            }
+        }
+
+        #[pymethods]
+        impl WorkerOptions {
+            #[new]
+            #[args(options="**")]
+            fn new(options: Option<&PyDict>) -> PyResult<Self> {
+                let mut rv = Self::default();
+                for (key, value) in options.into_iter().flatten() {
+                    match key.extract::<&str>()? {
+                        $(stringify!($name) => rv.$name = value.extract().map_err(
+                            |err| PyTypeError::new_err(format!("Invalid worker option {}: {}", stringify!($name), err))
+                        )?,)*
+                        key => return Err(PyTypeError::new_err(format!("Unknown worker option: {}", key)))
+                    }
+                }
+                Ok(rv)
+            }
+        }
+    };
+}
+
+worker_options! {
+    threads: usize = 1,
+    pthreads: usize = 1,
+    http: String = "auto".to_string(),
+    http1_buffer_size: usize = 65535,
+    websockets: bool = true,
+    listener_shards: bool = false,
+    backlog: i32 = 1024,
+    request_filters: Vec<(String, Vec<String>)> = Vec::new(),
+    response_filters: Vec<(String, Vec<String>)> = Vec::new(),
+    synthetic_responses: Vec<SyntheticRoute> = Vec::new(),
+    idempotency_ttl: u64 = 0,
+    status_hooks: Vec<(String, String, String)> = Vec::new(),
+    file_drop_cache_size: u64 = 0,
+    file_cache_size: usize = 0,
+    file_cache_ttl: f64 = 1.0,
+    http2_max_concurrent_streams: u32 = 0,
+    http2_initial_stream_window_size: u32 = 1048576,
+    http2_initial_connection_window_size: u32 = 1048576,
+    http2_adaptive_window: bool = false,
+    deadline_header: Option<String> = None,
+    deadline_trusted: Vec<String> = vec!["127.0.0.1".to_string(), "::1".to_string()],
+    disconnect_policy: String = "discard".to_string(),
+    websocket_origins: Vec<String> = Vec::new(),
+    websocket_utf8: String = "strict".to_string(),
+    metrics_routes: Vec<String> = Vec::new(),
+    error_format: String = "auto".to_string(),
+    duplicate_headers: Vec<(String, String)> = Vec::new(),
+    path_decoding: String = "raw".to_string(),
+    options_routes: Vec<(String, Vec<String>)> = Vec::new(),
+    allowed_hosts: Vec<String> = Vec::new(),
+    presign_routes: Vec<String> = Vec::new(),
+    presign_credentials: Vec<(String, String)> = Vec::new(),
+    presign_region: String = "us-east-1".to_string(),
+    connection_trace_sample: u64 = 0,
+    slo_objective: Option<f64> = None,
+    slo_latency: Option<f64> = None,
+    slo_interval: f64 = 10.0,
+    metrics_path: Option<String> = None,
+    metrics_address: String = "127.0.0.1".to_string(),
+    metrics_port: Option<u16> = None,
+    stack_dump_dir: Option<String> = None,
+    stack_dump_threshold: Option<f64> = None,
+    idle_timeout: f64 = 30.0,
+    proxy_protocol: bool = false,
+    forwarded_trusted: Vec<String> = Vec::new(),
+    access_log: Option<String> = None,
+    access_log_format: String = "common".to_string(),
+    grpc_web: bool = false,
+    transcoding: bool = false,
+    uploads_path: Option<String> = None,
+    uploads_dir: Option<String> = None,
+    uploads_max_size: u64 = 0,
+    otel_endpoint: Option<String> = None,
+    otel_service_name: String = "granian".to_string(),
+    otel_sample_ratio: f64 = 1.0,
+    compression: bool = false,
+    compression_min_size: u64 = 1024,
+    compression_types: Vec<String> = Vec::new(),
+    decompression: bool = false,
+    decompression_max_size: u64 = 16777216,
+    header_read_timeout: f64 = 30.0,
+    body_read_timeout: f64 = 60.0,
+    request_timeout: f64 = 0.0,
+    max_body_size: u64 = 0,
+    max_header_size: usize = 65536,
+    max_headers: usize = 100,
+    max_uri_length: usize = 8192,
+    keep_alive_timeout: f64 = 30.0,
+    keep_alive_max_requests: u64 = 0,
+    keep_alive_header: bool = false,
+    drain_timeout: f64 = 30.0,
+    handler_cancellation: bool = false,
+    cancellation_grace: f64 = 0.0,
+    cancellation_exempt: Vec<String> = Vec::new(),
+    long_poll_routes: Vec<String> = Vec::new(),
+    header_validation: String = "strict".to_string(),
+    protocol_strict: bool = true,
+    ssl_cert: Option<String> = None,
+    ssl_key: Option<String> = None,
+    ssl_record_size_initial: usize = 0,
+    ssl_record_size_max: usize = 16384,
+    ssl_reload_interval: f64 = 0.0,
+    ssl_ca: Option<String> = None,
+    ssl_client_verify: String = "required".to_string(),
+    ssl_sni: Vec<(String, String, String)> = Vec::new()
+}
 
 pub(crate) struct WorkerConfig {
     pub id: i32,
@@ -18,37 +197,128 @@ pub(crate) struct WorkerConfig {
     pub pthreads: usize,
     pub http_mode: String,
     pub http1_buffer_max: usize,
+    pub http2_settings: Http2Settings,
     pub websockets_enabled: bool,
+    request_filters: RequestFilters,
+    response_filters: ResponseFilters,
+    synthetic_responses: SyntheticResponses,
+    idempotency: IdempotencyCache,
+    response_headers: ResponseHeaders,
+    status_hooks: StatusHooks,
+    listener_shards: bool,
+    backlog: i32,
+    files: FileResponses,
+    deadlines: Deadlines,
+    disconnect_policy: DisconnectPolicy,
+    websocket_origins: WebsocketOrigins,
+    websocket_utf8: Utf8Policy,
+    metrics_routes: RouteTemplates,
+    error_format: ErrorFormat,
+    duplicate_headers: DuplicateHeaders,
+    path_decoding: PathDecoding,
+    options_responses: OptionsResponses,
+    allowed_hosts: AllowedHosts,
+    presigned_urls: PresignedUrls,
+    connection_traces: ConnectionTraces,
+    pub slo: SloPolicy,
+    pub metrics_exposition: MetricsExposition,
+    stack_dumps: StackDumps,
+    pub idle_timeout: IdleTimeout,
+    pub proxy_protocol: ProxyProtocol,
+    forwarded: ForwardedHeaders,
+    access_log: AccessLog,
+    grpc_web: GrpcWeb,
+    transcoding: Transcoding,
+    uploads: ResumableUploads,
+    tracer: Tracer,
+    compression: Compression,
+    decompression: Decompression,
+    pub request_timeouts: RequestTimeouts,
+    body_limit: BodyLimit,
+    header_limits: HeaderLimits,
+    pub keep_alive: KeepAlive,
+    pub drain: Drain,
+    cancellation: Cancellation,
+    long_polls: LongPolls,
+    header_validation: HeaderValidation,
+    conformance: ProtocolConformance,
+    pub tls_records: RecordSizing,
+    tls_watch: CertificateWatch,
+    tls_client_auth: ClientAuth,
+    tls_sni: SniCertificates,
     pub ssl_enabled: bool,
     ssl_cert: Option<String>,
     ssl_key: Option<String>
 }
 
 impl WorkerConfig {
-    pub fn new(
-        id: i32,
-        socket_fd: i32,
-        threads: usize,
-        pthreads: usize,
-        http_mode: String,
-        http1_buffer_max: usize,
-        websockets_enabled: bool,
-        ssl_enabled: bool,
-        ssl_cert: Option<String>,
-        ssl_key: Option<String>
-    ) -> Self {
-        Self {
-            id,
+    pub fn new(identity: WorkerIdentity, socket_fd: i32, options: WorkerOptions) -> PyResult<Self> {
+        let _ = IDENTITY.set(identity);
+        Ok(Self {
+            id: identity.id,
             socket_fd,
-            threads,
-            pthreads,
-            http_mode,
-            http1_buffer_max,
-            websockets_enabled,
-            ssl_enabled,
-            ssl_cert,
-            ssl_key
-        }
+            threads: options.threads,
+            pthreads: options.pthreads,
+            http_mode: options.http,
+            http1_buffer_max: options.http1_buffer_size,
+            http2_settings: Http2Settings::new(
+                options.http2_max_concurrent_streams,
+                options.http2_initial_stream_window_size,
+                options.http2_initial_connection_window_size,
+                options.http2_adaptive_window
+            )?,
+            websockets_enabled: options.websockets,
+            request_filters: RequestFilters::new(options.request_filters)?,
+            response_filters: ResponseFilters::new(options.response_filters)?,
+            synthetic_responses: SyntheticResponses::new(options.synthetic_responses)?,
+            idempotency: IdempotencyCache::new(options.idempotency_ttl),
+            response_headers: ResponseHeaders::new(),
+            status_hooks: StatusHooks::new(options.status_hooks)?,
+            listener_shards: options.listener_shards,
+            backlog: options.backlog,
+            files: FileResponses::new(options.file_drop_cache_size, options.file_cache_size, options.file_cache_ttl),
+            deadlines: Deadlines::new(options.deadline_header, options.deadline_trusted)?,
+            disconnect_policy: DisconnectPolicy::new(&options.disconnect_policy)?,
+            websocket_origins: WebsocketOrigins::new(options.websocket_origins)?,
+            websocket_utf8: Utf8Policy::new(&options.websocket_utf8)?,
+            metrics_routes: RouteTemplates::new(options.metrics_routes)?,
+            error_format: ErrorFormat::new(&options.error_format)?,
+            duplicate_headers: DuplicateHeaders::new(options.duplicate_headers)?,
+            path_decoding: PathDecoding::new(&options.path_decoding)?,
+            options_responses: OptionsResponses::new(options.options_routes)?,
+            allowed_hosts: AllowedHosts::new(options.allowed_hosts)?,
+            presigned_urls: PresignedUrls::new(options.presign_routes, options.presign_credentials, options.presign_region)?,
+            connection_traces: ConnectionTraces::new(options.connection_trace_sample),
+            slo: SloPolicy::new(options.slo_objective, options.slo_latency, options.slo_interval)?,
+            metrics_exposition: MetricsExposition::new(options.metrics_path, options.metrics_address, options.metrics_port)?,
+            stack_dumps: StackDumps::new(options.stack_dump_dir, options.stack_dump_threshold)?,
+            idle_timeout: IdleTimeout::new(options.idle_timeout)?,
+            proxy_protocol: ProxyProtocol::new(options.proxy_protocol),
+            forwarded: ForwardedHeaders::new(options.forwarded_trusted)?,
+            access_log: AccessLog::new(options.access_log, &options.access_log_format)?,
+            grpc_web: GrpcWeb::new(options.grpc_web),
+            transcoding: Transcoding::new(options.transcoding),
+            uploads: ResumableUploads::new(options.uploads_path, options.uploads_dir, options.uploads_max_size)?,
+            tracer: Tracer::new(options.otel_endpoint, options.otel_service_name, options.otel_sample_ratio)?,
+            compression: Compression::new(options.compression, options.compression_min_size, options.compression_types)?,
+            decompression: Decompression::new(options.decompression, options.decompression_max_size),
+            request_timeouts: RequestTimeouts::new(options.header_read_timeout, options.body_read_timeout, options.request_timeout)?,
+            body_limit: BodyLimit::new(options.max_body_size),
+            header_limits: HeaderLimits::new(options.max_header_size, options.max_headers, options.max_uri_length)?,
+            keep_alive: KeepAlive::new(options.keep_alive_timeout, options.keep_alive_max_requests, options.keep_alive_header)?,
+            drain: Drain::new(options.drain_timeout)?,
+            cancellation: Cancellation::new(options.handler_cancellation, options.cancellation_grace, options.cancellation_exempt)?,
+            long_polls: LongPolls::new(options.long_poll_routes)?,
+            header_validation: HeaderValidation::new(&options.header_validation)?,
+            conformance: ProtocolConformance::new(options.protocol_strict),
+            tls_records: RecordSizing::new(options.ssl_record_size_initial, options.ssl_record_size_max)?,
+            tls_watch: CertificateWatch::new(options.ssl_reload_interval)?,
+            tls_client_auth: ClientAuth::new(options.ssl_ca, &options.ssl_client_verify)?,
+            tls_sni: SniCertificates::new(options.ssl_sni)?,
+            ssl_enabled: options.ssl_cert.is_some() && options.ssl_key.is_some(),
+            ssl_cert: options.ssl_cert,
+            ssl_key: options.ssl_key
+        })
     }
 
     #[cfg(unix)]
@@ -65,24 +335,139 @@ impl WorkerConfig {
         }
     }
 
-    pub fn tls_cfg(&self) -> tokio_rustls::rustls::ServerConfig {
-        let mut cfg = tokio_rustls::rustls::ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(
-                tls_load_certs(&self.ssl_cert.clone().unwrap()[..]).unwrap(),
-                tls_load_pkey(&self.ssl_key.clone().unwrap()[..]).unwrap()
-            )
-            .unwrap();
-        cfg.alpn_protocols = match &self.http_mode[..] {
-            "1" => vec![b"http/1.1".to_vec()],
-            "2" => vec![b"h2".to_vec()],
-            _ => vec![b"h2".to_vec(), b"http/1.1".to_vec()]
-        };
-        cfg
+    // With listener shards, every thread but the first one accepts from its own socket,
+    // bound to the worker address with SO_REUSEPORT: the kernel balances connections
+    // across the sockets, so threads don't contend on a shared accept queue.
+    pub fn thread_listener(&self, thread_id: usize) -> TcpListener {
+        // Unix domain sockets can't be sharded, all the threads accept from the same one
+        let unix = crate::tcp::is_unix_listener(&std::mem::ManuallyDrop::new(self.tcp_listener()));
+        if !self.listener_shards || thread_id == 0 || unix {
+            return self.tcp_listener()
+        }
+        match self.shard_listener() {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::warn!("Unable to open listener shard, falling back to the shared one: {}", err);
+                self.tcp_listener()
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn shard_listener(&self) -> std::io::Result<TcpListener> {
+        let shared = std::mem::ManuallyDrop::new(self.tcp_listener());
+        let address = shared.local_addr()?;
+        let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_reuse_port(true)?;
+        socket.set_nodelay(true)?;
+        socket.bind(&address.into())?;
+        socket.listen(self.backlog)?;
+        Ok(socket.into())
+    }
+
+    #[cfg(windows)]
+    fn shard_listener(&self) -> std::io::Result<TcpListener> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported, "SO_REUSEPORT is not available on Windows"
+        ))
+    }
+
+    pub fn tls_cfg(&self) -> Result<tokio_rustls::rustls::ServerConfig, Error> {
+        let certificate = ReloadableCertificate::shared(
+            self.ssl_cert.as_deref().unwrap_or_default(),
+            self.ssl_key.as_deref().unwrap_or_default()
+        ).map_err(Error::tls)?;
+        let resolver = self.tls_sni.resolver(certificate).map_err(Error::tls)?;
+        self.tls_watch.start();
+        let mut cfg = self.tls_client_auth.config().with_cert_resolver(resolver);
+        cfg.alpn_protocols = crate::tls::alpn_protocols(&self.http_mode);
+        cfg.max_fragment_size = self.tls_records.max_fragment_size();
+        Ok(cfg)
+    }
+
+    pub fn ctx(&self) -> WorkerCtx {
+        WorkerCtx {
+            request_filters: self.request_filters.clone(),
+            response_filters: self.response_filters.clone(),
+            synthetic_responses: self.synthetic_responses.clone(),
+            idempotency: self.idempotency.clone(),
+            response_headers: self.response_headers.clone(),
+            status_hooks: self.status_hooks.clone(),
+            files: self.files.clone(),
+            deadlines: self.deadlines.clone(),
+            disconnect_policy: self.disconnect_policy,
+            websocket_origins: self.websocket_origins.clone(),
+            websocket_utf8: self.websocket_utf8,
+            metrics_routes: self.metrics_routes.clone(),
+            metrics_exposition: self.metrics_exposition.clone(),
+            error_format: self.error_format,
+            duplicate_headers: self.duplicate_headers.clone(),
+            path_decoding: self.path_decoding,
+            options_responses: self.options_responses.clone(),
+            allowed_hosts: self.allowed_hosts.clone(),
+            presigned_urls: self.presigned_urls.clone(),
+            connection_traces: self.connection_traces.clone(),
+            stack_dumps: self.stack_dumps.clone(),
+            forwarded: self.forwarded.clone(),
+            access_log: self.access_log.clone(),
+            grpc_web: self.grpc_web,
+            transcoding: self.transcoding,
+            uploads: self.uploads.clone(),
+            tracer: self.tracer.clone(),
+            compression: self.compression.clone(),
+            decompression: self.decompression,
+            body_limit: self.body_limit,
+            header_limits: self.header_limits,
+            drain: self.drain.clone(),
+            cancellation: self.cancellation.clone(),
+            long_polls: self.long_polls.clone(),
+            header_validation: self.header_validation,
+            conformance: self.conformance
+        }
     }
 }
 
+// Per-worker settings shared by all the request handlers
+pub(crate) struct WorkerCtx {
+    pub request_filters: RequestFilters,
+    pub response_filters: ResponseFilters,
+    pub synthetic_responses: SyntheticResponses,
+    pub idempotency: IdempotencyCache,
+    pub response_headers: ResponseHeaders,
+    pub status_hooks: StatusHooks,
+    pub files: FileResponses,
+    pub deadlines: Deadlines,
+    pub disconnect_policy: DisconnectPolicy,
+    pub websocket_origins: WebsocketOrigins,
+    pub websocket_utf8: Utf8Policy,
+    pub metrics_routes: RouteTemplates,
+    pub metrics_exposition: MetricsExposition,
+    pub error_format: ErrorFormat,
+    pub duplicate_headers: DuplicateHeaders,
+    pub path_decoding: PathDecoding,
+    pub options_responses: OptionsResponses,
+    pub allowed_hosts: AllowedHosts,
+    pub presigned_urls: PresignedUrls,
+    pub connection_traces: ConnectionTraces,
+    pub stack_dumps: StackDumps,
+    pub forwarded: ForwardedHeaders,
+    pub access_log: AccessLog,
+    pub grpc_web: GrpcWeb,
+    pub transcoding: Transcoding,
+    pub uploads: ResumableUploads,
+    pub tracer: Tracer,
+    pub compression: Compression,
+    pub decompression: Decompression,
+    pub body_limit: BodyLimit,
+    pub header_limits: HeaderLimits,
+    pub drain: Drain,
+    pub cancellation: Cancellation,
+    pub long_polls: LongPolls,
+    pub header_validation: HeaderValidation,
+    pub conformance: ProtocolConformance
+}
+
 // pub(crate) struct Worker<R>
 // where R: Future<Output=Response<Body>> + Send
 // {
@@ -106,28 +491,88 @@ where
     }
 }
 
+// Hyper goes back to serving both protocols whenever told any of them is not
+// the only one, so only the selected protocol gets set.
+pub(crate) fn http_protocols<I, E>(builder: hyper::server::Builder<I, E>, mode: &str) -> hyper::server::Builder<I, E> {
+    match mode {
+        "1" => builder.http1_only(true),
+        "2" => builder.http2_only(true),
+        _ => builder
+    }
+}
+
 macro_rules! build_service {
-    ($callback_wrapper:expr, $rt:expr, $target:expr) => {
-        hyper::service::make_service_fn(|socket: &hyper::server::conn::AddrStream| {
+    ($callback_wrapper:expr, $rt:expr, $ctx:expr, $target:expr) => {
+        hyper::service::make_service_fn(|stream: &crate::idle::IdleStream<crate::proxy::ProxyStream>| {
+            let socket = stream.get_ref().get_ref();
+            let proxied = stream.get_ref().proxied();
             let local_addr = socket.local_addr();
             let remote_addr = socket.remote_addr();
+            let request_seen = stream.request_seen();
+            let exchange = stream.exchange();
+            let keep_alive = stream.keep_alive();
             let callback_wrapper = $callback_wrapper.clone();
             let rth = $rt.clone();
+            let ctx = $ctx.clone();
+            let conn_trace = ctx.connection_traces.sample(remote_addr, None);
+            let conn_span = ctx.tracer.connection(remote_addr, None);
 
             async move {
                 Ok::<_, std::convert::Infallible>(hyper::service::service_fn(move |req| {
+                    request_seen.mark();
+                    let in_flight = keep_alive.start(&req);
+                    let req = exchange.request(req);
+                    let remote_addr = proxied.remote_addr(remote_addr);
+                    let scheme = proxied.scheme("http");
                     let callback_wrapper = callback_wrapper.clone();
                     let rth = rth.clone();
+                    let ctx = ctx.clone();
+                    let conn_trace = conn_trace.clone();
+                    let conn_span = conn_span.clone();
+                    let exchange = exchange.clone();
 
                     async move {
-                        Ok::<_, std::convert::Infallible>($target(
-                            rth,
-                            callback_wrapper,
-                            local_addr,
-                            remote_addr,
+                        let _in_flight = crate::metrics::METRICS.requests_in_flight.track();
+                        let mut req = req;
+                        let trace = crate::diagnostics::RequestTrace::attach(&mut req, &conn_trace);
+                        let idempotency = ctx.idempotency.clone();
+                        let deadlines = ctx.deadlines.clone();
+                        let error_format = ctx.error_format;
+                        let drain = ctx.drain.clone();
+                        let accept = req.headers().get(hyper::header::ACCEPT).cloned();
+                        let access = ctx.access_log.request(&req);
+                        let (client_addr, scheme) = ctx.forwarded.resolve(req.headers(), remote_addr, scheme);
+                        let hooked = ctx.status_hooks.request(&req, client_addr, scheme);
+                        let span = ctx.tracer.request(&mut req, &conn_span, client_addr, scheme);
+                        let res = deadlines.handle(req, remote_addr, |req| idempotency.handle(
                             req,
-                            "http"
-                        ).await)
+                            client_addr,
+                            |req| $target(
+                                rth,
+                                callback_wrapper,
+                                ctx,
+                                local_addr,
+                                client_addr,
+                                req,
+                                scheme
+                            )
+                        )).await;
+                        let res = error_format.render(accept.as_ref(), exchange.respond(res));
+                        let res = match hooked {
+                            Some(hooked) => hooked.respond(res),
+                            None => res
+                        };
+                        let res = in_flight.respond(crate::http::reason_fallback(res), drain.draining());
+                        trace.check_response(res.status());
+                        trace.response_started();
+                        crate::metrics::METRICS.record_response(res.status(), trace.elapsed());
+                        if let Some(access) = access {
+                            access.finish(client_addr, &res, trace.elapsed());
+                        }
+                        if let Some(span) = span {
+                            span.finish(&res);
+                        }
+                        Ok::<_, std::convert::Infallible>(res)
                     }
                 }))
             }
@@ -136,28 +581,79 @@ macro_rules! build_service {
 }
 
 macro_rules! build_service_ssl {
-    ($callback_wrapper:expr, $rt:expr, $target:expr) => {
-        hyper::service::make_service_fn(|stream: &crate::tls::TlsAddrStream| {
-            let (socket, _) = stream.get_ref();
+    ($callback_wrapper:expr, $rt:expr, $ctx:expr, $target:expr) => {
+        hyper::service::make_service_fn(|stream: &crate::idle::IdleStream<crate::tls::TlsAddrStream>| {
+            let (socket, _) = stream.get_ref().get_ref();
+            let proxied = stream.get_ref().proxied();
             let local_addr = socket.local_addr();
             let remote_addr = socket.remote_addr();
+            let request_seen = stream.request_seen();
+            let exchange = stream.exchange();
+            let keep_alive = stream.keep_alive();
             let callback_wrapper = $callback_wrapper.clone();
             let rth = $rt.clone();
+            let ctx = $ctx.clone();
+            let conn_trace = ctx.connection_traces.sample(remote_addr, Some(stream.get_ref().handshake()));
+            let conn_span = ctx.tracer.connection(remote_addr, Some(stream.get_ref().handshake()));
+            let tls_session = stream.get_ref().session();
 
             async move {
                 Ok::<_, std::convert::Infallible>(hyper::service::service_fn(move |req| {
+                    request_seen.mark();
+                    let in_flight = keep_alive.start(&req);
+                    let req = exchange.request(req);
+                    let remote_addr = proxied.remote_addr(remote_addr);
                     let callback_wrapper = callback_wrapper.clone();
                     let rth = rth.clone();
+                    let ctx = ctx.clone();
+                    let conn_trace = conn_trace.clone();
+                    let conn_span = conn_span.clone();
+                    let exchange = exchange.clone();
+                    let tls_session = tls_session.clone();
 
                     async move {
-                        Ok::<_, std::convert::Infallible>($target(
-                            rth,
-                            callback_wrapper,
-                            local_addr,
-                            remote_addr,
+                        let _in_flight = crate::metrics::METRICS.requests_in_flight.track();
+                        let mut req = req;
+                        req.extensions_mut().insert(tls_session);
+                        let trace = crate::diagnostics::RequestTrace::attach(&mut req, &conn_trace);
+                        let idempotency = ctx.idempotency.clone();
+                        let deadlines = ctx.deadlines.clone();
+                        let error_format = ctx.error_format;
+                        let drain = ctx.drain.clone();
+                        let accept = req.headers().get(hyper::header::ACCEPT).cloned();
+                        let access = ctx.access_log.request(&req);
+                        let (client_addr, scheme) = ctx.forwarded.resolve(req.headers(), remote_addr, "https");
+                        let hooked = ctx.status_hooks.request(&req, client_addr, scheme);
+                        let span = ctx.tracer.request(&mut req, &conn_span, client_addr, scheme);
+                        let res = deadlines.handle(req, remote_addr, |req| idempotency.handle(
                             req,
-                            "https"
-                        ).await)
+                            client_addr,
+                            |req| $target(
+                                rth,
+                                callback_wrapper,
+                                ctx,
+                                local_addr,
+                                client_addr,
+                                req,
+                                scheme
+                            )
+                        )).await;
+                        let res = error_format.render(accept.as_ref(), exchange.respond(res));
+                        let res = match hooked {
+                            Some(hooked) => hooked.respond(res),
+                            None => res
+                        };
+                        let res = in_flight.respond(crate::http::reason_fallback(res), drain.draining());
+                        trace.check_response(res.status());
+                        trace.response_started();
+                        crate::metrics::METRICS.record_response(res.status(), trace.elapsed());
+                        if let Some(access) = access {
+                            access.finish(client_addr, &res, trace.elapsed());
+                        }
+                        if let Some(span) = span {
+                            span.finish(&res);
+                        }
+                        Ok::<_, std::convert::Infallible>(res)
                     }
                 }))
             }
@@ -174,37 +670,52 @@ macro_rules! serve_rth {
             context: &PyAny,
             signal_rx: PyObject
         ) {
-            pyo3_log::init();
+            crate::logging::init();
             let rt = crate::runtime::init_runtime_mt(self.config.threads, self.config.pthreads);
             let rth = rt.handler();
             let tcp_listener = self.config.tcp_listener();
-            let http1_only = self.config.http_mode == "1";
-            let http2_only = self.config.http_mode == "2";
+            let http_mode = self.config.http_mode.clone();
             let http1_buffer_max = self.config.http1_buffer_max.clone();
+            let http2_settings = self.config.http2_settings;
+            let idle_timeout = self.config.idle_timeout;
+            let request_timeouts = self.config.request_timeouts;
+            let keep_alive = self.config.keep_alive;
+            let proxy_protocol = self.config.proxy_protocol;
+            let drain = self.config.drain.clone();
             let callback_wrapper = crate::callbacks::CallbackWrapper::new(
                 callback, event_loop, context
             );
+            let ctx = std::sync::Arc::new(self.config.ctx());
 
             let worker_id = self.config.id;
             log::info!("Started worker-{}", worker_id);
+            let _slo = self.config.slo.start();
+            let _metrics = self.config.metrics_exposition.start();
 
             let svc_loop = crate::runtime::run_until_complete(
                 rt.handler(),
                 event_loop,
                 async move {
                     let service = crate::workers::build_service!(
-                        callback_wrapper, rth, $target
+                        callback_wrapper, rth, ctx, $target
                     );
-                    let server = hyper::Server::from_tcp(tcp_listener).unwrap()
-                        .http1_only(http1_only)
-                        .http2_only(http2_only)
+                    let builder = hyper::Server::builder(
+                        crate::idle::listen(tcp_listener, idle_timeout, request_timeouts, keep_alive, proxy_protocol).unwrap()
+                    )
                         .http1_max_buf_size(http1_buffer_max)
-                        .serve(service);
-                    server.with_graceful_shutdown(async move {
+                        .http2_max_concurrent_streams(http2_settings.max_concurrent_streams)
+                        .http2_initial_stream_window_size(http2_settings.stream_window)
+                        .http2_initial_connection_window_size(http2_settings.connection_window)
+                        .http2_adaptive_window(http2_settings.adaptive_window);
+                    let server = crate::workers::http_protocols(builder, &http_mode).serve(service);
+                    let server = server.with_graceful_shutdown(drain.signal(async move {
                         Python::with_gil(|py| {
                             crate::runtime::into_future(signal_rx.as_ref(py)).unwrap()
                         }).await.unwrap();
-                    }).await.unwrap();
+                    }));
+                    if let Some(ret) = drain.run(server).await {
+                        ret.unwrap();
+                    }
                     log::info!("Stopping worker-{}", worker_id);
                     Ok(())
                 }
@@ -217,6 +728,7 @@ macro_rules! serve_rth {
                     std::process::exit(1);
                 }
             };
+            event_loop.py().allow_threads(|| drop(rt));
         }
     };
 }
@@ -230,42 +742,59 @@ macro_rules! serve_rth_ssl {
             context: &PyAny,
             signal_rx: PyObject
         ) {
-            pyo3_log::init();
+            crate::logging::init();
             let rt = crate::runtime::init_runtime_mt(self.config.threads, self.config.pthreads);
             let rth = rt.handler();
             let tcp_listener = self.config.tcp_listener();
-            let http1_only = self.config.http_mode == "1";
-            let http2_only = self.config.http_mode == "2";
+            let http_mode = self.config.http_mode.clone();
             let http1_buffer_max = self.config.http1_buffer_max.clone();
-            let tls_cfg = self.config.tls_cfg();
+            let http2_settings = self.config.http2_settings;
+            let idle_timeout = self.config.idle_timeout;
+            let request_timeouts = self.config.request_timeouts;
+            let keep_alive = self.config.keep_alive;
+            let proxy_protocol = self.config.proxy_protocol;
+            let drain = self.config.drain.clone();
+            let tls_cfg = match self.config.tls_cfg() {
+                Ok(cfg) => cfg,
+                Err(err) => err.exit()
+            };
+            let tls_records = self.config.tls_records;
             let callback_wrapper = crate::callbacks::CallbackWrapper::new(
                 callback, event_loop, context
             );
+            let ctx = std::sync::Arc::new(self.config.ctx());
 
             let worker_id = self.config.id;
             log::info!("Started worker-{}", worker_id);
+            let _slo = self.config.slo.start();
+            let _metrics = self.config.metrics_exposition.start();
 
             let svc_loop = crate::runtime::run_until_complete(
                 rt.handler(),
                 event_loop,
                 async move {
                     let service = crate::workers::build_service_ssl!(
-                        callback_wrapper, rth, $target
+                        callback_wrapper, rth, ctx, $target
                     );
-                    let server = hyper::Server::builder(
+                    let builder = hyper::Server::builder(
                         crate::tls::tls_listen(
-                            std::sync::Arc::new(tls_cfg), tcp_listener
+                            std::sync::Arc::new(tls_cfg), tls_records, idle_timeout, request_timeouts, keep_alive, proxy_protocol, tcp_listener
                         )
                     )
-                        .http1_only(http1_only)
-                        .http2_only(http2_only)
                         .http1_max_buf_size(http1_buffer_max)
-                        .serve(service);
-                    server.with_graceful_shutdown(async move {
+                        .http2_max_concurrent_streams(http2_settings.max_concurrent_streams)
+                        .http2_initial_stream_window_size(http2_settings.stream_window)
+                        .http2_initial_connection_window_size(http2_settings.connection_window)
+                        .http2_adaptive_window(http2_settings.adaptive_window);
+                    let server = crate::workers::http_protocols(builder, &http_mode).serve(service);
+                    let server = server.with_graceful_shutdown(drain.signal(async move {
                         Python::with_gil(|py| {
                             crate::runtime::into_future(signal_rx.as_ref(py)).unwrap()
                         }).await.unwrap();
-                    }).await.unwrap();
+                    }));
+                    if let Some(ret) = drain.run(server).await {
+                        ret.unwrap();
+                    }
                     log::info!("Stopping worker-{}", worker_id);
                     Ok(())
                 }
@@ -278,6 +807,7 @@ macro_rules! serve_rth_ssl {
                     std::process::exit(1);
                 }
             };
+            event_loop.py().allow_threads(|| drop(rt));
         }
     };
 }
@@ -291,27 +821,36 @@ macro_rules! serve_wth {
             context: &PyAny,
             signal_rx: PyObject
         ) {
-            pyo3_log::init();
+            crate::logging::init();
             let rtm = crate::runtime::init_runtime_mt(1, 1);
 
             let worker_id = self.config.id;
             log::info!("Started worker-{}", worker_id);
+            let _slo = self.config.slo.start();
+            let _metrics = self.config.metrics_exposition.start();
 
             let callback_wrapper = crate::callbacks::CallbackWrapper::new(
                 callback, event_loop, context
             );
+            let ctx = std::sync::Arc::new(self.config.ctx());
             let mut workers = vec![];
             let (stx, srx) = tokio::sync::watch::channel(false);
 
             for thread_id in 0..self.config.threads {
                 log::info!("Started worker-{} runtime-{}", worker_id, thread_id + 1);
 
-                let tcp_listener = self.config.tcp_listener();
-                let http1_only = self.config.http_mode == "1";
-                let http2_only = self.config.http_mode == "2";
+                let tcp_listener = self.config.thread_listener(thread_id);
+                let http_mode = self.config.http_mode.clone();
                 let http1_buffer_max = self.config.http1_buffer_max.clone();
+                let http2_settings = self.config.http2_settings;
+                let idle_timeout = self.config.idle_timeout;
+                let request_timeouts = self.config.request_timeouts;
+                let keep_alive = self.config.keep_alive;
+                let proxy_protocol = self.config.proxy_protocol;
+                let drain = self.config.drain.clone();
                 let pthreads = self.config.pthreads.clone();
                 let callback_wrapper = callback_wrapper.clone();
+                let ctx = ctx.clone();
                 let mut srx = srx.clone();
 
                 workers.push(std::thread::spawn(move || {
@@ -321,17 +860,24 @@ macro_rules! serve_wth {
 
                     crate::runtime::block_on_local(rt, local, async move {
                         let service = crate::workers::build_service!(
-                            callback_wrapper, rth, $target
+                            callback_wrapper, rth, ctx, $target
                         );
-                        let server = hyper::Server::from_tcp(tcp_listener).unwrap()
+                        let builder = hyper::Server::builder(
+                            crate::idle::listen(tcp_listener, idle_timeout, request_timeouts, keep_alive, proxy_protocol).unwrap()
+                        )
                             .executor(crate::workers::WorkerExecutor)
-                            .http1_only(http1_only)
-                            .http2_only(http2_only)
                             .http1_max_buf_size(http1_buffer_max)
-                            .serve(service);
-                        server.with_graceful_shutdown(async move {
+                            .http2_max_concurrent_streams(http2_settings.max_concurrent_streams)
+                            .http2_initial_stream_window_size(http2_settings.stream_window)
+                            .http2_initial_connection_window_size(http2_settings.connection_window)
+                            .http2_adaptive_window(http2_settings.adaptive_window);
+                        let server = crate::workers::http_protocols(builder, &http_mode).serve(service);
+                        let server = server.with_graceful_shutdown(drain.signal(async move {
                             srx.changed().await.unwrap();
-                        }).await.unwrap();
+                        }));
+                        if let Some(ret) = drain.run(server).await {
+                            ret.unwrap();
+                        }
                         log::info!("Stopping worker-{} runtime-{}", worker_id, thread_id + 1);
                     });
                 }));
@@ -360,6 +906,7 @@ macro_rules! serve_wth {
                     std::process::exit(1);
                 }
             };
+            event_loop.py().allow_threads(|| drop(rtm));
         }
     };
 }
@@ -373,28 +920,41 @@ macro_rules! serve_wth_ssl {
             context: &PyAny,
             signal_rx: PyObject
         ) {
-            pyo3_log::init();
+            crate::logging::init();
             let rtm = crate::runtime::init_runtime_mt(1, 1);
 
             let worker_id = self.config.id;
             log::info!("Started worker-{}", worker_id);
+            let _slo = self.config.slo.start();
+            let _metrics = self.config.metrics_exposition.start();
 
             let callback_wrapper = crate::callbacks::CallbackWrapper::new(
                 callback, event_loop, context
             );
+            let ctx = std::sync::Arc::new(self.config.ctx());
             let mut workers = vec![];
             let (stx, srx) = tokio::sync::watch::channel(false);
 
             for thread_id in 0..self.config.threads {
                 log::info!("Started worker-{} runtime-{}", worker_id, thread_id + 1);
 
-                let tcp_listener = self.config.tcp_listener();
-                let http1_only = self.config.http_mode == "1";
-                let http2_only = self.config.http_mode == "2";
+                let tcp_listener = self.config.thread_listener(thread_id);
+                let http_mode = self.config.http_mode.clone();
                 let http1_buffer_max = self.config.http1_buffer_max.clone();
-                let tls_cfg = self.config.tls_cfg();
+                let http2_settings = self.config.http2_settings;
+                let idle_timeout = self.config.idle_timeout;
+                let request_timeouts = self.config.request_timeouts;
+                let keep_alive = self.config.keep_alive;
+                let proxy_protocol = self.config.proxy_protocol;
+                let drain = self.config.drain.clone();
+                let tls_cfg = match self.config.tls_cfg() {
+                    Ok(cfg) => cfg,
+                    Err(err) => err.exit()
+                };
+                let tls_records = self.config.tls_records;
                 let pthreads = self.config.pthreads.clone();
                 let callback_wrapper = callback_wrapper.clone();
+                let ctx = ctx.clone();
                 let mut srx = srx.clone();
 
                 workers.push(std::thread::spawn(move || {
@@ -404,21 +964,26 @@ macro_rules! serve_wth_ssl {
 
                     crate::runtime::block_on_local(rt, local, async move {
                         let service = crate::workers::build_service_ssl!(
-                            callback_wrapper, rth, $target
+                            callback_wrapper, rth, ctx, $target
                         );
-                        let server = hyper::Server::builder(
+                        let builder = hyper::Server::builder(
                             crate::tls::tls_listen(
-                                std::sync::Arc::new(tls_cfg), tcp_listener
+                                std::sync::Arc::new(tls_cfg), tls_records, idle_timeout, request_timeouts, keep_alive, proxy_protocol, tcp_listener
                             )
                         )
                             .executor(crate::workers::WorkerExecutor)
-                            .http1_only(http1_only)
-                            .http2_only(http2_only)
                             .http1_max_buf_size(http1_buffer_max)
-                            .serve(service);
-                        server.with_graceful_shutdown(async move {
+                            .http2_max_concurrent_streams(http2_settings.max_concurrent_streams)
+                            .http2_initial_stream_window_size(http2_settings.stream_window)
+                            .http2_initial_connection_window_size(http2_settings.connection_window)
+                            .http2_adaptive_window(http2_settings.adaptive_window);
+                        let server = crate::workers::http_protocols(builder, &http_mode).serve(service);
+                        let server = server.with_graceful_shutdown(drain.signal(async move {
                             srx.changed().await.unwrap();
-                        }).await.unwrap();
+                        }));
+                        if let Some(ret) = drain.run(server).await {
+                            ret.unwrap();
+                        }
                         log::info!("Stopping worker-{} runtime-{}", worker_id, thread_id + 1);
                     });
                 }));
@@ -447,6 +1012,7 @@ macro_rules! serve_wth_ssl {
                     std::process::exit(1);
                 }
             };
+            event_loop.py().allow_threads(|| drop(rtm));
         }
     };
 }
@@ -462,6 +1028,7 @@ pub(crate) fn init_pymodule(module: &PyModule) -> PyResult<()> {
     module.add_class::<ASGIWorker>()?;
     module.add_class::<RSGIWorker>()?;
     module.add_class::<WSGIWorker>()?;
+    module.add_class::<WorkerOptions>()?;
 
     Ok(())
 }
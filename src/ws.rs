@@ -3,32 +3,60 @@ use hyper::{
     Request,
     Response,
     StatusCode,
-    header::{CONNECTION, UPGRADE},
+    header::{CONNECTION, HOST, HeaderMap, ORIGIN, SEC_WEBSOCKET_ACCEPT, UPGRADE},
     http::response::Builder
 };
+use futures::{sink::{Sink, SinkExt}, stream::{SplitStream, StreamExt}};
 use tungstenite::{
+    Message,
     error::ProtocolError,
     handshake::derive_accept_key,
-    protocol::{Role, WebSocketConfig}
+    protocol::{CloseFrame, Role, WebSocketConfig, frame::coding::CloseCode}
 };
 use pin_project::pin_project;
-use std::{future::Future, pin::Pin, task::{Context, Poll}};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}},
+    task::{Context, Poll}
+};
 use tokio_tungstenite::WebSocketStream;
-use tokio::sync::mpsc;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc
+};
+
+use super::{
+    access::{SessionCounts, SessionRecord},
+    metrics::WebsocketMetrics,
+    utils::header_contains_value
+};
 
-use super::utils::header_contains_value;
 
+pub(crate) type WebsocketTransport = WebSocketStream<InboundFrames<hyper::upgrade::Upgraded>>;
 
 #[pin_project]
 #[derive(Debug)]
-pub struct HyperWebsocket {
+pub(crate) struct HyperWebsocket {
     #[pin]
     inner: hyper::upgrade::OnUpgrade,
     config: Option<WebSocketConfig>,
+    utf8: Utf8Policy,
+    fragments: Option<FragmentQueue>
+}
+
+impl HyperWebsocket {
+    // Incoming messages get delivered as their frames come, rather than assembled
+    pub fn deliver_fragments(&mut self) -> FragmentQueue {
+        self.fragments.get_or_insert_with(FragmentQueue::default).clone()
+    }
 }
 
 impl Future for HyperWebsocket {
-    type Output = Result<WebSocketStream<hyper::upgrade::Upgraded>, tungstenite::Error>;
+    type Output = Result<WebsocketTransport, tungstenite::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let this = self.project();
@@ -42,7 +70,7 @@ impl Future for HyperWebsocket {
         )?;
 
         let stream = WebSocketStream::from_raw_socket(
-            upgraded,
+            InboundFrames::new(upgraded, *this.utf8, this.fragments.take()),
             Role::Server,
             this.config.take(),
         );
@@ -58,18 +86,39 @@ impl Future for HyperWebsocket {
 pub(crate) struct UpgradeData {
     response_builder: Option<Builder>,
     response_tx: Option<mpsc::Sender<Response<Body>>>,
-    pub consumed: bool
+    pub consumed: bool,
+    pub metrics: Arc<WebsocketMetrics>,
+    pub session: Option<SessionRecord>
 }
 
 impl UpgradeData {
     pub fn new(
         response_builder: Builder,
-        response_tx: mpsc::Sender<Response<Body>>)
-    -> Self {
+        response_tx: mpsc::Sender<Response<Body>>,
+        metrics: Arc<WebsocketMetrics>,
+        session: Option<SessionRecord>
+    ) -> Self {
         Self {
             response_builder: Some(response_builder),
             response_tx: Some(response_tx),
-            consumed: false
+            consumed: false,
+            metrics,
+            session
+        }
+    }
+
+    // Adds the application headers to the handshake response, except for
+    // the ones negotiating the upgrade, which are owned by the server.
+    pub fn extend_headers(&mut self, headers: HeaderMap) {
+        let target = match self.response_builder.as_mut().and_then(|builder| builder.headers_mut()) {
+            Some(target) => target,
+            None => return
+        };
+        for (key, value) in headers.iter() {
+            if key == CONNECTION || key == UPGRADE || key == SEC_WEBSOCKET_ACCEPT {
+                continue
+            }
+            target.append(key.clone(), value.clone());
         }
     }
 
@@ -85,6 +134,402 @@ impl UpgradeData {
     }
 }
 
+const CLOSE_UNSET: u32 = 0;
+const CLOSE_NO_STATUS: u16 = 1005;
+const CLOSE_ABNORMAL: u16 = 1006;
+
+struct StatsState {
+    metrics: Arc<WebsocketMetrics>,
+    session: Option<SessionRecord>,
+    opened: AtomicBool,
+    close_code: AtomicU32,
+    frames_in: AtomicU64,
+    frames_out: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64
+}
+
+impl Drop for StatsState {
+    fn drop(&mut self) {
+        if !self.opened.load(Ordering::Relaxed) {
+            return
+        }
+        let close_code = match self.close_code.load(Ordering::Relaxed) {
+            CLOSE_UNSET => CLOSE_ABNORMAL,
+            code => code as u16
+        };
+        self.metrics.connections.dec();
+        self.metrics.record_close(close_code);
+        if let Some(session) = self.session.take() {
+            session.finish(close_code, SessionCounts {
+                frames_in: self.frames_in.load(Ordering::Relaxed),
+                frames_out: self.frames_out.load(Ordering::Relaxed),
+                bytes_in: self.bytes_in.load(Ordering::Relaxed),
+                bytes_out: self.bytes_out.load(Ordering::Relaxed)
+            });
+        }
+    }
+}
+
+// Statistics of a websocket connection, counted once the handshake completes.
+// The first close code seen, from either side, is the one recorded when the
+// connection gets dropped; connections ending with no close frame are abnormal.
+#[derive(Clone)]
+pub(crate) struct WebsocketStats {
+    state: Arc<StatsState>
+}
+
+impl WebsocketStats {
+    pub fn new(metrics: Arc<WebsocketMetrics>, session: Option<SessionRecord>) -> Self {
+        Self {
+            state: Arc::new(StatsState {
+                metrics,
+                session,
+                opened: AtomicBool::new(false),
+                close_code: AtomicU32::new(CLOSE_UNSET),
+                frames_in: AtomicU64::new(0),
+                frames_out: AtomicU64::new(0),
+                bytes_in: AtomicU64::new(0),
+                bytes_out: AtomicU64::new(0)
+            })
+        }
+    }
+
+    pub fn opened(&self) {
+        if !self.state.opened.swap(true, Ordering::Relaxed) {
+            self.state.metrics.connections.inc();
+            self.state.metrics.connections_total.inc();
+        }
+    }
+
+    pub fn inbound(&self, message: &Message) {
+        match message {
+            Message::Text(_) | Message::Binary(_) => {
+                self.state.metrics.messages_in.inc();
+                self.state.frames_in.fetch_add(1, Ordering::Relaxed);
+                self.inbound_bytes(message.len());
+            },
+            Message::Close(frame) => {
+                self.closed(frame.as_ref().map_or(CLOSE_NO_STATUS, |frame| frame.code.into()))
+            },
+            _ => {}
+        }
+    }
+
+    // Fragmented messages are counted once their final frame is received
+    pub fn inbound_fragment(&self, message: &Message, fragment: Fragment) {
+        match fragment {
+            Fragment::Start | Fragment::Continue => self.inbound_bytes(message.len()),
+            _ => self.inbound(message)
+        }
+    }
+
+    fn inbound_bytes(&self, size: usize) {
+        self.state.metrics.bytes_in.add(size as u64);
+        self.state.bytes_in.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn outbound(&self, size: usize) {
+        self.state.metrics.messages_out.inc();
+        self.state.frames_out.fetch_add(1, Ordering::Relaxed);
+        self.outbound_bytes(size);
+    }
+
+    // Fragmented messages are counted once their final frame is sent
+    pub fn outbound_fragment(&self, size: usize, last: bool) {
+        match last {
+            true => self.outbound(size),
+            false => self.outbound_bytes(size)
+        }
+    }
+
+    fn outbound_bytes(&self, size: usize) {
+        self.state.metrics.bytes_out.add(size as u64);
+        self.state.bytes_out.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn closed(&self, code: u16) {
+        let _ = self.state.close_code.compare_exchange(
+            CLOSE_UNSET, code as u32, Ordering::Relaxed, Ordering::Relaxed
+        );
+    }
+}
+
+// Payloads of text frames are validated with the `strict` policy, as they get
+// decoded into strings: invalid UTF-8 fails the connection right away with the
+// 1007 close code. With the `bytes` one text frames are handed over as binary,
+// skipping the validation for applications treating payloads as opaque.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Utf8Policy {
+    #[default]
+    Strict,
+    Bytes
+}
+
+impl Utf8Policy {
+    pub fn new(value: &str) -> PyResult<Self> {
+        match value {
+            "strict" => Ok(Self::Strict),
+            "bytes" => Ok(Self::Bytes),
+            _ => Err(PyValueError::new_err(format!("Invalid websocket UTF-8 policy: {}", value)))
+        }
+    }
+}
+
+const OPCODE_CONTINUE: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+
+enum FrameState {
+    // the bytes of the frame header read so far
+    Header([u8; 14], usize),
+    Payload(u64)
+}
+
+// Position of an incoming message in the fragmented one it is part of, if any
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Fragment {
+    None = 0,
+    Start = 1,
+    Continue = 2,
+    Finish = 3
+}
+
+// The fragments of the data frames read, in order, with whether they belong to a
+// text message
+pub(crate) type FragmentQueue = Arc<Mutex<VecDeque<(Fragment, bool)>>>;
+
+// The incoming side of websocket connections, below tungstenite: frames get
+// followed through their headers, so that their first byte can be rewritten
+// before tungstenite parses them, while payloads go through untouched.
+// Delivering fragments, the frames of fragmented messages get turned into final
+// binary ones, each handed over by tungstenite as a message of its own, as text
+// ones might split characters; the queue tells their position and kind.
+pub(crate) struct InboundFrames<S> {
+    inner: S,
+    state: FrameState,
+    utf8: Utf8Policy,
+    fragments: Option<FragmentQueue>,
+    // the opcode of the fragmented message being read
+    message: Option<u8>
+}
+
+impl<S> InboundFrames<S> {
+    fn new(inner: S, utf8: Utf8Policy, fragments: Option<FragmentQueue>) -> Self {
+        Self { inner, state: FrameState::Header([0; 14], 0), utf8, fragments, message: None }
+    }
+
+    fn rewrite(&mut self, head: u8) -> u8 {
+        let head = match head & 0x0f {
+            OPCODE_TEXT if self.utf8 == Utf8Policy::Bytes => (head & 0xf0) | OPCODE_BINARY,
+            _ => head
+        };
+        let fragments = match &self.fragments {
+            Some(fragments) => fragments,
+            None => return head
+        };
+        let (fin, opcode) = (head & 0x80 != 0, head & 0x0f);
+        let fragment = match (opcode, self.message) {
+            (OPCODE_TEXT | OPCODE_BINARY, None) if fin => {
+                fragments.lock().unwrap().push_back((Fragment::None, false));
+                return head
+            },
+            (OPCODE_TEXT | OPCODE_BINARY, None) => {
+                self.message = Some(opcode);
+                Fragment::Start
+            },
+            (OPCODE_CONTINUE, Some(_)) if fin => Fragment::Finish,
+            (OPCODE_CONTINUE, Some(_)) => Fragment::Continue,
+            // a reserved bit fails messages interrupting fragmented ones, as tungstenite would
+            (OPCODE_TEXT | OPCODE_BINARY, Some(_)) => return head | 0x40,
+            // control frames, and continuations of no message tungstenite refuses
+            _ => return head
+        };
+        let text = self.message == Some(OPCODE_TEXT);
+        if fragment == Fragment::Finish {
+            self.message = None;
+        }
+        fragments.lock().unwrap().push_back((fragment, text));
+        0x80 | (head & 0x70) | OPCODE_BINARY
+    }
+
+    fn inspect(&mut self, data: &mut [u8]) {
+        let mut pos = 0;
+        while pos < data.len() {
+            if let FrameState::Header(_, 0) = self.state {
+                data[pos] = self.rewrite(data[pos]);
+            }
+            match &mut self.state {
+                FrameState::Header(head, read) => {
+                    head[*read] = data[pos];
+                    *read += 1;
+                    pos += 1;
+                    if *read < 2 {
+                        continue
+                    }
+                    let (size, len) = frame_header(head);
+                    if *read == size && len > 0 {
+                        self.state = FrameState::Payload(len);
+                    } else if *read == size {
+                        self.state = FrameState::Header([0; 14], 0);
+                    }
+                },
+                FrameState::Payload(remaining) => {
+                    let size = (*remaining).min((data.len() - pos) as u64);
+                    pos += size as usize;
+                    *remaining -= size;
+                    if *remaining == 0 {
+                        self.state = FrameState::Header([0; 14], 0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The header size and payload length of a frame, from the first two bytes of its
+// header: the length is only complete once the whole header is read
+fn frame_header(head: &[u8; 14]) -> (usize, u64) {
+    let (extended, len) = match head[1] & 0x7f {
+        126 => (2, u16::from_be_bytes([head[2], head[3]]) as u64),
+        127 => (8, u64::from_be_bytes(head[2..10].try_into().unwrap())),
+        len => (0, len as u64)
+    };
+    let mask = if head[1] & 0x80 != 0 { 4 } else { 0 };
+    (2 + extended + mask, len)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for InboundFrames<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let ret = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = ret {
+            self.inspect(&mut buf.filled_mut()[filled..]);
+        }
+        ret
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for InboundFrames<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+// The incoming messages of a websocket, with their position in the fragmented
+// message they are part of. The characters of text fragments might be split
+// across frames, so their trailing incomplete ones wait for the next fragment.
+pub(crate) struct InboundMessages {
+    stream: SplitStream<WebsocketTransport>,
+    fragments: Option<FragmentQueue>,
+    partial: Vec<u8>
+}
+
+impl InboundMessages {
+    pub fn new(stream: SplitStream<WebsocketTransport>, fragments: Option<FragmentQueue>) -> Self {
+        Self { stream, fragments, partial: Vec::new() }
+    }
+
+    pub async fn next(&mut self) -> Option<Result<(Message, Fragment), tungstenite::Error>> {
+        let message = match self.stream.next().await? {
+            Ok(message) => message,
+            Err(err) => return Some(Err(err))
+        };
+        let fragments = match (&self.fragments, &message) {
+            (Some(fragments), Message::Text(_) | Message::Binary(_)) => fragments,
+            _ => return Some(Ok((message, Fragment::None)))
+        };
+        let (fragment, text) = fragments.lock().unwrap().pop_front().unwrap_or((Fragment::None, false));
+        if !text {
+            return Some(Ok((message, fragment)))
+        }
+        self.partial.extend_from_slice(&message.into_data());
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(err) if err.error_len().is_none() && fragment != Fragment::Finish => err.valid_up_to(),
+            Err(_) => return Some(Err(tungstenite::Error::Utf8))
+        };
+        let rest = self.partial.split_off(valid);
+        let data = std::mem::replace(&mut self.partial, rest);
+        Some(Ok((Message::Text(String::from_utf8(data).unwrap()), fragment)))
+    }
+}
+
+pub(crate) fn invalid_payload_close() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: CloseCode::Invalid,
+        reason: "Invalid UTF-8 payload".into()
+    }))
+}
+
+pub(crate) async fn fail_invalid_payload<S>(sink: &mut S, stats: &WebsocketStats)
+where S: Sink<Message> + Unpin
+{
+    stats.closed(CloseCode::Invalid.into());
+    let _ = sink.send(invalid_payload_close()).await;
+}
+
+// Origins allowed to open websockets. Without explicit entries, handshakes are
+// only accepted when their `Origin` matches the requested host; requests with no
+// `Origin` at all don't come from browsers, so they are not subject to the check.
+#[derive(Clone, Default)]
+pub(crate) struct WebsocketOrigins {
+    allowed: Arc<Vec<String>>,
+    any: bool
+}
+
+impl WebsocketOrigins {
+    pub fn new(origins: Vec<String>) -> PyResult<Self> {
+        let mut allowed = Vec::with_capacity(origins.len());
+        let mut any = false;
+        for origin in origins {
+            let value = normalize_origin(&origin);
+            match value.split_once("://") {
+                _ if value == "*" => any = true,
+                Some(("http" | "https", host)) if !host.is_empty() && !host.contains('/') => allowed.push(value),
+                _ => return Err(PyValueError::new_err(format!("Invalid websocket origin: {}", origin)))
+            }
+        }
+        Ok(Self { allowed: Arc::new(allowed), any })
+    }
+
+    pub fn allows<B>(&self, request: &Request<B>) -> bool {
+        let origin = match request.headers().get(ORIGIN) {
+            Some(origin) => origin,
+            None => return true
+        };
+        if self.any {
+            return true
+        }
+        let origin = match origin.to_str() {
+            Ok(origin) => normalize_origin(origin),
+            _ => return false
+        };
+        if !self.allowed.is_empty() {
+            return self.allowed.contains(&origin)
+        }
+        let host = request.headers().get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| request.uri().authority().map(|authority| authority.as_str()));
+        match (origin.split_once("://"), host) {
+            (Some((_, origin_host)), Some(host)) => origin_host.eq_ignore_ascii_case(host.trim()),
+            _ => false
+        }
+    }
+}
+
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_ascii_lowercase()
+}
+
 #[inline]
 pub(crate) fn is_upgrade_request<B>(request: &Request<B>) -> bool {
     header_contains_value(
@@ -97,6 +542,7 @@ pub(crate) fn is_upgrade_request<B>(request: &Request<B>) -> bool {
 pub(crate) fn upgrade_intent<B>(
     mut request: impl std::borrow::BorrowMut<Request<B>>,
     config: Option<WebSocketConfig>,
+    utf8: Utf8Policy
 ) -> Result<(Builder, HyperWebsocket), ProtocolError> {
     let request = request.borrow_mut();
 
@@ -119,6 +565,8 @@ pub(crate) fn upgrade_intent<B>(
     let stream = HyperWebsocket {
         inner: hyper::upgrade::on(request),
         config,
+        utf8,
+        fragments: None
     };
 
     Ok((response_builder, stream))
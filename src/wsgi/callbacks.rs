@@ -1,37 +1,31 @@
 use pyo3::prelude::*;
 use tokio::task::JoinHandle;
 
-use crate::callbacks::CallbackWrapper;
-use super::{
-    errors::error_proto,
-    types::WSGIScope as Scope
-};
+use crate::{callbacks::CallbackWrapper, errors::Error};
+use super::types::WSGIScope as Scope;
 
 
 pub(crate) async fn call_rtb_http(
     cb: CallbackWrapper,
     scope: Scope
-) -> PyResult<(i32, Vec<(String, String)>, Vec<u8>)> {
+) -> Result<(String, Vec<(String, String)>, Vec<u8>), Error> {
     let callback = cb.callback.clone();
 
     let fut = Python::with_gil(|py| {
         callback.call1(py, (scope,))?
-            .extract::<(i32, Vec<(String, String)>, Vec<u8>)>(py)
+            .extract::<(String, Vec<(String, String)>, Vec<u8>)>(py)
     });
 
-    match fut {
-        Ok(res) => Ok(res),
-        _ => error_proto!()
-    }
+    fut.map_err(Error::from)
 }
 
 pub(crate) async fn call_rtt_http(
     cb: CallbackWrapper,
     scope: Scope
-) -> PyResult<(i32, Vec<(String, String)>, Vec<u8>)> {
+) -> Result<(String, Vec<(String, String)>, Vec<u8>), Error> {
     let callback = cb.callback.clone();
 
-    let fut: JoinHandle<PyResult<(i32, Vec<(String, String)>, Vec<u8>)>> = tokio::task::spawn_blocking(move || {
+    let fut: JoinHandle<PyResult<(String, Vec<(String, String)>, Vec<u8>)>> = tokio::task::spawn_blocking(move || {
         Python::with_gil(|py| {
             let res = callback.call1(py, (scope,))?.extract(py)?;
             Ok(res)
@@ -39,15 +33,7 @@ pub(crate) async fn call_rtt_http(
     });
 
     match fut.await {
-        Ok(res) => {
-            match res {
-                Ok(res) => Ok(res),
-                _ => error_proto!()
-            }
-        },
-        _ => {
-            log::error!("WSGI protocol failure");
-            error_proto!()
-        }
+        Ok(res) => res.map_err(Error::from),
+        _ => Err(Error::protocol("WSGI protocol failure"))
     }
 }
@@ -2,14 +2,19 @@ use hyper::{
     Body,
     Request,
     Response,
-    header::{SERVER as HK_SERVER, HeaderName, HeaderValue}
+    StatusCode,
+    ext::ReasonPhrase
 };
-use std::net::SocketAddr;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{net::SocketAddr, sync::Arc};
 
 use crate::{
     callbacks::CallbackWrapper,
-    http::{HV_SERVER, response_500},
+    diagnostics::RequestTrace,
+    errors::Error,
+    http::{reason_phrase, response_status},
     runtime::RuntimeRef,
+    workers::WorkerCtx,
 };
 use super::{
     callbacks::{call_rtb_http, call_rtt_http},
@@ -17,34 +22,115 @@ use super::{
 };
 
 
+// WSGI status lines carry a reason phrase along with the code, which gets sent
+// only when different from the canonical one.
+fn status_line(line: &str) -> PyResult<(StatusCode, Option<ReasonPhrase>)> {
+    let (code, reason) = line.split_once(' ').unwrap_or((line, ""));
+    let status = code.parse()
+        .map_err(|_| PyValueError::new_err(format!("Invalid response status {:?}", line)))
+        .and_then(response_status)?;
+    let reason = match reason.trim() {
+        "" => None,
+        reason if Some(reason) == status.canonical_reason() => None,
+        reason => Some(reason_phrase(reason)?)
+    };
+    Ok((status, reason))
+}
+
+
 macro_rules! handle_request {
     ($func_name:ident, $handler:expr) => {
         pub(crate) async fn $func_name(
             _rt: RuntimeRef,
             callback: CallbackWrapper,
+            ctx: Arc<WorkerCtx>,
             server_addr: SocketAddr,
             client_addr: SocketAddr,
             req: Request<Body>,
             scheme: &str
         ) -> Response<Body> {
-            match $handler(
-                callback,
-                Scope::new(scheme, server_addr, client_addr, req).await
-            ).await {
+            if let Some(res) = ctx.header_limits.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.duplicate_headers.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.allowed_hosts.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.path_decoding.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.presigned_urls.check(&req) {
+                return res
+            }
+            if let Some(res) = ctx.metrics_exposition.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.options_responses.respond(&req) {
+                return res
+            }
+            if let Some(res) = ctx.synthetic_responses.respond(&req, client_addr, scheme) {
+                return res
+            }
+            let (req, upload) = match ctx.uploads.handle(req).await {
+                Ok(handled) => handled,
+                Err(res) => return res
+            };
+            let req = match ctx.decompression.decode(req).await {
+                Ok(req) => req,
+                Err(res) => return res
+            };
+            if let Some(res) = ctx.body_limit.check(&req) {
+                return res
+            }
+            let req = ctx.request_filters.apply(req);
+            let (req, body_limit) = ctx.body_limit.apply(req);
+            let compress = ctx.compression.negotiate(&req);
+            let filter = ctx.response_filters.request(&req);
+            let (req, grpc) = ctx.grpc_web.translate(req);
+            let (req, transcode) = match ctx.transcoding.translate(req).await {
+                Ok(translated) => translated,
+                Err(res) => return res
+            };
+            let trace = RequestTrace::of(&req);
+            let span = ctx.tracer.callback(&req);
+            let _watch = ctx.stack_dumps.watch(&req);
+            let scope = Scope::new(scheme, server_addr, client_addr, req, &ctx.duplicate_headers, ctx.path_decoding).await;
+            if let Some(res) = body_limit.check() {
+                return res
+            }
+            let scratch = scope.scratch().guard();
+            trace.callback_started();
+            let ret = $handler(callback, scope).await;
+            trace.callback_ended();
+            span.finish(ret.is_err());
+            match ret {
                 Ok((status, pyheaders, body)) => {
+                    let (status, reason) = match status_line(&status) {
+                        Ok(status) => status,
+                        Err(err) => return Error::from(err).response()
+                    };
+                    let pyheaders = match ctx.header_validation.headers(
+                        pyheaders.iter().map(|(key, val)| (key.as_bytes(), val.as_bytes()))
+                    ) {
+                        Ok(headers) => headers,
+                        Err(err) => return Error::app(err).response()
+                    };
                     let mut res = Response::new(Body::from(body));
-                    *res.status_mut() = hyper::StatusCode::from_u16(status as u16).unwrap();
+                    *res.status_mut() = status;
+                    if let Some(reason) = reason {
+                        res.extensions_mut().insert(reason);
+                    }
                     let headers = res.headers_mut();
-                    headers.insert(HK_SERVER, HV_SERVER);
                     for (key, val) in pyheaders {
-                        headers.insert(
-                            HeaderName::from_bytes(key.as_bytes()).unwrap(),
-                            HeaderValue::from_str(&val).unwrap()
-                        );
+                        headers.insert(key, val);
                     }
-                    res
+                    scratch.attach(ctx.response_headers.apply(compress.respond(
+                        filter.respond(grpc.respond(transcode.respond(upload.respond(res)).await))
+                    )))
                 },
-                _ => response_500()
+                Err(err) => grpc.respond(err.response())
             }
         }
     };
@@ -2,7 +2,7 @@ use pyo3::prelude::*;
 
 mod callbacks;
 mod errors;
-mod http;
+pub(crate) mod http;
 pub(crate) mod serve;
 mod types;
 
@@ -3,6 +3,8 @@ use pyo3::prelude::*;
 use crate::{
     workers::{
         WorkerConfig,
+        WorkerIdentity,
+        WorkerOptions,
         serve_rth,
         serve_wth,
         serve_rth_ssl,
@@ -17,6 +19,10 @@ pub struct WSGIWorker {
 }
 
 impl WSGIWorker {
+    pub(crate) fn from_config(config: WorkerConfig) -> Self {
+        Self { config }
+    }
+
     serve_rth!(_serve_rth, handle_rtb);
     serve_wth!(_serve_wth, handle_rtt);
     serve_rth_ssl!(_serve_rth_ssl, handle_rtb);
@@ -26,35 +32,28 @@ impl WSGIWorker {
 #[pymethods]
 impl WSGIWorker {
     #[new]
-    #[args(socket_fd, threads="1", http1_buffer_max="65535")]
     fn new(
         worker_id: i32,
+        worker_generation: u32,
+        memory_recycles: u64,
         socket_fd: i32,
-        threads: usize,
-        pthreads: usize,
-        http_mode: String,
-        http1_buffer_max: usize,
-        ssl_enabled: bool,
-        ssl_cert: Option<String>,
-        ssl_key: Option<String>
+        options: WorkerOptions
     ) -> PyResult<Self> {
-        Ok(Self {
-            config: WorkerConfig::new(
-                worker_id,
-                socket_fd,
-                threads,
-                pthreads,
-                http_mode,
-                http1_buffer_max,
-                false,
-                ssl_enabled,
-                ssl_cert,
-                ssl_key
-            )
-        })
+        // Options of features needing asynchronous applications keep their defaults
+        let options = WorkerOptions {
+            websockets: false,
+            websocket_origins: Vec::new(),
+            metrics_routes: Vec::new(),
+            handler_cancellation: false,
+            cancellation_exempt: Vec::new(),
+            long_poll_routes: Vec::new(),
+            ..options
+        };
+        let identity = WorkerIdentity { id: worker_id, generation: worker_generation, memory_recycles };
+        Ok(Self { config: WorkerConfig::new(identity, socket_fd, options)? })
     }
 
-    fn serve_rth(
+    pub(crate) fn serve_rth(
         &self,
         callback: PyObject,
         event_loop: &PyAny,
@@ -67,7 +66,7 @@ impl WSGIWorker {
         }
     }
 
-    fn serve_wth(
+    pub(crate) fn serve_wth(
         &self,
         callback: PyObject,
         event_loop: &PyAny,
@@ -3,6 +3,17 @@ use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 use std::{collections::HashMap, net::SocketAddr};
 
+use crate::{
+    deadlines::{Deadline, request_deadline},
+    diagnostics::RequestTrace,
+    http::DuplicateHeaders,
+    scratch::ScratchDir,
+    tcp::addr_repr,
+    urls::{PathDecoding, query_params},
+    utils::header_value_latin1,
+    workers::identity
+};
+
 #[pyclass(module = "granian._granian")]
 pub(crate) struct WSGIScope {
     #[pyo3(get)]
@@ -16,7 +27,10 @@ pub(crate) struct WSGIScope {
     client: String,
     #[pyo3(get)]
     headers: HashMap<String, String>,
-    body: Bytes
+    path_decoding: PathDecoding,
+    body: Bytes,
+    deadline: Option<Deadline>,
+    scratch: ScratchDir
 }
 
 impl WSGIScope {
@@ -25,39 +39,64 @@ impl WSGIScope {
         server: SocketAddr,
         client: SocketAddr,
         request: Request<Body>,
+        duplicates: &DuplicateHeaders,
+        path_decoding: PathDecoding
     ) -> Self {
         let headers = request.headers();
         let mut pyheaders = HashMap::with_capacity(headers.keys_len());
-        for (key, val) in headers.iter() {
+        for key in headers.keys() {
+            let value = match duplicates.joined(headers, key) {
+                Some(joined) => joined,
+                None => header_value_latin1(&headers[key]).into_owned()
+            };
             pyheaders.insert(
                 format!("HTTP_{}", key.as_str().replace("-", "_").to_uppercase()),
-                val.to_str().unwrap().into(),
+                value
             );
         }
 
         let method = request.method().to_string();
         let uri = request.uri().clone();
+        let deadline = request_deadline(&request);
 
-        let body = hyper::body::to_bytes(request)
-            .await
-            .unwrap_or(bytes::Bytes::new());
+        let trace = RequestTrace::of(&request);
+        let body = match hyper::body::to_bytes(request).await {
+            Ok(body) => body,
+            _ => {
+                trace.client_disconnected();
+                bytes::Bytes::new()
+            }
+        };
 
         Self {
             scheme: scheme.to_string(),
             method,
             uri,
-            server: server.to_string(),
-            client: client.to_string(),
+            server: addr_repr(server),
+            client: addr_repr(client),
             headers: pyheaders,
-            body
+            path_decoding,
+            body,
+            deadline,
+            scratch: ScratchDir::default()
         }
     }
+
+    pub fn scratch(&self) -> ScratchDir {
+        self.scratch.clone()
+    }
 }
 
 #[pymethods]
 impl WSGIScope {
+    // Following PEP 3333, decoded bytes are exposed as latin-1 characters
     #[getter(path)]
-    fn get_path(&self) -> &str {
+    fn get_path(&self) -> String {
+        self.path_decoding.path_bytes(self.uri.path()).iter().map(|&byte| byte as char).collect()
+    }
+
+    #[getter(raw_path)]
+    fn get_raw_path(&self) -> &str {
         self.uri.path()
     }
 
@@ -66,8 +105,30 @@ impl WSGIScope {
         self.uri.query().unwrap_or("")
     }
 
+    #[getter(worker_id)]
+    fn get_worker_id(&self) -> i32 {
+        identity().id
+    }
+
+    #[getter(worker_generation)]
+    fn get_worker_generation(&self) -> u32 {
+        identity().generation
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        query_params(self.uri.query().unwrap_or(""))
+    }
+
     #[getter(body)]
     fn get_body<'p>(&self, py: Python<'p>) -> &'p PyBytes {
         PyBytes::new(py, &self.body.to_vec()[..])
     }
+
+    fn scratch_dir(&self) -> PyResult<String> {
+        Ok(self.scratch.path()?.to_string_lossy().into_owned())
+    }
+
+    fn deadline_remaining(&self) -> Option<f64> {
+        self.deadline.map(|deadline| deadline.remaining())
+    }
 }
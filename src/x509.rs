@@ -0,0 +1,174 @@
+use std::fmt::Write;
+
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_T61_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UNIVERSAL_STRING: u8 = 0x1c;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_VERSION: u8 = 0xa0;
+
+// Short names of the attribute types listed by RFC 4514, by their encoded OID
+const ATTRIBUTE_TYPES: &[(&[u8], &str)] = &[
+    (&[0x55, 0x04, 0x03], "CN"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x09], "STREET"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+    (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01], "UID"),
+    (&[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19], "DC")
+];
+
+// A DER element, along with the encoding of the whole element
+struct Element<'a> {
+    tag: u8,
+    content: &'a [u8],
+    encoded: &'a [u8]
+}
+
+fn read_element<'a>(data: &mut &'a [u8]) -> Option<Element<'a>> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = match first {
+        0..=0x7f => first as usize,
+        0x81..=0x84 => {
+            let size = (first & 0x7f) as usize;
+            if rest.len() < size {
+                return None
+            }
+            let (len, tail) = rest.split_at(size);
+            rest = tail;
+            len.iter().fold(0, |acc, byte| (acc << 8) | *byte as usize)
+        },
+        _ => return None
+    };
+    if rest.len() < len {
+        return None
+    }
+    let header_len = data.len() - rest.len();
+    let element = Element { tag, content: &rest[..len], encoded: &data[..header_len + len] };
+    *data = &rest[len..];
+    Some(element)
+}
+
+fn expect_element<'a>(data: &mut &'a [u8], tag: u8) -> Option<Element<'a>> {
+    read_element(data).filter(|element| element.tag == tag)
+}
+
+fn oid_repr(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for byte in oid {
+        arc = (arc << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - first * 40);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    arcs.iter().map(|arc| arc.to_string()).collect::<Vec<String>>().join(".")
+}
+
+fn string_value(element: &Element) -> Option<String> {
+    match element.tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => {
+            std::str::from_utf8(element.content).ok().map(|value| value.to_string())
+        },
+        TAG_T61_STRING => Some(element.content.iter().map(|byte| *byte as char).collect()),
+        TAG_BMP_STRING => char::decode_utf16(
+            element.content.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        ).collect::<Result<String, _>>().ok(),
+        TAG_UNIVERSAL_STRING => element.content.chunks_exact(4)
+            .map(|quad| char::from_u32(u32::from_be_bytes([quad[0], quad[1], quad[2], quad[3]])))
+            .collect(),
+        _ => None
+    }
+}
+
+fn escape_value(value: &str, out: &mut String) {
+    let last = value.chars().count().saturating_sub(1);
+    for (idx, ch) in value.chars().enumerate() {
+        match ch {
+            '\0' => {
+                out.push_str("\\00");
+                continue
+            },
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' => out.push('\\'),
+            ' ' if idx == 0 || idx == last => out.push('\\'),
+            '#' if idx == 0 => out.push('\\'),
+            _ => {}
+        }
+        out.push(ch);
+    }
+}
+
+// Appends `type=value`, with values of unknown types or string kinds written as
+// the hex dump of their encoding, as RFC 4514 prescribes
+fn write_attribute(oid: &[u8], value: &Element, out: &mut String) {
+    let name = ATTRIBUTE_TYPES.iter().find(|(known, _)| *known == oid).map(|(_, name)| *name);
+    match (name, string_value(value)) {
+        (Some(name), Some(value)) => {
+            out.push_str(name);
+            out.push('=');
+            escape_value(&value, out);
+        },
+        (name, _) => {
+            out.push_str(&name.map(|name| name.to_string()).unwrap_or_else(|| oid_repr(oid)));
+            out.push_str("=#");
+            for byte in value.encoded {
+                let _ = write!(out, "{:02x}", byte);
+            }
+        }
+    }
+}
+
+// The distinguished name of a `Name` content, in the RFC 4514 string form: the
+// relative names go in reverse order, multi-valued ones joined by `+`.
+fn name_repr(mut data: &[u8]) -> Option<String> {
+    let mut rdns = Vec::new();
+    while !data.is_empty() {
+        let set = expect_element(&mut data, TAG_SET)?;
+        let mut attributes = set.content;
+        let mut rdn = String::new();
+        while !attributes.is_empty() {
+            let attribute = expect_element(&mut attributes, TAG_SEQUENCE)?;
+            let mut fields = attribute.content;
+            let oid = expect_element(&mut fields, TAG_OID)?;
+            let value = read_element(&mut fields)?;
+            if !rdn.is_empty() {
+                rdn.push('+');
+            }
+            write_attribute(oid.content, &value, &mut rdn);
+        }
+        rdns.push(rdn);
+    }
+    rdns.reverse();
+    Some(rdns.join(","))
+}
+
+// The subject of a DER encoded X.509 certificate, `None` when it can't be parsed
+pub(crate) fn certificate_subject(der: &[u8]) -> Option<String> {
+    let mut data = der;
+    let mut certificate = expect_element(&mut data, TAG_SEQUENCE)?.content;
+    let mut tbs = expect_element(&mut certificate, TAG_SEQUENCE)?.content;
+    // the optional version, serial number, signature algorithm, issuer and
+    // validity precede the subject
+    if read_element(&mut tbs)?.tag == TAG_VERSION {
+        read_element(&mut tbs)?;
+    }
+    expect_element(&mut tbs, TAG_SEQUENCE)?;
+    expect_element(&mut tbs, TAG_SEQUENCE)?;
+    expect_element(&mut tbs, TAG_SEQUENCE)?;
+    name_repr(expect_element(&mut tbs, TAG_SEQUENCE)?.content)
+}
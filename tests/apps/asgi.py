@@ -1,4 +1,5 @@
 import json
+import pathlib
 
 PLAINTEXT_RESPONSE = {
     'type': 'http.response.start',
@@ -31,7 +32,11 @@ async def info(scope, receive, send):
             'headers': {
                 k.decode("utf8"): v.decode("utf8")
                 for k, v in scope['headers']
-            }
+            },
+            'worker': [
+                scope['extensions']['granian.worker']['id'],
+                scope['extensions']['granian.worker']['generation']
+            ]
         }).encode("utf8"),
         'more_body': False
     })
@@ -47,6 +52,18 @@ async def echo(scope, receive, send):
     })
 
 
+async def file(scope, receive, send):
+    await send({
+        'type': 'http.response.start',
+        'status': 200,
+        'headers': [[b'content-type', b'text/plain']]
+    })
+    await send({
+        'type': 'http.response.pathsend',
+        'path': str(pathlib.Path(__file__).parent.parent / "fixtures" / "file.txt")
+    })
+
+
 async def ws_reject(scope, receive, send):
     return
 
@@ -86,6 +103,19 @@ async def ws_echo(scope, receive, send):
         await send(rv)
 
 
+async def ws_headers(scope, receive, send):
+    await send({
+        'type': 'websocket.accept',
+        'headers': [
+            (b'x-granian-test', b'ws'),
+            (b'set-cookie', b'a=1'),
+            (b'set-cookie', b'b=2')
+        ]
+    })
+    await send({'type': 'websocket.send', 'text': 'ok'})
+    await send({'type': 'websocket.close'})
+
+
 async def ws_push(scope, receive, send):
     await send({'type': 'websocket.accept'})
 
@@ -111,9 +141,11 @@ def app(scope, receive, send):
     return {
         "/info": info,
         "/echo": echo,
+        "/file": file,
         "/ws_reject": ws_reject,
         "/ws_info": ws_info,
         "/ws_echo": ws_echo,
+        "/ws_headers": ws_headers,
         "/ws_push": ws_push,
         "/err_app": err_app,
         "/err_proto": err_proto
@@ -1,4 +1,5 @@
 import json
+import pathlib
 
 from granian.rsgi import (
     HTTPProtocol,
@@ -20,7 +21,9 @@ async def info(scope: Scope, protocol: HTTPProtocol):
             'method': scope.method,
             'path': scope.path,
             'query_string': scope.query_string,
-            'headers': {k: v for k, v in scope.headers.items()}
+            'content_length': scope.content_length,
+            'headers': {k: v for k, v in scope.headers.items()},
+            'worker': [scope.worker_id, scope.worker_generation]
         }).encode("utf8")
     )
 
@@ -34,6 +37,58 @@ async def echo(_, protocol: HTTPProtocol):
     )
 
 
+async def echo_chunks(_, protocol: HTTPProtocol):
+    msg = bytearray()
+    async for chunk in protocol:
+        with memoryview(chunk) as view:
+            msg.extend(view)
+        chunk.release()
+    protocol.response_bytes(
+        200,
+        [('content-type', 'text/plain; charset=utf-8')],
+        bytes(msg)
+    )
+
+
+async def echo_receive(_, protocol: HTTPProtocol):
+    msg = bytearray()
+    while chunk := await protocol.receive():
+        msg.extend(memoryview(chunk))
+    protocol.response_bytes(
+        200,
+        [('content-type', 'text/plain; charset=utf-8')],
+        bytes(msg)
+    )
+
+
+async def file(_, protocol: HTTPProtocol):
+    protocol.response_file(
+        200,
+        [('content-type', 'text/plain')],
+        str(pathlib.Path(__file__).parent.parent / "fixtures" / "file.txt")
+    )
+
+
+async def stream(_, protocol: HTTPProtocol):
+    trx = protocol.response_stream(200, [('content-type', 'text/plain')])
+    for idx in range(3):
+        await trx.send_str(f"chunk {idx}\n")
+    await trx.send_bytes(b"end")
+
+
+async def stream_err(_, protocol: HTTPProtocol):
+    trx = protocol.response_stream(200, [('content-type', 'text/plain')])
+    await trx.send_bytes(b"partial")
+    1 / 0
+
+
+async def upgrade(_, protocol: HTTPProtocol):
+    trx = await protocol.upgrade([('x-granian-test', 'raw')])
+    data = await trx.read()
+    await trx.write(data)
+    await trx.close()
+
+
 async def ws_reject(_, protocol: WebsocketProtocol):
     protocol.close(403)
 
@@ -74,6 +129,37 @@ async def ws_echo(_, protocol: WebsocketProtocol):
     protocol.close()
 
 
+async def ws_headers(_, protocol: WebsocketProtocol):
+    trx = await protocol.accept(headers=[
+        ('x-granian-test', 'ws'),
+        ('set-cookie', 'a=1'),
+        ('set-cookie', 'b=2')
+    ])
+    await trx.send_str("ok")
+    while True:
+        message = await trx.receive()
+        if message.kind == WebsocketMessageType.close:
+            break
+
+    protocol.close()
+
+
+async def ws_fragments(_, protocol: WebsocketProtocol):
+    trx = await protocol.accept()
+
+    await trx.send_str_fragment("foo")
+    await trx.send_str_fragment("bar")
+    await trx.send_str_fragment("baz", last=True)
+    await trx.send_bytes_fragment(b"foo")
+    await trx.send_bytes_fragment(b"bar", last=True)
+    while True:
+        message = await trx.receive()
+        if message.kind == WebsocketMessageType.close:
+            break
+
+    protocol.close()
+
+
 async def ws_push(_, protocol: WebsocketProtocol):
     trx = await protocol.accept()
 
@@ -94,9 +180,25 @@ def app(scope, protocol):
     return {
         "/info": info,
         "/echo": echo,
+        "/echo_chunks": echo_chunks,
+        "/echo_receive": echo_receive,
+        "/file": file,
+        "/stream": stream,
+        "/stream_err": stream_err,
+        "/upgrade": upgrade,
         "/ws_reject": ws_reject,
         "/ws_info": ws_info,
         "/ws_echo": ws_echo,
+        "/ws_headers": ws_headers,
+        "/ws_fragments": ws_fragments,
         "/ws_push": ws_push,
         "/err_app": err_app
     }[scope.path](scope, protocol)
+
+
+def fast(scope):
+    if scope.path == "/fast":
+        return 200, [("content-type", "text/plain")], scope.method
+
+
+app.__rsgi_fast__ = fast
@@ -12,7 +12,8 @@ def info(environ, protocol):
         'path': environ["PATH_INFO"],
         'query_string': environ["QUERY_STRING"],
         'content_length': environ['CONTENT_LENGTH'],
-        'headers': {k: v for k, v in environ.items() if k.startswith("HTTP_")}
+        'headers': {k: v for k, v in environ.items() if k.startswith("HTTP_")},
+        'worker': [environ['granian.worker_id'], environ['granian.worker_generation']]
     }).encode("utf8")]
 
 
@@ -0,0 +1,57 @@
+// Embedding needs libpython linked: run with `cargo test --no-default-features`
+#![cfg(not(feature = "extension-module"))]
+
+use _granian::{errors::ErrorKind, server::{Interface, Server}};
+use pyo3::{prelude::*, types::PyModule};
+use std::{io::{Read, Write}, net::TcpStream, thread};
+
+
+const APP: &str = r#"
+async def app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], f"embedded {scope.method} {scope.path}")
+"#;
+
+fn request(addr: std::net::SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /info HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+// A single test, as the interpreter binds signal handling to the thread initializing it
+#[test]
+fn embedded_server() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| -> PyResult<()> {
+        py.import("sys")?.getattr("path")?.call_method1("insert", (0, env!("CARGO_MANIFEST_DIR")))?;
+
+        let err = Server::builder().build().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::App);
+
+        let app: PyObject = PyModule::from_code(py, APP, "embedded.py", "embedded")?.getattr("app")?.into();
+        let server = Server::builder()
+            .bind(([127, 0, 0, 1], 0))
+            .interface(Interface::Rsgi)
+            .app(app.clone_ref(py))
+            .build()
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let err = Server::builder().bind(addr).app(app).build().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Bind);
+
+        let client = thread::spawn(move || {
+            let response = request(addr);
+            unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+            response
+        });
+        server.serve(py)?;
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nembedded GET /info"));
+        Ok(())
+    }).unwrap();
+}
@@ -0,0 +1,87 @@
+import asyncio
+import logging
+import struct
+
+import pytest
+
+from granian._granian import metrics, reset_log_levels
+from granian.testing import TestServer
+
+
+PREFACE = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"
+# GET / on stream 1, with the request headers HPACK encoded by hand
+REQUEST_HEADERS = b"\x82\x86\x84\x41\x09localhost"
+
+
+async def rsgi_app(scope, proto):
+    transport = proto.response_stream(200, [("content-type", "text/plain")])
+    await transport.send_bytes(b"partial")
+    await asyncio.sleep(0.1)
+    raise RuntimeError("failed while streaming")
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"text/plain")]})
+    await send({"type": "http.response.body", "body": b"partial", "more_body": True})
+    await asyncio.sleep(0.1)
+    raise RuntimeError("failed while streaming")
+
+
+INTERFACES = [("rsgi", rsgi_app), ("asgi", asgi_app)]
+
+
+def _aborted():
+    line = next(line for line in metrics().splitlines() if line.startswith("granian_responses_aborted_total"))
+    return int(line.split()[1])
+
+
+def _frame(kind, flags, stream, payload=b""):
+    return struct.pack(">I", len(payload))[1:] + bytes([kind, flags]) + struct.pack(">I", stream) + payload
+
+
+async def _read_frame(reader):
+    head = await asyncio.wait_for(reader.readexactly(9), 2)
+    size = struct.unpack(">I", b"\x00" + head[:3])[0]
+    return head[3], head[4], struct.unpack(">I", head[5:])[0] & 0x7FFFFFFF, await reader.readexactly(size)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_abort_http1(interface, app, caplog):
+    caplog.set_level(logging.WARNING, logger="_granian")
+    reset_log_levels()
+    aborted = _aborted()
+    async with TestServer(app, interface) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        response = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+
+    head, body = response.split(b"\r\n\r\n", 1)
+    assert b"transfer-encoding: chunked" in head.lower()
+    assert body == b"7\r\npartial\r\n"
+    assert _aborted() == aborted + 1
+    assert any(record.getMessage().startswith("Response aborted (") for record in caplog.records)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_abort_http2(interface, app):
+    async with TestServer(app, interface) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(PREFACE + _frame(0x4, 0, 0) + _frame(0x1, 0x5, 1, REQUEST_HEADERS))
+        body, reset = b"", None
+        while reset is None:
+            kind, flags, stream, payload = await _read_frame(reader)
+            if kind == 0x4 and not flags & 0x1:
+                writer.write(_frame(0x4, 0x1, 0))
+            elif kind == 0x0 and stream == 1:
+                assert not flags & 0x1
+                body += payload
+            elif kind == 0x3 and stream == 1:
+                reset = struct.unpack(">I", payload)[0]
+        writer.close()
+
+    assert body == b"partial"
+    # INTERNAL_ERROR
+    assert reset == 0x2
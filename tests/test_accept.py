@@ -0,0 +1,38 @@
+import asyncio
+import os
+
+import pytest
+
+from granian._granian import metrics
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_empty(204, [])
+
+
+def test_accept_metrics():
+    rendered = metrics()
+    assert 'granian_accept_errors_total{worker="0"} 0' in rendered
+    assert 'granian_connections_shed_total{worker="0"} 0' in rendered
+
+
+@pytest.mark.skipif(not os.path.exists("/proc/net/netstat"), reason="no kernel TCP statistics available")
+def test_listen_drops_metrics():
+    rendered = metrics()
+    assert 'granian_listen_overflows_total{worker="0"} ' in rendered
+    assert 'granian_listen_drops_total{worker="0"} ' in rendered
+
+
+@pytest.mark.asyncio
+@pytest.mark.skipif(not os.path.exists("/proc/net/netstat"), reason="no kernel TCP statistics available")
+async def test_listen_queue_metrics():
+    async with TestServer(rsgi_app, "rsgi") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+        rendered = metrics()
+
+    assert 'granian_listen_queue_length{worker="0"} 0' in rendered
+    assert 'granian_listen_queue_limit{worker="0"} 128' in rendered
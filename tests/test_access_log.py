@@ -0,0 +1,166 @@
+import asyncio
+import json
+import logging
+import re
+
+import pytest
+import websockets
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    if scope.proto == "ws":
+        trx = await proto.accept()
+        message = await trx.receive()
+        await trx.send_str(message.data)
+        await trx.receive()
+        return
+    proto.response_str(200, [("content-type", "text/plain")], "hello")
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 201, "headers": [(b"content-type", b"text/plain")]})
+    await send({"type": "http.response.body", "body": b"hi"})
+
+
+async def _request(server, target, headers=()):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(f"GET {target} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n{head}\r\n".encode())
+    await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+
+
+async def _lines(path, count=1):
+    for _ in range(100):
+        lines = path.read_text().splitlines() if path.exists() else []
+        if len(lines) >= count:
+            return lines
+        await asyncio.sleep(0.02)
+    raise AssertionError("access log lines not written")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app", "status", "size"], [("rsgi", rsgi_app, 200, 5), ("asgi", asgi_app, 201, 2)])
+async def test_common_format(tmp_path, interface, app, status, size):
+    path = tmp_path / "access.log"
+    async with TestServer(app, interface, access_log=str(path)) as server:
+        await _request(server, "/some/path?q=1")
+        lines = await _lines(path)
+
+    assert re.fullmatch(
+        rf'127\.0\.0\.1 - - \[\d{{2}}/\w{{3}}/\d{{4}}:\d{{2}}:\d{{2}}:\d{{2}} \+0000\] '
+        rf'"GET /some/path\?q=1 HTTP/1\.1" {status} {size}',
+        lines[0]
+    )
+
+
+@pytest.mark.asyncio
+async def test_combined_format(tmp_path):
+    path = tmp_path / "access.log"
+    async with TestServer(rsgi_app, "rsgi", access_log=str(path), access_log_format="combined") as server:
+        await _request(server, "/", [("user-agent", 'agent "quoted"')])
+        lines = await _lines(path)
+
+    assert lines[0].endswith(' "GET / HTTP/1.1" 200 5 "-" "agent \\x22quoted\\x22"')
+
+
+@pytest.mark.asyncio
+async def test_custom_format(tmp_path):
+    path = tmp_path / "access.log"
+    fmt = "%m %U %q %H %s %{x-request-id}i %{content-type}o %D 100%%"
+    async with TestServer(
+        rsgi_app, "rsgi", access_log=str(path), access_log_format=fmt,
+        forwarded_trusted=["127.0.0.1"]
+    ) as server:
+        await _request(server, "/a?b=c", [("x-request-id", "abc"), ("x-forwarded-for", "192.0.2.10")])
+        await _request(server, "/d")
+        lines = await _lines(path, 2)
+
+    assert re.fullmatch(r"GET /a \?b=c HTTP/1\.1 200 abc text/plain \d+ 100%", lines[0])
+    assert re.fullmatch(r"GET /d  HTTP/1\.1 200 - text/plain \d+ 100%", lines[1])
+
+
+@pytest.mark.asyncio
+async def test_json_format(tmp_path):
+    path = tmp_path / "access.log"
+    async with TestServer(rsgi_app, "rsgi", access_log=str(path), access_log_format="json") as server:
+        await _request(server, "/a?b=c", [("user-agent", 'agent "quoted"')])
+        lines = await _lines(path)
+
+    record = json.loads(lines[0])
+    assert re.fullmatch(r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d{3}Z", record.pop("time"))
+    assert record.pop("latency") >= 0
+    assert record == {
+        "event": "request",
+        "worker": 0,
+        "generation": 0,
+        "client": "127.0.0.1",
+        "method": "GET",
+        "path": "/a",
+        "query": "b=c",
+        "protocol": "HTTP/1.1",
+        "status": 200,
+        "bytes": 5,
+        "referer": None,
+        "user_agent": 'agent \\x22quoted\\x22'
+    }
+
+
+@pytest.mark.asyncio
+async def test_json_format_websocket(tmp_path):
+    path = tmp_path / "access.log"
+    async with TestServer(rsgi_app, "rsgi", access_log=str(path), access_log_format="json") as server:
+        async with websockets.connect(f"{server.ws_url}/ws") as ws:
+            await ws.send("hello")
+            await ws.recv()
+        lines = await _lines(path, 2)
+
+    records = {record["event"]: record for record in map(json.loads, lines)}
+    assert records["request"]["status"] == 101
+    session = records["websocket"]
+    assert session["path"] == "/ws"
+    assert session["close_code"] == 1000
+    assert (session["frames_in"], session["frames_out"]) == (1, 1)
+    assert (session["bytes_in"], session["bytes_out"]) == (5, 5)
+    assert session["duration"] >= 0
+
+
+@pytest.mark.asyncio
+async def test_forwarded_client(tmp_path):
+    path = tmp_path / "access.log"
+    async with TestServer(
+        rsgi_app, "rsgi", access_log=str(path), access_log_format="%h", forwarded_trusted=["127.0.0.1"]
+    ) as server:
+        await _request(server, "/", [("x-forwarded-for", "192.0.2.10")])
+        lines = await _lines(path)
+
+    assert lines == ["192.0.2.10"]
+
+
+@pytest.mark.asyncio
+async def test_python_logger():
+    records = []
+    handler = logging.Handler()
+    handler.emit = lambda record: records.append(record.getMessage())
+    logger = logging.getLogger("granian.access")
+    logger.addHandler(handler)
+    logger.setLevel(logging.INFO)
+    try:
+        async with TestServer(rsgi_app, "rsgi", access_log="python", access_log_format="%m %U %s") as server:
+            await _request(server, "/logged")
+            for _ in range(100):
+                if records:
+                    break
+                await asyncio.sleep(0.02)
+    finally:
+        logger.removeHandler(handler)
+
+    assert records == ["GET /logged 200"]
+
+
+@pytest.mark.parametrize("fmt", ["%Z", "%{x-test}", "trailing %", "%{bad header}i"])
+def test_invalid_format(fmt):
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", access_log_format=fmt)
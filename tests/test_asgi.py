@@ -1,3 +1,5 @@
+import asyncio
+
 import httpx
 import pytest
 
@@ -29,6 +31,7 @@ async def test_scope(asgi_server, threading_mode):
     assert data['path'] == '/info'
     assert data['query_string'] == 'test=true'
     assert data['headers']['host'] == f'localhost:{port}'
+    assert data['worker'] == [1, 1]
 
 
 @pytest.mark.asyncio
@@ -47,6 +50,46 @@ async def test_body(asgi_server, threading_mode):
     assert res.text == "test"
 
 
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_body_chunked(asgi_server, threading_mode):
+    async with asgi_server(threading_mode) as port:
+        res = httpx.post(f"http://localhost:{port}/echo", content=iter([b"te", b"st"]))
+
+    assert res.status_code == 200
+    assert res.text == "test"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_file_range(asgi_server, threading_mode):
+    async with asgi_server(threading_mode) as port:
+        full = httpx.get(f"http://localhost:{port}/file")
+        part = httpx.get(f"http://localhost:{port}/file", headers={"range": "bytes=5-9"})
+        invalid = httpx.get(f"http://localhost:{port}/file", headers={"range": "bytes=50-"})
+
+    assert full.status_code == 200
+    assert full.headers["content-type"] == "text/plain"
+    assert full.headers["accept-ranges"] == "bytes"
+    assert full.text == "0123456789abcdefghij"
+    assert part.status_code == 206
+    assert part.headers["content-range"] == "bytes 5-9/20"
+    assert part.text == "56789"
+    assert invalid.status_code == 416
+
+
 @pytest.mark.asyncio
 @pytest.mark.parametrize(
     "threading_mode",
@@ -75,3 +118,30 @@ async def test_protocol_error(asgi_server, threading_mode):
         res = httpx.get(f"http://localhost:{port}/err_proto")
 
     assert res.status_code == 500
+
+
+@pytest.mark.asyncio
+async def test_stream_backpressure():
+    from granian.testing import TestServer
+
+    chunk, sent = b"x" * 65536, []
+
+    async def app(scope, receive, send):
+        await send({"type": "http.response.start", "status": 200, "headers": []})
+        for _ in range(1000):
+            await send({"type": "http.response.body", "body": chunk, "more_body": True})
+            sent.append(1)
+        await send({"type": "http.response.body", "body": b""})
+
+    async with TestServer(app, "asgi") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        await reader.readuntil(b"\r\n\r\n")
+        await asyncio.sleep(0.5)
+        stalled = len(sent)
+        body = await asyncio.wait_for(reader.read(), 10)
+        writer.close()
+
+    assert stalled < 1000
+    assert len(sent) == 1000
+    assert body.count(b"x") == 1000 * 65536
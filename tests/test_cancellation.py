@@ -0,0 +1,82 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestServer
+
+
+events = []
+
+
+async def rsgi_app(scope, proto):
+    try:
+        await asyncio.sleep(2)
+    except asyncio.CancelledError:
+        events.append(f"cancelled {scope.path}")
+        raise
+    events.append(f"completed {scope.path}")
+    proto.response_bytes(200, [("content-type", "text/plain")], b"done")
+
+
+async def _abandon(server, path, hold=0.1):
+    _, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(f"GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n".encode())
+    await writer.drain()
+    await asyncio.sleep(hold)
+    writer.close()
+
+
+async def _wait_event(timeout):
+    loop = asyncio.get_running_loop()
+    deadline = loop.time() + timeout
+    while not events and loop.time() < deadline:
+        await asyncio.sleep(0.05)
+    return events[0] if events else None
+
+
+@pytest.mark.asyncio
+async def test_cancellation_disabled():
+    events.clear()
+    server = await TestServer(rsgi_app, "rsgi").start()
+    await _abandon(server, "/slow")
+    event = await _wait_event(3)
+    await server.shutdown()
+
+    assert event == "completed /slow"
+
+
+@pytest.mark.asyncio
+async def test_cancellation_on_disconnect():
+    events.clear()
+    server = await TestServer(rsgi_app, "rsgi", handler_cancellation=True).start()
+    await _abandon(server, "/slow")
+    event = await _wait_event(1)
+    await server.shutdown()
+
+    assert event == "cancelled /slow"
+
+
+@pytest.mark.asyncio
+async def test_cancellation_grace():
+    events.clear()
+    server = await TestServer(rsgi_app, "rsgi", handler_cancellation=True, cancellation_grace=0.6).start()
+    await _abandon(server, "/slow")
+    early = await _wait_event(0.3)
+    event = await _wait_event(1)
+    await server.shutdown()
+
+    assert early is None
+    assert event == "cancelled /slow"
+
+
+@pytest.mark.asyncio
+async def test_cancellation_exempt():
+    events.clear()
+    server = await TestServer(
+        rsgi_app, "rsgi", handler_cancellation=True, cancellation_exempt=["/payments/{id}"]
+    ).start()
+    await _abandon(server, "/payments/1")
+    event = await _wait_event(3)
+    await server.shutdown()
+
+    assert event == "completed /payments/1"
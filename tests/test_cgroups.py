@@ -0,0 +1,51 @@
+import os
+
+import pytest
+
+from granian._granian import cpu_quota, metrics
+from granian.server import Granian
+
+
+def _cpu_stat():
+    for path in ("/sys/fs/cgroup/cpu.stat", "/sys/fs/cgroup/cpu/cpu.stat", "/sys/fs/cgroup/cpu,cpuacct/cpu.stat"):
+        if os.path.exists(path):
+            with open(path) as f:
+                if "nr_periods" in f.read():
+                    return path
+    return None
+
+
+def _quota_warnings(capsys):
+    return [line for line in capsys.readouterr().out.splitlines() if "CPU quota" in line]
+
+
+def test_cpu_quota():
+    quota = cpu_quota()
+    assert quota is None or quota > 0
+
+
+@pytest.mark.skipif(_cpu_stat() is None, reason="no cgroup CPU statistics available")
+def test_throttling_metrics():
+    rendered = metrics()
+    assert 'granian_cgroup_cpu_periods_total{worker="0"} ' in rendered
+    assert 'granian_cgroup_cpu_throttled_periods_total{worker="0"} ' in rendered
+    assert 'granian_cgroup_cpu_throttled_seconds_total{worker="0"} ' in rendered
+
+
+def test_quota_warning(monkeypatch, capsys):
+    monkeypatch.setattr("granian.server.cpu_quota", lambda: 1.5)
+    Granian("tests.apps.asgi:app", workers=2, threads=2)._check_cpu_quota()
+
+    assert _quota_warnings(capsys) == [
+        "[WARNING] Running 2 workers with 2 threads each (4 threads) exceeds the cgroup CPU quota of 1.5 CPUs, "
+        "expect CPU throttling"
+    ]
+
+
+def test_quota_no_warning(monkeypatch, capsys):
+    monkeypatch.setattr("granian.server.cpu_quota", lambda: 2.5)
+    Granian("tests.apps.asgi:app", workers=3, threads=1)._check_cpu_quota()
+    monkeypatch.setattr("granian.server.cpu_quota", lambda: None)
+    Granian("tests.apps.asgi:app", workers=64, threads=1)._check_cpu_quota()
+
+    assert _quota_warnings(capsys) == []
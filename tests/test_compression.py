@@ -0,0 +1,186 @@
+import asyncio
+import gzip
+import random
+import zlib
+
+import pytest
+
+from granian.testing import TestServer
+
+
+TEXT = b"".join(b"line %d of a compressible text body\n" % (idx % 50) for idx in range(2000))
+FAR = random.Random(7).randbytes(30000)
+BODIES = {
+    "text": TEXT,
+    "small": b"tiny",
+    "random": random.Random(42).randbytes(200000),
+    "mixed": b"".join(random.Random(idx).randbytes(100) + TEXT[:400] for idx in range(1000)),
+    # runs longer than the longest match, and matches as far as the window goes
+    "runs": b"".join(bytes([idx]) * (idx * 37 % 1000 + 1) for idx in range(256)) * 4,
+    "far": FAR + TEXT[:2000] + FAR + random.Random(8).randbytes(3000) + FAR,
+    "bytes": bytes(range(256)) * 1000,
+    "sparse": b"".join(b"\0" * random.Random(idx).randrange(2000) + b"%d" % idx for idx in range(500))
+}
+
+
+async def rsgi_app(scope, proto):
+    body = BODIES[scope.headers.get("x-kind") or "text"]
+    content_type = scope.headers.get("x-type") or "text/plain; charset=utf-8"
+    proto.response_bytes(200, [("content-type", content_type), ("etag", '"abc"')], body)
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"text/event-stream")]})
+    for idx in range(3):
+        await send({"type": "http.response.body", "body": b"data: %d\n\n" % idx, "more_body": True})
+        await asyncio.sleep(0.01)
+    await send({"type": "http.response.body", "body": b""})
+
+
+def _dechunk(data):
+    ret = b""
+    while data:
+        size, data = data.split(b"\r\n", 1)
+        size = int(size, 16)
+        if not size:
+            break
+        ret, data = ret + data[:size], data[size + 2:]
+    return ret
+
+
+async def asgi_chunks_app(scope, receive, send):
+    body = BODIES[dict(scope["headers"])[b"x-kind"].decode()]
+    rng = random.Random(len(body))
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"text/plain")]})
+    while body:
+        size = rng.choice([1, 7, 100, 4000, 70000])
+        await send({"type": "http.response.body", "body": body[:size], "more_body": True})
+        body = body[size:]
+    await send({"type": "http.response.body", "body": b""})
+
+
+async def _request(headers=(), app=rsgi_app, interface="rsgi", **kwargs):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    async with TestServer(app, interface, **kwargs) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(f"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n{head}\r\n".encode())
+        response = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    res_headers = {}
+    for line in lines[1:]:
+        key, value = line.split(": ", 1)
+        key = key.lower()
+        res_headers[key] = f"{res_headers[key]}, {value}" if key in res_headers else value
+    if res_headers.get("transfer-encoding") == "chunked":
+        body = _dechunk(body)
+    return res_headers, body
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("kind", ["text", "random", "mixed"])
+async def test_gzip(kind):
+    headers, body = await _request([("accept-encoding", "gzip, deflate"), ("x-kind", kind)], compression=True)
+    _, expected = await _request([("x-kind", kind)])
+
+    assert headers["content-encoding"] == "gzip"
+    assert headers["vary"] == "accept-encoding"
+    assert "content-length" not in headers
+    assert headers["etag"] == 'W/"abc"'
+    assert gzip.decompress(body) == expected
+    if kind == "text":
+        assert len(body) < len(expected) / 10
+
+
+@pytest.mark.asyncio
+async def test_deflate():
+    headers, body = await _request([("accept-encoding", "gzip;q=0.5, deflate")], compression=True)
+
+    assert headers["content-encoding"] == "deflate"
+    assert zlib.decompress(body) == TEXT
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["encoding", "decompress"], [("gzip", gzip.decompress), ("deflate", zlib.decompress)])
+@pytest.mark.parametrize("kind", ["text", "random", "mixed", "runs", "far", "bytes", "sparse"])
+async def test_roundtrip(encoding, decompress, kind):
+    headers, body = await _request([("accept-encoding", encoding), ("x-kind", kind)], compression=True)
+
+    assert headers["content-encoding"] == encoding
+    assert decompress(body) == BODIES[kind]
+    # incompressible data gets stored, rather than growing with the codes
+    assert len(body) < len(BODIES[kind]) * 1.01 + 100
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["encoding", "decompress"], [("gzip", gzip.decompress), ("deflate", zlib.decompress)])
+@pytest.mark.parametrize("kind", ["text", "random", "far", "sparse"])
+async def test_roundtrip_streamed(encoding, decompress, kind):
+    headers, body = await _request(
+        [("accept-encoding", encoding), ("x-kind", kind)], app=asgi_chunks_app, interface="asgi", compression=True
+    )
+
+    assert headers["content-encoding"] == encoding
+    assert decompress(body) == BODIES[kind]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("accept", ["identity", "gzip;q=0, *;q=0", ""])
+async def test_not_accepted(accept):
+    headers, body = await _request([("accept-encoding", accept)], compression=True)
+
+    assert "content-encoding" not in headers
+    assert headers["vary"] == "accept-encoding"
+    assert body == TEXT
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["headers", "kwargs"],
+    [
+        ([("x-kind", "small")], {}),
+        ([("x-type", "image/png")], {}),
+        ([("x-type", "application/json")], {"compression_types": ["text/*"]}),
+        ([], {"compression_min_size": 1000000})
+    ]
+)
+async def test_not_compressible(headers, kwargs):
+    res_headers, _ = await _request([("accept-encoding", "gzip")] + headers, compression=True, **kwargs)
+
+    assert "content-encoding" not in res_headers
+    assert "vary" not in res_headers
+
+
+@pytest.mark.asyncio
+async def test_custom_types():
+    headers, body = await _request(
+        [("accept-encoding", "gzip"), ("x-type", "application/vnd.api+json")],
+        compression=True,
+        compression_types=["application/*+json"]
+    )
+
+    assert headers["content-encoding"] == "gzip"
+    assert gzip.decompress(body) == TEXT
+
+
+@pytest.mark.asyncio
+async def test_streamed_response():
+    headers, body = await _request([("accept-encoding", "gzip")], app=asgi_app, interface="asgi", compression=True)
+
+    assert headers["content-encoding"] == "gzip"
+    assert gzip.decompress(body) == b"data: 0\n\ndata: 1\n\ndata: 2\n\n"
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    headers, body = await _request([("accept-encoding", "gzip")])
+
+    assert "content-encoding" not in headers
+    assert "vary" not in headers
+    assert body == TEXT
+
+
+def test_invalid_types():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", compression=True, compression_types=["text/*/*"])
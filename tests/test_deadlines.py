@@ -0,0 +1,49 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_app(scope, proto):
+    if scope.path == "/slow":
+        await asyncio.sleep(2)
+    remaining = scope.deadline_remaining()
+    proto.response_str(200, [("content-type", "text/plain")], "none" if remaining is None else f"{remaining:.1f}")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("budget", ["5", "5S", "5000m"])
+async def test_deadline_budget(budget):
+    async with TestClient(rsgi_app, "rsgi", deadline_header="x-deadline") as client:
+        res = await client.get("/", headers={"x-deadline": budget})
+
+    assert res.status_code == 200
+    assert 4.0 < float(res.text) <= 5.0
+
+
+@pytest.mark.asyncio
+async def test_deadline_expired():
+    async with TestClient(rsgi_app, "rsgi", deadline_header="x-deadline") as client:
+        res = await client.get("/slow", headers={"x-deadline": "100m"})
+
+    assert res.status_code == 504
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("budget", ["1e30", "1.8e19", "inf", "nan", "-1", "5X", "abc"])
+async def test_deadline_invalid_budget(budget):
+    async with TestClient(rsgi_app, "rsgi", deadline_header="x-deadline") as client:
+        res = await client.get("/", headers={"x-deadline": budget})
+
+    assert res.status_code == 200
+    assert res.text == "none"
+
+
+@pytest.mark.asyncio
+async def test_deadline_disabled():
+    async with TestClient(rsgi_app, "rsgi") as client:
+        res = await client.get("/", headers={"x-deadline": "5"})
+
+    assert res.status_code == 200
+    assert res.text == "none"
@@ -0,0 +1,309 @@
+import asyncio
+import gzip
+import random
+import zlib
+
+import pytest
+
+from granian.testing import TestServer
+
+
+TEXT = b"".join(b"field %d=some repeated request value&" % (idx % 70) for idx in range(3000))
+BODIES = {
+    "text": TEXT,
+    "empty": b"",
+    "random": random.Random(42).randbytes(100000),
+    "runs": b"".join(bytes([idx]) * (idx * 37 % 1000 + 1) for idx in range(256)) * 4,
+    "far": (random.Random(7).randbytes(30000) + TEXT[:2700]) * 3
+}
+
+
+async def rsgi_app(scope, proto):
+    body = await proto()
+    headers = [
+        ("x-encoding", scope.headers.get("content-encoding") or ""),
+        ("x-length", scope.headers.get("content-length") or "")
+    ]
+    proto.response_bytes(200, headers, body)
+
+
+async def asgi_app(scope, receive, send):
+    body, more = b"", True
+    while more:
+        message = await receive()
+        body, more = body + message.get("body", b""), message.get("more_body", False)
+    headers = dict(scope["headers"])
+    await send({
+        "type": "http.response.start",
+        "status": 200,
+        "headers": [
+            (b"x-encoding", headers.get(b"content-encoding", b"")),
+            (b"x-length", headers.get(b"content-length", b""))
+        ]
+    })
+    await send({"type": "http.response.body", "body": body})
+
+
+def _raw_deflate(data, **kwargs):
+    compressor = zlib.compressobj(wbits=-15, **kwargs)
+    return compressor.compress(data) + compressor.flush()
+
+
+def _zlib(data, **kwargs):
+    compressor = zlib.compressobj(**kwargs)
+    return compressor.compress(data) + compressor.flush()
+
+
+def _gzip_flagged(data):
+    # with the optional extra field, file name, comment and header check
+    header = b"\x1f\x8b\x08\x1e\0\0\0\0\0\xff" + b"\x04\0abcd" + b"name.txt\0" + b"a comment\0"
+    header += (zlib.crc32(header) & 0xffff).to_bytes(2, "little")
+    trailer = zlib.crc32(data).to_bytes(4, "little") + len(data).to_bytes(4, "little")
+    return header + _raw_deflate(data) + trailer
+
+
+def _flushed(data):
+    # sync and full flushes in between, leaving empty stored blocks around
+    compressor = zlib.compressobj()
+    ret = b""
+    for idx in range(0, len(data), 5000):
+        ret += compressor.compress(data[idx:idx + 5000])
+        ret += compressor.flush(zlib.Z_FULL_FLUSH if idx % 2 else zlib.Z_SYNC_FLUSH)
+    return ret + compressor.flush()
+
+
+def _chunked(data, size=1000):
+    chunks = [data[idx:idx + size] for idx in range(0, len(data), size)]
+    return b"".join(b"%x\r\n%s\r\n" % (len(chunk), chunk) for chunk in chunks) + b"0\r\n\r\n"
+
+
+async def _send(server, body, encoding, chunked=False):
+    framing = "transfer-encoding: chunked" if chunked else f"content-length: {len(body)}"
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(
+        f"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n"
+        f"content-encoding: {encoding}\r\n{framing}\r\n\r\n".encode() + (_chunked(body) if chunked else body)
+    )
+    response = await asyncio.wait_for(reader.read(), 5)
+    writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    return int(lines[0].split(" ")[1]), dict(line.lower().split(": ", 1) for line in lines[1:]), body
+
+
+async def _request(body, encoding, app=rsgi_app, interface="rsgi", chunked=False, **kwargs):
+    async with TestServer(app, interface, **kwargs) as server:
+        return await _send(server, body, encoding, chunked=chunked)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+@pytest.mark.parametrize(
+    ["encoding", "compress"],
+    [
+        ("gzip", gzip.compress),
+        ("x-gzip", lambda data: gzip.compress(data, compresslevel=1)),
+        ("deflate", zlib.compress),
+        ("deflate", _raw_deflate)
+    ]
+)
+async def test_decompress(interface, app, encoding, compress):
+    status, headers, body = await _request(compress(TEXT), encoding, app=app, interface=interface, decompression=True)
+
+    assert status == 200
+    assert headers["x-encoding"] == ""
+    assert headers["x-length"] == str(len(TEXT))
+    assert body == TEXT
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("level", [0, 1, 6, 9])
+async def test_decompress_data(level):
+    data = b"".join(
+        random.Random(idx).randbytes(random.Random(idx).randrange(1, 300)) + TEXT[:idx * 7] for idx in range(300)
+    )
+    status, _, body = await _request(gzip.compress(data, compresslevel=level), "gzip", decompression=True)
+
+    assert status == 200
+    assert body == data
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["encoding", "compress"],
+    [
+        ("gzip", lambda data: gzip.compress(data, compresslevel=9)),
+        ("gzip", _gzip_flagged),
+        ("deflate", lambda data: _zlib(data, level=0)),
+        ("deflate", lambda data: _zlib(data, strategy=zlib.Z_FIXED)),
+        ("deflate", lambda data: _zlib(data, strategy=zlib.Z_HUFFMAN_ONLY)),
+        ("deflate", lambda data: _zlib(data, strategy=zlib.Z_RLE)),
+        ("deflate", lambda data: _zlib(data, wbits=9, memLevel=1)),
+        ("deflate", _flushed),
+        ("deflate", lambda data: _raw_deflate(data, level=1))
+    ]
+)
+async def test_decompress_roundtrip(encoding, compress):
+    async with TestServer(rsgi_app, "rsgi", decompression=True) as server:
+        for data in BODIES.values():
+            status, headers, body = await _send(server, compress(data), encoding)
+
+            assert status == 200
+            assert headers["x-length"] == str(len(data))
+            assert body == data
+
+
+@pytest.mark.asyncio
+async def test_decompress_chunked():
+    status, headers, body = await _request(gzip.compress(TEXT), "gzip", chunked=True, decompression=True)
+
+    assert status == 200
+    assert headers["x-length"] == str(len(TEXT))
+    assert body == TEXT
+
+
+@pytest.mark.asyncio
+async def test_gzip_members():
+    status, _, body = await _request(gzip.compress(b"first ") + gzip.compress(b"second"), "gzip", decompression=True)
+
+    assert status == 200
+    assert body == b"first second"
+
+
+@pytest.mark.asyncio
+async def test_bomb():
+    bomb = gzip.compress(b"\0" * 10000000)
+    status, _, _ = await _request(bomb, "gzip", decompression=True, decompression_max_size=1000000)
+
+    assert len(bomb) < 1000000
+    assert status == 413
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "body",
+    [
+        b"not gzip data",
+        gzip.compress(TEXT)[:-100],
+        gzip.compress(TEXT)[:-8] + b"\0" * 8
+    ]
+)
+async def test_invalid(body):
+    status, _, res = await _request(body, "gzip", decompression=True)
+
+    assert status == 400
+    assert res.startswith(b"Invalid request body")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["encoding", "body"],
+    [
+        # reserved block type
+        ("deflate", b"\x07\0"),
+        # stored block length not matching its complement
+        ("deflate", b"\x01\x05\0\xfa\xfehello"),
+        # matches reaching before the start of the data
+        ("deflate", _raw_deflate(TEXT, zdict=TEXT[:3000])),
+        ("deflate", _zlib(TEXT, zdict=TEXT[:3000])),
+        # no final block
+        ("deflate", _raw_deflate(TEXT)[:-20] + b"\0\0\0\xff\xff"),
+        ("deflate", _zlib(TEXT)[:-4] + b"\0\0\0\0"),
+        ("deflate", _zlib(TEXT) + b"trailing"),
+        ("gzip", gzip.compress(TEXT)[:-4] + (len(TEXT) + 1).to_bytes(4, "little")),
+        ("gzip", gzip.compress(TEXT) + b"\x1f\x8b"),
+        ("gzip", b"\x1f\x8b\x08\x08\0\0\0\0\0\xffunterminated name")
+    ]
+)
+async def test_malformed(encoding, body):
+    status, _, res = await _request(body, encoding, decompression=True)
+
+    assert status == 400
+    assert res.startswith(b"Invalid request body")
+
+
+def _reference(data, wbits):
+    decompressor = zlib.decompressobj(wbits)
+    try:
+        ret = decompressor.decompress(data) + decompressor.flush()
+    except zlib.error:
+        return None
+    return ret if decompressor.eof and not decompressor.unused_data else None
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["encoding", "compress", "wbits"],
+    [("gzip", gzip.compress, 31), ("deflate", zlib.compress, 15), ("deflate", _raw_deflate, -15)]
+)
+async def test_corrupted(encoding, compress, wbits):
+    rng = random.Random(wbits)
+    async with TestServer(rsgi_app, "rsgi", decompression=True) as server:
+        for data in (TEXT, BODIES["random"][:20000], BODIES["far"]):
+            valid = compress(data)
+            for _ in range(10):
+                idx = rng.randrange(len(valid))
+                flipped = valid[:idx] + bytes([valid[idx] ^ (1 << rng.randrange(8))]) + valid[idx + 1:]
+                truncated = valid[:rng.randrange(len(valid))]
+                for body in (flipped, truncated):
+                    status, _, res = await _send(server, body, encoding)
+                    # raw streams have no checksum, and might still be valid
+                    expected = _reference(body, wbits)
+
+                    if expected is None:
+                        assert status == 400
+                        assert res.startswith(b"Invalid request body")
+                    else:
+                        assert status == 200
+                        assert res == expected
+
+
+@pytest.mark.asyncio
+async def test_other_coding():
+    status, headers, body = await _request(b"brotli data", "br", decompression=True)
+
+    assert status == 200
+    assert headers["x-encoding"] == "br"
+    assert body == b"brotli data"
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    data = gzip.compress(TEXT)
+    status, headers, body = await _request(data, "gzip")
+
+    assert status == 200
+    assert headers["x-encoding"] == "gzip"
+    assert body == data
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+@pytest.mark.parametrize("chunked", [False, True])
+async def test_filtered(interface, app, chunked):
+    data = TEXT.replace(b"&", b"\r\n")
+    status, _, body = await _request(
+        gzip.compress(data),
+        "gzip",
+        app=app,
+        interface=interface,
+        chunked=chunked,
+        decompression=True,
+        request_filters={"/": ["normalize_newlines"]}
+    )
+
+    assert status == 200
+    assert body == TEXT.replace(b"&", b"\n")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("chunked", [False, True])
+async def test_body_limit(chunked):
+    data = gzip.compress(TEXT)
+    status, _, _ = await _request(data, "gzip", chunked=chunked, decompression=True, max_body_size=len(TEXT) - 1)
+    status_ok, _, body = await _request(data, "gzip", chunked=chunked, decompression=True, max_body_size=len(TEXT))
+
+    assert len(data) < len(TEXT) - 1
+    assert status == 413
+    assert status_ok == 200
+    assert body == TEXT
@@ -0,0 +1,68 @@
+import asyncio
+
+import pytest
+
+from granian._granian import ASGIConnectionClosed
+from granian.rsgi import ProtocolClosed
+from granian.testing import TestServer
+
+
+CHUNKS = 50
+
+
+def _rsgi_app(outcomes, done):
+    async def app(scope, proto):
+        transport = proto.response_stream(200, [("content-type", "text/plain")])
+        try:
+            for _ in range(CHUNKS):
+                await transport.send_str("x" * 4096)
+                await asyncio.sleep(0.01)
+            outcomes.append("completed")
+        except ProtocolClosed:
+            outcomes.append("error")
+        except asyncio.CancelledError:
+            outcomes.append("cancelled")
+            raise
+        finally:
+            done.set()
+    return app
+
+
+def _asgi_app(outcomes, done):
+    async def app(scope, receive, send):
+        if scope["type"] != "http":
+            return
+        await send({"type": "http.response.start", "status": 200, "headers": []})
+        try:
+            for _ in range(CHUNKS):
+                await send({"type": "http.response.body", "body": b"x" * 4096, "more_body": True})
+                await asyncio.sleep(0.01)
+            await send({"type": "http.response.body", "body": b""})
+            outcomes.append("completed")
+        except ASGIConnectionClosed:
+            outcomes.append("error")
+        except asyncio.CancelledError:
+            outcomes.append("cancelled")
+            raise
+        finally:
+            done.set()
+    return app
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["rsgi", "asgi"])
+@pytest.mark.parametrize(
+    ["policy", "outcome"],
+    [("discard", "completed"), ("error", "error"), ("cancel", "cancelled")]
+)
+async def test_disconnect_policy(interface, policy, outcome):
+    outcomes, done = [], asyncio.Event()
+    app = (_rsgi_app if interface == "rsgi" else _asgi_app)(outcomes, done)
+    async with TestServer(app, interface, disconnect_policy=policy) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 5)
+        writer.close()
+        await asyncio.wait_for(done.wait(), 5)
+
+    assert outcomes == [outcome]
@@ -0,0 +1,73 @@
+import asyncio
+
+import pytest
+import websockets
+
+from granian.rsgi import WebsocketMessageType
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    if scope.proto == "ws":
+        trx = await proto.accept()
+        while True:
+            message = await trx.receive()
+            if message.kind == WebsocketMessageType.close:
+                return
+            await trx.send_str(message.data)
+    await asyncio.sleep(0.5)
+    proto.response_bytes(200, [("content-type", "text/plain")], b"done")
+
+
+async def _response(reader, timeout=3):
+    head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), timeout)
+    lines = head.decode().rstrip("\r\n").split("\r\n")
+    headers = dict(line.lower().split(": ", 1) for line in lines[1:])
+    body = await reader.readexactly(int(headers["content-length"]))
+    return int(lines[0].split(" ")[1]), headers, body
+
+
+@pytest.mark.asyncio
+async def test_drain_in_flight_request():
+    server = await TestServer(rsgi_app, "rsgi").start()
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+    await asyncio.sleep(0.1)
+    shutdown = asyncio.ensure_future(server.shutdown())
+    status, headers, body = await _response(reader)
+    closed = await asyncio.wait_for(reader.read(), 2) == b""
+    writer.close()
+    await asyncio.wait_for(shutdown, 2)
+
+    assert status == 200
+    assert body == b"done"
+    assert headers["connection"] == "close"
+    assert closed
+
+
+@pytest.mark.asyncio
+async def test_drain_websocket_session():
+    server = await TestServer(rsgi_app, "rsgi").start()
+    async with websockets.connect(f"{server.ws_url}/ws") as ws:
+        shutdown = asyncio.ensure_future(server.shutdown())
+        await asyncio.sleep(0.2)
+        await ws.send("still there")
+        echo = await ws.recv()
+        drained = shutdown.done()
+    await asyncio.wait_for(shutdown, 2)
+
+    assert echo == "still there"
+    assert not drained
+
+
+@pytest.mark.asyncio
+async def test_drain_timeout():
+    server = await TestServer(rsgi_app, "rsgi", drain_timeout=0.5).start()
+    async with websockets.connect(f"{server.ws_url}/ws") as ws:
+        started = asyncio.get_running_loop().time()
+        await asyncio.wait_for(server.shutdown(), 2)
+        elapsed = asyncio.get_running_loop().time() - started
+        with pytest.raises(websockets.ConnectionClosed):
+            await asyncio.wait_for(ws.recv(), 2)
+
+    assert 0.4 < elapsed < 1.5
@@ -0,0 +1,34 @@
+import socket
+
+import pytest
+
+from granian.errors import AppError, BindError, GranianError, ProtocolError, TimeoutError, TlsError
+from granian.net import SocketHolder
+
+
+@pytest.mark.parametrize(
+    ["error", "code", "exit_status"],
+    [
+        (BindError, "bind", 71),
+        (TlsError, "tls", 78),
+        (ProtocolError, "protocol", 76),
+        (AppError, "app", 70),
+        (TimeoutError, "timeout", 75)
+    ]
+)
+def test_error_codes(error, code, exit_status):
+    assert issubclass(error, GranianError)
+    assert error.code == code
+    assert error.exit_status == exit_status
+
+
+def test_bind_error():
+    sock = socket.socket()
+    sock.bind(("127.0.0.1", 0))
+    sock.listen()
+    port = sock.getsockname()[1]
+
+    with pytest.raises(BindError):
+        SocketHolder.from_address("127.0.0.1", port, 128)
+
+    sock.close()
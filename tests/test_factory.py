@@ -0,0 +1,49 @@
+import multiprocessing
+
+from functools import partial
+
+from granian._internal import load_target
+from granian.constants import Interfaces
+from granian.server import Granian
+
+
+_loaded = multiprocessing.SimpleQueue()
+
+
+def factory(context):
+    return ("app", context.worker_id, context.worker_generation, context.config["workers"], context.config["interface"])
+
+
+def plain_factory():
+    return "plain app"
+
+
+# Reports what the worker got from its callback loader, instead of serving it
+def _loading_worker(worker_id, worker_generation, memory_recycles, callback_loader, *args):
+    _loaded.put(callback_loader())
+
+
+def _boot_worker(server, target):
+    server._spawn_args = (_loading_worker, partial(load_target, target), lambda: None)
+    proc = server._boot_worker(1)
+    proc.join(5)
+    return _loaded.get()
+
+
+def test_factory_context():
+    server = Granian("tests.test_factory:factory", workers=2, factory=True)
+
+    assert _boot_worker(server, server.target) == ("app", 1, 1, 2, Interfaces.RSGI)
+    assert _boot_worker(server, server.target) == ("app", 1, 2, 2, Interfaces.RSGI)
+
+
+def test_factory_without_arguments():
+    server = Granian("tests.test_factory:plain_factory", factory=True)
+
+    assert _boot_worker(server, server.target) == "plain app"
+
+
+def test_target_not_called_without_factory():
+    server = Granian("tests.test_factory:plain_factory")
+
+    assert _boot_worker(server, server.target)() == "plain app"
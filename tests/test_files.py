@@ -0,0 +1,156 @@
+import pathlib
+
+import pytest
+
+from granian.testing import MockClock, TestClient
+
+
+FIXTURES = pathlib.Path(__file__).parent / "fixtures"
+
+
+def _file_app(path, headers=()):
+    async def app(scope, proto):
+        proto.response_file(200, list(headers), str(path))
+    return app
+
+
+@pytest.mark.asyncio
+async def test_file_headers():
+    async with TestClient(_file_app(FIXTURES / "file.txt"), "rsgi") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 200
+    assert res.header("content-type") == "text/plain; charset=utf-8"
+    assert res.header("content-length") == "20"
+    assert res.text == "0123456789abcdefghij"
+
+
+@pytest.mark.asyncio
+async def test_file_app_content_type():
+    app = _file_app(FIXTURES / "file.txt", [("content-type", "application/x-custom")])
+    async with TestClient(app, "rsgi") as client:
+        res = await client.get("/")
+
+    assert res.header("content-type") == "application/x-custom"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("path", [FIXTURES / "missing.txt", FIXTURES])
+async def test_file_not_found(path):
+    async with TestClient(_file_app(path), "rsgi") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 404
+
+
+@pytest.mark.asyncio
+async def test_file_open_failure():
+    async with TestClient(_file_app(str(FIXTURES / "file.txt") + "\x00"), "rsgi") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 500
+
+
+def _asgi_file_app(path):
+    async def app(scope, receive, send):
+        assert "http.response.pathsend" in scope["extensions"]
+        await send({"type": "http.response.start", "status": 200, "headers": []})
+        await send({"type": "http.response.pathsend", "path": str(path)})
+    return app
+
+
+@pytest.mark.asyncio
+async def test_asgi_pathsend():
+    async with TestClient(_asgi_file_app(FIXTURES / "file.txt"), "asgi") as client:
+        res = await client.get("/", headers={"range": "bytes=-4"})
+
+    assert res.status_code == 206
+    assert res.header("content-type") == "text/plain; charset=utf-8"
+    assert res.header("content-range") == "bytes 16-19/20"
+    assert res.text == "ghij"
+
+
+@pytest.mark.asyncio
+async def test_asgi_pathsend_not_found():
+    async with TestClient(_asgi_file_app(FIXTURES / "missing.txt"), "asgi") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 404
+
+
+def _replace(path, content):
+    # a new inode, as deployments swapping files do
+    tmp = path.with_suffix(".tmp")
+    tmp.write_text(content)
+    tmp.replace(path)
+
+
+@pytest.mark.asyncio
+async def test_file_cache_ttl(tmp_path):
+    path = tmp_path / "cached.txt"
+    path.write_text("first")
+    with MockClock() as clock:
+        async with TestClient(_file_app(path), "rsgi", file_cache_size=4, file_cache_ttl=5) as client:
+            res = await client.get("/")
+            _replace(path, "second!")
+            res_cached = await client.get("/")
+            clock.advance(6)
+            res_checked = await client.get("/")
+
+    assert res.text == "first"
+    assert res_cached.text == "first"
+    assert res_cached.header("content-length") == "5"
+    assert res_checked.text == "second!"
+    assert res_checked.header("content-length") == "7"
+
+
+@pytest.mark.asyncio
+async def test_file_cache_removed(tmp_path):
+    path = tmp_path / "cached.txt"
+    path.write_text("content")
+    with MockClock() as clock:
+        async with TestClient(_file_app(path), "rsgi", file_cache_size=4, file_cache_ttl=5) as client:
+            res = await client.get("/")
+            path.unlink()
+            res_cached = await client.get("/")
+            clock.advance(6)
+            res_checked = await client.get("/")
+
+    assert res.text == "content"
+    assert res_cached.text == "content"
+    assert res_checked.status_code == 404
+
+
+@pytest.mark.asyncio
+async def test_file_cache_eviction(tmp_path):
+    paths = {name: tmp_path / f"{name}.txt" for name in ("a", "b")}
+    for name, path in paths.items():
+        path.write_text(name)
+
+    async def app(scope, proto):
+        proto.response_file(200, [], str(paths[scope.path.strip("/")]))
+
+    with MockClock():
+        async with TestClient(app, "rsgi", file_cache_size=1, file_cache_ttl=60) as client:
+            await client.get("/a")
+            await client.get("/b")
+            _replace(paths["a"], "A")
+            res_evicted = await client.get("/a")
+            _replace(paths["a"], "AA")
+            res_cached = await client.get("/a")
+
+    assert res_evicted.text == "A"
+    assert res_cached.text == "A"
+
+
+@pytest.mark.asyncio
+async def test_file_cache_disabled(tmp_path):
+    path = tmp_path / "cached.txt"
+    path.write_text("first")
+    with MockClock():
+        async with TestClient(_file_app(path), "rsgi") as client:
+            await client.get("/")
+            _replace(path, "second")
+            res = await client.get("/")
+
+    assert res.text == "second"
@@ -0,0 +1,160 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestClient, TestServer
+
+
+CHUNKS = {
+    "/replace": [b"hello wo", b"rld, hello w", b"orld"],
+    "/bom": [b"\xef", b"\xbb", b"\xbfdata"],
+    "/bom-partial": [b"\xef\xbb", b"x"],
+    "/banner": [b"<html><bo", b"dy class='main'>", b"content</body></html>"],
+    "/newlines": [b"a\r", b"\nb\r", b"c\r\n"],
+    "/newlines-empty": [b"a\r", b"", b"\nb"]
+}
+
+
+async def rsgi_app(scope, proto):
+    headers = [("content-type", "text/plain"), ("etag", '"tag"')]
+    if scope.path in CHUNKS:
+        transport = proto.response_stream(200, headers)
+        for chunk in CHUNKS[scope.path]:
+            await transport.send_bytes(chunk)
+        return
+    status = 200
+    if scope.path == "/encoded":
+        headers.append(("content-encoding", "gzip"))
+    elif scope.path == "/identity":
+        headers.append(("content-encoding", "identity"))
+    elif scope.path == "/range":
+        status = 206
+        headers.append(("content-range", "bytes 0-10/20"))
+    elif scope.path == "/not-modified":
+        proto.response_empty(304, headers)
+        return
+    proto.response_bytes(status, headers, b"hello world")
+
+
+async def _get(path, filters, method="GET"):
+    async with TestClient(rsgi_app, "rsgi", response_filters={"text/*": filters}) as client:
+        return await client.request(method, path)
+
+
+@pytest.mark.asyncio
+async def test_replace_across_chunks():
+    res = await _get("/replace", ["replace:world|there"])
+
+    assert res.status_code == 200
+    assert res.content == b"hello there, hello there"
+    assert res.header("etag") is None
+
+
+@pytest.mark.asyncio
+async def test_strip_bom_across_chunks():
+    res = await _get("/bom", ["strip_bom"])
+    res_partial = await _get("/bom-partial", ["strip_bom"])
+
+    assert res.content == b"data"
+    assert res_partial.content == b"\xef\xbbx"
+
+
+@pytest.mark.asyncio
+async def test_banner_across_chunks():
+    res = await _get("/banner", ["inject_banner:<p>banner</p>"])
+
+    assert res.content == b"<html><body class='main'><p>banner</p>content</body></html>"
+
+
+@pytest.mark.asyncio
+async def test_newlines_across_chunks():
+    res = await _get("/newlines", ["normalize_newlines"])
+
+    assert res.content == b"a\nb\nc\n"
+
+
+@pytest.mark.asyncio
+async def test_newlines_empty_chunk():
+    res = await _get("/newlines-empty", ["normalize_newlines"])
+
+    assert res.content == b"a\nb"
+
+
+@pytest.mark.asyncio
+async def test_banner_long_tag():
+    sent = asyncio.Event()
+
+    async def app(scope, proto):
+        transport = proto.response_stream(200, [("content-type", "text/html")])
+        await transport.send_bytes(b"<html><body " + b"x" * 300)
+        await sent.wait()
+        await transport.send_bytes(b" class='main'>content</body></html>")
+
+    async with TestServer(app, "rsgi", response_filters={"text/*": ["inject_banner:<p>banner</p>"]}) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        # the start of the tag gets sent on, without waiting for its end
+        head = await asyncio.wait_for(reader.readuntil(b"x" * 300), 5)
+        sent.set()
+        rest = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+
+    assert head.endswith(b"<html><body " + b"x" * 300)
+    assert b" class='main'><p>banner</p>content</body></html>" in rest
+
+
+@pytest.mark.asyncio
+async def test_identity_filtered():
+    res = await _get("/identity", ["replace:world|there"])
+
+    assert res.content == b"hello there"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["path", "status"], [("/encoded", 200), ("/range", 206)])
+async def test_unfiltered(path, status):
+    res = await _get(path, ["replace:world|there"])
+
+    assert res.status_code == status
+    assert res.content == b"hello world"
+    assert res.header("etag") == '"tag"'
+
+
+@pytest.mark.asyncio
+async def test_unfiltered_bodiless():
+    res_head = await _get("/", ["replace:world|there"], method="HEAD")
+    res_not_modified = await _get("/not-modified", ["replace:world|there"])
+
+    assert res_head.status_code == 200
+    assert res_head.header("etag") == '"tag"'
+    assert res_not_modified.status_code == 304
+    assert res_not_modified.header("etag") == '"tag"'
+
+
+async def echo_app(scope, proto):
+    body = await proto()
+    proto.response_bytes(200, [("content-type", "text/plain")], body)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["path", "filtered"],
+    [("/api", True), ("/api/items", True), ("/apis", False), ("/static/app.js", True), ("/staticx", False)]
+)
+async def test_request_filters_prefix(path, filtered):
+    filters = {"/api": ["normalize_newlines"], "/static/": ["normalize_newlines"]}
+    async with TestClient(echo_app, "rsgi", request_filters=filters) as client:
+        res = await client.request("POST", path, body=b"a\r\nb")
+
+    assert res.content == (b"a\nb" if filtered else b"a\r\nb")
+
+
+@pytest.mark.asyncio
+async def test_request_filters_encoded():
+    filters = {"/": ["normalize_newlines"]}
+    async with TestClient(echo_app, "rsgi", request_filters=filters) as client:
+        res = await client.request("POST", "/", headers=[("content-encoding", "br")], body=b"a\r\nb")
+        res_identity = await client.request("POST", "/", headers=[("content-encoding", "identity")], body=b"a\r\nb")
+
+    assert res.content == b"a\r\nb"
+    assert res_identity.content == b"a\nb"
@@ -0,0 +1,79 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], f"{scope.client} {scope.scheme}")
+
+
+async def asgi_app(scope, receive, send):
+    host, port = scope["client"]
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": f"{host}:{port} {scope['scheme']}".encode()})
+
+
+async def _client(app, interface, headers, trusted=("127.0.0.1",)):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    async with TestServer(app, interface, forwarded_trusted=list(trusted)) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(f"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n{head}\r\n".encode())
+        response = await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+    return response.split(b"\r\n\r\n", 1)[1].decode()
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+async def test_x_forwarded(interface, app):
+    headers = [("x-forwarded-for", "192.0.2.10"), ("x-forwarded-proto", "https")]
+    assert await _client(app, interface, headers) == "192.0.2.10:0 https"
+
+
+@pytest.mark.asyncio
+async def test_x_forwarded_chain():
+    headers = [("x-forwarded-for", "192.0.2.10, 10.0.0.5"), ("x-forwarded-proto", "https, http")]
+    body = await _client(rsgi_app, "rsgi", headers, trusted=["127.0.0.1", "10.0.0.0/8"])
+    assert body == "192.0.2.10:0 https"
+
+
+@pytest.mark.asyncio
+async def test_x_forwarded_spoofed_hops_ignored():
+    headers = [("x-forwarded-for", "203.0.113.1, 192.0.2.10")]
+    assert await _client(rsgi_app, "rsgi", headers) == "192.0.2.10:0 http"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app", "expected"],
+    [("asgi", asgi_app, "2001:db8::17:4711 https"), ("rsgi", rsgi_app, "[2001:db8::17]:4711 https")]
+)
+async def test_forwarded(interface, app, expected):
+    headers = [("forwarded", 'for="[2001:db8::17]:4711";proto=https;by=10.0.0.1')]
+    assert await _client(app, interface, headers) == expected
+
+
+@pytest.mark.asyncio
+async def test_forwarded_preferred():
+    headers = [("x-forwarded-for", "203.0.113.1"), ("forwarded", "for=192.0.2.10")]
+    assert await _client(rsgi_app, "rsgi", headers) == "192.0.2.10:0 http"
+
+
+@pytest.mark.asyncio
+async def test_forwarded_unknown_node():
+    body = await _client(rsgi_app, "rsgi", [("forwarded", "for=unknown;proto=https")])
+    assert body.startswith("127.0.0.1:") and body.endswith(" https")
+
+
+@pytest.mark.asyncio
+async def test_untrusted_peer():
+    headers = [("x-forwarded-for", "192.0.2.10"), ("x-forwarded-proto", "https")]
+    body = await _client(rsgi_app, "rsgi", headers, trusted=["10.0.0.0/8"])
+    assert body.startswith("127.0.0.1:") and body.endswith(" http")
+
+
+def test_invalid_trusted_network():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", forwarded_trusted=["10.0.0.0/33"])
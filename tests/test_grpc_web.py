@@ -0,0 +1,150 @@
+import asyncio
+import base64
+import json
+import struct
+
+import pytest
+
+from granian.testing import TestServer
+
+
+def _frame(payload, flags=0):
+    return struct.pack(">BI", flags, len(payload)) + payload
+
+
+def _frames(data):
+    ret = []
+    while data:
+        flags, size = struct.unpack(">BI", data[:5])
+        ret.append((flags, data[5:5 + size]))
+        data = data[5 + size:]
+    return ret
+
+
+def _dechunk(data):
+    ret = b""
+    while data:
+        size, data = data.split(b"\r\n", 1)
+        size = int(size, 16)
+        if not size:
+            break
+        ret, data = ret + data[:size], data[size + 2:]
+    return ret
+
+
+async def rsgi_app(scope, proto):
+    body = await proto()
+    status = int(scope.headers.get("x-status", "200"))
+    headers = [("content-type", scope.headers.get("content-type"))]
+    if scope.headers.get("x-grpc-status"):
+        headers += [("grpc-status", scope.headers.get("x-grpc-status")), ("grpc-message", "no such user")]
+    proto.response_bytes(status, headers, body[::-1])
+
+
+async def rsgi_messages_app(scope, proto):
+    messages = [bytes(chunk) async for chunk in proto]
+    proto.response_bytes(200, [("x-codec", scope.headers.get("content-type"))], b"|".join(messages))
+
+
+async def asgi_app(scope, receive, send):
+    await receive()
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    for message in (b'{"name": "a"}', b'{"name": "b"}'):
+        await send({"type": "http.response.body", "body": message, "more_body": True})
+    await send({"type": "http.response.body", "body": b""})
+
+
+async def _call(app, interface, content_type, body, headers=(), grpc_web=True):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    async with TestServer(app, interface, grpc_web=grpc_web) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(
+            f"POST /users.v1.Users/Get HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n"
+            f"content-type: {content_type}\r\ncontent-length: {len(body)}\r\n{head}\r\n".encode() + body
+        )
+        response = await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    headers = dict(line.lower().split(": ", 1) for line in lines[1:])
+    if headers.get("transfer-encoding") == "chunked":
+        body = _dechunk(body)
+    return int(lines[0].split(" ")[1]), headers, body
+
+
+@pytest.mark.asyncio
+async def test_grpc_web():
+    status, headers, body = await _call(rsgi_app, "rsgi", "application/grpc-web+proto", _frame(b"\x08\x96\x01"))
+
+    assert status == 200
+    assert headers["content-type"] == "application/grpc-web+proto"
+    assert _frames(body) == [(0, b"\x01\x96\x08"), (0x80, b"grpc-status: 0\r\n")]
+
+
+@pytest.mark.asyncio
+async def test_grpc_web_text():
+    # every segment gets its own padding
+    payload = base64.b64encode(_frame(b"ab")) + base64.b64encode(_frame(b"c"))
+    status, headers, body = await _call(rsgi_app, "rsgi", "application/grpc-web-text", payload)
+
+    assert status == 200
+    assert headers["content-type"] == "application/grpc-web-text"
+    assert _frames(base64.b64decode(body)) == [(0, b"cba"), (0x80, b"grpc-status: 0\r\n")]
+
+
+@pytest.mark.asyncio
+async def test_grpc_status_headers():
+    _, _, body = await _call(
+        rsgi_app, "rsgi", "application/grpc-web+proto", _frame(b"x"), [("x-grpc-status", "5")]
+    )
+
+    assert _frames(body) == [(0, b"x"), (0x80, b"grpc-status: 5\r\ngrpc-message: no such user\r\n")]
+
+
+@pytest.mark.asyncio
+async def test_http_error_status():
+    status, _, body = await _call(
+        rsgi_app, "rsgi", "application/grpc-web+proto", _frame(b"x"), [("x-status", "404")]
+    )
+
+    assert status == 200
+    assert _frames(body) == [(0x80, b"grpc-status: 12\r\ngrpc-message: Not Found\r\n")]
+
+
+@pytest.mark.asyncio
+async def test_connect_request_messages():
+    request = _frame(b'{"name": "a"}') + _frame(b'{"name": "b"}')
+    status, headers, body = await _call(rsgi_messages_app, "rsgi", "application/connect+json", request)
+
+    assert status == 200
+    assert headers["content-type"] == "application/connect+json"
+    assert headers["x-codec"] == "application/json"
+    assert _frames(body) == [(0, b'{"name": "a"}|{"name": "b"}'), (0x02, b"{}")]
+
+
+@pytest.mark.asyncio
+async def test_connect_response_messages():
+    _, _, body = await _call(asgi_app, "asgi", "application/connect+json", _frame(b"{}"))
+
+    assert _frames(body) == [(0, b'{"name": "a"}'), (0, b'{"name": "b"}'), (0x02, b"{}")]
+
+
+@pytest.mark.asyncio
+async def test_connect_error():
+    _, _, body = await _call(
+        rsgi_app, "rsgi", "application/connect+proto", _frame(b"x"), [("x-grpc-status", "5")]
+    )
+
+    frames = _frames(body)
+    assert frames[-1][0] == 0x02
+    assert json.loads(frames[-1][1]) == {"error": {"code": "not_found", "message": "no such user"}}
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    _, headers, body = await _call(
+        rsgi_app, "rsgi", "application/grpc-web+proto", _frame(b"ab"), grpc_web=False
+    )
+
+    assert headers["content-type"] == "application/grpc-web+proto"
+    assert body == _frame(b"ab")[::-1]
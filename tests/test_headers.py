@@ -0,0 +1,57 @@
+import json
+
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_headers(scope, proto):
+    proto.response_str(200, [("content-type", "application/json")], json.dumps({
+        "joined": scope.headers.get("x-test"),
+        "all": scope.headers.get_all("x-test"),
+        "cookie": scope.headers.get("cookie")
+    }))
+
+
+def wsgi_headers(environ, protocol):
+    protocol("200 OK", [("content-type", "application/json")])
+    return [json.dumps({
+        "joined": environ.get("HTTP_X_TEST"),
+        "cookie": environ.get("HTTP_COOKIE")
+    }).encode("utf8")]
+
+
+DUPLICATES = [("x-test", "a"), ("x-test", "b"), ("cookie", "a=1"), ("cookie", "b=2")]
+
+
+@pytest.mark.asyncio
+async def test_rsgi_duplicates():
+    async with TestClient(rsgi_headers, "rsgi") as client:
+        res = await client.get("/", headers=DUPLICATES)
+
+    assert res.status_code == 200
+    assert res.json() == {"joined": "a, b", "all": ["a", "b"], "cookie": "a=1; b=2"}
+
+
+@pytest.mark.asyncio
+async def test_wsgi_duplicates():
+    async with TestClient(wsgi_headers, "wsgi") as client:
+        res = await client.get("/", headers=DUPLICATES)
+
+    assert res.status_code == 200
+    assert res.json() == {"joined": "a, b", "cookie": "a=1; b=2"}
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("rsgi", rsgi_headers),
+        ("wsgi", wsgi_headers)
+    ]
+)
+async def test_duplicate_host_rejected(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.get("/", headers=[("host", "example.com"), ("host", "example.org")])
+
+    assert res.status_code == 400
@@ -0,0 +1,66 @@
+import logging
+
+import pytest
+
+from granian._granian import reset_log_levels
+from granian.testing import TestClient
+
+
+async def rsgi_app(scope, proto):
+    status = int(scope.path.strip("/") or 200)
+    proto.response_str(status, [("content-type", "application/json")], '{"error": true}')
+
+
+HOOKS = [
+    ("5xx", "header", "retry-after:30"),
+    ("502", "body", "<h1>Back soon</h1><p>{method} {path}</p>"),
+    ("502", "header", "content-type:text/html"),
+    ("503", "log", "Service unavailable"),
+]
+
+
+@pytest.mark.asyncio
+async def test_status_hook_body():
+    async with TestClient(rsgi_app, "rsgi", status_hooks=HOOKS) as client:
+        res = await client.get("/502")
+
+    assert res.status_code == 502
+    assert res.header("content-type") == "text/html"
+    assert res.header("retry-after") == "30"
+    assert res.text == "<h1>Back soon</h1><p>GET /502</p>"
+
+
+@pytest.mark.asyncio
+async def test_status_hook_class(caplog):
+    caplog.set_level(logging.WARNING, logger="_granian")
+    reset_log_levels()
+    async with TestClient(rsgi_app, "rsgi", status_hooks=HOOKS) as client:
+        res = await client.get("/503")
+        res_ok = await client.get("/")
+
+    assert res.status_code == 503
+    assert res.header("content-type") == "application/json"
+    assert res.header("retry-after") == "30"
+    assert res.text == '{"error": true}'
+    assert res_ok.header("retry-after") is None
+    assert any(
+        record.getMessage() == "Service unavailable (503 response to GET /503)" for record in caplog.records
+    )
+
+
+@pytest.mark.asyncio
+async def test_status_hook_server_errors():
+    async with TestClient(
+        rsgi_app, "rsgi", allowed_hosts=["example.com"], status_hooks=[("4xx", "header", "x-rejected:1")]
+    ) as client:
+        res = await client.get("/")
+
+    assert res.status_code == 400
+    assert res.header("x-rejected") == "1"
+
+
+def test_status_hook_invalid():
+    with pytest.raises(ValueError):
+        TestClient(rsgi_app, "rsgi", status_hooks=[("6x", "log", "")])
+    with pytest.raises(ValueError):
+        TestClient(rsgi_app, "rsgi", status_hooks=[("500", "redirect", "/")])
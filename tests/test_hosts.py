@@ -0,0 +1,67 @@
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], scope.headers.get("host"))
+
+
+def wsgi_app(environ, protocol):
+    protocol("200 OK", [("content-type", "text/plain")])
+    return [environ["HTTP_HOST"].encode("utf8")]
+
+
+HOSTS = ["example.com", "*.example.org", "127.0.0.1", "[::1]"]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "host",
+    ["example.com", "EXAMPLE.com:8000", "example.com.", "api.example.org", "a.b.example.org", "127.0.0.1:80", "[::1]:8000"]
+)
+async def test_allowed(host):
+    async with TestClient(rsgi_app, "rsgi", allowed_hosts=HOSTS) as client:
+        res = await client.get("/", headers={"host": host})
+
+    assert res.status_code == 200
+    assert res.text == host
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "host",
+    ["evil.com", "example.org", "example.com.evil.com", "evilexample.org", "example.com:http", "example.com/x", "[::2]"]
+)
+async def test_rejected(host):
+    async with TestClient(rsgi_app, "rsgi", allowed_hosts=HOSTS) as client:
+        res = await client.get("/", headers={"host": host})
+
+    assert res.status_code == 400
+
+
+@pytest.mark.asyncio
+async def test_authority_checked():
+    async with TestClient(wsgi_app, "wsgi", allowed_hosts=HOSTS) as client:
+        res = await client.get("http://evil.com/", headers={"host": "example.com"})
+        res_valid = await client.get("http://api.example.org/", headers={"host": "example.com"})
+
+    assert res.status_code == 400
+    assert res_valid.status_code == 200
+
+
+@pytest.mark.asyncio
+async def test_any_host():
+    async with TestClient(rsgi_app, "rsgi") as client:
+        res = await client.get("/", headers={"host": "anything.test"})
+
+    assert res.status_code == 200
+
+
+@pytest.mark.asyncio
+async def test_rejected_json():
+    async with TestClient(rsgi_app, "rsgi", allowed_hosts=HOSTS) as client:
+        res = await client.get("/", headers={"host": "evil.com", "accept": "application/json"})
+
+    assert res.status_code == 400
+    assert res.json()["title"] == "Bad Request"
@@ -0,0 +1,88 @@
+import asyncio
+import struct
+
+import pytest
+
+from granian.testing import TestServer
+
+
+PREFACE = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"
+# GET / on stream 1, with the request headers HPACK encoded by hand
+REQUEST_HEADERS = b"\x82\x86\x84\x41\x09localhost"
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], scope.http_version)
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": scope["http_version"].encode()})
+
+
+def _frame(kind, flags, stream, payload=b""):
+    return struct.pack(">I", len(payload))[1:] + bytes([kind, flags]) + struct.pack(">I", stream) + payload
+
+
+async def _read_frame(reader):
+    head = await asyncio.wait_for(reader.readexactly(9), 2)
+    size = struct.unpack(">I", b"\x00" + head[:3])[0]
+    return head[3], head[4], struct.unpack(">I", head[5:])[0] & 0x7FFFFFFF, await reader.readexactly(size)
+
+
+# Talks HTTP/2 with prior knowledge over cleartext, returning the server settings
+# and the body of the response
+async def _request(server):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(PREFACE + _frame(0x4, 0, 0) + _frame(0x1, 0x5, 1, REQUEST_HEADERS))
+    settings, body = {}, b""
+    while True:
+        kind, flags, stream, payload = await _read_frame(reader)
+        if kind == 0x4 and not flags & 0x1:
+            for idx in range(0, len(payload), 6):
+                key, value = struct.unpack(">HI", payload[idx:idx + 6])
+                settings[key] = value
+            writer.write(_frame(0x4, 0x1, 0))
+        elif kind == 0x0 and stream == 1:
+            body += payload
+            if flags & 0x1:
+                break
+    writer.close()
+    return settings, body
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+async def test_prior_knowledge(interface, app):
+    async with TestServer(app, interface) as server:
+        _, body = await _request(server)
+
+    assert body == b"2"
+
+
+@pytest.mark.asyncio
+async def test_http2_only():
+    async with TestServer(rsgi_app, "rsgi", http="2") as server:
+        _, body = await _request(server)
+
+    assert body == b"2"
+
+
+@pytest.mark.asyncio
+async def test_max_concurrent_streams():
+    async with TestServer(rsgi_app, "rsgi", http2_max_concurrent_streams=10) as server:
+        settings, _ = await _request(server)
+
+    # SETTINGS_MAX_CONCURRENT_STREAMS
+    assert settings[0x3] == 10
+
+
+@pytest.mark.asyncio
+async def test_http1_only():
+    async with TestServer(rsgi_app, "rsgi", http="1") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(PREFACE)
+        data = await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+
+    assert not data.startswith(b"\x00")
@@ -1,3 +1,4 @@
+import asyncio
 import httpx
 import json
 import pathlib
@@ -55,3 +56,20 @@ async def test_rsgi_ws_scope(rsgi_server, threading_mode):
 
     data = json.loads(res)
     assert data['scheme'] == 'https'
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("server_tls", ["asgi", "rsgi"], indirect=True)
+@pytest.mark.parametrize("protocols,selected", [(["h2", "http/1.1"], "h2"), (["http/1.1"], "http/1.1")])
+async def test_alpn(server_tls, protocols, selected):
+    ssl_context = ssl.create_default_context()
+    ssl_context.check_hostname = False
+    ssl_context.verify_mode = ssl.CERT_NONE
+    ssl_context.set_alpn_protocols(protocols)
+
+    async with server_tls("runtime") as port:
+        _, writer = await asyncio.open_connection("localhost", port, ssl=ssl_context)
+        negotiated = writer.get_extra_info("ssl_object").selected_alpn_protocol()
+        writer.close()
+
+    assert negotiated == selected
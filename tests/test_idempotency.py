@@ -0,0 +1,111 @@
+import asyncio
+
+import pytest
+
+from granian.testing import MockClock, TestClient
+
+
+def _app(calls, size=None, stream=False, delay=0):
+    async def app(scope, proto):
+        body = await proto()
+        calls.append(body)
+        if delay:
+            await asyncio.sleep(delay)
+        text = "x" * size if size else str(len(calls))
+        headers = [("set-cookie", f"session={len(calls)}")]
+        if stream:
+            transport = proto.response_stream(200, headers)
+            await transport.send_str(text)
+            return
+        proto.response_str(200, headers, text)
+
+    return app
+
+
+@pytest.mark.asyncio
+async def test_idempotency_replay():
+    calls = []
+    headers = {"idempotency-key": "key"}
+    async with TestClient(_app(calls), idempotency_ttl=60) as client:
+        res = await client.post("/", headers=headers, body=b"data")
+        res_replay = await client.post("/", headers=headers, body=b"data")
+        res_other = await client.post("/other", headers=headers, body=b"data")
+
+    assert res.text == "1"
+    assert res.header("set-cookie") == "session=1"
+    assert res.header("idempotent-replayed") is None
+    assert res_replay.text == "1"
+    assert res_replay.header("idempotent-replayed") == "true"
+    assert res_replay.header("set-cookie") is None
+    assert res_other.text == "2"
+    assert calls == [b"data", b"data"]
+
+
+@pytest.mark.asyncio
+async def test_idempotency_body_mismatch():
+    calls = []
+    headers = {"idempotency-key": "key"}
+    async with TestClient(_app(calls), idempotency_ttl=60) as client:
+        res = await client.post("/", headers=headers, body=b"data")
+        res_mismatch = await client.post("/", headers=headers, body=b"other")
+
+    assert res.status_code == 200
+    assert res_mismatch.status_code == 422
+    assert calls == [b"data"]
+
+
+@pytest.mark.asyncio
+async def test_idempotency_caller_scoped():
+    calls = []
+    async with TestClient(_app(calls), idempotency_ttl=60) as client:
+        res_alice = await client.post("/", headers={"idempotency-key": "key", "authorization": "Bearer alice"})
+        res_bob = await client.post("/", headers={"idempotency-key": "key", "authorization": "Bearer bob"})
+        res_cookie = await client.post("/", headers={"idempotency-key": "key", "cookie": "session=bob"})
+        res_alice_retry = await client.post("/", headers={"idempotency-key": "key", "authorization": "Bearer alice"})
+
+    assert [res_alice.text, res_bob.text, res_cookie.text] == ["1", "2", "3"]
+    assert res_alice_retry.text == "1"
+    assert res_alice_retry.header("idempotent-replayed") == "true"
+
+
+@pytest.mark.asyncio
+async def test_idempotency_coalescing():
+    calls = []
+    headers = {"idempotency-key": "key"}
+    async with TestClient(_app(calls, delay=0.2), idempotency_ttl=60) as client:
+        responses = await asyncio.gather(*[client.post("/", headers=headers, body=b"data") for _ in range(3)])
+
+    assert len(calls) == 1
+    assert [res.text for res in responses] == ["1"] * 3
+    assert sorted(res.header("idempotent-replayed") or "" for res in responses) == ["", "true", "true"]
+
+
+@pytest.mark.asyncio
+async def test_idempotency_expiry():
+    calls = []
+    headers = {"idempotency-key": "key"}
+    with MockClock() as clock:
+        async with TestClient(_app(calls), idempotency_ttl=60) as client:
+            res = await client.post("/", headers=headers, body=b"data")
+            clock.advance(61)
+            res_expired = await client.post("/", headers=headers, body=b"other")
+
+    assert res.text == "1"
+    assert res_expired.status_code == 200
+    assert res_expired.text == "2"
+    assert res_expired.header("idempotent-replayed") is None
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["size", "stream"], [(2 * 1024 * 1024, False), (None, True)])
+async def test_idempotency_uncached(size, stream):
+    calls = []
+    headers = {"idempotency-key": "key"}
+    async with TestClient(_app(calls, size=size, stream=stream), idempotency_ttl=60) as client:
+        res = await client.post("/", headers=headers)
+        res_retry = await client.post("/", headers=headers)
+
+    assert res.status_code == 200
+    assert res_retry.status_code == 200
+    assert res_retry.header("idempotent-replayed") is None
+    assert len(calls) == 2
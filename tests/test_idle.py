@@ -0,0 +1,55 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], "ok")
+
+
+async def _closed(reader, timeout=2):
+    return await asyncio.wait_for(reader.read(), timeout) == b""
+
+
+@pytest.mark.asyncio
+async def test_idle_connection_closed():
+    async with TestServer(rsgi_app, "rsgi", idle_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        assert await _closed(reader)
+        writer.close()
+
+
+@pytest.mark.asyncio
+async def test_partial_head_closed():
+    async with TestServer(rsgi_app, "rsgi", idle_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n")
+        assert await _closed(reader)
+        writer.close()
+
+
+@pytest.mark.asyncio
+async def test_keep_alive_not_timed_out():
+    async with TestServer(rsgi_app, "rsgi", idle_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        for _ in range(2):
+            writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            await asyncio.wait_for(reader.readuntil(b"ok"), 2)
+            await asyncio.sleep(0.4)
+        writer.close()
+
+
+@pytest.mark.asyncio
+async def test_idle_timeout_disabled():
+    async with TestServer(rsgi_app, "rsgi", idle_timeout=0) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        with pytest.raises(asyncio.TimeoutError):
+            await asyncio.wait_for(reader.read(), 0.5)
+        writer.close()
+
+
+def test_invalid_idle_timeout():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", idle_timeout=-1)
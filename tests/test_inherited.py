@@ -0,0 +1,85 @@
+import asyncio
+import os
+import socket
+
+import pytest
+
+from granian._granian import ListenerHolder
+from granian.net import systemd_listen_fds
+from granian.server import Granian
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], scope.server)
+
+
+async def _get(reader, writer):
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    response = await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    return int(head.split(b" ", 2)[1]), body
+
+
+@pytest.mark.asyncio
+async def test_tcp_socket():
+    sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+    sock.bind(("127.0.0.1", 0))
+    sock.listen(16)
+    port = sock.getsockname()[1]
+
+    async with TestServer(rsgi_app, "rsgi", fd=sock.detach()):
+        status, body = await _get(*await asyncio.open_connection("127.0.0.1", port))
+
+    assert status == 200
+    assert body == f"127.0.0.1:{port}".encode()
+
+
+@pytest.mark.asyncio
+async def test_unix_socket(tmp_path):
+    path = str(tmp_path / "granian.sock")
+    sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    sock.bind(path)
+    sock.listen(16)
+
+    async with TestServer(rsgi_app, "rsgi", fd=sock.detach()):
+        status, body = await _get(*await asyncio.open_unix_connection(path))
+
+    assert status == 200
+    assert body == b""
+
+
+def test_not_listening():
+    sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+
+    with pytest.raises(Exception, match="not a listening socket"):
+        ListenerHolder.from_fd(sock.fileno())
+    # left open for the owner to close
+    assert os.fstat(sock.fileno())
+    sock.close()
+
+
+def test_systemd_listen_fds(monkeypatch):
+    monkeypatch.setenv("LISTEN_PID", str(os.getpid()))
+    monkeypatch.setenv("LISTEN_FDS", "2")
+
+    assert systemd_listen_fds() == [3, 4]
+    assert "LISTEN_PID" not in os.environ
+    assert "LISTEN_FDS" not in os.environ
+
+
+def test_systemd_listen_fds_other_process(monkeypatch):
+    monkeypatch.setenv("LISTEN_PID", str(os.getpid() + 1))
+    monkeypatch.setenv("LISTEN_FDS", "1")
+
+    assert systemd_listen_fds() == []
+    assert os.environ["LISTEN_FDS"] == "1"
+
+
+def test_explicit_fd_precedence(monkeypatch):
+    monkeypatch.setenv("LISTEN_PID", str(os.getpid()))
+    monkeypatch.setenv("LISTEN_FDS", "1")
+
+    assert Granian("tests.apps.rsgi:app", fd=10)._inherited_fd() == 10
+    assert Granian("tests.apps.rsgi:app")._inherited_fd() == 3
@@ -0,0 +1,95 @@
+import subprocess
+import sys
+
+import pytest
+
+from granian._internal import detect_interface
+from granian.constants import Interfaces
+from granian.errors import ConfigurationError, StartupError
+from granian.testing import TestClient
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"text/plain")]})
+    await send({"type": "http.response.body", "body": b"asgi"})
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], "rsgi")
+
+
+def wsgi_app(environ, start_response):
+    start_response("200 OK", [("content-type", "text/plain")])
+    return [b"wsgi"]
+
+
+class ASGIApp:
+    async def __call__(self, scope, receive, send, extra=None):
+        await asgi_app(scope, receive, send)
+
+
+class DualApp(ASGIApp):
+    async def __rsgi__(self, scope, proto):
+        await rsgi_app(scope, proto)
+
+
+async def ambiguous_app(*args):
+    pass
+
+
+def unknown_app(scope, receive, send):
+    pass
+
+
+@pytest.mark.parametrize(
+    ["app", "interface"],
+    [
+        (asgi_app, Interfaces.ASGI),
+        (rsgi_app, Interfaces.RSGI),
+        (wsgi_app, Interfaces.WSGI),
+        (ASGIApp(), Interfaces.ASGI),
+        (DualApp(), Interfaces.RSGI)
+    ]
+)
+def test_detect(app, interface):
+    assert detect_interface(app) == interface
+
+
+@pytest.mark.parametrize(
+    ["app", "message"],
+    [
+        (ambiguous_app, "is ambiguous, matching ASGI and RSGI"),
+        (unknown_app, "matches none of ASGI 3, RSGI and WSGI"),
+        ("app", "it is not callable")
+    ]
+)
+def test_detect_failure(app, message):
+    with pytest.raises(ConfigurationError) as exc:
+        detect_interface(app)
+
+    assert message in str(exc.value)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("app", [asgi_app, rsgi_app, wsgi_app, DualApp()])
+async def test_client(app):
+    async with TestClient(app, "auto") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 200
+    assert res.text == ("rsgi" if isinstance(app, DualApp) else app.__name__[:4])
+
+
+def test_ambiguous_stops_server():
+    proc = subprocess.run(
+        [
+            sys.executable, "-c",
+            "from granian import Granian; "
+            "Granian('tests.test_interface_detection:ambiguous_app', interface='auto', port=0, workers=1).serve()"
+        ],
+        capture_output=True,
+        timeout=30
+    )
+
+    assert proc.returncode == StartupError.exit_status
+    assert b"is ambiguous, matching ASGI and RSGI" in proc.stdout
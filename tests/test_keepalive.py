@@ -0,0 +1,189 @@
+import asyncio
+import base64
+import os
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    if scope.proto == "ws":
+        trx = await proto.accept()
+        message = await trx.receive()
+        await trx.send_str(message.data)
+        return
+    proto.response_bytes(200, [("content-type", "text/plain")], b"hello")
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    for idx in range(3):
+        await asyncio.sleep(0.3)
+        await send({"type": "http.response.body", "body": b"chunk %d\n" % idx, "more_body": True})
+    await send({"type": "http.response.body", "body": b""})
+
+
+def _server(app=rsgi_app, interface="rsgi", **kwargs):
+    return TestServer(app, interface, idle_timeout=0, **kwargs)
+
+
+async def _response(reader, timeout=2):
+    head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), timeout)
+    lines = head.decode().rstrip("\r\n").split("\r\n")
+    headers = dict(line.lower().split(": ", 1) for line in lines[1:])
+    if "content-length" in headers:
+        return int(lines[0].split(" ")[1]), headers, await reader.readexactly(int(headers["content-length"]))
+    body = b""
+    while True:
+        size = int(await asyncio.wait_for(reader.readline(), timeout), 16)
+        chunk = await reader.readexactly(size + 2)
+        if not size:
+            return int(lines[0].split(" ")[1]), headers, body
+        body += chunk[:-2]
+
+
+async def _closed(reader, timeout=2):
+    return await asyncio.wait_for(reader.read(), timeout) == b""
+
+
+@pytest.mark.asyncio
+async def test_idle_timeout():
+    async with _server(keep_alive_timeout=0.3) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        status, _, _ = await _response(reader)
+        started = asyncio.get_running_loop().time()
+        closed = await _closed(reader)
+        elapsed = asyncio.get_running_loop().time() - started
+        writer.close()
+
+    assert status == 200
+    assert closed
+    assert 0.2 < elapsed < 1.5
+
+
+@pytest.mark.asyncio
+async def test_idle_timeout_reset():
+    async with _server(keep_alive_timeout=0.4) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        for _ in range(3):
+            writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            status, _, body = await _response(reader)
+            await asyncio.sleep(0.25)
+        writer.close()
+
+    assert status == 200
+    assert body == b"hello"
+
+
+@pytest.mark.asyncio
+async def test_streamed_response_not_timed_out():
+    async with _server(asgi_app, "asgi", keep_alive_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        status, _, body = await _response(reader)
+        writer.close()
+
+    assert status == 200
+    assert body == b"chunk 0\nchunk 1\nchunk 2\n"
+
+
+@pytest.mark.asyncio
+async def test_max_requests():
+    async with _server(keep_alive_max_requests=2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        _, first, _ = await _response(reader)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        status, second, body = await _response(reader)
+        closed = await _closed(reader)
+        writer.close()
+
+    assert "connection" not in first
+    assert status == 200
+    assert body == b"hello"
+    assert second["connection"] == "close"
+    assert closed
+
+
+@pytest.mark.asyncio
+async def test_header():
+    async with _server(keep_alive_timeout=5, keep_alive_max_requests=3, keep_alive_header=True) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        values = []
+        for _ in range(3):
+            writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            _, headers, _ = await _response(reader)
+            values.append(headers.get("keep-alive"))
+        writer.close()
+
+    assert values == ["timeout=5, max=2", "timeout=5, max=1", None]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "request_head",
+    [
+        b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+        b"GET / HTTP/1.0\r\nhost: localhost\r\n\r\n"
+    ]
+)
+async def test_header_not_persistent(request_head):
+    async with _server(keep_alive_header=True) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(request_head)
+        _, headers, _ = await _response(reader)
+        writer.close()
+
+    assert "keep-alive" not in headers
+
+
+@pytest.mark.asyncio
+async def test_http10_keep_alive():
+    async with _server(keep_alive_header=True) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.0\r\nhost: localhost\r\nconnection: keep-alive\r\n\r\n")
+        _, headers, _ = await _response(reader)
+        writer.close()
+
+    assert headers["keep-alive"] == "timeout=30"
+
+
+@pytest.mark.asyncio
+async def test_websocket_not_timed_out():
+    key = base64.b64encode(os.urandom(16)).decode()
+    async with _server(keep_alive_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(
+            f"GET / HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\nconnection: upgrade\r\n"
+            f"sec-websocket-key: {key}\r\nsec-websocket-version: 13\r\n\r\n".encode()
+        )
+        head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 2)
+        await asyncio.sleep(0.5)
+        mask = os.urandom(4)
+        writer.write(bytes([0x81, 0x80 | 5]) + mask + bytes(byte ^ mask[idx % 4] for idx, byte in enumerate(b"hello")))
+        frame = await asyncio.wait_for(reader.readexactly(7), 2)
+        writer.close()
+
+    assert head.startswith(b"HTTP/1.1 101")
+    assert frame == b"\x81\x05hello"
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    async with _server(keep_alive_timeout=0) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        await _response(reader)
+        await asyncio.sleep(0.5)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        status, _, _ = await _response(reader)
+        writer.close()
+
+    assert status == 200
+
+
+def test_invalid_timeout():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", keep_alive_timeout=-1)
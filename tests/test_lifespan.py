@@ -0,0 +1,112 @@
+import subprocess
+import sys
+
+import pytest
+
+from granian.errors import StartupError
+from granian.testing import TestClient
+
+
+events = []
+
+
+async def app(scope, receive, send):
+    if scope["type"] == "lifespan":
+        while True:
+            message = await receive()
+            events.append(message["type"])
+            if message["type"] == "lifespan.startup":
+                scope["state"]["greeting"] = "hello"
+                await send({"type": "lifespan.startup.complete"})
+            else:
+                await send({"type": "lifespan.shutdown.complete"})
+                return
+    body = f"{scope['state'].get('greeting')} {scope['state'].get('leaked')}"
+    scope["state"]["leaked"] = True
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": body.encode()})
+
+
+async def counting_app(scope, receive, send):
+    if scope["type"] == "lifespan":
+        await receive()
+        scope["state"]["hits"] = []
+        await send({"type": "lifespan.startup.complete"})
+        await receive()
+        await send({"type": "lifespan.shutdown.complete"})
+        return
+    scope["state"]["hits"].append(scope["path"])
+    body = ",".join(scope["state"]["hits"])
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": body.encode()})
+
+
+async def failing_app(scope, receive, send):
+    assert scope["type"] == "lifespan"
+    await receive()
+    await send({"type": "lifespan.startup.failed", "message": "database unreachable"})
+
+
+async def unsupported_app(scope, receive, send):
+    assert scope["type"] == "http"
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": b"ok"})
+
+
+@pytest.mark.asyncio
+async def test_lifespan_events():
+    events.clear()
+    async with TestClient(app, "asgi") as client:
+        assert events == ["lifespan.startup"]
+        await client.get("/")
+
+    assert events == ["lifespan.startup", "lifespan.shutdown"]
+
+
+@pytest.mark.asyncio
+async def test_lifespan_state():
+    async with TestClient(app, "asgi") as client:
+        first = await client.get("/")
+        second = await client.get("/")
+
+    assert first.text == "hello None"
+    assert second.text == "hello None"
+
+
+@pytest.mark.asyncio
+async def test_lifespan_state_shared_values():
+    async with TestClient(counting_app, "asgi") as client:
+        await client.get("/a")
+        res = await client.get("/b")
+
+    assert res.text == "/a,/b"
+
+
+@pytest.mark.asyncio
+async def test_lifespan_startup_failed():
+    with pytest.raises(RuntimeError):
+        async with TestClient(failing_app, "asgi"):
+            pass
+
+
+@pytest.mark.asyncio
+async def test_lifespan_unsupported():
+    async with TestClient(unsupported_app, "asgi") as client:
+        res = await client.get("/")
+
+    assert res.text == "ok"
+
+
+def test_startup_failure_stops_server():
+    proc = subprocess.run(
+        [
+            sys.executable, "-c",
+            "from granian import Granian; "
+            "Granian('tests.test_lifespan:failing_app', interface='asgi', port=0, workers=1).serve()"
+        ],
+        capture_output=True,
+        timeout=30
+    )
+
+    assert proc.returncode == StartupError.exit_status
+    assert b"ASGI application startup failed: database unreachable" in proc.stdout
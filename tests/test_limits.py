@@ -0,0 +1,166 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    body = await proto()
+    proto.response_bytes(200, [("content-type", "text/plain")], b"received %d" % len(body))
+
+
+async def asgi_app(scope, receive, send):
+    body, more = b"", True
+    while more:
+        message = await receive()
+        if message["type"] == "http.disconnect":
+            return
+        body, more = body + message.get("body", b""), message.get("more_body", False)
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": b"received %d" % len(body)})
+
+
+def wsgi_app(environ, start_response):
+    body = environ["wsgi.input"]
+    start_response("200 OK", [("content-type", "text/plain")])
+    return [b"received %d" % len(body)]
+
+
+INTERFACES = [("rsgi", rsgi_app), ("asgi", asgi_app), ("wsgi", wsgi_app)]
+
+
+def _chunked(data, size=1000):
+    chunks = [data[idx:idx + size] for idx in range(0, len(data), size)]
+    return b"".join(b"%x\r\n%s\r\n" % (len(chunk), chunk) for chunk in chunks) + b"0\r\n\r\n"
+
+
+async def _request(head, body=b"", app=rsgi_app, interface="rsgi", **kwargs):
+    async with TestServer(app, interface, **kwargs) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n" + head + b"\r\n" + body)
+        try:
+            await writer.drain()
+        except ConnectionError:
+            pass
+        response = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    return int(lines[0].split(" ")[1]), dict(line.lower().split(": ", 1) for line in lines[1:]), body
+
+
+async def _get(target=b"/", headers=b"", app=rsgi_app, interface="rsgi", **kwargs):
+    async with TestServer(app, interface, **kwargs) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET " + target + b" HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n" + headers + b"\r\n")
+        response = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+    return int(response.split(b" ", 2)[1])
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_content_length_over(interface, app):
+    # no body is sent, the declared length alone gets the request rejected
+    status, headers, _ = await _request(b"content-length: 10000\r\n", app=app, interface=interface, max_body_size=1000)
+
+    assert status == 413
+    assert headers["connection"] == "close"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_content_length_within(interface, app):
+    status, _, body = await _request(
+        b"content-length: 1000\r\n", b"x" * 1000, app=app, interface=interface, max_body_size=1000
+    )
+
+    assert status == 200
+    assert body == b"received 1000"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_chunked_over(interface, app):
+    status, headers, _ = await _request(
+        b"transfer-encoding: chunked\r\n", _chunked(b"x" * 5000), app=app, interface=interface, max_body_size=1000
+    )
+
+    assert status == 413
+    assert headers["connection"] == "close"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_chunked_within(interface, app):
+    status, _, body = await _request(
+        b"transfer-encoding: chunked\r\n", _chunked(b"x" * 3000), app=app, interface=interface, max_body_size=3000
+    )
+
+    assert status == 200
+    assert body == b"received 3000"
+
+
+@pytest.mark.asyncio
+async def test_chunked_aborted():
+    seen = []
+
+    async def app(scope, proto):
+        try:
+            async for chunk in proto:
+                seen.append(len(chunk))
+        finally:
+            proto.response_empty(200, [])
+
+    status, _, _ = await _request(
+        b"transfer-encoding: chunked\r\n", _chunked(b"x" * 5000), app=app, max_body_size=2500
+    )
+
+    assert status == 413
+    assert sum(seen) <= 2500
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    status, _, body = await _request(b"transfer-encoding: chunked\r\n", _chunked(b"x" * 100000))
+
+    assert status == 200
+    assert body == b"received 100000"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_header_size(interface, app):
+    headers = b"".join(b"x-field-%d: %s\r\n" % (idx, b"v" * 100) for idx in range(10))
+
+    assert await _get(headers=headers, app=app, interface=interface, max_header_size=2000) == 200
+    assert await _get(headers=headers, app=app, interface=interface, max_header_size=1000) == 431
+
+
+@pytest.mark.asyncio
+async def test_header_count():
+    headers = b"".join(b"x-field-%d: value\r\n" % idx for idx in range(20))
+
+    assert await _get(headers=headers, max_headers=30) == 200
+    assert await _get(headers=headers, max_headers=10) == 431
+
+
+@pytest.mark.asyncio
+async def test_uri_length():
+    target = b"/" + b"a" * 500 + b"?q=" + b"b" * 500
+
+    assert await _get(target, max_uri_length=2000) == 200
+    assert await _get(target, max_uri_length=1000) == 414
+
+
+@pytest.mark.asyncio
+async def test_head_limits_disabled():
+    headers = b"".join(b"x-field-%d: %s\r\n" % (idx, b"v" * 1000) for idx in range(90))
+
+    assert await _get(b"/" + b"a" * 20000, headers, max_header_size=0, max_headers=0, max_uri_length=0) == 200
+
+
+def test_invalid_header_count():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", max_headers=101)
@@ -0,0 +1,129 @@
+import json
+import logging
+import multiprocessing
+import socket
+import sys
+
+import pytest
+
+from granian.admin import AdminServer
+from granian.log import JSONFormatter, LogLevels, log_target, set_log_level
+from granian.server import Granian
+
+
+@pytest.mark.parametrize(
+    ("name", "expected"),
+    [
+        ("granian::ws", "_granian.ws"),
+        ("granian", "granian"),
+        ("_granian", "_granian"),
+        ("tokio::runtime", "tokio.runtime"),
+        ("myapp.db", "myapp.db"),
+        ("root", "")
+    ]
+)
+def test_log_target(name, expected):
+    assert log_target(name) == expected
+
+
+def test_set_log_level():
+    set_log_level("granian::ws", LogLevels.debug)
+    assert logging.getLogger("_granian.ws").level == logging.DEBUG
+    set_log_level("granian::ws", None)
+    assert logging.getLogger("_granian.ws").level == logging.NOTSET
+
+    with pytest.raises(ValueError):
+        set_log_level("root", None)
+
+
+def _record(name, msg, exc_info=None, **attrs):
+    record = logging.LogRecord(name, logging.WARNING, __file__, 1, msg, (), exc_info)
+    record.__dict__.update(attrs)
+    return record
+
+
+def test_json_formatter():
+    formatter = JSONFormatter()
+    data = json.loads(formatter.format(_record("myapp", 'said "hi"', worker_id=2, worker_generation=1)))
+
+    assert data.pop("time").endswith("Z")
+    assert data == {"level": "warning", "logger": "myapp", "message": 'said "hi"', "worker": 2, "generation": 1}
+
+
+def test_json_formatter_exception():
+    try:
+        raise RuntimeError("boom")
+    except RuntimeError:
+        data = json.loads(JSONFormatter().format(_record("myapp", "failed", sys.exc_info())))
+
+    assert data["message"] == "failed"
+    assert data["exception"].endswith("RuntimeError: boom")
+    assert "worker" not in data
+
+
+def test_json_formatter_access_records():
+    formatter = JSONFormatter()
+    line = '{"event":"request","status":200}'
+
+    assert formatter.format(_record("granian.access", line)) == line
+    assert json.loads(formatter.format(_record("granian.access", "GET / 200")))["message"] == "GET / 200"
+
+
+def _command(path, *lines):
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+        sock.connect(path)
+        reader = sock.makefile("rb")
+        rv = []
+        for line in lines:
+            sock.sendall(line.encode("utf8") + b"\n")
+            rv.append(reader.readline().decode("utf8").strip())
+    return rv
+
+
+def test_admin_log_level(tmp_path):
+    path = str(tmp_path / "admin.sock")
+    server = Granian("tests.apps.asgi:app", log_targets={"myapp": "warning"})
+    control, server._controls[1] = multiprocessing.Pipe(duplex=False)
+    admin = AdminServer(path, {
+        "log-level": server._admin_log_level,
+        "log-levels": server._admin_log_levels
+    })
+    admin.start()
+    try:
+        replies = _command(
+            path,
+            "log-level granian::ws debug",
+            "log-level granian::ws verbose",
+            "log-levels",
+            "log-level myapp reset",
+            "log-levels",
+            "reload"
+        )
+    finally:
+        admin.stop()
+
+    assert replies[0] == "ok"
+    assert replies[1].startswith("error")
+    assert replies[2] == "ok root=info granian::ws=debug myapp=warning"
+    assert replies[3] == "ok"
+    assert replies[4] == "ok root=info granian::ws=debug"
+    assert replies[5] == "error unknown command reload"
+    assert control.recv() == ("log-level", ("granian::ws", LogLevels.debug))
+    assert control.recv() == ("log-level", ("myapp", None))
+    assert logging.getLogger("_granian.ws").level == logging.DEBUG
+    assert logging.getLogger("myapp").level == logging.NOTSET
+    set_log_level("granian::ws", None)
+
+
+def test_admin_stale_socket(tmp_path):
+    path = str(tmp_path / "admin.sock")
+    stale = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    stale.bind(path)
+    stale.close()
+
+    admin = AdminServer(path, {})
+    admin.start()
+    try:
+        assert _command(path, "log-levels") == ["error unknown command log-levels"]
+    finally:
+        admin.stop()
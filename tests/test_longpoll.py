@@ -0,0 +1,88 @@
+import asyncio
+
+import httpx
+import pytest
+
+from granian._granian import metrics
+from granian.testing import TestServer
+
+
+released = set()
+
+
+async def _wait(channel):
+    while channel not in released:
+        await asyncio.sleep(0.01)
+
+
+async def rsgi_app(scope, proto):
+    if scope.method == "POST":
+        released.add(scope.path.rsplit("/", 1)[-1])
+        proto.response_empty(204, [])
+        return
+    await _wait(scope.path.rsplit("/", 1)[-1])
+    proto.response_str(200, [("content-type", "text/plain")], scope.headers.get("x-client", ""))
+
+
+async def asgi_app(scope, receive, send):
+    channel = scope["path"].rsplit("/", 1)[-1]
+    if scope["method"] == "POST":
+        released.add(channel)
+        await send({"type": "http.response.start", "status": 204, "headers": []})
+        await send({"type": "http.response.body", "body": b""})
+        return
+    await _wait(channel)
+    headers = dict(scope["headers"])
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"text/plain")]})
+    await send({"type": "http.response.body", "body": headers.get(b"x-client", b"")})
+
+
+INTERFACES = [("rsgi", rsgi_app), ("asgi", asgi_app)]
+
+
+def _parked():
+    line = next(line for line in metrics().splitlines() if line.startswith("granian_long_polls_parked"))
+    return int(line.split()[1])
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_long_poll_parked(interface, app):
+    released.clear()
+    async with TestServer(app, interface, long_poll_routes=["/events/{channel}"]) as server:
+        async with httpx.AsyncClient(limits=httpx.Limits(max_connections=10)) as client:
+            polls = [
+                asyncio.ensure_future(client.get(f"{server.url}/events/news", headers={"x-client": str(idx)}))
+                for idx in range(5)
+            ]
+            await asyncio.sleep(0.2)
+            parked = _parked()
+            res_notify = await client.post(f"{server.url}/events/news")
+            responses = await asyncio.gather(*polls)
+
+    assert parked == 5
+    assert res_notify.status_code == 204
+    assert [res.status_code for res in responses] == [200] * 5
+    assert [res.text for res in responses] == [str(idx) for idx in range(5)]
+    assert _parked() == 0
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_long_poll_other_routes(interface, app):
+    released.clear()
+    async with TestServer(app, interface, long_poll_routes=["/events/{channel}"]) as server:
+        async with httpx.AsyncClient() as client:
+            poll = asyncio.ensure_future(client.get(f"{server.url}/updates/news", headers={"x-client": "1"}))
+            await asyncio.sleep(0.2)
+            parked = _parked()
+            await client.post(f"{server.url}/updates/news")
+            res = await poll
+
+    assert parked == 0
+    assert res.text == "1"
+
+
+def test_long_poll_invalid_route():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", long_poll_routes=["events"])
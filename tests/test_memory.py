@@ -0,0 +1,74 @@
+import multiprocessing
+import os
+import sys
+import time
+
+import pytest
+
+from granian._granian import metrics, process_memory
+from granian.server import Granian
+
+
+linux_only = pytest.mark.skipif(not sys.platform.startswith("linux"), reason="RSS is only read on Linux")
+
+
+_booted = multiprocessing.SimpleQueue()
+
+
+# Reports the memory recycles count it got, just like the real workers expose
+def _idle_worker(worker_id, worker_generation, memory_recycles, *args):
+    _booted.put((worker_id, worker_generation, memory_recycles))
+    time.sleep(30)
+
+
+@linux_only
+def test_process_memory():
+    assert process_memory(os.getpid()) > 1024 * 1024
+
+
+@linux_only
+def test_memory_metrics():
+    rendered = metrics()
+    assert 'granian_worker_memory_recycles_total{worker="0"} 0' in rendered
+    rss = [
+        line for line in rendered.splitlines()
+        if line.startswith("granian_worker_resident_memory_bytes")
+    ]
+    assert len(rss) == 1
+    assert int(rss[0].split()[1]) > 0
+
+
+@linux_only
+def test_recycle_workers():
+    server = Granian("tests.apps.asgi:app", workers_max_rss=1)
+    server._spawn_args = (_idle_worker, None, lambda: None)
+    server.procs.append(server._boot_worker(1))
+    original = server.procs[0]
+    try:
+        server._recycle_workers()
+        replacement = server.procs[0]
+
+        assert replacement.pid != original.pid
+        assert server.generations[1] == 2
+        assert server.memory_recycles[1] == 1
+        assert sorted([_booted.get(), _booted.get()]) == [(1, 1, 0), (1, 2, 1)]
+        original.join(5)
+        assert not original.is_alive()
+        server._reap_retiring()
+        assert server._retiring == []
+    finally:
+        server.shutdown()
+
+
+@linux_only
+def test_no_recycle_below_ceiling():
+    server = Granian("tests.apps.asgi:app", workers_max_rss=1024 * 1024)
+    server._spawn_args = (_idle_worker, None, lambda: None)
+    server.procs.append(server._boot_worker(1))
+    original = server.procs[0]
+    try:
+        server._recycle_workers()
+        assert server.procs[0] is original
+        assert server.memory_recycles == {}
+    finally:
+        server.shutdown()
@@ -0,0 +1,49 @@
+import httpx
+import pytest
+
+from granian._granian import metrics
+from granian.testing import TestServer
+
+
+calls = []
+
+
+async def rsgi_app(scope, proto):
+    calls.append(scope.path)
+    proto.response_str(200, [("content-type", "text/plain")], scope.path)
+
+
+def test_uptime_metric():
+    uptime = [line for line in metrics().splitlines() if line.startswith("granian_worker_uptime_seconds")]
+    assert len(uptime) == 1
+    assert float(uptime[0].split()[1]) > 0
+
+
+@pytest.mark.asyncio
+async def test_metrics_path():
+    calls.clear()
+    async with TestServer(rsgi_app, "rsgi", metrics_path="/metrics") as server:
+        async with httpx.AsyncClient() as client:
+            await client.get(f"{server.url}/")
+            res = await client.get(f"{server.url}/metrics")
+            res_post = await client.post(f"{server.url}/metrics")
+
+    assert calls == ["/"]
+    assert res.status_code == 200
+    assert res.headers["content-type"] == "text/plain; version=0.0.4; charset=utf-8"
+    assert 'granian_requests_total{worker="0",status="2xx"}' in res.text
+    assert "granian_requests_in_flight" in res.text
+    assert "granian_request_duration_seconds_bucket" in res.text
+    assert res_post.status_code == 405
+    assert res_post.headers["allow"] == "GET, HEAD"
+
+
+@pytest.mark.asyncio
+async def test_metrics_path_disabled():
+    calls.clear()
+    async with TestServer(rsgi_app, "rsgi") as server:
+        async with httpx.AsyncClient() as client:
+            res = await client.get(f"{server.url}/metrics")
+
+    assert calls == ["/metrics"]
+    assert res.text == "/metrics"
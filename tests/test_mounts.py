@@ -0,0 +1,104 @@
+import asyncio
+
+import pytest
+
+from granian.mounts import MountedApps
+from granian.testing import TestClient
+
+
+events = []
+
+
+def _app(name, startup=None):
+    async def app(scope, receive, send):
+        if scope["type"] == "lifespan":
+            while True:
+                message = await receive()
+                events.append(f"{name}:{message['type']}")
+                if message["type"] == "lifespan.startup":
+                    if startup is not None and not await startup():
+                        await send({"type": "lifespan.startup.failed", "message": "database unreachable"})
+                        return
+                    scope["state"]["name"] = name
+                    await send({"type": "lifespan.startup.complete"})
+                else:
+                    await send({"type": "lifespan.shutdown.complete"})
+                    return
+        body = f"{scope['state'].get('name')} {scope['root_path']} {scope['path']}"
+        await send({"type": "http.response.start", "status": 200, "headers": []})
+        await send({"type": "http.response.body", "body": body.encode()})
+    return app
+
+
+async def _fail():
+    return False
+
+
+async def _hang():
+    await asyncio.sleep(10)
+    return True
+
+
+@pytest.mark.asyncio
+async def test_mounts_dispatch():
+    app = MountedApps(_app("main"), {"/api": _app("api"), "/api/v2/": _app("v2")})
+    async with TestClient(app, "asgi") as client:
+        main = await client.get("/apis")
+        api = await client.get("/api/items")
+        nested = await client.get("/api/v2/items")
+
+    assert main.text == "main  /apis"
+    assert api.text == "api /api /api/items"
+    assert nested.text == "v2 /api/v2 /api/v2/items"
+
+
+@pytest.mark.asyncio
+async def test_mounts_lifespan():
+    events.clear()
+    app = MountedApps(_app("main"), {"/api": _app("api")})
+    async with TestClient(app, "asgi"):
+        assert sorted(events) == ["api:lifespan.startup", "main:lifespan.startup"]
+
+    assert sorted(events[2:]) == ["api:lifespan.shutdown", "main:lifespan.shutdown"]
+
+
+@pytest.mark.asyncio
+async def test_mounts_startup_failure():
+    events.clear()
+    app = MountedApps(_app("main"), {"/api": _app("api", _fail)})
+    with pytest.raises(RuntimeError):
+        async with TestClient(app, "asgi"):
+            pass
+
+    assert "main:lifespan.shutdown" in events
+
+
+@pytest.mark.asyncio
+async def test_mounts_startup_failure_disable():
+    app = MountedApps(_app("main"), {"/api": _app("api", _fail)}, failure="disable")
+    async with TestClient(app, "asgi") as client:
+        main = await client.get("/")
+        api = await client.get("/api")
+
+    assert main.status_code == 200
+    assert api.status_code == 503
+
+
+@pytest.mark.asyncio
+async def test_mounts_startup_timeout():
+    app = MountedApps(_app("main"), {"/api": _app("api", _hang)}, startup_timeout=0.2, failure="disable")
+    started = asyncio.get_running_loop().time()
+    async with TestClient(app, "asgi") as client:
+        elapsed = asyncio.get_running_loop().time() - started
+        api = await client.get("/api/items")
+
+    assert elapsed < 2
+    assert api.status_code == 503
+
+
+@pytest.mark.asyncio
+async def test_mounts_root_failure():
+    app = MountedApps(_app("main", _fail), {"/api": _app("api")}, failure="disable")
+    with pytest.raises(RuntimeError):
+        async with TestClient(app, "asgi"):
+            pass
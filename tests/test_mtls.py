@@ -0,0 +1,141 @@
+import asyncio
+import json
+import pathlib
+import ssl
+
+import pytest
+
+from granian.testing import TestServer
+
+
+FIXTURES = pathlib.Path.cwd() / "tests" / "fixtures" / "tls"
+SUBJECT = "CN=client\\, one,OU=Tests,O=Granian,C=IT"
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "application/json")], json.dumps({
+        "cert": scope.client_cert.hex() if scope.client_cert else None,
+        "subject": scope.client_cert_subject,
+        "verify": scope.client_cert_verify
+    }))
+
+
+async def asgi_app(scope, receive, send):
+    tls = scope["extensions"].get("tls")
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"content-type", b"application/json")]})
+    await send({"type": "http.response.body", "body": json.dumps(tls).encode()})
+
+
+def _server(app, interface, **kwargs):
+    return TestServer(
+        app,
+        interface,
+        ssl_cert=FIXTURES / "cert.pem",
+        ssl_key=FIXTURES / "key.pem",
+        ssl_ca=FIXTURES / "ca.pem",
+        **kwargs
+    )
+
+
+async def _get(server, cert=None, key=None):
+    ssl_context = ssl.create_default_context(cafile=str(FIXTURES / "cert.pem"))
+    ssl_context.check_hostname = False
+    if cert:
+        ssl_context.load_cert_chain(FIXTURES / cert, FIXTURES / key)
+    reader, writer = await asyncio.open_connection(server.host, server.port, ssl=ssl_context)
+    try:
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        data = await asyncio.wait_for(reader.read(), 2)
+    finally:
+        writer.close()
+    head, _, body = data.partition(b"\r\n\r\n")
+    return int(head.split(b" ")[1]), json.loads(body)
+
+
+async def _refused(server, cert=None, key=None):
+    try:
+        await _get(server, cert, key)
+    except (ssl.SSLError, ConnectionError, IndexError):
+        return True
+    return False
+
+
+def _fixture_der(name):
+    return ssl.PEM_cert_to_DER_cert((FIXTURES / name).read_text())
+
+
+@pytest.mark.asyncio
+async def test_rsgi_client_cert():
+    async with _server(rsgi_app, "rsgi") as server:
+        status, data = await _get(server, "client.pem", "client-key.pem")
+
+    assert status == 200
+    assert data["cert"] == _fixture_der("client.pem").hex()
+    assert data["subject"] == SUBJECT
+    assert data["verify"] == "SUCCESS"
+
+
+@pytest.mark.asyncio
+async def test_asgi_client_cert():
+    async with _server(asgi_app, "asgi") as server:
+        status, data = await _get(server, "client.pem", "client-key.pem")
+
+    assert status == 200
+    assert len(data["client_cert_chain"]) == 1
+    assert ssl.PEM_cert_to_DER_cert(data["client_cert_chain"][0]) == _fixture_der("client.pem")
+    assert data["client_cert_name"] == SUBJECT
+    assert data["client_cert_error"] is None
+    assert data["tls_version"] in (0x0303, 0x0304)
+    assert data["cipher_suite"] > 0
+
+
+@pytest.mark.asyncio
+async def test_required_without_cert():
+    async with _server(rsgi_app, "rsgi") as server:
+        assert await _refused(server)
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("mode", ["required", "optional"])
+async def test_untrusted_cert(mode):
+    async with _server(rsgi_app, "rsgi", ssl_client_verify=mode) as server:
+        assert await _refused(server, "cert2.pem", "key2.pem")
+
+
+@pytest.mark.asyncio
+async def test_optional_without_cert():
+    async with _server(rsgi_app, "rsgi", ssl_client_verify="optional") as server:
+        status, data = await _get(server)
+        _, with_cert = await _get(server, "client.pem", "client-key.pem")
+
+    assert status == 200
+    assert data == {"cert": None, "subject": None, "verify": "NONE"}
+    assert with_cert["verify"] == "SUCCESS"
+
+
+@pytest.mark.asyncio
+async def test_asgi_without_client_auth():
+    async with TestServer(
+        asgi_app, "asgi", ssl_cert=FIXTURES / "cert.pem", ssl_key=FIXTURES / "key.pem"
+    ) as server:
+        _, data = await _get(server)
+
+    assert data["client_cert_chain"] == []
+    assert data["client_cert_name"] is None
+
+
+def test_invalid_mode():
+    with pytest.raises(ValueError):
+        _server(rsgi_app, "rsgi", ssl_client_verify="never")
+
+
+def test_invalid_ca(tmp_path):
+    (tmp_path / "ca.pem").write_text("not a bundle")
+    with pytest.raises(ValueError):
+        TestServer(
+            rsgi_app,
+            "rsgi",
+            ssl_cert=FIXTURES / "cert.pem",
+            ssl_key=FIXTURES / "key.pem",
+            ssl_ca=tmp_path / "ca.pem"
+        )
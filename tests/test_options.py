@@ -0,0 +1,71 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestClient, TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], f"{scope.method} {scope.path}")
+
+
+ROUTES = {"/users/{id}": ["get", "delete"], "/status": ["GET", "OPTIONS"]}
+
+
+@pytest.mark.asyncio
+async def test_asterisk():
+    async with TestClient(rsgi_app, "rsgi") as client:
+        res = await client.request("OPTIONS", "*")
+
+    assert res.status_code == 200
+    assert res.header("allow") == "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"
+    assert res.content == b""
+
+
+@pytest.mark.asyncio
+async def test_asterisk_configured():
+    async with TestClient(rsgi_app, "rsgi", options_routes={"*": ["GET", "POST"]}) as client:
+        res = await client.request("OPTIONS", "*")
+
+    assert res.status_code == 200
+    assert res.header("allow") == "GET, POST, OPTIONS"
+
+
+@pytest.mark.asyncio
+async def test_routes():
+    async with TestClient(rsgi_app, "rsgi", options_routes=ROUTES) as client:
+        res = await client.request("OPTIONS", "/users/1")
+        res_static = await client.request("OPTIONS", "/status")
+        res_unmatched = await client.request("OPTIONS", "/users")
+        res_get = await client.get("/users/1")
+
+    assert res.status_code == 200
+    assert res.header("allow") == "GET, DELETE, OPTIONS"
+    assert res_static.header("allow") == "GET, OPTIONS"
+    assert res_unmatched.text == "OPTIONS /users"
+    assert res_get.text == "GET /users/1"
+
+
+@pytest.mark.asyncio
+async def test_routes_preflight():
+    async with TestClient(rsgi_app, "rsgi", options_routes=ROUTES) as client:
+        res = await client.request("OPTIONS", "/users/1", headers={
+            "origin": "http://localhost",
+            "access-control-request-method": "DELETE"
+        })
+
+    assert res.status_code == 200
+    assert res.text == "OPTIONS /users/1"
+
+
+@pytest.mark.asyncio
+async def test_asterisk_server():
+    async with TestServer(rsgi_app, "rsgi") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"OPTIONS * HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        data = await reader.read()
+        writer.close()
+
+    head = data.split(b"\r\n\r\n")[0].lower()
+    assert head.startswith(b"http/1.1 200")
+    assert b"allow: get, head, post, put, patch, delete, options" in head
@@ -0,0 +1,151 @@
+import asyncio
+import json
+
+import pytest
+
+from granian.testing import TestServer
+
+
+TRACE_ID = "4bf92f3577b34da6a3ce929d0e0e4736"
+PARENT_ID = "00f067aa0ba902b7"
+
+
+async def rsgi_app(scope, proto):
+    if scope.path == "/fail":
+        raise RuntimeError("failed")
+    proto.response_str(200, [("x-traceparent", scope.headers.get("traceparent") or "")], "hello")
+
+
+async def asgi_app(scope, receive, send):
+    if scope["path"] == "/fail":
+        raise RuntimeError("failed")
+    traceparent = dict(scope["headers"]).get(b"traceparent", b"")
+    await send({"type": "http.response.start", "status": 200, "headers": [(b"x-traceparent", traceparent)]})
+    await send({"type": "http.response.body", "body": b"hello"})
+
+
+class Collector:
+    def __init__(self):
+        self.spans = []
+
+    async def handle(self, reader, writer):
+        head = await reader.readuntil(b"\r\n\r\n")
+        size = next(
+            int(line.split(b":", 1)[1]) for line in head.split(b"\r\n") if line.lower().startswith(b"content-length:")
+        )
+        payload = json.loads(await reader.readexactly(size))
+        for resource in payload["resourceSpans"]:
+            for scope in resource["scopeSpans"]:
+                self.spans.extend(scope["spans"])
+        writer.write(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}")
+        await writer.drain()
+        writer.close()
+
+    async def wait_for(self, name):
+        for _ in range(150):
+            if any(span["name"] == name for span in self.spans):
+                return {span["name"]: span for span in self.spans}
+            await asyncio.sleep(0.02)
+        raise AssertionError(f"span {name} not exported")
+
+
+async def _request(server, target, headers=()):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(f"GET {target} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n{head}\r\n".encode())
+    response = await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+    head = response.split(b"\r\n\r\n", 1)[0].decode().split("\r\n")
+    return int(head[0].split(" ")[1]), dict(line.lower().split(": ", 1) for line in head[1:])
+
+
+async def _traced(target, headers=(), span="connection", app=rsgi_app, interface="rsgi", **kwargs):
+    collector = Collector()
+    endpoint = await asyncio.start_server(collector.handle, "127.0.0.1", 0)
+    port = endpoint.sockets[0].getsockname()[1]
+    try:
+        async with TestServer(app, interface, otel_endpoint=f"http://127.0.0.1:{port}", **kwargs) as server:
+            status, res_headers = await _request(server, target, headers)
+            spans = await collector.wait_for(span)
+    finally:
+        endpoint.close()
+    return status, res_headers, spans
+
+
+def _attributes(span):
+    return {attr["key"]: list(attr["value"].values())[0] for attr in span["attributes"]}
+
+
+@pytest.mark.asyncio
+async def test_parent_trace():
+    _, headers, spans = await _traced("/a?b=c", [("traceparent", f"00-{TRACE_ID}-{PARENT_ID}-01")])
+    request, callback, connection = spans["GET"], spans["callback"], spans["connection"]
+
+    assert request["traceId"] == TRACE_ID
+    assert request["parentSpanId"] == PARENT_ID
+    assert request["kind"] == 2
+    assert headers["x-traceparent"] == f"00-{TRACE_ID}-{request['spanId']}-01"
+    assert callback["traceId"] == TRACE_ID
+    assert callback["parentSpanId"] == request["spanId"]
+    assert request["links"] == [{"traceId": connection["traceId"], "spanId": connection["spanId"]}]
+    assert "parentSpanId" not in connection
+    assert int(request["startTimeUnixNano"]) <= int(callback["startTimeUnixNano"])
+    assert int(callback["endTimeUnixNano"]) <= int(request["endTimeUnixNano"])
+
+    attributes = _attributes(request)
+    assert attributes["http.request.method"] == "GET"
+    assert attributes["url.path"] == "/a"
+    assert attributes["url.query"] == "b=c"
+    assert attributes["http.response.status_code"] == "200"
+    assert _attributes(connection)["granian.requests"] == "1"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+async def test_root_trace(interface, app):
+    _, headers, spans = await _traced("/", app=app, interface=interface, otel_service_name="svc")
+    request = spans["GET"]
+
+    assert "parentSpanId" not in request
+    assert headers["x-traceparent"] == f"00-{request['traceId']}-{request['spanId']}-01"
+    assert spans["callback"]["parentSpanId"] == request["spanId"]
+
+
+@pytest.mark.asyncio
+async def test_unsampled_parent():
+    _, headers, spans = await _traced("/", [("traceparent", f"00-{TRACE_ID}-{PARENT_ID}-00")])
+
+    assert list(spans) == ["connection"]
+    _, trace_id, span_id, flags = headers["x-traceparent"].split("-")
+    assert trace_id == TRACE_ID
+    assert span_id != PARENT_ID
+    assert flags == "00"
+
+
+@pytest.mark.asyncio
+async def test_sampled_parent():
+    _, _, spans = await _traced(
+        "/", [("traceparent", f"00-{TRACE_ID}-{PARENT_ID}-01")], span="callback", otel_sample_ratio=0.0
+    )
+
+    assert "connection" not in spans
+    assert spans["GET"]["traceId"] == TRACE_ID
+    assert spans["GET"]["links"] == []
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+async def test_callback_error(interface, app):
+    status, _, spans = await _traced("/fail", app=app, interface=interface)
+
+    assert status == 500
+    assert spans["GET"]["status"] == {"code": 2}
+    assert spans["callback"]["status"] == {"code": 2}
+
+
+@pytest.mark.parametrize(
+    "kwargs", [{"otel_endpoint": "https://localhost:4318"}, {"otel_endpoint": "localhost"}, {"otel_sample_ratio": 2.0}]
+)
+def test_invalid_config(kwargs):
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", **kwargs)
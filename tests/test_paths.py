@@ -0,0 +1,91 @@
+import json
+
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_paths(scope, proto):
+    proto.response_str(200, [("content-type", "application/json")], json.dumps({
+        "path": scope.path,
+        "raw_path": scope.raw_path,
+        "query": scope.query_params()
+    }))
+
+
+async def asgi_paths(scope, receive, send):
+    await send({
+        "type": "http.response.start",
+        "status": 200,
+        "headers": [[b"content-type", b"application/json"]]
+    })
+    await send({
+        "type": "http.response.body",
+        "body": json.dumps({
+            "path": scope["path"],
+            "raw_path": scope["raw_path"].decode("ascii"),
+            "query": scope["extensions"]["granian.query_params"]()
+        }).encode("utf8")
+    })
+
+
+def wsgi_paths(environ, protocol):
+    protocol("200 OK", [("content-type", "application/json")])
+    return [json.dumps({
+        "path": environ["PATH_INFO"].encode("latin-1").decode("utf8"),
+        "raw_path": environ["granian.raw_path"],
+        "query": environ["granian.query_params"]()
+    }).encode("utf8")]
+
+
+APPS = {"asgi": asgi_paths, "rsgi": rsgi_paths, "wsgi": wsgi_paths}
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["asgi", "rsgi", "wsgi"])
+async def test_raw(interface):
+    async with TestClient(APPS[interface], interface) as client:
+        res = await client.get("/a%20b?x=1")
+
+    assert res.status_code == 200
+    assert res.json()["path"] == "/a%20b"
+    assert res.json()["raw_path"] == "/a%20b"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["asgi", "rsgi", "wsgi"])
+async def test_lenient(interface):
+    async with TestClient(APPS[interface], interface, path_decoding="lenient") as client:
+        res = await client.get("/caf%C3%A9/a%2Fb/%zz")
+
+    assert res.status_code == 200
+    assert res.json()["path"] == "/café/a%2Fb/%zz"
+    assert res.json()["raw_path"] == "/caf%C3%A9/a%2Fb/%zz"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("path", ["/a%2Fb", "/a%zz", "/a%FF", "/a?x=%zz"])
+async def test_strict_rejected(path):
+    async with TestClient(rsgi_paths, "rsgi", path_decoding="strict") as client:
+        res = await client.get(path)
+
+    assert res.status_code == 400
+
+
+@pytest.mark.asyncio
+async def test_strict():
+    async with TestClient(rsgi_paths, "rsgi", path_decoding="strict") as client:
+        res = await client.get("/a%20b")
+
+    assert res.status_code == 200
+    assert res.json()["path"] == "/a b"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["asgi", "rsgi", "wsgi"])
+async def test_query_params(interface):
+    async with TestClient(APPS[interface], interface) as client:
+        res = await client.get("/?a=1&b=x+y&a=%C3%A9&flag")
+
+    assert res.status_code == 200
+    assert res.json()["query"] == [["a", "1"], ["b", "x y"], ["a", "é"], ["flag", ""]]
@@ -0,0 +1,106 @@
+import asyncio
+
+import pytest
+
+from granian.errors import ProtocolError, ProtocolViolationError
+from granian.testing import TestServer
+
+
+async def asgi_app(scope, receive, send):
+    path = scope["path"]
+    if path == "/str-body":
+        await send({"type": "http.response.start", "status": 200, "headers": [(b"x-case", b"str-body")]})
+        try:
+            await send({"type": "http.response.body", "body": "hello"})
+        except ProtocolViolationError as exc:
+            await send({"type": "http.response.body", "body": str(exc).encode()})
+    elif path == "/body-first":
+        await send({"type": "http.response.body", "body": b"hello"})
+    elif path == "/double-start":
+        await send({"type": "http.response.start", "status": 500, "headers": []})
+        await send({"type": "http.response.start", "status": "201", "headers": {"x-case": "double-start"}})
+        await send({"type": "http.response.body", "body": b"hello", "more_body": 1})
+        await send({"type": "http.response.body", "body": None, "more_body": 0})
+
+
+async def rsgi_app(scope, proto):
+    if scope.path == "/str-status":
+        try:
+            proto.response_str("201", [("x-case", "str-status")], "hello")
+        except ProtocolViolationError as exc:
+            proto.response_str(400, [], str(exc))
+    elif scope.path == "/dict-headers":
+        proto.response_bytes(200, {"x-case": "dict-headers", "x-count": 1}, "hello")
+    elif scope.path == "/double-response":
+        proto.response_str(200, [("x-case", "double-response")], "hello")
+        proto.response_str(500, [], "again")
+
+
+async def _get(server, path):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(f"GET {path} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n".encode())
+    data = await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+    head, _, body = data.partition(b"\r\n\r\n")
+    lines = head.decode("latin-1").split("\r\n")
+    headers = dict(tuple(line.split(": ", 1)) for line in lines[1:])
+    if headers.get("transfer-encoding") == "chunked":
+        body = b"".join(body.split(b"\r\n")[1::2])
+    return int(lines[0].split(" ")[1]), headers, body
+
+
+def test_error_hierarchy():
+    assert issubclass(ProtocolViolationError, ProtocolError)
+
+
+@pytest.mark.asyncio
+async def test_asgi_strict():
+    async with TestServer(asgi_app, "asgi") as server:
+        status, _, body = await _get(server, "/str-body")
+        double_start, _, _ = await _get(server, "/double-start")
+
+    assert status == 200
+    assert body == b"ASGI response body should be a bytes-like object, got str"
+    assert double_start == 500
+
+
+@pytest.mark.asyncio
+async def test_rsgi_strict():
+    async with TestServer(rsgi_app, "rsgi") as server:
+        status, _, body = await _get(server, "/str-status")
+        dict_headers, _, _ = await _get(server, "/dict-headers")
+
+    assert status == 400
+    assert body == b"RSGI response status should be an integer, got str"
+    assert dict_headers == 500
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["path", "expected_status", "expected_body"],
+    [("/str-body", 200, b"hello"), ("/body-first", 200, b"hello"), ("/double-start", 201, b"hello")]
+)
+async def test_asgi_lenient(path, expected_status, expected_body):
+    async with TestServer(asgi_app, "asgi", protocol_strict=False) as server:
+        status, headers, body = await _get(server, path)
+
+    assert status == expected_status
+    assert body == expected_body
+    if path != "/body-first":
+        assert headers["x-case"] == path[1:]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["path", "expected_status"],
+    [("/str-status", 201), ("/dict-headers", 200), ("/double-response", 200)]
+)
+async def test_rsgi_lenient(path, expected_status):
+    async with TestServer(rsgi_app, "rsgi", protocol_strict=False) as server:
+        status, headers, body = await _get(server, path)
+
+    assert status == expected_status
+    assert body == b"hello"
+    assert headers["x-case"] == path[1:]
+    if path == "/dict-headers":
+        assert headers["x-count"] == "1"
@@ -0,0 +1,91 @@
+import asyncio
+import ipaddress
+
+import pytest
+
+from granian.testing import TestServer
+
+V2_SIGNATURE = b"\r\n\r\n\x00\r\nQUIT\n"
+REQUEST = b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n"
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], f"{scope.client} {scope.scheme}")
+
+
+async def asgi_app(scope, receive, send):
+    host, port = scope["client"]
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": f"{host}:{port} {scope['scheme']}".encode()})
+
+
+def _v2_header(command=0x21, src=None, dst=None, tlvs=b""):
+    if src is None:
+        return V2_SIGNATURE + bytes([command, 0x00]) + len(tlvs).to_bytes(2, "big") + tlvs
+    src_ip, dst_ip = ipaddress.ip_address(src[0]), ipaddress.ip_address(dst[0])
+    family = 0x11 if src_ip.version == 4 else 0x21
+    addresses = src_ip.packed + dst_ip.packed + src[1].to_bytes(2, "big") + dst[1].to_bytes(2, "big")
+    payload = addresses + tlvs
+    return V2_SIGNATURE + bytes([command, family]) + len(payload).to_bytes(2, "big") + payload
+
+
+async def _exchange(app, interface, data):
+    async with TestServer(app, interface, proxy_protocol=True) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(data)
+        response = await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+    return response
+
+
+async def _body(app, interface, data):
+    response = await _exchange(app, interface, data)
+    assert response.startswith(b"HTTP/1.1 200")
+    return response.split(b"\r\n\r\n", 1)[1].decode()
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+async def test_v1(interface, app):
+    header = b"PROXY TCP4 192.0.2.10 198.51.100.1 41234 443\r\n"
+    assert await _body(app, interface, header + REQUEST) == "192.0.2.10:41234 http"
+
+
+@pytest.mark.asyncio
+async def test_v1_unknown():
+    body = await _body(rsgi_app, "rsgi", b"PROXY UNKNOWN\r\n" + REQUEST)
+    assert body.startswith("127.0.0.1:")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+async def test_v2(interface, app):
+    header = _v2_header(src=("192.0.2.10", 41234), dst=("198.51.100.1", 80))
+    assert await _body(app, interface, header + REQUEST) == "192.0.2.10:41234 http"
+
+
+@pytest.mark.asyncio
+async def test_v2_ipv6():
+    header = _v2_header(src=("2001:db8::1", 41234), dst=("2001:db8::2", 80))
+    assert await _body(rsgi_app, "rsgi", header + REQUEST) == "[2001:db8::1]:41234 http"
+
+
+@pytest.mark.asyncio
+async def test_v2_ssl_tlv():
+    # PP2_TYPE_SSL with the PP2_CLIENT_SSL flag, a zero verify result and a version sub-TLV
+    ssl = bytes([0x01]) + bytes(4) + bytes([0x21]) + (7).to_bytes(2, "big") + b"TLSv1.3"
+    tlvs = bytes([0x20]) + len(ssl).to_bytes(2, "big") + ssl
+    header = _v2_header(src=("192.0.2.10", 41234), dst=("198.51.100.1", 443), tlvs=tlvs)
+    assert await _body(rsgi_app, "rsgi", header + REQUEST) == "192.0.2.10:41234 https"
+
+
+@pytest.mark.asyncio
+async def test_v2_local():
+    body = await _body(rsgi_app, "rsgi", _v2_header(command=0x20) + REQUEST)
+    assert body.startswith("127.0.0.1:")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("header", [b"", b"PROXY TCP4 not-an-ip 198.51.100.1 41234 443\r\n", V2_SIGNATURE + b"\x31\x11\x00\x00"])
+async def test_invalid_header_closed(header):
+    assert await _exchange(rsgi_app, "rsgi", header + REQUEST) == b""
@@ -0,0 +1,87 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestServer
+
+
+INJECTED = "value\r\nset-cookie: injected=1"
+HEADERS = [("x-value", INJECTED), ("bad name", "value"), ("x-other", "other")]
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, HEADERS, "hello")
+
+
+async def rsgi_retry_app(scope, proto):
+    try:
+        proto.response_str(200, HEADERS, "hello")
+    except Exception as exc:
+        proto.response_str(400, [("content-type", "text/plain")], type(exc).__name__)
+
+
+async def asgi_app(scope, receive, send):
+    await send({
+        "type": "http.response.start",
+        "status": 200,
+        "headers": [(key.encode(), value.encode()) for key, value in HEADERS]
+    })
+    await send({"type": "http.response.body", "body": b"hello"})
+
+
+def wsgi_app(environ, start_response):
+    start_response("200 OK", HEADERS)
+    return [b"hello"]
+
+
+APPS = [("rsgi", rsgi_app), ("asgi", asgi_app), ("wsgi", wsgi_app)]
+
+
+async def _get(server):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    data = await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+    head, _, body = data.partition(b"\r\n\r\n")
+    lines = head.decode("latin-1").split("\r\n")
+    headers = [tuple(line.split(": ", 1)) for line in lines[1:]]
+    return int(lines[0].split(" ")[1]), headers, body
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], APPS)
+async def test_strict(interface, app):
+    async with TestServer(app, interface) as server:
+        status, headers, _ = await _get(server)
+
+    assert status == 500
+    assert all(key.lower() != "set-cookie" for key, _ in headers)
+
+
+@pytest.mark.asyncio
+async def test_strict_retry():
+    async with TestServer(rsgi_retry_app, "rsgi") as server:
+        status, _, body = await _get(server)
+
+    assert status == 400
+    assert body == b"RSGIProtocolError"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], APPS)
+async def test_sanitize(interface, app):
+    async with TestServer(app, interface, header_validation="sanitize") as server:
+        status, headers, body = await _get(server)
+
+    names = [key.lower() for key, _ in headers]
+    assert status == 200
+    assert body == b"hello"
+    assert ("x-value", "valueset-cookie: injected=1") in headers
+    assert ("x-other", "other") in headers
+    assert "set-cookie" not in names
+    assert "bad name" not in names
+
+
+def test_invalid_mode():
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", header_validation="ignore")
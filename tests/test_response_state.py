@@ -0,0 +1,77 @@
+import pytest
+
+from granian.errors import ProtocolError, ResponseStateError
+from granian.testing import TestClient
+
+
+async def asgi_app(scope, receive, send):
+    start = {"type": "http.response.start", "status": 200, "headers": []}
+    errors = []
+    if scope["path"] == "/body-first":
+        try:
+            await send({"type": "http.response.body", "body": b"early"})
+        except ResponseStateError as exc:
+            errors.append(str(exc))
+    await send(start)
+    if scope["path"] == "/double-start":
+        try:
+            await send(start)
+        except ResponseStateError as exc:
+            errors.append(str(exc))
+    await send({"type": "http.response.body", "body": b"", "more_body": True})
+    await send({"type": "http.response.body", "body": "\n".join(errors).encode()})
+    if scope["path"] == "/after-complete":
+        try:
+            await send({"type": "http.response.body", "body": b"late"})
+        except ResponseStateError as exc:
+            print(exc)
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [], "ok")
+    try:
+        proto.response_str(500, [], "again")
+    except ResponseStateError as exc:
+        print(exc)
+
+
+def test_error_hierarchy():
+    assert issubclass(ResponseStateError, ProtocolError)
+    assert issubclass(ResponseStateError, RuntimeError)
+
+
+@pytest.mark.asyncio
+async def test_asgi_body_before_start():
+    async with TestClient(asgi_app, "asgi") as client:
+        res = await client.get("/body-first")
+
+    assert res.status_code == 200
+    assert res.text == "Unexpected response body: the response is not started yet, expecting its start"
+
+
+@pytest.mark.asyncio
+async def test_asgi_double_start():
+    async with TestClient(asgi_app, "asgi") as client:
+        res = await client.get("/double-start")
+
+    assert res.status_code == 200
+    assert res.text == "Unexpected response start: the response is started, expecting its body or a file"
+
+
+@pytest.mark.asyncio
+async def test_asgi_body_after_complete(capsys):
+    async with TestClient(asgi_app, "asgi") as client:
+        res = await client.get("/after-complete")
+
+    assert res.status_code == 200
+    assert "Unexpected response body: the response is already completed" in capsys.readouterr().out
+
+
+@pytest.mark.asyncio
+async def test_rsgi_double_response(capsys):
+    async with TestClient(rsgi_app, "rsgi") as client:
+        res = await client.get("/")
+
+    assert res.status_code == 200
+    assert res.text == "ok"
+    assert "Unexpected response: the response is already completed" in capsys.readouterr().out
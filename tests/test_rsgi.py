@@ -1,3 +1,5 @@
+import asyncio
+
 import httpx
 import pytest
 
@@ -25,7 +27,9 @@ async def test_scope(rsgi_server, threading_mode):
     assert data['method'] == "GET"
     assert data['path'] == '/info'
     assert data['query_string'] == 'test=true'
+    assert data['content_length'] == 0
     assert data['headers']['host'] == f'localhost:{port}'
+    assert data['worker'] == [1, 1]
 
 
 @pytest.mark.asyncio
@@ -44,6 +48,157 @@ async def test_body(rsgi_server, threading_mode):
     assert res.text == "test"
 
 
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_body_chunks(rsgi_server, threading_mode):
+    data = b"test" * 100_000
+    async with rsgi_server(threading_mode) as port:
+        res = httpx.post(f"http://localhost:{port}/echo_chunks", content=data)
+
+    assert res.status_code == 200
+    assert res.content == data
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_body_receive(rsgi_server, threading_mode):
+    data = b"test" * 100_000
+    async with rsgi_server(threading_mode) as port:
+        res = httpx.post(f"http://localhost:{port}/echo_receive", content=data)
+
+    assert res.status_code == 200
+    assert res.content == data
+
+
+@pytest.mark.asyncio
+async def test_body_receive_incremental():
+    from granian.testing import TestServer
+
+    async def app(scope, protocol):
+        chunk = await protocol.receive()
+        protocol.response_bytes(200, [], b"first %d" % len(chunk))
+
+    async with TestServer(app, "rsgi") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        # the rest of the body never gets sent
+        writer.write(
+            b"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n"
+            b"transfer-encoding: chunked\r\n\r\n4\r\ntest\r\n"
+        )
+        data = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+
+    assert data.startswith(b"HTTP/1.1 200")
+    assert data.endswith(b"first 4")
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_body_chunked(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        info = httpx.post(f"http://localhost:{port}/info", content=iter([b"te", b"st"]))
+        res = httpx.post(f"http://localhost:{port}/echo", content=iter([b"te", b"st"]))
+
+    assert info.status_code == 200
+    assert info.json()['content_length'] is None
+    assert res.status_code == 200
+    assert res.text == "test"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_file_range(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        full = httpx.get(f"http://localhost:{port}/file")
+        part = httpx.get(f"http://localhost:{port}/file", headers={"range": "bytes=5-9"})
+        suffix = httpx.get(f"http://localhost:{port}/file", headers={"range": "bytes=-3"})
+        invalid = httpx.get(f"http://localhost:{port}/file", headers={"range": "bytes=50-"})
+
+    assert full.status_code == 200
+    assert full.headers["accept-ranges"] == "bytes"
+    assert full.text == "0123456789abcdefghij"
+    assert part.status_code == 206
+    assert part.headers["content-range"] == "bytes 5-9/20"
+    assert part.text == "56789"
+    assert suffix.status_code == 206
+    assert suffix.text == "hij"
+    assert invalid.status_code == 416
+    assert invalid.headers["content-range"] == "bytes */20"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_file_if_range(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        full = httpx.get(f"http://localhost:{port}/file")
+        etag = httpx.get(
+            f"http://localhost:{port}/file",
+            headers={"range": "bytes=10-", "if-range": full.headers["etag"]}
+        )
+        date = httpx.get(
+            f"http://localhost:{port}/file",
+            headers={"range": "bytes=10-", "if-range": full.headers["last-modified"]}
+        )
+        stale = httpx.get(
+            f"http://localhost:{port}/file",
+            headers={"range": "bytes=10-", "if-range": '"stale"'}
+        )
+
+    assert etag.status_code == 206
+    assert etag.text == "abcdefghij"
+    assert date.status_code == 206
+    assert date.text == "abcdefghij"
+    assert stale.status_code == 200
+    assert stale.text == "0123456789abcdefghij"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_fast_path(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        res = httpx.get(f"http://localhost:{port}/fast")
+
+    assert res.status_code == 200
+    assert res.headers["content-type"] == "text/plain"
+    assert res.text == "GET"
+
+
 @pytest.mark.asyncio
 @pytest.mark.parametrize(
     "threading_mode",
@@ -57,3 +212,91 @@ async def test_app_error(rsgi_server, threading_mode):
         res = httpx.get(f"http://localhost:{port}/err_app")
 
     assert res.status_code == 500
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_upgrade(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        reader, writer = await asyncio.open_connection("localhost", port)
+        writer.write(
+            b"GET /upgrade HTTP/1.1\r\nhost: localhost\r\n"
+            b"connection: upgrade\r\nupgrade: echo\r\n\r\n"
+        )
+        head = await reader.readuntil(b"\r\n\r\n")
+        writer.write(b"ping")
+        data = await reader.read()
+        writer.close()
+
+    assert head.startswith(b"HTTP/1.1 101")
+    assert b"upgrade: echo" in head
+    assert b"x-granian-test: raw" in head
+    assert data == b"ping"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_stream(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        res = httpx.get(f"http://localhost:{port}/stream")
+
+    assert res.status_code == 200
+    assert res.headers["transfer-encoding"] == "chunked"
+    assert res.text == "chunk 0\nchunk 1\nchunk 2\nend"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_stream_error(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        reader, writer = await asyncio.open_connection("localhost", port)
+        writer.write(b"GET /stream_err HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        data = await asyncio.wait_for(reader.read(), 5)
+        writer.close()
+
+    assert data.startswith(b"HTTP/1.1 200")
+    assert not data.endswith(b"0\r\n\r\n")
+
+
+@pytest.mark.asyncio
+async def test_stream_backpressure():
+    from granian.testing import TestServer
+
+    chunk, sent = b"x" * 65536, []
+
+    async def app(scope, protocol):
+        trx = protocol.response_stream(200, [])
+        for _ in range(1000):
+            await trx.send_bytes(chunk)
+            sent.append(1)
+
+    async with TestServer(app, "rsgi") as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        await reader.readuntil(b"\r\n\r\n")
+        await asyncio.sleep(0.5)
+        stalled = len(sent)
+        body = await asyncio.wait_for(reader.read(), 10)
+        writer.close()
+
+    assert stalled < 1000
+    assert len(sent) == 1000
+    assert body.count(b"x") == 1000 * 65536
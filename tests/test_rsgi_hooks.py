@@ -0,0 +1,71 @@
+import asyncio
+import subprocess
+import sys
+
+import pytest
+
+from granian.errors import StartupError
+from granian.testing import TestClient
+
+
+class App:
+    def __init__(self):
+        self.events = []
+        self.pool = None
+
+    async def __rsgi_init__(self, loop):
+        await asyncio.sleep(0)
+        assert loop is asyncio.get_running_loop()
+        self.pool = "pool"
+        self.events.append("init")
+
+    async def __rsgi_del__(self, loop):
+        await asyncio.sleep(0)
+        self.events.append("del")
+
+    async def __rsgi__(self, scope, proto):
+        proto.response_str(200, [], self.pool)
+
+
+class SyncApp(App):
+    def __rsgi_init__(self, loop):
+        self.pool = "sync pool"
+        self.events.append("init")
+
+    def __rsgi_del__(self, loop):
+        self.events.append("del")
+
+
+class FailingApp(App):
+    def __rsgi_init__(self, loop):
+        raise RuntimeError("cache unreachable")
+
+
+failing_app = FailingApp()
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["cls", "pool"], [(App, "pool"), (SyncApp, "sync pool")])
+async def test_hooks(cls, pool):
+    app = cls()
+    async with TestClient(app, "rsgi") as client:
+        assert app.events == ["init"]
+        res = await client.get("/")
+
+    assert res.text == pool
+    assert app.events == ["init", "del"]
+
+
+def test_init_failure_stops_server():
+    proc = subprocess.run(
+        [
+            sys.executable, "-c",
+            "from granian import Granian; "
+            "Granian('tests.test_rsgi_hooks:failing_app', interface='rsgi', port=0, workers=1).serve()"
+        ],
+        capture_output=True,
+        timeout=30
+    )
+
+    assert proc.returncode == StartupError.exit_status
+    assert b"RSGI application init failed" in proc.stdout
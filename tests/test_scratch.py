@@ -0,0 +1,85 @@
+import asyncio
+import os
+import tempfile
+
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_app(scope, proto):
+    path = scope.scratch_dir()
+    assert scope.scratch_dir() == path
+    with open(os.path.join(path, "upload.bin"), "wb") as f:
+        f.write(b"data")
+    if scope.path == "/fail":
+        raise RuntimeError("failed")
+    proto.response_str(200, [], path)
+
+
+async def asgi_app(scope, receive, send):
+    path = scope["extensions"]["granian.scratch_dir"]()
+    open(os.path.join(path, "upload.bin"), "wb").close()
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": path.encode()})
+
+
+def wsgi_app(environ, start_response):
+    path = environ["granian.scratch_dir"]()
+    open(os.path.join(path, "upload.bin"), "wb").close()
+    start_response("200 OK", [])
+    return [path.encode()]
+
+
+async def _removed(path):
+    # removal happens off the event loop
+    for _ in range(50):
+        if not os.path.exists(path):
+            return True
+        await asyncio.sleep(0.02)
+    return False
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app), ("wsgi", wsgi_app)])
+async def test_scratch_dir_removed(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.get("/")
+        res_other = await client.get("/")
+
+    assert res.status_code == 200
+    assert os.path.basename(res.text).startswith("granian-")
+    assert res.text != res_other.text
+    assert await _removed(res.text)
+    assert await _removed(res_other.text)
+
+
+@pytest.mark.asyncio
+async def test_scratch_dir_removed_on_error():
+    created = []
+
+    async def app(scope, proto):
+        created.append(scope.scratch_dir())
+        await rsgi_app(scope, proto)
+
+    async with TestClient(app) as client:
+        res = await client.get("/fail")
+
+    assert res.status_code == 500
+    assert await _removed(created[0])
+
+
+@pytest.mark.asyncio
+async def test_scratch_dir_lazy():
+    def scratch_dirs():
+        prefix = f"granian-{os.getpid()}-"
+        return {name for name in os.listdir(tempfile.gettempdir()) if name.startswith(prefix)}
+
+    async def app(scope, proto):
+        proto.response_str(200, [], str(len(scratch_dirs())))
+
+    before = scratch_dirs()
+    async with TestClient(app) as client:
+        res = await client.get("/")
+
+    assert res.text == str(len(before))
@@ -0,0 +1,53 @@
+import pytest
+
+from granian.errors import ConfigurationError
+from granian.secrets import Secret, resolve
+from granian.server import Granian
+
+
+def test_resolve_env(monkeypatch):
+    monkeypatch.setenv("GRANIAN_TEST_SECRET", "s3cr3t")
+    value = resolve("env:GRANIAN_TEST_SECRET")
+
+    assert value == "s3cr3t"
+    assert isinstance(value, Secret)
+    assert "s3cr3t" not in repr(value)
+
+
+def test_resolve_file(tmp_path):
+    path = tmp_path / "secret"
+    path.write_text("s3cr3t\n")
+
+    assert resolve(f"file:{path}") == "s3cr3t"
+
+
+def test_resolve_plain():
+    assert resolve("value") == "value"
+    assert not isinstance(resolve("value"), Secret)
+
+
+def test_resolve_missing(monkeypatch, tmp_path):
+    monkeypatch.delenv("GRANIAN_TEST_SECRET", raising=False)
+    with pytest.raises(ConfigurationError):
+        resolve("env:GRANIAN_TEST_SECRET")
+    with pytest.raises(ConfigurationError) as exc:
+        resolve(f"file:{tmp_path / 'missing'}")
+
+    assert exc.value.exit_status == 78
+
+
+def test_server_config(monkeypatch, capsys):
+    monkeypatch.setenv("GRANIAN_TEST_SECRET", "s3cr3t")
+    server = Granian(
+        "app:app",
+        synthetic_responses={"/token": (200, {"x-token": "env:GRANIAN_TEST_SECRET"}, "env:GRANIAN_TEST_SECRET")},
+        log_level="debug"
+    )
+    server._resolve_secrets()
+    server._dump_config()
+    logs = capsys.readouterr().out
+
+    assert server.synthetic_responses[0][2] == [("x-token", "s3cr3t")]
+    assert server.synthetic_responses[0][3] == "s3cr3t"
+    assert "s3cr3t" not in logs
+    assert "<redacted>" in logs
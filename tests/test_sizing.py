@@ -0,0 +1,55 @@
+import pytest
+
+from granian import sizing
+from granian.server import Granian
+from granian.sizing import auto_sizing, available_cpus
+
+MIB = 1024 * 1024
+
+
+@pytest.fixture
+def limits(monkeypatch):
+    def set_limits(cpus, memory=None):
+        monkeypatch.setattr(sizing, "available_cpus", lambda: cpus)
+        monkeypatch.setattr(sizing, "memory_limit", lambda: memory)
+    return set_limits
+
+
+def test_available_cpus(monkeypatch):
+    monkeypatch.setattr(sizing.os, "sched_getaffinity", lambda pid: set(range(8)), raising=False)
+    monkeypatch.setattr(sizing, "cpu_quota", lambda: 2.5)
+    assert available_cpus() == 3
+    monkeypatch.setattr(sizing, "cpu_quota", lambda: None)
+    assert available_cpus() == 8
+
+
+def test_cpu_bound(limits):
+    limits(4)
+    assert auto_sizing(None, None)[:2] == (4, 1)
+
+
+def test_memory_bound(limits):
+    limits(8, 1024 * MIB)
+    assert auto_sizing(None, None)[:2] == (4, 2)
+    assert auto_sizing(None, None, worker_memory=512)[:2] == (2, 4)
+    limits(8, 128 * MIB)
+    assert auto_sizing(None, None)[:2] == (1, 8)
+
+
+def test_explicit_values(limits):
+    limits(8, 128 * MIB)
+    assert auto_sizing(16, None)[:2] == (16, 1)
+    assert auto_sizing(2, None)[:2] == (2, 4)
+    assert auto_sizing(None, 3)[:2] == (1, 3)
+    assert auto_sizing(0, 0)[:2] == (1, 1)
+
+
+def test_server_sizing(limits):
+    limits(4, 1024 * MIB)
+    server = Granian("tests.apps.asgi:app", pthreads=None)
+    assert (server.workers, server.threads, server.pthreads) == (4, 1, 1)
+    assert server._sizing.describe() == "4 workers with 1 threads each, sized on 4 CPUs and 1024 MiB of memory"
+
+    server = Granian("tests.apps.asgi:app", workers=2, threads=2)
+    assert (server.workers, server.threads, server.pthreads) == (2, 2, 1)
+    assert server._sizing is None
@@ -0,0 +1,73 @@
+import asyncio
+import json
+import logging
+
+import pytest
+
+from granian._granian import reset_log_levels
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    if scope.path == "/slow":
+        await asyncio.sleep(0.03)
+    status = 500 if scope.path == "/error" else 200
+    proto.response_str(status, [("content-type", "text/plain")], "ok")
+
+
+async def _requests(server, path, count):
+    for _ in range(count):
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(f"GET {path} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n".encode("ascii"))
+        await reader.read()
+        writer.close()
+
+
+def _alerts(caplog):
+    return [
+        json.loads(record.getMessage().split(" ", 2)[2])
+        for record in caplog.records
+        if record.getMessage().startswith("SLO alert ")
+    ]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(("path", "indicator"), [("/slow", "latency"), ("/error", "errors")])
+async def test_burn_rate_alert(caplog, path, indicator):
+    caplog.set_level(logging.INFO, logger="_granian")
+    reset_log_levels()
+    async with TestServer(
+        rsgi_app, "rsgi", slo_objective=0.99, slo_latency=0.01, slo_interval=0.1
+    ) as server:
+        await asyncio.sleep(0.15)
+        await _requests(server, path, 12)
+        await asyncio.sleep(0.3)
+
+    alerts = [alert for alert in _alerts(caplog) if alert["alert"] == "firing"]
+    assert {(alert["indicator"], alert["severity"]) for alert in alerts} == {
+        (indicator, "page"), (indicator, "ticket")
+    }
+    assert all(alert["burn_rate_short"] >= alert["burn_rate_threshold"] for alert in alerts)
+
+
+@pytest.mark.asyncio
+async def test_no_alert(caplog):
+    caplog.set_level(logging.INFO, logger="_granian")
+    reset_log_levels()
+    async with TestServer(
+        rsgi_app, "rsgi", slo_objective=0.99, slo_latency=0.01, slo_interval=0.1
+    ) as server:
+        await asyncio.sleep(0.15)
+        await _requests(server, "/", 12)
+        await asyncio.sleep(0.3)
+
+    assert _alerts(caplog) == []
+
+
+@pytest.mark.parametrize(
+    "options",
+    [{"slo_objective": 1.5}, {"slo_objective": 0.99, "slo_latency": 60.0}, {"slo_objective": 0.99, "slo_interval": 0}]
+)
+def test_invalid_policy(options):
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", **options)
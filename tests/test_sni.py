@@ -0,0 +1,83 @@
+import asyncio
+import pathlib
+import ssl
+
+import pytest
+
+from granian.errors import TlsError
+from granian.testing import TestServer
+
+
+FIXTURES = pathlib.Path.cwd() / "tests" / "fixtures" / "tls"
+SNI = {
+    "api.example.test": (FIXTURES / "cert2.pem", FIXTURES / "key2.pem"),
+    "*.wild.test": (FIXTURES / "cert2.pem", FIXTURES / "key2.pem")
+}
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], "ok")
+
+
+def _server(**kwargs):
+    return TestServer(
+        rsgi_app,
+        "rsgi",
+        ssl_cert=FIXTURES / "cert.pem",
+        ssl_key=FIXTURES / "key.pem",
+        **kwargs
+    )
+
+
+def _fixture(name):
+    return ssl.PEM_cert_to_DER_cert((FIXTURES / name).read_text())
+
+
+# connecting to the IP address with no server hostname sends no SNI
+async def _peer_cert(server, hostname=None):
+    ssl_context = ssl.create_default_context()
+    ssl_context.check_hostname = False
+    ssl_context.verify_mode = ssl.CERT_NONE
+    reader, writer = await asyncio.open_connection(
+        server.host, server.port, ssl=ssl_context, server_hostname=hostname
+    )
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    data = await asyncio.wait_for(reader.read(), 2)
+    cert = writer.get_extra_info("ssl_object").getpeercert(binary_form=True)
+    writer.close()
+    assert data.startswith(b"HTTP/1.1 200")
+    return cert
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["hostname", "fixture"],
+    [
+        ("api.example.test", "cert2.pem"),
+        ("API.Example.Test", "cert2.pem"),
+        ("a.wild.test", "cert2.pem"),
+        ("a.b.wild.test", "cert.pem"),
+        ("wild.test", "cert.pem"),
+        ("other.test", "cert.pem"),
+        (None, "cert.pem")
+    ]
+)
+async def test_sni(hostname, fixture):
+    async with _server(ssl_sni=SNI) as server:
+        assert await _peer_cert(server, hostname) == _fixture(fixture)
+
+
+@pytest.mark.parametrize("hostname", ["", "*", "a.*.test", "api..test", "*.*.test"])
+def test_invalid_hostname(hostname):
+    with pytest.raises(ValueError):
+        _server(ssl_sni={hostname: SNI["api.example.test"]})
+
+
+def test_duplicate_hostname():
+    with pytest.raises(ValueError):
+        _server(ssl_sni={"api.example.test": SNI["api.example.test"], "API.example.test.": SNI["api.example.test"]})
+
+
+def test_missing_files(tmp_path):
+    with pytest.raises(TlsError):
+        _server(ssl_sni={"api.example.test": (tmp_path / "cert.pem", tmp_path / "key.pem")})
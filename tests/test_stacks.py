@@ -0,0 +1,97 @@
+import asyncio
+import multiprocessing
+import socket
+import threading
+import time
+
+import pytest
+
+from granian.admin import AdminServer
+from granian.server import Granian
+from granian.stacks import dump_stacks
+from granian.testing import TestClient
+
+
+async def blocking_app(scope, proto):
+    if scope.path == "/slow":
+        time.sleep(0.3)
+    proto.response_str(200, [("content-type", "text/plain")], "ok")
+
+
+async def _snapshots(path, count=1):
+    for _ in range(20):
+        snapshots = list(path.glob("granian-stacks-*.txt"))
+        if len(snapshots) >= count:
+            return snapshots
+        await asyncio.sleep(0.05)
+    return list(path.glob("granian-stacks-*.txt"))
+
+
+def test_dump_stacks(tmp_path):
+    paths = []
+    thread = threading.Thread(
+        target=lambda: paths.append(dump_stacks(str(tmp_path), "testing", (2, 3))),
+        name="dumper"
+    )
+    thread.start()
+    thread.join()
+
+    assert paths[0].startswith(str(tmp_path / "granian-stacks-worker-2.3-"))
+    with open(paths[0]) as f:
+        contents = f.read()
+    assert "Reason: testing" in contents
+    assert '"MainThread"' in contents
+    assert "in test_dump_stacks" in contents
+    assert '"dumper"' not in contents
+
+
+@pytest.mark.asyncio
+async def test_slow_handler_dump(tmp_path):
+    async with TestClient(
+        blocking_app, "rsgi", stack_dump_dir=str(tmp_path), stack_dump_threshold=0.1
+    ) as client:
+        res = await client.get("/slow")
+        snapshots = await _snapshots(tmp_path)
+
+    assert res.status_code == 200
+    assert len(snapshots) == 1
+    contents = snapshots[0].read_text()
+    assert "Reason: handler for GET /slow running for more than 0.100s" in contents
+    assert "in blocking_app" in contents
+
+
+@pytest.mark.asyncio
+async def test_fast_handler_no_dump(tmp_path):
+    async with TestClient(
+        blocking_app, "rsgi", stack_dump_dir=str(tmp_path), stack_dump_threshold=0.1
+    ) as client:
+        res = await client.get("/")
+        await asyncio.sleep(0.2)
+
+    assert res.status_code == 200
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_invalid_threshold():
+    with pytest.raises(ValueError):
+        TestClient(blocking_app, "rsgi", stack_dump_threshold=0)
+
+
+def test_admin_stack_dump(tmp_path):
+    path = str(tmp_path / "admin.sock")
+    server = Granian("tests.apps.asgi:app")
+    control, server._controls[1] = multiprocessing.Pipe(duplex=False)
+    admin = AdminServer(path, {"stack-dump": server._admin_stack_dump})
+    admin.start()
+    try:
+        with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+            sock.connect(path)
+            sock.sendall(b"stack-dump\nstack-dump now\n")
+            reader = sock.makefile("rb")
+            replies = [reader.readline().decode("utf8").strip() for _ in range(2)]
+    finally:
+        admin.stop()
+
+    assert replies[0] == "ok workers=1"
+    assert replies[1].startswith("error")
+    assert control.recv() == ("stack-dump", "requested through the admin socket")
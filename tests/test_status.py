@@ -0,0 +1,126 @@
+import asyncio
+
+import pytest
+
+from granian.testing import TestClient, TestServer
+
+H2_PREFACE = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"
+# `:status` values of the HPACK static table
+H2_STATUSES = {0x88: 200, 0x89: 204, 0x8a: 206, 0x8b: 304, 0x8c: 400, 0x8d: 404, 0x8e: 500}
+INVALID_STATUSES = {"/invalid": 1000, "/informational": 103}
+
+
+async def rsgi_app(scope, proto):
+    if scope.path == "/reason":
+        proto.response_str(599, [], "timeout", reason="Network Connect Timeout")
+    elif scope.path in INVALID_STATUSES:
+        try:
+            proto.response_empty(INVALID_STATUSES[scope.path], [])
+        except ValueError:
+            proto.response_str(200, [], "rejected")
+    else:
+        proto.response_empty(499, [])
+
+
+async def asgi_app(scope, receive, send):
+    status = INVALID_STATUSES.get(scope["path"], 499)
+    try:
+        await send({"type": "http.response.start", "status": status, "headers": []})
+    except ValueError:
+        await send({"type": "http.response.start", "status": 200, "headers": []})
+        await send({"type": "http.response.body", "body": b"rejected"})
+        return
+    await send({"type": "http.response.body", "body": b""})
+
+
+def wsgi_app(environ, start_response):
+    status = {
+        "/reason": "599 Network Connect Timeout",
+        "/invalid": "abc",
+        "/informational": "103 Early Hints"
+    }.get(environ["PATH_INFO"], "499 ")
+    start_response(status, [])
+    return [b""]
+
+
+async def _status_line(app, interface, path):
+    async with TestServer(app, interface) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(f"GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n".encode("ascii"))
+        line = await asyncio.wait_for(reader.readline(), 2)
+        writer.close()
+    return line.decode("ascii").strip()
+
+
+def _h2_frame(kind, flags, stream, payload):
+    return len(payload).to_bytes(3, "big") + bytes([kind, flags]) + stream.to_bytes(4, "big") + payload
+
+
+# A bare HTTP/2 prior knowledge request, returning the status of the first
+# response headers received, as long as it is one of the HPACK static table.
+async def _h2_status(app, interface, path):
+    headers = [(b":method", b"GET"), (b":scheme", b"http"), (b":path", path.encode()), (b":authority", b"localhost")]
+    block = b"".join(b"\x00" + bytes([len(key)]) + key + bytes([len(value)]) + value for key, value in headers)
+    async with TestServer(app, interface) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(H2_PREFACE + _h2_frame(4, 0, 0, b"") + _h2_frame(1, 0x5, 1, block))
+        while True:
+            head = await asyncio.wait_for(reader.readexactly(9), 2)
+            payload = await reader.readexactly(int.from_bytes(head[:3], "big"))
+            if head[3] == 1 and int.from_bytes(head[5:9], "big") == 1:
+                break
+        writer.close()
+    return H2_STATUSES.get(payload[0])
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app), ("wsgi", wsgi_app)])
+async def test_unregistered_status(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.get("/")
+
+    assert res.status_code == 499
+
+
+@pytest.mark.asyncio
+async def test_unregistered_status_line():
+    assert await _status_line(asgi_app, "asgi", "/") == "HTTP/1.1 499"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("wsgi", wsgi_app)])
+async def test_reason_phrase(interface, app):
+    assert await _status_line(app, interface, "/reason") == "HTTP/1.1 599 Network Connect Timeout"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+@pytest.mark.parametrize("path", ["/invalid", "/informational"])
+async def test_invalid_status_rejected(interface, app, path):
+    async with TestClient(app, interface) as client:
+        res = await client.get(path)
+
+    assert res.status_code == 200
+    assert res.text == "rejected"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("path", ["/invalid", "/informational"])
+async def test_invalid_wsgi_status(path):
+    async with TestClient(wsgi_app, "wsgi") as client:
+        res = await client.get(path)
+
+    assert res.status_code == 500
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+async def test_informational_status_http1(interface, app):
+    assert await _status_line(app, interface, "/informational") == "HTTP/1.1 200 OK"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("asgi", asgi_app), ("rsgi", rsgi_app)])
+async def test_informational_status_http2(interface, app):
+    assert await _h2_status(app, interface, "/informational") == 200
+    assert await _h2_status(wsgi_app, "wsgi", "/informational") == 500
@@ -0,0 +1,86 @@
+import json
+
+import pytest
+
+from granian.testing import TestClient
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], f"app {scope.method}")
+
+
+SYNTHETIC = {
+    "/page": (200, {"content-type": "text/html; charset=utf-8"}, "<p>{path}?{query_string} on {host}</p>"),
+    "/text": (200, {"content-type": "text/plain; charset=utf-8"}, "{path}?{query_string}"),
+    "/info": (
+        200,
+        {"content-type": "application/json"},
+        '{{"path": "{path}", "query": "{query_string}", "host": "{host}"}}'
+    ),
+}
+
+
+@pytest.mark.asyncio
+async def test_synthetic_html_escaped():
+    async with TestClient(rsgi_app, "rsgi", synthetic_responses=SYNTHETIC) as client:
+        res = await client.get("/page?q=x'onclick='alert(1)&y=1", {"host": '<b>"evil"</b>'})
+
+    assert res.status_code == 200
+    assert res.text == (
+        "<p>/page?q=x&#39;onclick=&#39;alert(1)&amp;y=1 "
+        "on &lt;b&gt;&quot;evil&quot;&lt;/b&gt;</p>"
+    )
+
+
+@pytest.mark.asyncio
+async def test_synthetic_json_escaped():
+    host = 'evil", "admin": true, "x": "'
+    async with TestClient(rsgi_app, "rsgi", synthetic_responses=SYNTHETIC) as client:
+        res = await client.get("/info?q=a\\", {"host": host})
+
+    assert res.status_code == 200
+    assert json.loads(res.text) == {"path": "/info", "query": "q=a\\", "host": host}
+
+
+@pytest.mark.asyncio
+async def test_synthetic_text_verbatim():
+    async with TestClient(rsgi_app, "rsgi", synthetic_responses=SYNTHETIC) as client:
+        res = await client.get("/text?q='b'&x={}")
+
+    assert res.status_code == 200
+    assert res.text == "/text?q='b'&x={}"
+
+
+@pytest.mark.asyncio
+async def test_synthetic_methods():
+    async with TestClient(rsgi_app, "rsgi", synthetic_responses=SYNTHETIC) as client:
+        res_get = await client.get("/text")
+        res_head = await client.request("HEAD", "/text")
+        res_post = await client.post("/text", body=b"data")
+        res_delete = await client.request("DELETE", "/text")
+
+    assert res_get.text == "/text?"
+    assert res_get.header("content-length") == "6"
+    assert res_head.status_code == 200
+    assert res_head.content == b""
+    assert res_head.header("content-length") == "6"
+    assert res_post.status_code == 200
+    assert res_post.text == "app POST"
+    assert res_delete.text == "app DELETE"
+
+
+@pytest.mark.asyncio
+async def test_status_hook_body_escaped():
+    hooks = [
+        ("404", "body", "<p>{path}?{query_string}</p>"),
+        ("404", "header", "content-type:text/html"),
+    ]
+
+    async def app(scope, proto):
+        proto.response_empty(404, [])
+
+    async with TestClient(app, "rsgi", status_hooks=hooks) as client:
+        res = await client.get('/"missing\'?x=&')
+
+    assert res.status_code == 404
+    assert res.text == "<p>/&quot;missing&#39;?x=&amp;</p>"
@@ -0,0 +1,164 @@
+import httpx
+import pytest
+import websockets
+
+from granian.testing import MockClock, TestClient, TestServer
+
+from apps import asgi, rsgi, wsgi
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("asgi", asgi.app),
+        ("rsgi", rsgi.app),
+        ("wsgi", wsgi.app)
+    ]
+)
+async def test_info(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.post("/info?test=true", body=b"test")
+
+    assert res.status_code == 200
+    assert res.header("content-type") == "application/json"
+
+    data = res.json()
+    assert data["method"] == "POST"
+    assert data["path"] == "/info"
+    assert data["query_string"] == "test=true"
+    assert data["worker"] == [0, 0]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("asgi", asgi.app),
+        ("rsgi", rsgi.app),
+        ("wsgi", wsgi.app)
+    ]
+)
+async def test_body(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.post("/echo", body="test")
+
+    assert res.status_code == 200
+    assert res.text == "test"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("asgi", asgi.app),
+        ("rsgi", rsgi.app),
+        ("wsgi", wsgi.app)
+    ]
+)
+async def test_app_error(interface, app):
+    async with TestClient(app, interface) as client:
+        res = await client.get("/err_app")
+
+    assert res.status_code == 500
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("asgi", asgi.app),
+        ("rsgi", rsgi.app),
+        ("wsgi", wsgi.app)
+    ]
+)
+async def test_server(interface, app):
+    async with TestServer(app, interface) as server:
+        assert server.port > 0
+        async with httpx.AsyncClient() as client:
+            res = await client.post(f"{server.url}/echo", content=b"test")
+
+    assert res.status_code == 200
+    assert res.text == "test"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app"],
+    [
+        ("asgi", asgi.app),
+        ("rsgi", rsgi.app)
+    ]
+)
+async def test_server_ws(interface, app):
+    async with TestServer(app, interface) as server:
+        async with websockets.connect(f"{server.ws_url}/ws_echo") as ws:
+            await ws.send("foo")
+            res = await ws.recv()
+
+    assert res == "foo"
+
+
+@pytest.mark.asyncio
+async def test_server_shutdown():
+    server = await TestServer(rsgi.app).start()
+    url = server.url
+    await server.shutdown()
+
+    with pytest.raises(httpx.ConnectError):
+        async with httpx.AsyncClient() as client:
+            await client.get(f"{url}/info")
+
+
+@pytest.mark.asyncio
+async def test_mock_clock():
+    calls = []
+
+    async def counter(scope, proto):
+        calls.append(scope.path)
+        proto.response_str(200, [], str(len(calls)))
+
+    headers = {"idempotency-key": "test"}
+    with MockClock() as clock:
+        async with TestClient(counter, idempotency_ttl=60) as client:
+            res = await client.post("/", headers=headers)
+            assert res.text == "1"
+            clock.advance(59)
+            res = await client.post("/", headers=headers)
+            assert res.text == "1"
+            assert res.header("idempotent-replayed") == "true"
+            clock.advance(2)
+            res = await client.post("/", headers=headers)
+            assert res.text == "2"
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["accept", "content_type"],
+    [
+        (None, None),
+        ("*/*", None),
+        ("application/json", "application/problem+json"),
+        ("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8", "text/html; charset=utf-8"),
+        ("text/html;q=0.2, application/problem+json;q=0.5", "application/problem+json")
+    ]
+)
+async def test_error_negotiation(accept, content_type):
+    headers = {"accept": accept} if accept else {}
+    async with TestClient(rsgi.app) as client:
+        res = await client.get("/err_app", headers=headers)
+
+    assert res.status_code == 500
+    assert res.header("content-type") == content_type
+    if content_type == "application/problem+json":
+        assert res.json() == {"type": "about:blank", "title": "Internal Server Error", "status": 500}
+
+
+@pytest.mark.asyncio
+async def test_error_format_forced():
+    async with TestClient(rsgi.app, error_format="html") as client:
+        res = await client.get("/err_app", headers={"accept": "application/json"})
+
+    assert res.status_code == 500
+    assert res.header("content-type") == "text/html; charset=utf-8"
+    assert "<h1>500 Internal Server Error</h1>" in res.text
@@ -0,0 +1,204 @@
+import asyncio
+import base64
+import os
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    if scope.proto == "ws":
+        trx = await proto.accept()
+        message = await trx.receive()
+        await trx.send_str(message.data)
+        return
+    body = await proto()
+    proto.response_bytes(200, [("content-type", "text/plain")], b"received " + body)
+
+
+async def asgi_app(scope, receive, send):
+    body, more = b"", True
+    while more:
+        message = await receive()
+        if message["type"] == "http.disconnect":
+            return
+        body, more = body + message.get("body", b""), message.get("more_body", False)
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": b"received " + body})
+
+
+def _server(app=rsgi_app, interface="rsgi", **kwargs):
+    return TestServer(app, interface, idle_timeout=0, **kwargs)
+
+
+async def _response(reader, timeout=2):
+    response = await asyncio.wait_for(reader.read(), timeout)
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    return int(lines[0].split(" ")[1]), dict(line.lower().split(": ", 1) for line in lines[1:]), body
+
+
+async def _trickle(writer, chunks, delay):
+    try:
+        for chunk in chunks:
+            await asyncio.sleep(delay)
+            writer.write(chunk)
+            await writer.drain()
+    except ConnectionError:
+        pass
+
+
+@pytest.mark.asyncio
+async def test_header_timeout():
+    async with _server(header_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n")
+        status, headers, _ = await _response(reader)
+        writer.close()
+
+    assert status == 408
+    assert headers["connection"] == "close"
+
+
+@pytest.mark.asyncio
+async def test_header_timeout_trickle():
+    head = b"GET / HTTP/1.1\r\nhost: localhost\r\nx-padding: " + b"a" * 40 + b"\r\n\r\n"
+    async with _server(header_read_timeout=0.3) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        sender = asyncio.create_task(_trickle(writer, [head[idx:idx + 1] for idx in range(len(head))], 0.02))
+        status, _, _ = await _response(reader)
+        sender.cancel()
+        writer.close()
+
+    assert status == 408
+
+
+@pytest.mark.asyncio
+async def test_keep_alive_not_timed_out():
+    async with _server(header_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        for _ in range(2):
+            writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            await asyncio.wait_for(reader.readuntil(b"received "), 2)
+            await asyncio.sleep(0.4)
+        writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n")
+        status, _, _ = await _response(reader)
+        writer.close()
+
+    assert status == 408
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], [("rsgi", rsgi_app), ("asgi", asgi_app)])
+async def test_body_timeout(interface, app):
+    async with _server(app, interface, body_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 10\r\n\r\nabc")
+        status, headers, _ = await _response(reader)
+        writer.close()
+
+    assert status == 408
+    assert headers["connection"] == "close"
+
+
+@pytest.mark.asyncio
+async def test_body_timeout_streamed():
+    async def app(scope, proto):
+        try:
+            async for _ in proto:
+                pass
+        finally:
+            proto.response_empty(200, [])
+
+    async with _server(app, body_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 10\r\n\r\nabc")
+        status, _, _ = await _response(reader)
+        writer.close()
+
+    assert status == 408
+
+
+@pytest.mark.asyncio
+async def test_body_steady():
+    async with _server(body_read_timeout=0.3) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\ncontent-length: 8\r\n\r\n")
+        for _ in range(8):
+            await asyncio.sleep(0.1)
+            writer.write(b"x")
+        status, _, body = await _response(reader)
+        writer.close()
+
+    assert status == 200
+    assert body == b"received xxxxxxxx"
+
+
+@pytest.mark.asyncio
+async def test_request_timeout():
+    async with _server(request_timeout=0.5) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\n\r\n")
+        sender = asyncio.create_task(_trickle(writer, [b"1\r\nx\r\n"] * 10, 0.1))
+        status, _, _ = await _response(reader)
+        sender.cancel()
+        writer.close()
+
+    assert status == 408
+
+
+@pytest.mark.asyncio
+async def test_slow_application():
+    async def app(scope, proto):
+        await asyncio.sleep(0.5)
+        await rsgi_app(scope, proto)
+
+    async with _server(app, header_read_timeout=0.2, body_read_timeout=0.2, request_timeout=0.3) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(b"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\ncontent-length: 3\r\n\r\nabc")
+        status, _, body = await _response(reader)
+        writer.close()
+
+    assert status == 200
+    assert body == b"received abc"
+
+
+@pytest.mark.asyncio
+async def test_websocket_not_timed_out():
+    key = base64.b64encode(os.urandom(16)).decode()
+    async with _server(header_read_timeout=0.2, body_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(
+            f"GET / HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\nconnection: upgrade\r\n"
+            f"sec-websocket-key: {key}\r\nsec-websocket-version: 13\r\n\r\n".encode()
+        )
+        head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 2)
+        await asyncio.sleep(0.5)
+        mask = os.urandom(4)
+        writer.write(bytes([0x81, 0x80 | 5]) + mask + bytes(byte ^ mask[idx % 4] for idx, byte in enumerate(b"hello")))
+        frame = await asyncio.wait_for(reader.readexactly(7), 2)
+        writer.close()
+
+    assert head.startswith(b"HTTP/1.1 101")
+    assert frame == b"\x81\x05hello"
+
+
+@pytest.mark.asyncio
+async def test_http2_not_timed_out():
+    async with _server(header_read_timeout=0.2) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        # the connection preface, with an empty settings frame
+        writer.write(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n" + b"\x00\x00\x00\x04\x00\x00\x00\x00\x00")
+        await asyncio.sleep(0.5)
+        data = await asyncio.wait_for(reader.read(1024), 2)
+        writer.close()
+
+    assert data
+    assert b"408" not in data
+
+
+@pytest.mark.parametrize("kwargs", [{"header_read_timeout": -1}, {"body_read_timeout": -1}, {"request_timeout": -1}])
+def test_invalid_timeouts(kwargs):
+    with pytest.raises(ValueError):
+        TestServer(rsgi_app, "rsgi", **kwargs)
@@ -0,0 +1,112 @@
+import asyncio
+import pathlib
+import shutil
+import ssl
+
+import pytest
+
+from granian._granian import reload_certificates
+from granian.testing import TestServer
+
+
+FIXTURES = pathlib.Path.cwd() / "tests" / "fixtures" / "tls"
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], scope.scheme)
+
+
+def _install(tmp_path, name):
+    shutil.copy(FIXTURES / f"{name}.pem", tmp_path / "cert.tmp")
+    shutil.copy(FIXTURES / f"{name.replace('cert', 'key')}.pem", tmp_path / "key.tmp")
+    # renames keep the swap atomic, like certificate managers do
+    (tmp_path / "cert.tmp").replace(tmp_path / "cert.pem")
+    (tmp_path / "key.tmp").replace(tmp_path / "key.pem")
+
+
+def _fixture(name):
+    return ssl.PEM_cert_to_DER_cert((FIXTURES / f"{name}.pem").read_text())
+
+
+async def _connect(server):
+    ssl_context = ssl.create_default_context()
+    ssl_context.check_hostname = False
+    ssl_context.verify_mode = ssl.CERT_NONE
+    return await asyncio.open_connection(server.host, server.port, ssl=ssl_context)
+
+
+async def _get(reader, writer):
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+    head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 2)
+    return int(head.split(b" ")[1]), await asyncio.wait_for(reader.readexactly(5), 2)
+
+
+def _peer_cert(writer):
+    return writer.get_extra_info("ssl_object").getpeercert(binary_form=True)
+
+
+@pytest.mark.asyncio
+async def test_reload(tmp_path):
+    _install(tmp_path, "cert")
+    async with TestServer(
+        rsgi_app, "rsgi", ssl_cert=tmp_path / "cert.pem", ssl_key=tmp_path / "key.pem"
+    ) as server:
+        reader, writer = await _connect(server)
+        assert _peer_cert(writer) == _fixture("cert")
+
+        _install(tmp_path, "cert2")
+        assert reload_certificates() >= 1
+
+        new_reader, new_writer = await _connect(server)
+        assert _peer_cert(new_writer) == _fixture("cert2")
+        assert await _get(new_reader, new_writer) == (200, b"https")
+        new_writer.close()
+
+        # the connection established before the reload keeps working
+        assert await _get(reader, writer) == (200, b"https")
+        assert _peer_cert(writer) == _fixture("cert")
+        writer.close()
+
+
+@pytest.mark.asyncio
+async def test_reload_invalid(tmp_path):
+    _install(tmp_path, "cert")
+    async with TestServer(
+        rsgi_app, "rsgi", ssl_cert=tmp_path / "cert.pem", ssl_key=tmp_path / "key.pem"
+    ) as server:
+        (tmp_path / "cert.pem").write_text("not a certificate")
+        reload_certificates()
+
+        reader, writer = await _connect(server)
+        assert _peer_cert(writer) == _fixture("cert")
+        assert await _get(reader, writer) == (200, b"https")
+        writer.close()
+
+
+@pytest.mark.asyncio
+async def test_reload_interval(tmp_path):
+    _install(tmp_path, "cert")
+    async with TestServer(
+        rsgi_app,
+        "rsgi",
+        ssl_cert=tmp_path / "cert.pem",
+        ssl_key=tmp_path / "key.pem",
+        ssl_reload_interval=0.1
+    ) as server:
+        _install(tmp_path, "cert2")
+        await asyncio.sleep(0.5)
+
+        _, writer = await _connect(server)
+        assert _peer_cert(writer) == _fixture("cert2")
+        writer.close()
+
+
+def test_reload_interval_invalid():
+    with pytest.raises(ValueError):
+        TestServer(
+            rsgi_app,
+            "rsgi",
+            ssl_cert=FIXTURES / "cert.pem",
+            ssl_key=FIXTURES / "key.pem",
+            ssl_reload_interval=-1
+        )
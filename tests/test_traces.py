@@ -0,0 +1,79 @@
+import asyncio
+import json
+import logging
+
+import pytest
+
+from granian._granian import reset_log_levels
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], "ok")
+
+
+async def _requests(server, count):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    for idx in range(count):
+        connection = "close" if idx == count - 1 else "keep-alive"
+        writer.write(
+            f"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: {connection}\r\n\r\n".encode("ascii")
+        )
+        await reader.readuntil(b"ok")
+    await reader.read()
+    writer.close()
+
+
+def _traces(caplog):
+    return [
+        json.loads(record.getMessage().split(" ", 2)[2])
+        for record in caplog.records
+        if record.getMessage().startswith("Connection trace ")
+    ]
+
+
+@pytest.mark.asyncio
+async def test_connection_trace(caplog):
+    caplog.set_level(logging.INFO, logger="_granian")
+    reset_log_levels()
+    async with TestServer(rsgi_app, "rsgi", connection_trace_sample=1) as server:
+        await _requests(server, 2)
+        await asyncio.sleep(0.2)
+
+    traces = _traces(caplog)
+    assert len(traces) == 1
+    trace = traces[0]
+    assert trace["tls"] is False
+    assert trace["requests"] == 2
+    events = [(event["event"], event.get("request")) for event in trace["events"]]
+    assert events == [
+        ("accept", None),
+        ("headers", 1), ("callback_start", 1), ("callback_end", 1), ("first_byte", 1),
+        ("headers", 2), ("callback_start", 2), ("callback_end", 2), ("first_byte", 2),
+        ("close", None)
+    ]
+    timestamps = [event["at_us"] for event in trace["events"]]
+    assert timestamps == sorted(timestamps)
+
+
+@pytest.mark.asyncio
+async def test_connection_trace_sampling(caplog):
+    caplog.set_level(logging.INFO, logger="_granian")
+    reset_log_levels()
+    async with TestServer(rsgi_app, "rsgi", connection_trace_sample=2) as server:
+        for _ in range(4):
+            await _requests(server, 1)
+        await asyncio.sleep(0.2)
+
+    assert [trace["connection"] for trace in _traces(caplog)] == [1, 3]
+
+
+@pytest.mark.asyncio
+async def test_connection_trace_disabled(caplog):
+    caplog.set_level(logging.INFO, logger="_granian")
+    reset_log_levels()
+    async with TestServer(rsgi_app, "rsgi") as server:
+        await _requests(server, 1)
+        await asyncio.sleep(0.2)
+
+    assert _traces(caplog) == []
@@ -0,0 +1,102 @@
+import asyncio
+import json
+
+import pytest
+
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    body = await proto()
+    content_type = scope.headers.get("content-type") or ""
+    if content_type == "application/json":
+        body = json.dumps({"received": json.loads(body)}, separators=(",", ":")).encode()
+    proto.response_bytes(200, [("content-type", "application/json"), ("x-received", content_type)], body)
+
+
+async def _request(body=b"", headers=(), transcoding=True):
+    head = "".join(f"{key}: {value}\r\n" for key, value in headers)
+    async with TestServer(rsgi_app, "rsgi", transcoding=transcoding) as server:
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(
+            f"POST / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n"
+            f"content-length: {len(body)}\r\n{head}\r\n".encode() + body
+        )
+        response = await asyncio.wait_for(reader.read(), 2)
+        writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    lines = head.decode().split("\r\n")
+    return int(lines[0].split(" ")[1]), dict(line.lower().split(": ", 1) for line in lines[1:]), body
+
+
+@pytest.mark.asyncio
+async def test_msgpack_request():
+    # {"name": "alice", "age": 30, "tags": ["x"]}
+    body = b"\x83\xa4name\xa5alice\xa3age\x1e\xa4tags\x91\xa1x"
+    status, headers, res = await _request(body, [("content-type", "application/msgpack")])
+
+    assert status == 200
+    assert headers["x-received"] == "application/json"
+    assert json.loads(res) == {"received": {"name": "alice", "age": 30, "tags": ["x"]}}
+
+
+@pytest.mark.asyncio
+async def test_cbor_request():
+    # {"a": [1, -2, 1.5, true, null], "b": h'0102'}
+    body = b"\xa2\x61a\x85\x01\x21\xf9\x3e\x00\xf5\xf6\x61b\x42\x01\x02"
+    status, _, res = await _request(body, [("content-type", "application/cbor")])
+
+    assert status == 200
+    assert json.loads(res) == {"received": {"a": [1, -2, 1.5, True, None], "b": "AQI="}}
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["accept", "content_type", "expected"],
+    [
+        # {"received": {"n": [1, -1, 300]}}
+        ("application/msgpack", "application/msgpack", b"\x81\xa8received\x81\xa1n\x93\x01\xff\xcd\x01\x2c"),
+        ("application/json;q=0.5, application/cbor", "application/cbor", b"\xa1hreceived\xa1an\x83\x01\x20\x19\x01\x2c")
+    ]
+)
+async def test_response_encoding(accept, content_type, expected):
+    status, headers, res = await _request(
+        b'{"n": [1, -1, 300]}', [("content-type", "application/json"), ("accept", accept)]
+    )
+
+    assert status == 200
+    assert headers["content-type"] == content_type
+    assert headers["vary"] == "accept"
+    assert res == expected
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("accept", ["*/*", "application/json, application/msgpack;q=0.9"])
+async def test_json_preferred(accept):
+    _, headers, res = await _request(b'{"n": 1}', [("content-type", "application/json"), ("accept", accept)])
+
+    assert headers["content-type"] == "application/json"
+    assert headers["vary"] == "accept"
+    assert res == b'{"received":{"n":1}}'
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "body", [b"\x82\xa4name", b"\xc7\x01\x01\x00", b"\x81\xa1a\x01\x02"]
+)
+async def test_invalid_request(body):
+    status, _, _ = await _request(body, [("content-type", "application/msgpack")])
+
+    assert status == 400
+
+
+@pytest.mark.asyncio
+async def test_disabled():
+    _, headers, res = await _request(
+        b"\x80", [("content-type", "application/msgpack"), ("accept", "application/msgpack")], transcoding=False
+    )
+
+    assert headers["content-type"] == "application/json"
+    assert headers["x-received"] == "application/msgpack"
+    assert "vary" not in headers
+    assert res == b"\x80"
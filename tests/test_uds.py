@@ -0,0 +1,89 @@
+import asyncio
+import json
+import os
+import socket
+import stat
+
+import pytest
+
+from granian._granian import ListenerHolder
+from granian.testing import TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "application/json")], json.dumps([scope.server, scope.client]))
+
+
+async def asgi_app(scope, receive, send):
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": json.dumps([scope["server"], scope["client"]]).encode()})
+
+
+def wsgi_app(environ, start_response):
+    start_response("200 OK", [("content-type", "application/json")])
+    return [json.dumps([environ["SERVER_NAME"], environ["SERVER_PORT"], environ["REMOTE_ADDR"]]).encode()]
+
+
+async def _get(path):
+    reader, writer = await asyncio.open_unix_connection(path)
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+    response = await asyncio.wait_for(reader.read(), 2)
+    writer.close()
+    head, body = response.split(b"\r\n\r\n", 1)
+    return int(head.split(b" ", 2)[1]), body
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    ["interface", "app", "expected"],
+    [
+        ("rsgi", rsgi_app, ["", ""]),
+        ("asgi", asgi_app, [None, None]),
+        ("wsgi", wsgi_app, ["", "", ""])
+    ]
+)
+async def test_addresses(tmp_path, interface, app, expected):
+    path = str(tmp_path / "granian.sock")
+    async with TestServer(app, interface, uds=path):
+        status, body = await _get(path)
+
+    assert status == 200
+    assert json.loads(body) == expected
+
+
+@pytest.mark.asyncio
+async def test_stale_socket(tmp_path):
+    path = str(tmp_path / "granian.sock")
+    stale = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    stale.bind(path)
+    stale.close()
+
+    async with TestServer(rsgi_app, "rsgi", uds=path):
+        status, _ = await _get(path)
+
+    assert status == 200
+
+
+def test_socket_in_use(tmp_path):
+    path = str(tmp_path / "granian.sock")
+    listener = ListenerHolder.from_unix_path(path, 128)
+
+    with pytest.raises(Exception, match="already in use"):
+        ListenerHolder.from_unix_path(path, 128)
+    assert listener.get_fd() > 0
+
+
+def test_not_a_socket(tmp_path):
+    path = tmp_path / "granian.sock"
+    path.write_text("")
+
+    with pytest.raises(Exception, match="not a socket"):
+        ListenerHolder.from_unix_path(str(path), 128)
+    assert path.read_text() == ""
+
+
+def test_permissions(tmp_path):
+    path = str(tmp_path / "granian.sock")
+    ListenerHolder.from_unix_path(path, 128, 0o660)
+
+    assert stat.S_IMODE(os.stat(path).st_mode) == 0o660
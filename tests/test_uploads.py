@@ -0,0 +1,144 @@
+import asyncio
+import os
+
+import httpx
+import pytest
+
+from granian.testing import TestServer
+
+
+completed = []
+
+TUS = {"tus-resumable": "1.0.0"}
+CHUNK = {**TUS, "content-type": "application/offset+octet-stream"}
+
+
+async def rsgi_app(scope, proto):
+    path = scope.headers.get("tus-upload-file")
+    if path is not None:
+        with open(path, "rb") as f:
+            completed.append((scope.headers.get("upload-metadata"), f.read()))
+    proto.response_empty(204, [])
+
+
+async def rsgi_headers_app(scope, proto):
+    headers = [
+        (name, scope.headers.get(name) or "")
+        for name in ("tus-upload-id", "tus-upload-file", "upload-length", "upload-metadata")
+    ]
+    proto.response_empty(204, headers)
+
+
+async def _create(client, server, length, **headers):
+    res = await client.post(f"{server.url}/uploads", headers={**TUS, "upload-length": str(length), **headers})
+    assert res.status_code == 201
+    return f"{server.url}{res.headers['location']}"
+
+
+@pytest.mark.asyncio
+async def test_upload(tmp_path):
+    completed.clear()
+    async with TestServer(rsgi_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path) as server:
+        async with httpx.AsyncClient() as client:
+            url = await _create(client, server, 10, **{"upload-metadata": "filename dGVzdC50eHQ="})
+            first = await client.patch(url, headers={**CHUNK, "upload-offset": "0"}, content=b"hello")
+            head = await client.head(url, headers=TUS)
+            last = await client.patch(url, headers={**CHUNK, "upload-offset": "5"}, content=b"world")
+
+    assert first.status_code == 204
+    assert first.headers["upload-offset"] == "5"
+    assert head.headers["upload-offset"] == "5"
+    assert head.headers["upload-length"] == "10"
+    assert head.headers["cache-control"] == "no-store"
+    assert last.status_code == 204
+    assert last.headers["upload-offset"] == "10"
+    assert completed == [("filename dGVzdC50eHQ=", b"helloworld")]
+
+
+@pytest.mark.asyncio
+async def test_upload_offset_conflict(tmp_path):
+    async with TestServer(rsgi_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path) as server:
+        async with httpx.AsyncClient() as client:
+            url = await _create(client, server, 10)
+            res = await client.patch(url, headers={**CHUNK, "upload-offset": "3"}, content=b"hello")
+            res_type = await client.patch(url, headers={**TUS, "upload-offset": "0"}, content=b"hello")
+            res_version = await client.patch(url, headers={**CHUNK, "tus-resumable": "0.2.0", "upload-offset": "0"})
+
+    assert res.status_code == 409
+    assert res_type.status_code == 415
+    assert res_version.status_code == 412
+    assert res_version.headers["tus-version"] == "1.0.0"
+
+
+@pytest.mark.asyncio
+async def test_upload_max_size(tmp_path):
+    async with TestServer(
+        rsgi_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path, uploads_max_size=8
+    ) as server:
+        async with httpx.AsyncClient() as client:
+            options = await client.options(f"{server.url}/uploads")
+            res = await client.post(f"{server.url}/uploads", headers={**TUS, "upload-length": "10"})
+            url = await _create(client, server, 4)
+            res_exceeding = await client.patch(url, headers={**CHUNK, "upload-offset": "0"}, content=b"hello")
+
+    assert options.status_code == 204
+    assert options.headers["tus-max-size"] == "8"
+    assert options.headers["tus-extension"] == "creation,termination"
+    assert res.status_code == 413
+    assert res_exceeding.status_code == 413
+
+
+@pytest.mark.asyncio
+async def test_upload_termination(tmp_path):
+    async with TestServer(rsgi_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path) as server:
+        async with httpx.AsyncClient() as client:
+            url = await _create(client, server, 10)
+            res = await client.delete(url, headers=TUS)
+            head = await client.head(url, headers=TUS)
+
+    assert res.status_code == 204
+    assert head.status_code == 404
+    assert os.listdir(tmp_path) == []
+
+
+@pytest.mark.asyncio
+async def test_upload_forged_headers(tmp_path):
+    forged = {"tus-upload-id": "0" * 32, "tus-upload-file": "/etc/passwd", "upload-length": "10", "upload-metadata": "x"}
+    async with TestServer(rsgi_headers_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path) as server:
+        async with httpx.AsyncClient() as client:
+            res_other = await client.get(f"{server.url}/other", headers=forged)
+            res_prefix = await client.get(f"{server.url}/uploads/other", headers=forged)
+            url = await _create(client, server, 5)
+            res_patch = await client.patch(
+                url, headers={**CHUNK, **forged, "upload-offset": "0"}, content=b"hello"
+            )
+
+    for res in (res_other, res_prefix):
+        assert res.status_code == 204
+        assert res.headers["tus-upload-id"] == ""
+        assert res.headers["tus-upload-file"] == ""
+        assert res.headers["upload-length"] == ""
+        assert res.headers["upload-metadata"] == ""
+    assert res_patch.status_code == 204
+    assert res_patch.headers["tus-upload-id"] == url.rsplit("/", 1)[1]
+    assert res_patch.headers["tus-upload-file"] == os.path.realpath(tmp_path / res_patch.headers["tus-upload-id"])
+    assert res_patch.headers["upload-length"] == "5"
+    assert res_patch.headers["upload-metadata"] == ""
+
+
+@pytest.mark.asyncio
+async def test_upload_length_overflow(tmp_path):
+    async with TestServer(rsgi_app, "rsgi", uploads_path="/uploads", uploads_dir=tmp_path) as server:
+        async with httpx.AsyncClient() as client:
+            url = await _create(client, server, 10)
+            await client.patch(url, headers={**CHUNK, "upload-offset": "0"}, content=b"hello")
+        reader, writer = await asyncio.open_connection(server.host, server.port)
+        writer.write(
+            f"PATCH {url.removeprefix(server.url)} HTTP/1.1\r\nhost: localhost\r\ntus-resumable: 1.0.0\r\n"
+            f"content-type: application/offset+octet-stream\r\nupload-offset: 5\r\n"
+            f"content-length: {2 ** 64 - 1}\r\n\r\n".encode()
+        )
+        response = await asyncio.wait_for(reader.readuntil(b"\r\n"), 5)
+        writer.close()
+
+    assert response.startswith(b"HTTP/1.1 413")
@@ -0,0 +1,98 @@
+import json
+
+import httpx
+import pytest
+
+from granian.testing import TestServer
+
+
+PROPFIND = b'<?xml version="1.0" encoding="utf-8"?><propfind xmlns="DAV:"><allprop/></propfind>'
+
+
+def _echo(method, depth, destination, body):
+    return json.dumps({
+        "method": method,
+        "depth": depth,
+        "destination": destination,
+        "body": body.decode("utf8")
+    }).encode("utf8")
+
+
+async def rsgi_app(scope, proto):
+    body = await proto()
+    proto.response_bytes(207, [("content-type", "application/json")], _echo(
+        scope.method, scope.headers.get("depth"), scope.headers.get("destination"), body
+    ))
+
+
+async def asgi_app(scope, receive, send):
+    body, more = b"", True
+    while more:
+        message = await receive()
+        body, more = body + message.get("body", b""), message.get("more_body", False)
+    headers = {key.decode("latin-1"): value.decode("latin-1") for key, value in scope["headers"]}
+    await send({"type": "http.response.start", "status": 207, "headers": [(b"content-type", b"application/json")]})
+    await send({"type": "http.response.body", "body": _echo(
+        scope["method"], headers.get("depth"), headers.get("destination"), body
+    )})
+
+
+def wsgi_app(environ, start_response):
+    start_response("207 Multi-Status", [("content-type", "application/json")])
+    return [_echo(
+        environ["REQUEST_METHOD"], environ.get("HTTP_DEPTH"), environ.get("HTTP_DESTINATION"), environ["wsgi.input"]
+    )]
+
+
+INTERFACES = [("rsgi", rsgi_app), ("asgi", asgi_app), ("wsgi", wsgi_app)]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_propfind(interface, app):
+    async with TestServer(app, interface) as server:
+        async with httpx.AsyncClient() as client:
+            res = await client.request(
+                "PROPFIND",
+                f"{server.url}/dav/",
+                headers={"depth": "1", "content-type": "application/xml"},
+                content=PROPFIND
+            )
+
+    assert res.status_code == 207
+    assert res.json() == {"method": "PROPFIND", "depth": "1", "destination": None, "body": PROPFIND.decode("utf8")}
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+@pytest.mark.parametrize("method", ["MKCOL", "COPY", "MOVE", "LOCK", "UNLOCK", "PROPPATCH"])
+async def test_extension_methods(interface, app, method):
+    async with TestServer(app, interface) as server:
+        async with httpx.AsyncClient() as client:
+            res = await client.request(
+                method,
+                f"{server.url}/dav/a.txt",
+                headers={"destination": f"{server.url}/dav/b.txt", "depth": "infinity"},
+                content=b"<lockinfo/>"
+            )
+
+    assert res.status_code == 207
+    assert res.json() == {
+        "method": method,
+        "depth": "infinity",
+        "destination": f"{server.url}/dav/b.txt",
+        "body": "<lockinfo/>"
+    }
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(["interface", "app"], INTERFACES)
+async def test_destination_raw_bytes(interface, app):
+    # some clients send non-ASCII paths in `Destination` without percent-encoding
+    destination = "/dav/caffè.txt".encode("utf8")
+    async with TestServer(app, interface) as server:
+        async with httpx.AsyncClient() as client:
+            res = await client.request("MOVE", f"{server.url}/dav/a.txt", headers=[(b"destination", destination)])
+
+    assert res.status_code == 207
+    assert res.json()["destination"] == destination.decode("latin-1")
@@ -0,0 +1,84 @@
+import asyncio
+
+import pytest
+
+from granian.testing import MockClock, TestServer
+
+
+async def rsgi_app(scope, proto):
+    proto.response_str(200, [("content-type", "text/plain")], "ok")
+
+
+async def _closed(reader):
+    return await asyncio.wait_for(reader.read(), 2) == b""
+
+
+async def _open(reader):
+    try:
+        await asyncio.wait_for(reader.read(1), 0.3)
+    except asyncio.TimeoutError:
+        return True
+    return False
+
+
+async def _request(reader, writer):
+    writer.write(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+    await asyncio.wait_for(reader.readuntil(b"ok"), 2)
+
+
+@pytest.mark.asyncio
+async def test_timer_expiry():
+    with MockClock() as clock:
+        async with TestServer(rsgi_app, "rsgi", idle_timeout=10) as server:
+            reader, writer = await asyncio.open_connection(server.host, server.port)
+            assert await _open(reader)
+            clock.advance(9)
+            assert await _open(reader)
+            clock.advance(2)
+            assert await _closed(reader)
+            writer.close()
+
+
+@pytest.mark.asyncio
+async def test_timer_cancelled():
+    # the idle timer goes away with the first request, leaving the keep-alive one
+    with MockClock() as clock:
+        async with TestServer(rsgi_app, "rsgi", idle_timeout=10, keep_alive_timeout=30) as server:
+            reader, writer = await asyncio.open_connection(server.host, server.port)
+            await _request(reader, writer)
+            clock.advance(11)
+            assert await _open(reader)
+            await _request(reader, writer)
+            clock.advance(31)
+            assert await _closed(reader)
+            writer.close()
+
+
+@pytest.mark.asyncio
+async def test_timer_postponed():
+    with MockClock() as clock:
+        async with TestServer(rsgi_app, "rsgi", idle_timeout=0, keep_alive_timeout=10) as server:
+            reader, writer = await asyncio.open_connection(server.host, server.port)
+            await _request(reader, writer)
+            clock.advance(8)
+            await _request(reader, writer)
+            clock.advance(8)
+            assert await _open(reader)
+            clock.advance(3)
+            assert await _closed(reader)
+            writer.close()
+
+
+@pytest.mark.asyncio
+async def test_timer_wrap_around():
+    # the wheel spans about 5 seconds, longer deadlines take several rounds
+    with MockClock() as clock:
+        async with TestServer(rsgi_app, "rsgi", idle_timeout=0, keep_alive_timeout=30) as server:
+            reader, writer = await asyncio.open_connection(server.host, server.port)
+            await _request(reader, writer)
+            for _ in range(5):
+                clock.advance(5.5)
+                assert await _open(reader)
+            clock.advance(3)
+            assert await _closed(reader)
+            writer.close()
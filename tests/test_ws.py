@@ -30,6 +30,40 @@ async def test_reject(server, threading_mode):
     assert exc.value.status_code == 403
 
 
+@pytest.mark.asyncio
+@pytest.mark.parametrize("server", ["asgi", "rsgi"], indirect=True)
+@pytest.mark.parametrize("threading_mode", ["runtime", "workers"])
+async def test_accept_headers(server, threading_mode):
+    async with server(threading_mode) as port:
+        async with websockets.connect(f"ws://localhost:{port}/ws_headers") as ws:
+            res = await ws.recv()
+            headers = ws.response_headers
+
+    assert res == "ok"
+    assert headers["x-granian-test"] == "ws"
+    assert headers.get_all("set-cookie") == ["a=1", "b=2"]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("server", ["asgi", "rsgi"], indirect=True)
+@pytest.mark.parametrize("threading_mode", ["runtime", "workers"])
+async def test_origin(server, threading_mode):
+    async with server(threading_mode) as port:
+        async with websockets.connect(
+            f"ws://localhost:{port}/ws_echo",
+            origin=f"http://localhost:{port}"
+        ) as ws:
+            await ws.send("foo")
+            res = await ws.recv()
+        with pytest.raises(websockets.InvalidStatusCode) as exc:
+            async with websockets.connect(
+                f"ws://localhost:{port}/ws_echo",
+                origin="http://example.com"
+            ) as ws:
+                pass
+
+    assert res == "foo"
+    assert exc.value.status_code == 403
 @pytest.mark.asyncio
 @pytest.mark.skip
 @pytest.mark.parametrize("server", ["asgi", "rsgi"], indirect=True)
@@ -93,3 +127,21 @@ async def test_rsgi_scope(rsgi_server, threading_mode):
     assert data['path'] == '/ws_info'
     assert data['query_string'] == 'test=true'
     assert data['headers']['host'] == f'localhost:{port}'
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize(
+    "threading_mode",
+    [
+        "runtime",
+        "workers"
+    ]
+)
+async def test_rsgi_fragments(rsgi_server, threading_mode):
+    async with rsgi_server(threading_mode) as port:
+        async with websockets.connect(f"ws://localhost:{port}/ws_fragments") as ws:
+            res_text = await ws.recv()
+            res_bytes = await ws.recv()
+
+    assert res_text == "foobarbaz"
+    assert res_bytes == b"foobar"
@@ -0,0 +1,146 @@
+import asyncio
+import base64
+import os
+import struct
+
+import pytest
+
+from granian.rsgi import ProtocolClosed, WebsocketMessageFragment, WebsocketMessageType
+from granian.testing import TestServer
+
+
+def _app(messages, done, count, fragments=True):
+    async def app(scope, proto):
+        transport = await proto.accept(fragments=fragments)
+        while len(messages) < count:
+            try:
+                message = await transport.receive()
+            except ProtocolClosed:
+                messages.append(None)
+                break
+            messages.append((message.kind, getattr(message, "data", None), message.fragment))
+            if message.kind == WebsocketMessageType.close:
+                break
+        done.set()
+    return app
+
+
+def _frame(opcode, payload, fin=True):
+    mask = os.urandom(4)
+    masked = bytes(byte ^ mask[idx % 4] for idx, byte in enumerate(payload))
+    return struct.pack("!BB", (0x80 if fin else 0) | opcode, 0x80 | len(payload)) + mask + masked
+
+
+async def _send(server, *frames):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    key = base64.b64encode(os.urandom(16)).decode()
+    writer.write(
+        f"GET /ws HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\nconnection: upgrade\r\n"
+        f"sec-websocket-key: {key}\r\nsec-websocket-version: 13\r\n\r\n".encode()
+    )
+    head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 5)
+    assert head.startswith(b"HTTP/1.1 101")
+    for frame in frames:
+        writer.write(frame)
+        await writer.drain()
+    return reader, writer
+
+
+@pytest.mark.asyncio
+async def test_ws_fragments_bytes():
+    messages, done = [], asyncio.Event()
+    async with TestServer(_app(messages, done, 4), "rsgi") as server:
+        _, writer = await _send(
+            server,
+            _frame(0x2, b"foo", fin=False),
+            # control frames can interleave fragments
+            _frame(0x9, b"ping"),
+            _frame(0x0, b"bar", fin=False),
+            _frame(0x0, b"baz"),
+            _frame(0x2, b"whole")
+        )
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    assert messages == [
+        (WebsocketMessageType.bytes, b"foo", WebsocketMessageFragment.start),
+        (WebsocketMessageType.bytes, b"bar", WebsocketMessageFragment.continuation),
+        (WebsocketMessageType.bytes, b"baz", WebsocketMessageFragment.finish),
+        (WebsocketMessageType.bytes, b"whole", WebsocketMessageFragment.none)
+    ]
+
+
+@pytest.mark.asyncio
+async def test_ws_fragments_text():
+    messages, done = [], asyncio.Event()
+    payload = "café!".encode()
+    async with TestServer(_app(messages, done, 4), "rsgi") as server:
+        _, writer = await _send(
+            server,
+            _frame(0x1, payload[:2], fin=False),
+            # a character split across frames
+            _frame(0x0, payload[2:4], fin=False),
+            _frame(0x0, payload[4:]),
+            _frame(0x1, b"whole")
+        )
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    assert messages == [
+        (WebsocketMessageType.string, "ca", WebsocketMessageFragment.start),
+        (WebsocketMessageType.string, "f", WebsocketMessageFragment.continuation),
+        (WebsocketMessageType.string, "é!", WebsocketMessageFragment.finish),
+        (WebsocketMessageType.string, "whole", WebsocketMessageFragment.none)
+    ]
+
+
+@pytest.mark.asyncio
+async def test_ws_fragments_invalid_utf8():
+    messages, done = [], asyncio.Event()
+    async with TestServer(_app(messages, done, 2), "rsgi") as server:
+        reader, writer = await _send(
+            server,
+            _frame(0x1, b"ca", fin=False),
+            _frame(0x0, b"f\xc3")
+        )
+        frame = await asyncio.wait_for(reader.readexactly(4), 5)
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    assert frame[0] == 0x88
+    assert struct.unpack("!H", frame[2:4])[0] == 1007
+    assert messages == [
+        (WebsocketMessageType.string, "ca", WebsocketMessageFragment.start),
+        (WebsocketMessageType.close, None, WebsocketMessageFragment.none)
+    ]
+
+
+@pytest.mark.asyncio
+async def test_ws_fragments_assembled():
+    messages, done = [], asyncio.Event()
+    async with TestServer(_app(messages, done, 1, fragments=False), "rsgi") as server:
+        _, writer = await _send(
+            server,
+            _frame(0x2, b"foo", fin=False),
+            _frame(0x0, b"bar")
+        )
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    assert messages == [(WebsocketMessageType.bytes, b"foobar", WebsocketMessageFragment.none)]
+
+
+@pytest.mark.asyncio
+async def test_ws_fragments_interrupted():
+    messages, done = [], asyncio.Event()
+    async with TestServer(_app(messages, done, 2), "rsgi") as server:
+        _, writer = await _send(
+            server,
+            _frame(0x2, b"foo", fin=False),
+            _frame(0x2, b"bar")
+        )
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    # the connection fails, with no message for the interrupting frame
+    assert messages == [(WebsocketMessageType.bytes, b"foo", WebsocketMessageFragment.start), None]
@@ -0,0 +1,161 @@
+import asyncio
+import base64
+import os
+import struct
+
+import pytest
+
+from granian.rsgi import WebsocketMessageType
+from granian.testing import TestServer
+
+
+def _rsgi_app(messages, done):
+    async def app(scope, proto):
+        transport = await proto.accept()
+        message = await transport.receive()
+        messages.append(message.kind)
+        done.set()
+    return app
+
+
+def _asgi_app(messages, done):
+    async def app(scope, receive, send):
+        if scope["type"] != "websocket":
+            return
+        await receive()
+        await send({"type": "websocket.accept"})
+        message = await receive()
+        messages.append((message["type"], message.get("code")))
+        done.set()
+    return app
+
+
+def _rsgi_data_app(messages, done):
+    async def app(scope, proto):
+        transport = await proto.accept()
+        while len(messages) < 3:
+            message = await transport.receive()
+            messages.append((message.kind, message.data))
+        done.set()
+    return app
+
+
+def _asgi_data_app(messages, done):
+    async def app(scope, receive, send):
+        if scope["type"] != "websocket":
+            return
+        await receive()
+        await send({"type": "websocket.accept"})
+        while len(messages) < 3:
+            message = await receive()
+            messages.append((message.get("bytes"), message.get("text")))
+        done.set()
+    return app
+
+
+def _frame(opcode, payload, fin=True):
+    mask = os.urandom(4)
+    masked = bytes(byte ^ mask[idx % 4] for idx, byte in enumerate(payload))
+    head = (0x80 if fin else 0) | opcode
+    if len(payload) < 126:
+        header = struct.pack("!BB", head, 0x80 | len(payload))
+    elif len(payload) < 65536:
+        header = struct.pack("!BBH", head, 0x80 | 126, len(payload))
+    else:
+        header = struct.pack("!BBQ", head, 0x80 | 127, len(payload))
+    return header + mask + masked
+
+
+async def _connect(server):
+    reader, writer = await asyncio.open_connection(server.host, server.port)
+    key = base64.b64encode(os.urandom(16)).decode()
+    writer.write(
+        f"GET /ws HTTP/1.1\r\nhost: localhost\r\nupgrade: websocket\r\nconnection: upgrade\r\n"
+        f"sec-websocket-key: {key}\r\nsec-websocket-version: 13\r\n\r\n".encode()
+    )
+    head = await asyncio.wait_for(reader.readuntil(b"\r\n\r\n"), 5)
+    assert head.startswith(b"HTTP/1.1 101")
+    return reader, writer
+
+
+async def _send_text(server, payload):
+    reader, writer = await _connect(server)
+    writer.write(_frame(0x1, payload))
+    frame = await asyncio.wait_for(reader.readexactly(4), 5)
+    writer.close()
+    return frame
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["rsgi", "asgi"])
+async def test_ws_invalid_utf8(interface):
+    messages, done = [], asyncio.Event()
+    app = (_rsgi_app if interface == "rsgi" else _asgi_app)(messages, done)
+    async with TestServer(app, interface) as server:
+        frame = await _send_text(server, b"caf\xc3")
+        await asyncio.wait_for(done.wait(), 5)
+
+    # an unfragmented close frame, carrying the 1007 code
+    assert frame[0] == 0x88
+    assert struct.unpack("!H", frame[2:4])[0] == 1007
+    if interface == "rsgi":
+        assert messages == [WebsocketMessageType.close]
+    else:
+        assert messages == [("websocket.disconnect", 1007)]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["rsgi", "asgi"])
+async def test_ws_utf8_bytes(interface):
+    messages, done = [], asyncio.Event()
+    app = (_rsgi_data_app if interface == "rsgi" else _asgi_data_app)(messages, done)
+    async with TestServer(app, interface, websocket_utf8="bytes") as server:
+        reader, writer = await _connect(server)
+        writer.write(_frame(0x1, b"caf\xc3"))
+        # fragments of text messages are delivered as bytes once assembled
+        writer.write(_frame(0x1, "caf".encode(), fin=False) + _frame(0x0, "\u00e9".encode()))
+        # frame headers split across reads
+        frame = _frame(0x1, b"x" * 70000)
+        for chunk in (frame[:1], frame[1:5], frame[5:12], frame[12:]):
+            writer.write(chunk)
+            await writer.drain()
+            await asyncio.sleep(0.05)
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    if interface == "rsgi":
+        assert messages == [
+            (WebsocketMessageType.bytes, b"caf\xc3"),
+            (WebsocketMessageType.bytes, "caf\u00e9".encode()),
+            (WebsocketMessageType.bytes, b"x" * 70000)
+        ]
+    else:
+        assert messages == [(b"caf\xc3", None), ("caf\u00e9".encode(), None), (b"x" * 70000, None)]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("interface", ["rsgi", "asgi"])
+async def test_ws_utf8_strict(interface):
+    messages, done = [], asyncio.Event()
+    app = (_rsgi_data_app if interface == "rsgi" else _asgi_data_app)(messages, done)
+    async with TestServer(app, interface, websocket_utf8="strict") as server:
+        reader, writer = await _connect(server)
+        writer.write(_frame(0x1, "caf".encode(), fin=False) + _frame(0x0, "\u00e9".encode()))
+        writer.write(_frame(0x2, b"caf\xc3"))
+        writer.write(_frame(0x1, b"x" * 300))
+        await asyncio.wait_for(done.wait(), 5)
+        writer.close()
+
+    if interface == "rsgi":
+        assert messages == [
+            (WebsocketMessageType.string, "caf\u00e9"),
+            (WebsocketMessageType.bytes, b"caf\xc3"),
+            (WebsocketMessageType.string, "x" * 300)
+        ]
+    else:
+        assert messages == [(None, "caf\u00e9"), (b"caf\xc3", None), (None, "x" * 300)]
+
+
+def test_ws_utf8_invalid_policy():
+    with pytest.raises(ValueError):
+        TestServer(lambda scope, proto: None, "rsgi", websocket_utf8="lenient")
@@ -25,6 +25,7 @@ async def test_scope(wsgi_server, threading_mode):
     assert data['query_string'] == 'test=true'
     assert data['headers']['HTTP_HOST'] == f'localhost:{port}'
     assert data['content_length'] == str(len(payload))
+    assert data['worker'] == [1, 1]
 
 
 @pytest.mark.asyncio